@@ -1,5 +1,6 @@
 pub mod capture;
 pub mod channel;
+pub mod gba;
 mod io;
 
 use crate::{
@@ -89,6 +90,7 @@ pub struct Audio {
     control: Control,
     bias: u16,
     master_volume: u8,
+    pub(crate) gba: gba::Sound,
     #[cfg(feature = "xq-audio")]
     #[savestate(skip)]
     custom_sample_rate: Option<NonZeroU32>,
@@ -148,6 +150,7 @@ impl Audio {
             control: Control(0),
             bias: 0,
             master_volume: 0,
+            gba: gba::Sound::new(),
             #[cfg(feature = "xq-audio")]
             custom_sample_rate,
             #[cfg(feature = "xq-audio")]
@@ -253,6 +256,10 @@ impl Audio {
     #[inline(never)]
     #[allow(clippy::let_unit_value)]
     pub(crate) fn handle_sample_ready<E: cpu::Engine>(emu: &mut Emu<E>, time: arm7::Timestamp) {
+        if emu.arm7.is_in_gba_mode() {
+            return gba::Sound::handle_sample_ready(emu, time);
+        }
+
         #[cfg(feature = "xq-audio")]
         if emu.audio.custom_sample_rate.is_none() {
             Self::handle_xq_sample_ready(emu, time);
@@ -423,7 +430,12 @@ impl Audio {
         };
         #[cfg(not(feature = "xq-audio"))]
         {
-            emu.audio.sample_chunk.push(output);
+            // The sound amplifier is powered down in sleep mode
+            emu.audio.sample_chunk.push(if emu.spi.power.sleeping() {
+                [0; 2]
+            } else {
+                output
+            });
             if emu.audio.sample_chunk.len() >= emu.audio.sample_chunk_size as usize {
                 emu.audio
                     .backend
@@ -442,7 +454,9 @@ impl Audio {
     #[inline(never)]
     #[cfg(feature = "xq-audio")]
     pub(crate) fn handle_xq_sample_ready<E: cpu::Engine>(emu: &mut Emu<E>, time: arm7::Timestamp) {
-        let output = if emu.audio.control.master_enable() {
+        let output = if emu.arm7.is_in_gba_mode() {
+            emu.audio.gba.resampled_output(time)
+        } else if emu.audio.control.master_enable() {
             macro_rules! channel_output {
                 ($i: expr$(, |$ident: ident| $code: expr)?) => {
                     if emu.audio.channels[$i].control().running()
@@ -538,7 +552,12 @@ impl Audio {
             [0.0; 2]
         };
 
-        emu.audio.sample_chunk.push(output);
+        // The sound amplifier is powered down in sleep mode
+        emu.audio.sample_chunk.push(if emu.spi.power.sleeping() {
+            [0.0; 2]
+        } else {
+            output
+        });
         if emu.audio.sample_chunk.len() >= emu.audio.sample_chunk_size as usize {
             emu.audio
                 .backend
@@ -0,0 +1,657 @@
+//! GBA mode sound hardware: the four PSG channels inherited from the Game Boy, and the two Direct
+//! Sound FIFOs fed through DMA.
+//!
+//! One sample is produced every 512 GBA cycles (32.768 kHz), which matches the DS mixer's output
+//! rate; FIFO samples are consumed on the overflows of their selected timers, approximated at
+//! sample granularity.
+
+// TODO:
+// - FIFOs driven by count-up timers aren't supported
+// - The SOUNDBIAS amplitude resolution is ignored, output is always 9-bit at 32.768 kHz
+
+use super::{Audio, OutputSample, CYCLES_PER_SAMPLE};
+use crate::{
+    cpu::{self, arm7},
+    emu::Emu,
+    utils::{schedule::RawTimestamp, Savestate},
+};
+
+// 512 GBA cycles per sample
+const GBA_CYCLES_PER_SAMPLE: u32 = 512;
+// The frame sequencer runs at 512 Hz
+const SAMPLES_PER_FRAME_SEQ_STEP: u8 = 64;
+
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+const DUTY_ENV_REGS: [u32; 2] = [0x62, 0x68];
+const FREQ_CONTROL_REGS: [u32; 2] = [0x64, 0x6C];
+
+const READ_MASKS: [u16; 0x16] = [
+    0x007F, 0xFFC0, 0x4000, 0, // Square 1
+    0xFFC0, 0, 0x4000, 0, // Square 2
+    0x00E0, 0xE000, 0x4000, 0, // Wave
+    0xFF00, 0, 0x40FF, 0, // Noise
+    0xFF77, 0x770F, 0x0080, 0, // SOUNDCNT_L/H/X
+    0xC3FE, 0, // SOUNDBIAS
+];
+
+#[derive(Clone, Copy, Savestate)]
+struct Square {
+    enabled: bool,
+    length: u8,
+    volume: u8,
+    env_timer: u8,
+    freq: u16,
+    sweep_timer: u8,
+    timer: u32,
+    duty_pos: u8,
+}
+
+#[derive(Clone, Copy, Savestate)]
+struct Wave {
+    enabled: bool,
+    length: u16,
+    timer: u32,
+    pos: u8,
+}
+
+#[derive(Clone, Copy, Savestate)]
+struct Noise {
+    enabled: bool,
+    length: u8,
+    volume: u8,
+    env_timer: u8,
+    lfsr: u16,
+    timer: u32,
+    output: bool,
+}
+
+#[derive(Clone, Copy, Savestate)]
+struct Fifo {
+    buffer: [u8; 32],
+    read_pos: u8,
+    len: u8,
+    cur_sample: u8,
+    timer_counter: RawTimestamp,
+}
+
+impl Fifo {
+    fn new() -> Self {
+        Fifo {
+            buffer: [0; 32],
+            read_pos: 0,
+            len: 0,
+            cur_sample: 0,
+            timer_counter: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.read_pos = 0;
+        self.len = 0;
+        self.cur_sample = 0;
+    }
+
+    fn push(&mut self, value: u8) {
+        if self.len >= 32 {
+            return;
+        }
+        self.buffer[((self.read_pos + self.len) & 31) as usize] = value;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        self.cur_sample = self.buffer[self.read_pos as usize];
+        self.read_pos = (self.read_pos + 1) & 31;
+        self.len -= 1;
+    }
+}
+
+#[derive(Savestate)]
+pub struct Sound {
+    regs: [u16; 0x16],
+    wave_ram: [u8; 0x20],
+    master_enable: bool,
+    squares: [Square; 2],
+    wave: Wave,
+    noise: Noise,
+    fifos: [Fifo; 2],
+    frame_seq_counter: u8,
+    frame_seq_step: u8,
+    // The last two native samples and the time the latest one was produced at, used to resample
+    // the output to a custom sample rate (delayed by one native sample)
+    #[cfg(feature = "xq-audio")]
+    #[savestate(skip)]
+    resampler_outputs: [[OutputSample; 2]; 2],
+    #[cfg(feature = "xq-audio")]
+    #[savestate(skip)]
+    resampler_time: RawTimestamp,
+}
+
+impl Sound {
+    pub(super) fn new() -> Self {
+        let square = Square {
+            enabled: false,
+            length: 0,
+            volume: 0,
+            env_timer: 0,
+            freq: 0,
+            sweep_timer: 0,
+            timer: 0,
+            duty_pos: 0,
+        };
+        let mut result = Sound {
+            regs: [0; 0x16],
+            wave_ram: [0; 0x20],
+            master_enable: false,
+            squares: [square; 2],
+            wave: Wave {
+                enabled: false,
+                length: 0,
+                timer: 0,
+                pos: 0,
+            },
+            noise: Noise {
+                enabled: false,
+                length: 0,
+                volume: 0,
+                env_timer: 0,
+                lfsr: 0x7FFF,
+                timer: 0,
+                output: false,
+            },
+            fifos: [Fifo::new(); 2],
+            frame_seq_counter: 0,
+            frame_seq_step: 0,
+            #[cfg(feature = "xq-audio")]
+            resampler_outputs: [[0.0; 2]; 2],
+            #[cfg(feature = "xq-audio")]
+            resampler_time: 0,
+        };
+        // SOUNDBIAS is initialized to 0x200 by the GBA BIOS
+        result.regs[reg_index(0x88)] = 0x200;
+        result
+    }
+
+    #[inline]
+    fn reg(&self, addr: u32) -> u16 {
+        self.regs[reg_index(addr)]
+    }
+
+    #[inline]
+    pub fn bias(&self) -> u16 {
+        self.reg(0x88) & 0x3FE
+    }
+
+    fn channel_status(&self) -> u16 {
+        self.squares[0].enabled as u16
+            | (self.squares[1].enabled as u16) << 1
+            | (self.wave.enabled as u16) << 2
+            | (self.noise.enabled as u16) << 3
+    }
+
+    pub(crate) fn read_16(&self, addr: u32) -> u16 {
+        match addr & 0xFE {
+            0x84 => (self.master_enable as u16) << 7 | self.channel_status(),
+            addr @ 0x60..=0x8A => self.regs[reg_index(addr)] & READ_MASKS[reg_index(addr)],
+            addr @ 0x90..=0x9F => {
+                let base = self.wave_cpu_bank_base() | (addr as usize & 0xF);
+                u16::from_le_bytes([self.wave_ram[base], self.wave_ram[base | 1]])
+            }
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn read_8(&self, addr: u32) -> u8 {
+        (self.read_16(addr & !1) >> ((addr & 1) << 3)) as u8
+    }
+
+    pub(crate) fn write_16(&mut self, addr: u32, value: u16) {
+        match addr & 0xFE {
+            0x84 => self.write_master_enable(value & 0x80 != 0),
+            0x82 => {
+                self.regs[reg_index(0x82)] = value;
+                self.apply_control_h();
+            }
+            0x88 => self.regs[reg_index(0x88)] = value,
+            addr @ 0x60..=0x80 => {
+                if self.master_enable {
+                    self.regs[reg_index(addr)] = value;
+                    self.apply_psg_write(addr);
+                }
+            }
+            addr @ 0x90..=0x9F => {
+                let base = self.wave_cpu_bank_base() | (addr as usize & 0xF);
+                self.wave_ram[base..base + 2].copy_from_slice(&value.to_le_bytes());
+            }
+            addr @ 0xA0..=0xA7 => {
+                let fifo = &mut self.fifos[(addr >> 2 & 1) as usize];
+                fifo.push(value as u8);
+                fifo.push((value >> 8) as u8);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn write_8(&mut self, addr: u32, value: u8) {
+        match addr & 0xFF {
+            addr @ 0xA0..=0xA7 => self.fifos[(addr >> 2 & 1) as usize].push(value),
+            addr @ 0x90..=0x9F => {
+                self.wave_ram[self.wave_cpu_bank_base() | (addr as usize & 0xF)] = value;
+            }
+            addr @ 0x60..=0x8B => {
+                let shift = (addr & 1) << 3;
+                let prev_value = self.regs[reg_index(addr)];
+                self.write_16(
+                    addr,
+                    (prev_value & !(0xFF << shift)) | (value as u16) << shift,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn wave_cpu_bank_base(&self) -> usize {
+        // The CPU accesses the bank that's not selected for playback
+        if self.reg(0x70) & 0x40 == 0 {
+            0x10
+        } else {
+            0
+        }
+    }
+
+    fn write_master_enable(&mut self, value: bool) {
+        self.master_enable = value;
+        if !value {
+            self.regs[..reg_index(0x82)].fill(0);
+            self.squares
+                .iter_mut()
+                .for_each(|square| square.enabled = false);
+            self.wave.enabled = false;
+            self.noise.enabled = false;
+        }
+    }
+
+    fn apply_control_h(&mut self) {
+        let control_h = self.reg(0x82);
+        for (i, fifo) in self.fifos.iter_mut().enumerate() {
+            if control_h & 0x800 << (i << 2) != 0 {
+                fifo.reset();
+            }
+        }
+        self.regs[reg_index(0x82)] &= !0x8800;
+    }
+
+    fn apply_psg_write(&mut self, addr: u32) {
+        let value = self.reg(addr);
+        match addr {
+            0x62 | 0x68 => {
+                let i = (addr == 0x68) as usize;
+                self.squares[i].length = 64 - (value & 0x3F) as u8;
+                if value >> 11 == 0 {
+                    // Disabling the DAC disables the channel
+                    self.squares[i].enabled = false;
+                }
+            }
+            0x64 | 0x6C => {
+                let i = (addr == 0x6C) as usize;
+                self.squares[i].freq = value & 0x7FF;
+                if value & 0x8000 != 0 {
+                    self.restart_square(i);
+                }
+            }
+            0x70 => {
+                if value & 0x80 == 0 {
+                    self.wave.enabled = false;
+                }
+            }
+            0x72 => self.wave.length = 256 - (value & 0xFF),
+            0x74 => {
+                if value & 0x8000 != 0 {
+                    self.wave.enabled = self.reg(0x70) & 0x80 != 0;
+                    if self.wave.length == 0 {
+                        self.wave.length = 256;
+                    }
+                    self.wave.timer = 0;
+                    self.wave.pos = 0;
+                }
+            }
+            0x78 => {
+                self.noise.length = 64 - (value & 0x3F) as u8;
+                if value >> 11 == 0 {
+                    self.noise.enabled = false;
+                }
+            }
+            0x7C => {
+                if value & 0x8000 != 0 {
+                    let env = self.reg(0x78);
+                    self.noise.enabled = env >> 11 != 0;
+                    if self.noise.length == 0 {
+                        self.noise.length = 64;
+                    }
+                    self.noise.volume = (env >> 12) as u8;
+                    self.noise.env_timer = (env >> 8 & 7) as u8;
+                    self.noise.lfsr = if value & 8 != 0 { 0x7F } else { 0x7FFF };
+                    self.noise.timer = 0;
+                }
+            }
+            _ => {}
+        }
+        // Clear the restart bits, so that later 8-bit writes to the other half of the register
+        // don't restart the channel again
+        if matches!(addr, 0x64 | 0x6C | 0x74 | 0x7C) {
+            self.regs[reg_index(addr)] &= 0x7FFF;
+        }
+    }
+
+    fn restart_square(&mut self, i: usize) {
+        let env = self.reg(DUTY_ENV_REGS[i]);
+        let sweep = if i == 0 { self.reg(0x60) } else { 0 };
+        let square = &mut self.squares[i];
+        square.enabled = env >> 11 != 0;
+        if square.length == 0 {
+            square.length = 64;
+        }
+        square.volume = (env >> 12) as u8;
+        square.env_timer = (env >> 8 & 7) as u8;
+        square.sweep_timer = (sweep >> 4 & 7) as u8;
+        square.timer = 0;
+        if sweep & 7 != 0 && sweep_target(square.freq, sweep) > 0x7FF {
+            square.enabled = false;
+        }
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        let step = self.frame_seq_step;
+        self.frame_seq_step = (step + 1) & 7;
+
+        if step & 1 == 0 {
+            for i in 0..2 {
+                let length_enabled = self.reg(FREQ_CONTROL_REGS[i]) & 0x4000 != 0;
+                let square = &mut self.squares[i];
+                if length_enabled && square.length != 0 {
+                    square.length -= 1;
+                    if square.length == 0 {
+                        square.enabled = false;
+                    }
+                }
+            }
+            if self.reg(0x74) & 0x4000 != 0 && self.wave.length != 0 {
+                self.wave.length -= 1;
+                if self.wave.length == 0 {
+                    self.wave.enabled = false;
+                }
+            }
+            if self.reg(0x7C) & 0x4000 != 0 && self.noise.length != 0 {
+                self.noise.length -= 1;
+                if self.noise.length == 0 {
+                    self.noise.enabled = false;
+                }
+            }
+        }
+
+        if step == 2 || step == 6 {
+            let sweep = self.reg(0x60);
+            let square = &mut self.squares[0];
+            let sweep_time = (sweep >> 4 & 7) as u8;
+            if square.enabled && sweep_time != 0 {
+                square.sweep_timer = square.sweep_timer.saturating_sub(1);
+                if square.sweep_timer == 0 {
+                    square.sweep_timer = sweep_time;
+                    let new_freq = sweep_target(square.freq, sweep);
+                    if new_freq > 0x7FF {
+                        square.enabled = false;
+                    } else if sweep & 7 != 0 {
+                        square.freq = new_freq;
+                        self.regs[reg_index(0x64)] =
+                            (self.regs[reg_index(0x64)] & !0x7FF) | new_freq;
+                    }
+                }
+            }
+        }
+
+        if step == 7 {
+            for i in 0..2 {
+                let env = self.reg(DUTY_ENV_REGS[i]);
+                let square = &mut self.squares[i];
+                step_envelope(&mut square.volume, &mut square.env_timer, env);
+            }
+            let env = self.reg(0x78);
+            step_envelope(&mut self.noise.volume, &mut self.noise.env_timer, env);
+        }
+    }
+
+    fn run_psg(&mut self) -> [i32; 4] {
+        self.frame_seq_counter += 1;
+        if self.frame_seq_counter >= SAMPLES_PER_FRAME_SEQ_STEP {
+            self.frame_seq_counter = 0;
+            self.step_frame_sequencer();
+        }
+
+        let mut output = [0; 4];
+
+        for i in 0..2 {
+            let duty = (self.reg(DUTY_ENV_REGS[i]) >> 6 & 3) as usize;
+            let square = &mut self.squares[i];
+            let period = 16 * (0x800 - square.freq as u32);
+            square.timer += GBA_CYCLES_PER_SAMPLE;
+            square.duty_pos = ((square.duty_pos as u32 + square.timer / period) & 7) as u8;
+            square.timer %= period;
+            if square.enabled {
+                let volume = square.volume as i32;
+                output[i] = if DUTY_PATTERNS[duty] & 1 << square.duty_pos != 0 {
+                    volume
+                } else {
+                    -volume
+                };
+            }
+        }
+
+        {
+            let control = self.reg(0x70);
+            let volume_control = self.reg(0x72);
+            let freq = (self.reg(0x74) & 0x7FF) as u32;
+            let wave = &mut self.wave;
+            let period = 8 * (0x800 - freq);
+            let len = if control & 0x20 != 0 { 64 } else { 32 };
+            wave.timer += GBA_CYCLES_PER_SAMPLE;
+            wave.pos = ((wave.pos as u32 + wave.timer / period) % len) as u8;
+            wave.timer %= period;
+            if wave.enabled {
+                let start_bank = (control >> 6 & 1) as usize;
+                let sample_index = ((start_bank << 5) + wave.pos as usize) & 63;
+                let byte = self.wave_ram[sample_index >> 1];
+                let sample = if sample_index & 1 == 0 {
+                    byte >> 4
+                } else {
+                    byte & 0xF
+                };
+                let sample = sample as i32 * 2 - 15;
+                output[2] = if volume_control & 0x8000 != 0 {
+                    sample * 3 / 4
+                } else {
+                    match volume_control >> 13 & 3 {
+                        0 => 0,
+                        shift => sample >> (shift - 1),
+                    }
+                };
+            }
+        }
+
+        {
+            let control = self.reg(0x7C);
+            let noise = &mut self.noise;
+            let ratio = (control & 7) as u32;
+            let shift = (control >> 4) as u32 + 1;
+            let period = if ratio == 0 { 16 } else { 32 * ratio } << shift;
+            noise.timer += GBA_CYCLES_PER_SAMPLE;
+            while noise.timer >= period {
+                noise.timer -= period;
+                let carry = noise.lfsr & 1 != 0;
+                noise.lfsr >>= 1;
+                if carry {
+                    noise.lfsr ^= if control & 8 != 0 { 0x60 } else { 0x6000 };
+                }
+                noise.output = carry;
+            }
+            if noise.enabled {
+                let volume = noise.volume as i32;
+                output[3] = if noise.output { volume } else { -volume };
+            }
+        }
+
+        output
+    }
+
+    fn run_fifos<E: cpu::Engine>(emu: &mut Emu<E>) {
+        let control_h = emu.audio.gba.reg(0x82);
+        for i in 0..2 {
+            let timer = &emu.arm7.timers.0[(control_h >> (10 + (i << 2)) & 1) as usize];
+            let fifo = &mut emu.audio.gba.fifos[i];
+            if !timer.control().running() || timer.count_up() {
+                continue;
+            }
+            let period =
+                ((0x1_0000 - timer.reload() as RawTimestamp) << timer.cycle_shift()).max(1);
+            fifo.timer_counter += CYCLES_PER_SAMPLE;
+            let pops = (fifo.timer_counter / period).min(32);
+            fifo.timer_counter %= period;
+            for _ in 0..pops {
+                fifo.pop();
+            }
+            if pops != 0 && fifo.len <= 16 {
+                emu.arm7
+                    .start_gba_sound_fifo_dma_transfers(0x0400_00A0 + ((i as u32) << 2));
+            }
+        }
+    }
+
+    pub(super) fn handle_sample_ready<E: cpu::Engine>(emu: &mut Emu<E>, time: arm7::Timestamp) {
+        Self::run_fifos(emu);
+
+        let sound = &mut emu.audio.gba;
+        let output = if sound.master_enable {
+            let psg_output = sound.run_psg();
+            let control_l = sound.reg(0x80);
+            let control_h = sound.reg(0x82);
+            let psg_shift = 2 - (control_h & 3).min(2);
+            let bias = sound.bias() as i32;
+
+            [(0, 8), (4, 12)].map(|(volume_shift, enable_shift)| {
+                let mut psg_sample = 0;
+                for (i, sample) in psg_output.into_iter().enumerate() {
+                    if control_l & 1 << (enable_shift + i) != 0 {
+                        psg_sample += sample;
+                    }
+                }
+                let psg_volume = (control_l >> volume_shift & 7) as i32 + 1;
+                let mut sample = (psg_sample * psg_volume) >> psg_shift;
+
+                let side_bit = (volume_shift != 0) as u16;
+                for (i, fifo) in sound.fifos.iter().enumerate() {
+                    if control_h & 0x100 << (side_bit + ((i as u16) << 2)) != 0 {
+                        let factor = if control_h & 4 << i != 0 { 4 } else { 2 };
+                        sample += fifo.cur_sample as i8 as i32 * factor;
+                    }
+                }
+
+                to_output_sample((sample + bias).clamp(0, 0x3FF))
+            })
+        } else {
+            Default::default()
+        };
+
+        #[cfg(feature = "xq-audio")]
+        let push_output = if emu.audio.custom_sample_rate.is_some() {
+            let sound = &mut emu.audio.gba;
+            sound.resampler_outputs = [sound.resampler_outputs[1], output];
+            sound.resampler_time = time.0;
+            false
+        } else {
+            true
+        };
+        #[cfg(not(feature = "xq-audio"))]
+        let push_output = true;
+        if push_output {
+            emu.audio.sample_chunk.push(output);
+            if emu.audio.sample_chunk.len() >= emu.audio.sample_chunk_size as usize {
+                emu.audio
+                    .backend
+                    .handle_sample_chunk(&mut emu.audio.sample_chunk);
+            }
+        }
+        let cur_time = emu.arm7.schedule.cur_time();
+        emu.arm7.schedule.schedule_event(
+            arm7::event_slots::AUDIO,
+            arm7::Timestamp(
+                time.0.max(cur_time.0 - cur_time.0 % CYCLES_PER_SAMPLE) + CYCLES_PER_SAMPLE,
+            ),
+        );
+    }
+}
+
+#[cfg(feature = "xq-audio")]
+impl Sound {
+    /// Returns the output at `time` for a custom sample rate, linearly interpolated between the
+    /// last two native samples.
+    pub(super) fn resampled_output(&self, time: arm7::Timestamp) -> [OutputSample; 2] {
+        let factor = (time.0.saturating_sub(self.resampler_time) as f32
+            * (1.0 / CYCLES_PER_SAMPLE as f32))
+            .min(1.0);
+        let [prev, cur] = self.resampler_outputs;
+        [0, 1].map(|i| prev[i] + (cur[i] - prev[i]) * factor)
+    }
+}
+
+#[cfg(feature = "xq-audio")]
+#[inline]
+fn to_output_sample(sample: i32) -> OutputSample {
+    (sample as f32 * (1.0 / 512.0) - 1.0) as OutputSample
+}
+
+#[cfg(not(feature = "xq-audio"))]
+#[inline]
+fn to_output_sample(sample: i32) -> OutputSample {
+    sample as OutputSample
+}
+
+#[inline]
+fn reg_index(addr: u32) -> usize {
+    ((addr & 0xFF) as usize - 0x60) >> 1
+}
+
+fn sweep_target(freq: u16, sweep: u16) -> u16 {
+    let delta = freq >> (sweep & 7);
+    if sweep & 8 != 0 {
+        freq - delta
+    } else {
+        freq + delta
+    }
+}
+
+fn step_envelope(volume: &mut u8, timer: &mut u8, env: u16) {
+    let period = (env >> 8 & 7) as u8;
+    if period == 0 {
+        return;
+    }
+    *timer = timer.saturating_sub(1);
+    if *timer == 0 {
+        *timer = period;
+        if env & 0x800 != 0 {
+            if *volume < 15 {
+                *volume += 1;
+            }
+        } else if *volume > 0 {
+            *volume -= 1;
+        }
+    }
+}
+
+impl Audio {
+    pub(crate) fn enter_gba_mode(&mut self) {
+        self.gba = Sound::new();
+    }
+}
@@ -26,6 +26,9 @@ use crate::{
 use psr::Psr;
 
 pub trait Engine: Sized + LoadableInPlace + Storable {
+    /// Identifies the engine in savestate headers.
+    const NAME: &'static str;
+
     type GlobalData: LoadableInPlace + Storable;
     type Arm7Data: Arm7Data + CoreData<Engine = Self> + LoadableInPlace + Storable;
     type Arm9Data: Arm9Data + CoreData<Engine = Self> + LoadableInPlace + Storable;
@@ -51,6 +54,20 @@ pub struct Regs {
     pub spsr_und: Psr,
 }
 
+/// A CPU core's architectural state, independent of the engine emulating it; this is what gets
+/// stored in the engine-independent part of savestates.
+///
+/// `regs.gprs[15]` points two instructions past the next one to be executed, like r15 reads do.
+#[derive(Clone, Debug, Savestate)]
+pub struct CoreState {
+    pub regs: Regs,
+    pub cpsr: Psr,
+    /// The opcodes of the next two instructions to be executed (zero-extended in Thumb state), if
+    /// the engine emulates the pipeline; otherwise, they're fetched from memory again when the
+    /// state is applied.
+    pub pipeline: Option<[u32; 2]>,
+}
+
 pub trait CoreData {
     type Engine: Engine;
 
@@ -69,8 +86,17 @@ pub trait CoreData {
     fn regs(&self) -> Regs;
     fn set_regs(emu: &mut Emu<Self::Engine>, values: &Regs);
 
+    fn core_state(&self) -> CoreState;
+    /// Replaces the core's architectural state without taking any cycles, resetting any
+    /// engine-specific timing state (e.g. pending interlocks); called while loading savestates,
+    /// after all memory mappings have been restored.
+    fn set_core_state(emu: &mut Emu<Self::Engine>, state: &CoreState);
+
     fn jump_and_link(emu: &mut Emu<Self::Engine>, addr: u32, lr: u32);
     fn return_from_hle_swi(emu: &mut Emu<Self::Engine>, r0_3: [u32; 4]);
+    /// Calls a guest function from inside an HLE SWI, with the specified values in r0-r3; `lr`
+    /// should point to a BIOS call that resumes the SWI.
+    fn call_from_hle_swi(emu: &mut Emu<Self::Engine>, addr: u32, r0_3: [u32; 4], lr: u32);
 
     cfg_if::cfg_if! {
         if #[cfg(any(feature = "debugger-hooks", doc))] {
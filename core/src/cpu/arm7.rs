@@ -1,6 +1,6 @@
 pub mod bus;
 mod irqs;
-pub use irqs::{IrqFlags, Irqs};
+pub use irqs::{IrqFlags, IrqFlags2, Irqs, ScheduleUpdate};
 mod schedule;
 pub use schedule::{event_slots, Event, EventSlotIndex, Schedule, Timestamp};
 pub mod dma;
@@ -25,10 +25,14 @@ pub struct Arm7<E: Engine> {
     #[cfg(feature = "debugger-hooks")]
     #[savestate(skip)]
     pub(super) debug: debug::Arm7Data<E>,
+    // Stored separately, as engine-specific savestate data
+    #[savestate(skip)]
     pub engine_data: E::Arm7Data,
     pub(super) hle_bios: hle_bios::arm7::State,
     #[savestate(skip)]
     bios: OwnedBytesCellPtr<BIOS_SIZE>,
+    #[savestate(skip)]
+    gba_bios: Option<OwnedBytesCellPtr<BIOS_SIZE>>,
     pub wram: OwnedBytesCellPtr<0x1_0000>,
     pub schedule: Schedule,
     #[savestate(skip)]
@@ -43,6 +47,10 @@ pub struct Arm7<E: Engine> {
     last_bios_word: u32,
     pub dma: cpu::dma::Controller<dma::Timing, ()>,
     last_dma_words: [u32; 4],
+    gba_mode: bool,
+    gba_wait_control: u16,
+    #[savestate(skip)]
+    pub(crate) gba_mode_failed: bool,
     #[cfg(feature = "debugger-hooks")]
     #[savestate(skip)]
     pub is_stopped: bool,
@@ -55,6 +63,7 @@ impl<E: Engine> Arm7<E> {
     pub(crate) fn new(
         engine_data: E::Arm7Data,
         bios: Option<OwnedBytesCellPtr<BIOS_SIZE>>,
+        gba_bios: Option<OwnedBytesCellPtr<BIOS_SIZE>>,
         #[cfg(feature = "log")] logger: slog::Logger,
     ) -> Self {
         let mut schedule = Schedule::new();
@@ -72,6 +81,7 @@ impl<E: Engine> Arm7<E> {
                 unsafe { buf.as_mut_arr() }.copy_from_slice(&hle_bios::arm7::BIOS);
                 buf
             }),
+            gba_bios,
             wram: OwnedBytesCellPtr::new_zeroed(),
             schedule,
             bus_ptrs: bus::ptrs::Ptrs::new_boxed(),
@@ -117,6 +127,9 @@ impl<E: Engine> Arm7<E> {
                 running_channels: 0,
             },
             last_dma_words: [0; 4],
+            gba_mode: false,
+            gba_wait_control: 0,
+            gba_mode_failed: false,
             #[cfg(feature = "debugger-hooks")]
             is_stopped: false,
             #[cfg(feature = "debugger-hooks")]
@@ -363,6 +376,32 @@ impl<E: Engine> Arm7<E> {
         self.last_dma_words
     }
 
+    #[inline]
+    pub fn gba_bios(&self) -> Option<&Bytes<BIOS_SIZE>> {
+        self.gba_bios
+            .as_ref()
+            .map(|bios| unsafe { bios.as_bytes() })
+    }
+
+    /// Returns whether the ARM7 has been switched to GBA mode, in which it runs GBA software on its
+    /// own while the ARM9 is stopped; there's no way to switch back other than resetting the
+    /// system.
+    #[inline]
+    pub fn is_in_gba_mode(&self) -> bool {
+        self.gba_mode
+    }
+
+    #[inline]
+    pub fn gba_wait_control(&self) -> u16 {
+        self.gba_wait_control
+    }
+
+    #[inline]
+    pub fn write_gba_wait_control(&mut self, value: u16) {
+        self.gba_wait_control = value & 0x5FFF;
+        self.bus_timings.set_gba_wait_control(self.gba_wait_control);
+    }
+
     #[inline]
     pub fn invalidate_word_range(&mut self, bounds: (u32, u32)) {
         self.engine_data.invalidate_word_range(bounds);
@@ -397,6 +436,79 @@ impl<E: Engine> Arm7<E> {
         }
     }
 
+    /// Switches the system to GBA mode, as requested through `HALTCNT`: the GBA BIOS gets mapped in
+    /// place of the ARM7 one, the memory map, timings, 2D engines and sound hardware are
+    /// reconfigured to behave like a GBA's, and the ARM7 is reset.
+    pub(crate) fn enter_gba_mode(emu: &mut Emu<E>) {
+        if emu.arm7.gba_bios.is_none() {
+            // GBA software can't run without its BIOS, stop the system and let the frontend know
+            // why through `RunOutput::MissingGbaBios`
+            #[cfg(feature = "log")]
+            slog::error!(
+                emu.arm7.logger,
+                "Requested switch to GBA mode without a GBA BIOS, stopping"
+            );
+            emu.arm7.gba_mode_failed = true;
+            emu.request_shutdown();
+            return;
+        }
+
+        emu.arm7.gba_mode = true;
+        emu.arm7.hle_bios.enabled = false;
+        emu.arm7.post_boot_flag = false;
+        emu.arm7.last_bios_word = 0;
+
+        for i in 0..4 {
+            let i = cpu::dma::Index::new(i);
+            emu.arm7.write_dma_channel_control(i, cpu::dma::Control(0));
+            emu.arm7.timers.write_control(
+                cpu::timers::Index::new(i.get()),
+                cpu::timers::Control(0),
+                &mut emu.arm7.schedule,
+                &mut emu.arm7.irqs,
+            );
+        }
+        emu.arm7.timers.set_base_cycle_shift(1);
+        emu.arm7
+            .irqs
+            .write_master_enable(false, &mut emu.arm7.schedule);
+        emu.arm7
+            .irqs
+            .write_enabled(IrqFlags(0), &mut emu.arm7.schedule);
+        emu.arm7.irqs.write_requested(IrqFlags(0), ());
+
+        crate::gpu::Gpu::enter_gba_mode(emu);
+        emu.audio.enter_gba_mode();
+
+        emu.arm7.gba_wait_control = 0;
+        Self::setup_gba_bus(emu);
+
+        Self::set_cpsr(emu, Psr::from_raw(0xD3));
+        Self::jump(emu, 0);
+    }
+
+    /// Maps GBA EWRAM and IWRAM, which are backed by the first 256 KiB of main memory and by
+    /// shared WRAM respectively, and sets up the GBA memory timings.
+    pub(crate) fn setup_gba_bus(emu: &mut Emu<E>) {
+        unsafe {
+            emu.arm7.bus_ptrs.unmap_range((0, 0xFFFF_FFFF));
+            emu.arm7.map_sys_bus_ptr_range(
+                bus::ptrs::mask::ALL,
+                emu.main_mem().as_mut_ptr(),
+                0x4_0000,
+                (0x0200_0000, 0x02FF_FFFF),
+            );
+            emu.arm7.map_sys_bus_ptr_range(
+                bus::ptrs::mask::ALL,
+                emu.swram.contents().as_mut_ptr(),
+                0x8000,
+                (0x0300_0000, 0x03FF_FFFF),
+            );
+        }
+        emu.arm7.invalidate_word_range((0, 0xFFFF_FFFF));
+        emu.arm7.bus_timings.setup_gba(emu.arm7.gba_wait_control);
+    }
+
     #[inline]
     pub(crate) fn recalc_swram(&mut self, swram: &Swram) {
         unsafe {
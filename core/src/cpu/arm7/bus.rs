@@ -1,5 +1,6 @@
 mod access;
 mod fallback;
+mod gba;
 pub use access::*;
 pub(crate) mod ptrs;
 pub(super) mod timings;
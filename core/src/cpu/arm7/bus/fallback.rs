@@ -1,10 +1,15 @@
-use super::super::{IrqFlags, BIOS_SIZE};
+use super::{
+    super::{Arm7, IrqFlags, IrqFlags2, BIOS_SIZE},
+    gba,
+};
 use crate::{
     cpu::{bus::AccessType, dma, timers, CoreData, Engine},
     ds_slot,
+    dsi::Dsi,
     emu::{input::KeyIrqControl, AudioWifiPowerControl, Emu, LocalExMemControl},
     gpu, ipc, rtc, spi,
     utils::mem_prelude::*,
+    wifi::WiFi,
 };
 
 // TODO:
@@ -17,6 +22,9 @@ use crate::{
 pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
     #[cfg(feature = "debugger-hooks")]
     check_watchpoints!(emu, emu.arm7, addr, 0, 1, Read);
+    if emu.arm7.gba_mode && !gba::is_shared_io(addr) {
+        return gba::read_8::<A, _>(emu, addr);
+    }
     match addr >> 24 {
         0x00 if addr < BIOS_SIZE as u32 => {
             let max_pc = if addr < emu.arm7.bios_prot as u32 {
@@ -197,6 +205,11 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
 
                     0x400..=0x51F => emu.audio.read_8::<A>(addr),
 
+                    0x4000..=0x4FFF => {
+                        (Dsi::read_arm7_io::<A, _>(emu, addr, 0xFF << ((addr & 3) << 3))
+                            >> ((addr & 3) << 3)) as u8
+                    }
+
                     _ => {
                         #[cfg(feature = "log")]
                         if !A::IS_DEBUG {
@@ -210,7 +223,7 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
                     }
                 }
             } else if addr >> 16 & 0x7F == 0 {
-                emu.wifi.read_8::<A>(addr as u16)
+                WiFi::read_8::<A, _>(emu, addr as u16)
             } else {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -225,7 +238,9 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
 
         0x08 | 0x09 => {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
-                (emu.arm7.local_ex_mem_control().gba_rom_halfword(addr) >> ((addr & 1) << 3)) as u8
+                (emu.gba_slot
+                    .read_rom_16(addr, emu.arm7.local_ex_mem_control())
+                    >> ((addr & 1) << 3)) as u8
             } else {
                 0
             }
@@ -233,7 +248,7 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
 
         0x0A => {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
-                0xFF
+                emu.gba_slot.read_ram(addr)
             } else {
                 0
             }
@@ -254,6 +269,9 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
     #[cfg(feature = "debugger-hooks")]
     check_watchpoints!(emu, emu.arm7, addr, 1, 5, Read);
     addr &= !1;
+    if emu.arm7.gba_mode && !gba::is_shared_io(addr) {
+        return gba::read_16::<A, _>(emu, addr);
+    }
     match addr >> 24 {
         0x00 if addr < BIOS_SIZE as u32 => {
             let max_pc = if addr < emu.arm7.bios_prot as u32 {
@@ -401,6 +419,12 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
                     0x214 => emu.arm7.irqs.requested().0 as u16,
                     0x216 => (emu.arm7.irqs.requested().0 >> 16) as u16,
 
+                    0x218 => emu.arm7.irqs.enabled_2().0 as u16,
+                    0x21A => (emu.arm7.irqs.enabled_2().0 >> 16) as u16,
+
+                    0x21C => emu.arm7.irqs.requested_2().0 as u16,
+                    0x21E => (emu.arm7.irqs.requested_2().0 >> 16) as u16,
+
                     0x240 => {
                         emu.gpu.vram.arm7_status().0 as u16 | (emu.swram.control().0 as u16) << 8
                     }
@@ -416,6 +440,11 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
 
                     0x400..=0x51E => emu.audio.read_16::<A>(addr),
 
+                    0x4000..=0x4FFE => {
+                        (Dsi::read_arm7_io::<A, _>(emu, addr, 0xFFFF << ((addr & 2) << 3))
+                            >> ((addr & 2) << 3)) as u16
+                    }
+
                     _ => {
                         #[cfg(feature = "log")]
                         if !A::IS_DEBUG {
@@ -429,7 +458,7 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
                     }
                 }
             } else if addr >> 16 & 0x7F == 0 {
-                emu.wifi.read_16::<A>(addr as u16)
+                WiFi::read_16::<A, _>(emu, addr as u16)
             } else {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -444,7 +473,8 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
 
         0x08 | 0x09 => {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
-                emu.arm7.local_ex_mem_control().gba_rom_halfword(addr)
+                emu.gba_slot
+                    .read_rom_16(addr, emu.arm7.local_ex_mem_control())
             } else {
                 0
             }
@@ -452,7 +482,7 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
 
         0x0A => {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
-                0xFFFF
+                emu.gba_slot.read_ram(addr) as u16 * 0x0101
             } else {
                 0
             }
@@ -473,6 +503,9 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
     #[cfg(feature = "debugger-hooks")]
     check_watchpoints!(emu, emu.arm7, addr, 3, 0x55, Read);
     addr &= !3;
+    if emu.arm7.gba_mode && !gba::is_shared_io(addr) {
+        return gba::read_32::<A, _>(emu, addr);
+    }
     match addr >> 24 {
         0x00 if addr < BIOS_SIZE as u32 => {
             let max_pc = if addr < emu.arm7.bios_prot as u32 {
@@ -609,6 +642,8 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
                     0x208 => emu.arm7.irqs.master_enable() as u32,
                     0x210 => emu.arm7.irqs.enabled().0,
                     0x214 => emu.arm7.irqs.requested().0,
+                    0x218 => emu.arm7.irqs.enabled_2().0,
+                    0x21C => emu.arm7.irqs.requested_2().0,
 
                     0x240 => {
                         emu.gpu.vram.arm7_status().0 as u32 | (emu.swram.control().0 as u32) << 8
@@ -620,6 +655,8 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
 
                     0x400..=0x51C => emu.audio.read_32::<A>(addr),
 
+                    0x4000..=0x4FFC => Dsi::read_arm7_io::<A, _>(emu, addr, 0xFFFF_FFFF),
+
                     0x10_0000 => {
                         if A::IS_DEBUG {
                             emu.ipc.peek_7()
@@ -655,7 +692,7 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
                     }
                 }
             } else if addr >> 16 & 0x7F == 0 {
-                emu.wifi.read_32::<A>(addr as u16)
+                WiFi::read_32::<A, _>(emu, addr as u16)
             } else {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -670,7 +707,8 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
 
         0x08 | 0x09 => {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
-                emu.arm7.local_ex_mem_control().gba_rom_word(addr)
+                emu.gba_slot
+                    .read_rom_32(addr, emu.arm7.local_ex_mem_control())
             } else {
                 0
             }
@@ -678,7 +716,7 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
 
         0x0A => {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
-                0xFFFF_FFFF
+                emu.gba_slot.read_ram(addr) as u32 * 0x0101_0101
             } else {
                 0
             }
@@ -700,12 +738,19 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
     emu.arm7.engine_data.invalidate_word(addr);
     #[cfg(feature = "debugger-hooks")]
     check_watchpoints!(emu, emu.arm7, addr, 0, 2, Write);
+    if emu.arm7.gba_mode && !gba::is_shared_io(addr) {
+        return gba::write_8::<A, _>(emu, addr, value);
+    }
     match addr >> 24 {
         #[cfg(feature = "bft-w")]
-        0x02 => unsafe {
-            emu.main_mem()
-                .write_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
-        },
+        0x02 => {
+            // Main memory is shared with the ARM9, which might have code cached there
+            emu.arm9.engine_data.invalidate_word(addr);
+            unsafe {
+                emu.main_mem()
+                    .write_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
+            }
+        }
 
         #[cfg(feature = "bft-w")]
         0x03 => {
@@ -875,13 +920,15 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
                     0x301 => match value >> 6 {
                         0 => {}
                         1 => {
-                            unimplemented!("GBA mode switch");
+                            Arm7::enter_gba_mode(emu);
                         }
                         2 => {
                             emu.arm7.irqs.halt(&mut emu.arm7.schedule);
                         }
                         _ => {
-                            todo!("Sleep mode switch");
+                            emu.spi
+                                .power
+                                .enter_sleep(&mut emu.arm7.irqs, &mut emu.arm7.schedule);
                         }
                     },
 
@@ -890,6 +937,16 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
 
                     0x400..=0x51F => emu.audio.write_8::<A>(addr, value),
 
+                    0x4000..=0x4FFF => {
+                        let shift = (addr & 3) << 3;
+                        Dsi::write_arm7_io::<A, _>(
+                            emu,
+                            addr,
+                            (value as u32) << shift,
+                            0xFF << shift,
+                        );
+                    }
+
                     _ =>
                     {
                         #[cfg(feature = "log")]
@@ -904,7 +961,7 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
                     }
                 }
             } else if addr >> 16 & 0x7F == 0 {
-                emu.wifi.write_8::<A>(addr as u16, value);
+                WiFi::write_8::<A, _>(emu, addr as u16, value);
             } else {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -920,6 +977,19 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
 
         0x06 => emu.gpu.vram.write_arm7(addr, value),
 
+        0x08 | 0x09 => {
+            if emu.global_ex_mem_control().arm7_gba_slot_access() {
+                // 8-bit writes show up on both halves of the 16-bit bus
+                emu.gba_slot.write_rom_16(addr, value as u16 * 0x0101);
+            }
+        }
+
+        0x0A => {
+            if emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_ram(addr, value);
+            }
+        }
+
         _ =>
         {
             #[cfg(feature = "log")]
@@ -942,12 +1012,19 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
     #[cfg(feature = "debugger-hooks")]
     check_watchpoints!(emu, emu.arm7, addr, 1, 0xA, Write);
     addr &= !1;
+    if emu.arm7.gba_mode && !gba::is_shared_io(addr) {
+        return gba::write_16::<A, _>(emu, addr, value);
+    }
     match addr >> 24 {
         #[cfg(feature = "bft-w")]
-        0x02 => unsafe {
-            emu.main_mem()
-                .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
-        },
+        0x02 => {
+            // Main memory is shared with the ARM9, which might have code cached there
+            emu.arm9.engine_data.invalidate_word(addr);
+            unsafe {
+                emu.main_mem()
+                    .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
+            }
+        }
 
         #[cfg(feature = "bft-w")]
         0x03 => {
@@ -1240,6 +1317,16 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
 
                     0x400..=0x51E => emu.audio.write_16::<A>(addr, value),
 
+                    0x4000..=0x4FFE => {
+                        let shift = (addr & 2) << 3;
+                        Dsi::write_arm7_io::<A, _>(
+                            emu,
+                            addr,
+                            (value as u32) << shift,
+                            0xFFFF << shift,
+                        );
+                    }
+
                     _ =>
                     {
                         #[cfg(feature = "log")]
@@ -1254,7 +1341,7 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
                     }
                 }
             } else if addr >> 16 & 0x7F == 0 {
-                emu.wifi.write_16::<A>(addr as u16, value);
+                WiFi::write_16::<A, _>(emu, addr as u16, value);
             } else {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -1270,6 +1357,18 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
 
         0x06 => emu.gpu.vram.write_arm7(addr, value),
 
+        0x08 | 0x09 => {
+            if emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_rom_16(addr, value);
+            }
+        }
+
+        0x0A => {
+            if emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_ram(addr, value as u8);
+            }
+        }
+
         _ =>
         {
             #[cfg(feature = "log")]
@@ -1292,12 +1391,19 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
     #[cfg(feature = "debugger-hooks")]
     check_watchpoints!(emu, emu.arm7, addr, 3, 0xAA, Write);
     addr &= !3;
+    if emu.arm7.gba_mode && !gba::is_shared_io(addr) {
+        return gba::write_32::<A, _>(emu, addr, value);
+    }
     match addr >> 24 {
         #[cfg(feature = "bft-w")]
-        0x02 => unsafe {
-            emu.main_mem()
-                .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
-        },
+        0x02 => {
+            // Main memory is shared with the ARM9, which might have code cached there
+            emu.arm9.engine_data.invalidate_word(addr);
+            unsafe {
+                emu.main_mem()
+                    .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
+            }
+        }
 
         #[cfg(feature = "bft-w")]
         0x03 => {
@@ -1480,6 +1586,14 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
                         .arm7
                         .irqs
                         .write_requested(IrqFlags(emu.arm7.irqs.requested().0 & !value), ()),
+                    0x218 => emu
+                        .arm7
+                        .irqs
+                        .write_enabled_2(IrqFlags2(value), &mut emu.arm7.schedule),
+                    0x21C => emu
+                        .arm7
+                        .irqs
+                        .write_requested_2(IrqFlags2(emu.arm7.irqs.requested_2().0 & !value), ()),
 
                     0x304 => emu.write_audio_wifi_power_control(AudioWifiPowerControl(value as u8)),
 
@@ -1491,6 +1605,26 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
 
                     0x400..=0x51C => emu.audio.write_32::<A>(addr, value),
 
+                    0x4000..=0x4FFC => Dsi::write_arm7_io::<A, _>(emu, addr, value, 0xFFFF_FFFF),
+
+                    0x10_0010 => {
+                        if emu.ds_slot.arm7_access() {
+                            emu.ds_slot.write_rom_data_arm7(
+                                value,
+                                &mut emu.arm7.irqs,
+                                &mut emu.arm7.schedule,
+                            );
+                        } else {
+                            #[cfg(feature = "log")]
+                            if !A::IS_DEBUG {
+                                slog::warn!(
+                                    emu.arm7.logger,
+                                    "Tried to write to DS slot ROM data while inaccessible"
+                                );
+                            }
+                        }
+                    }
+
                     _ =>
                     {
                         #[cfg(feature = "log")]
@@ -1505,7 +1639,7 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
                     }
                 }
             } else if addr >> 16 & 0x7F == 0 {
-                emu.wifi.write_32::<A>(addr as u16, value);
+                WiFi::write_32::<A, _>(emu, addr as u16, value);
             } else {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -1521,6 +1655,19 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
 
         0x06 => emu.gpu.vram.write_arm7(addr, value),
 
+        0x08 | 0x09 => {
+            if emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_rom_16(addr, value as u16);
+                emu.gba_slot.write_rom_16(addr | 2, (value >> 16) as u16);
+            }
+        }
+
+        0x0A => {
+            if emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_ram(addr, value as u8);
+            }
+        }
+
         _ =>
         {
             #[cfg(feature = "log")]
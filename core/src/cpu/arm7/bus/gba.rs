@@ -0,0 +1,388 @@
+//! The ARM7's memory map while in GBA mode.
+//!
+//! EWRAM and IWRAM are handled through the regular bus pointers (see
+//! [`Arm7::setup_gba_bus`](super::super::Arm7::setup_gba_bus)); the I/O registers that are laid
+//! out the same way as on the DS (DMA, timers, keypad and `IME`) are still handled by the regular
+//! DS handlers in `fallback`.
+
+use super::super::{IrqFlags, BIOS_SIZE};
+use crate::{
+    cpu::{bus::AccessType, CoreData, Engine},
+    emu::Emu,
+    utils::mem_prelude::*,
+};
+
+// TODO:
+// - Internal memory control (0x0400_0800) isn't emulated
+// - Stop mode is treated like halt mode
+
+/// Returns whether `addr` belongs to an I/O register that behaves the same way in GBA and DS mode,
+/// and can thus be handled by the regular ARM7 I/O handlers.
+#[inline]
+pub(super) fn is_shared_io(addr: u32) -> bool {
+    addr >> 24 == 4
+        && matches!(
+            addr & 0xFF_FFFF,
+            0x0B0..=0x0DF | 0x100..=0x10F | 0x130..=0x133 | 0x208..=0x20B
+        )
+}
+
+fn read_bios<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u32 {
+    if addr >= BIOS_SIZE as u32 {
+        #[cfg(feature = "log")]
+        if !A::IS_DEBUG {
+            slog::warn!(emu.arm7.logger, "Unknown GBA read @ {:#010X}", addr);
+        }
+        return 0;
+    }
+    let pc = emu.arm7.engine_data.r15();
+    if pc < BIOS_SIZE as u32 || A::IS_DEBUG {
+        let word = emu
+            .arm7
+            .gba_bios
+            .as_ref()
+            .map_or(0, |bios| bios.read_le(addr as usize & !3));
+        if !A::IS_DEBUG {
+            emu.arm7.last_bios_word = word;
+        }
+        word
+    } else {
+        #[cfg(feature = "log")]
+        slog::warn!(
+            emu.arm7.logger,
+            "Forbidden read from GBA BIOS region @ {:#06X} (PC = {:#010X})",
+            addr,
+            pc,
+        );
+        emu.arm7.last_bios_word
+    }
+}
+
+fn read_io_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u16 {
+    match addr & 0xFF_FFFE {
+        0x000..=0x05F => emu.gpu.read_gba_io_16::<A>(addr),
+        0x060..=0x0AF => emu.audio.gba.read_16(addr),
+        0x200 => emu.arm7.irqs.enabled().0 as u16 & 0x3FFF,
+        0x202 => emu.arm7.irqs.requested().0 as u16 & 0x3FFF,
+        0x204 => emu.arm7.gba_wait_control,
+        0x300 => emu.arm7.post_boot_flag as u16,
+        _ => {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(emu.arm7.logger, "Unknown GBA IO read16 @ {:#010X}", addr);
+            }
+            0
+        }
+    }
+}
+
+fn write_haltcnt<E: Engine>(emu: &mut Emu<E>, _value: u8) {
+    #[cfg(feature = "log")]
+    if _value & 0x80 != 0 {
+        slog::warn!(emu.arm7.logger, "Entering stop mode, treating it as halt");
+    }
+    emu.arm7.irqs.halt(&mut emu.arm7.schedule);
+}
+
+fn write_io_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u16) {
+    match addr & 0xFF_FFFE {
+        0x000..=0x05F => emu.gpu.write_gba_io_16::<A>(addr, value),
+        0x060..=0x0AF => emu.audio.gba.write_16(addr, value),
+        0x200 => emu
+            .arm7
+            .irqs
+            .write_enabled(IrqFlags(value as u32 & 0x3FFF), &mut emu.arm7.schedule),
+        0x202 => emu
+            .arm7
+            .irqs
+            .write_requested(IrqFlags(emu.arm7.irqs.requested().0 & !(value as u32)), ()),
+        0x204 => emu.arm7.write_gba_wait_control(value),
+        0x300 => {
+            emu.arm7.post_boot_flag |= value & 1 != 0;
+            write_haltcnt(emu, (value >> 8) as u8);
+        }
+        _ =>
+        {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(
+                    emu.arm7.logger,
+                    "Unknown GBA IO write16 @ {:#010X}: {:#06X}",
+                    addr,
+                    value
+                );
+            }
+        }
+    }
+}
+
+fn write_io_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8) {
+    let shift = (addr & 1) << 3;
+    match addr & 0xFF_FFFF {
+        0x000..=0x05F => emu.gpu.write_gba_io_8::<A>(addr, value),
+        0x060..=0x0AF => emu.audio.gba.write_8(addr, value),
+        0x202 | 0x203 => emu.arm7.irqs.write_requested(
+            IrqFlags(emu.arm7.irqs.requested().0 & !((value as u32) << shift)),
+            (),
+        ),
+        0x200 | 0x201 | 0x204 | 0x205 => {
+            let prev_value = read_io_16::<A, _>(emu, addr & !1);
+            write_io_16::<A, _>(
+                emu,
+                addr & !1,
+                (prev_value & !(0xFF << shift)) | (value as u16) << shift,
+            );
+        }
+        0x300 => emu.arm7.post_boot_flag |= value & 1 != 0,
+        0x301 => write_haltcnt(emu, value),
+        _ =>
+        {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(
+                    emu.arm7.logger,
+                    "Unknown GBA IO write8 @ {:#010X}: {:#04X}",
+                    addr,
+                    value
+                );
+            }
+        }
+    }
+}
+
+pub(super) fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
+    match addr >> 24 {
+        0x00 => (read_bios::<A, _>(emu, addr) >> ((addr & 3) << 3)) as u8,
+
+        #[cfg(feature = "bft-r")]
+        0x02 => unsafe { emu.main_mem().read_unchecked((addr & 0x3_FFFF) as usize) },
+
+        #[cfg(feature = "bft-r")]
+        0x03 => emu.swram.contents().read((addr & 0x7FFF) as usize),
+
+        0x04 => (read_io_16::<A, _>(emu, addr & !1) >> ((addr & 1) << 3)) as u8,
+
+        0x05 => emu.gpu.vram.palette.read((addr & 0x3FF) as usize),
+
+        0x06 => emu.gpu.read_gba_vram(addr),
+
+        0x07 => emu.gpu.vram.oam.read((addr & 0x3FF) as usize),
+
+        0x08..=0x0D => {
+            (emu.gba_slot
+                .read_rom_16(addr, emu.arm7.local_ex_mem_control())
+                >> ((addr & 1) << 3)) as u8
+        }
+
+        0x0E | 0x0F => emu.gba_slot.read_ram(addr),
+
+        _ => {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(emu.arm7.logger, "Unknown GBA read8 @ {:#010X}", addr);
+            }
+            0
+        }
+    }
+}
+
+pub(super) fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u16 {
+    match addr >> 24 {
+        0x00 => (read_bios::<A, _>(emu, addr) >> ((addr & 2) << 3)) as u16,
+
+        #[cfg(feature = "bft-r")]
+        0x02 => unsafe { emu.main_mem().read_le_unchecked((addr & 0x3_FFFE) as usize) },
+
+        #[cfg(feature = "bft-r")]
+        0x03 => emu.swram.contents().read_le((addr & 0x7FFE) as usize),
+
+        0x04 => read_io_16::<A, _>(emu, addr),
+
+        0x05 => emu.gpu.vram.palette.read_le((addr & 0x3FE) as usize),
+
+        0x06 => emu.gpu.read_gba_vram(addr),
+
+        0x07 => emu.gpu.vram.oam.read_le((addr & 0x3FE) as usize),
+
+        0x08..=0x0D => emu
+            .gba_slot
+            .read_rom_16(addr, emu.arm7.local_ex_mem_control()),
+
+        0x0E | 0x0F => emu.gba_slot.read_ram(addr) as u16 * 0x0101,
+
+        _ => {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(emu.arm7.logger, "Unknown GBA read16 @ {:#010X}", addr);
+            }
+            0
+        }
+    }
+}
+
+pub(super) fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u32 {
+    match addr >> 24 {
+        0x00 => read_bios::<A, _>(emu, addr),
+
+        #[cfg(feature = "bft-r")]
+        0x02 => unsafe { emu.main_mem().read_le_unchecked((addr & 0x3_FFFC) as usize) },
+
+        #[cfg(feature = "bft-r")]
+        0x03 => emu.swram.contents().read_le((addr & 0x7FFC) as usize),
+
+        0x04 => {
+            read_io_16::<A, _>(emu, addr) as u32 | (read_io_16::<A, _>(emu, addr | 2) as u32) << 16
+        }
+
+        0x05 => emu.gpu.vram.palette.read_le((addr & 0x3FC) as usize),
+
+        0x06 => emu.gpu.read_gba_vram(addr),
+
+        0x07 => emu.gpu.vram.oam.read_le((addr & 0x3FC) as usize),
+
+        0x08..=0x0D => emu
+            .gba_slot
+            .read_rom_32(addr, emu.arm7.local_ex_mem_control()),
+
+        0x0E | 0x0F => emu.gba_slot.read_ram(addr) as u32 * 0x0101_0101,
+
+        _ => {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(emu.arm7.logger, "Unknown GBA read32 @ {:#010X}", addr);
+            }
+            0
+        }
+    }
+}
+
+pub(super) fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8) {
+    match addr >> 24 {
+        #[cfg(feature = "bft-w")]
+        0x02 => unsafe {
+            emu.main_mem()
+                .write_unchecked((addr & 0x3_FFFF) as usize, value);
+        },
+
+        #[cfg(feature = "bft-w")]
+        0x03 => emu.swram.contents().write((addr & 0x7FFF) as usize, value),
+
+        0x04 => write_io_8::<A, _>(emu, addr, value),
+
+        // 8-bit writes to palette RAM write the value to both bytes of the halfword
+        0x05 => emu
+            .gpu
+            .vram
+            .write_palette(addr & 0x3FE, value as u16 * 0x0101),
+
+        0x06 => emu.gpu.write_gba_vram_8(addr, value),
+
+        // 8-bit writes to OAM are ignored
+        0x07 => {}
+
+        0x08..=0x0D => emu.gba_slot.write_rom_16(addr, value as u16 * 0x0101),
+
+        0x0E | 0x0F => emu.gba_slot.write_ram(addr, value),
+
+        _ =>
+        {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(
+                    emu.arm7.logger,
+                    "Unknown GBA write8 @ {:#010X}: {:#04X}",
+                    addr,
+                    value
+                );
+            }
+        }
+    }
+}
+
+pub(super) fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u16) {
+    match addr >> 24 {
+        #[cfg(feature = "bft-w")]
+        0x02 => unsafe {
+            emu.main_mem()
+                .write_le_unchecked((addr & 0x3_FFFE) as usize, value);
+        },
+
+        #[cfg(feature = "bft-w")]
+        0x03 => emu
+            .swram
+            .contents()
+            .write_le((addr & 0x7FFE) as usize, value),
+
+        0x04 => write_io_16::<A, _>(emu, addr, value),
+
+        0x05 => emu.gpu.vram.write_palette(addr & 0x3FE, value),
+
+        0x06 => emu.gpu.write_gba_vram_16(addr, value),
+
+        0x07 => emu.gpu.vram.write_oam(addr & 0x3FE, value),
+
+        0x08..=0x0D => emu.gba_slot.write_rom_16(addr, value),
+
+        0x0E | 0x0F => emu.gba_slot.write_ram(addr, value as u8),
+
+        _ =>
+        {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(
+                    emu.arm7.logger,
+                    "Unknown GBA write16 @ {:#010X}: {:#06X}",
+                    addr,
+                    value
+                );
+            }
+        }
+    }
+}
+
+pub(super) fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u32) {
+    match addr >> 24 {
+        #[cfg(feature = "bft-w")]
+        0x02 => unsafe {
+            emu.main_mem()
+                .write_le_unchecked((addr & 0x3_FFFC) as usize, value);
+        },
+
+        #[cfg(feature = "bft-w")]
+        0x03 => emu
+            .swram
+            .contents()
+            .write_le((addr & 0x7FFC) as usize, value),
+
+        0x04 => {
+            write_io_16::<A, _>(emu, addr, value as u16);
+            write_io_16::<A, _>(emu, addr | 2, (value >> 16) as u16);
+        }
+
+        0x05 => emu.gpu.vram.write_palette(addr & 0x3FC, value),
+
+        0x06 => emu.gpu.write_gba_vram_32(addr, value),
+
+        0x07 => emu.gpu.vram.write_oam(addr & 0x3FC, value),
+
+        0x08..=0x0D => {
+            emu.gba_slot.write_rom_16(addr, value as u16);
+            emu.gba_slot.write_rom_16(addr | 2, (value >> 16) as u16);
+        }
+
+        0x0E | 0x0F => emu.gba_slot.write_ram(addr, value as u8),
+
+        _ =>
+        {
+            #[cfg(feature = "log")]
+            if !A::IS_DEBUG {
+                slog::warn!(
+                    emu.arm7.logger,
+                    "Unknown GBA write32 @ {:#010X}: {:#010X}",
+                    addr,
+                    value
+                );
+            }
+        }
+    }
+}
@@ -205,4 +205,18 @@ impl Ptrs {
             }
         }
     }
+
+    pub fn unmap_range(&mut self, (lower_bound, upper_bound): (u32, u32)) {
+        debug_assert!(lower_bound & Self::PAGE_MASK == 0);
+        debug_assert!(upper_bound & Self::PAGE_MASK == Self::PAGE_MASK);
+
+        let lower_bound = (lower_bound >> Self::PAGE_SHIFT) as usize;
+        let upper_bound = (upper_bound >> Self::PAGE_SHIFT) as usize;
+        #[cfg(any(feature = "bft-r", feature = "bft-w"))]
+        for attrs in &mut self.attrs[lower_bound..=upper_bound] {
+            *attrs &= !(mask::ALL | attrs::BAK_MASK_ALL);
+        }
+        #[cfg(not(any(feature = "bft-r", feature = "bft-w")))]
+        self.attrs[lower_bound..=upper_bound].fill(0);
+    }
 }
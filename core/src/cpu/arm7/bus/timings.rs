@@ -75,4 +75,78 @@ impl Timings {
             (0x0600_0000, 0x06FF_FFFF),
         );
     }
+
+    /// Sets up the timings used in GBA mode, where the ARM7 runs at half its usual clock rate; all
+    /// cycle counts are doubled to express them in terms of the DS' system clock.
+    pub fn setup_gba(&mut self, wait_control: u16) {
+        // TODO: Internal cycles are still counted at the DS' clock rate.
+
+        self.0.fill(Cycles {
+            n32: 2,
+            s32: 2,
+            n16: 2,
+            s16: 2,
+        });
+
+        // BIOS, IWRAM, I/O, OAM: same as unmapped
+
+        // EWRAM
+        self.set_range(
+            Cycles {
+                n32: 12,
+                s32: 12,
+                n16: 6,
+                s16: 6,
+            },
+            (0x0200_0000, 0x02FF_FFFF),
+        );
+
+        // Palette RAM, VRAM
+        self.set_range(
+            Cycles {
+                n32: 4,
+                s32: 4,
+                n16: 2,
+                s16: 2,
+            },
+            (0x0500_0000, 0x06FF_FFFF),
+        );
+
+        self.set_gba_wait_control(wait_control);
+    }
+
+    /// Updates the timings of the GBA slot regions in GBA mode according to `WAITCNT`.
+    pub fn set_gba_wait_control(&mut self, value: u16) {
+        const N_CYCLES: [u8; 4] = [4, 3, 2, 8];
+
+        for (i, (n_shift, s_shift, s_cycles)) in [(2, 4, [2, 1]), (5, 7, [4, 1]), (8, 10, [8, 1])]
+            .into_iter()
+            .enumerate()
+        {
+            let n16 = 1 + N_CYCLES[(value >> n_shift & 3) as usize];
+            let s16 = 1 + s_cycles[(value >> s_shift & 1) as usize];
+            let start_addr = 0x0800_0000 + ((i as u32) << 25);
+            self.set_range(
+                Cycles {
+                    n32: (n16 + s16) << 1,
+                    s32: s16 << 2,
+                    n16: n16 << 1,
+                    s16: s16 << 1,
+                },
+                (start_addr, start_addr | 0x01FF_FFFF),
+            );
+        }
+
+        // SRAM (8-bit bus)
+        let sram_cycles = (1 + N_CYCLES[(value & 3) as usize]) << 1;
+        self.set_range(
+            Cycles {
+                n32: sram_cycles,
+                s32: sram_cycles,
+                n16: sram_cycles,
+                s16: sram_cycles,
+            },
+            (0x0E00_0000, 0x0FFF_FFFF),
+        );
+    }
 }
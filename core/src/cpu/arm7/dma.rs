@@ -18,6 +18,9 @@ pub enum Timing {
     DsSlot,    // x
     WiFi,      // -
     GbaSlot,   // -
+    // GBA mode only
+    HBlank,       // x
+    GbaSoundFifo, // x
     Disabled,
 }
 
@@ -43,19 +46,55 @@ impl<E: Engine> Arm7<E> {
             return;
         }
 
-        channel.timing = match value.timing_arm7() {
-            0 => Timing::Immediate,
-            1 => Timing::VBlank,
-            2 => Timing::DsSlot,
-            _ => {
-                if i.get() & 1 == 0 {
-                    Timing::WiFi
-                } else {
-                    Timing::GbaSlot
+        channel.timing = if self.gba_mode {
+            match value.timing_arm7() {
+                0 => Timing::Immediate,
+                1 => Timing::VBlank,
+                2 => Timing::HBlank,
+                _ => {
+                    if matches!(i.get(), 1 | 2) {
+                        Timing::GbaSoundFifo
+                    } else {
+                        // TODO: DMA 3 video capture mode
+                        Timing::Disabled
+                    }
+                }
+            }
+        } else {
+            match value.timing_arm7() {
+                0 => Timing::Immediate,
+                1 => Timing::VBlank,
+                2 => Timing::DsSlot,
+                _ => {
+                    if i.get() & 1 == 0 {
+                        Timing::WiFi
+                    } else {
+                        Timing::GbaSlot
+                    }
                 }
             }
         };
 
+        if channel.timing == Timing::GbaSoundFifo {
+            // Sound FIFO DMAs always transfer 4 words to a fixed address, regardless of the
+            // specified unit size, count and destination address control.
+            channel.control.set_is_32_bit(true);
+            channel.src_addr_incr = match value.src_addr_control() {
+                0 => 4,
+                1 => -4,
+                _ => 0,
+            };
+            channel.dst_addr_incr = 0;
+            channel.unit_count = 4;
+            channel.repeat = value.repeat();
+            if !prev_value.enabled() {
+                channel.cur_src_addr = channel.src_addr & !3;
+                channel.cur_dst_addr = channel.dst_addr & !3;
+                channel.remaining_units = 4;
+            }
+            return;
+        }
+
         let incr_shift = 1 + value.is_32_bit() as u8;
         channel.src_addr_incr = match value.src_addr_control() {
             0 => 1,
@@ -116,6 +155,16 @@ impl<E: Engine> Arm7<E> {
         }
     }
 
+    /// Starts the sound FIFO DMA transfers targeting the FIFO at `fifo_addr` in GBA mode.
+    pub(crate) fn start_gba_sound_fifo_dma_transfers(&mut self, fifo_addr: u32) {
+        for i in 1..3 {
+            let channel = &self.dma.channels[i as usize];
+            if channel.timing == Timing::GbaSoundFifo && channel.dst_addr & !3 == fifo_addr {
+                self.start_dma_transfer::<false>(Index::new(i));
+            }
+        }
+    }
+
     fn end_dma_transfer(&mut self, i: Index) {
         let channel = &mut self.dma.channels[i.get() as usize];
         if channel.repeat {
@@ -40,18 +40,54 @@ proc_bitfield::bitfield! {
         pub ipc_recv_fifo_not_empty: bool @ 18,     // x
         pub ds_slot_transfer_complete: bool @ 19,   // x
         pub ds_slot_ext: bool @ 20,                 // -
-        pub lid_opened: bool @ 22,                  // -
+        pub lid_opened: bool @ 22,                  // x
         pub spi_data_ready: bool @ 23,              // x
         pub wifi: bool @ 24,                        // -
+        pub ds_slot_transfer_complete_2: bool @ 25, // -
+        pub ds_slot_ext_2: bool @ 26,               // -
+        pub ndma0: bool @ 28,                       // x
+        pub ndma1: bool @ 29,                       // x
+        pub ndma2: bool @ 30,                       // x
+        pub ndma3: bool @ 31,                       // x
     }
 }
 
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct IrqFlags2(pub u32): Debug {
+        pub gpio18_0: bool @ 0,                     // -
+        pub gpio18_1: bool @ 1,                     // -
+        pub gpio18_2: bool @ 2,                     // -
+        pub gpio33_0: bool @ 4,                     // -
+        pub headphone_connect: bool @ 5,            // -
+        pub power_button: bool @ 6,                 // -
+        pub sound_enable_output: bool @ 7,          // -
+        pub sd_mmc: bool @ 8,                       // -
+        pub sd_data1: bool @ 9,                     // -
+        pub sdio: bool @ 10,                        // -
+        pub sdio_data1: bool @ 11,                  // -
+        pub aes: bool @ 12,                         // x
+        pub i2c: bool @ 13,                         // -
+        pub mic_ext: bool @ 14,                     // -
+    }
+}
+
+const DS_IRQ_MASK: u32 = 0x01DF_3FFF;
+const DSI_IRQ_MASK: u32 = 0xF7DF_3FFF;
+/// The IRQ sources that can wake the ARM7 up from sleep mode: the RTC, the keypad, the GBA slot and
+/// the lid being opened.
+const WAKEUP_IRQ_MASK: u32 = 0x0040_3080;
+
 #[derive(Savestate)]
 pub struct Irqs {
     enabled: IrqFlags,
     requested: IrqFlags,
+    enabled_2: IrqFlags2,
+    requested_2: IrqFlags2,
+    mask: u32,
     master_enable: bool,
     halted: bool,
+    sleeping: bool,
     cpu_irq_line: bool,
     enabled_in_cpsr: bool,
     triggered: bool,
@@ -62,8 +98,12 @@ impl Irqs {
         Irqs {
             enabled: IrqFlags(0),
             requested: IrqFlags(0),
+            enabled_2: IrqFlags2(0),
+            requested_2: IrqFlags2(0),
+            mask: DS_IRQ_MASK,
             master_enable: false,
             halted: false,
+            sleeping: false,
             cpu_irq_line: false,
             enabled_in_cpsr: false,
             triggered: false,
@@ -80,6 +120,16 @@ impl Irqs {
         self.requested
     }
 
+    #[inline]
+    pub fn enabled_2(&self) -> IrqFlags2 {
+        self.enabled_2
+    }
+
+    #[inline]
+    pub fn requested_2(&self) -> IrqFlags2 {
+        self.requested_2
+    }
+
     #[inline]
     pub fn master_enable(&self) -> bool {
         self.master_enable
@@ -92,12 +142,28 @@ impl Irqs {
 
     #[inline]
     pub fn halt<S: ScheduleUpdate>(&mut self, schedule: S) {
-        self.halted = self.enabled.0 & self.requested.0 == 0;
+        self.halted = !self.any_pending();
         if self.halted {
             schedule.stop_execution();
         }
     }
 
+    #[inline]
+    pub fn sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    /// Enters sleep mode, halting the CPU until one of the enabled wakeup IRQ sources is
+    /// requested; other IRQs are ignored until then.
+    #[inline]
+    pub fn sleep<S: ScheduleUpdate>(&mut self, schedule: S) {
+        self.sleeping = !self.any_wakeup_pending();
+        self.halted = self.sleeping;
+        if self.sleeping {
+            schedule.stop_execution();
+        }
+    }
+
     #[inline]
     pub fn cpu_irq_line(&self) -> bool {
         self.cpu_irq_line
@@ -136,31 +202,82 @@ impl Irqs {
         self.triggered
     }
 
+    #[inline]
+    fn any_pending(&self) -> bool {
+        self.enabled.0 & self.requested.0 != 0 || self.enabled_2.0 & self.requested_2.0 != 0
+    }
+
+    #[inline]
+    fn any_wakeup_pending(&self) -> bool {
+        self.enabled.0 & self.requested.0 & WAKEUP_IRQ_MASK != 0
+    }
+
     #[inline]
     fn update_pending<S: ScheduleUpdate>(&mut self, schedule: S) {
-        let pending = self.enabled.0 & self.requested.0;
-        self.halted &= pending == 0;
+        let pending = self.any_pending();
+        if self.sleeping {
+            if self.any_wakeup_pending() {
+                self.sleeping = false;
+                self.halted = false;
+            }
+        } else {
+            self.halted &= !pending;
+        }
         if self.master_enable {
-            self.set_irq_line(pending != 0, schedule);
+            self.set_irq_line(pending, schedule);
         }
     }
 
+    /// Switches between the DS and DSi sets of IRQ sources, as selected by `SCFG_EXT7`; IE2/IF2
+    /// are only writable while the extended sources are enabled.
+    pub(crate) fn set_dsi_sources_enabled<S: ScheduleUpdate>(&mut self, value: bool, schedule: S) {
+        self.mask = if value { DSI_IRQ_MASK } else { DS_IRQ_MASK };
+        self.enabled.0 &= self.mask;
+        self.requested.0 &= self.mask;
+        if !value {
+            self.enabled_2 = IrqFlags2(0);
+            self.requested_2 = IrqFlags2(0);
+        }
+        self.update_pending(schedule);
+    }
+
+    #[inline]
+    pub fn dsi_sources_enabled(&self) -> bool {
+        self.mask == DSI_IRQ_MASK
+    }
+
     #[inline]
     pub fn write_enabled<S: ScheduleUpdate>(&mut self, value: IrqFlags, schedule: S) {
-        self.enabled = IrqFlags(value.0 & 0x01DF_3FFF);
+        self.enabled = IrqFlags(value.0 & self.mask);
         self.update_pending(schedule);
     }
 
     #[inline]
     pub fn write_requested<S: ScheduleUpdate>(&mut self, value: IrqFlags, schedule: S) {
-        self.requested = IrqFlags(value.0 & 0x01DF_3FFF);
+        self.requested = IrqFlags(value.0 & self.mask);
         self.update_pending(schedule);
     }
 
+    #[inline]
+    pub fn write_enabled_2<S: ScheduleUpdate>(&mut self, value: IrqFlags2, schedule: S) {
+        if self.dsi_sources_enabled() {
+            self.enabled_2 = IrqFlags2(value.0 & 0x7FF7);
+            self.update_pending(schedule);
+        }
+    }
+
+    #[inline]
+    pub fn write_requested_2<S: ScheduleUpdate>(&mut self, value: IrqFlags2, schedule: S) {
+        if self.dsi_sources_enabled() {
+            self.requested_2 = IrqFlags2(value.0 & 0x7FF7);
+            self.update_pending(schedule);
+        }
+    }
+
     #[inline]
     pub fn write_master_enable<S: ScheduleUpdate>(&mut self, value: bool, schedule: S) {
         self.master_enable = value;
-        self.set_irq_line(value && self.enabled.0 & self.requested.0 != 0, schedule);
+        self.set_irq_line(value && self.any_pending(), schedule);
     }
 }
 
@@ -175,3 +292,61 @@ impl cpu::Irqs for Irqs {
         self.write_requested(IrqFlags(self.requested().0 | 0x100 << i.get()), ());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn irqs_with_enabled(enabled: u32) -> Irqs {
+        let mut irqs = Irqs::new(&mut Schedule::new());
+        irqs.write_enabled(IrqFlags(enabled), ());
+        irqs
+    }
+
+    fn request(irqs: &mut Irqs, bit: u32) {
+        irqs.write_requested(IrqFlags(irqs.requested().0 | 1 << bit), ());
+    }
+
+    #[test]
+    fn each_wakeup_irq_ends_sleep() {
+        for bit in (0..32).filter(|bit| WAKEUP_IRQ_MASK & 1 << bit != 0) {
+            let mut irqs = irqs_with_enabled(DS_IRQ_MASK);
+            irqs.sleep(());
+            assert!(irqs.sleeping() && irqs.halted());
+            request(&mut irqs, bit);
+            assert!(!irqs.sleeping(), "IRQ {bit} didn't end sleep");
+            assert!(!irqs.halted(), "IRQ {bit} didn't resume execution");
+        }
+    }
+
+    #[test]
+    fn other_irqs_dont_end_sleep() {
+        for bit in (0..32).filter(|bit| DS_IRQ_MASK & !WAKEUP_IRQ_MASK & 1 << bit != 0) {
+            let mut irqs = irqs_with_enabled(DS_IRQ_MASK);
+            irqs.sleep(());
+            request(&mut irqs, bit);
+            assert!(irqs.sleeping(), "IRQ {bit} ended sleep");
+            assert!(irqs.halted(), "IRQ {bit} resumed execution");
+        }
+    }
+
+    #[test]
+    fn disabled_wakeup_irqs_dont_end_sleep() {
+        let mut irqs = irqs_with_enabled(DS_IRQ_MASK & !WAKEUP_IRQ_MASK);
+        irqs.sleep(());
+        request(&mut irqs, 22);
+        assert!(irqs.sleeping());
+
+        // Enabling a source that's already requested wakes the CPU up
+        irqs.write_enabled(IrqFlags(DS_IRQ_MASK), ());
+        assert!(!irqs.sleeping() && !irqs.halted());
+    }
+
+    #[test]
+    fn sleep_with_pending_wakeup_irq_returns_immediately() {
+        let mut irqs = irqs_with_enabled(DS_IRQ_MASK);
+        request(&mut irqs, 12);
+        irqs.sleep(());
+        assert!(!irqs.sleeping() && !irqs.halted());
+    }
+}
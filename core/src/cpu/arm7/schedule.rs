@@ -4,6 +4,7 @@ use crate::{
     ds_slot::DsSlot,
     emu::{self, Emu},
     utils::{def_event_slot_index, def_event_slots, def_timestamp, schedule, Savestate},
+    wifi::WiFi,
 };
 
 def_timestamp!(#[derive(Savestate)] pub struct Timestamp);
@@ -47,6 +48,7 @@ pub enum Event {
     #[cfg(feature = "xq-audio")]
     XqAudioSampleReady, // Max 1
     Timer(timers::Index), // Max 4
+    WifiTick,           // Max 1
 }
 
 def_event_slots! {
@@ -60,6 +62,7 @@ def_event_slots! {
     #[cfg(feature = "xq-audio")]
     XQ_AUDIO,
     TIMERS_START..TIMERS_END 4,
+    WIFI,
 }
 
 def_event_slot_index!(bounded_esi, event_slots, pub struct EventSlotIndex(u8));
@@ -110,6 +113,7 @@ impl Schedule {
                     &mut emu.arm7.schedule,
                     &mut emu.arm7.irqs,
                 ),
+                Event::WifiTick => WiFi::handle_tick(emu, time),
             }
         }
     }
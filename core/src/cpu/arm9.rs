@@ -42,6 +42,8 @@ pub struct Arm9<E: Engine> {
     #[cfg(feature = "debugger-hooks")]
     #[savestate(skip)]
     pub(super) debug: debug::Arm9Data<E>,
+    // Stored separately, as engine-specific savestate data
+    #[savestate(skip)]
     pub engine_data: E::Arm9Data,
     pub(super) hle_bios: hle_bios::arm9::State,
     #[savestate(skip)]
@@ -422,6 +424,27 @@ impl<E: Engine> Arm9<E> {
         self.engine_data.invalidate_word_range(bounds);
     }
 
+    /// Disables the fast write pointers used by both the CPU and DMA for all pages overlapping the
+    /// specified range, so that any writes to it go through the fallback handlers (and
+    /// `CoreData::invalidate_word`).
+    #[cfg(feature = "bft-w")]
+    pub(crate) fn disable_write_range(
+        &mut self,
+        (lower_bound, upper_bound): (u32, u32),
+        flags: cpu::bus::WDisableFlags,
+    ) {
+        for addr in (lower_bound & !cp15::ptrs::Ptrs::PAGE_MASK..=upper_bound)
+            .step_by(cp15::ptrs::Ptrs::PAGE_SIZE)
+        {
+            self.cp15.ptrs.disable_write(addr, flags);
+        }
+        for addr in (lower_bound & !bus::ptrs::Ptrs::PAGE_MASK..=upper_bound)
+            .step_by(bus::ptrs::Ptrs::PAGE_SIZE)
+        {
+            self.bus_ptrs.disable_write(addr, flags);
+        }
+    }
+
     #[inline]
     pub(crate) unsafe fn map_sys_bus_ptr_range(
         &mut self,
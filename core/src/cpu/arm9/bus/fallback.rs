@@ -4,9 +4,10 @@ use crate::{
     cpu::{
         arm9::{div_engine, sqrt_engine},
         bus::AccessType,
-        dma, timers,
+        dma, timers, CoreData,
     },
     ds_slot,
+    dsi::{nwram::Nwram, Dsi},
     emu::{input::KeyIrqControl, swram, Emu, GlobalExMemControl, LocalExMemControl},
     gpu::{self, engine_3d},
     ipc,
@@ -179,6 +180,8 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
                 emu.gpu.engine_2d_b.read_8::<A>(addr)
             }
 
+            0x4000..=0x4FFF => (Dsi::read_arm9_io::<A, _>(emu, addr) >> ((addr & 3) << 3)) as u8,
+
             _ => {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -209,7 +212,9 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
                 0
             } else {
-                (emu.arm9.local_ex_mem_control().gba_rom_halfword(addr) >> ((addr & 1) << 3)) as u8
+                (emu.gba_slot
+                    .read_rom_16(addr, emu.arm9.local_ex_mem_control())
+                    >> ((addr & 1) << 3)) as u8
             }
         }
 
@@ -217,7 +222,7 @@ pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u8 {
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
                 0
             } else {
-                0xFF
+                emu.gba_slot.read_ram(addr)
             }
         }
 
@@ -391,6 +396,8 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
 
             0x1000..=0x1002 | 0x1008..=0x1056 | 0x106C => emu.gpu.engine_2d_b.read_16::<A>(addr),
 
+            0x4000..=0x4FFE => (Dsi::read_arm9_io::<A, _>(emu, addr) >> ((addr & 2) << 3)) as u16,
+
             _ => {
                 #[cfg(feature = "log")]
                 if !A::IS_DEBUG {
@@ -421,7 +428,8 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
                 0
             } else {
-                emu.arm9.local_ex_mem_control().gba_rom_halfword(addr)
+                emu.gba_slot
+                    .read_rom_16(addr, emu.arm9.local_ex_mem_control())
             }
         }
 
@@ -429,7 +437,7 @@ pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u16
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
                 0
             } else {
-                0xFFFF
+                emu.gba_slot.read_ram(addr) as u16 * 0x0101
             }
         }
 
@@ -586,6 +594,8 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
 
             0x1000 | 0x1008..=0x1054 | 0x106C => emu.gpu.engine_2d_b.read_32::<A>(addr),
 
+            0x4000..=0x4FFC => Dsi::read_arm9_io::<A, _>(emu, addr),
+
             0x10_0000 => {
                 if A::IS_DEBUG {
                     emu.ipc.peek_9()
@@ -637,7 +647,8 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
                 0
             } else {
-                emu.arm9.local_ex_mem_control().gba_rom_word(addr)
+                emu.gba_slot
+                    .read_rom_32(addr, emu.arm9.local_ex_mem_control())
             }
         }
 
@@ -645,7 +656,7 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
             if emu.global_ex_mem_control().arm7_gba_slot_access() {
                 0
             } else {
-                0xFFFF_FFFF
+                emu.gba_slot.read_ram(addr) as u32 * 0x0101_0101
             }
         }
 
@@ -673,10 +684,14 @@ pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32) -> u32
 pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8) {
     match addr >> 24 {
         #[cfg(feature = "bft-w")]
-        0x02 => unsafe {
-            emu.main_mem()
-                .write_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
-        },
+        0x02 => {
+            // Main memory is shared with the ARM7, which might have code cached there
+            emu.arm7.engine_data.invalidate_word(addr);
+            unsafe {
+                emu.main_mem()
+                    .write_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
+            }
+        }
 
         #[cfg(feature = "bft-w")]
         0x03 => unsafe {
@@ -868,9 +883,11 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
                 &mut emu.arm9,
                 &mut emu.gpu.engine_3d,
             ),
-            0x247 => emu
-                .swram
-                .write_control(swram::Control(value), &mut emu.arm7, &mut emu.arm9),
+            0x247 => {
+                emu.swram
+                    .write_control(swram::Control(value), &mut emu.arm7, &mut emu.arm9);
+                Nwram::recalc(emu);
+            }
             0x248 => emu
                 .gpu
                 .vram
@@ -900,6 +917,11 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
                 emu.gpu.engine_2d_b.write_8::<A>(addr, value);
             }
 
+            0x4000..=0x4FFF => {
+                let shift = (addr & 3) << 3;
+                Dsi::write_arm9_io::<A, _>(emu, addr, (value as u32) << shift, 0xFF << shift);
+            }
+
             _ =>
             {
                 #[cfg(feature = "log")]
@@ -914,6 +936,19 @@ pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32, value: u8)
             }
         },
 
+        0x08 | 0x09 => {
+            if !emu.global_ex_mem_control().arm7_gba_slot_access() {
+                // 8-bit writes show up on both halves of the 16-bit bus
+                emu.gba_slot.write_rom_16(addr, value as u16 * 0x0101);
+            }
+        }
+
+        0x0A => {
+            if !emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_ram(addr, value);
+            }
+        }
+
         _ =>
         {
             #[cfg(feature = "log")]
@@ -934,10 +969,14 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
     addr &= !1;
     match addr >> 24 {
         #[cfg(feature = "bft-w")]
-        0x02 => unsafe {
-            emu.main_mem()
-                .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
-        },
+        0x02 => {
+            // Main memory is shared with the ARM7, which might have code cached there
+            emu.arm7.engine_data.invalidate_word(addr);
+            unsafe {
+                emu.main_mem()
+                    .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
+            }
+        }
 
         #[cfg(feature = "bft-w")]
         0x03 => unsafe {
@@ -1264,6 +1303,7 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
                         &mut emu.arm7,
                         &mut emu.arm9,
                     );
+                    Nwram::recalc(emu);
                 }
                 0x248 => {
                     emu.gpu
@@ -1301,6 +1341,11 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
                     emu.gpu.engine_2d_b.write_16::<A>(addr, value);
                 }
 
+                0x4000..=0x4FFE => {
+                    let shift = (addr & 2) << 3;
+                    Dsi::write_arm9_io::<A, _>(emu, addr, (value as u32) << shift, 0xFFFF << shift);
+                }
+
                 _ =>
                 {
                     #[cfg(feature = "log")]
@@ -1328,6 +1373,18 @@ pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, value
 
         0x07 => emu.gpu.vram.write_oam(addr & 0x7FE, value),
 
+        0x08 | 0x09 => {
+            if !emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_rom_16(addr, value);
+            }
+        }
+
+        0x0A => {
+            if !emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_ram(addr, value as u8);
+            }
+        }
+
         _ =>
         {
             #[cfg(feature = "log")]
@@ -1348,10 +1405,14 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, mut v
     addr &= !3;
     match addr >> 24 {
         #[cfg(feature = "bft-w")]
-        0x02 => unsafe {
-            emu.main_mem()
-                .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
-        },
+        0x02 => {
+            // Main memory is shared with the ARM7, which might have code cached there
+            emu.arm7.engine_data.invalidate_word(addr);
+            unsafe {
+                emu.main_mem()
+                    .write_le_unchecked((addr & emu.main_mem_mask().get()) as usize, value);
+            }
+        }
 
         #[cfg(feature = "bft-w")]
         0x03 => unsafe {
@@ -1583,6 +1644,7 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, mut v
                         &mut emu.arm7,
                         &mut emu.arm9,
                     );
+                    Nwram::recalc(emu);
                 }
                 0x248 => {
                     emu.gpu
@@ -1642,6 +1704,26 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, mut v
                     emu.gpu.engine_2d_b.write_32::<A>(addr, value);
                 }
 
+                0x4000..=0x4FFC => Dsi::write_arm9_io::<A, _>(emu, addr, value, 0xFFFF_FFFF),
+
+                0x10_0010 => {
+                    if emu.ds_slot.arm9_access() {
+                        emu.ds_slot.write_rom_data_arm9(
+                            value,
+                            &mut emu.arm9.irqs,
+                            &mut emu.arm9.schedule,
+                        );
+                    } else {
+                        #[cfg(feature = "log")]
+                        if !A::IS_DEBUG {
+                            slog::warn!(
+                                emu.arm9.logger,
+                                "Tried to write to DS slot ROM data while inaccessible"
+                            );
+                        }
+                    }
+                }
+
                 _ =>
                 {
                     #[cfg(feature = "log")]
@@ -1669,6 +1751,19 @@ pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, mut addr: u32, mut v
 
         0x07 => emu.gpu.vram.write_oam(addr & 0x7FC, value),
 
+        0x08 | 0x09 => {
+            if !emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_rom_16(addr, value as u16);
+                emu.gba_slot.write_rom_16(addr | 2, (value >> 16) as u16);
+            }
+        }
+
+        0x0A => {
+            if !emu.global_ex_mem_control().arm7_gba_slot_access() {
+                emu.gba_slot.write_ram(addr, value as u8);
+            }
+        }
+
         _ =>
         {
             #[cfg(feature = "log")]
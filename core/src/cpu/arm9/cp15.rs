@@ -283,6 +283,11 @@ impl Cp15 {
         self.dtcm_control
     }
 
+    #[inline]
+    pub fn dtcm_mode(&self) -> TcmMode {
+        self.dtcm_mode
+    }
+
     #[inline]
     pub fn dtcm_bounds(&self) -> (u32, u32) {
         self.dtcm_bounds
@@ -305,6 +310,11 @@ impl Cp15 {
         self.itcm_control
     }
 
+    #[inline]
+    pub fn itcm_mode(&self) -> TcmMode {
+        self.itcm_mode
+    }
+
     #[inline]
     pub fn itcm_upper_bound(&self) -> u32 {
         self.itcm_upper_bound
@@ -429,6 +439,16 @@ impl Cp15 {
 
         emu.arm9.cp15.restore_tcm();
         Self::remap_all_pu_regions(emu);
+
+        // Engine data isn't necessarily restored from the savestate, so any state derived from
+        // the control register needs to be recalculated
+        let control = emu.arm9.cp15.control;
+        emu.arm9
+            .engine_data
+            .set_high_exc_vectors(control.high_exc_vectors());
+        emu.arm9
+            .engine_data
+            .set_t_bit_load_disabled(control.t_bit_load_disabled());
     }
 
     #[allow(clippy::similar_names)]
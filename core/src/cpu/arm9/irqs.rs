@@ -34,19 +34,28 @@ proc_bitfield::bitfield! {
         pub dma3: bool @ 11,                        // x
         pub keypad: bool @ 12,                      // x
         pub gba_slot_ext: bool @ 13,                // -
+        pub camera: bool @ 14,                      // -
         pub ipc_sync: bool @ 16,                    // x
         pub ipc_send_fifo_empty: bool @ 17,         // x
         pub ipc_recv_fifo_not_empty: bool @ 18,     // x
         pub ds_slot_transfer_complete: bool @ 19,   // x
         pub ds_slot_ext: bool @ 20,                 // -
         pub gx_fifo: bool @ 21,                     // x
+        pub ndma0: bool @ 28,                       // x
+        pub ndma1: bool @ 29,                       // x
+        pub ndma2: bool @ 30,                       // x
+        pub ndma3: bool @ 31,                       // x
     }
 }
 
+const DS_IRQ_MASK: u32 = 0x003F_3F7F;
+const DSI_IRQ_MASK: u32 = 0xF03F_7F7F;
+
 #[derive(Savestate)]
 pub struct Irqs {
     enabled: IrqFlags,
     requested: IrqFlags,
+    mask: u32,
     master_enable: bool,
     halted: bool,
     cpu_irq_line: bool,
@@ -59,6 +68,7 @@ impl Irqs {
         Irqs {
             enabled: IrqFlags(0),
             requested: IrqFlags(0),
+            mask: DS_IRQ_MASK,
             master_enable: false,
             halted: false,
             cpu_irq_line: false,
@@ -141,15 +151,28 @@ impl Irqs {
         }
     }
 
+    /// Switches between the DS and DSi sets of IRQ sources, as selected by `SCFG_EXT9`.
+    pub(crate) fn set_dsi_sources_enabled<S: ScheduleUpdate>(&mut self, value: bool, schedule: S) {
+        self.mask = if value { DSI_IRQ_MASK } else { DS_IRQ_MASK };
+        self.enabled.0 &= self.mask;
+        self.requested.0 &= self.mask;
+        self.update_pending(schedule);
+    }
+
+    #[inline]
+    pub fn dsi_sources_enabled(&self) -> bool {
+        self.mask == DSI_IRQ_MASK
+    }
+
     #[inline]
     pub fn write_enabled<S: ScheduleUpdate>(&mut self, value: IrqFlags, schedule: S) {
-        self.enabled = IrqFlags(value.0 & 0x003F_3F7F);
+        self.enabled = IrqFlags(value.0 & self.mask);
         self.update_pending(schedule);
     }
 
     #[inline]
     pub fn write_requested<S: ScheduleUpdate>(&mut self, value: IrqFlags, schedule: S) {
-        self.requested = IrqFlags(value.0 & 0x003F_3F7F);
+        self.requested = IrqFlags(value.0 & self.mask);
         self.update_pending(schedule);
     }
 
@@ -1,4 +1,7 @@
-use super::common;
+use super::{
+    common::{self, CallbackAction, CallbackUncomp, UncompFormat},
+    dsi,
+};
 use crate::{
     cpu::{
         arm7::{self, bus},
@@ -138,6 +141,12 @@ static VOLUME_TABLE: [u8; 0x2D4] = [
 pub const BIOS_CALL_INSTR: u32 = 0xE60_B105_0;
 pub const BIOS_CALL_INSTR_MASK: u32 = 0xFFFF_FFF0;
 
+const BX_LR_INSTR: u32 = 0xE12F_FF1E;
+
+/// The addresses of the boot procedures returned by `GetBootProcs`; they're only called by the
+/// firmware to load the cartridge, which never runs with the HLE BIOS, so they just return.
+const BOOT_PROC_ADDRS: [u32; 3] = [0x020C, 0x0210, 0x0214];
+
 pub static BIOS: [u8; arm7::BIOS_SIZE] = {
     let mut bytes = [0; arm7::BIOS_SIZE];
 
@@ -161,65 +170,75 @@ pub static BIOS: [u8; arm7::BIOS_SIZE] = {
     write_32!(0x0018, BIOS_CALL_INSTR | 1);
     write_32!(0x0200, BIOS_CALL_INSTR);
     write_32!(0x0204, BIOS_CALL_INSTR | 2);
+    write_32!(0x0208, BIOS_CALL_INSTR | 5);
+    write_32!(BOOT_PROC_ADDRS[0] as usize, BX_LR_INSTR);
+    write_32!(BOOT_PROC_ADDRS[1] as usize, BX_LR_INSTR);
+    write_32!(BOOT_PROC_ADDRS[2] as usize, BX_LR_INSTR);
     write_32!(0x2DDC, BIOS_CALL_INSTR | 4);
 
     bytes
 };
 
-fn bit_unpack<E: Engine>(
-    _emu: &mut Emu<E>,
-    _src_addr: u32,
-    _dst_addr: u32,
-    _unpack_data_addr: u32,
-) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(_emu.arm7.logger, "Unimplemented BitUnPack SWI");
-}
+struct CpuBus<'a, E: Engine>(&'a mut Emu<E>);
 
-fn huff_uncomp_read_callback<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm7.logger,
-        "Unimplemented HuffUnCompReadByCallback SWI"
-    );
-}
+impl<E: Engine> common::Bus for CpuBus<'_, E> {
+    fn read_8(&mut self, addr: u32) -> u8 {
+        bus::read_8::<CpuAccess, _>(self.0, addr)
+    }
 
-fn lz77_uncomp_read_normal_write_8<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm7.logger,
-        "Unimplemented LZ77UnCompReadNormalWrite8bit SWI"
-    );
+    fn read_16(&mut self, addr: u32) -> u16 {
+        bus::read_16::<CpuAccess, _>(self.0, addr)
+    }
+
+    fn read_32(&mut self, addr: u32) -> u32 {
+        bus::read_32::<CpuAccess, _>(self.0, addr)
+    }
+
+    fn write_8(&mut self, addr: u32, value: u8) {
+        bus::write_8::<CpuAccess, _>(self.0, addr, value);
+    }
+
+    fn write_16(&mut self, addr: u32, value: u16) {
+        bus::write_16::<CpuAccess, _>(self.0, addr, value);
+    }
+
+    fn write_32(&mut self, addr: u32, value: u32) {
+        bus::write_32::<CpuAccess, _>(self.0, addr, value);
+    }
 }
 
-fn lz77_uncomp_read_callback_write_16<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm7.logger,
-        "Unimplemented LZ77UnCompReadByCallbackWrite16bit SWI"
-    );
+fn handle_callback_action<E: Engine>(emu: &mut Emu<E>, action: CallbackAction) {
+    let mut r0_3 = emu.arm7.hle_bios.swi_r0_3;
+    match action {
+        CallbackAction::Call { addr, r0 } => {
+            r0_3[0] = r0;
+            E::Arm7Data::call_from_hle_swi(emu, addr, r0_3, 0x0000_0208);
+        }
+        CallbackAction::Return(r0) => {
+            r0_3[0] = r0;
+            E::Arm7Data::return_from_hle_swi(emu, r0_3);
+        }
+    }
 }
 
-fn rl_uncomp_read_normal_write_8<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm7.logger,
-        "Unimplemented RLUnCompReadNormalWrite8bit SWI"
-    );
+fn uncomp_read_callback<E: Engine>(
+    emu: &mut Emu<E>,
+    format: UncompFormat,
+    write_16: bool,
+    r0_3: [u32; 4],
+) {
+    emu.arm7.hle_bios.swi_r0_3 = r0_3;
+    let mut callback_uncomp = core::mem::take(&mut emu.arm7.hle_bios.callback_uncomp);
+    let action = callback_uncomp.start(&mut CpuBus(emu), format, write_16, r0_3);
+    emu.arm7.hle_bios.callback_uncomp = callback_uncomp;
+    handle_callback_action(emu, action);
 }
 
-fn rl_uncomp_read_callback_write_16<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm7.logger,
-        "Unimplemented RLUnCompReadByCallbackWrite16bit SWI"
-    );
+pub fn resume_swi_callback<E: Engine>(emu: &mut Emu<E>, result: u32) {
+    let mut callback_uncomp = core::mem::take(&mut emu.arm7.hle_bios.callback_uncomp);
+    let action = callback_uncomp.resume(&mut CpuBus(emu), result);
+    emu.arm7.hle_bios.callback_uncomp = callback_uncomp;
+    handle_callback_action(emu, action);
 }
 
 fn cpu_set<E: Engine>(emu: &mut Emu<E>, r0_3: [u32; 4]) -> [u32; 2] {
@@ -331,10 +350,18 @@ pub fn resume_intr_wait<E: Engine>(emu: &mut Emu<E>) {
     }
 }
 
-fn soft_reset<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(_emu.arm7.logger, "Unimplemented SoftReset SWI");
+fn soft_reset<E: Engine>(emu: &mut Emu<E>) {
+    for addr in (0x0380_FE00..0x0381_0000).step_by(4) {
+        bus::write_32::<CpuAccess, _>(emu, addr, 0);
+    }
+    // The header is copied to the end of the 16 MiB of main memory when running in DSi mode
+    let header_base = if common::dsi_swis_enabled(emu) {
+        0x02FF_FE00
+    } else {
+        0x027F_FE00
+    };
+    let entry_addr = bus::read_32::<CpuAccess, _>(emu, header_base | 0x34);
+    E::Arm7Data::setup_direct_boot(emu, entry_addr);
 }
 
 fn wait_by_loop<E: Engine>(emu: &mut Emu<E>, iterations: i32) -> u32 {
@@ -404,16 +431,16 @@ fn sound_bias<E: Engine>(emu: &mut Emu<E>, value: u32, delay: i32) {
     }
 }
 
-fn get_boot_procs<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(_emu.arm7.logger, "Unimplemented GetBootProcs SWI");
+fn get_boot_procs() -> (u32, u32, u32) {
+    let [r0, r1, r2] = BOOT_PROC_ADDRS;
+    (r0, r1, r2)
 }
 
 #[derive(Savestate)]
 pub struct State {
     pub enabled: bool,
     swi_r0_3: [u32; 4],
+    callback_uncomp: CallbackUncomp,
 }
 
 impl State {
@@ -421,12 +448,13 @@ impl State {
         State {
             enabled,
             swi_r0_3: [0; 4],
+            callback_uncomp: CallbackUncomp::new(),
         }
     }
 }
 
 #[cfg(feature = "log")]
-static SWI_NAMES: [&str; 0x20] = [
+static SWI_NAMES: [&str; 0x2A] = [
     "SoftReset",
     "?",
     "?",
@@ -459,6 +487,16 @@ static SWI_NAMES: [&str; 0x20] = [
     "GetBootProcs",
     "",
     "CustomHalt",
+    "RSA_Init_crypto_heap",
+    "RSA_Decrypt",
+    "RSA_Decrypt_Unpad",
+    "RSA_Decrypt_Unpad_OpenPGP_SHA1",
+    "SHA1_Init",
+    "SHA1_Update",
+    "SHA1_Finish",
+    "SHA1_Init_update_fin",
+    "SHA1_Compare_20_bytes",
+    "SHA1_Random_maybe",
 ];
 
 pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
@@ -467,11 +505,14 @@ pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
         emu.arm7.logger,
         "SWI {:#04X} ({})",
         number,
-        SWI_NAMES[number as usize]
+        SWI_NAMES.get(number as usize).unwrap_or(&"?")
     );
 
     match number {
-        0x00 => soft_reset(emu),
+        0x00 => {
+            soft_reset(emu);
+            return;
+        }
 
         0x03 => r0_3[0] = wait_by_loop(emu, r0_3[0] as i32),
 
@@ -514,17 +555,30 @@ pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
 
         0x0F => (r0_3[0], r0_3[1], r0_3[3]) = common::is_debugger::<_, 0x7F_FFFA>(emu),
 
-        0x10 => bit_unpack(emu, r0_3[0], r0_3[1], r0_3[2]),
+        0x10 => common::bit_unpack(&mut CpuBus(emu), r0_3[0], r0_3[1], r0_3[2]),
 
-        0x11 => lz77_uncomp_read_normal_write_8(emu),
+        0x11 => {
+            common::uncomp_read_normal(&mut CpuBus(emu), UncompFormat::Lz77, r0_3[0], r0_3[1]);
+        }
 
-        0x12 => lz77_uncomp_read_callback_write_16(emu),
+        0x12 => {
+            uncomp_read_callback(emu, UncompFormat::Lz77, true, r0_3);
+            return;
+        }
 
-        0x13 => huff_uncomp_read_callback(emu),
+        0x13 => {
+            uncomp_read_callback(emu, UncompFormat::Huffman, false, r0_3);
+            return;
+        }
 
-        0x14 => rl_uncomp_read_normal_write_8(emu),
+        0x14 => {
+            common::uncomp_read_normal(&mut CpuBus(emu), UncompFormat::Rl, r0_3[0], r0_3[1]);
+        }
 
-        0x15 => rl_uncomp_read_callback_write_16(emu),
+        0x15 => {
+            uncomp_read_callback(emu, UncompFormat::Rl, true, r0_3);
+            return;
+        }
 
         // TODO: r3 value
         0x1A => (r0_3[0], r0_3[1]) = get_sine_table(r0_3[0]),
@@ -535,12 +589,24 @@ pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
         // TODO: r3 value
         0x1C => (r0_3[0], r0_3[1]) = get_volume_table(r0_3[0]),
 
-        0x1D => get_boot_procs(emu),
+        0x1D => (r0_3[0], r0_3[1], r0_3[2]) = get_boot_procs(),
 
         0x1F => custom_halt(emu, r0_3[2] as u8),
 
+        0x20..=0x29 if common::dsi_swis_enabled(emu) => {
+            r0_3[0] = dsi::handle_swi(&mut CpuBus(emu), number, r0_3);
+        }
+
+        // The remaining entries in the SWI table point to a function that returns immediately,
+        // while numbers past its end jump to garbage
+        0x01 | 0x02 | 0x0A | 0x16..=0x19 | 0x1E => {
+            #[cfg(feature = "log")]
+            slog::warn!(emu.arm7.logger, "Unused SWI {:#04X}", number);
+        }
+
         _ => {
-            unimplemented!("Invalid ARM7 SWI {:#X}", number);
+            #[cfg(feature = "log")]
+            slog::error!(emu.arm7.logger, "Invalid SWI {:#04X}", number);
         }
     }
 
@@ -1,4 +1,7 @@
-use super::common;
+use super::{
+    common::{self, CallbackAction, CallbackUncomp, UncompFormat},
+    dsi,
+};
 use crate::{
     cpu::{
         arm9::{self, bus},
@@ -37,91 +40,80 @@ pub static BIOS: [u8; arm9::BIOS_SIZE] = {
     write_32!(0x018, BIOS_CALL_INSTR | 1);
     write_32!(0x200, BIOS_CALL_INSTR);
     write_32!(0x204, BIOS_CALL_INSTR | 2);
+    write_32!(0x208, BIOS_CALL_INSTR | 5);
     write_32!(0x298, BIOS_CALL_INSTR | 4);
 
     bytes
 };
 
-fn bit_unpack<E: Engine>(
-    _emu: &mut Emu<E>,
-    _src_addr: u32,
-    _dst_addr: u32,
-    _unpack_data_addr: u32,
-) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(_emu.arm9.logger, "Unimplemented BitUnPack SWI");
-}
+struct CpuBus<'a, E: Engine>(&'a mut Emu<E>);
 
-fn diff_8_unfilter_write_8<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented Diff8bitUnFilterWrite8bit SWI"
-    );
-}
+impl<E: Engine> common::Bus for CpuBus<'_, E> {
+    fn read_8(&mut self, addr: u32) -> u8 {
+        bus::read_8::<CpuAccess, _>(self.0, addr)
+    }
 
-fn diff_16_unfilter<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented Diff8bitUnFilterWrite16bit SWI"
-    );
-}
+    fn read_16(&mut self, addr: u32) -> u16 {
+        bus::read_16::<CpuAccess, _>(self.0, addr)
+    }
 
-fn huff_uncomp_read_callback<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented HuffUnCompReadByCallback SWI"
-    );
-}
+    fn read_32(&mut self, addr: u32) -> u32 {
+        bus::read_32::<CpuAccess, _, false>(self.0, addr)
+    }
 
-fn lz77_uncomp_read_normal_write_8<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented LZ77UnCompReadNormalWrite8bit SWI"
-    );
+    fn write_8(&mut self, addr: u32, value: u8) {
+        bus::write_8::<CpuAccess, _>(self.0, addr, value);
+    }
+
+    fn write_16(&mut self, addr: u32, value: u16) {
+        bus::write_16::<CpuAccess, _>(self.0, addr, value);
+    }
+
+    fn write_32(&mut self, addr: u32, value: u32) {
+        bus::write_32::<CpuAccess, _>(self.0, addr, value);
+    }
 }
 
-fn lz77_uncomp_read_callback_write_16<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented LZ77UnCompReadByCallbackWrite16bit SWI"
-    );
+fn handle_callback_action<E: Engine>(emu: &mut Emu<E>, action: CallbackAction) {
+    let mut r0_3 = emu.arm9.hle_bios.swi_r0_3;
+    match action {
+        CallbackAction::Call { addr, r0 } => {
+            r0_3[0] = r0;
+            E::Arm9Data::call_from_hle_swi(emu, addr, r0_3, 0xFFFF_0208);
+        }
+        CallbackAction::Return(r0) => {
+            r0_3[0] = r0;
+            E::Arm9Data::return_from_hle_swi(emu, r0_3);
+        }
+    }
 }
 
-fn rl_uncomp_read_normal_write_8<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented RLUnCompReadNormalWrite8bit SWI"
-    );
+fn uncomp_read_callback<E: Engine>(
+    emu: &mut Emu<E>,
+    format: UncompFormat,
+    write_16: bool,
+    r0_3: [u32; 4],
+) {
+    emu.arm9.hle_bios.swi_r0_3 = r0_3;
+    let mut callback_uncomp = core::mem::take(&mut emu.arm9.hle_bios.callback_uncomp);
+    let action = callback_uncomp.start(&mut CpuBus(emu), format, write_16, r0_3);
+    emu.arm9.hle_bios.callback_uncomp = callback_uncomp;
+    handle_callback_action(emu, action);
 }
 
-fn rl_uncomp_read_callback_write_16<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(
-        _emu.arm9.logger,
-        "Unimplemented RLUnCompReadByCallbackWrite16bit SWI"
-    );
+pub fn resume_swi_callback<E: Engine>(emu: &mut Emu<E>, result: u32) {
+    let mut callback_uncomp = core::mem::take(&mut emu.arm9.hle_bios.callback_uncomp);
+    let action = callback_uncomp.resume(&mut CpuBus(emu), result);
+    emu.arm9.hle_bios.callback_uncomp = callback_uncomp;
+    handle_callback_action(emu, action);
 }
 
 fn cpu_set<E: Engine>(emu: &mut Emu<E>, r0_3: [u32; 4]) -> [u32; 2] {
     let mut src_addr = r0_3[0];
     let mut dst_addr = r0_3[1];
-    let units = r0_3[3] & 0xF_FFFF;
-    let fixed = r0_3[3] & 1 << 24 != 0;
-    if r0_3[3] & 1 << 26 != 0 {
+    let units = r0_3[2] & 0xF_FFFF;
+    let fixed = r0_3[2] & 1 << 24 != 0;
+    if r0_3[2] & 1 << 26 != 0 {
         if fixed {
             let value = bus::read_32::<CpuAccess, _, false>(emu, src_addr);
             for _ in 0..units {
@@ -158,8 +150,8 @@ fn cpu_set<E: Engine>(emu: &mut Emu<E>, r0_3: [u32; 4]) -> [u32; 2] {
 fn cpu_fast_set<E: Engine>(emu: &mut Emu<E>, r0_3: [u32; 4]) -> (u32, u32, u32) {
     let mut src_addr = r0_3[0];
     let mut dst_addr = r0_3[1];
-    let units = r0_3[3] & 0xF_FFFF;
-    let fixed = r0_3[3] & 1 << 24 != 0;
+    let units = r0_3[2] & 0xF_FFFF;
+    let fixed = r0_3[2] & 1 << 24 != 0;
     let mut r3 = r0_3[3];
     if fixed {
         let value = bus::read_32::<CpuAccess, _, false>(emu, src_addr);
@@ -228,10 +220,23 @@ pub fn resume_intr_wait<E: Engine>(emu: &mut Emu<E>) {
     }
 }
 
-fn soft_reset<E: Engine>(_emu: &mut Emu<E>) {
-    // TODO
-    #[cfg(feature = "log")]
-    slog::error!(_emu.arm9.logger, "Unimplemented SoftReset SWI");
+fn soft_reset<E: Engine>(emu: &mut Emu<E>) {
+    let dtcm_top = emu
+        .arm9
+        .cp15
+        .dtcm_control()
+        .base_addr()
+        .wrapping_add(0x4000);
+    for addr in (dtcm_top.wrapping_sub(0x200)..dtcm_top).step_by(4) {
+        bus::write_32::<CpuAccess, _>(emu, addr, 0);
+    }
+    let header_base = if common::dsi_swis_enabled(emu) {
+        0x02FF_FE00
+    } else {
+        0x027F_FE00
+    };
+    let entry_addr = bus::read_32::<CpuAccess, _, false>(emu, header_base | 0x24);
+    E::Arm9Data::setup_direct_boot(emu, entry_addr);
 }
 
 fn wait_by_loop<E: Engine>(emu: &mut Emu<E>, iterations: i32) -> u32 {
@@ -246,6 +251,7 @@ pub struct State {
     pub enabled: bool,
     swi_r0_3: [u32; 4],
     intr_wait_mask: u32,
+    callback_uncomp: CallbackUncomp,
 }
 
 impl State {
@@ -254,12 +260,13 @@ impl State {
             enabled,
             swi_r0_3: [0; 4],
             intr_wait_mask: 0,
+            callback_uncomp: CallbackUncomp::new(),
         }
     }
 }
 
 #[cfg(feature = "log")]
-static SWI_NAMES: [&str; 0x20] = [
+static SWI_NAMES: [&str; 0x2A] = [
     "SoftReset",
     "?",
     "?",
@@ -292,6 +299,16 @@ static SWI_NAMES: [&str; 0x20] = [
     "?",
     "",
     "CustomPost",
+    "RSA_Init_crypto_heap",
+    "RSA_Decrypt",
+    "RSA_Decrypt_Unpad",
+    "RSA_Decrypt_Unpad_OpenPGP_SHA1",
+    "SHA1_Init",
+    "SHA1_Update",
+    "SHA1_Finish",
+    "SHA1_Init_update_fin",
+    "SHA1_Compare_20_bytes",
+    "SHA1_Random_maybe",
 ];
 
 pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
@@ -300,11 +317,14 @@ pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
         emu.arm9.logger,
         "SWI {:#04X} ({})",
         number,
-        SWI_NAMES[number as usize]
+        SWI_NAMES.get(number as usize).unwrap_or(&"?")
     );
 
     match number {
-        0x00 => soft_reset(emu),
+        0x00 => {
+            soft_reset(emu);
+            return;
+        }
 
         0x03 => r0_3[0] = wait_by_loop(emu, r0_3[0] as i32),
 
@@ -343,28 +363,53 @@ pub fn handle_swi<E: Engine>(emu: &mut Emu<E>, number: u8, mut r0_3: [u32; 4]) {
 
         0x0F => (r0_3[0], r0_3[1], r0_3[3]) = common::is_debugger::<_, 0x7F_FFF8>(emu),
 
-        0x10 => bit_unpack(emu, r0_3[0], r0_3[1], r0_3[2]),
+        0x10 => common::bit_unpack(&mut CpuBus(emu), r0_3[0], r0_3[1], r0_3[2]),
 
-        0x11 => lz77_uncomp_read_normal_write_8(emu),
+        0x11 => {
+            common::uncomp_read_normal(&mut CpuBus(emu), UncompFormat::Lz77, r0_3[0], r0_3[1]);
+        }
 
-        0x12 => lz77_uncomp_read_callback_write_16(emu),
+        0x12 => {
+            uncomp_read_callback(emu, UncompFormat::Lz77, true, r0_3);
+            return;
+        }
 
-        0x13 => huff_uncomp_read_callback(emu),
+        0x13 => {
+            uncomp_read_callback(emu, UncompFormat::Huffman, false, r0_3);
+            return;
+        }
 
-        0x14 => rl_uncomp_read_normal_write_8(emu),
+        0x14 => {
+            common::uncomp_read_normal(&mut CpuBus(emu), UncompFormat::Rl, r0_3[0], r0_3[1]);
+        }
 
-        0x15 => rl_uncomp_read_callback_write_16(emu),
+        0x15 => {
+            uncomp_read_callback(emu, UncompFormat::Rl, true, r0_3);
+            return;
+        }
 
-        0x16 => diff_8_unfilter_write_8(emu),
+        0x16 => common::diff_8_unfilter_write_8(&mut CpuBus(emu), r0_3[0], r0_3[1]),
 
-        0x18 => diff_16_unfilter(emu),
+        0x18 => common::diff_16_unfilter(&mut CpuBus(emu), r0_3[0], r0_3[1]),
 
         0x1F => {
             bus::write_32::<CpuAccess, _>(emu, 0x0400_0300, r0_3[0]);
         }
 
+        0x20..=0x29 if common::dsi_swis_enabled(emu) => {
+            r0_3[0] = dsi::handle_swi(&mut CpuBus(emu), number, r0_3);
+        }
+
+        // The remaining entries in the SWI table point to a function that returns immediately,
+        // while numbers past its end jump to garbage
+        0x01 | 0x02 | 0x07 | 0x08 | 0x0A | 0x17 | 0x19..=0x1E => {
+            #[cfg(feature = "log")]
+            slog::warn!(emu.arm9.logger, "Unused SWI {:#04X}", number);
+        }
+
         _ => {
-            unimplemented!("Invalid ARM9 SWI {:#X}", number);
+            #[cfg(feature = "log")]
+            slog::error!(emu.arm9.logger, "Invalid SWI {:#04X}", number);
         }
     }
 
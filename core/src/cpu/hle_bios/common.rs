@@ -1,4 +1,9 @@
-use crate::{cpu::Engine, emu::Emu, utils::mem_prelude::*};
+use crate::{
+    cpu::Engine,
+    emu::Emu,
+    utils::{mem_prelude::*, Savestate},
+    Model,
+};
 
 pub fn div(numer: u32, denom: u32) -> (u32, u32, u32) {
     if denom == 0 {
@@ -48,13 +53,425 @@ pub fn crc16(init: u32, len: u32, r3: u32, mut f: impl FnMut() -> u16) -> (u32,
 }
 
 pub fn is_debugger<E: Engine, const ADDR: usize>(emu: &Emu<E>) -> (u32, u32, u32) {
-    emu.main_mem().write_le::<u16>(
-        ADDR & emu.main_mem_mask().get() as usize,
-        emu.is_debugger() as u16,
-    );
-    (
-        emu.is_debugger() as u32,
-        (emu.is_debugger() as u32) << 2,
-        0x027F_FFE0,
-    )
+    // The BIOS detects the debugger's 8 MB of main memory by checking whether the flag's address
+    // is mirrored 4 MB below it, so the result also depends on the amount of main memory currently
+    // mapped (i.e. DSi mode is detected as a debugger too)
+    let mask = emu.main_mem_mask().get() as usize;
+    let is_debugger = ADDR & mask != (ADDR - 0x40_0000) & mask;
+    emu.main_mem()
+        .write_le::<u16>(ADDR & mask, is_debugger as u16);
+    (is_debugger as u32, (is_debugger as u32) << 2, 0x027F_FFE0)
+}
+
+/// Returns whether the DSi-exclusive SWIs (0x20-0x29) are present, i.e. whether a DSi is running a
+/// title in DSi mode.
+pub fn dsi_swis_enabled<E: Engine>(emu: &Emu<E>) -> bool {
+    emu.model() == Model::Dsi && !emu.dsi.scfg.rom_control().arm7_ds_bios()
+}
+
+/// The memory accesses performed by BIOS functions whose implementation is shared between both
+/// CPUs.
+pub trait Bus {
+    fn read_8(&mut self, addr: u32) -> u8;
+    fn read_16(&mut self, addr: u32) -> u16;
+    fn read_32(&mut self, addr: u32) -> u32;
+    fn write_8(&mut self, addr: u32, value: u8);
+    fn write_16(&mut self, addr: u32, value: u16);
+    fn write_32(&mut self, addr: u32, value: u32);
+}
+
+pub fn bit_unpack(bus: &mut impl Bus, mut src_addr: u32, mut dst_addr: u32, info_addr: u32) {
+    let src_len = bus.read_16(info_addr);
+    let src_width = bus.read_8(info_addr.wrapping_add(2)) as u32;
+    let dst_width = bus.read_8(info_addr.wrapping_add(3)) as u32;
+    let offset = bus.read_32(info_addr.wrapping_add(4));
+    let offset_zero = offset & 1 << 31 != 0;
+    let offset = offset & 0x7FFF_FFFF;
+
+    if !matches!(src_width, 1 | 2 | 4 | 8) || !matches!(dst_width, 1 | 2 | 4 | 8 | 16 | 32) {
+        // The BIOS doesn't validate the widths, and produces garbage (or hangs) when they're
+        // invalid
+        return;
+    }
+
+    let src_mask = (1 << src_width) - 1;
+    let mut output = 0_u32;
+    let mut output_bits = 0;
+    for _ in 0..src_len {
+        let byte = bus.read_8(src_addr) as u32;
+        src_addr = src_addr.wrapping_add(1);
+        for shift in (0..8).step_by(src_width as usize) {
+            let mut unit = byte >> shift & src_mask;
+            if unit != 0 || offset_zero {
+                unit = unit.wrapping_add(offset);
+            }
+            output |= unit << output_bits;
+            output_bits += dst_width;
+            if output_bits == 32 {
+                bus.write_32(dst_addr, output);
+                dst_addr = dst_addr.wrapping_add(4);
+                output = 0;
+                output_bits = 0;
+            }
+        }
+    }
+}
+
+pub fn diff_8_unfilter_write_8(bus: &mut impl Bus, mut src_addr: u32, mut dst_addr: u32) {
+    let header = bus.read_32(src_addr);
+    src_addr = src_addr.wrapping_add(4);
+    let mut value = 0_u8;
+    for _ in 0..header >> 8 {
+        value = value.wrapping_add(bus.read_8(src_addr));
+        src_addr = src_addr.wrapping_add(1);
+        bus.write_8(dst_addr, value);
+        dst_addr = dst_addr.wrapping_add(1);
+    }
+}
+
+pub fn diff_16_unfilter(bus: &mut impl Bus, mut src_addr: u32, mut dst_addr: u32) {
+    let header = bus.read_32(src_addr);
+    src_addr = src_addr.wrapping_add(4);
+    let mut value = 0_u16;
+    for _ in 0..header >> 9 {
+        value = value.wrapping_add(bus.read_16(src_addr));
+        src_addr = src_addr.wrapping_add(2);
+        bus.write_16(dst_addr, value);
+        dst_addr = dst_addr.wrapping_add(2);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Savestate)]
+pub enum UncompFormat {
+    Lz77,
+    Huffman,
+    Rl,
+}
+
+#[derive(Clone, Savestate)]
+struct HuffmanState {
+    data_bits: u8,
+    /// The tree table, including its leading size byte.
+    tree: [u8; 0x200],
+    tree_len: u16,
+    tree_bytes_read: u16,
+    node_index: u16,
+}
+
+/// A streaming decompressor for the BIOS's LZ77, Huffman and RL formats, fed one unit of
+/// compressed data at a time so that it can be driven by guest callbacks.
+#[derive(Clone, Savestate)]
+pub struct Uncomp {
+    format: UncompFormat,
+    write_16: bool,
+    dst_addr: u32,
+    remaining: u32,
+    /// The flags for the current group of LZ77 blocks, or the flag byte of the current RL block.
+    block_flags: u8,
+    /// The number of blocks left in the current LZ77 group, or of bytes left in the current RL
+    /// block.
+    block_len: u8,
+    lz77_ref_first_byte: Option<u8>,
+    /// Output bits that haven't been written yet, as writes are performed in halfwords (for the
+    /// 16-bit variants) or words (for Huffman-coded data).
+    output: u32,
+    output_bits: u8,
+    huffman: HuffmanState,
+}
+
+impl Uncomp {
+    pub fn new(format: UncompFormat, write_16: bool, dst_addr: u32) -> Self {
+        Uncomp {
+            format,
+            write_16,
+            dst_addr,
+            remaining: 0,
+            block_flags: 0,
+            block_len: 0,
+            lz77_ref_first_byte: None,
+            output: 0,
+            output_bits: 0,
+            huffman: HuffmanState {
+                data_bits: 0,
+                tree: [0; 0x200],
+                tree_len: 0,
+                tree_bytes_read: 0,
+                node_index: 0,
+            },
+        }
+    }
+
+    pub fn start(&mut self, header: u32) {
+        self.remaining = header >> 8;
+        if self.format == UncompFormat::Huffman {
+            self.huffman.data_bits = header as u8 & 0xF;
+            if !matches!(self.huffman.data_bits, 1 | 2 | 4 | 8) {
+                // Anything else would hang the BIOS
+                self.remaining = 0;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Returns whether the next unit of input should be a word, which is only the case for
+    /// Huffman-coded data after the tree has been read.
+    pub fn needs_word(&self) -> bool {
+        self.format == UncompFormat::Huffman
+            && self.huffman.tree_len != 0
+            && self.huffman.tree_bytes_read == self.huffman.tree_len
+    }
+
+    fn write_byte(&mut self, bus: &mut impl Bus, value: u8) {
+        if self.write_16 {
+            if self.dst_addr & 1 == 0 {
+                self.output = value as u32;
+            } else {
+                bus.write_16(
+                    self.dst_addr & !1,
+                    (self.output | (value as u32) << 8) as u16,
+                );
+            }
+        } else {
+            bus.write_8(self.dst_addr, value);
+        }
+        self.dst_addr = self.dst_addr.wrapping_add(1);
+        self.remaining -= 1;
+    }
+
+    fn read_back(&mut self, bus: &mut impl Bus, addr: u32) -> u8 {
+        if self.write_16 && self.dst_addr & 1 != 0 && addr == self.dst_addr.wrapping_sub(1) {
+            self.output as u8
+        } else {
+            bus.read_8(addr)
+        }
+    }
+
+    fn push_lz77(&mut self, bus: &mut impl Bus, value: u8) {
+        if self.block_len == 0 {
+            self.block_flags = value;
+            self.block_len = 8;
+            return;
+        }
+        if let Some(first_byte) = self.lz77_ref_first_byte.take() {
+            let disp = ((first_byte as u32 & 0xF) << 8 | value as u32) + 1;
+            for _ in 0..(first_byte >> 4) + 3 {
+                if self.remaining == 0 {
+                    break;
+                }
+                let value = self.read_back(bus, self.dst_addr.wrapping_sub(disp));
+                self.write_byte(bus, value);
+            }
+        } else if self.block_flags & 0x80 != 0 {
+            self.lz77_ref_first_byte = Some(value);
+            return;
+        } else {
+            self.write_byte(bus, value);
+        }
+        self.block_flags <<= 1;
+        self.block_len -= 1;
+    }
+
+    fn push_rl(&mut self, bus: &mut impl Bus, value: u8) {
+        if self.block_len == 0 {
+            self.block_flags = value;
+            self.block_len = if value & 0x80 != 0 {
+                (value & 0x7F) + 3
+            } else {
+                value + 1
+            };
+        } else if self.block_flags & 0x80 != 0 {
+            for _ in 0..self.block_len {
+                if self.remaining == 0 {
+                    break;
+                }
+                self.write_byte(bus, value);
+            }
+            self.block_len = 0;
+        } else {
+            self.write_byte(bus, value);
+            self.block_len -= 1;
+        }
+    }
+
+    fn push_huffman_tree(&mut self, value: u8) {
+        let huffman = &mut self.huffman;
+        if huffman.tree_len == 0 {
+            huffman.tree_len = (value as u16 + 1) << 1;
+            huffman.node_index = 1;
+        }
+        huffman.tree[huffman.tree_bytes_read as usize] = value;
+        huffman.tree_bytes_read += 1;
+    }
+
+    pub fn push_byte(&mut self, bus: &mut impl Bus, value: u8) {
+        match self.format {
+            UncompFormat::Lz77 => self.push_lz77(bus, value),
+            UncompFormat::Huffman => self.push_huffman_tree(value),
+            UncompFormat::Rl => self.push_rl(bus, value),
+        }
+    }
+
+    pub fn push_word(&mut self, bus: &mut impl Bus, value: u32) {
+        let data_mask = (1_u32 << self.huffman.data_bits) - 1;
+        for bit in (0..32).rev() {
+            let bit = value >> bit & 1;
+            let node_index = self.huffman.node_index;
+            let node = self.huffman.tree[node_index as usize];
+            let child_index =
+                ((node_index & !1) + ((node as u16 & 0x3F) << 1) + 2 + bit as u16) & 0x1FF;
+            if node & 0x80 >> bit == 0 {
+                self.huffman.node_index = child_index;
+                continue;
+            }
+            self.huffman.node_index = 1;
+            let data = self.huffman.tree[child_index as usize] as u32 & data_mask;
+            self.output |= data << self.output_bits;
+            self.output_bits += self.huffman.data_bits;
+            if self.output_bits == 32 {
+                bus.write_32(self.dst_addr, self.output);
+                self.dst_addr = self.dst_addr.wrapping_add(4);
+                self.remaining = self.remaining.saturating_sub(4);
+                self.output = 0;
+                self.output_bits = 0;
+                if self.remaining == 0 {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+pub fn uncomp_read_normal(
+    bus: &mut impl Bus,
+    format: UncompFormat,
+    mut src_addr: u32,
+    dst_addr: u32,
+) {
+    let mut uncomp = Uncomp::new(format, false, dst_addr);
+    uncomp.start(bus.read_32(src_addr));
+    src_addr = src_addr.wrapping_add(4);
+    while !uncomp.is_finished() {
+        let value = bus.read_8(src_addr);
+        src_addr = src_addr.wrapping_add(1);
+        uncomp.push_byte(bus, value);
+    }
+}
+
+/// What the CPU should do next while running a decompression function that reads its input
+/// through guest callbacks.
+pub enum CallbackAction {
+    /// Call the function at `addr` with the specified value in r0, and resume decompression with
+    /// its return value once it's done.
+    Call { addr: u32, r0: u32 },
+    /// Return from the SWI with the specified value in r0.
+    Return(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Savestate)]
+enum CallbackStage {
+    Open,
+    Read,
+    Close,
+}
+
+/// The state of a decompression function reading its input through guest callbacks, kept around
+/// while the callbacks run.
+///
+/// The callback structure passed in r3 contains pointers to functions to open the source (which
+/// receives r0-r2 and returns the header), close it (optional), and read 8 and 32 bits from it.
+#[derive(Clone, Savestate)]
+pub struct CallbackUncomp {
+    stage: CallbackStage,
+    callbacks_addr: u32,
+    src_addr: u32,
+    len: u32,
+    uncomp: Uncomp,
+}
+
+impl CallbackUncomp {
+    pub fn new() -> Self {
+        CallbackUncomp {
+            stage: CallbackStage::Open,
+            callbacks_addr: 0,
+            src_addr: 0,
+            len: 0,
+            uncomp: Uncomp::new(UncompFormat::Lz77, false, 0),
+        }
+    }
+
+    pub fn start(
+        &mut self,
+        bus: &mut impl Bus,
+        format: UncompFormat,
+        write_16: bool,
+        r0_3: [u32; 4],
+    ) -> CallbackAction {
+        self.stage = CallbackStage::Open;
+        self.callbacks_addr = r0_3[3];
+        self.src_addr = r0_3[0];
+        self.uncomp = Uncomp::new(format, write_16, r0_3[1]);
+        CallbackAction::Call {
+            addr: bus.read_32(self.callbacks_addr),
+            r0: self.src_addr,
+        }
+    }
+
+    fn next_action(&mut self, bus: &mut impl Bus) -> CallbackAction {
+        if self.uncomp.is_finished() {
+            let close_addr = bus.read_32(self.callbacks_addr.wrapping_add(4));
+            if close_addr == 0 {
+                return CallbackAction::Return(self.len);
+            }
+            self.stage = CallbackStage::Close;
+            return CallbackAction::Call {
+                addr: close_addr,
+                r0: self.src_addr,
+            };
+        }
+        self.stage = CallbackStage::Read;
+        let offset = if self.uncomp.needs_word() { 0x10 } else { 8 };
+        CallbackAction::Call {
+            addr: bus.read_32(self.callbacks_addr.wrapping_add(offset)),
+            r0: self.src_addr,
+        }
+    }
+
+    pub fn resume(&mut self, bus: &mut impl Bus, result: u32) -> CallbackAction {
+        match self.stage {
+            CallbackStage::Open => {
+                if (result as i32) < 0 {
+                    return CallbackAction::Return(result);
+                }
+                self.len = result >> 8;
+                self.uncomp.start(result);
+                self.src_addr = self.src_addr.wrapping_add(4);
+            }
+            CallbackStage::Read => {
+                if self.uncomp.needs_word() {
+                    self.uncomp.push_word(bus, result);
+                    self.src_addr = self.src_addr.wrapping_add(4);
+                } else {
+                    self.uncomp.push_byte(bus, result as u8);
+                    self.src_addr = self.src_addr.wrapping_add(1);
+                }
+            }
+            CallbackStage::Close => {
+                return CallbackAction::Return(if (result as i32) < 0 {
+                    result
+                } else {
+                    self.len
+                });
+            }
+        }
+        self.next_action(bus)
+    }
+}
+
+impl Default for CallbackUncomp {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,302 @@
+//! The SHA-1 and RSA functions added to both CPUs' BIOSes on the DSi, available as SWIs 0x20-0x29
+//! to titles running in DSi mode.
+//!
+//! SHA-1 contexts are opaque to callers, so they're stored in a format of our own choosing: the
+//! five state words, followed by the total message length as a 64-bit value and the 64-byte
+//! buffer for the current block, for a total of 0x5C bytes (the BIOS's own contexts take up 0x68).
+//! The crypto heap passed to the RSA functions isn't needed, and is left untouched.
+
+use super::common::Bus;
+use crate::dsi::sha1::Sha1;
+
+const SHA1_CTX_STATE: u32 = 0;
+const SHA1_CTX_LEN: u32 = 0x14;
+const SHA1_CTX_BUFFER: u32 = 0x1C;
+
+const RSA_KEY_LEN: usize = 0x80;
+
+/// The size of the chunks data to hash is read from memory in, so that arbitrarily long (or bogus)
+/// lengths don't need to be buffered in full.
+const SHA1_CHUNK_LEN: u32 = 0x200;
+
+fn read_bytes(bus: &mut impl Bus, addr: u32, len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| bus.read_8(addr.wrapping_add(i)))
+        .collect()
+}
+
+fn write_bytes(bus: &mut impl Bus, addr: u32, bytes: &[u8]) {
+    for (i, &byte) in bytes.iter().enumerate() {
+        bus.write_8(addr.wrapping_add(i as u32), byte);
+    }
+}
+
+fn read_sha1_ctx(bus: &mut impl Bus, addr: u32) -> Sha1 {
+    let mut sha1 = Sha1::new();
+    for (i, word) in sha1.state.iter_mut().enumerate() {
+        *word = bus.read_32(addr.wrapping_add(SHA1_CTX_STATE + (i << 2) as u32));
+    }
+    sha1.len = bus.read_32(addr.wrapping_add(SHA1_CTX_LEN)) as u64
+        | (bus.read_32(addr.wrapping_add(SHA1_CTX_LEN + 4)) as u64) << 32;
+    let buffer = read_bytes(bus, addr.wrapping_add(SHA1_CTX_BUFFER), 64);
+    sha1.buffer.copy_from_slice(&buffer);
+    sha1
+}
+
+fn write_sha1_ctx(bus: &mut impl Bus, addr: u32, sha1: &Sha1) {
+    for (i, &word) in sha1.state.iter().enumerate() {
+        bus.write_32(addr.wrapping_add(SHA1_CTX_STATE + (i << 2) as u32), word);
+    }
+    bus.write_32(addr.wrapping_add(SHA1_CTX_LEN), sha1.len as u32);
+    bus.write_32(addr.wrapping_add(SHA1_CTX_LEN + 4), (sha1.len >> 32) as u32);
+    write_bytes(bus, addr.wrapping_add(SHA1_CTX_BUFFER), &sha1.buffer);
+}
+
+fn sha1_update_from_bus(bus: &mut impl Bus, sha1: &mut Sha1, src_addr: u32, len: u32) {
+    let mut chunk = [0; SHA1_CHUNK_LEN as usize];
+    let mut offset = 0;
+    while offset < len {
+        let chunk_len = (len - offset).min(SHA1_CHUNK_LEN);
+        let chunk = &mut chunk[..chunk_len as usize];
+        for (i, byte) in chunk.iter_mut().enumerate() {
+            *byte = bus.read_8(src_addr.wrapping_add(offset + i as u32));
+        }
+        sha1.update(chunk);
+        offset += chunk_len;
+    }
+}
+
+fn sha1_init(bus: &mut impl Bus, ctx_addr: u32) {
+    write_sha1_ctx(bus, ctx_addr, &Sha1::new());
+}
+
+fn sha1_update(bus: &mut impl Bus, ctx_addr: u32, src_addr: u32, len: u32) {
+    let mut sha1 = read_sha1_ctx(bus, ctx_addr);
+    sha1_update_from_bus(bus, &mut sha1, src_addr, len);
+    write_sha1_ctx(bus, ctx_addr, &sha1);
+}
+
+fn sha1_finish(bus: &mut impl Bus, dst_addr: u32, ctx_addr: u32) {
+    let digest = read_sha1_ctx(bus, ctx_addr).finish();
+    write_bytes(bus, dst_addr, &digest);
+}
+
+fn sha1_init_update_finish(bus: &mut impl Bus, dst_addr: u32, src_addr: u32, len: u32) {
+    let mut sha1 = Sha1::new();
+    sha1_update_from_bus(bus, &mut sha1, src_addr, len);
+    write_bytes(bus, dst_addr, &sha1.finish());
+}
+
+fn sha1_compare(bus: &mut impl Bus, a_addr: u32, b_addr: u32) -> bool {
+    read_bytes(bus, a_addr, Sha1::DIGEST_LEN) == read_bytes(bus, b_addr, Sha1::DIGEST_LEN)
+}
+
+/// Fills the destination with the output of the FIPS 186-2 pseudo-random number generator (as
+/// described in its appendix 3.1, without optional user input), using the source buffer as the
+/// initial seed key. Its `G` function is the SHA-1 compression function applied once to the seed
+/// key padded with zeros to a full block, without the message length padding.
+fn sha1_random(bus: &mut impl Bus, dst_addr: u32, dst_len: u32, src_addr: u32, src_len: u32) {
+    // The seed key can be at most as long as a block
+    let mut key = read_bytes(bus, src_addr, (src_len as usize).min(64));
+    let mut offset = 0;
+    while offset < dst_len {
+        let mut block = [0; 64];
+        block[..key.len()].copy_from_slice(&key);
+        let mut sha1 = Sha1::new();
+        sha1.process_block(&block);
+        let mut output = [0; Sha1::DIGEST_LEN];
+        for (bytes, word) in output.chunks_exact_mut(4).zip(sha1.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+
+        let len = (dst_len - offset).min(Sha1::DIGEST_LEN as u32);
+        write_bytes(bus, dst_addr.wrapping_add(offset), &output[..len as usize]);
+        offset += len;
+
+        // XKEY = (1 + XKEY + x) mod 2^b, with both values in big-endian order
+        let mut carry = 1;
+        for (i, byte) in key.iter_mut().rev().enumerate() {
+            let x = if i < Sha1::DIGEST_LEN {
+                output[Sha1::DIGEST_LEN - 1 - i]
+            } else {
+                0
+            };
+            let sum = *byte as u16 + x as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+    }
+}
+
+/// Converts a big-endian byte string into little-endian 32-bit limbs.
+fn to_limbs(bytes: &[u8], len: usize) -> Vec<u32> {
+    let mut limbs = vec![0; len];
+    for (i, &byte) in bytes.iter().rev().enumerate() {
+        limbs[i >> 2] |= (byte as u32) << ((i & 3) << 3);
+    }
+    limbs
+}
+
+fn from_limbs(limbs: &[u32]) -> Vec<u8> {
+    limbs
+        .iter()
+        .rev()
+        .flat_map(|limb| limb.to_be_bytes())
+        .collect()
+}
+
+fn mul_mod(a: &[u32], b: &[u32], modulus: &[u32]) -> Vec<u32> {
+    let len = modulus.len();
+
+    let mut product = vec![0_u32; len * 2];
+    for (i, &a) in a.iter().enumerate() {
+        let mut carry = 0_u64;
+        for (j, &b) in b.iter().enumerate() {
+            let value = product[i + j] as u64 + a as u64 * b as u64 + carry;
+            product[i + j] = value as u32;
+            carry = value >> 32;
+        }
+        product[i + len] = carry as u32;
+    }
+
+    // Binary long division, keeping one extra limb for the bit shifted out of the remainder
+    let mut rem = vec![0_u32; len + 1];
+    for bit in (0..len * 64).rev() {
+        let mut carry = product[bit >> 5] >> (bit & 31) & 1;
+        for limb in &mut rem {
+            let next_carry = *limb >> 31;
+            *limb = *limb << 1 | carry;
+            carry = next_carry;
+        }
+        let ge = rem[len] != 0
+            || rem[..len]
+                .iter()
+                .rev()
+                .zip(modulus.iter().rev())
+                .find(|(a, b)| a != b)
+                .map_or(true, |(a, b)| a > b);
+        if ge {
+            let mut borrow = 0_i64;
+            for (i, limb) in rem.iter_mut().enumerate() {
+                let value = *limb as i64 - modulus.get(i).copied().unwrap_or(0) as i64 - borrow;
+                *limb = value as u32;
+                borrow = (value < 0) as i64;
+            }
+        }
+    }
+    rem.truncate(len);
+    rem
+}
+
+/// Computes `base^65537 mod modulus`, the RSA public key operation with the exponent used for
+/// all of the DSi's keys.
+fn rsa_public(base: &[u8], modulus: &[u8]) -> Vec<u8> {
+    let byte_len = modulus.len();
+    let len = (byte_len + 3) >> 2;
+    let modulus = to_limbs(modulus, len);
+    let mut one = vec![0; len];
+    one[0] = 1;
+    let base = mul_mod(&to_limbs(base, len), &one, &modulus);
+    let mut result = base.clone();
+    for _ in 0..16 {
+        result = mul_mod(&result, &result, &modulus);
+    }
+    let result = from_limbs(&mul_mod(&result, &base, &modulus));
+    result[result.len() - byte_len..].to_vec()
+}
+
+/// Decrypts a `RSA_KEY_LEN`-byte signature and strips its PKCS #1 block type 1 padding, returning
+/// the payload if the padding is valid.
+fn rsa_decrypt_unpad(bus: &mut impl Bus, sig_addr: u32, key_addr: u32) -> Option<Vec<u8>> {
+    let sig = read_bytes(bus, sig_addr, RSA_KEY_LEN);
+    let key = read_bytes(bus, key_addr, RSA_KEY_LEN);
+    let block = rsa_public(&sig, &key);
+    if block[..2] != [0, 1] {
+        return None;
+    }
+    let padding_len = block[2..].iter().take_while(|&&byte| byte == 0xFF).count();
+    let separator = 2 + padding_len;
+    if padding_len < 8 || block.get(separator) != Some(&0) {
+        return None;
+    }
+    Some(block[separator + 1..].to_vec())
+}
+
+/// The ASN.1 DigestInfo prefix identifying a SHA-1 hash.
+static SHA1_DIGEST_INFO: [u8; 15] = [
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2B, 0x0E, 0x03, 0x02, 0x1A, 0x05, 0x00, 0x04, 0x14,
+];
+
+/// Handles the DSi-exclusive SWIs, returning the new value of r0.
+pub fn handle_swi(bus: &mut impl Bus, number: u8, r0_3: [u32; 4]) -> u32 {
+    match number {
+        // RSA_Init_crypto_heap
+        0x20 => r0_3[0],
+
+        // RSA_Decrypt (r1 points to the destination, source and key addresses, followed by the
+        // key's length in bytes)
+        0x21 => {
+            let dst_addr = bus.read_32(r0_3[1]);
+            let src_addr = bus.read_32(r0_3[1].wrapping_add(4));
+            let key_addr = bus.read_32(r0_3[1].wrapping_add(8));
+            let key_len = (bus.read_32(r0_3[1].wrapping_add(12)) as usize).min(0x200);
+            if key_len == 0 {
+                return 0;
+            }
+            let src = read_bytes(bus, src_addr, key_len);
+            let key = read_bytes(bus, key_addr, key_len);
+            write_bytes(bus, dst_addr, &rsa_public(&src, &key));
+            1
+        }
+
+        // RSA_Decrypt_Unpad
+        0x22 => match rsa_decrypt_unpad(bus, r0_3[2], r0_3[3]) {
+            Some(payload) => {
+                write_bytes(bus, r0_3[1], &payload);
+                payload.len() as u32
+            }
+            None => 0,
+        },
+
+        // RSA_Decrypt_Unpad_OpenPGP_SHA1
+        0x23 => match rsa_decrypt_unpad(bus, r0_3[2], r0_3[3]) {
+            Some(payload)
+                if payload.len() == SHA1_DIGEST_INFO.len() + Sha1::DIGEST_LEN
+                    && payload.starts_with(&SHA1_DIGEST_INFO) =>
+            {
+                write_bytes(bus, r0_3[1], &payload[SHA1_DIGEST_INFO.len()..]);
+                1
+            }
+            _ => 0,
+        },
+
+        0x24 => {
+            sha1_init(bus, r0_3[0]);
+            r0_3[0]
+        }
+
+        0x25 => {
+            sha1_update(bus, r0_3[0], r0_3[1], r0_3[2]);
+            r0_3[0]
+        }
+
+        0x26 => {
+            sha1_finish(bus, r0_3[0], r0_3[1]);
+            r0_3[0]
+        }
+
+        0x27 => {
+            sha1_init_update_finish(bus, r0_3[0], r0_3[1], r0_3[2]);
+            r0_3[0]
+        }
+
+        0x28 => sha1_compare(bus, r0_3[0], r0_3[1]) as u32,
+
+        0x29 => {
+            sha1_random(bus, r0_3[0], r0_3[1], r0_3[2], r0_3[3]);
+            r0_3[0]
+        }
+
+        _ => unreachable!(),
+    }
+}
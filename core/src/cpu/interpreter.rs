@@ -1,10 +1,12 @@
 mod regs;
 pub use regs::Regs;
 mod alu_utils;
+#[cfg(feature = "interp-cache")]
+mod cache;
 mod common;
 
 use super::Engine;
-use crate::utils::Savestate;
+use crate::{emu::Emu, utils::Savestate};
 
 macro_rules! reg {
     ($cpu: expr, $reg: expr) => {
@@ -59,15 +61,126 @@ macro_rules! update_spsr {
 mod arm7;
 mod arm9;
 
+/// Caches of code derived from memory contents kept by an engine for one of the CPUs, notified of
+/// all memory writes through [`CoreData::invalidate_word`](super::CoreData::invalidate_word) and
+/// [`CoreData::invalidate_word_range`](super::CoreData::invalidate_word_range).
+pub trait CodeCache {
+    fn new() -> Self;
+    fn invalidate_word(&mut self, addr: u32);
+    fn invalidate_word_range(&mut self, bounds: (u32, u32));
+    fn clear(&mut self);
+}
+
+impl CodeCache for () {
+    #[inline]
+    fn new() -> Self {}
+
+    #[inline]
+    fn invalidate_word(&mut self, _addr: u32) {}
+
+    #[inline]
+    fn invalidate_word_range(&mut self, _bounds: (u32, u32)) {}
+
+    #[inline]
+    fn clear(&mut self) {}
+}
+
+/// An engine built on the interpreter's instruction handlers, only differing from it in the way
+/// instructions are fetched and dispatched.
+pub trait InterpEngine:
+    Engine<GlobalData = (), Arm7Data = arm7::EngineData<Self>, Arm9Data = arm9::EngineData<Self>>
+    + 'static
+{
+    type Arm7Cache: CodeCache;
+    type Arm9Cache: CodeCache;
+
+    /// Runs ARM7 instructions until the ARM7 schedule's target time is reached, returning `false`
+    /// if execution was stopped by a breakpoint.
+    fn run_arm7_instrs(emu: &mut Emu<Self>) -> bool;
+
+    /// Runs ARM9 instructions until the ARM9 schedule's target time is reached, returning `false`
+    /// if execution was stopped by a breakpoint.
+    fn run_arm9_instrs(emu: &mut Emu<Self>) -> bool;
+}
+
+// The engine data's layout depends on which features are enabled
+const ENGINE_NAME: &str = match (
+    cfg!(feature = "interp-pipeline"),
+    cfg!(feature = "interp-pipeline-accurate-reloads"),
+    cfg!(feature = "interp-arm9-interlocks"),
+) {
+    (false, _, false) => "interpreter",
+    (false, _, true) => "interpreter+interlocks",
+    (true, false, false) => "interpreter+pipeline",
+    (true, false, true) => "interpreter+pipeline+interlocks",
+    (true, true, false) => "interpreter+pipeline+accurate-reloads",
+    (true, true, true) => "interpreter+pipeline+accurate-reloads+interlocks",
+};
+
 #[derive(Savestate)]
 pub struct Interpreter;
 
 impl Engine for Interpreter {
+    const NAME: &'static str = ENGINE_NAME;
+
     type GlobalData = ();
-    type Arm7Data = arm7::EngineData;
-    type Arm9Data = arm9::EngineData;
+    type Arm7Data = arm7::EngineData<Self>;
+    type Arm9Data = arm9::EngineData<Self>;
 
     fn into_data(self) -> (Self::GlobalData, Self::Arm7Data, Self::Arm9Data) {
         ((), arm7::EngineData::new(), arm9::EngineData::new())
     }
 }
+
+impl InterpEngine for Interpreter {
+    type Arm7Cache = ();
+    type Arm9Cache = ();
+
+    #[inline]
+    fn run_arm7_instrs(emu: &mut Emu<Self>) -> bool {
+        arm7::run_instrs(emu)
+    }
+
+    #[inline]
+    fn run_arm9_instrs(emu: &mut Emu<Self>) -> bool {
+        arm9::run_instrs(emu)
+    }
+}
+
+/// An interpreter that runs blocks of instructions pre-decoded from RAM, invalidated whenever the
+/// code they were decoded from is written to, instead of fetching and decoding every instruction
+/// as it gets executed; timings and results are identical to [`Interpreter`]'s.
+#[cfg(feature = "interp-cache")]
+#[derive(Savestate)]
+pub struct CachedInterpreter;
+
+#[cfg(feature = "interp-cache")]
+impl Engine for CachedInterpreter {
+    // The caches aren't saved, so the engine data is the same as the interpreter's and savestates
+    // can be loaded by either engine
+    const NAME: &'static str = ENGINE_NAME;
+
+    type GlobalData = ();
+    type Arm7Data = arm7::EngineData<Self>;
+    type Arm9Data = arm9::EngineData<Self>;
+
+    fn into_data(self) -> (Self::GlobalData, Self::Arm7Data, Self::Arm9Data) {
+        ((), arm7::EngineData::new(), arm9::EngineData::new())
+    }
+}
+
+#[cfg(feature = "interp-cache")]
+impl InterpEngine for CachedInterpreter {
+    type Arm7Cache = arm7::cache::Cache;
+    type Arm9Cache = arm9::cache::Cache;
+
+    #[inline]
+    fn run_arm7_instrs(emu: &mut Emu<Self>) -> bool {
+        arm7::cache::run_instrs(emu)
+    }
+
+    #[inline]
+    fn run_arm9_instrs(emu: &mut Emu<Self>) -> bool {
+        arm9::cache::run_instrs(emu)
+    }
+}
@@ -1,9 +1,15 @@
 mod arm;
+#[cfg(feature = "interp-cache")]
+pub(super) mod cache;
 mod thumb;
 
 #[cfg(feature = "interp-pipeline")]
 use super::common::{thumb_pipeline_entry, PipelineEntry};
-use super::{super::Regs as EngineRegs, common::StateSource, Interpreter, Regs};
+use super::{
+    super::{CoreState, Regs as EngineRegs},
+    common::StateSource,
+    CodeCache, InterpEngine, Regs,
+};
 #[cfg(feature = "debugger-hooks")]
 use crate::cpu::debug;
 use crate::{
@@ -20,7 +26,7 @@ use crate::{
 
 #[derive(Savestate)]
 #[load(in_place_only)]
-pub struct EngineData {
+pub struct EngineData<E: InterpEngine> {
     #[cfg(feature = "interp-pipeline-accurate-reloads")]
     r15_increment: u32,
     pub regs: Regs,
@@ -30,10 +36,12 @@ pub struct EngineData {
     #[cfg(feature = "debugger-hooks")]
     #[savestate(skip)]
     next_breakpoint_addr: u32,
+    #[savestate(skip)]
+    cache: E::Arm7Cache,
 }
 
-impl EngineData {
-    pub const fn new() -> Self {
+impl<E: InterpEngine> EngineData<E> {
+    pub fn new() -> Self {
         EngineData {
             #[cfg(feature = "interp-pipeline-accurate-reloads")]
             r15_increment: 4,
@@ -43,6 +51,7 @@ impl EngineData {
             prefetch_nseq: false,
             #[cfg(feature = "debugger-hooks")]
             next_breakpoint_addr: u32::MAX,
+            cache: E::Arm7Cache::new(),
         }
     }
 }
@@ -68,13 +77,13 @@ fn multiply_cycles(mut op: u32) -> RawTimestamp {
 }
 
 #[inline]
-fn add_cycles(emu: &mut Emu<Interpreter>, cycles: RawTimestamp) {
+fn add_cycles(emu: &mut Emu<impl InterpEngine>, cycles: RawTimestamp) {
     emu.arm7
         .schedule
         .set_cur_time(emu.arm7.schedule.cur_time() + Timestamp(cycles));
 }
 
-fn reload_pipeline<const STATE_SOURCE: StateSource>(emu: &mut Emu<Interpreter>) {
+fn reload_pipeline<const STATE_SOURCE: StateSource>(emu: &mut Emu<impl InterpEngine>) {
     let mut addr = reg!(emu.arm7, 15);
 
     if match STATE_SOURCE {
@@ -155,7 +164,7 @@ fn reload_pipeline<const STATE_SOURCE: StateSource>(emu: &mut Emu<Interpreter>)
     }
 }
 
-fn set_cpsr_update_control(emu: &mut Emu<Interpreter>, value: Psr) {
+fn set_cpsr_update_control(emu: &mut Emu<impl InterpEngine>, value: Psr) {
     let prev_value = emu.arm7.engine_data.regs.cpsr;
     emu.arm7.engine_data.regs.cpsr = value;
     emu.arm7
@@ -167,7 +176,7 @@ fn set_cpsr_update_control(emu: &mut Emu<Interpreter>, value: Psr) {
         .update_mode::<false>(prev_value.mode(), value.mode());
 }
 
-fn restore_spsr(emu: &mut Emu<Interpreter>) {
+fn restore_spsr(emu: &mut Emu<impl InterpEngine>) {
     if !emu.arm7.engine_data.regs.has_spsr() {
         unimplemented!("Unpredictable SPSR restore in non-exception mode");
     }
@@ -179,7 +188,7 @@ fn restore_spsr(emu: &mut Emu<Interpreter>) {
     }
 }
 
-fn handle_undefined<const THUMB: bool>(emu: &mut Emu<Interpreter>, _instr: u32) {
+fn handle_undefined<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>, _instr: u32) {
     #[cfg(feature = "log")]
     slog::warn!(
         emu.arm7.logger,
@@ -221,7 +230,7 @@ fn handle_undefined<const THUMB: bool>(emu: &mut Emu<Interpreter>, _instr: u32)
     reload_pipeline::<{ StateSource::Arm }>(emu);
 }
 
-fn handle_swi<const THUMB: bool>(emu: &mut Emu<Interpreter>, number: u8) {
+fn handle_swi<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>, number: u8) {
     #[cfg(feature = "debugger-hooks")]
     if let Some(swi_hook) = emu.arm7.swi_hook() {
         if unsafe { swi_hook.get()(emu, number) } {
@@ -263,7 +272,7 @@ fn handle_swi<const THUMB: bool>(emu: &mut Emu<Interpreter>, number: u8) {
 }
 
 fn enter_hle_swi<const FROM_USER_CODE: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     number: u8,
     return_addr: u32,
 ) {
@@ -315,7 +324,7 @@ fn enter_hle_swi<const FROM_USER_CODE: bool>(
     hle_bios::arm7::handle_swi(emu, number, emu.arm7.engine_data.regs.r0_3());
 }
 
-fn return_from_hle_swi(emu: &mut Emu<Interpreter>) {
+fn return_from_hle_swi(emu: &mut Emu<impl InterpEngine>) {
     let base_addr = reg!(emu.arm7, 13);
     reg!(emu.arm7, 13) = base_addr.wrapping_add(8);
     for (i, reg) in [2_u8, 14].into_iter().enumerate() {
@@ -344,7 +353,7 @@ fn return_from_hle_swi(emu: &mut Emu<Interpreter>) {
     reload_pipeline::<{ StateSource::Cpsr }>(emu);
 }
 
-fn enter_hle_irq<const FROM_USER_CODE: bool>(emu: &mut Emu<Interpreter>, return_addr: u32) {
+fn enter_hle_irq<const FROM_USER_CODE: bool>(emu: &mut Emu<impl InterpEngine>, return_addr: u32) {
     if FROM_USER_CODE {
         let prev_cpsr = emu.arm7.engine_data.regs.cpsr;
         emu.arm7.engine_data.regs.cpsr = prev_cpsr.with_mode(Mode::IRQ).with_irqs_disabled(true);
@@ -370,7 +379,7 @@ fn enter_hle_irq<const FROM_USER_CODE: bool>(emu: &mut Emu<Interpreter>, return_
     reg!(emu.arm7, 0) = hle_bios::arm7::handle_irq(emu);
 }
 
-fn return_from_hle_irq(emu: &mut Emu<Interpreter>) {
+fn return_from_hle_irq(emu: &mut Emu<impl InterpEngine>) {
     let base_addr = reg!(emu.arm7, 13);
     reg!(emu.arm7, 13) = base_addr.wrapping_add(0x18);
     for (i, reg) in [0_u8, 1, 2, 3, 12, 14].into_iter().enumerate() {
@@ -382,15 +391,159 @@ fn return_from_hle_irq(emu: &mut Emu<Interpreter>) {
     reload_pipeline::<{ StateSource::Cpsr }>(emu);
 }
 
-impl CoreData for EngineData {
-    type Engine = Interpreter;
+/// Moves the pipeline forward, fetching the instruction 2 instructions ahead of the one about to
+/// be executed, and returns the latter.
+#[cfg(feature = "interp-pipeline")]
+#[inline(always)]
+fn advance_pipeline<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>) -> PipelineEntry {
+    let addr = reg!(emu.arm7, 15);
+    let instr = emu.arm7.engine_data.pipeline[0];
+    emu.arm7.engine_data.pipeline[0] = emu.arm7.engine_data.pipeline[1];
+    if THUMB {
+        emu.arm7.engine_data.pipeline[1] =
+            thumb_pipeline_entry(bus::read_16::<CpuAccess, _>(emu, addr) as PipelineEntry);
+        let timings = emu.arm7.bus_timings.get(addr);
+        add_cycles(
+            emu,
+            if addr & 0x3FE == 0 || emu.arm7.engine_data.prefetch_nseq {
+                timings.n16
+            } else {
+                timings.s16
+            } as RawTimestamp,
+        );
+    } else {
+        emu.arm7.engine_data.pipeline[1] = bus::read_32::<CpuAccess, _>(emu, addr) as PipelineEntry;
+        let timings = emu.arm7.bus_timings.get(addr);
+        add_cycles(
+            emu,
+            if addr & 0x3FC == 0 || emu.arm7.engine_data.prefetch_nseq {
+                timings.n32
+            } else {
+                timings.s32
+            } as RawTimestamp,
+        );
+    }
+    instr
+}
 
-    fn setup(emu: &mut Emu<Interpreter>) {
+/// Executes an instruction taken from the pipeline by [`advance_pipeline`].
+#[cfg(feature = "interp-pipeline")]
+#[inline(always)]
+fn handle_pipeline_entry<const THUMB: bool>(
+    emu: &mut Emu<impl InterpEngine>,
+    instr: PipelineEntry,
+) {
+    emu.arm7.engine_data.prefetch_nseq = false;
+    #[cfg(feature = "interp-pipeline-accurate-reloads")]
+    if instr & 1 << 32 == 0 {
+        arm::handle_instr(emu, instr as u32);
+    } else {
+        thumb::handle_instr(emu, instr as u16);
+    }
+    #[cfg(not(feature = "interp-pipeline-accurate-reloads"))]
+    if THUMB {
+        thumb::handle_instr(emu, instr as u16);
+    } else {
+        arm::handle_instr(emu, instr);
+    }
+}
+
+/// Adds the timings of the code fetches performed while executing the instruction at `addr`.
+#[cfg(not(feature = "interp-pipeline"))]
+#[inline(always)]
+fn add_fetch_cycles<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>, addr: u32) {
+    let timings = emu.arm7.bus_timings.get(addr);
+    add_cycles(
+        emu,
+        if THUMB {
+            timings.s16 as RawTimestamp
+                + if emu.arm7.engine_data.prefetch_nseq {
+                    timings.n16
+                } else {
+                    timings.s16
+                } as RawTimestamp
+        } else {
+            timings.s32 as RawTimestamp
+                + if emu.arm7.engine_data.prefetch_nseq {
+                    timings.n32
+                } else {
+                    timings.s32
+                } as RawTimestamp
+        },
+    );
+}
+
+/// Fetches and executes a single instruction.
+#[inline(always)]
+fn step(emu: &mut Emu<impl InterpEngine>) {
+    #[cfg(feature = "interp-pipeline")]
+    if emu.arm7.engine_data.regs.cpsr.thumb_state() {
+        let instr = advance_pipeline::<true>(emu);
+        handle_pipeline_entry::<true>(emu, instr);
+    } else {
+        let instr = advance_pipeline::<false>(emu);
+        handle_pipeline_entry::<false>(emu, instr);
+    }
+    #[cfg(not(feature = "interp-pipeline"))]
+    if emu.arm7.engine_data.regs.cpsr.thumb_state() {
+        let addr = reg!(emu.arm7, 15).wrapping_sub(4);
+        add_fetch_cycles::<true>(emu, addr);
+        let instr = bus::read_16::<CpuAccess, _>(emu, addr);
+        emu.arm7.engine_data.prefetch_nseq = false;
+        thumb::handle_instr(emu, instr);
+    } else {
+        let addr = reg!(emu.arm7, 15).wrapping_sub(8);
+        add_fetch_cycles::<false>(emu, addr);
+        let instr = bus::read_32::<CpuAccess, _>(emu, addr);
+        emu.arm7.engine_data.prefetch_nseq = false;
+        arm::handle_instr(emu, instr);
+    }
+}
+
+/// Calls the breakpoint hook if the next instruction to be executed has a breakpoint set on it,
+/// returning whether execution should stop.
+#[cfg(feature = "debugger-hooks")]
+#[inline]
+fn hit_breakpoint(emu: &mut Emu<impl InterpEngine>) -> bool {
+    let r15 =
+        reg!(emu.arm7, 15).wrapping_sub(8 >> emu.arm7.engine_data.regs.cpsr.thumb_state() as u8);
+    if emu.arm7.engine_data.next_breakpoint_addr == r15 {
+        if let Some(breakpoint_hook) = emu.arm7.breakpoint_hook().as_ref() {
+            if unsafe { breakpoint_hook.get()(emu, r15) } {
+                emu.arm7
+                    .schedule
+                    .set_target_time(emu.arm7.schedule.cur_time());
+                emu.arm7.was_stopped_by_debug_hook = true;
+                emu.arm7.is_stopped = true;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Executes instructions one at a time until the schedule's target time is reached, returning
+/// `false` if execution was stopped by a breakpoint.
+pub(super) fn run_instrs(emu: &mut Emu<impl InterpEngine>) -> bool {
+    while emu.arm7.schedule.cur_time() < emu.arm7.schedule.target_time() {
+        #[cfg(feature = "debugger-hooks")]
+        if hit_breakpoint(emu) {
+            return false;
+        }
+        step(emu);
+    }
+    true
+}
+
+impl<E: InterpEngine> CoreData for EngineData<E> {
+    type Engine = E;
+
+    fn setup(emu: &mut Emu<E>) {
         reg!(emu.arm7, 15) = 0;
         reload_pipeline::<{ StateSource::Arm }>(emu);
     }
 
-    fn setup_direct_boot(emu: &mut Emu<Interpreter>, entry_addr: u32) {
+    fn setup_direct_boot(emu: &mut Emu<E>, entry_addr: u32) {
         let prev_mode = emu.arm7.engine_data.regs.cpsr.mode();
         emu.arm7.engine_data.regs.cpsr.set_mode(Mode::SYSTEM);
         emu.arm7
@@ -411,22 +564,27 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn post_load(_emu: &mut Emu<Interpreter>) {
+    fn post_load(emu: &mut Emu<E>) {
         get_next_breakpoint!(
-            _emu,
-            reg!(_emu.arm7, 15),
-            1 | (!_emu.arm7.engine_data.regs.cpsr.thumb_state() as u32) << 1
+            emu,
+            reg!(emu.arm7, 15),
+            1 | (!emu.arm7.engine_data.regs.cpsr.thumb_state() as u32) << 1
         );
+        emu.arm7.engine_data.cache.clear();
     }
 
     #[inline]
-    fn invalidate_word(&mut self, _addr: u32) {}
+    fn invalidate_word(&mut self, addr: u32) {
+        self.cache.invalidate_word(addr);
+    }
 
     #[inline]
-    fn invalidate_word_range(&mut self, _bounds: (u32, u32)) {}
+    fn invalidate_word_range(&mut self, bounds: (u32, u32)) {
+        self.cache.invalidate_word_range(bounds);
+    }
 
     #[inline]
-    fn jump(emu: &mut Emu<Interpreter>, addr: u32) {
+    fn jump(emu: &mut Emu<E>, addr: u32) {
         reg!(emu.arm7, 15) = addr;
         reload_pipeline::<{ StateSource::R15Bit0 }>(emu);
     }
@@ -442,7 +600,7 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn set_cpsr(emu: &mut Emu<Interpreter>, value: Psr) {
+    fn set_cpsr(emu: &mut Emu<E>, value: Psr) {
         set_cpsr_update_control(emu, value);
         #[cfg(feature = "interp-pipeline-accurate-reloads")]
         {
@@ -457,7 +615,7 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn set_regs(emu: &mut Emu<Interpreter>, regs: &EngineRegs) {
+    fn set_regs(emu: &mut Emu<E>, regs: &EngineRegs) {
         emu.arm7.engine_data.regs.set_from_engine_regs(regs);
         reg!(emu.arm7, 15) = reg!(emu.arm7, 15)
             .wrapping_sub(8 >> emu.arm7.engine_data.regs.cpsr.thumb_state() as u8);
@@ -465,7 +623,54 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn jump_and_link(emu: &mut Emu<Interpreter>, addr: u32, lr: u32) {
+    fn core_state(&self) -> CoreState {
+        CoreState {
+            regs: self.regs.to_engine_regs(),
+            cpsr: self.regs.cpsr,
+            #[cfg(feature = "interp-pipeline")]
+            pipeline: Some(self.pipeline.map(|entry| entry as u32)),
+            #[cfg(not(feature = "interp-pipeline"))]
+            pipeline: None,
+        }
+    }
+
+    fn set_core_state(emu: &mut Emu<E>, state: &CoreState) {
+        emu.arm7.engine_data.regs.restore_cpsr(state.cpsr);
+        emu.arm7.engine_data.regs.set_from_engine_regs(&state.regs);
+        #[cfg(feature = "interp-pipeline-accurate-reloads")]
+        {
+            emu.arm7.engine_data.r15_increment = 4 >> state.cpsr.thumb_state() as u8;
+        }
+        #[cfg(feature = "interp-pipeline")]
+        {
+            let thumb = state.cpsr.thumb_state();
+            let pipeline = state.pipeline.unwrap_or_else(|| {
+                let addr = reg!(emu.arm7, 15);
+                if thumb {
+                    [
+                        bus::read_16::<CpuAccess, _>(emu, addr.wrapping_sub(4)) as u32,
+                        bus::read_16::<CpuAccess, _>(emu, addr.wrapping_sub(2)) as u32,
+                    ]
+                } else {
+                    [
+                        bus::read_32::<CpuAccess, _>(emu, addr.wrapping_sub(8)),
+                        bus::read_32::<CpuAccess, _>(emu, addr.wrapping_sub(4)),
+                    ]
+                }
+            });
+            emu.arm7.engine_data.pipeline = if thumb {
+                pipeline.map(|instr| thumb_pipeline_entry(instr as PipelineEntry))
+            } else {
+                pipeline.map(|instr| instr as PipelineEntry)
+            };
+        }
+        emu.arm7.engine_data.prefetch_nseq = false;
+        // Memory contents and mappings were replaced without going through the bus
+        emu.arm7.engine_data.cache.clear();
+    }
+
+    #[inline]
+    fn jump_and_link(emu: &mut Emu<E>, addr: u32, lr: u32) {
         reg!(emu.arm7, 14) = lr;
         reg!(emu.arm7, 15) = addr;
         reload_pipeline::<{ StateSource::R15Bit0 }>(emu);
@@ -477,13 +682,19 @@ impl CoreData for EngineData {
         return_from_hle_swi(emu);
     }
 
+    #[inline]
+    fn call_from_hle_swi(emu: &mut Emu<Self::Engine>, addr: u32, r0_3: [u32; 4], lr: u32) {
+        emu.arm7.engine_data.regs.set_r0_3(r0_3);
+        Self::jump_and_link(emu, addr, lr);
+    }
+
     cfg_if::cfg_if! {
         if #[cfg(feature = "debugger-hooks")] {
             #[inline]
-            fn set_swi_hook(&mut self, _hook: &Option<debug::SwiHook<Interpreter>>) {}
+            fn set_swi_hook(&mut self, _hook: &Option<debug::SwiHook<E>>) {}
 
             #[inline]
-            fn set_undef_hook(&mut self, _hook: &Option<debug::UndefHook<Interpreter>>) {}
+            fn set_undef_hook(&mut self, _hook: &Option<debug::UndefHook<E>>) {}
 
             #[inline]
             fn add_breakpoint(&mut self, addr: u32) {
@@ -512,12 +723,12 @@ impl CoreData for EngineData {
             }
 
             #[inline]
-            fn set_breakpoint_hook(&mut self, _hook: &Option<debug::BreakpointHook<Interpreter>>) {}
+            fn set_breakpoint_hook(&mut self, _hook: &Option<debug::BreakpointHook<E>>) {}
 
             #[inline]
             fn set_mem_watchpoint_hook(
                 &mut self,
-                _hook: &Option<debug::MemWatchpointHook<Interpreter>>,
+                _hook: &Option<debug::MemWatchpointHook<E>>,
             ) {}
 
             #[inline]
@@ -542,15 +753,15 @@ impl CoreData for EngineData {
     }
 }
 
-impl Arm7Data for EngineData {
+impl<E: InterpEngine> Arm7Data for EngineData<E> {
     #[inline]
-    fn run_stalled_until(emu: &mut Emu<Interpreter>, end_time: Timestamp) {
+    fn run_stalled_until(emu: &mut Emu<E>, end_time: Timestamp) {
         emu.arm7.schedule.set_cur_time(end_time);
         Schedule::handle_pending_events(emu);
     }
 
     #[inline]
-    fn run_until(emu: &mut Emu<Interpreter>, end_time: Timestamp) {
+    fn run_until(emu: &mut Emu<E>, end_time: Timestamp) {
         while emu.arm7.schedule.cur_time() < end_time {
             Schedule::handle_pending_events(emu);
             emu.arm7
@@ -613,108 +824,8 @@ impl Arm7Data for EngineData {
                     .set_cur_time(emu.arm7.schedule.target_time());
                 continue;
             }
-            while emu.arm7.schedule.cur_time() < emu.arm7.schedule.target_time() {
-                #[cfg(feature = "debugger-hooks")]
-                {
-                    let r15 = reg!(emu.arm7, 15)
-                        .wrapping_sub(8 >> emu.arm7.engine_data.regs.cpsr.thumb_state() as u8);
-                    if emu.arm7.engine_data.next_breakpoint_addr == r15 {
-                        if let Some(breakpoint_hook) = emu.arm7.breakpoint_hook().as_ref() {
-                            if unsafe { breakpoint_hook.get()(emu, r15) } {
-                                emu.arm7
-                                    .schedule
-                                    .set_target_time(emu.arm7.schedule.cur_time());
-                                emu.arm7.was_stopped_by_debug_hook = true;
-                                emu.arm7.is_stopped = true;
-                                return;
-                            }
-                        }
-                    }
-                }
-                #[cfg(feature = "interp-pipeline")]
-                {
-                    let addr = reg!(emu.arm7, 15);
-                    let instr = emu.arm7.engine_data.pipeline[0];
-                    emu.arm7.engine_data.pipeline[0] = emu.arm7.engine_data.pipeline[1];
-                    if emu.arm7.engine_data.regs.cpsr.thumb_state() {
-                        emu.arm7.engine_data.pipeline[1] =
-                            thumb_pipeline_entry(
-                                bus::read_16::<CpuAccess, _>(emu, addr) as PipelineEntry
-                            );
-                        let timings = emu.arm7.bus_timings.get(addr);
-                        add_cycles(
-                            emu,
-                            if addr & 0x3FE == 0 || emu.arm7.engine_data.prefetch_nseq {
-                                timings.n16
-                            } else {
-                                timings.s16
-                            } as RawTimestamp,
-                        );
-                        #[cfg(not(feature = "interp-pipeline-accurate-reloads"))]
-                        {
-                            emu.arm7.engine_data.prefetch_nseq = false;
-                            thumb::handle_instr(emu, instr as u16);
-                        }
-                    } else {
-                        emu.arm7.engine_data.pipeline[1] =
-                            bus::read_32::<CpuAccess, _>(emu, addr) as PipelineEntry;
-                        let timings = emu.arm7.bus_timings.get(addr);
-                        add_cycles(
-                            emu,
-                            if addr & 0x3FC == 0 || emu.arm7.engine_data.prefetch_nseq {
-                                timings.n32
-                            } else {
-                                timings.s32
-                            } as RawTimestamp,
-                        );
-                        #[cfg(not(feature = "interp-pipeline-accurate-reloads"))]
-                        {
-                            emu.arm7.engine_data.prefetch_nseq = false;
-                            arm::handle_instr(emu, instr);
-                        }
-                    }
-                    #[cfg(feature = "interp-pipeline-accurate-reloads")]
-                    {
-                        emu.arm7.engine_data.prefetch_nseq = false;
-                        if instr & 1 << 32 == 0 {
-                            arm::handle_instr(emu, instr as u32);
-                        } else {
-                            thumb::handle_instr(emu, instr as u16);
-                        }
-                    }
-                }
-                #[cfg(not(feature = "interp-pipeline"))]
-                if emu.arm7.engine_data.regs.cpsr.thumb_state() {
-                    let addr = reg!(emu.arm7, 15).wrapping_sub(4);
-                    let timings = emu.arm7.bus_timings.get(addr);
-                    add_cycles(
-                        emu,
-                        timings.s16 as RawTimestamp
-                            + if emu.arm7.engine_data.prefetch_nseq {
-                                timings.n16
-                            } else {
-                                timings.s16
-                            } as RawTimestamp,
-                    );
-                    let instr = bus::read_16::<CpuAccess, _>(emu, addr);
-                    emu.arm7.engine_data.prefetch_nseq = false;
-                    thumb::handle_instr(emu, instr);
-                } else {
-                    let addr = reg!(emu.arm7, 15).wrapping_sub(8);
-                    let timings = emu.arm7.bus_timings.get(addr);
-                    add_cycles(
-                        emu,
-                        timings.s32 as RawTimestamp
-                            + if emu.arm7.engine_data.prefetch_nseq {
-                                timings.n32
-                            } else {
-                                timings.s32
-                            } as RawTimestamp,
-                    );
-                    let instr = bus::read_32::<CpuAccess, _>(emu, addr);
-                    emu.arm7.engine_data.prefetch_nseq = false;
-                    arm::handle_instr(emu, instr);
-                };
+            if !E::run_arm7_instrs(emu) {
+                return;
             }
         }
     }
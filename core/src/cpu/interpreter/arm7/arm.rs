@@ -9,15 +9,32 @@ use other::*;
 
 use super::super::{
     common::{DpOpTy, DpOperand, MiscAddressing, ShiftTy, WbAddressing, WbOffTy},
-    Interpreter,
+    InterpEngine,
 };
 use crate::emu::Emu;
+use core::marker::PhantomData;
 
-static INSTR_TABLE: [fn(&mut Emu<Interpreter>, u32); 0x1000] =
-    include!(concat!(env!("OUT_DIR"), "/interp_arm7_arm.rs"));
+pub type Handler<E> = fn(&mut Emu<E>, u32);
+
+struct Tables<E>(PhantomData<E>);
+
+impl<E: InterpEngine> Tables<E> {
+    const INSTR_TABLE: &'static [Handler<E>; 0x1000] =
+        &include!(concat!(env!("OUT_DIR"), "/interp_arm7_arm.rs"));
+}
+
+#[inline]
+pub fn decode<E: InterpEngine>(instr: u32) -> Handler<E> {
+    Tables::<E>::INSTR_TABLE[((instr >> 16 & 0xFF0) | (instr >> 4 & 0xF)) as usize]
+}
+
+#[inline]
+pub fn handle_instr<E: InterpEngine>(emu: &mut Emu<E>, instr: u32) {
+    handle_decoded_instr(emu, decode(instr), instr);
+}
 
 #[inline]
-pub fn handle_instr(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn handle_decoded_instr<E: InterpEngine>(emu: &mut Emu<E>, handler: Handler<E>, instr: u32) {
     if emu
         .arm7
         .engine_data
@@ -25,7 +42,7 @@ pub fn handle_instr(emu: &mut Emu<Interpreter>, instr: u32) {
         .cpsr
         .satisfies_condition((instr >> 28) as u8)
     {
-        INSTR_TABLE[((instr >> 16 & 0xFF0) | (instr >> 4 & 0xF)) as usize](emu, instr);
+        handler(emu, instr);
     } else {
         inc_r15!(emu.arm7, 4);
     }
@@ -1,5 +1,5 @@
 use super::super::{
-    super::Interpreter, enter_hle_irq, enter_hle_swi, handle_swi, handle_undefined,
+    super::InterpEngine, enter_hle_irq, enter_hle_swi, handle_swi, handle_undefined,
     reload_pipeline, return_from_hle_irq, set_cpsr_update_control, StateSource,
 };
 use crate::{
@@ -9,11 +9,11 @@ use crate::{
 };
 use core::intrinsics::unlikely;
 
-pub fn nop(emu: &mut Emu<Interpreter>, _instr: u32) {
+pub fn nop(emu: &mut Emu<impl InterpEngine>, _instr: u32) {
     inc_r15!(emu.arm7, 4);
 }
 
-pub fn mrs<const SPSR: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mrs<const SPSR: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let result = if SPSR {
         spsr!(emu.arm7).raw()
     } else {
@@ -28,7 +28,7 @@ pub fn mrs<const SPSR: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
     inc_r15!(emu.arm7, 4);
 }
 
-pub fn msr<const IMM: bool, const SPSR: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn msr<const IMM: bool, const SPSR: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     // TODO: Can reserved bits be modified in the SPSRs?
     let value = if IMM {
         (instr & 0xFF).rotate_right(instr >> 7 & 0x1E)
@@ -56,12 +56,12 @@ pub fn msr<const IMM: bool, const SPSR: bool>(emu: &mut Emu<Interpreter>, instr:
     inc_r15!(emu.arm7, 4);
 }
 
-pub fn swi(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn swi(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_swi::<false>(emu, (instr >> 16) as u8);
 }
 
 pub fn undefined<const MAYBE_HLE_BIOS_CALL: bool, const MAYBE_DLDI_CALL: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     instr: u32,
 ) {
     if MAYBE_HLE_BIOS_CALL
@@ -92,6 +92,10 @@ pub fn undefined<const MAYBE_HLE_BIOS_CALL: bool, const MAYBE_DLDI_CALL: bool>(
                 enter_hle_swi::<false>(emu, number, return_addr);
                 return;
             }
+            5 => {
+                hle_bios::arm7::resume_swi_callback(emu, reg!(emu.arm7, 0));
+                return;
+            }
             _ => {}
         }
     } else if MAYBE_DLDI_CALL
@@ -100,7 +104,7 @@ pub fn undefined<const MAYBE_HLE_BIOS_CALL: bool, const MAYBE_DLDI_CALL: bool>(
     {
         let function = instr as u8 & 0xF;
         if function < dldi::CALL_INSTR_FUNCTIONS {
-            reg!(emu.arm7, 0) = dldi::handle_call_instr_function::<Interpreter, false>(
+            reg!(emu.arm7, 0) = dldi::handle_call_instr_function::<_, false>(
                 emu,
                 function,
                 [reg!(emu.arm7, 0), reg!(emu.arm7, 1), reg!(emu.arm7, 2)],
@@ -116,7 +120,7 @@ pub fn undefined<const MAYBE_HLE_BIOS_CALL: bool, const MAYBE_DLDI_CALL: bool>(
 
 // TODO: Check what happens with cdp/ldc/stc P14,...
 
-pub fn mcr(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mcr(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     if instr >> 8 & 0xF == 14 {
         inc_r15!(emu.arm7, 4);
     } else {
@@ -125,7 +129,7 @@ pub fn mcr(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn mrc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mrc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     if instr >> 8 & 0xF == 14 {
         #[cfg(feature = "interp-pipeline")]
         let result = emu.arm7.engine_data.pipeline[1] as u32;
@@ -144,14 +148,14 @@ pub fn mrc(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn cdp(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn cdp(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
 
-pub fn ldc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn ldc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
 
-pub fn stc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn stc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
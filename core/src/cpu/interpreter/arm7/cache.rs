@@ -0,0 +1,397 @@
+#[cfg(not(feature = "interp-pipeline"))]
+use super::add_fetch_cycles;
+#[cfg(feature = "debugger-hooks")]
+use super::hit_breakpoint;
+use super::{
+    super::{
+        cache::{self, Block, Region, MAX_BLOCK_LEN, PAGE_MASK},
+        CachedInterpreter, CodeCache,
+    },
+    arm, step, thumb,
+};
+#[cfg(feature = "interp-pipeline")]
+use super::{advance_pipeline, handle_pipeline_entry};
+use crate::{
+    cpu::{
+        arm7::bus::{self, ptrs::Ptrs},
+        bus::{w_disable_flags, DebugCpuAccess},
+        Schedule as _,
+    },
+    emu::Emu,
+    Model,
+};
+use std::rc::Rc;
+
+type ArmOp = (arm::Handler<CachedInterpreter>, u32);
+type ThumbOp = (thumb::Handler<CachedInterpreter>, u16);
+
+const MAIN_MEM_START: u32 = 0x0200_0000;
+const MAIN_MEM_END: u32 = 0x02FF_FFFF;
+const SWRAM_START: u32 = 0x0300_0000;
+const WRAM_START: u32 = 0x0380_0000;
+const WRAM_END: u32 = 0x03FF_FFFF;
+
+/// The offset of SWRAM inside the local memory region, right after the ARM7's private WRAM.
+const LOCAL_SWRAM_BASE: u32 = 0x1_0000;
+
+#[derive(Clone, Copy)]
+enum RegionKind {
+    MainMem,
+    Local,
+}
+
+pub struct Cache {
+    main_mem: Region<ArmOp, ThumbOp>,
+    main_mem_mask: u32,
+    protected_main_mem_pages: [u64; 0x100_0000 / Ptrs::PAGE_SIZE / 64],
+    // Contains both WRAM and the SWRAM banks mapped to the ARM7, so that code running from WRAM
+    // through the SWRAM mirrors (when no SWRAM is mapped to the ARM7) shares its blocks
+    local: Region<ArmOp, ThumbOp>,
+    /// The base offset and mask used to get the local region offset of SWRAM addresses.
+    swram_mapping: (u32, u32),
+    protected_local_pages: u8,
+}
+
+impl Cache {
+    fn clear_main_mem(&mut self) {
+        self.main_mem.clear();
+        self.protected_main_mem_pages.fill(0);
+    }
+
+    fn clear_local(&mut self) {
+        self.local.clear();
+        self.protected_local_pages = 0;
+    }
+
+    fn region(&mut self, region: RegionKind) -> &mut Region<ArmOp, ThumbOp> {
+        match region {
+            RegionKind::MainMem => &mut self.main_mem,
+            RegionKind::Local => &mut self.local,
+        }
+    }
+}
+
+impl CodeCache for Cache {
+    fn new() -> Self {
+        Cache {
+            main_mem: Region::new(),
+            main_mem_mask: 0,
+            protected_main_mem_pages: [0; 0x100_0000 / Ptrs::PAGE_SIZE / 64],
+            local: Region::new(),
+            swram_mapping: (0, 0xFFFF),
+            protected_local_pages: 0,
+        }
+    }
+
+    #[inline]
+    fn invalidate_word(&mut self, addr: u32) {
+        match addr >> 24 {
+            0x02 => self.main_mem.invalidate_word(addr & self.main_mem_mask),
+            0x03 => self.local.invalidate_word(if addr & 1 << 23 == 0 {
+                self.swram_mapping.0 | (addr & self.swram_mapping.1)
+            } else {
+                addr & 0xFFFF
+            }),
+            _ => {}
+        }
+    }
+
+    #[inline]
+    fn invalidate_word_range(&mut self, (lower_bound, upper_bound): (u32, u32)) {
+        if lower_bound <= MAIN_MEM_END && upper_bound >= MAIN_MEM_START {
+            self.clear_main_mem();
+        }
+        if lower_bound <= WRAM_END && upper_bound >= SWRAM_START {
+            self.clear_local();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.clear_main_mem();
+        self.clear_local();
+    }
+}
+
+#[inline]
+fn code_region(emu: &mut Emu<CachedInterpreter>, addr: u32) -> Option<(RegionKind, u32)> {
+    // The GBA memory map is handled separately by the bus and never cached
+    if emu.arm7.is_in_gba_mode() {
+        return None;
+    }
+    match addr >> 24 {
+        0x02 => {
+            let main_mem_mask = emu.main_mem_mask().get();
+            let cache = &mut emu.arm7.engine_data.cache;
+            if cache.main_mem_mask != main_mem_mask {
+                cache.clear_main_mem();
+                cache.main_mem_mask = main_mem_mask;
+            }
+            Some((RegionKind::MainMem, addr & main_mem_mask))
+        }
+        // On the DSi, NWRAM can be mapped over the SWRAM area, and the fallback bus handlers that
+        // write protection relies on don't see it; code running from WRAM and SWRAM is never cached
+        // there, to avoid disabling the fast write pointers
+        0x03 if emu.model() != Model::Dsi => {
+            let swram_mapping = match emu.swram.control().layout() {
+                0 => (0, 0xFFFF),
+                1 => (LOCAL_SWRAM_BASE, 0x3FFF),
+                2 => (LOCAL_SWRAM_BASE | 0x4000, 0x3FFF),
+                _ => (LOCAL_SWRAM_BASE, 0x7FFF),
+            };
+            let cache = &mut emu.arm7.engine_data.cache;
+            if cache.swram_mapping != swram_mapping {
+                cache.clear_local();
+                cache.swram_mapping = swram_mapping;
+            }
+            Some((
+                RegionKind::Local,
+                if addr & 1 << 23 == 0 {
+                    swram_mapping.0 | (addr & swram_mapping.1)
+                } else {
+                    addr & 0xFFFF
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn protect_page(emu: &mut Emu<CachedInterpreter>, region: RegionKind, offset: u32) {
+    let cache = &mut emu.arm7.engine_data.cache;
+    let page = offset >> Ptrs::PAGE_SHIFT;
+    match region {
+        RegionKind::MainMem => {
+            if cache.protected_main_mem_pages[page as usize >> 6] & 1 << (page & 63) != 0 {
+                return;
+            }
+            cache.protected_main_mem_pages[page as usize >> 6] |= 1 << (page & 63);
+            // Main memory can also be written by the ARM9 and its DMA, so its fast write pointers
+            // need to be disabled too
+            let mirror_stride = cache.main_mem_mask as usize + 1;
+            let page_start = MAIN_MEM_START | (offset & !Ptrs::PAGE_MASK);
+            for page_addr in (page_start..=MAIN_MEM_END).step_by(mirror_stride) {
+                emu.arm7
+                    .bus_ptrs
+                    .disable_write(page_addr, w_disable_flags::JIT);
+                emu.arm9.disable_write_range(
+                    (page_addr, page_addr | Ptrs::PAGE_MASK),
+                    w_disable_flags::JIT,
+                );
+            }
+        }
+        RegionKind::Local => {
+            if cache.protected_local_pages & 1 << page != 0 {
+                return;
+            }
+            cache.protected_local_pages |= 1 << page;
+            let swram_mapping = cache.swram_mapping;
+            for page_addr in (SWRAM_START..=WRAM_END).step_by(Ptrs::PAGE_SIZE) {
+                let page_offset = if page_addr < WRAM_START {
+                    swram_mapping.0 | (page_addr & swram_mapping.1)
+                } else {
+                    page_addr & 0xFFFF
+                };
+                if page_offset >> Ptrs::PAGE_SHIFT == page {
+                    emu.arm7
+                        .bus_ptrs
+                        .disable_write(page_addr, w_disable_flags::JIT);
+                }
+            }
+        }
+    }
+}
+
+fn arm_block(emu: &mut Emu<CachedInterpreter>, addr: u32) -> Option<Rc<Block<ArmOp>>> {
+    let (region, offset) = code_region(emu, addr)?;
+    if let Some(block) = emu.arm7.engine_data.cache.region(region).arm_block(offset) {
+        return Some(block);
+    }
+    let mut ops = Vec::new();
+    let mut cur_addr = addr;
+    loop {
+        let instr = bus::read_32::<DebugCpuAccess, _>(emu, cur_addr);
+        ops.push((arm::decode(instr), instr));
+        cur_addr = cur_addr.wrapping_add(4);
+        if cache::arm_instr_ends_block(instr)
+            || cur_addr & PAGE_MASK == 0
+            || ops.len() == MAX_BLOCK_LEN
+        {
+            break;
+        }
+    }
+    protect_page(emu, region, offset);
+    let block = Block::new(
+        offset,
+        offset + (ops.len() << 2) as u32,
+        ops.into_boxed_slice(),
+    );
+    Some(
+        emu.arm7
+            .engine_data
+            .cache
+            .region(region)
+            .insert_arm_block(block),
+    )
+}
+
+fn thumb_block(emu: &mut Emu<CachedInterpreter>, addr: u32) -> Option<Rc<Block<ThumbOp>>> {
+    let (region, offset) = code_region(emu, addr)?;
+    if let Some(block) = emu
+        .arm7
+        .engine_data
+        .cache
+        .region(region)
+        .thumb_block(offset)
+    {
+        return Some(block);
+    }
+    let mut ops = Vec::new();
+    let mut cur_addr = addr;
+    loop {
+        let instr = bus::read_16::<DebugCpuAccess, _>(emu, cur_addr);
+        ops.push((thumb::decode(instr), instr));
+        cur_addr = cur_addr.wrapping_add(2);
+        if cache::thumb_instr_ends_block(instr)
+            || cur_addr & PAGE_MASK == 0
+            || ops.len() == MAX_BLOCK_LEN
+        {
+            break;
+        }
+    }
+    protect_page(emu, region, offset);
+    let block = Block::new(
+        offset,
+        offset + (ops.len() << 1) as u32,
+        ops.into_boxed_slice(),
+    );
+    Some(
+        emu.arm7
+            .engine_data
+            .cache
+            .region(region)
+            .insert_thumb_block(block),
+    )
+}
+
+/// Returns whether execution can continue with the next instruction in the current block, after
+/// the one at `prev_addr`.
+#[inline]
+fn can_continue_block<Op, const THUMB: bool>(
+    emu: &Emu<CachedInterpreter>,
+    block: &Rc<Block<Op>>,
+    prev_addr: u32,
+) -> bool {
+    cache::is_valid(block)
+        && reg!(emu.arm7, 15) == prev_addr.wrapping_add(if THUMB { 6 } else { 12 })
+        && emu.arm7.engine_data.regs.cpsr.thumb_state() == THUMB
+        && emu.arm7.schedule.cur_time() < emu.arm7.schedule.target_time()
+}
+
+/// Runs the ARM block starting at `addr` until its end, a change in control flow, its
+/// invalidation or the schedule's target time, returning `false` if execution was stopped by a
+/// breakpoint.
+fn run_arm_block(emu: &mut Emu<CachedInterpreter>, block: &Rc<Block<ArmOp>>, addr: u32) -> bool {
+    for (i, &(handler, instr)) in block.ops.iter().enumerate() {
+        let addr = addr.wrapping_add((i << 2) as u32);
+        if i != 0 {
+            if !can_continue_block::<_, false>(emu, block, addr.wrapping_sub(4)) {
+                return true;
+            }
+            #[cfg(feature = "debugger-hooks")]
+            if hit_breakpoint(emu) {
+                return false;
+            }
+        }
+        #[cfg(feature = "interp-pipeline")]
+        {
+            // The pipeline might hold instructions fetched before the block was last decoded, in
+            // which case they take precedence
+            let entry = advance_pipeline::<false>(emu);
+            if cache::is_arm_entry(entry, instr) {
+                emu.arm7.engine_data.prefetch_nseq = false;
+                arm::handle_decoded_instr(emu, handler, instr);
+            } else {
+                handle_pipeline_entry::<false>(emu, entry);
+            }
+        }
+        #[cfg(not(feature = "interp-pipeline"))]
+        {
+            add_fetch_cycles::<false>(emu, addr);
+            emu.arm7.engine_data.prefetch_nseq = false;
+            arm::handle_decoded_instr(emu, handler, instr);
+        }
+    }
+    true
+}
+
+/// Runs the Thumb block starting at `addr`; see [`run_arm_block`].
+fn run_thumb_block(
+    emu: &mut Emu<CachedInterpreter>,
+    block: &Rc<Block<ThumbOp>>,
+    addr: u32,
+) -> bool {
+    for (i, &(handler, instr)) in block.ops.iter().enumerate() {
+        let addr = addr.wrapping_add((i << 1) as u32);
+        if i != 0 {
+            if !can_continue_block::<_, true>(emu, block, addr.wrapping_sub(2)) {
+                return true;
+            }
+            #[cfg(feature = "debugger-hooks")]
+            if hit_breakpoint(emu) {
+                return false;
+            }
+        }
+        #[cfg(feature = "interp-pipeline")]
+        {
+            let entry = advance_pipeline::<true>(emu);
+            if cache::is_thumb_entry(entry, instr) {
+                emu.arm7.engine_data.prefetch_nseq = false;
+                handler(emu, instr);
+            } else {
+                handle_pipeline_entry::<true>(emu, entry);
+            }
+        }
+        #[cfg(not(feature = "interp-pipeline"))]
+        {
+            add_fetch_cycles::<true>(emu, addr);
+            emu.arm7.engine_data.prefetch_nseq = false;
+            handler(emu, instr);
+        }
+    }
+    true
+}
+
+/// Executes instructions until the schedule's target time is reached, running cached blocks for
+/// code in RAM and falling back to the interpreter's fetch and decode for everything else;
+/// returns `false` if execution was stopped by a breakpoint.
+pub(in super::super) fn run_instrs(emu: &mut Emu<CachedInterpreter>) -> bool {
+    while emu.arm7.schedule.cur_time() < emu.arm7.schedule.target_time() {
+        #[cfg(feature = "debugger-hooks")]
+        if hit_breakpoint(emu) {
+            return false;
+        }
+        let not_stopped = if emu.arm7.engine_data.regs.cpsr.thumb_state() {
+            let addr = reg!(emu.arm7, 15).wrapping_sub(4);
+            match thumb_block(emu, addr) {
+                Some(block) => run_thumb_block(emu, &block, addr),
+                None => {
+                    step(emu);
+                    true
+                }
+            }
+        } else {
+            let addr = reg!(emu.arm7, 15).wrapping_sub(8);
+            match arm_block(emu, addr) {
+                Some(block) => run_arm_block(emu, &block, addr),
+                None => {
+                    step(emu);
+                    true
+                }
+            }
+        };
+        if !not_stopped {
+            return false;
+        }
+    }
+    true
+}
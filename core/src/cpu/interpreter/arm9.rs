@@ -1,9 +1,15 @@
 mod arm;
+#[cfg(feature = "interp-cache")]
+pub(super) mod cache;
 mod thumb;
 
 #[cfg(feature = "interp-pipeline")]
 use super::common::{thumb_pipeline_entry, PipelineEntry};
-use super::{super::Regs as EngineRegs, common::StateSource, Interpreter, Regs};
+use super::{
+    super::{CoreState, Regs as EngineRegs},
+    common::StateSource,
+    CodeCache, InterpEngine, Regs,
+};
 #[cfg(feature = "debugger-hooks")]
 use crate::cpu::debug;
 #[cfg(feature = "interp-arm9-interlocks")]
@@ -32,7 +38,7 @@ struct Interlock {
 
 #[derive(Savestate)]
 #[load(in_place_only)]
-pub struct EngineData {
+pub struct EngineData<E: InterpEngine> {
     #[cfg(feature = "interp-pipeline-accurate-reloads")]
     r15_increment: u32,
     pub regs: Regs,
@@ -49,10 +55,12 @@ pub struct EngineData {
     #[savestate(skip)]
     next_breakpoint_addr: u32,
     exc_vectors_start: u32,
+    #[savestate(skip)]
+    cache: E::Arm9Cache,
 }
 
-impl EngineData {
-    pub const fn new() -> Self {
+impl<E: InterpEngine> EngineData<E> {
+    pub fn new() -> Self {
         EngineData {
             #[cfg(feature = "interp-pipeline-accurate-reloads")]
             r15_increment: 4,
@@ -72,12 +80,13 @@ impl EngineData {
             #[cfg(feature = "debugger-hooks")]
             next_breakpoint_addr: 0xFFFF_FFFF,
             exc_vectors_start: 0xFFFF_0000,
+            cache: E::Arm9Cache::new(),
         }
     }
 }
 
 #[inline]
-fn add_cycles(emu: &mut Emu<Interpreter>, cycles: RawTimestamp) {
+fn add_cycles(emu: &mut Emu<impl InterpEngine>, cycles: RawTimestamp) {
     emu.arm9
         .schedule
         .set_cur_time(emu.arm9.schedule.cur_time() + Timestamp(cycles));
@@ -86,7 +95,9 @@ fn add_cycles(emu: &mut Emu<Interpreter>, cycles: RawTimestamp) {
 const ARM_BKPT: u32 = 0xE120_0070;
 const THUMB_BKPT: u16 = 0xBE00;
 
-fn prefetch_arm<const RESET_DATA_CYCLES: bool, const INC_R15: bool>(emu: &mut Emu<Interpreter>) {
+fn prefetch_arm<const RESET_DATA_CYCLES: bool, const INC_R15: bool>(
+    emu: &mut Emu<impl InterpEngine>,
+) {
     #[cfg(feature = "interp-arm9-interlocks")]
     let fetch_addr = reg!(emu.arm9, 15);
     if INC_R15 {
@@ -117,7 +128,9 @@ fn prefetch_arm<const RESET_DATA_CYCLES: bool, const INC_R15: bool>(emu: &mut Em
     }
 }
 
-fn prefetch_thumb<const RESET_DATA_CYCLES: bool, const INC_R15: bool>(emu: &mut Emu<Interpreter>) {
+fn prefetch_thumb<const RESET_DATA_CYCLES: bool, const INC_R15: bool>(
+    emu: &mut Emu<impl InterpEngine>,
+) {
     #[cfg(feature = "interp-arm9-interlocks")]
     let fetch_addr = reg!(emu.arm9, 15);
     if INC_R15 {
@@ -165,14 +178,14 @@ fn prefetch_thumb<const RESET_DATA_CYCLES: bool, const INC_R15: bool>(emu: &mut
     allow(unused_variables, clippy::needless_pass_by_ref_mut)
 )]
 #[inline]
-fn apply_reg_interlock_1<const PORT_C: bool>(emu: &mut Emu<Interpreter>, reg: u8) {
+fn apply_reg_interlock_1<const PORT_C: bool>(emu: &mut Emu<impl InterpEngine>, reg: u8) {
     #[cfg(feature = "interp-arm9-interlocks")]
     {
         let interlock = emu.arm9.engine_data.interlocks[reg as usize];
         let interlock_end = if PORT_C {
             interlock.port_c
         } else {
-            interlock_c.port_ab
+            interlock.port_ab
         };
         if emu.arm9.engine_data.bus_cycle < interlock_end {
             add_cycles(
@@ -192,7 +205,7 @@ fn apply_reg_interlock_1<const PORT_C: bool>(emu: &mut Emu<Interpreter>, reg: u8
 )]
 #[inline]
 fn apply_reg_interlocks_2<const OFFSET_A: u8, const B_PORT_C: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     reg_a: u8,
     reg_b: u8,
 ) {
@@ -227,7 +240,7 @@ fn apply_reg_interlocks_2<const OFFSET_A: u8, const B_PORT_C: bool>(
 )]
 #[inline]
 fn apply_reg_interlocks_3<const OFFSET_AB: u8, const C_PORT_C: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     reg_a: u8,
     reg_b: u8,
     reg_c: u8,
@@ -266,7 +279,7 @@ fn apply_reg_interlocks_3<const OFFSET_AB: u8, const C_PORT_C: bool>(
 // this).
 
 #[inline]
-fn write_reg_clear_interlock_ab(emu: &mut Emu<Interpreter>, reg: u8, value: u32) {
+fn write_reg_clear_interlock_ab(emu: &mut Emu<impl InterpEngine>, reg: u8, value: u32) {
     #[cfg(feature = "interp-arm9-interlocks")]
     {
         emu.arm9.engine_data.interlocks[reg as usize].port_ab = 0;
@@ -275,7 +288,12 @@ fn write_reg_clear_interlock_ab(emu: &mut Emu<Interpreter>, reg: u8, value: u32)
 }
 
 #[inline]
-fn write_reg_interlock_ab(emu: &mut Emu<Interpreter>, reg: u8, value: u32, _offset: RawTimestamp) {
+fn write_reg_interlock_ab(
+    emu: &mut Emu<impl InterpEngine>,
+    reg: u8,
+    value: u32,
+    _offset: RawTimestamp,
+) {
     #[cfg(feature = "interp-arm9-interlocks")]
     {
         emu.arm9.engine_data.interlocks[reg as usize].port_ab =
@@ -286,7 +304,7 @@ fn write_reg_interlock_ab(emu: &mut Emu<Interpreter>, reg: u8, value: u32, _offs
 
 #[inline]
 fn write_reg_interlock(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     reg: u8,
     value: u32,
     _port_ab_offset: RawTimestamp,
@@ -308,7 +326,7 @@ fn write_reg_interlock(
 )]
 #[inline]
 fn add_interlock(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     reg: u8,
     port_ab_offset: RawTimestamp,
     port_c_offset: RawTimestamp,
@@ -331,7 +349,7 @@ fn add_interlock(
     allow(unused_variables, clippy::needless_pass_by_ref_mut)
 )]
 #[inline]
-fn add_bus_cycles(emu: &mut Emu<Interpreter>, cycles: RawTimestamp) {
+fn add_bus_cycles(emu: &mut Emu<impl InterpEngine>, cycles: RawTimestamp) {
     #[cfg(feature = "interp-arm9-interlocks")]
     {
         emu.arm9.engine_data.bus_cycle += cycles;
@@ -353,7 +371,7 @@ macro_rules! get_next_breakpoint {
     };
 }
 
-fn reload_pipeline<const STATE_SOURCE: StateSource>(emu: &mut Emu<Interpreter>) {
+fn reload_pipeline<const STATE_SOURCE: StateSource>(emu: &mut Emu<impl InterpEngine>) {
     let mut addr = reg!(emu.arm9, 15);
 
     if match STATE_SOURCE {
@@ -512,7 +530,7 @@ fn reload_pipeline<const STATE_SOURCE: StateSource>(emu: &mut Emu<Interpreter>)
 }
 
 #[inline]
-fn set_cpsr_update_control(emu: &mut Emu<Interpreter>, value: Psr) {
+fn set_cpsr_update_control(emu: &mut Emu<impl InterpEngine>, value: Psr) {
     let prev_value = emu.arm9.engine_data.regs.cpsr;
     emu.arm9.engine_data.regs.cpsr = value;
     emu.arm9
@@ -524,7 +542,7 @@ fn set_cpsr_update_control(emu: &mut Emu<Interpreter>, value: Psr) {
         .update_mode::<false>(prev_value.mode(), value.mode());
 }
 
-fn restore_spsr(emu: &mut Emu<Interpreter>) {
+fn restore_spsr(emu: &mut Emu<impl InterpEngine>) {
     if !emu.arm9.engine_data.regs.has_spsr() {
         unimplemented!("Unpredictable SPSR restore in non-exception mode");
     }
@@ -536,7 +554,7 @@ fn restore_spsr(emu: &mut Emu<Interpreter>) {
     }
 }
 
-fn handle_undefined<const THUMB: bool>(emu: &mut Emu<Interpreter>, _instr: u32) {
+fn handle_undefined<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>, _instr: u32) {
     #[cfg(feature = "log")]
     slog::warn!(
         emu.arm9.logger,
@@ -588,7 +606,7 @@ fn handle_undefined<const THUMB: bool>(emu: &mut Emu<Interpreter>, _instr: u32)
     reload_pipeline::<{ StateSource::Arm }>(emu);
 }
 
-fn handle_swi<const THUMB: bool>(emu: &mut Emu<Interpreter>, number: u8) {
+fn handle_swi<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>, number: u8) {
     #[cfg(feature = "debugger-hooks")]
     if let Some(swi_hook) = emu.arm9.swi_hook() {
         if unsafe { swi_hook.get()(emu, number) } {
@@ -639,7 +657,7 @@ fn handle_swi<const THUMB: bool>(emu: &mut Emu<Interpreter>, number: u8) {
     reload_pipeline::<{ StateSource::Arm }>(emu);
 }
 
-fn handle_prefetch_abort<const THUMB: bool>(emu: &mut Emu<Interpreter>) {
+fn handle_prefetch_abort<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>) {
     #[cfg(feature = "log")]
     slog::warn!(
         emu.arm9.logger,
@@ -687,7 +705,7 @@ fn handle_prefetch_abort<const THUMB: bool>(emu: &mut Emu<Interpreter>) {
     reload_pipeline::<{ StateSource::Arm }>(emu);
 }
 
-fn handle_data_abort<const THUMB: bool>(emu: &mut Emu<Interpreter>, _addr: u32) {
+fn handle_data_abort<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>, _addr: u32) {
     // r15 is assumed to be PC + 3i, and not PC + 2i (where i = instr size)
     #[cfg(feature = "log")]
     slog::warn!(
@@ -732,7 +750,7 @@ fn handle_data_abort<const THUMB: bool>(emu: &mut Emu<Interpreter>, _addr: u32)
 }
 
 fn enter_hle_swi<const FROM_USER_CODE: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     number: u8,
     return_addr: u32,
 ) {
@@ -784,7 +802,7 @@ fn enter_hle_swi<const FROM_USER_CODE: bool>(
     hle_bios::arm9::handle_swi(emu, number, emu.arm9.engine_data.regs.r0_3());
 }
 
-fn return_from_hle_swi(emu: &mut Emu<Interpreter>) {
+fn return_from_hle_swi(emu: &mut Emu<impl InterpEngine>) {
     let base_addr = reg!(emu.arm9, 13);
     reg!(emu.arm9, 13) = base_addr.wrapping_add(8);
     for (i, reg) in [2_u8, 14].into_iter().enumerate() {
@@ -813,7 +831,7 @@ fn return_from_hle_swi(emu: &mut Emu<Interpreter>) {
     reload_pipeline::<{ StateSource::Cpsr }>(emu);
 }
 
-fn enter_hle_irq<const FROM_USER_CODE: bool>(emu: &mut Emu<Interpreter>, return_addr: u32) {
+fn enter_hle_irq<const FROM_USER_CODE: bool>(emu: &mut Emu<impl InterpEngine>, return_addr: u32) {
     if FROM_USER_CODE {
         let prev_cpsr = emu.arm9.engine_data.regs.cpsr;
         emu.arm9.engine_data.regs.cpsr = prev_cpsr.with_mode(Mode::IRQ).with_irqs_disabled(true);
@@ -839,7 +857,7 @@ fn enter_hle_irq<const FROM_USER_CODE: bool>(emu: &mut Emu<Interpreter>, return_
     reg!(emu.arm9, 0) = hle_bios::arm9::handle_irq(emu);
 }
 
-fn return_from_hle_irq(emu: &mut Emu<Interpreter>) {
+fn return_from_hle_irq(emu: &mut Emu<impl InterpEngine>) {
     let base_addr = reg!(emu.arm9, 13);
     reg!(emu.arm9, 13) = base_addr.wrapping_add(0x18);
     for (i, reg) in [0_u8, 1, 2, 3, 12, 14].into_iter().enumerate() {
@@ -853,7 +871,7 @@ fn return_from_hle_irq(emu: &mut Emu<Interpreter>) {
 
 #[allow(unused_variables)]
 #[inline]
-fn can_read(emu: &Emu<Interpreter>, addr: u32, privileged: bool) -> bool {
+fn can_read(emu: &Emu<impl InterpEngine>, addr: u32, privileged: bool) -> bool {
     #[cfg(feature = "pu-checks")]
     {
         emu.arm9.cp15.perm_map.read(addr, privileged)
@@ -864,7 +882,7 @@ fn can_read(emu: &Emu<Interpreter>, addr: u32, privileged: bool) -> bool {
 
 #[allow(unused_variables)]
 #[inline]
-fn can_write(emu: &Emu<Interpreter>, addr: u32, privileged: bool) -> bool {
+fn can_write(emu: &Emu<impl InterpEngine>, addr: u32, privileged: bool) -> bool {
     #[cfg(feature = "pu-checks")]
     {
         emu.arm9.cp15.perm_map.write(addr, privileged)
@@ -875,7 +893,7 @@ fn can_write(emu: &Emu<Interpreter>, addr: u32, privileged: bool) -> bool {
 
 #[allow(unused_variables)]
 #[inline]
-fn can_execute(emu: &Emu<Interpreter>, addr: u32, privileged: bool) -> bool {
+fn can_execute(emu: &Emu<impl InterpEngine>, addr: u32, privileged: bool) -> bool {
     #[cfg(feature = "pu-checks")]
     {
         emu.arm9.cp15.perm_map.execute(addr, privileged)
@@ -884,17 +902,206 @@ fn can_execute(emu: &Emu<Interpreter>, addr: u32, privileged: bool) -> bool {
     true
 }
 
-impl CoreData for EngineData {
-    type Engine = Interpreter;
+/// Moves the pipeline forward, fetching the instruction 2 instructions ahead of the one about to
+/// be executed (unless interlocks are emulated, in which case instruction handlers perform the
+/// fetch themselves), and returns the latter.
+#[cfg(feature = "interp-pipeline")]
+#[inline(always)]
+fn advance_pipeline<const THUMB: bool>(emu: &mut Emu<impl InterpEngine>) -> PipelineEntry {
+    #[cfg(not(feature = "interp-arm9-interlocks"))]
+    let addr = reg!(emu.arm9, 15);
+    let instr = emu.arm9.engine_data.pipeline[0];
+    emu.arm9.engine_data.pipeline[0] = emu.arm9.engine_data.pipeline[1];
+    #[cfg(not(feature = "interp-arm9-interlocks"))]
+    if THUMB {
+        if addr & 2 == 0 {
+            if unlikely(!can_execute(
+                emu,
+                addr,
+                emu.arm9.engine_data.regs.is_in_priv_mode(),
+            )) {
+                // Cause a prefetch abort when run by replacing the prefetced instructions with
+                // BKPT
+                emu.arm9.engine_data.pipeline[1] = thumb_pipeline_entry(
+                    THUMB_BKPT as PipelineEntry | (THUMB_BKPT as PipelineEntry) << 16,
+                );
+                add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+            } else {
+                let new_instrs = bus::read_32::<CpuAccess, _, true>(emu, addr);
+                let cycles = emu.arm9.cp15.timings.get(addr).code;
+                emu.arm9.engine_data.pipeline[1] =
+                    thumb_pipeline_entry(new_instrs as PipelineEntry);
+                add_cycles(
+                    emu,
+                    cycles.max(emu.arm9.engine_data.data_cycles) as RawTimestamp,
+                );
+            }
+        } else {
+            emu.arm9.engine_data.pipeline[1] =
+                thumb_pipeline_entry(emu.arm9.engine_data.pipeline[1] >> 16);
+            add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+        }
+    } else if unlikely(!can_execute(
+        emu,
+        addr,
+        emu.arm9.engine_data.regs.is_in_priv_mode(),
+    )) {
+        // Cause a prefetch abort when run by replacing the prefetced instruction with BKPT
+        emu.arm9.engine_data.pipeline[1] = ARM_BKPT as PipelineEntry;
+        add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+    } else {
+        let new_instr = bus::read_32::<CpuAccess, _, true>(emu, addr);
+        let cycles = emu.arm9.cp15.timings.get(addr).code;
+        emu.arm9.engine_data.pipeline[1] = new_instr as PipelineEntry;
+        add_cycles(
+            emu,
+            cycles.max(emu.arm9.engine_data.data_cycles) as RawTimestamp,
+        );
+    }
+    instr
+}
+
+/// Executes an instruction taken from the pipeline by [`advance_pipeline`].
+#[cfg(feature = "interp-pipeline")]
+#[inline(always)]
+fn handle_pipeline_entry<const THUMB: bool>(
+    emu: &mut Emu<impl InterpEngine>,
+    instr: PipelineEntry,
+) {
+    // With interlocks, the data cycles of the previous instruction are only consumed by the
+    // handler's own prefetch
+    #[cfg(not(feature = "interp-arm9-interlocks"))]
+    {
+        emu.arm9.engine_data.data_cycles = 1;
+    }
+    #[cfg(feature = "interp-pipeline-accurate-reloads")]
+    if instr & 1 << 32 == 0 {
+        arm::handle_instr(emu, instr as u32);
+    } else {
+        thumb::handle_instr(emu, instr as u16);
+    }
+    #[cfg(not(feature = "interp-pipeline-accurate-reloads"))]
+    if THUMB {
+        thumb::handle_instr(emu, instr as u16);
+    } else {
+        arm::handle_instr(emu, instr as u32);
+    }
+}
+
+/// Adds the timings of the code fetch for the instruction at `addr`.
+#[cfg(not(feature = "interp-pipeline"))]
+#[inline(always)]
+fn add_fetch_cycles(emu: &mut Emu<impl InterpEngine>, addr: u32) {
+    add_cycles(
+        emu,
+        emu.arm9
+            .cp15
+            .timings
+            .get(addr)
+            .code
+            .max(emu.arm9.engine_data.data_cycles) as RawTimestamp,
+    );
+}
+
+/// Fetches and executes a single instruction.
+#[inline(always)]
+fn step(emu: &mut Emu<impl InterpEngine>) {
+    #[cfg(feature = "interp-pipeline")]
+    if emu.arm9.engine_data.regs.cpsr.thumb_state() {
+        let instr = advance_pipeline::<true>(emu);
+        handle_pipeline_entry::<true>(emu, instr);
+    } else {
+        let instr = advance_pipeline::<false>(emu);
+        handle_pipeline_entry::<false>(emu, instr);
+    }
+    #[cfg(not(feature = "interp-pipeline"))]
+    if emu.arm9.engine_data.regs.cpsr.thumb_state() {
+        let addr = reg!(emu.arm9, 15).wrapping_sub(4);
+        if addr & 2 != 0 {
+            add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+            emu.arm9.engine_data.data_cycles = 1;
+            let instr = emu.arm9.engine_data.thumb_next_instr;
+            thumb::handle_instr(emu, instr);
+        } else if unlikely(!can_execute(
+            emu,
+            addr,
+            emu.arm9.engine_data.regs.is_in_priv_mode(),
+        )) {
+            add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+            emu.arm9.engine_data.data_cycles = 1;
+            thumb::handle_instr(emu, THUMB_BKPT);
+        } else {
+            let instrs = bus::read_32::<CpuAccess, _, true>(emu, addr);
+            add_fetch_cycles(emu, addr);
+            emu.arm9.engine_data.thumb_next_instr = (instrs >> 16) as u16;
+            emu.arm9.engine_data.data_cycles = 1;
+            thumb::handle_instr(emu, instrs as u16);
+        }
+    } else {
+        let addr = reg!(emu.arm9, 15).wrapping_sub(8);
+        if unlikely(!can_execute(
+            emu,
+            addr,
+            emu.arm9.engine_data.regs.is_in_priv_mode(),
+        )) {
+            add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+            emu.arm9.engine_data.data_cycles = 1;
+            arm::handle_instr(emu, ARM_BKPT);
+        } else {
+            let instr = bus::read_32::<CpuAccess, _, true>(emu, addr);
+            add_fetch_cycles(emu, addr);
+            emu.arm9.engine_data.data_cycles = 1;
+            arm::handle_instr(emu, instr);
+        }
+    }
+}
+
+/// Calls the breakpoint hook if the next instruction to be executed has a breakpoint set on it,
+/// returning whether execution should stop.
+#[cfg(feature = "debugger-hooks")]
+#[inline]
+fn hit_breakpoint(emu: &mut Emu<impl InterpEngine>) -> bool {
+    let r15 =
+        reg!(emu.arm9, 15).wrapping_sub(8 >> emu.arm9.engine_data.regs.cpsr.thumb_state() as u8);
+    if emu.arm9.engine_data.next_breakpoint_addr == r15 {
+        if let Some(breakpoint_hook) = emu.arm9.breakpoint_hook().as_ref() {
+            if unsafe { breakpoint_hook.get()(emu, r15) } {
+                emu.arm9
+                    .schedule
+                    .set_target_time(emu.arm9.schedule.cur_time());
+                emu.arm9.was_stopped_by_debug_hook = true;
+                emu.arm9.is_stopped = true;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Executes instructions one at a time until the schedule's target time is reached, returning
+/// `false` if execution was stopped by a breakpoint.
+pub(super) fn run_instrs(emu: &mut Emu<impl InterpEngine>) -> bool {
+    while emu.arm9.schedule.cur_time() < emu.arm9.schedule.target_time() {
+        #[cfg(feature = "debugger-hooks")]
+        if hit_breakpoint(emu) {
+            return false;
+        }
+        step(emu);
+    }
+    true
+}
+
+impl<E: InterpEngine> CoreData for EngineData<E> {
+    type Engine = E;
 
     #[inline]
-    fn setup(emu: &mut Emu<Interpreter>) {
+    fn setup(emu: &mut Emu<E>) {
         add_bus_cycles(emu, 2);
         reg!(emu.arm9, 15) = 0xFFFF_0000;
         reload_pipeline::<{ StateSource::Arm }>(emu);
     }
 
-    fn setup_direct_boot(emu: &mut Emu<Interpreter>, entry_addr: u32) {
+    fn setup_direct_boot(emu: &mut Emu<E>, entry_addr: u32) {
         let prev_mode = emu.arm9.engine_data.regs.cpsr.mode();
         emu.arm9.engine_data.regs.cpsr.set_mode(Mode::SYSTEM);
         emu.arm9
@@ -923,22 +1130,27 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn post_load(_emu: &mut Emu<Interpreter>) {
+    fn post_load(emu: &mut Emu<E>) {
         get_next_breakpoint!(
-            _emu,
-            reg!(_emu.arm9, 15),
-            1 | (!_emu.arm9.engine_data.regs.cpsr.thumb_state() as u32) << 1
+            emu,
+            reg!(emu.arm9, 15),
+            1 | (!emu.arm9.engine_data.regs.cpsr.thumb_state() as u32) << 1
         );
+        emu.arm9.engine_data.cache.clear();
     }
 
     #[inline]
-    fn invalidate_word(&mut self, _addr: u32) {}
+    fn invalidate_word(&mut self, addr: u32) {
+        self.cache.invalidate_word(addr);
+    }
 
     #[inline]
-    fn invalidate_word_range(&mut self, _bounds: (u32, u32)) {}
+    fn invalidate_word_range(&mut self, bounds: (u32, u32)) {
+        self.cache.invalidate_word_range(bounds);
+    }
 
     #[inline]
-    fn jump(emu: &mut Emu<Interpreter>, addr: u32) {
+    fn jump(emu: &mut Emu<E>, addr: u32) {
         reg!(emu.arm9, 15) = addr;
         reload_pipeline::<{ StateSource::R15Bit0 }>(emu);
     }
@@ -954,7 +1166,7 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn set_cpsr(emu: &mut Emu<Interpreter>, value: Psr) {
+    fn set_cpsr(emu: &mut Emu<E>, value: Psr) {
         set_cpsr_update_control(emu, value);
         #[cfg(feature = "interp-pipeline-accurate-reloads")]
         {
@@ -969,7 +1181,7 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn set_regs(emu: &mut Emu<Interpreter>, regs: &EngineRegs) {
+    fn set_regs(emu: &mut Emu<E>, regs: &EngineRegs) {
         emu.arm9.engine_data.regs.set_from_engine_regs(regs);
         reg!(emu.arm9, 15) = reg!(emu.arm9, 15)
             .wrapping_sub(8 >> emu.arm9.engine_data.regs.cpsr.thumb_state() as u8);
@@ -977,7 +1189,89 @@ impl CoreData for EngineData {
     }
 
     #[inline]
-    fn jump_and_link(emu: &mut Emu<Interpreter>, addr: u32, lr: u32) {
+    fn core_state(&self) -> CoreState {
+        CoreState {
+            regs: self.regs.to_engine_regs(),
+            cpsr: self.regs.cpsr,
+            #[cfg(feature = "interp-pipeline")]
+            pipeline: Some(self.pipeline.map(|entry| {
+                if self.regs.cpsr.thumb_state() {
+                    entry as u16 as u32
+                } else {
+                    entry as u32
+                }
+            })),
+            #[cfg(not(feature = "interp-pipeline"))]
+            pipeline: None,
+        }
+    }
+
+    fn set_core_state(emu: &mut Emu<E>, state: &CoreState) {
+        emu.arm9.engine_data.regs.restore_cpsr(state.cpsr);
+        emu.arm9.engine_data.regs.set_from_engine_regs(&state.regs);
+        #[cfg(feature = "interp-pipeline-accurate-reloads")]
+        {
+            emu.arm9.engine_data.r15_increment = 4 >> state.cpsr.thumb_state() as u8;
+        }
+        #[cfg(feature = "interp-pipeline")]
+        {
+            let thumb = state.cpsr.thumb_state();
+            let pipeline = state.pipeline.unwrap_or_else(|| {
+                let addr = reg!(emu.arm9, 15);
+                if thumb {
+                    [
+                        bus::read_16::<CpuAccess, _>(emu, addr.wrapping_sub(4)) as u32,
+                        bus::read_16::<CpuAccess, _>(emu, addr.wrapping_sub(2)) as u32,
+                    ]
+                } else {
+                    [
+                        bus::read_32::<CpuAccess, _, true>(emu, addr.wrapping_sub(8)),
+                        bus::read_32::<CpuAccess, _, true>(emu, addr.wrapping_sub(4)),
+                    ]
+                }
+            });
+            emu.arm9.engine_data.pipeline = if thumb {
+                let mut pipeline = pipeline.map(|instr| instr & 0xFFFF);
+                // Word-aligned Thumb fetches also contain the following instruction in their upper
+                // halfword, which gets used for the next prefetch
+                let second_addr = reg!(emu.arm9, 15).wrapping_sub(2);
+                if second_addr & 2 == 0 {
+                    pipeline[1] |= (bus::read_16::<CpuAccess, _>(emu, second_addr.wrapping_add(2))
+                        as u32)
+                        << 16;
+                }
+                pipeline.map(|instr| thumb_pipeline_entry(instr as PipelineEntry))
+            } else {
+                pipeline.map(|instr| instr as PipelineEntry)
+            };
+        }
+        #[cfg(not(feature = "interp-pipeline"))]
+        {
+            // The second halfword of a Thumb instruction pair is only fetched along with the
+            // first one
+            let addr = reg!(emu.arm9, 15).wrapping_sub(4);
+            if emu.arm9.engine_data.regs.cpsr.thumb_state() && addr & 2 != 0 {
+                emu.arm9.engine_data.thumb_next_instr = match state.pipeline {
+                    Some(pipeline) => pipeline[0] as u16,
+                    None => bus::read_16::<CpuAccess, _>(emu, addr),
+                };
+            }
+        }
+        #[cfg(feature = "interp-arm9-interlocks")]
+        {
+            emu.arm9.engine_data.bus_cycle = 0;
+            emu.arm9.engine_data.interlocks = [Interlock {
+                port_ab: 0,
+                port_c: 0,
+            }; 16];
+        }
+        emu.arm9.engine_data.data_cycles = 1;
+        // Memory contents and mappings were replaced without going through the bus
+        emu.arm9.engine_data.cache.clear();
+    }
+
+    #[inline]
+    fn jump_and_link(emu: &mut Emu<E>, addr: u32, lr: u32) {
         reg!(emu.arm9, 14) = lr;
         reg!(emu.arm9, 15) = addr;
         reload_pipeline::<{ StateSource::R15Bit0 }>(emu);
@@ -989,13 +1283,19 @@ impl CoreData for EngineData {
         return_from_hle_swi(emu);
     }
 
+    #[inline]
+    fn call_from_hle_swi(emu: &mut Emu<Self::Engine>, addr: u32, r0_3: [u32; 4], lr: u32) {
+        emu.arm9.engine_data.regs.set_r0_3(r0_3);
+        Self::jump_and_link(emu, addr, lr);
+    }
+
     cfg_if::cfg_if! {
         if #[cfg(feature = "debugger-hooks")] {
             #[inline]
-            fn set_swi_hook(&mut self, _hook: &Option<debug::SwiHook<Interpreter>>) {}
+            fn set_swi_hook(&mut self, _hook: &Option<debug::SwiHook<E>>) {}
 
             #[inline]
-            fn set_undef_hook(&mut self, _hook: &Option<debug::UndefHook<Interpreter>>) {}
+            fn set_undef_hook(&mut self, _hook: &Option<debug::UndefHook<E>>) {}
 
             #[inline]
             fn add_breakpoint(&mut self, addr: u32) {
@@ -1024,12 +1324,12 @@ impl CoreData for EngineData {
             }
 
             #[inline]
-            fn set_breakpoint_hook(&mut self, _hook: &Option<debug::BreakpointHook<Interpreter>>) {}
+            fn set_breakpoint_hook(&mut self, _hook: &Option<debug::BreakpointHook<E>>) {}
 
             #[inline]
             fn set_mem_watchpoint_hook(
                 &mut self,
-                _hook: &Option<debug::MemWatchpointHook<Interpreter>>,
+                _hook: &Option<debug::MemWatchpointHook<E>>,
             ) {}
 
             #[inline]
@@ -1075,7 +1375,7 @@ macro_rules! handle_pending_events {
     };
 }
 
-impl Arm9Data for EngineData {
+impl<E: InterpEngine> Arm9Data for EngineData<E> {
     #[inline]
     fn set_high_exc_vectors(&mut self, value: bool) {
         self.exc_vectors_start = if value { 0xFFFF_0000 } else { 0 };
@@ -1089,22 +1389,22 @@ impl Arm9Data for EngineData {
             #[inline]
             fn set_prefetch_abort_hook(
                 &mut self,
-                _hook: &Option<debug::PrefetchAbortHook<Interpreter>>,
+                _hook: &Option<debug::PrefetchAbortHook<E>>,
             ) {}
 
             #[inline]
-            fn set_data_abort_hook(&mut self, _hook: &Option<debug::DataAbortHook<Interpreter>>) {}
+            fn set_data_abort_hook(&mut self, _hook: &Option<debug::DataAbortHook<E>>) {}
         }
     }
 
     #[inline]
-    fn run_stalled_until(emu: &mut Emu<Interpreter>, end_time: Timestamp) {
+    fn run_stalled_until(emu: &mut Emu<E>, end_time: Timestamp) {
         emu.arm9.schedule.set_cur_time(end_time);
         handle_pending_events!(emu, {});
     }
 
     #[inline]
-    fn run_until(emu: &mut Emu<Interpreter>, end_time: Timestamp) {
+    fn run_until(emu: &mut Emu<E>, end_time: Timestamp) {
         while emu.arm9.schedule.cur_time() < end_time {
             handle_pending_events!(emu, return);
             emu.arm9
@@ -1169,167 +1469,8 @@ impl Arm9Data for EngineData {
                         .set_cur_time(emu.arm9.schedule.target_time());
                     continue;
                 }
-                while emu.arm9.schedule.cur_time() < emu.arm9.schedule.target_time() {
-                    #[cfg(feature = "debugger-hooks")]
-                    {
-                        let r15 = reg!(emu.arm9, 15)
-                            .wrapping_sub(8 >> emu.arm9.engine_data.regs.cpsr.thumb_state() as u8);
-                        if emu.arm9.engine_data.next_breakpoint_addr == r15 {
-                            if let Some(breakpoint_hook) = emu.arm9.breakpoint_hook().as_ref() {
-                                if unsafe { breakpoint_hook.get()(emu, r15) } {
-                                    emu.arm9
-                                        .schedule
-                                        .set_target_time(emu.arm9.schedule.cur_time());
-                                    emu.arm9.was_stopped_by_debug_hook = true;
-                                    emu.arm9.is_stopped = true;
-                                    return;
-                                }
-                            }
-                        }
-                    }
-                    #[cfg(feature = "interp-pipeline")]
-                    {
-                        #[cfg(not(feature = "interp-arm9-interlocks"))]
-                        let addr = reg!(emu.arm9, 15);
-                        let instr = emu.arm9.engine_data.pipeline[0];
-                        emu.arm9.engine_data.pipeline[0] = emu.arm9.engine_data.pipeline[1];
-                        #[cfg(not(feature = "interp-arm9-interlocks"))]
-                        if emu.arm9.engine_data.regs.cpsr.thumb_state() {
-                            if addr & 2 == 0 {
-                                if unlikely(!can_execute(
-                                    emu,
-                                    addr,
-                                    emu.arm9.engine_data.regs.is_in_priv_mode(),
-                                )) {
-                                    // Cause a prefetch abort when run by replacing the prefetced
-                                    // instructions with BKPT
-                                    emu.arm9.engine_data.pipeline[1] = thumb_pipeline_entry(
-                                        THUMB_BKPT as PipelineEntry
-                                            | (THUMB_BKPT as PipelineEntry) << 16,
-                                    );
-                                    add_cycles(
-                                        emu,
-                                        emu.arm9.engine_data.data_cycles as RawTimestamp,
-                                    );
-                                } else {
-                                    let new_instrs = bus::read_32::<CpuAccess, _, true>(emu, addr);
-                                    let cycles = emu.arm9.cp15.timings.get(addr).code;
-                                    emu.arm9.engine_data.pipeline[1] =
-                                        thumb_pipeline_entry(new_instrs as PipelineEntry);
-                                    add_cycles(
-                                        emu,
-                                        cycles.max(emu.arm9.engine_data.data_cycles)
-                                            as RawTimestamp,
-                                    );
-                                }
-                            } else {
-                                emu.arm9.engine_data.pipeline[1] =
-                                    thumb_pipeline_entry(emu.arm9.engine_data.pipeline[1] >> 16);
-                                add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
-                            }
-                            #[cfg(not(feature = "interp-pipeline-accurate-reloads"))]
-                            {
-                                emu.arm9.engine_data.data_cycles = 1;
-                                thumb::handle_instr(emu, instr as u16);
-                            }
-                        } else {
-                            if unlikely(!can_execute(
-                                emu,
-                                addr,
-                                emu.arm9.engine_data.regs.is_in_priv_mode(),
-                            )) {
-                                // Cause a prefetch abort when run by replacing the prefetced
-                                // instruction with BKPT
-                                emu.arm9.engine_data.pipeline[1] = ARM_BKPT as PipelineEntry;
-                                add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
-                            } else {
-                                let new_instr = bus::read_32::<CpuAccess, _, true>(emu, addr);
-                                let cycles = emu.arm9.cp15.timings.get(addr).code;
-                                emu.arm9.engine_data.pipeline[1] = new_instr as PipelineEntry;
-                                add_cycles(
-                                    emu,
-                                    cycles.max(emu.arm9.engine_data.data_cycles) as RawTimestamp,
-                                );
-                            }
-                            #[cfg(not(feature = "interp-pipeline-accurate-reloads"))]
-                            {
-                                emu.arm9.engine_data.data_cycles = 1;
-                                arm::handle_instr(emu, instr as u32);
-                            }
-                        }
-                        #[cfg(feature = "interp-pipeline-accurate-reloads")]
-                        {
-                            emu.arm9.engine_data.data_cycles = 1;
-                            if instr & 1 << 32 == 0 {
-                                arm::handle_instr(emu, instr as u32);
-                            } else {
-                                thumb::handle_instr(emu, instr as u16);
-                            }
-                        }
-                    }
-                    #[cfg(not(feature = "interp-pipeline"))]
-                    {
-                        if emu.arm9.engine_data.regs.cpsr.thumb_state() {
-                            let addr = reg!(emu.arm9, 15).wrapping_sub(4);
-                            let instr = if addr & 2 == 0 {
-                                if unlikely(!can_execute(
-                                    emu,
-                                    addr,
-                                    emu.arm9.engine_data.regs.is_in_priv_mode(),
-                                )) {
-                                    add_cycles(
-                                        emu,
-                                        emu.arm9.engine_data.data_cycles as RawTimestamp,
-                                    );
-                                    THUMB_BKPT
-                                } else {
-                                    let instrs = bus::read_32::<CpuAccess, _, true>(emu, addr);
-                                    add_cycles(
-                                        emu,
-                                        emu.arm9
-                                            .cp15
-                                            .timings
-                                            .get(addr)
-                                            .code
-                                            .max(emu.arm9.engine_data.data_cycles)
-                                            as RawTimestamp,
-                                    );
-                                    emu.arm9.engine_data.thumb_next_instr = (instrs >> 16) as u16;
-                                    instrs as u16
-                                }
-                            } else {
-                                add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
-                                emu.arm9.engine_data.thumb_next_instr
-                            };
-                            emu.arm9.engine_data.data_cycles = 1;
-                            thumb::handle_instr(emu, instr);
-                        } else {
-                            let addr = reg!(emu.arm9, 15).wrapping_sub(8);
-                            let instr = if unlikely(!can_execute(
-                                emu,
-                                addr,
-                                emu.arm9.engine_data.regs.is_in_priv_mode(),
-                            )) {
-                                add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
-                                ARM_BKPT
-                            } else {
-                                let instr = bus::read_32::<CpuAccess, _, true>(emu, addr);
-                                add_cycles(
-                                    emu,
-                                    emu.arm9
-                                        .cp15
-                                        .timings
-                                        .get(addr)
-                                        .code
-                                        .max(emu.arm9.engine_data.data_cycles)
-                                        as RawTimestamp,
-                                );
-                                instr
-                            };
-                            emu.arm9.engine_data.data_cycles = 1;
-                            arm::handle_instr(emu, instr);
-                        }
-                    }
+                if !E::run_arm9_instrs(emu) {
+                    return;
                 }
             }
         }
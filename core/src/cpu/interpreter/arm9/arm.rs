@@ -11,25 +11,44 @@ use super::{add_bus_cycles, prefetch_arm};
 use crate::{
     cpu::interpreter::{
         common::{DpOpTy, DpOperand, MiscAddressing, ShiftTy, WbAddressing, WbOffTy},
-        Interpreter,
+        InterpEngine,
     },
     emu::Emu,
 };
+use core::marker::PhantomData;
 
-static INSTR_TABLE_COND: [fn(&mut Emu<Interpreter>, u32); 0x1000] =
-    include!(concat!(env!("OUT_DIR"), "/interp_arm9_arm_cond.rs"));
+pub type Handler<E> = fn(&mut Emu<E>, u32);
 
-static INSTR_TABLE_UNCOND: [fn(&mut Emu<Interpreter>, u32); 0x1000] =
-    include!(concat!(env!("OUT_DIR"), "/interp_arm9_arm_uncond.rs"));
+struct Tables<E>(PhantomData<E>);
+
+impl<E: InterpEngine> Tables<E> {
+    const INSTR_TABLE_COND: &'static [Handler<E>; 0x1000] =
+        &include!(concat!(env!("OUT_DIR"), "/interp_arm9_arm_cond.rs"));
+
+    const INSTR_TABLE_UNCOND: &'static [Handler<E>; 0x1000] =
+        &include!(concat!(env!("OUT_DIR"), "/interp_arm9_arm_uncond.rs"));
+}
 
 #[inline]
-pub fn handle_instr(emu: &mut Emu<Interpreter>, instr: u32) {
-    let cond = (instr >> 28) as u8;
+pub fn decode<E: InterpEngine>(instr: u32) -> Handler<E> {
     let index = ((instr >> 16 & 0xFF0) | (instr >> 4 & 0xF)) as usize;
-    if cond == 0xF {
-        INSTR_TABLE_UNCOND[index](emu, instr);
-    } else if emu.arm9.engine_data.regs.cpsr.satisfies_condition(cond) {
-        INSTR_TABLE_COND[index](emu, instr);
+    if instr >> 28 == 0xF {
+        Tables::<E>::INSTR_TABLE_UNCOND[index]
+    } else {
+        Tables::<E>::INSTR_TABLE_COND[index]
+    }
+}
+
+#[inline]
+pub fn handle_instr<E: InterpEngine>(emu: &mut Emu<E>, instr: u32) {
+    handle_decoded_instr(emu, decode(instr), instr);
+}
+
+#[inline]
+pub fn handle_decoded_instr<E: InterpEngine>(emu: &mut Emu<E>, handler: Handler<E>, instr: u32) {
+    let cond = (instr >> 28) as u8;
+    if cond == 0xF || emu.arm9.engine_data.regs.cpsr.satisfies_condition(cond) {
+        handler(emu, instr);
     } else {
         add_bus_cycles(emu, 1);
         prefetch_arm::<true, true>(emu);
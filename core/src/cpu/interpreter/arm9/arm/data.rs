@@ -7,7 +7,7 @@ use crate::{
     cpu::interpreter::{
         alu_utils::{arithmetic, bit_ops, shifts},
         common::{DpOpTy, DpOperand, ShiftTy, StateSource},
-        Interpreter,
+        InterpEngine,
     },
     emu::Emu,
 };
@@ -18,7 +18,7 @@ use core::intrinsics::{likely, unlikely};
 //       others take 3, regardless of the operation.
 
 pub fn dp_op<const OP_TY: DpOpTy, const OPERAND: DpOperand, const SET_FLAGS: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     instr: u32,
 ) {
     let src_reg = (instr >> 16 & 0xF) as u8;
@@ -267,7 +267,7 @@ pub fn dp_op<const OP_TY: DpOpTy, const OPERAND: DpOperand, const SET_FLAGS: boo
     }
 }
 
-pub fn clz(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn clz(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     apply_reg_interlock_1::<false>(emu, src_reg);
     add_bus_cycles(emu, 1);
@@ -279,7 +279,7 @@ pub fn clz(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn mul<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mul<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 8 & 0xF) as u8;
     let acc_reg = (instr >> 12 & 0xF) as u8;
@@ -315,7 +315,7 @@ pub fn mul<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<Interpreter>, i
 //   the multiplication instruction.
 // - Only RdHi can cause an interlock in subsequent instructions, RdLo is immediately available.
 
-pub fn umull<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn umull<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 8 & 0xF) as u8;
     let dst_acc_reg_low = (instr >> 12 & 0xF) as u8;
@@ -350,7 +350,7 @@ pub fn umull<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<Interpreter>,
     }
 }
 
-pub fn smull<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn smull<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 8 & 0xF) as u8;
     let dst_acc_reg_low = (instr >> 12 & 0xF) as u8;
@@ -386,7 +386,7 @@ pub fn smull<const ACC: bool, const SET_FLAGS: bool>(emu: &mut Emu<Interpreter>,
     }
 }
 
-pub fn smulxy<const ACC: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn smulxy<const ACC: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 8 & 0xF) as u8;
     let acc_reg = (instr >> 12 & 0xF) as u8;
@@ -413,7 +413,7 @@ pub fn smulxy<const ACC: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn smulwy<const ACC: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn smulwy<const ACC: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 8 & 0xF) as u8;
     let acc_reg = (instr >> 12 & 0xF) as u8;
@@ -440,7 +440,7 @@ pub fn smulwy<const ACC: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn smlalxy(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn smlalxy(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 8 & 0xF) as u8;
     let dst_acc_reg_low = (instr >> 12 & 0xF) as u8;
@@ -462,7 +462,7 @@ pub fn smlalxy(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn qaddsub<const SUB: bool, const DOUBLED: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn qaddsub<const SUB: bool, const DOUBLED: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let src_reg = (instr & 0xF) as u8;
     let op_reg = (instr >> 16 & 0xF) as u8;
     apply_reg_interlocks_2::<0, false>(emu, src_reg, op_reg);
@@ -9,7 +9,7 @@ use crate::{
         arm9::{bus, cp15::Cp15},
         bus::CpuAccess,
         hle_bios,
-        interpreter::Interpreter,
+        interpreter::InterpEngine,
         psr::Psr,
     },
     dldi,
@@ -17,7 +17,7 @@ use crate::{
 };
 use core::intrinsics::{likely, unlikely};
 
-pub fn mrs<const SPSR: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mrs<const SPSR: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let result = if SPSR {
         spsr!(emu.arm9).raw()
     } else {
@@ -32,7 +32,7 @@ pub fn mrs<const SPSR: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
     add_cycles(emu, 1);
 }
 
-pub fn msr<const IMM: bool, const SPSR: bool>(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn msr<const IMM: bool, const SPSR: bool>(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     let value = if IMM {
         (instr & 0xFF).rotate_right(instr >> 7 & 0x1E)
     } else {
@@ -63,16 +63,16 @@ pub fn msr<const IMM: bool, const SPSR: bool>(emu: &mut Emu<Interpreter>, instr:
     }
 }
 
-pub fn bkpt(emu: &mut Emu<Interpreter>, _instr: u32) {
+pub fn bkpt(emu: &mut Emu<impl InterpEngine>, _instr: u32) {
     handle_prefetch_abort::<false>(emu);
 }
 
-pub fn swi(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn swi(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_swi::<false>(emu, (instr >> 16) as u8);
 }
 
 pub fn undefined<const MAYBE_HLE_BIOS_SWI: bool, const MAYBE_DLDI_CALL: bool>(
-    emu: &mut Emu<Interpreter>,
+    emu: &mut Emu<impl InterpEngine>,
     instr: u32,
 ) {
     if MAYBE_HLE_BIOS_SWI
@@ -103,6 +103,10 @@ pub fn undefined<const MAYBE_HLE_BIOS_SWI: bool, const MAYBE_DLDI_CALL: bool>(
                 enter_hle_swi::<false>(emu, number, return_addr);
                 return;
             }
+            5 => {
+                hle_bios::arm9::resume_swi_callback(emu, reg!(emu.arm9, 0));
+                return;
+            }
             _ => {}
         }
     } else if MAYBE_DLDI_CALL
@@ -111,7 +115,7 @@ pub fn undefined<const MAYBE_HLE_BIOS_SWI: bool, const MAYBE_DLDI_CALL: bool>(
     {
         let function = instr as u8 & 0xF;
         if function < dldi::CALL_INSTR_FUNCTIONS {
-            reg!(emu.arm9, 0) = dldi::handle_call_instr_function::<Interpreter, true>(
+            reg!(emu.arm9, 0) = dldi::handle_call_instr_function::<_, true>(
                 emu,
                 function,
                 [reg!(emu.arm9, 0), reg!(emu.arm9, 1), reg!(emu.arm9, 2)],
@@ -129,7 +133,7 @@ pub fn undefined<const MAYBE_HLE_BIOS_SWI: bool, const MAYBE_DLDI_CALL: bool>(
 // TODO: Confirm that MCRR/MRRC are undefined for CP15 (the ARM946E-S manual only mentions CDP, LDC
 //       and STC), and check timings of undefined CDP/LDC/STC/MCRR/MRRC
 
-pub fn mcr(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mcr(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     if likely(emu.arm9.engine_data.regs.is_in_priv_mode() && instr >> 8 & 0xF == 15) {
         let src_reg = (instr >> 12 & 0xF) as u8;
         apply_reg_interlock_1::<false>(emu, src_reg);
@@ -148,7 +152,7 @@ pub fn mcr(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn mrc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mrc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     if likely(emu.arm9.engine_data.regs.is_in_priv_mode() && instr >> 8 & 0xF == 15) {
         prefetch_arm::<true, true>(emu);
         let result = Cp15::read_reg(
@@ -170,22 +174,22 @@ pub fn mrc(emu: &mut Emu<Interpreter>, instr: u32) {
     }
 }
 
-pub fn mcrr(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mcrr(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
 
-pub fn mrrc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn mrrc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
 
-pub fn cdp(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn cdp(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
 
-pub fn ldc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn ldc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
 
-pub fn stc(emu: &mut Emu<Interpreter>, instr: u32) {
+pub fn stc(emu: &mut Emu<impl InterpEngine>, instr: u32) {
     handle_undefined::<false>(emu, instr);
 }
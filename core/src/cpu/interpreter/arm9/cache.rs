@@ -0,0 +1,404 @@
+#[cfg(feature = "debugger-hooks")]
+use super::hit_breakpoint;
+use super::{
+    super::{
+        cache::{self, Block, Region, MAX_BLOCK_LEN, PAGE_MASK},
+        CachedInterpreter, CodeCache,
+    },
+    arm, step, thumb,
+};
+#[cfg(not(feature = "interp-pipeline"))]
+use super::{add_cycles, add_fetch_cycles, can_execute};
+#[cfg(feature = "interp-pipeline")]
+use super::{advance_pipeline, handle_pipeline_entry};
+#[cfg(not(feature = "interp-pipeline"))]
+use crate::utils::schedule::RawTimestamp;
+use crate::{
+    cpu::{
+        arm9::{
+            bus::{self, ptrs::Ptrs},
+            cp15::TcmMode,
+        },
+        bus::{w_disable_flags, DebugCpuAccess},
+        Schedule as _,
+    },
+    emu::Emu,
+};
+#[cfg(not(feature = "interp-pipeline"))]
+use core::intrinsics::unlikely;
+use std::rc::Rc;
+
+type ArmOp = (arm::Handler<CachedInterpreter>, u32);
+// Thumb code is fetched a word at a time, so ops for word-aligned instructions also hold the
+// following one in their upper halfword
+type ThumbOp = (thumb::Handler<CachedInterpreter>, u32);
+
+const MAIN_MEM_START: u32 = 0x0200_0000;
+const MAIN_MEM_END: u32 = 0x02FF_FFFF;
+const ITCM_MASK: u32 = 0x7FFF;
+const ITCM_PAGE_SHIFT: u32 = 12;
+const ITCM_PAGE_MASK: u32 = (1 << ITCM_PAGE_SHIFT) - 1;
+
+#[derive(Clone, Copy)]
+enum RegionKind {
+    MainMem,
+    Itcm,
+}
+
+pub struct Cache {
+    main_mem: Region<ArmOp, ThumbOp>,
+    main_mem_mask: u32,
+    protected_main_mem_pages: [u64; 0x100_0000 / Ptrs::PAGE_SIZE / 64],
+    itcm: Region<ArmOp, ThumbOp>,
+    itcm_upper_bound: u32,
+    protected_itcm_pages: u8,
+}
+
+impl Cache {
+    fn clear_main_mem(&mut self) {
+        self.main_mem.clear();
+        self.protected_main_mem_pages.fill(0);
+    }
+
+    fn clear_itcm(&mut self) {
+        self.itcm.clear();
+        self.protected_itcm_pages = 0;
+    }
+
+    fn region(&mut self, region: RegionKind) -> &mut Region<ArmOp, ThumbOp> {
+        match region {
+            RegionKind::MainMem => &mut self.main_mem,
+            RegionKind::Itcm => &mut self.itcm,
+        }
+    }
+}
+
+impl CodeCache for Cache {
+    fn new() -> Self {
+        Cache {
+            main_mem: Region::new(),
+            main_mem_mask: 0,
+            protected_main_mem_pages: [0; 0x100_0000 / Ptrs::PAGE_SIZE / 64],
+            itcm: Region::new(),
+            itcm_upper_bound: 0,
+            protected_itcm_pages: 0,
+        }
+    }
+
+    #[inline]
+    fn invalidate_word(&mut self, addr: u32) {
+        if addr >> 24 == MAIN_MEM_START >> 24 {
+            self.main_mem.invalidate_word(addr & self.main_mem_mask);
+        }
+        if addr <= self.itcm_upper_bound {
+            self.itcm.invalidate_word(addr & ITCM_MASK);
+        }
+    }
+
+    #[inline]
+    fn invalidate_word_range(&mut self, (lower_bound, upper_bound): (u32, u32)) {
+        if lower_bound <= MAIN_MEM_END && upper_bound >= MAIN_MEM_START {
+            self.clear_main_mem();
+        }
+        if lower_bound <= self.itcm_upper_bound {
+            self.clear_itcm();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.clear_main_mem();
+        self.clear_itcm();
+    }
+}
+
+#[inline]
+fn code_region(emu: &mut Emu<CachedInterpreter>, addr: u32) -> Option<(RegionKind, u32)> {
+    let itcm_upper_bound = emu.arm9.cp15.itcm_upper_bound();
+    if emu.arm9.cp15.itcm_mode() == TcmMode::Normal && addr <= itcm_upper_bound {
+        let cache = &mut emu.arm9.engine_data.cache;
+        if cache.itcm_upper_bound != itcm_upper_bound {
+            // The ITCM's mirrors changed, so the write protection needs to be set up again
+            cache.clear_itcm();
+            cache.itcm_upper_bound = itcm_upper_bound;
+        }
+        Some((RegionKind::Itcm, addr & ITCM_MASK))
+    } else if addr >> 24 == MAIN_MEM_START >> 24 {
+        let main_mem_mask = emu.main_mem_mask().get();
+        let cache = &mut emu.arm9.engine_data.cache;
+        if cache.main_mem_mask != main_mem_mask {
+            cache.clear_main_mem();
+            cache.main_mem_mask = main_mem_mask;
+        }
+        Some((RegionKind::MainMem, addr & main_mem_mask))
+    } else {
+        None
+    }
+}
+
+fn protect_page(emu: &mut Emu<CachedInterpreter>, region: RegionKind, offset: u32) {
+    let cache = &mut emu.arm9.engine_data.cache;
+    match region {
+        RegionKind::MainMem => {
+            // Main memory can also be written by the ARM7 and by DMA, so all of its fast write
+            // pointers need to be disabled
+            let page = (offset >> Ptrs::PAGE_SHIFT) as usize;
+            if cache.protected_main_mem_pages[page >> 6] & 1 << (page & 63) != 0 {
+                return;
+            }
+            cache.protected_main_mem_pages[page >> 6] |= 1 << (page & 63);
+            let mirror_stride = cache.main_mem_mask as usize + 1;
+            let page_start = MAIN_MEM_START | (offset & !Ptrs::PAGE_MASK);
+            for page_addr in (page_start..=MAIN_MEM_END).step_by(mirror_stride) {
+                emu.arm9.disable_write_range(
+                    (page_addr, page_addr | Ptrs::PAGE_MASK),
+                    w_disable_flags::JIT,
+                );
+                emu.arm7
+                    .bus_ptrs
+                    .disable_write(page_addr, w_disable_flags::JIT);
+            }
+        }
+        RegionKind::Itcm => {
+            let page_mask = 1 << (offset >> ITCM_PAGE_SHIFT);
+            if cache.protected_itcm_pages & page_mask != 0 {
+                return;
+            }
+            cache.protected_itcm_pages |= page_mask;
+            let page_start = offset & !ITCM_PAGE_MASK;
+            for page_addr in (page_start..=cache.itcm_upper_bound).step_by(ITCM_MASK as usize + 1) {
+                emu.arm9.disable_write_range(
+                    (page_addr, page_addr | ITCM_PAGE_MASK),
+                    w_disable_flags::JIT,
+                );
+            }
+        }
+    }
+}
+
+fn arm_block(emu: &mut Emu<CachedInterpreter>, addr: u32) -> Option<Rc<Block<ArmOp>>> {
+    let (region, offset) = code_region(emu, addr)?;
+    if let Some(block) = emu.arm9.engine_data.cache.region(region).arm_block(offset) {
+        return Some(block);
+    }
+    let mut ops = Vec::new();
+    let mut cur_addr = addr;
+    loop {
+        let instr = bus::read_32::<DebugCpuAccess, _, true>(emu, cur_addr);
+        ops.push((arm::decode(instr), instr));
+        cur_addr = cur_addr.wrapping_add(4);
+        if cache::arm_instr_ends_block(instr)
+            || cur_addr & PAGE_MASK == 0
+            || ops.len() == MAX_BLOCK_LEN
+        {
+            break;
+        }
+    }
+    protect_page(emu, region, offset);
+    let block = Block::new(
+        offset,
+        offset + (ops.len() << 2) as u32,
+        ops.into_boxed_slice(),
+    );
+    Some(
+        emu.arm9
+            .engine_data
+            .cache
+            .region(region)
+            .insert_arm_block(block),
+    )
+}
+
+fn thumb_block(emu: &mut Emu<CachedInterpreter>, addr: u32) -> Option<Rc<Block<ThumbOp>>> {
+    let (region, offset) = code_region(emu, addr)?;
+    if let Some(block) = emu
+        .arm9
+        .engine_data
+        .cache
+        .region(region)
+        .thumb_block(offset)
+    {
+        return Some(block);
+    }
+    let mut ops = Vec::new();
+    let mut cur_addr = addr;
+    loop {
+        let instrs = if cur_addr & 2 == 0 {
+            bus::read_32::<DebugCpuAccess, _, true>(emu, cur_addr)
+        } else {
+            bus::read_16::<DebugCpuAccess, _>(emu, cur_addr) as u32
+        };
+        ops.push((thumb::decode(instrs as u16), instrs));
+        cur_addr = cur_addr.wrapping_add(2);
+        if cache::thumb_instr_ends_block(instrs as u16)
+            || cur_addr & PAGE_MASK == 0
+            || ops.len() == MAX_BLOCK_LEN
+        {
+            break;
+        }
+    }
+    protect_page(emu, region, offset);
+    let block = Block::new(
+        offset,
+        offset + (ops.len() << 1) as u32,
+        ops.into_boxed_slice(),
+    );
+    Some(
+        emu.arm9
+            .engine_data
+            .cache
+            .region(region)
+            .insert_thumb_block(block),
+    )
+}
+
+/// Returns whether execution can continue with the next instruction in the current block, after
+/// the one at `prev_addr`.
+#[inline]
+fn can_continue_block<Op, const THUMB: bool>(
+    emu: &Emu<CachedInterpreter>,
+    block: &Rc<Block<Op>>,
+    prev_addr: u32,
+) -> bool {
+    cache::is_valid(block)
+        && reg!(emu.arm9, 15) == prev_addr.wrapping_add(if THUMB { 6 } else { 12 })
+        && emu.arm9.engine_data.regs.cpsr.thumb_state() == THUMB
+        && emu.arm9.schedule.cur_time() < emu.arm9.schedule.target_time()
+}
+
+/// Runs the ARM block starting at `addr` until its end, a change in control flow, its
+/// invalidation or the schedule's target time, returning `false` if execution was stopped by a
+/// breakpoint.
+fn run_arm_block(emu: &mut Emu<CachedInterpreter>, block: &Rc<Block<ArmOp>>, addr: u32) -> bool {
+    for (i, &(handler, instr)) in block.ops.iter().enumerate() {
+        let addr = addr.wrapping_add((i << 2) as u32);
+        if i != 0 {
+            if !can_continue_block::<_, false>(emu, block, addr.wrapping_sub(4)) {
+                return true;
+            }
+            #[cfg(feature = "debugger-hooks")]
+            if hit_breakpoint(emu) {
+                return false;
+            }
+        }
+        #[cfg(feature = "interp-pipeline")]
+        {
+            // The pipeline might hold instructions fetched before the block was last decoded, in
+            // which case they take precedence
+            let entry = advance_pipeline::<false>(emu);
+            if cache::is_arm_entry(entry, instr) {
+                emu.arm9.engine_data.data_cycles = 1;
+                arm::handle_decoded_instr(emu, handler, instr);
+            } else {
+                handle_pipeline_entry::<false>(emu, entry);
+            }
+        }
+        #[cfg(not(feature = "interp-pipeline"))]
+        {
+            if unlikely(!can_execute(
+                emu,
+                addr,
+                emu.arm9.engine_data.regs.is_in_priv_mode(),
+            )) {
+                // Let the interpreter raise the prefetch abort
+                step(emu);
+                return true;
+            }
+            add_fetch_cycles(emu, addr);
+            emu.arm9.engine_data.data_cycles = 1;
+            arm::handle_decoded_instr(emu, handler, instr);
+        }
+    }
+    true
+}
+
+/// Runs the Thumb block starting at `addr`; see [`run_arm_block`].
+fn run_thumb_block(
+    emu: &mut Emu<CachedInterpreter>,
+    block: &Rc<Block<ThumbOp>>,
+    addr: u32,
+) -> bool {
+    for (i, &(handler, instrs)) in block.ops.iter().enumerate() {
+        let addr = addr.wrapping_add((i << 1) as u32);
+        if i != 0 {
+            if !can_continue_block::<_, true>(emu, block, addr.wrapping_sub(2)) {
+                return true;
+            }
+            #[cfg(feature = "debugger-hooks")]
+            if hit_breakpoint(emu) {
+                return false;
+            }
+        }
+        #[cfg(feature = "interp-pipeline")]
+        {
+            let entry = advance_pipeline::<true>(emu);
+            if cache::is_thumb_entry(entry, instrs as u16) {
+                emu.arm9.engine_data.data_cycles = 1;
+                handler(emu, instrs as u16);
+            } else {
+                handle_pipeline_entry::<true>(emu, entry);
+            }
+        }
+        #[cfg(not(feature = "interp-pipeline"))]
+        if addr & 2 != 0 {
+            // The second halfword of a word is executed from the previous fetch, which might
+            // have happened before the block was last decoded
+            add_cycles(emu, emu.arm9.engine_data.data_cycles as RawTimestamp);
+            emu.arm9.engine_data.data_cycles = 1;
+            let instr = emu.arm9.engine_data.thumb_next_instr;
+            if instr == instrs as u16 {
+                handler(emu, instr);
+            } else {
+                thumb::handle_instr(emu, instr);
+            }
+        } else {
+            if unlikely(!can_execute(
+                emu,
+                addr,
+                emu.arm9.engine_data.regs.is_in_priv_mode(),
+            )) {
+                step(emu);
+                return true;
+            }
+            add_fetch_cycles(emu, addr);
+            emu.arm9.engine_data.thumb_next_instr = (instrs >> 16) as u16;
+            emu.arm9.engine_data.data_cycles = 1;
+            handler(emu, instrs as u16);
+        }
+    }
+    true
+}
+
+/// Executes instructions until the schedule's target time is reached, running cached blocks for
+/// code in main memory and ITCM and falling back to the interpreter's fetch and decode for
+/// everything else; returns `false` if execution was stopped by a breakpoint.
+pub(in super::super) fn run_instrs(emu: &mut Emu<CachedInterpreter>) -> bool {
+    while emu.arm9.schedule.cur_time() < emu.arm9.schedule.target_time() {
+        #[cfg(feature = "debugger-hooks")]
+        if hit_breakpoint(emu) {
+            return false;
+        }
+        let not_stopped = if emu.arm9.engine_data.regs.cpsr.thumb_state() {
+            let addr = reg!(emu.arm9, 15).wrapping_sub(4);
+            match thumb_block(emu, addr) {
+                Some(block) => run_thumb_block(emu, &block, addr),
+                None => {
+                    step(emu);
+                    true
+                }
+            }
+        } else {
+            let addr = reg!(emu.arm9, 15).wrapping_sub(8);
+            match arm_block(emu, addr) {
+                Some(block) => run_arm_block(emu, &block, addr),
+                None => {
+                    step(emu);
+                    true
+                }
+            }
+        };
+        if !not_stopped {
+            return false;
+        }
+    }
+    true
+}
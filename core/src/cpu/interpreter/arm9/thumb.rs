@@ -9,14 +9,26 @@ use other::*;
 
 use super::super::{
     common::{DpOpImm8Ty, DpOpRegTy, ShiftImmTy},
-    Interpreter,
+    InterpEngine,
 };
 use crate::emu::Emu;
+use core::marker::PhantomData;
 
-static INSTR_TABLE: [fn(&mut Emu<Interpreter>, u16); 0x400] =
-    include!(concat!(env!("OUT_DIR"), "/interp_arm9_thumb.rs"));
+pub type Handler<E> = fn(&mut Emu<E>, u16);
+
+struct Tables<E>(PhantomData<E>);
+
+impl<E: InterpEngine> Tables<E> {
+    const INSTR_TABLE: &'static [Handler<E>; 0x400] =
+        &include!(concat!(env!("OUT_DIR"), "/interp_arm9_thumb.rs"));
+}
+
+#[inline]
+pub fn decode<E: InterpEngine>(instr: u16) -> Handler<E> {
+    Tables::<E>::INSTR_TABLE[(instr >> 6) as usize]
+}
 
 #[inline]
-pub fn handle_instr(emu: &mut Emu<Interpreter>, instr: u16) {
-    INSTR_TABLE[(instr >> 6) as usize](emu, instr);
+pub fn handle_instr<E: InterpEngine>(emu: &mut Emu<E>, instr: u16) {
+    decode::<E>(instr)(emu, instr);
 }
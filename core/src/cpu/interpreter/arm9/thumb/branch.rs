@@ -2,12 +2,12 @@ use super::super::{
     add_bus_cycles, apply_reg_interlock_1, handle_undefined, prefetch_thumb, reload_pipeline,
 };
 use crate::{
-    cpu::interpreter::{common::StateSource, Interpreter},
+    cpu::interpreter::{common::StateSource, InterpEngine},
     emu::Emu,
 };
 use core::intrinsics::unlikely;
 
-pub fn b(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn b(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     let branch_addr = reg!(emu.arm9, 15).wrapping_add(((instr as i32) << 21 >> 20) as u32);
     add_bus_cycles(emu, 2);
     prefetch_thumb::<true, false>(emu);
@@ -15,7 +15,7 @@ pub fn b(emu: &mut Emu<Interpreter>, instr: u16) {
     reload_pipeline::<{ StateSource::Thumb }>(emu);
 }
 
-pub fn b_cond<const COND: u8>(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn b_cond<const COND: u8>(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     prefetch_thumb::<true, false>(emu);
     if !emu.arm9.engine_data.regs.cpsr.satisfies_condition(COND) {
         inc_r15!(emu.arm9, 2);
@@ -26,7 +26,7 @@ pub fn b_cond<const COND: u8>(emu: &mut Emu<Interpreter>, instr: u16) {
     reload_pipeline::<{ StateSource::Thumb }>(emu);
 }
 
-pub fn bx<const LINK: bool>(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn bx<const LINK: bool>(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     let addr_reg = (instr >> 3 & 0xF) as u8;
     apply_reg_interlock_1::<false>(emu, addr_reg);
     add_bus_cycles(emu, 2);
@@ -39,13 +39,13 @@ pub fn bx<const LINK: bool>(emu: &mut Emu<Interpreter>, instr: u16) {
     reload_pipeline::<{ StateSource::R15Bit0 }>(emu);
 }
 
-pub fn bl_prefix(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn bl_prefix(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     reg!(emu.arm9, 14) = reg!(emu.arm9, 15).wrapping_add(((instr as i32) << 21 >> 9) as u32);
     add_bus_cycles(emu, 1);
     prefetch_thumb::<true, true>(emu);
 }
 
-pub fn bl_suffix<const EXCHANGE: bool>(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn bl_suffix<const EXCHANGE: bool>(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     if unlikely(EXCHANGE && instr & 1 != 0) {
         return handle_undefined::<true>(emu, instr as u32);
     }
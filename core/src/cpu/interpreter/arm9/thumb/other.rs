@@ -1,14 +1,14 @@
-use super::super::{super::Interpreter, handle_prefetch_abort, handle_swi, handle_undefined};
+use super::super::{super::InterpEngine, handle_prefetch_abort, handle_swi, handle_undefined};
 use crate::emu::Emu;
 
-pub fn bkpt(emu: &mut Emu<Interpreter>, _instr: u16) {
+pub fn bkpt(emu: &mut Emu<impl InterpEngine>, _instr: u16) {
     handle_prefetch_abort::<true>(emu);
 }
 
-pub fn swi(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn swi(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     handle_swi::<true>(emu, instr as u8);
 }
 
-pub fn undefined(emu: &mut Emu<Interpreter>, instr: u16) {
+pub fn undefined(emu: &mut Emu<impl InterpEngine>, instr: u16) {
     handle_undefined::<true>(emu, instr as u32);
 }
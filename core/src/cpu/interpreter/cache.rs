@@ -0,0 +1,207 @@
+//! Block caches used by [`CachedInterpreter`](super::CachedInterpreter).
+//!
+//! Blocks hold runs of consecutive instructions pre-decoded with the handler tables generated by
+//! `build.rs`, and are indexed by their offset inside the memory region they were decoded from, so
+//! that all mirrors of the same code share them. The bus pages containing cached code get their
+//! fast write pointers disabled through the `bft-w` mechanism, so that all writes to them go
+//! through `CoreData::invalidate_word` and remove the blocks overlapping the written word.
+//!
+//! A block is only valid as long as its region still holds it; as blocks are reference-counted,
+//! executors keep a reference to the block they're running and stop as soon as they find it's the
+//! only one left (i.e. the block was invalidated by the last instruction).
+
+#[cfg(feature = "interp-pipeline")]
+use super::common::{thumb_pipeline_entry, PipelineEntry};
+use std::rc::Rc;
+
+/// The size of the pages blocks are grouped into; blocks never cross page boundaries, so that
+/// invalidating a word only needs to look at the blocks in its page.
+pub const PAGE_SHIFT: u32 = 10;
+pub const PAGE_SIZE: u32 = 1 << PAGE_SHIFT;
+pub const PAGE_MASK: u32 = PAGE_SIZE - 1;
+
+pub const MAX_BLOCK_LEN: usize = 64;
+
+pub struct Block<Op> {
+    start: u32,
+    end: u32,
+    pub ops: Box<[Op]>,
+}
+
+impl<Op> Block<Op> {
+    /// Creates a block of instructions covering the region offsets from `start` to `end`
+    /// (exclusive).
+    pub fn new(start: u32, end: u32, ops: Box<[Op]>) -> Self {
+        debug_assert!(start < end && (end - 1) >> PAGE_SHIFT == start >> PAGE_SHIFT);
+        Block { start, end, ops }
+    }
+
+    #[inline]
+    fn overlaps_word(&self, word_offset: u32) -> bool {
+        self.start < word_offset + 4 && self.end > word_offset
+    }
+
+    fn mark_code_words(&self, code_words: &mut [u64; CODE_WORDS_LEN]) {
+        for word in (self.start & PAGE_MASK) >> 2..=((self.end - 1) & PAGE_MASK) >> 2 {
+            code_words[word as usize >> 6] |= 1 << (word & 63);
+        }
+    }
+}
+
+/// Returns whether the block `block` is running from is still valid.
+#[inline]
+pub fn is_valid<Op>(block: &Rc<Block<Op>>) -> bool {
+    Rc::strong_count(block) > 1
+}
+
+const CODE_WORDS_LEN: usize = PAGE_SIZE as usize / 4 / 64;
+
+struct Page<A, T> {
+    arm: [Option<Rc<Block<A>>>; PAGE_SIZE as usize / 4],
+    thumb: [Option<Rc<Block<T>>>; PAGE_SIZE as usize / 2],
+    code_words: [u64; CODE_WORDS_LEN],
+}
+
+/// The blocks decoded from a single memory region, containing ARM blocks with ops of type `A` and
+/// Thumb blocks with ops of type `T`.
+pub struct Region<A, T> {
+    pages: Vec<Option<Box<Page<A, T>>>>,
+}
+
+impl<A, T> Region<A, T> {
+    pub const fn new() -> Self {
+        Region { pages: Vec::new() }
+    }
+
+    #[inline]
+    fn page(&self, offset: u32) -> Option<&Page<A, T>> {
+        self.pages.get((offset >> PAGE_SHIFT) as usize)?.as_deref()
+    }
+
+    fn page_mut_or_insert(&mut self, offset: u32) -> &mut Page<A, T> {
+        let page_index = (offset >> PAGE_SHIFT) as usize;
+        if page_index >= self.pages.len() {
+            self.pages.resize_with(page_index + 1, || None);
+        }
+        self.pages[page_index].get_or_insert_with(|| {
+            Box::new(Page {
+                arm: core::array::from_fn(|_| None),
+                thumb: core::array::from_fn(|_| None),
+                code_words: [0; CODE_WORDS_LEN],
+            })
+        })
+    }
+
+    #[inline]
+    pub fn arm_block(&self, offset: u32) -> Option<Rc<Block<A>>> {
+        self.page(offset)?.arm[(offset & PAGE_MASK) as usize >> 2].clone()
+    }
+
+    #[inline]
+    pub fn thumb_block(&self, offset: u32) -> Option<Rc<Block<T>>> {
+        self.page(offset)?.thumb[(offset & PAGE_MASK) as usize >> 1].clone()
+    }
+
+    pub fn insert_arm_block(&mut self, block: Block<A>) -> Rc<Block<A>> {
+        let page = self.page_mut_or_insert(block.start);
+        block.mark_code_words(&mut page.code_words);
+        let block = Rc::new(block);
+        page.arm[(block.start & PAGE_MASK) as usize >> 2] = Some(Rc::clone(&block));
+        block
+    }
+
+    pub fn insert_thumb_block(&mut self, block: Block<T>) -> Rc<Block<T>> {
+        let page = self.page_mut_or_insert(block.start);
+        block.mark_code_words(&mut page.code_words);
+        let block = Rc::new(block);
+        page.thumb[(block.start & PAGE_MASK) as usize >> 1] = Some(Rc::clone(&block));
+        block
+    }
+
+    /// Removes all blocks overlapping the word containing the specified offset.
+    #[inline]
+    pub fn invalidate_word(&mut self, offset: u32) {
+        let Some(Some(page)) = self.pages.get_mut((offset >> PAGE_SHIFT) as usize) else {
+            return;
+        };
+        let word = (offset & PAGE_MASK) >> 2;
+        if page.code_words[word as usize >> 6] & 1 << (word & 63) == 0 {
+            return;
+        }
+        let word_offset = offset & !3;
+        let page = &mut **page;
+        page.code_words = [0; CODE_WORDS_LEN];
+        for entry in &mut page.arm {
+            if let Some(block) = entry {
+                if block.overlaps_word(word_offset) {
+                    *entry = None;
+                } else {
+                    block.mark_code_words(&mut page.code_words);
+                }
+            }
+        }
+        for entry in &mut page.thumb {
+            if let Some(block) = entry {
+                if block.overlaps_word(word_offset) {
+                    *entry = None;
+                } else {
+                    block.mark_code_words(&mut page.code_words);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
+
+/// Returns whether the ARM instruction `instr` should end a block, as it (usually) changes the
+/// control flow or the CPU mode; any other change of r15 or of the CPU state is still detected
+/// after running each instruction.
+pub fn arm_instr_ends_block(instr: u32) -> bool {
+    instr >> 28 == 0xF
+        || match instr >> 25 & 7 {
+            // Data processing, PSR transfers, BX/BLX and halfword/doubleword transfers writing r15
+            0 | 1 => instr >> 12 & 0xF == 0xF || instr & 0x0FFF_FFD0 == 0x012F_FF10,
+            // Single data transfers loading r15 and undefined instructions
+            2 | 3 => {
+                (instr & 1 << 20 != 0 && instr >> 12 & 0xF == 0xF)
+                    || instr & 0x0E00_0010 == 0x0600_0010
+            }
+            // Block transfers loading r15
+            4 => instr & 1 << 20 != 0 && instr & 1 << 15 != 0,
+            // B/BL, coprocessor instructions and SWI
+            _ => true,
+        }
+}
+
+/// Returns whether the Thumb instruction `instr` should end a block; see
+/// [`arm_instr_ends_block`].
+pub fn thumb_instr_ends_block(instr: u16) -> bool {
+    // Hi register operations writing r15 (including CMP, for simplicity) and BX/BLX
+    instr & 0xFC87 == 0x4487
+        || instr & 0xFF00 == 0x4700
+        // POP {.., pc}
+        || instr & 0xFF00 == 0xBD00
+        // BKPT
+        || instr & 0xFF00 == 0xBE00
+        // Conditional branches, undefined instructions and SWI
+        || instr >> 12 == 0xD
+        // B and the second halves of BL/BLX
+        || matches!(instr >> 11, 0x1C | 0x1D | 0x1F)
+}
+
+/// Returns whether the pipeline entry about to be executed is the ARM instruction `instr`.
+#[cfg(feature = "interp-pipeline")]
+#[inline]
+pub fn is_arm_entry(entry: PipelineEntry, instr: u32) -> bool {
+    entry == instr as PipelineEntry
+}
+
+/// Returns whether the pipeline entry about to be executed is the Thumb instruction `instr`.
+#[cfg(feature = "interp-pipeline")]
+#[inline]
+pub fn is_thumb_entry(entry: PipelineEntry, instr: u16) -> bool {
+    entry as u16 == instr && entry & thumb_pipeline_entry(0) == thumb_pipeline_entry(0)
+}
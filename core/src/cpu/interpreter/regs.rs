@@ -90,6 +90,14 @@ impl Regs {
         self.spsr_und = regs.spsr_und;
     }
 
+    /// Replaces the CPSR without switching register banks, for when the banked registers already
+    /// correspond to its mode.
+    pub(super) fn restore_cpsr(&mut self, value: Psr) {
+        self.cpsr = value;
+        self.is_in_priv_mode = value.mode().is_privileged();
+        self.has_spsr = value.mode().has_spsr();
+    }
+
     #[inline]
     pub const fn cpsr(&self) -> Psr {
         self.cpsr
@@ -42,6 +42,7 @@ pub use bounded::Index;
 #[load(in_place_only)]
 pub struct Timer {
     control: Control,
+    base_cycle_shift: u8,
     cycle_shift: u8,
     count_up: bool,
     schedule_overflows: bool,
@@ -55,6 +56,7 @@ impl Timer {
     const fn new() -> Self {
         Timer {
             control: Control(0),
+            base_cycle_shift: 0,
             cycle_shift: 0,
             count_up: false,
             schedule_overflows: false,
@@ -108,6 +110,16 @@ impl Timers {
         Timers([Timer::new(); 4])
     }
 
+    /// Sets the number of system clock cycles each timer tick takes (before applying the prescaler)
+    /// to `1 << value`; this is used to run the timers at the GBA's clock rate in GBA mode. Should
+    /// only be called while all timers are stopped.
+    pub(crate) fn set_base_cycle_shift(&mut self, value: u8) {
+        for timer in &mut self.0 {
+            timer.base_cycle_shift = value;
+            timer.cycle_shift = value + [0, 6, 8, 10][timer.control.prescaler() as usize];
+        }
+    }
+
     pub(crate) fn handle_scheduled_overflow<S: Schedule>(
         &mut self,
         i: Index,
@@ -220,7 +232,8 @@ impl Timers {
         let timer = &mut self.0[i.get() as usize];
         let prev_value = timer.control;
         timer.control = value;
-        timer.cycle_shift = [0, 6, 8, 10][timer.control.prescaler() as usize];
+        timer.cycle_shift =
+            timer.base_cycle_shift + [0, 6, 8, 10][timer.control.prescaler() as usize];
         if value.running() {
             if !prev_value.running() {
                 timer.counter = timer.reload;
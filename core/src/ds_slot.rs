@@ -80,6 +80,8 @@ pub use bounded::{RomOutputLen, RomOutputPos};
 #[load(in_place_only)]
 pub struct DsSlot {
     pub rom: rom::Rom,
+    #[load(with_in_place = "spi.load_with_variant(save)?")]
+    #[store(with = "spi.store_with_variant(save)?")]
     pub spi: spi::Spi,
     spi_control: AuxSpiControl,
     rom_control: RomControl,
@@ -98,11 +100,15 @@ pub struct DsSlot {
 
 impl DsSlot {
     pub(crate) fn new(
-        rom: rom::Rom,
-        spi: spi::Spi,
+        mut rom: rom::Rom,
+        mut spi: spi::Spi,
         arm7_schedule: &mut arm7::Schedule,
         arm9_schedule: &mut arm9::Schedule,
     ) -> Self {
+        if let (rom::Rom::Normal(rom), spi::Spi::Nand(nand)) = (&mut rom, &mut spi) {
+            rom.set_has_nand_save();
+            nand.set_rw_start(rom.nand_rw_start());
+        }
         arm7_schedule.set_event(
             arm7::event_slots::DS_SLOT_ROM,
             arm7::Event::DsSlotRomDataReady,
@@ -183,6 +189,7 @@ impl DsSlot {
             self.rom_cmd.clone(),
             &mut self.rom_output_buffer,
             self.rom_output_len,
+            &mut self.spi,
         );
         // The command itself takes 8 CLK pulses to transfer, while every data byte takes 4 pulses
         // (the DS game card slot can only transfer 8 bits on every CLK cycle)
@@ -330,6 +337,79 @@ impl DsSlot {
         self.rom_data_out
     }
 
+    pub(crate) fn write_rom_data_arm7(
+        &mut self,
+        value: u32,
+        irqs: &mut arm7::Irqs,
+        schedule: &mut arm7::Schedule,
+    ) {
+        if !self.rom_control.data_ready() || !self.rom_control.write_enabled() {
+            return;
+        }
+        self.rom_control.set_data_ready(false);
+        self.rom_output_buffer
+            .write_le(self.rom_output_pos.get() as usize, value);
+        let new_rom_output_pos = self.rom_output_pos.get() + 4;
+        if new_rom_output_pos < self.rom_output_len.get() {
+            self.rom_output_pos = RomOutputPos::new(new_rom_output_pos);
+            let target = schedule.cur_time()
+                + arm7::Timestamp::from(Timestamp(
+                    (4 * self.rom_clk_pulse_duration) as RawTimestamp,
+                ));
+            schedule.schedule_event(arm7::event_slots::DS_SLOT_ROM, target);
+        } else {
+            self.finish_rom_write();
+            if self.spi_control.rom_transfer_complete_irq_enabled() {
+                irqs.write_requested(
+                    irqs.requested().with_ds_slot_transfer_complete(true),
+                    schedule,
+                );
+            }
+        }
+    }
+
+    pub(crate) fn write_rom_data_arm9(
+        &mut self,
+        value: u32,
+        irqs: &mut arm9::Irqs,
+        schedule: &mut arm9::Schedule,
+    ) {
+        if !self.rom_control.data_ready() || !self.rom_control.write_enabled() {
+            return;
+        }
+        self.rom_control.set_data_ready(false);
+        self.rom_output_buffer
+            .write_le(self.rom_output_pos.get() as usize, value);
+        let new_rom_output_pos = self.rom_output_pos.get() + 4;
+        if new_rom_output_pos < self.rom_output_len.get() {
+            self.rom_output_pos = RomOutputPos::new(new_rom_output_pos);
+            let target = schedule.cur_time()
+                + arm9::Timestamp::from(Timestamp(
+                    (4 * self.rom_clk_pulse_duration) as RawTimestamp,
+                ));
+            schedule.schedule_event(arm9::event_slots::DS_SLOT_ROM, target);
+        } else {
+            self.finish_rom_write();
+            if self.spi_control.rom_transfer_complete_irq_enabled() {
+                irqs.write_requested(
+                    irqs.requested().with_ds_slot_transfer_complete(true),
+                    schedule,
+                );
+            }
+        }
+    }
+
+    /// Ends a ROM transfer in write mode, passing the data written by the DS (stored in the
+    /// output buffer) to the cartridge.
+    fn finish_rom_write(&mut self) {
+        self.rom_control.set_busy(false);
+        self.rom.handle_rom_write(
+            self.rom_cmd.clone(),
+            &self.rom_output_buffer[..self.rom_output_len.get() as usize],
+            &mut self.spi,
+        );
+    }
+
     #[inline]
     pub fn spi_data_out(&self) -> u8 {
         // TODO: What's the response while busy?
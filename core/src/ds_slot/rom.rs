@@ -5,7 +5,7 @@ pub mod header;
 pub mod icon_title;
 pub mod normal;
 
-use super::RomOutputLen;
+use super::{spi::Spi, RomOutputLen};
 use crate::{
     utils::{mem_prelude::*, Savestate},
     Model,
@@ -87,7 +87,9 @@ trait RomDevice {
         cmd: Bytes<8>,
         output: &mut Bytes<0x4000>,
         output_len: RomOutputLen,
+        spi: &mut Spi,
     );
+    fn handle_rom_write(&mut self, cmd: Bytes<8>, input: &[u8], spi: &mut Spi);
 }
 
 #[derive(Savestate)]
@@ -107,8 +109,19 @@ impl Rom {
         cmd: Bytes<8>,
         output: &mut Bytes<0x4000>,
         output_len: RomOutputLen,
+        spi: &mut Spi,
     ) {
-        forward_to_variants!(Rom; Normal, Empty; self, handle_rom_command(cmd, output, output_len));
+        forward_to_variants!(
+            Rom;
+            Normal, Empty;
+            self, handle_rom_command(cmd, output, output_len, spi)
+        );
+    }
+
+    /// Handles the data sent to the cartridge by a command in write mode, once the transfer is
+    /// complete.
+    pub fn handle_rom_write(&mut self, cmd: Bytes<8>, input: &[u8], spi: &mut Spi) {
+        forward_to_variants!(Rom; Normal, Empty; self, handle_rom_write(cmd, input, spi));
     }
 
     pub fn into_contents(self) -> Option<Box<dyn Contents>> {
@@ -1,4 +1,4 @@
-use super::super::RomOutputLen;
+use super::super::{spi::Spi, RomOutputLen};
 #[cfg(feature = "log")]
 use crate::utils::mem_prelude::*;
 #[allow(unused_imports)]
@@ -39,6 +39,7 @@ impl super::RomDevice for Empty {
         _cmd: Bytes<8>,
         output: &mut Bytes<0x4000>,
         output_len: RomOutputLen,
+        _spi: &mut Spi,
     ) {
         #[cfg(feature = "log")]
         slog::trace!(self.logger, "{:016X}", _cmd.read_be::<u64>(0));
@@ -46,4 +47,10 @@ impl super::RomDevice for Empty {
         // the data bus or does it get filled with 0xFF? GBATEK seems to imply the latter.
         output[..output_len.get() as usize].fill(0xFF);
     }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn handle_rom_write(&mut self, _cmd: Bytes<8>, _input: &[u8], _spi: &mut Spi) {
+        #[cfg(feature = "log")]
+        slog::trace!(self.logger, "{:016X} (write)", _cmd.read_be::<u64>(0));
+    }
 }
@@ -214,8 +214,88 @@ impl<'a> Header<'a> {
         self.0.read_le::<u16>(0x98)
     }
 
+    #[inline]
+    pub fn logo_crc(&self) -> u16 {
+        self.0.read_le::<u16>(0x15C)
+    }
+
     #[inline]
     pub fn header_crc(&self) -> u16 {
         self.0.read_le::<u16>(0x15E)
     }
 }
+
+/// The DSi-specific extension of the cartridge header, present in titles with a DSi-capable unit
+/// code.
+#[derive(Clone, Copy)]
+pub struct DsiHeader<'a>(&'a [u8]);
+
+impl<'a> DsiHeader<'a> {
+    #[inline]
+    pub fn new(bytes: &'a [u8; 0x1000]) -> Self {
+        DsiHeader(bytes)
+    }
+
+    #[inline]
+    pub fn header(&self) -> Header<'a> {
+        Header(&self.0[..0x170])
+    }
+
+    #[inline]
+    pub fn mbk_slot_control(&self) -> &'a [u8] {
+        &self.0[0x180..0x194]
+    }
+
+    #[inline]
+    pub fn arm9_mbk_windows(&self) -> [u32; 3] {
+        [
+            self.0.read_le::<u32>(0x194),
+            self.0.read_le::<u32>(0x198),
+            self.0.read_le::<u32>(0x19C),
+        ]
+    }
+
+    #[inline]
+    pub fn arm7_mbk_windows(&self) -> [u32; 3] {
+        [
+            self.0.read_le::<u32>(0x1A0),
+            self.0.read_le::<u32>(0x1A4),
+            self.0.read_le::<u32>(0x1A8),
+        ]
+    }
+
+    #[inline]
+    pub fn mbk_write_protect(&self) -> u32 {
+        self.0.read_le::<u32>(0x1AC) & 0x00FF_FFFF
+    }
+
+    #[inline]
+    pub fn arm9i_rom_offset(&self) -> u32 {
+        self.0.read_le::<u32>(0x1C0)
+    }
+
+    #[inline]
+    pub fn arm9i_ram_addr(&self) -> u32 {
+        self.0.read_le::<u32>(0x1C8)
+    }
+
+    #[inline]
+    pub fn arm9i_size(&self) -> u32 {
+        self.0.read_le::<u32>(0x1CC)
+    }
+
+    #[inline]
+    pub fn arm7i_rom_offset(&self) -> u32 {
+        self.0.read_le::<u32>(0x1D0)
+    }
+
+    #[inline]
+    pub fn arm7i_ram_addr(&self) -> u32 {
+        self.0.read_le::<u32>(0x1D8)
+    }
+
+    #[inline]
+    pub fn arm7i_size(&self) -> u32 {
+        self.0.read_le::<u32>(0x1DC)
+    }
+}
@@ -1,6 +1,14 @@
-use super::{super::RomOutputLen, is_valid_size, key1, Contents};
+use super::{
+    super::{
+        spi::{nand, Spi},
+        RomOutputLen,
+    },
+    header::Header,
+    is_valid_size, key1, Contents,
+};
 use crate::{
     cpu::arm7,
+    spi::firmware::crc16,
     utils::{make_zero, mem_prelude::*, zero, Savestate},
     Model,
 };
@@ -10,6 +18,20 @@ pub enum CreationError {
     InvalidSize,
 }
 
+/// The results of the checks the firmware performs on the cartridge before booting it, which are
+/// reported in main RAM when booting directly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BootChecks {
+    /// Whether the logo or header CRC16 values don't match the header's contents.
+    pub header_bad: bool,
+    /// Whether the secure area's CRC16 value or decrypted ID are incorrect.
+    pub secure_area_bad: bool,
+    /// Whether the header's secure area disable field decrypts to `NmMdOnly`.
+    pub secure_area_disabled: bool,
+}
+
+const DECRYPTED_SECURE_AREA_ID: u64 = 0xE7FF_DEFF_E7FF_DEFF;
+
 #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
 enum Stage {
     Initial,
@@ -32,6 +54,8 @@ pub struct Normal {
     chip_id: u32,
     #[savestate(skip)]
     key_buf: Option<Box<key1::KeyBuffer<false>>>, // Always at level 2
+    #[savestate(skip)]
+    boot_checks: BootChecks,
     stage: Stage,
 }
 
@@ -64,6 +88,7 @@ impl Normal {
             rom_mask,
             chip_id,
             key_buf: arm7_bios.map(|bios| key1::KeyBuffer::new_boxed::<2>(game_code, bios)),
+            boot_checks: BootChecks::default(),
             stage: Stage::Initial,
         })
     }
@@ -72,6 +97,30 @@ impl Normal {
         self.chip_id
     }
 
+    /// Marks the cartridge as having a NAND save chip in its chip ID, which also implies the newer
+    /// protocol used by large cartridges (bit 31).
+    pub(in super::super) fn set_has_nand_save(&mut self) {
+        self.chip_id |= 0x8800_0000;
+    }
+
+    /// Returns the ROM address the writable area of the NAND chip starts at, as specified in the
+    /// header.
+    pub fn nand_rw_start(&self) -> u32 {
+        let mut buf = zero();
+        self.contents.read_header(&mut buf);
+        let header = Header::new(&buf);
+        let start_blocks = match header.nand_raw_rw_start() {
+            0 => header.nand_raw_rom_end(),
+            start => start,
+        };
+        start_blocks as u32 * nand::BLOCK_LEN
+    }
+
+    /// Returns the results of the firmware's cartridge checks, only valid after a direct boot.
+    pub fn boot_checks(&self) -> BootChecks {
+        self.boot_checks
+    }
+
     pub fn into_contents(self) -> Box<dyn Contents> {
         self.contents
     }
@@ -93,6 +142,98 @@ impl Normal {
     }
 }
 
+fn decrypt_secure_area(key_buf: &key1::KeyBuffer<false>, secure_area: &mut [u8]) {
+    let res = key_buf.decrypt_64_bit([secure_area.read_le(0), secure_area.read_le(4)]);
+    secure_area.write_le(0, res[0]);
+    secure_area.write_le(4, res[1]);
+
+    let level_3_key_buf = key_buf.level_3::<2>();
+    for i in (0..0x800).step_by(8) {
+        let res =
+            level_3_key_buf.decrypt_64_bit([secure_area.read_le(i), secure_area.read_le(i + 4)]);
+        secure_area.write_le(i, res[0]);
+        secure_area.write_le(i + 4, res[1]);
+    }
+}
+
+fn encrypt_secure_area(key_buf: &key1::KeyBuffer<false>, secure_area: &mut [u8]) {
+    secure_area[..8].copy_from_slice(b"encryObj");
+    let level_3_key_buf = key_buf.level_3::<2>();
+    for i in (0..0x800).step_by(8) {
+        let res =
+            level_3_key_buf.encrypt_64_bit([secure_area.read_le(i), secure_area.read_le(i + 4)]);
+        secure_area.write_le(i, res[0]);
+        secure_area.write_le(i + 4, res[1]);
+    }
+    let res = key_buf.encrypt_64_bit([secure_area.read_le(0), secure_area.read_le(4)]);
+    secure_area.write_le(0, res[0]);
+    secure_area.write_le(4, res[1]);
+}
+
+fn is_header_valid(header_bytes: &Bytes<0x170>) -> bool {
+    let header = Header::new(header_bytes);
+    // The logo's CRC16 is also compared against the known value for the Nintendo logo
+    header.logo_crc() == 0xCF56
+        && crc16(0xFFFF, &header_bytes[0xC0..0x15C]) == header.logo_crc()
+        && crc16(0xFFFF, &header_bytes[..0x15E]) == header.header_crc()
+}
+
+impl Normal {
+    fn is_secure_area_disabled(&self, header: &Bytes<0x170>) -> bool {
+        let Some(key_buf) = self.key_buf.as_ref() else {
+            return false;
+        };
+        let res = key_buf.decrypt_64_bit([header.read_le(0x78), header.read_le(0x7C)]);
+        (res[0] as u64 | (res[1] as u64) << 32) == u64::from_le_bytes(*b"NmMdOnly")
+    }
+
+    /// Decrypts the secure area like the firmware does, returning whether it passed the CRC and
+    /// ID checks.
+    fn load_secure_area(&mut self, secure_area_start: u32, expected_crc: u16) -> Result<bool, ()> {
+        let mut rest = vec![0; 0x3800];
+        self.contents
+            .read_slice_wrapping(secure_area_start + 0x800, &mut rest);
+        let Some(secure_area) = self.contents.secure_area_mut() else {
+            return Ok(true);
+        };
+
+        let is_decrypted = secure_area.read_le::<u64>(0) == DECRYPTED_SECURE_AREA_ID;
+        let Some(key_buf) = self.key_buf.as_ref() else {
+            // Without the key table, the secure area can only be used as-is if it's been
+            // decrypted beforehand, in which case its CRC can't be checked
+            return if is_decrypted { Ok(true) } else { Err(()) };
+        };
+
+        // The secure area CRC is calculated on the encrypted data the cartridge would send
+        let mut encrypted = secure_area.to_vec();
+        if is_decrypted {
+            encrypt_secure_area(key_buf, &mut encrypted);
+        }
+        let crc = crc16(crc16(0xFFFF, &encrypted), &rest);
+
+        let mut is_valid = crc == expected_crc;
+        if !is_decrypted {
+            decrypt_secure_area(key_buf, secure_area);
+            if &secure_area[..8] == b"encryObj" {
+                secure_area.write_le(0, DECRYPTED_SECURE_AREA_ID);
+            } else {
+                // The firmware overwrites secure areas with invalid IDs with undefined
+                // instructions
+                for i in (0..0x800).step_by(4) {
+                    secure_area.write_le(i, 0xE7FF_DEFF_u32);
+                }
+                is_valid = false;
+            }
+        }
+
+        #[cfg(feature = "log")]
+        if !is_valid {
+            slog::warn!(self.logger, "Invalid secure area CRC or ID");
+        }
+        Ok(is_valid)
+    }
+}
+
 impl super::RomDevice for Normal {
     fn setup(&mut self, direct_boot: bool) -> Result<(), ()> {
         let mut buf = zero();
@@ -102,29 +243,17 @@ impl super::RomDevice for Normal {
 
         if direct_boot {
             self.stage = Stage::Key2;
+            self.boot_checks.header_bad = !is_header_valid(&buf);
+            #[cfg(feature = "log")]
+            if self.boot_checks.header_bad {
+                slog::warn!(self.logger, "Invalid cartridge logo or header CRC");
+            }
             if is_homebrew {
                 return Ok(());
             }
-            let Some(secure_area) = self.contents.secure_area_mut() else {
-                return Ok(());
-            };
-            if secure_area.read_le::<u64>(0) != 0xE7FF_DEFF_E7FF_DEFF {
-                let Some(key_buf) = self.key_buf.as_ref() else {
-                    return Err(());
-                };
-
-                let res = key_buf.decrypt_64_bit([secure_area.read_le(0), secure_area.read_le(4)]);
-                secure_area.write_le(0, res[0]);
-                secure_area.write_le(4, res[1]);
-
-                let level_3_key_buf = key_buf.level_3::<2>();
-                for i in (0..0x800).step_by(8) {
-                    let res = level_3_key_buf
-                        .decrypt_64_bit([secure_area.read_le(i), secure_area.read_le(i + 4)]);
-                    secure_area.write_le(i, res[0]);
-                    secure_area.write_le(i + 4, res[1]);
-                }
-            }
+            self.boot_checks.secure_area_disabled = self.is_secure_area_disabled(&buf);
+            self.boot_checks.secure_area_bad =
+                !self.load_secure_area(secure_area_start, Header::new(&buf).secure_area_crc())?;
         } else {
             let Some(secure_area) = self.contents.secure_area_mut() else {
                 return Ok(());
@@ -133,18 +262,8 @@ impl super::RomDevice for Normal {
                 .key_buf
                 .as_ref()
                 .expect("key_buf should be initialized");
-            if secure_area.read_le::<u64>(0) == 0xE7FF_DEFF_E7FF_DEFF {
-                secure_area[..8].copy_from_slice(b"encryObj");
-                let level_3_key_buf = key_buf.level_3::<2>();
-                for i in (0..0x800).step_by(8) {
-                    let res = level_3_key_buf
-                        .encrypt_64_bit([secure_area.read_le(i), secure_area.read_le(i + 4)]);
-                    secure_area.write_le(i, res[0]);
-                    secure_area.write_le(i + 4, res[1]);
-                }
-                let res = key_buf.encrypt_64_bit([secure_area.read_le(0), secure_area.read_le(4)]);
-                secure_area.write_le(0, res[0]);
-                secure_area.write_le(4, res[1]);
+            if secure_area.read_le::<u64>(0) == DECRYPTED_SECURE_AREA_ID {
+                encrypt_secure_area(key_buf, secure_area);
             }
         }
         Ok(())
@@ -155,6 +274,7 @@ impl super::RomDevice for Normal {
         mut cmd: Bytes<8>,
         output: &mut Bytes<0x4000>,
         output_len: RomOutputLen,
+        spi: &mut Spi,
     ) {
         match self.stage {
             Stage::Initial => {
@@ -226,7 +346,9 @@ impl super::RomDevice for Normal {
                     cmd.read_be::<u64>(0),
                     prev_cmd.read_be::<u64>(0)
                 );
-                // TODO: Handle repeated commands for larger carts (bit 31 of chip ID set)
+                // Carts with bit 31 of the chip ID set receive every KEY1 command twice, which
+                // needs no special handling, as none of the responses depend on the previous
+                // command; the repeated `Axxx` command is received in KEY2 mode and ignored there.
                 // TODO: Check other command bytes for correctness too
                 match cmd[0] >> 4 {
                     0x4 => {
@@ -283,6 +405,11 @@ impl super::RomDevice for Normal {
             Stage::Key2 => {
                 #[cfg(feature = "log")]
                 slog::trace!(self.logger, "KEY2: {:016X}", cmd.read_be::<u64>(0));
+                if let Spi::Nand(nand) = spi {
+                    if nand.handle_rom_command(&cmd, output, output_len.get() as usize) {
+                        return;
+                    }
+                }
                 match cmd[0] {
                     0xB7 => {
                         // if cmd.read_be::<u32>(4) & 0x00FF_FFFF == 0 {
@@ -336,4 +463,19 @@ impl super::RomDevice for Normal {
               // }
         }
     }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn handle_rom_write(&mut self, cmd: Bytes<8>, input: &[u8], spi: &mut Spi) {
+        match (self.stage, spi) {
+            (Stage::Key2, Spi::Nand(nand)) => nand.handle_rom_write(&cmd, input),
+            _ => {
+                #[cfg(feature = "log")]
+                slog::warn!(
+                    self.logger,
+                    "Unexpected ROM write command: {:016X}",
+                    cmd.read_be::<u64>(0)
+                );
+            }
+        }
+    }
 }
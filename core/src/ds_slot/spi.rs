@@ -1,10 +1,17 @@
 mod empty;
 pub use empty::Empty;
+mod auto_detect;
+pub use auto_detect::AutoDetect;
 pub mod eeprom_4k;
 pub mod eeprom_fram;
 pub mod flash;
+pub mod ir;
+pub mod nand;
 
-use crate::{utils::Savestate, SaveReloadContents};
+use crate::{
+    utils::{ReadSavestate, Savestate, WriteSavestate},
+    SaveReloadContents,
+};
 
 trait SpiDevice {
     fn contents(&self) -> &[u8];
@@ -16,13 +23,45 @@ trait SpiDevice {
     fn write_data(&mut self, data: u8, first: bool, last: bool) -> u8;
 }
 
-#[derive(Clone, Savestate)]
+#[derive(Savestate)]
 #[load(in_place_only)]
 pub enum Spi {
     Eeprom4k(eeprom_4k::Eeprom4k),
     EepromFram(eeprom_fram::EepromFram),
     Flash(flash::Flash),
     Empty(Empty),
+    AutoDetect(AutoDetect),
+    Nand(nand::Nand),
+    Ir(ir::Ir),
+}
+
+/// The type of a [`Spi`] device, stored in savestates before the device's own state (along with
+/// the size of its contents), so that devices created by [`AutoDetect`] can be recreated when
+/// loading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Variant {
+    Eeprom4k,
+    EepromFram,
+    Flash,
+    Empty,
+    AutoDetect,
+    Nand,
+    Ir,
+}
+
+impl Variant {
+    fn from_raw(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Variant::Eeprom4k,
+            1 => Variant::EepromFram,
+            2 => Variant::Flash,
+            3 => Variant::Empty,
+            4 => Variant::AutoDetect,
+            5 => Variant::Nand,
+            6 => Variant::Ir,
+            _ => return None,
+        })
+    }
 }
 
 impl Spi {
@@ -33,13 +72,81 @@ impl Spi {
             Spi::Eeprom4k(device) => Spi::Eeprom4k(device.reset()),
             Spi::EepromFram(device) => Spi::EepromFram(device.reset()),
             Spi::Flash(device) => Spi::Flash(device.reset()),
+            Spi::AutoDetect(device) => Spi::AutoDetect(device.reset()),
+            Spi::Nand(device) => Spi::Nand(device.reset()),
+            Spi::Ir(device) => Spi::Ir(device.reset()),
+        }
+    }
+
+    /// Whether the save type is still being detected, i.e. this is an [`AutoDetect`] device that
+    /// hasn't been replaced yet.
+    #[inline]
+    pub fn is_detecting_save_type(&self) -> bool {
+        matches!(self, Spi::AutoDetect(_))
+    }
+
+    fn variant(&self) -> Variant {
+        match self {
+            Spi::Eeprom4k(_) => Variant::Eeprom4k,
+            Spi::EepromFram(_) => Variant::EepromFram,
+            Spi::Flash(_) => Variant::Flash,
+            Spi::Empty(_) => Variant::Empty,
+            Spi::AutoDetect(_) => Variant::AutoDetect,
+            Spi::Nand(_) => Variant::Nand,
+            Spi::Ir(_) => Variant::Ir,
+        }
+    }
+
+    pub(super) fn store_with_variant<S: WriteSavestate>(
+        &mut self,
+        save: &mut S,
+    ) -> Result<(), S::Error> {
+        save.store_raw(self.variant() as u8);
+        save.store_raw(self.contents().len() as u32);
+        save.store(self)
+    }
+
+    /// Loads a device stored by [`store_with_variant`](Self::store_with_variant). If the type of
+    /// the device doesn't match the current one because the savestate was created on the other
+    /// side of save type auto-detection, the device is recreated (for states created after the
+    /// current device's type was detected) or kept as is (for states created before it).
+    pub(super) fn load_with_variant<S: ReadSavestate>(
+        &mut self,
+        save: &mut S,
+    ) -> Result<(), S::Error> {
+        let variant = Variant::from_raw(save.load_raw::<u8>()?).ok_or_else(S::invalid_enum)?;
+        let len = save.load_raw::<u32>()? as usize;
+        if variant == self.variant() && len == self.contents().len() {
+            return save.load_into(self);
+        }
+        match self {
+            Spi::AutoDetect(device) => {
+                *self = device
+                    .create_detected_device(variant, len)
+                    .ok_or_else(S::invalid_enum)?;
+                save.load_into(self)
+            }
+
+            Spi::Eeprom4k(_) | Spi::EepromFram(_) | Spi::Flash(_)
+                if variant == Variant::AutoDetect =>
+            {
+                // The chip's contents outlive the savestate like for any other save device, and
+                // the game will probe it again; the auto-detection state is only read to skip it
+                let mut auto_detect = Spi::AutoDetect(AutoDetect::new(
+                    #[cfg(feature = "log")]
+                    slog::Logger::root(slog::Discard, slog::o!()),
+                ));
+                save.load_into(&mut auto_detect)
+            }
+
+            _ => Err(S::invalid_enum()),
         }
     }
 
     pub fn contents(&self) -> &[u8] {
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, contents()
         )
     }
@@ -47,7 +154,7 @@ impl Spi {
     pub fn contents_mut(&mut self) -> &mut [u8] {
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, contents_mut()
         )
     }
@@ -55,7 +162,7 @@ impl Spi {
     pub fn reload_contents(&mut self, contents: SaveReloadContents) {
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, reload_contents(contents)
         );
     }
@@ -63,7 +170,7 @@ impl Spi {
     pub fn contents_dirty(&self) -> bool {
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, contents_dirty()
         )
     }
@@ -71,7 +178,7 @@ impl Spi {
     pub fn mark_contents_dirty(&mut self) {
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, mark_contents_dirty()
         );
     }
@@ -79,15 +186,22 @@ impl Spi {
     pub fn mark_contents_flushed(&mut self) {
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, mark_contents_flushed()
         );
     }
 
     pub fn write_data(&mut self, data: u8, first: bool, last: bool) -> u8 {
+        if let Spi::AutoDetect(device) = self {
+            let (result, detected) = device.handle_byte(data, first, last);
+            if let Some(detected) = detected {
+                *self = detected;
+            }
+            return result;
+        }
         forward_to_variants!(
             Spi;
-            Eeprom4k, EepromFram, Flash, Empty;
+            Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
             self, write_data(data, first, last)
         )
     }
@@ -95,6 +209,12 @@ impl Spi {
 
 impl_from_variants!(
     Spi;
-    Eeprom4k, EepromFram, Flash, Empty;
-    eeprom_4k::Eeprom4k, eeprom_fram::EepromFram, flash::Flash, Empty
+    Eeprom4k, EepromFram, Flash, Empty, AutoDetect, Nand, Ir;
+    eeprom_4k::Eeprom4k,
+    eeprom_fram::EepromFram,
+    flash::Flash,
+    Empty,
+    AutoDetect,
+    nand::Nand,
+    ir::Ir
 );
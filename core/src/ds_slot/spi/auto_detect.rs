@@ -0,0 +1,574 @@
+use super::{eeprom_4k::Eeprom4k, eeprom_fram::EepromFram, flash::Flash, Spi, SpiDevice, Variant};
+use crate::{utils::Savestate, SaveContents, SaveReloadContents};
+
+/// The maximum length of a buffered write command: the command byte, a 3-byte address and a
+/// 256-byte page.
+const WRITE_BUFFER_LEN: usize = 260;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Eeprom4k,
+    EepromFram { len: usize },
+    Flash { len: usize },
+}
+
+impl Kind {
+    /// The capacities of the chips sharing this kind's command set and address width, in
+    /// increasing order.
+    fn capacities(self) -> &'static [usize] {
+        match self {
+            Kind::Eeprom4k => &[0x200],
+            Kind::EepromFram { len: 0x2_0000 } => &[0x2_0000],
+            Kind::EepromFram { .. } => &[0x2000, 0x1_0000],
+            Kind::Flash { .. } => &[0x4_0000, 0x8_0000, 0x10_0000],
+        }
+    }
+
+    fn addr_bytes(self) -> u8 {
+        match self {
+            Kind::Eeprom4k => 1,
+            Kind::EepromFram { len } => 2 + (len > 0x1_0000) as u8,
+            Kind::Flash { .. } => 3,
+        }
+    }
+
+    fn with_len(self, len: usize) -> Self {
+        match self {
+            Kind::Eeprom4k => Kind::Eeprom4k,
+            Kind::EepromFram { .. } => Kind::EepromFram { len },
+            Kind::Flash { .. } => Kind::Flash { len },
+        }
+    }
+}
+
+/// A placeholder for the save memory of cartridges whose save type isn't known in advance, which
+/// determines the type from the first commands sent by the game and then replaces itself (inside
+/// the containing [`Spi`]) with the corresponding device, using blank contents.
+///
+/// Until then, it behaves like blank save memory of any type: reads return `0xFF` and the status
+/// register only reflects the write enable latch. The type is chosen based on:
+/// - Commands only supported by FLASH chips (ID reads, erases, power-down and wakeup), which
+///   select a FLASH device right away;
+/// - The address width of the first read (`0x03`) command, as games usually start by reading a
+///   single byte to check for the save chip, so everything after the command byte but the last one
+///   is part of the address: a 1-byte address selects a 4 Kib EEPROM, a 2-byte one an
+///   EEPROM/FRAM device, and a 3-byte one waits for the first write, with `0x02` selecting a
+///   1 Mib EEPROM and `0x0A` (page write) selecting a FLASH device;
+/// - If no read was issued before the first write, the length of the write command, assuming it
+///   writes at most a page.
+///
+/// When several capacities share the detected address width, the device is only created once the
+/// game reads the save memory after its first write. Games checking the capacity write to an
+/// address and then read from that address plus the capacity they expect, which mirrors the
+/// written data on the real chip; if the first read other than the written address itself is such
+/// a probe, the probed capacity is used, and otherwise the largest one, which is a superset of the
+/// smaller ones as long as the game doesn't rely on mirroring. Until then, reads from the written
+/// address return the written data.
+///
+/// Cartridges with an infrared transceiver in front of the save chip aren't detected, as their
+/// commands are prefixed by an IR command byte.
+#[derive(Clone, Savestate)]
+#[load(in_place_only)]
+pub struct AutoDetect {
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+
+    write_enabled: bool,
+    addr_bytes: u8,
+    status_written: bool,
+
+    cur_command: u8,
+    cur_command_len: u16,
+    write_buffer: Box<[u8; WRITE_BUFFER_LEN]>,
+    /// The length of the first write command in `write_buffer`, once completed.
+    write_len: u16,
+    /// The state of the write enable latch during the first write command.
+    write_was_enabled: bool,
+    /// Whether the first write command has completed, and the game's next read is awaited to
+    /// determine the capacity.
+    waiting_for_probe: bool,
+    read_addr: u32,
+}
+
+#[allow(clippy::new_without_default)]
+impl AutoDetect {
+    #[inline]
+    pub fn new(#[cfg(feature = "log")] logger: slog::Logger) -> Self {
+        AutoDetect {
+            #[cfg(feature = "log")]
+            logger,
+
+            write_enabled: false,
+            addr_bytes: 0,
+            status_written: false,
+
+            cur_command: 0,
+            cur_command_len: 0,
+            write_buffer: Box::new([0; WRITE_BUFFER_LEN]),
+            write_len: 0,
+            write_was_enabled: false,
+            waiting_for_probe: false,
+            read_addr: 0,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn reset(self) -> Self {
+        AutoDetect {
+            write_enabled: false,
+            cur_command: 0,
+            cur_command_len: 0,
+            ..self
+        }
+    }
+
+    fn kind_for_addr_bytes(&self, addr_bytes: u8, write_command: u8) -> Kind {
+        match addr_bytes {
+            1 => Kind::Eeprom4k,
+            2 => Kind::EepromFram { len: 0x1_0000 },
+            _ if write_command == 0x02 || self.status_written => Kind::EepromFram { len: 0x2_0000 },
+            _ => Kind::Flash { len: 0x10_0000 },
+        }
+    }
+
+    fn create_device(&self, kind: Kind, write_enabled: bool) -> Spi {
+        #[cfg(feature = "log")]
+        slog::info!(self.logger, "Detected save type: {:?}", kind);
+        let mut device: Spi = match kind {
+            Kind::Eeprom4k => Eeprom4k::new(
+                SaveContents::New(0x200),
+                None,
+                #[cfg(feature = "log")]
+                self.logger.new(slog::o!("detected" => "eeprom_4k")),
+            )
+            .expect("couldn't create 4 Kib EEPROM DS slot SPI device")
+            .into(),
+            Kind::EepromFram { len } => EepromFram::new(
+                SaveContents::New(len),
+                None,
+                #[cfg(feature = "log")]
+                self.logger.new(slog::o!("detected" => "eeprom_fram")),
+            )
+            .expect("couldn't create EEPROM/FRAM DS slot SPI device")
+            .into(),
+            Kind::Flash { len } => Flash::new(
+                SaveContents::New(len),
+                [0; 20],
+                #[cfg(feature = "log")]
+                self.logger.new(slog::o!("detected" => "flash")),
+            )
+            .expect("couldn't create FLASH DS slot SPI device")
+            .into(),
+        };
+        // Make sure the frontend stores the new save, even if the game never writes to it
+        device.mark_contents_dirty();
+        if write_enabled {
+            device.write_data(0x06, true, true);
+        }
+        device
+    }
+
+    /// Recreates the device a savestate was created with after its type had been detected, given
+    /// the type and size of its contents stored in the savestate.
+    pub(super) fn create_detected_device(&self, variant: Variant, len: usize) -> Option<Spi> {
+        let kind = match (variant, len) {
+            (Variant::Eeprom4k, 0x200) => Kind::Eeprom4k,
+            (Variant::EepromFram, 0x2000 | 0x1_0000 | 0x2_0000) => Kind::EepromFram { len },
+            (Variant::Flash, 0x4_0000 | 0x8_0000 | 0x10_0000) => Kind::Flash { len },
+            _ => return None,
+        };
+        Some(self.create_device(kind, self.write_enabled))
+    }
+
+    /// Creates the device for the detected type, replaying the write enable latch and the
+    /// buffered write command into it, and returns it along with its response to the last byte.
+    fn finish_buffered_write(&self, kind: Kind) -> (u8, Spi) {
+        let mut device = self.create_device(kind, self.write_was_enabled);
+        let len = self.write_len as usize;
+        let mut result = 0xFF;
+        for (i, &byte) in self.write_buffer[..len].iter().enumerate() {
+            result = device.write_data(byte, i == 0, i == len - 1);
+        }
+        (result, device)
+    }
+
+    fn written_addr(&self, kind: Kind) -> u32 {
+        self.write_buffer[1..=kind.addr_bytes() as usize]
+            .iter()
+            .fold(0, |addr, &byte| addr << 8 | byte as u32)
+    }
+
+    /// Ends the wait for a capacity probe once the address of the read command sent after the
+    /// first write is known, creating the device for the probed capacity (or the largest one) and
+    /// replaying the read command into it, unless the game is reading back the written data.
+    fn finish_probe(&self, kind: Kind, last: bool) -> Option<(u8, Spi)> {
+        let written_addr = self.written_addr(kind);
+        if self.read_addr == written_addr {
+            return None;
+        }
+        let offset = self.read_addr.wrapping_sub(written_addr) as usize;
+        let capacities = kind.capacities();
+        let capacity = if capacities.contains(&offset) {
+            offset
+        } else {
+            capacities[capacities.len() - 1]
+        };
+        let (_, mut device) = self.finish_buffered_write(kind.with_len(capacity));
+        device.write_data(0x03, true, false);
+        let addr_bytes = kind.addr_bytes();
+        let mut result = 0xFF;
+        for i in (0..addr_bytes).rev() {
+            result = device.write_data((self.read_addr >> (i * 8)) as u8, false, last && i == 0);
+        }
+        Some((result, device))
+    }
+
+    /// Returns the kind (with its largest capacity) selected by the first write command.
+    fn first_write_kind(&self) -> Kind {
+        let command = self.write_buffer[0];
+        if self.addr_bytes != 0 {
+            return self.kind_for_addr_bytes(self.addr_bytes, command);
+        }
+        match self.write_len - 1 {
+            0..=17 if command == 0x02 => Kind::Eeprom4k,
+            0..=130 if command == 0x02 => Kind::EepromFram { len: 0x1_0000 },
+            _ => self.kind_for_addr_bytes(3, command),
+        }
+    }
+
+    /// Finishes the first write command, creating the device right away if the detected kind
+    /// only has one capacity, or waiting for a capacity probe otherwise.
+    fn finish_first_write(&mut self) -> (u8, Option<Spi>) {
+        self.write_len = self
+            .cur_command_len
+            .saturating_add(1)
+            .min(WRITE_BUFFER_LEN as u16);
+        self.write_was_enabled = self.write_enabled;
+        let kind = self.first_write_kind();
+        if kind.capacities().len() == 1 {
+            let (result, device) = self.finish_buffered_write(kind);
+            return (result, Some(device));
+        }
+        self.write_enabled = false;
+        self.waiting_for_probe = true;
+        (0xFF, None)
+    }
+
+    /// Handles a byte sent by the game, returning the response and, once the save type has been
+    /// determined, the device that should replace this one.
+    pub(super) fn handle_byte(&mut self, value: u8, first: bool, last: bool) -> (u8, Option<Spi>) {
+        if first {
+            self.cur_command = value;
+            self.cur_command_len = 0;
+            if self.waiting_for_probe {
+                // Anything but status and data reads ends the wait for a capacity probe
+                if !matches!(value, 0x03 | 0x05) {
+                    let (_, mut device) = self.finish_buffered_write(self.first_write_kind());
+                    let result = device.write_data(value, true, last);
+                    return (result, Some(device));
+                }
+                self.read_addr = 0;
+                return (0xFF, None);
+            }
+            match value {
+                0x9F | 0xD8 | 0xDB | 0xB9 | 0xAB => {
+                    // FLASH-only commands: read JEDEC ID, sector erase, page erase, deep
+                    // power-down and release from deep power-down
+                    let mut device =
+                        self.create_device(Kind::Flash { len: 0x10_0000 }, self.write_enabled);
+                    let result = device.write_data(value, true, last);
+                    return (result, Some(device));
+                }
+
+                0x02 | 0x0A => self.write_buffer[0] = value,
+
+                0x06 => self.write_enabled = true,
+
+                0x04 => self.write_enabled = false,
+
+                _ => {}
+            }
+            return (0xFF, None);
+        }
+
+        self.cur_command_len = self.cur_command_len.saturating_add(1);
+
+        if self.waiting_for_probe {
+            let kind = self.first_write_kind();
+            let addr_bytes = u16::from(kind.addr_bytes());
+            let result = match self.cur_command {
+                0x03 if self.cur_command_len <= addr_bytes => {
+                    self.read_addr = self.read_addr << 8 | value as u32;
+                    if self.cur_command_len == addr_bytes {
+                        if let Some((result, device)) = self.finish_probe(kind, last) {
+                            return (result, Some(device));
+                        }
+                    }
+                    0xFF
+                }
+
+                // Reading back the written data, which starts at the same offset in the write
+                // command as in the read command; the rest of the chip is blank
+                0x03 if self.cur_command_len < self.write_len => {
+                    self.write_buffer[self.cur_command_len as usize]
+                }
+
+                0x03 => 0xFF,
+
+                _ => (self.write_enabled as u8) << 1,
+            };
+            return (result, None);
+        }
+
+        let result = match self.cur_command {
+            0x02 | 0x0A => {
+                if let Some(byte) = self.write_buffer.get_mut(self.cur_command_len as usize) {
+                    *byte = value;
+                }
+                0xFF
+            }
+
+            0x05 => (self.write_enabled as u8) << 1,
+
+            _ => 0xFF,
+        };
+
+        if !last {
+            return (result, None);
+        }
+
+        match self.cur_command {
+            0x01 => {
+                // Only EEPROM and FRAM chips have a writable status register; its contents are
+                // dropped, as no write protection can be active on a blank chip anyway
+                self.status_written = true;
+                self.write_enabled = false;
+            }
+
+            0x03 if self.addr_bytes == 0 && self.cur_command_len >= 2 => {
+                self.addr_bytes = (self.cur_command_len - 1).min(3) as u8;
+                if self.addr_bytes < 3 || self.status_written {
+                    let kind = self.kind_for_addr_bytes(self.addr_bytes, 0);
+                    if kind.capacities().len() == 1 {
+                        let device = self.create_device(kind, self.write_enabled);
+                        return (result, Some(device));
+                    }
+                }
+            }
+
+            0x0B if self.addr_bytes == 0 && self.cur_command_len >= 2 => {
+                // Either a 4 Kib EEPROM read from the upper half, or a FLASH fast read (with a
+                // 3-byte address and a dummy byte)
+                let kind = if self.cur_command_len == 2 {
+                    Kind::Eeprom4k
+                } else {
+                    Kind::Flash { len: 0x10_0000 }
+                };
+                return (result, Some(self.create_device(kind, self.write_enabled)));
+            }
+
+            0x02 | 0x0A => return self.finish_first_write(),
+
+            _ => {}
+        }
+
+        (result, None)
+    }
+}
+
+impl SpiDevice for AutoDetect {
+    fn contents(&self) -> &[u8] {
+        &[]
+    }
+
+    fn contents_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    fn reload_contents(&mut self, _contents: SaveReloadContents) {}
+
+    fn contents_dirty(&self) -> bool {
+        false
+    }
+
+    fn mark_contents_dirty(&mut self) {}
+
+    fn mark_contents_flushed(&mut self) {}
+
+    fn write_data(&mut self, value: u8, first: bool, last: bool) -> u8 {
+        // `Spi` calls `handle_byte` directly instead, to be able to replace the device once the
+        // save type is known
+        self.handle_byte(value, first, last).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_spi() -> Spi {
+        AutoDetect::new(
+            #[cfg(feature = "log")]
+            slog::Logger::root(slog::Discard, slog::o!()),
+        )
+        .into()
+    }
+
+    fn transfer(spi: &mut Spi, bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| spi.write_data(byte, i == 0, i == bytes.len() - 1))
+            .collect()
+    }
+
+    #[test]
+    fn read_address_width() {
+        let mut spi = new_spi();
+        assert_eq!(transfer(&mut spi, &[0x03, 0x00, 0x00]), [0xFF; 3]);
+        assert!(matches!(spi, Spi::Eeprom4k(_)));
+        assert_eq!(spi.contents().len(), 0x200);
+
+        // 8 KiB and 64 KiB EEPROMs share the same address width
+        let mut spi = new_spi();
+        transfer(&mut spi, &[0x03, 0x00, 0x00, 0x00]);
+        assert!(spi.is_detecting_save_type());
+        transfer(&mut spi, &[0x06]);
+        transfer(&mut spi, &[0x02, 0x00, 0x10, 0x12]);
+        assert!(spi.is_detecting_save_type());
+        transfer(&mut spi, &[0x03, 0x01, 0x00, 0x00]);
+        assert!(matches!(spi, Spi::EepromFram(_)));
+        assert_eq!(spi.contents().len(), 0x1_0000);
+        assert_eq!(spi.contents()[0x10], 0x12);
+    }
+
+    #[test]
+    fn three_byte_read_waits_for_write() {
+        let mut spi = new_spi();
+        transfer(&mut spi, &[0x03, 0x00, 0x00, 0x00, 0x00]);
+        assert!(spi.is_detecting_save_type());
+        transfer(&mut spi, &[0x06]);
+        transfer(&mut spi, &[0x0A, 0x00, 0x00, 0x00, 0x12]);
+        transfer(&mut spi, &[0x05, 0x00]);
+        transfer(&mut spi, &[0x03, 0x00, 0x10, 0x00, 0x00]);
+        assert!(matches!(spi, Spi::Flash(_)));
+        assert_eq!(spi.contents().len(), 0x10_0000);
+
+        let mut spi = new_spi();
+        transfer(&mut spi, &[0x03, 0x00, 0x00, 0x00, 0x00]);
+        transfer(&mut spi, &[0x06]);
+        transfer(&mut spi, &[0x02, 0x00, 0x00, 0x00, 0x12]);
+        assert!(matches!(spi, Spi::EepromFram(_)));
+        assert_eq!(spi.contents().len(), 0x2_0000);
+    }
+
+    #[test]
+    fn flash_only_commands() {
+        for command in [0x9F, 0xD8, 0xDB, 0xB9, 0xAB] {
+            let mut spi = new_spi();
+            transfer(&mut spi, &[command]);
+            assert!(matches!(spi, Spi::Flash(_)), "command {command:#04X}");
+        }
+    }
+
+    #[test]
+    fn write_length_without_read() {
+        let mut spi = new_spi();
+        transfer(&mut spi, &[0x06]);
+        let mut write = vec![0x02, 0x10];
+        write.extend_from_slice(&[0x55; 16]);
+        transfer(&mut spi, &write);
+        assert!(matches!(spi, Spi::Eeprom4k(_)));
+
+        let mut spi = new_spi();
+        transfer(&mut spi, &[0x06]);
+        let mut write = vec![0x02, 0x00, 0x10];
+        write.extend_from_slice(&[0x55; 32]);
+        transfer(&mut spi, &write);
+        transfer(&mut spi, &[0x04]);
+        assert!(matches!(spi, Spi::EepromFram(_)));
+        assert_eq!(spi.contents().len(), 0x1_0000);
+        assert_eq!(spi.contents()[0x10..0x30], [0x55; 32]);
+    }
+
+    #[test]
+    fn mirror_probe_selects_capacity() {
+        let mut spi = new_spi();
+        transfer(&mut spi, &[0x03, 0x00, 0x00, 0x00]);
+        transfer(&mut spi, &[0x06]);
+        transfer(&mut spi, &[0x02, 0x00, 0x20, 0xA5, 0x5A]);
+        // Status polling and reading back the written data don't end the wait
+        assert_eq!(transfer(&mut spi, &[0x05, 0x00]), [0xFF, 0x00]);
+        assert_eq!(
+            transfer(&mut spi, &[0x03, 0x00, 0x20, 0x00, 0x00, 0x00]),
+            [0xFF, 0xFF, 0xFF, 0xA5, 0x5A, 0xFF]
+        );
+        assert!(spi.is_detecting_save_type());
+        // Reading from the written address plus 8 KiB finds the written data mirrored
+        assert_eq!(
+            transfer(&mut spi, &[0x03, 0x20, 0x20, 0x00, 0x00]),
+            [0xFF, 0xFF, 0xFF, 0xA5, 0x5A]
+        );
+        assert!(matches!(spi, Spi::EepromFram(_)));
+        assert_eq!(spi.contents().len(), 0x2000);
+
+        for capacity in [0x4_0000_u32, 0x8_0000] {
+            let mut spi = new_spi();
+            transfer(&mut spi, &[0x06]);
+            let mut write = vec![0x0A, 0x00, 0x01, 0x00];
+            write.extend_from_slice(&[0xC3; 0x100]);
+            transfer(&mut spi, &write);
+            assert!(spi.is_detecting_save_type());
+            let [_, addr_high, addr_mid, addr_low] = (0x100 + capacity).to_be_bytes();
+            assert_eq!(
+                transfer(&mut spi, &[0x03, addr_high, addr_mid, addr_low, 0x00]),
+                [0xFF, 0xFF, 0xFF, 0xFF, 0xC3]
+            );
+            assert!(matches!(spi, Spi::Flash(_)));
+            assert_eq!(spi.contents().len(), capacity as usize);
+        }
+    }
+
+    #[test]
+    fn status_reflects_write_enable_latch() {
+        let mut spi = new_spi();
+        assert_eq!(transfer(&mut spi, &[0x05, 0x00]), [0xFF, 0x00]);
+        transfer(&mut spi, &[0x06]);
+        assert_eq!(transfer(&mut spi, &[0x05, 0x00]), [0xFF, 0x02]);
+        transfer(&mut spi, &[0x04]);
+        assert_eq!(transfer(&mut spi, &[0x05, 0x00]), [0xFF, 0x00]);
+        assert!(spi.is_detecting_save_type());
+    }
+
+    #[test]
+    fn detected_devices_are_recreated_from_savestates() {
+        let Spi::AutoDetect(auto_detect) = new_spi() else {
+            unreachable!();
+        };
+        assert!(matches!(
+            auto_detect.create_detected_device(Variant::Eeprom4k, 0x200),
+            Some(Spi::Eeprom4k(_))
+        ));
+        assert!(matches!(
+            auto_detect.create_detected_device(Variant::EepromFram, 0x2_0000),
+            Some(Spi::EepromFram(_))
+        ));
+        assert!(matches!(
+            auto_detect.create_detected_device(Variant::Flash, 0x10_0000),
+            Some(Spi::Flash(_))
+        ));
+        assert!(matches!(
+            auto_detect.create_detected_device(Variant::Flash, 0x4_0000),
+            Some(Spi::Flash(_))
+        ));
+        // Sizes and types auto-detection never creates
+        assert!(auto_detect
+            .create_detected_device(Variant::Flash, 0x2_0000)
+            .is_none());
+        assert!(auto_detect
+            .create_detected_device(Variant::Nand, 0x80_0000)
+            .is_none());
+    }
+}
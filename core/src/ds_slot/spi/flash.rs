@@ -5,14 +5,7 @@ pub type Status = flash::Status;
 #[derive(Clone, Savestate)]
 #[load(in_place_only)]
 pub struct Flash {
-    #[cfg(feature = "log")]
-    #[savestate(skip)]
-    logger: slog::Logger,
     pub contents: flash::Flash,
-    #[savestate(skip)]
-    has_ir: bool,
-    ir_cmd: u8,
-    first_ir_data_byte: bool,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -26,7 +19,6 @@ impl Flash {
     pub fn new(
         contents: SaveContents,
         id: [u8; 20],
-        has_ir: bool,
         #[cfg(feature = "log")] logger: slog::Logger,
     ) -> Result<Self, CreationError> {
         if !matches!(contents.len(), 0x4_0000 | 0x8_0000 | 0x10_0000) {
@@ -37,15 +29,10 @@ impl Flash {
                 contents,
                 id,
                 #[cfg(feature = "log")]
-                logger.new(slog::o!("contents" => "")),
+                logger,
             )
             // NOTE: The contents' length was just checked above, this should never occur.
             .expect("couldn't create SPI FLASH device"),
-            has_ir,
-            ir_cmd: 0,
-            first_ir_data_byte: false,
-            #[cfg(feature = "log")]
-            logger,
         })
     }
 
@@ -53,17 +40,9 @@ impl Flash {
     pub fn reset(self) -> Self {
         Flash {
             contents: self.contents.reset(),
-            ir_cmd: 0,
-            first_ir_data_byte: false,
-            ..self
         }
     }
 
-    #[inline]
-    pub fn has_ir(&self) -> bool {
-        self.has_ir
-    }
-
     #[inline]
     pub fn id(&self) -> &[u8; 20] {
         self.contents.id()
@@ -128,45 +107,6 @@ impl super::SpiDevice for Flash {
     }
 
     fn write_data(&mut self, value: u8, first: bool, last: bool) -> u8 {
-        if self.has_ir {
-            if first {
-                self.ir_cmd = value;
-                self.first_ir_data_byte = true;
-                0
-            } else {
-                let first = self.first_ir_data_byte;
-                self.first_ir_data_byte = false;
-                match self.ir_cmd {
-                    0x00 => {
-                        // Pass-through to FLASH chip
-                        self.contents.handle_byte(value, first, last)
-                    }
-
-                    0x08 => {
-                        // Read ID
-                        0xAA
-                    }
-
-                    _command => {
-                        #[cfg(feature = "log")]
-                        slog::warn!(
-                            self.logger,
-                            "Unknown IR byte (command {:#04X}): {:#04X}{}",
-                            _command,
-                            value,
-                            match (first, last) {
-                                (false, false) => "",
-                                (true, false) => " (first)",
-                                (false, true) => " (last)",
-                                (true, true) => " (first, last)",
-                            }
-                        );
-                        0
-                    }
-                }
-            }
-        } else {
-            self.contents.handle_byte(value, first, last)
-        }
+        self.contents.handle_byte(value, first, last)
     }
 }
@@ -0,0 +1,189 @@
+use super::{flash::Flash, SpiDevice};
+use crate::{utils::Savestate, SaveReloadContents};
+use core::any::Any;
+
+/// The maximum length of an IR packet, limited by the length byte returned by the receive
+/// command.
+pub const MAX_PACKET_LEN: usize = 0xFF;
+
+/// The other end of the infrared link, e.g. a (virtual) Pokéwalker.
+pub trait Backend {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Called when the game transmits a packet.
+    fn send_packet(&mut self, packet: &[u8]);
+
+    /// Copies the next received packet (if any) to `packet`, returning its length, or 0 if nothing
+    /// was received.
+    fn receive_packet(&mut self, packet: &mut [u8; MAX_PACKET_LEN]) -> usize;
+}
+
+/// An IR backend with nothing on the other end of the link.
+pub struct DummyBackend;
+
+impl Backend for DummyBackend {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn send_packet(&mut self, _packet: &[u8]) {}
+
+    fn receive_packet(&mut self, _packet: &mut [u8; MAX_PACKET_LEN]) -> usize {
+        0
+    }
+}
+
+/// An infrared transceiver placed in front of the save FLASH chip, as found in some Pokémon
+/// cartridges.
+///
+/// Every transfer starts with an IR command byte, and only command `00h` passes the rest of the
+/// transfer through to the FLASH chip.
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct Ir {
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+    #[savestate(skip)]
+    pub backend: Box<dyn Backend>,
+    pub flash: Flash,
+
+    cur_command: u8,
+    first_data_byte: bool,
+    packet: Box<[u8; MAX_PACKET_LEN]>,
+    packet_len: u8,
+    packet_pos: u8,
+}
+
+impl Ir {
+    pub fn new(
+        flash: Flash,
+        backend: Box<dyn Backend>,
+        #[cfg(feature = "log")] logger: slog::Logger,
+    ) -> Self {
+        Ir {
+            #[cfg(feature = "log")]
+            logger,
+            backend,
+            flash,
+
+            cur_command: 0,
+            first_data_byte: false,
+            packet: Box::new([0; MAX_PACKET_LEN]),
+            packet_len: 0,
+            packet_pos: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn reset(self) -> Self {
+        Ir {
+            flash: self.flash.reset(),
+            cur_command: 0,
+            first_data_byte: false,
+            packet_len: 0,
+            packet_pos: 0,
+            ..self
+        }
+    }
+}
+
+impl SpiDevice for Ir {
+    fn contents(&self) -> &[u8] {
+        self.flash.contents()
+    }
+
+    fn contents_mut(&mut self) -> &mut [u8] {
+        self.flash.contents_mut()
+    }
+
+    fn reload_contents(&mut self, contents: SaveReloadContents) {
+        self.flash.reload_contents(contents);
+    }
+
+    fn contents_dirty(&self) -> bool {
+        self.flash.contents_dirty()
+    }
+
+    fn mark_contents_dirty(&mut self) {
+        self.flash.mark_contents_dirty();
+    }
+
+    fn mark_contents_flushed(&mut self) {
+        self.flash.mark_contents_flushed();
+    }
+
+    fn write_data(&mut self, value: u8, first: bool, last: bool) -> u8 {
+        if first {
+            self.cur_command = value;
+            self.first_data_byte = true;
+            return 0;
+        }
+        let first = core::mem::replace(&mut self.first_data_byte, false);
+        match self.cur_command {
+            0x00 => {
+                // Pass-through to FLASH chip
+                self.flash.write_data(value, first, last)
+            }
+
+            0x01 => {
+                // Receive packet: the first byte is the packet's length, followed by its contents
+                if first {
+                    self.packet_len = self.backend.receive_packet(&mut self.packet) as u8;
+                    self.packet_pos = 0;
+                    self.packet_len
+                } else if self.packet_pos < self.packet_len {
+                    let result = self.packet[self.packet_pos as usize];
+                    self.packet_pos += 1;
+                    result
+                } else {
+                    0
+                }
+            }
+
+            0x02 => {
+                // Send packet, transmitted once the transfer ends
+                if first {
+                    self.packet_len = 0;
+                }
+                if (self.packet_len as usize) < MAX_PACKET_LEN {
+                    self.packet[self.packet_len as usize] = value;
+                    self.packet_len += 1;
+                }
+                if last {
+                    self.backend
+                        .send_packet(&self.packet[..self.packet_len as usize]);
+                    self.packet_len = 0;
+                }
+                0
+            }
+
+            0x08 => {
+                // Read ID
+                0xAA
+            }
+
+            _command => {
+                #[cfg(feature = "log")]
+                slog::warn!(
+                    self.logger,
+                    "Unknown IR byte (command {:#04X}): {:#04X}{}",
+                    _command,
+                    value,
+                    match (first, last) {
+                        (false, false) => "",
+                        (true, false) => " (first)",
+                        (false, true) => " (last)",
+                        (true, true) => " (first, last)",
+                    }
+                );
+                0
+            }
+        }
+    }
+}
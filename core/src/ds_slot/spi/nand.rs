@@ -0,0 +1,438 @@
+use crate::{
+    utils::{mem_prelude::*, Savestate},
+    SaveContents, SaveReloadContents,
+};
+
+/// The granularity of the save area, and the size of the windows selected through command `B2h`.
+pub const BLOCK_LEN: u32 = 0x2_0000;
+const PAGE_LEN: usize = 0x800;
+const SECTOR_LEN: usize = 0x200;
+
+/// The ID returned by command `94h`; only the first bytes, matching a Samsung 1 Gib NAND chip, are
+/// known, the rest is assumed to be zero.
+const ID: [u8; 8] = [0xEC, 0xF1, 0x00, 0x95, 0x40, 0x00, 0x00, 0x00];
+
+/// NAND save memory, found in large cartridges and accessed through the ROM bus instead of SPI
+/// (which only returns high-Z values); it's exposed as an SPI device so that its contents are
+/// handled like those of any other save type.
+///
+/// Only the writable area at the end of the chip is stored, starting at the offset given in the
+/// ROM header.
+#[derive(Clone, Savestate)]
+#[load(in_place_only)]
+pub struct Nand {
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+
+    #[savestate(skip)]
+    contents: BoxedByteSlice,
+    #[savestate(skip)]
+    contents_dirty: bool,
+    #[savestate(skip)]
+    rw_start: u32,
+
+    window: Option<u32>,
+    write_enabled: bool,
+    write_buffer: Box<Bytes<PAGE_LEN>>,
+    write_buffer_addr: u32,
+    /// A bitmask of the sectors in `write_buffer` that have been written since the last commit.
+    write_buffer_sectors: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CreationError {
+    InvalidSize,
+}
+
+impl Nand {
+    pub fn new(
+        contents: SaveContents,
+        #[cfg(feature = "log")] logger: slog::Logger,
+    ) -> Result<Self, CreationError> {
+        if !contents.len().is_power_of_two() || contents.len() < BLOCK_LEN as usize {
+            return Err(CreationError::InvalidSize);
+        }
+        Ok(Nand {
+            #[cfg(feature = "log")]
+            logger,
+
+            contents: contents.get_or_create(|len| {
+                let mut contents = BoxedByteSlice::new_zeroed(len);
+                contents.fill(0xFF);
+                contents
+            }),
+            contents_dirty: false,
+            rw_start: 0,
+
+            window: None,
+            write_enabled: false,
+            write_buffer: Box::new(Bytes::new([0xFF; PAGE_LEN])),
+            write_buffer_addr: 0,
+            write_buffer_sectors: 0,
+        })
+    }
+
+    #[must_use]
+    pub fn reset(self) -> Self {
+        Nand {
+            window: None,
+            write_enabled: false,
+            write_buffer_sectors: 0,
+            ..self
+        }
+    }
+
+    /// Sets the ROM address the writable area starts at, as specified in the ROM header.
+    #[inline]
+    pub fn set_rw_start(&mut self, value: u32) {
+        self.rw_start = value & !(BLOCK_LEN - 1);
+    }
+
+    #[inline]
+    pub fn rw_start(&self) -> u32 {
+        self.rw_start
+    }
+
+    #[inline]
+    pub fn window(&self) -> Option<u32> {
+        self.window
+    }
+
+    fn rw_offset(&self, addr: u32) -> Option<usize> {
+        let offset = addr.checked_sub(self.rw_start)? as usize;
+        (offset < self.contents.len()).then_some(offset)
+    }
+
+    fn commit_write_buffer(&mut self) {
+        let Some(page_offset) = self.rw_offset(self.write_buffer_addr) else {
+            self.write_buffer_sectors = 0;
+            return;
+        };
+        for i in 0..PAGE_LEN / SECTOR_LEN {
+            if self.write_buffer_sectors & 1 << i == 0 {
+                continue;
+            }
+            let start = i * SECTOR_LEN;
+            self.contents[page_offset + start..page_offset + start + SECTOR_LEN]
+                .copy_from_slice(&self.write_buffer[start..start + SECTOR_LEN]);
+        }
+        self.write_buffer_sectors = 0;
+        self.contents_dirty = true;
+    }
+
+    /// Handles a KEY2-stage ROM command, returning `false` if it should be handled as a regular
+    /// ROM command instead.
+    pub(in super::super) fn handle_rom_command(
+        &mut self,
+        cmd: &Bytes<8>,
+        output: &mut Bytes<0x4000>,
+        output_len: usize,
+    ) -> bool {
+        match cmd[0] {
+            0x94 => {
+                // Read NAND ID
+                output[..output_len].fill(0);
+                let len = output_len.min(ID.len());
+                output[..len].copy_from_slice(&ID[..len]);
+            }
+
+            0xB2 => {
+                // Select window; windows outside the writable area switch back to ROM reads
+                let addr = cmd.read_be::<u32>(1) & !(BLOCK_LEN - 1);
+                self.window = self.rw_offset(addr).map(|_| addr);
+                output[..output_len].fill(0);
+            }
+
+            0xB7 => {
+                // Read data, only handled here if it's inside the selected writable window
+                let addr = cmd.read_be::<u32>(1);
+                let Some(window) = self.window else {
+                    return false;
+                };
+                if addr & !(BLOCK_LEN - 1) != window {
+                    return false;
+                }
+                let offset = self.rw_offset(addr).unwrap();
+                let len = output_len.min(self.contents.len() - offset);
+                output[..len].copy_from_slice(&self.contents[offset..offset + len]);
+                output[len..output_len].fill(0xFF);
+            }
+
+            0x85 => {
+                // Write enable
+                self.write_enabled = self.window.is_some();
+                output[..output_len].fill(0);
+            }
+
+            // Write data; the data itself is received once the transfer is complete
+            0x81 => {}
+
+            0x82 => {
+                // Commit the write buffer
+                if self.write_enabled {
+                    self.commit_write_buffer();
+                }
+                output[..output_len].fill(0);
+            }
+
+            0x84 => {
+                // Discard the write buffer
+                self.write_buffer_sectors = 0;
+                output[..output_len].fill(0);
+            }
+
+            0x8B => {
+                // Leave save mode
+                self.window = None;
+                self.write_enabled = false;
+                output[..output_len].fill(0);
+            }
+
+            0xD6 => {
+                // Read status: bit 5 is set when ready (writes complete instantly), bit 4 while
+                // writes are enabled
+                let status = 0x20 | (self.write_enabled as u8) << 4;
+                output[..output_len].fill(status);
+            }
+
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handles the data sent by the DS for a KEY2-stage ROM command in write mode.
+    pub(in super::super) fn handle_rom_write(&mut self, cmd: &Bytes<8>, input: &[u8]) {
+        if cmd[0] != 0x81 {
+            #[cfg(feature = "log")]
+            slog::warn!(
+                self.logger,
+                "Unknown NAND write command: {:016X}",
+                cmd.read_be::<u64>(0)
+            );
+            return;
+        }
+        let addr = cmd.read_be::<u32>(1);
+        if !self.write_enabled || self.window != Some(addr & !(BLOCK_LEN - 1)) {
+            #[cfg(feature = "log")]
+            slog::warn!(self.logger, "Ignored NAND write @ {:#010X}", addr);
+            return;
+        }
+        let page_addr = addr & !(PAGE_LEN as u32 - 1);
+        if page_addr != self.write_buffer_addr {
+            self.write_buffer_addr = page_addr;
+            self.write_buffer_sectors = 0;
+        }
+        let start = addr as usize & (PAGE_LEN - 1);
+        let len = input.len().min(PAGE_LEN - start);
+        self.write_buffer[start..start + len].copy_from_slice(&input[..len]);
+        for sector in start / SECTOR_LEN..(start + len).div_ceil(SECTOR_LEN) {
+            self.write_buffer_sectors |= 1 << sector;
+        }
+    }
+}
+
+impl super::SpiDevice for Nand {
+    fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+
+    fn contents_mut(&mut self) -> &mut [u8] {
+        &mut self.contents
+    }
+
+    fn reload_contents(&mut self, contents: SaveReloadContents) {
+        match contents {
+            SaveReloadContents::Existing(contents) => {
+                self.contents[..contents.len()].copy_from_slice(&contents);
+                self.contents[contents.len()..].fill(0xFF);
+            }
+            SaveReloadContents::New => self.contents.fill(0xFF),
+        }
+    }
+
+    fn contents_dirty(&self) -> bool {
+        self.contents_dirty
+    }
+
+    fn mark_contents_dirty(&mut self) {
+        self.contents_dirty = true;
+    }
+
+    fn mark_contents_flushed(&mut self) {
+        self.contents_dirty = false;
+    }
+
+    fn write_data(&mut self, _data: u8, _first: bool, _last: bool) -> u8 {
+        0xFF // High-Z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::SpiDevice, *};
+
+    const RW_START: u32 = 0x0100_0000;
+    const LEN: usize = 2 * BLOCK_LEN as usize;
+
+    fn new_nand() -> Nand {
+        let mut nand = Nand::new(
+            SaveContents::New(LEN),
+            #[cfg(feature = "log")]
+            slog::Logger::root(slog::Discard, slog::o!()),
+        )
+        .unwrap();
+        nand.set_rw_start(RW_START);
+        nand
+    }
+
+    fn cmd(op: u8, addr: u32) -> Bytes<8> {
+        let mut cmd = Bytes::new([0; 8]);
+        cmd[0] = op;
+        cmd[1..5].copy_from_slice(&addr.to_be_bytes());
+        cmd
+    }
+
+    fn run(nand: &mut Nand, op: u8, addr: u32, output_len: usize) -> Option<Vec<u8>> {
+        let mut output = Box::new(Bytes::new([0; 0x4000]));
+        nand.handle_rom_command(&cmd(op, addr), &mut output, output_len)
+            .then(|| output[..output_len].to_vec())
+    }
+
+    fn write(nand: &mut Nand, addr: u32, data: &[u8]) {
+        assert!(run(nand, 0x81, addr, 0).is_some());
+        nand.handle_rom_write(&cmd(0x81, addr), data);
+    }
+
+    #[test]
+    fn creation() {
+        for len in [BLOCK_LEN as usize / 2, BLOCK_LEN as usize * 3] {
+            assert_eq!(
+                Nand::new(
+                    SaveContents::New(len),
+                    #[cfg(feature = "log")]
+                    slog::Logger::root(slog::Discard, slog::o!()),
+                )
+                .err(),
+                Some(CreationError::InvalidSize)
+            );
+        }
+
+        let mut nand = new_nand();
+        assert!(nand.contents().iter().all(|&byte| byte == 0xFF));
+        assert!(!nand.contents_dirty());
+        nand.set_rw_start(RW_START + 0x1234);
+        assert_eq!(nand.rw_start(), RW_START);
+    }
+
+    #[test]
+    fn id_and_status() {
+        let mut nand = new_nand();
+        assert_eq!(run(&mut nand, 0x94, 0, 4).unwrap(), ID[..4]);
+        let mut id = ID.to_vec();
+        id.resize(0x200, 0);
+        assert_eq!(run(&mut nand, 0x94, 0, 0x200).unwrap(), id);
+
+        assert_eq!(run(&mut nand, 0xD6, 0, 4).unwrap(), [0x20; 4]);
+        run(&mut nand, 0xB2, RW_START, 4);
+        run(&mut nand, 0x85, 0, 4);
+        assert_eq!(run(&mut nand, 0xD6, 0, 4).unwrap(), [0x30; 4]);
+    }
+
+    #[test]
+    fn windows() {
+        let mut nand = new_nand();
+        // Reads are left to the ROM until a writable window is selected
+        assert!(run(&mut nand, 0xB7, RW_START, 0x200).is_none());
+
+        run(&mut nand, 0xB2, RW_START - BLOCK_LEN, 4);
+        assert_eq!(nand.window(), None);
+        run(&mut nand, 0xB2, RW_START + LEN as u32, 4);
+        assert_eq!(nand.window(), None);
+
+        run(&mut nand, 0xB2, RW_START + BLOCK_LEN + 0x345, 4);
+        assert_eq!(nand.window(), Some(RW_START + BLOCK_LEN));
+        assert!(run(&mut nand, 0xB7, RW_START, 0x200).is_none());
+        assert_eq!(
+            run(&mut nand, 0xB7, RW_START + BLOCK_LEN, 0x200).unwrap(),
+            [0xFF; 0x200]
+        );
+
+        run(&mut nand, 0x8B, 0, 4);
+        assert_eq!(nand.window(), None);
+        assert!(run(&mut nand, 0xB7, RW_START + BLOCK_LEN, 0x200).is_none());
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let mut nand = new_nand();
+        let addr = RW_START + BLOCK_LEN + 0x1200;
+        run(&mut nand, 0xB2, addr, 4);
+        run(&mut nand, 0x85, 0, 4);
+        write(&mut nand, addr, &[0x5A; 0x200]);
+        // Not visible until committed
+        assert_eq!(run(&mut nand, 0xB7, addr, 0x200).unwrap(), [0xFF; 0x200]);
+        assert!(!nand.contents_dirty());
+
+        run(&mut nand, 0x82, 0, 4);
+        assert!(nand.contents_dirty());
+        assert_eq!(run(&mut nand, 0xB7, addr, 0x200).unwrap(), [0x5A; 0x200]);
+        let offset = (addr - RW_START) as usize;
+        assert!(nand.contents()[offset..offset + 0x200]
+            .iter()
+            .all(|&byte| byte == 0x5A));
+        // Only the written sector of the page is committed
+        assert!(nand.contents()[offset - 0x200..offset]
+            .iter()
+            .all(|&byte| byte == 0xFF));
+        assert!(nand.contents()[offset + 0x200..offset + 0x600]
+            .iter()
+            .all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn ignored_writes() {
+        let mut nand = new_nand();
+        let addr = RW_START + 0x800;
+
+        // Writes need to be enabled inside the selected window
+        run(&mut nand, 0xB2, addr, 4);
+        write(&mut nand, addr, &[0; 0x200]);
+        run(&mut nand, 0x82, 0, 4);
+        assert!(nand.contents().iter().all(|&byte| byte == 0xFF));
+
+        run(&mut nand, 0x85, 0, 4);
+        write(&mut nand, addr + BLOCK_LEN, &[0; 0x200]);
+        run(&mut nand, 0x82, 0, 4);
+        assert!(nand.contents().iter().all(|&byte| byte == 0xFF));
+
+        // Discarded writes are never committed
+        write(&mut nand, addr, &[0; 0x200]);
+        run(&mut nand, 0x84, 0, 4);
+        run(&mut nand, 0x82, 0, 4);
+        assert!(nand.contents().iter().all(|&byte| byte == 0xFF));
+
+        // Resetting disables writes and deselects the window
+        let mut nand = nand.reset();
+        assert_eq!(nand.window(), None);
+        assert_eq!(run(&mut nand, 0xD6, 0, 1).unwrap(), [0x20]);
+    }
+
+    #[test]
+    fn reload_contents() {
+        let mut nand = new_nand();
+        let mut contents = BoxedByteSlice::new_zeroed(BLOCK_LEN as usize);
+        contents[0] = 0x12;
+        nand.reload_contents(SaveReloadContents::Existing(contents));
+        assert_eq!(nand.contents()[0], 0x12);
+        assert!(nand.contents()[1..BLOCK_LEN as usize]
+            .iter()
+            .all(|&byte| byte == 0));
+        assert!(nand.contents()[BLOCK_LEN as usize..]
+            .iter()
+            .all(|&byte| byte == 0xFF));
+
+        nand.reload_contents(SaveReloadContents::New);
+        assert!(nand.contents().iter().all(|&byte| byte == 0xFF));
+    }
+}
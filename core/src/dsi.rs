@@ -0,0 +1,600 @@
+pub mod aes;
+pub mod boot;
+pub mod nand;
+pub mod ndma;
+pub mod nwram;
+pub mod scfg;
+pub mod sd_mmc;
+pub mod sha1;
+
+use crate::{
+    cpu::{
+        arm7, arm9,
+        bus::{AccessType, DmaAccess},
+        Engine,
+    },
+    emu::{Emu, MainMemMask},
+    utils::Savestate,
+    Model,
+};
+use aes::Aes;
+pub use nand::NandProvider;
+use ndma::Ndma;
+use nwram::Nwram;
+use scfg::Scfg;
+use sd_mmc::SdMmc;
+
+// TODO:
+// - The DSi boot ROMs aren't emulated: when booting from a NAND image, the stage 2 bootloader is
+//   loaded directly (see `boot`), with the AES key slots set up as the boot ROMs would leave them
+// - NDMA transfers complete instantly instead of running alongside the CPUs, and block intervals,
+//   timer-, DS slot- and display-driven startup modes are ignored
+// - The ARM9 134 MHz mode, new 2D/3D/VRAM features, camera, DSP, I2C and GPIO aren't emulated
+
+/// State for the hardware that's only present in (and only accessible when emulating) a DSi.
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct Dsi {
+    pub scfg: Scfg,
+    pub nwram: Nwram,
+    pub ndma_7: Ndma,
+    pub ndma_9: Ndma,
+    pub aes: Aes,
+    pub sd_mmc: SdMmc,
+    #[savestate(skip)]
+    console_id: u64,
+    ndma_running: bool,
+}
+
+impl Dsi {
+    pub(crate) fn new(
+        sd_card: Option<Box<dyn NandProvider>>,
+        nand: Option<Box<dyn NandProvider>>,
+        nand_cid: [u8; 16],
+        console_id: u64,
+    ) -> Self {
+        let mut aes = Aes::new();
+        for (i, slot) in nand::console_key_slots(console_id).into_iter().enumerate() {
+            aes.set_key_slot(i, slot);
+        }
+        Dsi {
+            scfg: Scfg::new(),
+            nwram: Nwram::new(),
+            ndma_7: Ndma::new(),
+            ndma_9: Ndma::new(),
+            aes,
+            sd_mmc: SdMmc::new(sd_card, nand, nand_cid),
+            console_id,
+            ndma_running: false,
+        }
+    }
+
+    #[inline]
+    pub fn console_id(&self) -> u64 {
+        self.console_id
+    }
+
+    /// Configures SCFG, IRQs and the main memory size for booting a title either in DSi mode or in
+    /// DS compatibility mode.
+    pub(crate) fn setup_for_boot<E: Engine>(emu: &mut Emu<E>, dsi_mode: bool) {
+        emu.dsi.scfg.setup_for_boot(dsi_mode);
+        Self::apply_ext_9(emu);
+        Self::apply_ext_7(emu);
+    }
+
+    fn apply_ext_9<E: Engine>(emu: &mut Emu<E>) {
+        let ext = emu.dsi.scfg.ext_9();
+        emu.arm9
+            .irqs
+            .set_dsi_sources_enabled(ext.ext_irqs(), &mut emu.arm9.schedule);
+        let main_mem_mask = if emu.is_debugger() {
+            ext.main_mem_size().max(0x80_0000)
+        } else {
+            ext.main_mem_size()
+        } - 1;
+        emu.set_main_mem_mask(MainMemMask::new(main_mem_mask));
+    }
+
+    fn apply_ext_7<E: Engine>(emu: &mut Emu<E>) {
+        let ext = emu.dsi.scfg.ext_7();
+        emu.arm7
+            .irqs
+            .set_dsi_sources_enabled(ext.ext_irqs(), &mut emu.arm7.schedule);
+    }
+
+    #[inline]
+    fn nwram_regs_accessible_9<E: Engine>(emu: &Emu<E>) -> bool {
+        emu.dsi.scfg.ext_9().nwram_regs()
+    }
+
+    #[inline]
+    fn nwram_regs_accessible_7<E: Engine>(emu: &Emu<E>) -> bool {
+        emu.dsi.scfg.ext_7().nwram_regs()
+    }
+
+    /// Reads from a word-aligned address in the ARM9's DSi I/O region (`0x0400_4000` to
+    /// `0x0400_4FFF`).
+    pub(crate) fn read_arm9_io<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u32) -> u32 {
+        if emu.model() != Model::Dsi {
+            return 0;
+        }
+        let scfg_regs = emu.dsi.scfg.ext_9().scfg_regs();
+        match addr & 0xFFFC {
+            0x4000 if scfg_regs => (emu.dsi.scfg.rom_control().0 & 3) as u32,
+            0x4004 if scfg_regs => {
+                emu.dsi.scfg.clock_9() as u32 | (emu.dsi.scfg.reset_9() as u32) << 16
+            }
+            0x4008 if scfg_regs => emu.dsi.scfg.ext_9().0,
+            0x4010 if scfg_regs => emu.dsi.scfg.mem_card_control() as u32,
+
+            0x4040..=0x4053 => {
+                let base = (addr & 0x1C) as usize;
+                u32::from_le_bytes(core::array::from_fn(|i| {
+                    emu.dsi.nwram.read_slot_control(base + i)
+                }))
+            }
+            0x4054..=0x405F if Self::nwram_regs_accessible_9(emu) => {
+                emu.dsi.nwram.windows_9()[((addr - 0x4054) >> 2) as usize]
+            }
+            0x4060 if Self::nwram_regs_accessible_9(emu) => emu.dsi.nwram.write_protect(),
+
+            0x4100..=0x4173 if emu.dsi.scfg.ext_9().ndma() => {
+                emu.dsi.ndma_9.read((addr & 0xFFFC) - 0x4100)
+            }
+
+            _ => {
+                #[cfg(feature = "log")]
+                if !A::IS_DEBUG {
+                    slog::warn!(
+                        emu.arm9.logger,
+                        "Unknown DSi IO read @ {:#07X}",
+                        addr & 0x00FF_FFFC
+                    );
+                }
+                0
+            }
+        }
+    }
+
+    /// Writes the bits selected by `mask` to a word-aligned address in the ARM9's DSi I/O region.
+    pub(crate) fn write_arm9_io<A: AccessType, E: Engine>(
+        emu: &mut Emu<E>,
+        addr: u32,
+        value: u32,
+        mask: u32,
+    ) {
+        if emu.model() != Model::Dsi {
+            return;
+        }
+        let scfg_regs = emu.dsi.scfg.ext_9().scfg_regs();
+        match addr & 0xFFFC {
+            // SCFG_A9ROM is read-only
+            0x4000 => {}
+            0x4004 if scfg_regs => {
+                if mask & 0xFFFF != 0 {
+                    emu.dsi.scfg.write_clock_9(
+                        ((emu.dsi.scfg.clock_9() as u32 & !mask) | (value & mask)) as u16,
+                    );
+                }
+                if mask & 0xFFFF_0000 != 0 {
+                    emu.dsi.scfg.write_reset_9((value >> 16) as u16);
+                }
+            }
+            0x4008 if scfg_regs => {
+                let prev = emu.dsi.scfg.ext_9();
+                emu.dsi
+                    .scfg
+                    .write_ext_9(scfg::Ext9((prev.0 & !mask) | (value & mask)));
+                if emu.dsi.scfg.ext_9() != prev {
+                    Self::apply_ext_9(emu);
+                }
+            }
+            0x4010 if scfg_regs => {
+                emu.dsi.scfg.write_mem_card_control(
+                    ((emu.dsi.scfg.mem_card_control() as u32 & !mask) | (value & mask)) as u16,
+                );
+            }
+
+            0x4040..=0x4053 if Self::nwram_regs_accessible_9(emu) => {
+                let base = (addr & 0x1C) as usize;
+                let mut changed = false;
+                for i in 0..4 {
+                    if mask & 0xFF << (i << 3) != 0 {
+                        changed |= emu
+                            .dsi
+                            .nwram
+                            .write_slot_control(base + i, (value >> (i << 3)) as u8);
+                    }
+                }
+                if changed {
+                    Nwram::recalc(emu);
+                }
+            }
+            0x4054..=0x405F if Self::nwram_regs_accessible_9(emu) => {
+                let i = ((addr - 0x4054) >> 2) as usize;
+                let prev = emu.dsi.nwram.windows_9()[i];
+                if emu
+                    .dsi
+                    .nwram
+                    .write_window_9(i, (prev & !mask) | (value & mask))
+                {
+                    Nwram::recalc(emu);
+                }
+            }
+            // MBK9 is read-only on the ARM9
+            0x4060 => {}
+
+            0x4100..=0x4173 if emu.dsi.scfg.ext_9().ndma() => {
+                if let Some(i) = emu.dsi.ndma_9.write((addr & 0xFFFC) - 0x4100, value, mask) {
+                    if emu.dsi.ndma_9.channels[i.get() as usize]
+                        .control
+                        .is_immediate()
+                    {
+                        Self::run_ndma_block::<true, E>(emu, i);
+                    }
+                }
+            }
+
+            _ =>
+            {
+                #[cfg(feature = "log")]
+                if !A::IS_DEBUG {
+                    slog::warn!(
+                        emu.arm9.logger,
+                        "Unknown DSi IO write @ {:#07X}: {:#010X} (mask {:#010X})",
+                        addr & 0x00FF_FFFC,
+                        value,
+                        mask
+                    );
+                }
+            }
+        }
+    }
+
+    /// Reads from a word-aligned address in the ARM7's DSi I/O region (`0x0400_4000` to
+    /// `0x0400_4FFF`); `mask` selects the bytes actually being accessed, as some of the ARM7-only
+    /// registers have read side effects.
+    pub(crate) fn read_arm7_io<A: AccessType, E: Engine>(
+        emu: &mut Emu<E>,
+        addr: u32,
+        mask: u32,
+    ) -> u32 {
+        if emu.model() != Model::Dsi {
+            return 0;
+        }
+        let scfg_regs = emu.dsi.scfg.ext_7().scfg_regs();
+        match addr & 0xFFFC {
+            0x4000 if scfg_regs => emu.dsi.scfg.rom_control().0 as u32,
+            0x4004 if scfg_regs => {
+                emu.dsi.scfg.clock_7() as u32 | (emu.dsi.scfg.jtag_7() as u32) << 16
+            }
+            0x4008 if scfg_regs => emu.dsi.scfg.ext_7().0,
+            0x4010 if scfg_regs => {
+                emu.dsi.scfg.mem_card_control() as u32
+                    | (emu.dsi.scfg.mem_card_insert_delay() as u32) << 16
+            }
+            0x4014 if scfg_regs => emu.dsi.scfg.mem_card_power_off_delay() as u32,
+            0x4020 if scfg_regs => emu.dsi.scfg.wifi_control() as u32,
+            // SCFG_OP: retail unit
+            0x4024 if scfg_regs => 0,
+
+            0x4040..=0x4053 => {
+                let base = (addr & 0x1C) as usize;
+                u32::from_le_bytes(core::array::from_fn(|i| {
+                    emu.dsi.nwram.read_slot_control(base + i)
+                }))
+            }
+            0x4054..=0x405F if Self::nwram_regs_accessible_7(emu) => {
+                emu.dsi.nwram.windows_7()[((addr - 0x4054) >> 2) as usize]
+            }
+            0x4060 if Self::nwram_regs_accessible_7(emu) => emu.dsi.nwram.write_protect(),
+
+            0x4100..=0x4173 if emu.dsi.scfg.ext_7().ndma() => {
+                emu.dsi.ndma_7.read((addr & 0xFFFC) - 0x4100)
+            }
+
+            0x4400..=0x44FF if emu.dsi.scfg.ext_7().aes() => {
+                let (value, irq) = emu.dsi.aes.read(addr & 0xFC, A::IS_DEBUG);
+                Self::handle_aes_update(emu, irq);
+                value
+            }
+
+            0x4800..=0x49FF if emu.dsi.scfg.ext_7().sd_mmc() => {
+                let (value, irq) = emu.dsi.sd_mmc.read(addr & 0x1FC, mask, A::IS_DEBUG);
+                Self::handle_sd_mmc_update(emu, irq);
+                value
+            }
+
+            0x4D00 if !emu.dsi.scfg.rom_control().console_id_disabled() => {
+                emu.dsi.console_id as u32
+            }
+            0x4D04 if !emu.dsi.scfg.rom_control().console_id_disabled() => {
+                (emu.dsi.console_id >> 32) as u32
+            }
+            0x4D08 if !emu.dsi.scfg.rom_control().console_id_disabled() => 1,
+
+            _ => {
+                #[cfg(feature = "log")]
+                if !A::IS_DEBUG {
+                    slog::warn!(
+                        emu.arm7.logger,
+                        "Unknown DSi IO read @ {:#07X}",
+                        addr & 0x00FF_FFFC
+                    );
+                }
+                0
+            }
+        }
+    }
+
+    /// Writes the bits selected by `mask` to a word-aligned address in the ARM7's DSi I/O region.
+    pub(crate) fn write_arm7_io<A: AccessType, E: Engine>(
+        emu: &mut Emu<E>,
+        addr: u32,
+        value: u32,
+        mask: u32,
+    ) {
+        if emu.model() != Model::Dsi {
+            return;
+        }
+        let scfg_regs = emu.dsi.scfg.ext_7().scfg_regs();
+        match addr & 0xFFFC {
+            0x4000 if scfg_regs => {
+                if mask & 0xFFFF != 0 {
+                    emu.dsi
+                        .scfg
+                        .write_rom_control(scfg::RomControl((value & mask) as u16));
+                }
+            }
+            0x4004 if scfg_regs => {
+                if mask & 0xFFFF != 0 {
+                    emu.dsi.scfg.write_clock_7(
+                        ((emu.dsi.scfg.clock_7() as u32 & !mask) | (value & mask)) as u16,
+                    );
+                }
+                if mask & 0xFFFF_0000 != 0 {
+                    emu.dsi.scfg.write_jtag_7((value >> 16) as u16);
+                }
+            }
+            0x4008 if scfg_regs => {
+                let prev = emu.dsi.scfg.ext_7();
+                emu.dsi
+                    .scfg
+                    .write_ext_7(scfg::Ext7((prev.0 & !mask) | (value & mask)));
+                if emu.dsi.scfg.ext_7() != prev {
+                    Self::apply_ext_7(emu);
+                }
+            }
+            0x4010 if scfg_regs => {
+                if mask & 0xFFFF != 0 {
+                    emu.dsi.scfg.write_mem_card_control(
+                        ((emu.dsi.scfg.mem_card_control() as u32 & !mask) | (value & mask)) as u16,
+                    );
+                }
+                if mask & 0xFFFF_0000 != 0 {
+                    emu.dsi.scfg.write_mem_card_insert_delay(
+                        ((((emu.dsi.scfg.mem_card_insert_delay() as u32) << 16 & !mask)
+                            | (value & mask))
+                            >> 16) as u16,
+                    );
+                }
+            }
+            0x4014 if scfg_regs => {
+                emu.dsi.scfg.write_mem_card_power_off_delay(
+                    ((emu.dsi.scfg.mem_card_power_off_delay() as u32 & !mask) | (value & mask))
+                        as u16,
+                );
+            }
+            0x4020 if scfg_regs => emu.dsi.scfg.write_wifi_control((value & mask) as u16),
+            0x4024 => {}
+
+            // MBK1-MBK5 are read-only on the ARM7
+            0x4040..=0x4053 => {}
+            0x4054..=0x405F if Self::nwram_regs_accessible_7(emu) => {
+                let i = ((addr - 0x4054) >> 2) as usize;
+                let prev = emu.dsi.nwram.windows_7()[i];
+                if emu
+                    .dsi
+                    .nwram
+                    .write_window_7(i, (prev & !mask) | (value & mask))
+                {
+                    Nwram::recalc(emu);
+                }
+            }
+            0x4060 if Self::nwram_regs_accessible_7(emu) => {
+                let prev = emu.dsi.nwram.write_protect();
+                emu.dsi
+                    .nwram
+                    .write_write_protect((prev & !mask) | (value & mask));
+            }
+
+            0x4100..=0x4173 if emu.dsi.scfg.ext_7().ndma() => {
+                if let Some(i) = emu.dsi.ndma_7.write((addr & 0xFFFC) - 0x4100, value, mask) {
+                    if emu.dsi.ndma_7.channels[i.get() as usize]
+                        .control
+                        .is_immediate()
+                    {
+                        Self::run_ndma_block::<false, E>(emu, i);
+                    }
+                    Self::handle_aes_update(emu, false);
+                    Self::handle_sd_mmc_update(emu, false);
+                }
+            }
+
+            0x4400..=0x44FF if emu.dsi.scfg.ext_7().aes() => {
+                let irq = emu.dsi.aes.write(addr & 0xFC, value, mask);
+                Self::handle_aes_update(emu, irq);
+            }
+
+            0x4800..=0x49FF if emu.dsi.scfg.ext_7().sd_mmc() => {
+                let irq = emu.dsi.sd_mmc.write(addr & 0x1FC, value, mask);
+                Self::handle_sd_mmc_update(emu, irq);
+            }
+
+            // The console ID registers are read-only
+            0x4D00..=0x4D0B => {}
+
+            _ =>
+            {
+                #[cfg(feature = "log")]
+                if !A::IS_DEBUG {
+                    slog::warn!(
+                        emu.arm7.logger,
+                        "Unknown DSi IO write @ {:#07X}: {:#010X} (mask {:#010X})",
+                        addr & 0x00FF_FFFC,
+                        value,
+                        mask
+                    );
+                }
+            }
+        }
+    }
+
+    /// Raises the AES IRQ if requested and services the engine's NDMA requests; FIFO accesses
+    /// performed by the NDMA transfers themselves are handled by the outermost call.
+    fn handle_aes_update<E: Engine>(emu: &mut Emu<E>, irq: bool) {
+        if irq {
+            emu.arm7.irqs.write_requested_2(
+                emu.arm7.irqs.requested_2().with_aes(true),
+                &mut emu.arm7.schedule,
+            );
+        }
+        if emu.dsi.ndma_running {
+            return;
+        }
+        loop {
+            if emu.dsi.aes.input_dma_requested()
+                && Self::start_ndma_transfers::<false, E>(emu, ndma::startup_mode::ARM7_AES_IN)
+            {
+                continue;
+            }
+            if emu.dsi.aes.output_dma_requested()
+                && Self::start_ndma_transfers::<false, E>(emu, ndma::startup_mode::ARM7_AES_OUT)
+            {
+                continue;
+            }
+            break;
+        }
+    }
+
+    /// Raises the SD/MMC IRQ if requested and services the controller's NDMA requests, the same way
+    /// as for the AES engine.
+    fn handle_sd_mmc_update<E: Engine>(emu: &mut Emu<E>, irq: bool) {
+        if irq {
+            emu.arm7.irqs.write_requested_2(
+                emu.arm7.irqs.requested_2().with_sd_mmc(true),
+                &mut emu.arm7.schedule,
+            );
+        }
+        if emu.dsi.ndma_running {
+            return;
+        }
+        while emu.dsi.sd_mmc.dma_requested()
+            && Self::start_ndma_transfers::<false, E>(emu, ndma::startup_mode::ARM7_SD_MMC)
+        {
+        }
+    }
+
+    /// Runs one block of every enabled NDMA channel with the specified startup mode, returning
+    /// whether any channel was triggered.
+    pub(crate) fn start_ndma_transfers<const ARM9: bool, E: Engine>(
+        emu: &mut Emu<E>,
+        startup_mode: u8,
+    ) -> bool {
+        if emu.model() != Model::Dsi {
+            return false;
+        }
+        let mut triggered = false;
+        for i in 0..4 {
+            let ndma = if ARM9 {
+                &emu.dsi.ndma_9
+            } else {
+                &emu.dsi.ndma_7
+            };
+            let control = ndma.channels[i as usize].control;
+            if control.enabled() && control.startup_mode() == startup_mode {
+                Self::run_ndma_block::<ARM9, E>(emu, ndma::Index::new(i));
+                triggered = true;
+            }
+        }
+        triggered
+    }
+
+    fn run_ndma_block<const ARM9: bool, E: Engine>(emu: &mut Emu<E>, i: ndma::Index) {
+        let was_running = emu.dsi.ndma_running;
+        emu.dsi.ndma_running = true;
+
+        macro_rules! channel {
+            () => {
+                if ARM9 {
+                    &mut emu.dsi.ndma_9.channels[i.get() as usize]
+                } else {
+                    &mut emu.dsi.ndma_7.channels[i.get() as usize]
+                }
+            };
+        }
+
+        let channel = channel!();
+        let words = channel.block_words();
+        let src_incr = channel.src_addr_incr() as u32;
+        let dst_incr = channel.dst_addr_incr() as u32;
+        let fill = channel.control.src_addr_control() == 3;
+        for _ in 0..words {
+            let channel = channel!();
+            let (src_addr, dst_addr, fill_data) = (
+                channel.cur_src_addr,
+                channel.cur_dst_addr,
+                channel.fill_data,
+            );
+            let value = if fill {
+                fill_data
+            } else if ARM9 {
+                arm9::bus::read_32::<DmaAccess, _, false>(emu, src_addr)
+            } else {
+                arm7::bus::read_32::<DmaAccess, _>(emu, src_addr)
+            };
+            if ARM9 {
+                arm9::bus::write_32::<DmaAccess, _>(emu, dst_addr, value);
+            } else {
+                arm7::bus::write_32::<DmaAccess, _>(emu, dst_addr, value);
+            }
+            let channel = channel!();
+            channel.cur_src_addr = channel.cur_src_addr.wrapping_add(src_incr);
+            channel.cur_dst_addr = channel.cur_dst_addr.wrapping_add(dst_incr);
+        }
+
+        let channel = channel!();
+        if channel.control.src_reload() {
+            channel.cur_src_addr = channel.src_addr;
+        }
+        if channel.control.dst_reload() {
+            channel.cur_dst_addr = channel.dst_addr;
+        }
+        let finished = if channel.control.is_immediate() {
+            true
+        } else if channel.control.repeat() {
+            false
+        } else {
+            channel.remaining_words = channel.remaining_words.saturating_sub(words);
+            channel.remaining_words == 0
+        };
+        if finished {
+            channel.control.set_enabled(false);
+            if channel.control.fire_irq() {
+                if ARM9 {
+                    emu.arm9.irqs.write_requested(
+                        arm9::IrqFlags(emu.arm9.irqs.requested().0 | 1 << (28 + i.get())),
+                        (),
+                    );
+                } else {
+                    emu.arm7.irqs.write_requested(
+                        arm7::IrqFlags(emu.arm7.irqs.requested().0 | 1 << (28 + i.get())),
+                        (),
+                    );
+                }
+            }
+        }
+
+        emu.dsi.ndma_running = was_running;
+    }
+}
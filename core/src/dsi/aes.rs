@@ -0,0 +1,569 @@
+use crate::utils::Savestate;
+
+// TODO:
+// - CCM MAC verification/output follows the common interpretation of the (sparse) documentation
+//   and hasn't been checked against hardware
+// - Transfers complete instantly, the engine's actual throughput isn't emulated
+
+#[rustfmt::skip]
+static SBOX: [u8; 256] = [
+    0x63, 0x7C, 0x77, 0x7B, 0xF2, 0x6B, 0x6F, 0xC5, 0x30, 0x01, 0x67, 0x2B, 0xFE, 0xD7, 0xAB, 0x76,
+    0xCA, 0x82, 0xC9, 0x7D, 0xFA, 0x59, 0x47, 0xF0, 0xAD, 0xD4, 0xA2, 0xAF, 0x9C, 0xA4, 0x72, 0xC0,
+    0xB7, 0xFD, 0x93, 0x26, 0x36, 0x3F, 0xF7, 0xCC, 0x34, 0xA5, 0xE5, 0xF1, 0x71, 0xD8, 0x31, 0x15,
+    0x04, 0xC7, 0x23, 0xC3, 0x18, 0x96, 0x05, 0x9A, 0x07, 0x12, 0x80, 0xE2, 0xEB, 0x27, 0xB2, 0x75,
+    0x09, 0x83, 0x2C, 0x1A, 0x1B, 0x6E, 0x5A, 0xA0, 0x52, 0x3B, 0xD6, 0xB3, 0x29, 0xE3, 0x2F, 0x84,
+    0x53, 0xD1, 0x00, 0xED, 0x20, 0xFC, 0xB1, 0x5B, 0x6A, 0xCB, 0xBE, 0x39, 0x4A, 0x4C, 0x58, 0xCF,
+    0xD0, 0xEF, 0xAA, 0xFB, 0x43, 0x4D, 0x33, 0x85, 0x45, 0xF9, 0x02, 0x7F, 0x50, 0x3C, 0x9F, 0xA8,
+    0x51, 0xA3, 0x40, 0x8F, 0x92, 0x9D, 0x38, 0xF5, 0xBC, 0xB6, 0xDA, 0x21, 0x10, 0xFF, 0xF3, 0xD2,
+    0xCD, 0x0C, 0x13, 0xEC, 0x5F, 0x97, 0x44, 0x17, 0xC4, 0xA7, 0x7E, 0x3D, 0x64, 0x5D, 0x19, 0x73,
+    0x60, 0x81, 0x4F, 0xDC, 0x22, 0x2A, 0x90, 0x88, 0x46, 0xEE, 0xB8, 0x14, 0xDE, 0x5E, 0x0B, 0xDB,
+    0xE0, 0x32, 0x3A, 0x0A, 0x49, 0x06, 0x24, 0x5C, 0xC2, 0xD3, 0xAC, 0x62, 0x91, 0x95, 0xE4, 0x79,
+    0xE7, 0xC8, 0x37, 0x6D, 0x8D, 0xD5, 0x4E, 0xA9, 0x6C, 0x56, 0xF4, 0xEA, 0x65, 0x7A, 0xAE, 0x08,
+    0xBA, 0x78, 0x25, 0x2E, 0x1C, 0xA6, 0xB4, 0xC6, 0xE8, 0xDD, 0x74, 0x1F, 0x4B, 0xBD, 0x8B, 0x8A,
+    0x70, 0x3E, 0xB5, 0x66, 0x48, 0x03, 0xF6, 0x0E, 0x61, 0x35, 0x57, 0xB9, 0x86, 0xC1, 0x1D, 0x9E,
+    0xE1, 0xF8, 0x98, 0x11, 0x69, 0xD9, 0x8E, 0x94, 0x9B, 0x1E, 0x87, 0xE9, 0xCE, 0x55, 0x28, 0xDF,
+    0x8C, 0xA1, 0x89, 0x0D, 0xBF, 0xE6, 0x42, 0x68, 0x41, 0x99, 0x2D, 0x0F, 0xB0, 0x54, 0xBB, 0x16,
+];
+
+const KEY_SCRAMBLER_CONST: u128 = 0xFFFE_FB4E_2959_0258_2A68_0F5F_1A4F_3E79;
+
+/// A plain AES-128 block cipher; only encryption is needed, as the engine exclusively uses counter
+/// based modes.
+#[derive(Clone, Savestate)]
+struct Cipher {
+    round_keys: [[u8; 16]; 11],
+}
+
+impl Cipher {
+    fn new(key: [u8; 16]) -> Self {
+        let mut round_keys = [[0; 16]; 11];
+        round_keys[0] = key;
+        let mut prev = key;
+        let mut rcon = 1_u8;
+        for round_key in &mut round_keys[1..] {
+            let mut word = [
+                SBOX[prev[13] as usize] ^ rcon,
+                SBOX[prev[14] as usize],
+                SBOX[prev[15] as usize],
+                SBOX[prev[12] as usize],
+            ];
+            for (dst, src) in round_key.chunks_exact_mut(4).zip(prev.chunks_exact(4)) {
+                for (word_byte, src_byte) in word.iter_mut().zip(src) {
+                    *word_byte ^= src_byte;
+                }
+                dst.copy_from_slice(&word);
+            }
+            prev = *round_key;
+            rcon = xtime(rcon);
+        }
+        Cipher { round_keys }
+    }
+
+    fn encrypt(&self, block: &mut [u8; 16]) {
+        add_round_key(block, &self.round_keys[0]);
+        for (i, round_key) in self.round_keys[1..].iter().enumerate() {
+            for byte in block.iter_mut() {
+                *byte = SBOX[*byte as usize];
+            }
+            shift_rows(block);
+            if i != 9 {
+                mix_columns(block);
+            }
+            add_round_key(block, round_key);
+        }
+    }
+}
+
+#[inline]
+fn xtime(value: u8) -> u8 {
+    value << 1 ^ if value & 0x80 != 0 { 0x1B } else { 0 }
+}
+
+#[inline]
+fn add_round_key(block: &mut [u8; 16], round_key: &[u8; 16]) {
+    for (byte, key_byte) in block.iter_mut().zip(round_key) {
+        *byte ^= key_byte;
+    }
+}
+
+#[inline]
+fn shift_rows(block: &mut [u8; 16]) {
+    let prev = *block;
+    for column in 0..4 {
+        for row in 0..4 {
+            block[column * 4 + row] = prev[((column + row) & 3) * 4 + row];
+        }
+    }
+}
+
+#[inline]
+fn mix_columns(block: &mut [u8; 16]) {
+    for column in block.chunks_exact_mut(4) {
+        let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+        let all = a ^ b ^ c ^ d;
+        column[0] ^= all ^ xtime(a ^ b);
+        column[1] ^= all ^ xtime(b ^ c);
+        column[2] ^= all ^ xtime(c ^ d);
+        column[3] ^= all ^ xtime(d ^ a);
+    }
+}
+
+/// Converts between the little-endian order the engine's registers and FIFOs use and the
+/// big-endian one the AES algorithm is specified in.
+#[inline]
+fn swap_16(value: [u8; 16]) -> [u8; 16] {
+    let mut result = value;
+    result.reverse();
+    result
+}
+
+#[inline]
+fn increment_counter(counter: &mut [u8; 16], counter_bytes: usize) {
+    for byte in counter[16 - counter_bytes..].iter_mut().rev() {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
+
+/// Runs AES-CTR over `data` (whose length must be a multiple of 16) the same way the engine does,
+/// with `key` and `counter` in register byte order.
+pub(super) fn ctr_crypt(key: [u8; 16], counter: [u8; 16], data: &mut [u8]) {
+    let cipher = Cipher::new(swap_16(key));
+    let mut counter = swap_16(counter);
+    for block in data.chunks_exact_mut(16) {
+        let mut keystream = counter;
+        cipher.encrypt(&mut keystream);
+        increment_counter(&mut counter, 16);
+        for (byte, key_byte) in block.iter_mut().zip(swap_16(keystream)) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct Control(pub u32): Debug {
+        pub write_fifo_len: u8 @ 0..=4,
+        pub read_fifo_len: u8 @ 5..=9,
+        pub flush_write_fifo: bool @ 10,
+        pub flush_read_fifo: bool @ 11,
+        pub write_dma_size: u8 @ 12..=13,
+        pub read_dma_size: u8 @ 14..=15,
+        pub raw_mac_len: u8 @ 16..=18,
+        pub mac_from_reg: bool @ 20,
+        pub mac_verified: bool @ 21,
+        pub apply_key_select: bool @ 24,
+        pub key_slot: u8 @ 26..=27,
+        pub raw_mode: u8 @ 28..=29,
+        pub irq_enabled: bool @ 30,
+        pub busy: bool @ 31,
+    }
+}
+
+impl Control {
+    #[inline]
+    pub fn mac_len(self) -> usize {
+        (self.raw_mac_len() as usize + 1) << 1
+    }
+
+    #[inline]
+    pub fn mode(self) -> Mode {
+        match self.raw_mode() {
+            0 => Mode::CcmDecrypt,
+            1 => Mode::CcmEncrypt,
+            _ => Mode::Ctr,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    CcmDecrypt,
+    CcmEncrypt,
+    Ctr,
+}
+
+#[derive(Clone, Copy, Savestate)]
+pub struct KeySlot {
+    pub normal: [u8; 16],
+    pub x: [u8; 16],
+    pub y: [u8; 16],
+}
+
+impl KeySlot {
+    /// Derives the normal key from the X and Y keys through the hardware key scrambler.
+    pub(super) fn derive_normal(&mut self) {
+        let x = u128::from_le_bytes(self.x);
+        let y = u128::from_le_bytes(self.y);
+        self.normal = ((x ^ y).wrapping_add(KEY_SCRAMBLER_CONST))
+            .rotate_left(42)
+            .to_le_bytes();
+    }
+}
+
+#[derive(Clone, Savestate)]
+struct Fifo {
+    words: [u32; 16],
+    len: u8,
+}
+
+impl Fifo {
+    const fn new() -> Self {
+        Fifo {
+            words: [0; 16],
+            len: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, value: u32) -> bool {
+        if self.len == 16 {
+            return false;
+        }
+        self.words[self.len as usize] = value;
+        self.len += 1;
+        true
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<u32> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = self.words[0];
+        self.words.copy_within(1.., 0);
+        self.len -= 1;
+        Some(value)
+    }
+
+    #[inline]
+    fn peek(&self) -> u32 {
+        self.words[0]
+    }
+
+    #[inline]
+    fn pop_block(&mut self) -> Option<[u8; 16]> {
+        if self.len < 4 {
+            return None;
+        }
+        let mut block = [0; 16];
+        for chunk in block.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&self.pop().unwrap_or(0).to_le_bytes());
+        }
+        Some(block)
+    }
+
+    #[inline]
+    fn push_block(&mut self, block: [u8; 16]) {
+        for chunk in block.chunks_exact(4) {
+            self.push(u32::from_le_bytes(chunk.try_into().unwrap()));
+        }
+    }
+}
+
+/// The DSi's AES engine, accessible from the ARM7 only and used to handle NAND, SD card and
+/// DSiWare encryption; it supports AES-CTR and AES-CCM (with 96-bit nonces).
+#[derive(Savestate)]
+pub struct Aes {
+    control: Control,
+    block_count: u32,
+    write_fifo: Fifo,
+    read_fifo: Fifo,
+    iv: [u8; 16],
+    mac: [u8; 16],
+    key_slots: [KeySlot; 4],
+    cur_key: [u8; 16],
+    cipher: Cipher,
+    counter: [u8; 16],
+    cbc_mac: [u8; 16],
+    remaining_blocks: u16,
+    awaiting_mac: bool,
+}
+
+impl Aes {
+    pub(super) fn new() -> Self {
+        Aes {
+            control: Control(0),
+            block_count: 0,
+            write_fifo: Fifo::new(),
+            read_fifo: Fifo::new(),
+            iv: [0; 16],
+            mac: [0; 16],
+            key_slots: [KeySlot {
+                normal: [0; 16],
+                x: [0; 16],
+                y: [0; 16],
+            }; 4],
+            cur_key: [0; 16],
+            cipher: Cipher::new([0; 16]),
+            counter: [0; 16],
+            cbc_mac: [0; 16],
+            remaining_blocks: 0,
+            awaiting_mac: false,
+        }
+    }
+
+    #[inline]
+    pub fn control(&self) -> Control {
+        self.control
+            .with_write_fifo_len(self.write_fifo.len)
+            .with_read_fifo_len(self.read_fifo.len)
+    }
+
+    #[inline]
+    pub fn block_count(&self) -> u32 {
+        self.block_count
+    }
+
+    #[inline]
+    pub fn key_slots(&self) -> &[KeySlot; 4] {
+        &self.key_slots
+    }
+
+    /// Sets the contents of a key slot directly, as done by the boot ROM before handing control to
+    /// the firmware.
+    #[inline]
+    pub fn set_key_slot(&mut self, i: usize, slot: KeySlot) {
+        self.key_slots[i] = slot;
+    }
+
+    /// Returns whether the engine is requesting more input data through NDMA.
+    #[inline]
+    pub fn input_dma_requested(&self) -> bool {
+        self.control.busy()
+            && self.write_fifo.len as usize
+                <= 16 - ((self.control.write_dma_size() as usize + 1) << 2)
+    }
+
+    /// Returns whether the engine has output data ready to be read through NDMA.
+    #[inline]
+    pub fn output_dma_requested(&self) -> bool {
+        self.read_fifo.len as usize >= (self.control.read_dma_size() as usize + 1) << 2
+    }
+
+    /// Reads from a word-aligned offset relative to `AES_CNT`, also returning whether an IRQ needs
+    /// to be requested.
+    pub(super) fn read(&mut self, offset: u32, peek: bool) -> (u32, bool) {
+        match offset {
+            0x00 => (self.control().0, false),
+            0x04 => (self.block_count, false),
+            0x0C => {
+                if peek {
+                    (self.read_fifo.peek(), false)
+                } else {
+                    let value = self.read_fifo.pop().unwrap_or(0);
+                    (value, self.process())
+                }
+            }
+            _ => (0, false),
+        }
+    }
+
+    /// Writes the bits selected by `mask` to a word-aligned offset relative to `AES_CNT`, returning
+    /// whether an IRQ needs to be requested.
+    pub(super) fn write(&mut self, offset: u32, value: u32, mask: u32) -> bool {
+        match offset {
+            0x00 => {
+                let value = value & mask;
+                if value & 1 << 10 != 0 {
+                    self.write_fifo = Fifo::new();
+                }
+                if value & 1 << 11 != 0 {
+                    self.read_fifo = Fifo::new();
+                }
+                let prev = self.control;
+                self.control.0 = (self.control.0 & !(mask & 0xFD17_F000)) | (value & 0xFD17_F000);
+                if self.control.apply_key_select() {
+                    self.cur_key = self.key_slots[self.control.key_slot() as usize].normal;
+                    self.control.set_apply_key_select(false);
+                }
+                if !prev.busy() && self.control.busy() {
+                    self.start();
+                }
+                self.process()
+            }
+            0x04 => {
+                self.block_count = (self.block_count & !mask) | (value & mask);
+                false
+            }
+            0x08 => {
+                self.write_fifo.push(value);
+                self.process()
+            }
+            0x20..=0x2F => {
+                write_bytes(&mut self.iv, offset - 0x20, value, mask);
+                false
+            }
+            0x30..=0x3F => {
+                write_bytes(&mut self.mac, offset - 0x30, value, mask);
+                false
+            }
+            0x40..=0xFF => {
+                let slot = &mut self.key_slots[((offset - 0x40) / 0x30) as usize];
+                let slot_offset = (offset - 0x40) % 0x30;
+                match slot_offset {
+                    0x00..=0x0F => write_bytes(&mut slot.normal, slot_offset, value, mask),
+                    0x10..=0x1F => write_bytes(&mut slot.x, slot_offset - 0x10, value, mask),
+                    _ => {
+                        write_bytes(&mut slot.y, slot_offset - 0x20, value, mask);
+                        if slot_offset == 0x2C && mask & 0xFF00_0000 != 0 {
+                            slot.derive_normal();
+                        }
+                    }
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn start(&mut self) {
+        self.cipher = Cipher::new(swap_16(self.cur_key));
+        self.remaining_blocks = (self.block_count >> 16) as u16;
+        self.awaiting_mac = false;
+        let iv = swap_16(self.iv);
+        match self.control.mode() {
+            Mode::Ctr => self.counter = iv,
+            Mode::CcmDecrypt | Mode::CcmEncrypt => {
+                // The nonce is stored in the first 12 bytes of the IV register, which end up at the
+                // end of the byte-swapped value
+                let nonce = &iv[4..];
+                let data_len = (self.remaining_blocks as u32) << 4;
+                let mut b0 = [0; 16];
+                b0[0] = ((self.control.mac_len() as u8 - 2) >> 1) << 3 | 2;
+                b0[1..13].copy_from_slice(nonce);
+                b0[13..].copy_from_slice(&data_len.to_be_bytes()[1..]);
+                self.cipher.encrypt(&mut b0);
+                self.cbc_mac = b0;
+
+                self.counter = [0; 16];
+                self.counter[0] = 2;
+                self.counter[1..13].copy_from_slice(nonce);
+            }
+        }
+        if self.remaining_blocks == 0 {
+            self.finish();
+        }
+    }
+
+    fn crypt_block(&mut self, block: &mut [u8; 16]) {
+        // CCM uses a 24-bit counter starting from 1 (0 is reserved for the MAC), while CTR
+        // increments the whole 128-bit IV
+        if self.control.mode() != Mode::Ctr {
+            increment_counter(&mut self.counter, 3);
+        }
+        let mut keystream = self.counter;
+        self.cipher.encrypt(&mut keystream);
+        if self.control.mode() == Mode::Ctr {
+            increment_counter(&mut self.counter, 16);
+        }
+        for (byte, key_byte) in block.iter_mut().zip(keystream) {
+            *byte ^= key_byte;
+        }
+    }
+
+    fn update_cbc_mac(&mut self, plaintext: &[u8; 16]) {
+        for (byte, plain_byte) in self.cbc_mac.iter_mut().zip(plaintext) {
+            *byte ^= plain_byte;
+        }
+        let mut mac = self.cbc_mac;
+        self.cipher.encrypt(&mut mac);
+        self.cbc_mac = mac;
+    }
+
+    /// Returns the encrypted CCM MAC for the data processed so far.
+    fn final_mac(&self) -> [u8; 16] {
+        let mut s0 = self.counter;
+        s0[13..].fill(0);
+        self.cipher.encrypt(&mut s0);
+        let mut mac = [0; 16];
+        for ((mac_byte, cbc_mac_byte), s0_byte) in mac
+            .iter_mut()
+            .zip(self.cbc_mac)
+            .zip(s0)
+            .take(self.control.mac_len())
+        {
+            *mac_byte = cbc_mac_byte ^ s0_byte;
+        }
+        mac
+    }
+
+    /// Processes as many blocks as the FIFOs allow, returning whether the current operation just
+    /// completed and an IRQ needs to be requested.
+    fn process(&mut self) -> bool {
+        if !self.control.busy() {
+            return false;
+        }
+        if self.awaiting_mac {
+            let Some(mac) = self.write_fifo.pop_block() else {
+                return false;
+            };
+            self.verify_mac(swap_16(mac));
+            return self.finish();
+        }
+        while self.remaining_blocks != 0 && self.read_fifo.len <= 12 {
+            let Some(block) = self.write_fifo.pop_block() else {
+                break;
+            };
+            let mut block = swap_16(block);
+            match self.control.mode() {
+                Mode::Ctr => self.crypt_block(&mut block),
+                Mode::CcmEncrypt => {
+                    self.update_cbc_mac(&block);
+                    self.crypt_block(&mut block);
+                }
+                Mode::CcmDecrypt => {
+                    self.crypt_block(&mut block);
+                    self.update_cbc_mac(&block);
+                }
+            }
+            self.read_fifo.push_block(swap_16(block));
+            self.remaining_blocks -= 1;
+        }
+        if self.remaining_blocks != 0 {
+            return false;
+        }
+        match self.control.mode() {
+            Mode::Ctr => self.finish(),
+            Mode::CcmEncrypt => {
+                if self.read_fifo.len > 12 {
+                    return false;
+                }
+                let mac = self.final_mac();
+                self.read_fifo.push_block(swap_16(mac));
+                self.finish()
+            }
+            Mode::CcmDecrypt => {
+                if self.control.mac_from_reg() {
+                    self.verify_mac(swap_16(self.mac));
+                    self.finish()
+                } else {
+                    self.awaiting_mac = true;
+                    self.process()
+                }
+            }
+        }
+    }
+
+    fn verify_mac(&mut self, expected: [u8; 16]) {
+        let mac_len = self.control.mac_len();
+        let mac = self.final_mac();
+        self.control
+            .set_mac_verified(mac[..mac_len] == expected[..mac_len]);
+    }
+
+    fn finish(&mut self) -> bool {
+        self.awaiting_mac = false;
+        self.control.set_busy(false);
+        self.control.irq_enabled()
+    }
+}
+
+#[inline]
+fn write_bytes(bytes: &mut [u8; 16], offset: u32, value: u32, mask: u32) {
+    let offset = offset as usize & 0xC;
+    let prev = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    bytes[offset..offset + 4].copy_from_slice(&((prev & !mask) | (value & mask)).to_le_bytes());
+}
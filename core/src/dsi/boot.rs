@@ -0,0 +1,141 @@
+//! Loading of the stage 2 bootloader from the eMMC, in place of the DSi boot ROMs.
+//!
+//! The boot ROMs read a boot info block from the start of the eMMC's boot area, which describes
+//! where the ARM9 and ARM7 binaries of the stage 2 bootloader are stored and which NWRAM layout
+//! they expect; the binaries are decrypted with AES-CTR, using console-independent keys from the
+//! ARM7 boot ROM, and the CPUs then jump straight to their load addresses.
+
+use super::{aes, nand::words_to_bytes, nwram::Nwram};
+use crate::{
+    cpu::{arm7, arm9, bus::CpuAccess, CoreData, Engine},
+    emu::Emu,
+    utils::mem_prelude::*,
+};
+
+/// The size of the DSi ARM7 boot ROM.
+pub const ARM7_BIOS_SIZE: usize = 0x1_0000;
+
+/// The offsets of the AES keys for the ARM9 and ARM7 stage 2 binaries inside the ARM7 boot ROM.
+const KEY_OFFSETS: [usize; 2] = [0xB5D8, 0xB5E8];
+
+/// The eMMC sector holding the boot info block.
+const BOOT_INFO_SECTOR: u32 = 1;
+
+/// An upper bound on the size of each stage 2 binary, to reject corrupted boot info blocks before
+/// reading them; the actual binaries are a few hundred KiB at most.
+const MAX_BINARY_LEN: u32 = 0x10_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage2Error {
+    MissingNand,
+    ReadFailed { sector: u32 },
+    InvalidBootInfo,
+}
+
+#[derive(Clone, Copy)]
+struct BinaryInfo {
+    nand_offset: u32,
+    ram_addr: u32,
+    len: u32,
+}
+
+fn read_binary<E: Engine>(
+    emu: &mut Emu<E>,
+    info: BinaryInfo,
+    key: [u8; 16],
+) -> Result<BoxedByteSlice, Stage2Error> {
+    let start_sector = info.nand_offset >> 9;
+    let end_sector = (info.nand_offset + info.len + 0x1FF) >> 9;
+    let mut data = BoxedByteSlice::new_zeroed(((end_sector - start_sector) as usize) << 9);
+    let mut sector_buffer = Bytes::new([0; 0x200]);
+    let emmc = emu.dsi.sd_mmc.emmc_mut();
+    for (sector, chunk) in (start_sector..end_sector).zip(data.chunks_exact_mut(0x200)) {
+        if !emmc.read_sector_raw(sector, &mut sector_buffer) {
+            return Err(Stage2Error::ReadFailed { sector });
+        }
+        chunk.copy_from_slice(&*sector_buffer);
+    }
+
+    let start = (info.nand_offset & 0x1FF) as usize;
+    let mut binary = BoxedByteSlice::new_zeroed(((info.len + 0xF) & !0xF) as usize);
+    binary[..info.len as usize].copy_from_slice(&data[start..start + info.len as usize]);
+    let counter = words_to_bytes([info.len, info.len.wrapping_neg(), !info.len, 0]);
+    aes::ctr_crypt(key, counter, &mut binary);
+    Ok(binary)
+}
+
+/// Loads the stage 2 bootloader from the eMMC into memory, applying the NWRAM layout it requires,
+/// and sets up both CPUs to start executing it.
+///
+/// # Errors
+/// - [`Stage2Error::MissingNand`]: no NAND image was provided.
+/// - [`Stage2Error::ReadFailed`]: a sector of the boot area couldn't be read.
+/// - [`Stage2Error::InvalidBootInfo`]: the boot info block describes binaries that can't be
+///   loaded, which usually means the NAND image is corrupted or isn't a DSi NAND dump.
+pub(crate) fn load_stage2<E: Engine>(
+    emu: &mut Emu<E>,
+    arm7_bios: &Bytes<ARM7_BIOS_SIZE>,
+) -> Result<(), Stage2Error> {
+    let emmc = emu.dsi.sd_mmc.emmc_mut();
+    if !emmc.is_present() {
+        return Err(Stage2Error::MissingNand);
+    }
+    let mut boot_info = Bytes::new([0; 0x200]);
+    if !emmc.read_sector_raw(BOOT_INFO_SECTOR, &mut boot_info) {
+        return Err(Stage2Error::ReadFailed {
+            sector: BOOT_INFO_SECTOR,
+        });
+    }
+
+    // The ARM9 and ARM7 binaries are described at 0x220 and 0x230 in the eMMC; the size at +0x4
+    // is the unpadded one and unused
+    let [arm9_info, arm7_info] = [0x20, 0x30].map(|base| BinaryInfo {
+        nand_offset: boot_info.read_le::<u32>(base),
+        ram_addr: boot_info.read_le::<u32>(base + 8),
+        len: boot_info.read_le::<u32>(base + 0xC),
+    });
+    for info in [arm9_info, arm7_info] {
+        if info.len == 0
+            || info.len > MAX_BINARY_LEN
+            || info.nand_offset.checked_add(info.len + 0x1FF).is_none()
+            || info.ram_addr.checked_add(info.len).is_none()
+        {
+            return Err(Stage2Error::InvalidBootInfo);
+        }
+    }
+
+    let key = |i: usize| -> [u8; 16] {
+        arm7_bios[KEY_OFFSETS[i]..KEY_OFFSETS[i] + 16]
+            .try_into()
+            .unwrap()
+    };
+    let arm9_binary = read_binary(emu, arm9_info, key(0))?;
+    let arm7_binary = read_binary(emu, arm7_info, key(1))?;
+
+    // The NWRAM layout is stored at 0x380 in the eMMC, with the same format as in DSi cartridge
+    // headers
+    emu.dsi.nwram.setup(
+        &boot_info[0x180..0x194],
+        [0x194, 0x198, 0x19C].map(|offset| boot_info.read_le::<u32>(offset)),
+        [0x1A0, 0x1A4, 0x1A8].map(|offset| boot_info.read_le::<u32>(offset)),
+        boot_info.read_le::<u32>(0x1AC) & 0x00FF_FFFF,
+    );
+    Nwram::recalc(emu);
+
+    for (&byte, addr) in arm9_binary[..arm9_info.len as usize]
+        .iter()
+        .zip(arm9_info.ram_addr..)
+    {
+        arm9::bus::write_8::<CpuAccess, _>(emu, addr, byte);
+    }
+    for (&byte, addr) in arm7_binary[..arm7_info.len as usize]
+        .iter()
+        .zip(arm7_info.ram_addr..)
+    {
+        arm7::bus::write_8::<CpuAccess, _>(emu, addr, byte);
+    }
+
+    E::Arm7Data::setup_direct_boot(emu, arm7_info.ram_addr);
+    E::Arm9Data::setup_direct_boot(emu, arm9_info.ram_addr);
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use super::{
+    aes::{self, KeySlot},
+    sha1::Sha1,
+};
+use crate::utils::Bytes;
+
+/// A source of 512-byte sectors backing one of the DSi's SD/MMC devices, i.e. either the internal
+/// eMMC (holding the system NAND image) or the SD card.
+pub trait NandProvider {
+    fn setup(&mut self) -> bool;
+    fn supports_writes(&self) -> bool;
+    fn sector_count(&self) -> u32;
+    fn read_sector(&mut self, sector: u32, buffer: &mut Bytes<0x200>) -> bool;
+    fn write_sector(&mut self, sector: u32, buffer: &Bytes<0x200>) -> bool;
+}
+
+/// Sectors of the eMMC that hold the stage 2 bootloader, which is encrypted with a different,
+/// console-independent scheme and needs to be passed through untouched; everything else (the MBR
+/// and all partitions) uses the console-unique AES-CTR key.
+const BOOT_AREA_SECTORS: core::ops::Range<u32> = 1..0x877;
+
+const NAND_KEY_Y: [u32; 4] = [0x0AB9_DC76, 0xBD4D_C4D3, 0x202D_DD1D, 0xE1A0_0005];
+
+#[inline]
+pub(super) fn words_to_bytes(words: [u32; 4]) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+/// Returns the X keys the boot ROM places in each of the AES engine's key slots for the console
+/// with the specified ID; slot 0 only gets its fixed first half, as the launcher fills in the rest
+/// for each title, and slot 2 is left for the firmware to set up.
+fn console_key_xs(console_id: u64) -> [Option<[u8; 16]>; 4] {
+    let (id_lo, id_hi) = (console_id as u32, (console_id >> 32) as u32);
+    [
+        Some(words_to_bytes([0x746E_694E, 0x6F64_6E65, 0, 0])),
+        Some(words_to_bytes([
+            0x4E00_004A,
+            0x4A00_004E,
+            id_hi ^ 0xC80C_4B72,
+            id_lo,
+        ])),
+        None,
+        Some(words_to_bytes([
+            id_lo,
+            id_lo ^ 0x24EE_6906,
+            id_hi ^ 0xE65B_601D,
+            id_hi,
+        ])),
+    ]
+}
+
+/// Returns the key slots the DSi boot ROM leaves set up for the console with the specified ID
+/// before jumping to the firmware; slot 3 holds the console-unique eMMC key.
+pub fn console_key_slots(console_id: u64) -> [KeySlot; 4] {
+    let key_xs = console_key_xs(console_id);
+    core::array::from_fn(|i| {
+        let mut slot = KeySlot {
+            normal: [0; 16],
+            x: key_xs[i].unwrap_or([0; 16]),
+            y: [0; 16],
+        };
+        if i == 3 {
+            slot.y = words_to_bytes(NAND_KEY_Y);
+            slot.derive_normal();
+        }
+        slot
+    })
+}
+
+/// Parses the footer no$gba-style NAND dumps end with (`"DSi eMMC CID/CPU"`, followed by the
+/// 16-byte CID and the 8-byte console ID), returning the CID and console ID if present.
+pub fn parse_nocash_footer(footer: &[u8; 0x40]) -> Option<([u8; 16], u64)> {
+    if &footer[..0x10] != b"DSi eMMC CID/CPU" {
+        return None;
+    }
+    let cid = footer[0x10..0x20].try_into().unwrap();
+    let console_id = u64::from_le_bytes(footer[0x20..0x28].try_into().unwrap());
+    Some((cid, console_id))
+}
+
+/// The console-unique AES-CTR encryption used for the eMMC contents, computed with the same cipher
+/// and register conventions as the emulated AES engine; the key is the normal key of slot 3, and
+/// the counter is the SHA-1 hash of the eMMC's CID plus the offset of each 16-byte block.
+#[derive(Clone)]
+pub struct Crypto {
+    key: [u8; 16],
+    base_counter: u128,
+}
+
+impl Crypto {
+    pub fn new(console_id: u64, cid: &[u8; 16]) -> Self {
+        let digest = Sha1::digest(cid);
+        Crypto {
+            key: console_key_slots(console_id)[3].normal,
+            base_counter: u128::from_le_bytes(digest[..16].try_into().unwrap()),
+        }
+    }
+
+    /// Encrypts or decrypts (the two are the same operation for CTR) a whole sector in place.
+    pub fn crypt_sector(&self, sector: u32, buffer: &mut Bytes<0x200>) {
+        let counter = self
+            .base_counter
+            .wrapping_add((sector as u128) << 5)
+            .to_le_bytes();
+        aes::ctr_crypt(self.key, counter, &mut buffer[..]);
+    }
+}
+
+/// Wraps a provider holding a decrypted NAND image, encrypting sectors on the fly with the
+/// console's keys so that the firmware, which decrypts them through the emulated AES engine, sees
+/// the same contents as it would on hardware.
+pub struct DecryptedImage<P: NandProvider> {
+    provider: P,
+    crypto: Crypto,
+}
+
+impl<P: NandProvider> DecryptedImage<P> {
+    pub fn new(provider: P, console_id: u64, cid: &[u8; 16]) -> Self {
+        DecryptedImage {
+            provider,
+            crypto: Crypto::new(console_id, cid),
+        }
+    }
+
+    pub fn into_inner(self) -> P {
+        self.provider
+    }
+}
+
+impl<P: NandProvider> NandProvider for DecryptedImage<P> {
+    fn setup(&mut self) -> bool {
+        self.provider.setup()
+    }
+
+    fn supports_writes(&self) -> bool {
+        self.provider.supports_writes()
+    }
+
+    fn sector_count(&self) -> u32 {
+        self.provider.sector_count()
+    }
+
+    fn read_sector(&mut self, sector: u32, buffer: &mut Bytes<0x200>) -> bool {
+        if !self.provider.read_sector(sector, buffer) {
+            return false;
+        }
+        if !BOOT_AREA_SECTORS.contains(&sector) {
+            self.crypto.crypt_sector(sector, buffer);
+        }
+        true
+    }
+
+    fn write_sector(&mut self, sector: u32, buffer: &Bytes<0x200>) -> bool {
+        if BOOT_AREA_SECTORS.contains(&sector) {
+            return self.provider.write_sector(sector, buffer);
+        }
+        let mut decrypted = buffer.clone();
+        self.crypto.crypt_sector(sector, &mut decrypted);
+        self.provider.write_sector(sector, &decrypted)
+    }
+}
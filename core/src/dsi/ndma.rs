@@ -0,0 +1,232 @@
+use crate::utils::Savestate;
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct Control(pub u32): Debug {
+        pub dst_addr_control: u8 @ 10..=11,
+        pub dst_reload: bool @ 12,
+        pub src_addr_control: u8 @ 13..=14,
+        pub src_reload: bool @ 15,
+        pub block_size_shift: u8 @ 16..=19,
+        pub startup_mode: u8 @ 24..=28,
+        pub repeat: bool @ 29,
+        pub fire_irq: bool @ 30,
+        pub enabled: bool @ 31,
+    }
+}
+
+impl Control {
+    #[inline]
+    pub fn is_immediate(self) -> bool {
+        self.startup_mode() >= startup_mode::IMMEDIATE
+    }
+}
+
+/// Values of the startup mode field of `NDMAxCNT`; modes 0-6 are common to both CPUs, while the
+/// rest differ between the ARM7 and the ARM9.
+pub mod startup_mode {
+    pub const TIMER_0: u8 = 0;
+    pub const DS_SLOT: u8 = 4;
+    pub const DS_SLOT_2: u8 = 5;
+    pub const VBLANK: u8 = 6;
+
+    pub const ARM9_HBLANK: u8 = 7;
+    pub const ARM9_DISPLAY_START: u8 = 8;
+    pub const ARM9_DISPLAY_FIFO: u8 = 9;
+    pub const ARM9_GX_FIFO: u8 = 10;
+    pub const ARM9_CAMERA: u8 = 11;
+
+    pub const ARM7_WIFI: u8 = 7;
+    pub const ARM7_SD_MMC: u8 = 8;
+    pub const ARM7_SDIO: u8 = 9;
+    pub const ARM7_AES_IN: u8 = 10;
+    pub const ARM7_AES_OUT: u8 = 11;
+    pub const ARM7_MIC: u8 = 12;
+
+    pub const IMMEDIATE: u8 = 16;
+}
+
+mod bounded {
+    use crate::utils::{bounded_int_lit, bounded_int_savestate};
+    bounded_int_lit!(pub struct Index(u8), max 3);
+    bounded_int_savestate!(Index(u8));
+}
+pub use bounded::*;
+
+#[derive(Clone, Copy, Savestate)]
+pub struct Channel {
+    pub(crate) src_addr: u32,
+    pub(crate) dst_addr: u32,
+    pub(crate) total_len: u32,
+    pub(crate) block_len: u32,
+    pub(crate) block_interval: u32,
+    pub(crate) fill_data: u32,
+    pub(crate) control: Control,
+    pub(crate) cur_src_addr: u32,
+    pub(crate) cur_dst_addr: u32,
+    pub(crate) remaining_words: u32,
+}
+
+impl Channel {
+    const fn new() -> Self {
+        Channel {
+            src_addr: 0,
+            dst_addr: 0,
+            total_len: 0,
+            block_len: 0,
+            block_interval: 0,
+            fill_data: 0,
+            control: Control(0),
+            cur_src_addr: 0,
+            cur_dst_addr: 0,
+            remaining_words: 0,
+        }
+    }
+
+    #[inline]
+    pub fn src_addr(&self) -> u32 {
+        self.src_addr
+    }
+
+    #[inline]
+    pub fn dst_addr(&self) -> u32 {
+        self.dst_addr
+    }
+
+    #[inline]
+    pub fn total_len(&self) -> u32 {
+        self.total_len
+    }
+
+    #[inline]
+    pub fn block_len(&self) -> u32 {
+        self.block_len
+    }
+
+    #[inline]
+    pub fn block_interval(&self) -> u32 {
+        self.block_interval
+    }
+
+    #[inline]
+    pub fn fill_data(&self) -> u32 {
+        self.fill_data
+    }
+
+    #[inline]
+    pub fn control(&self) -> Control {
+        self.control
+    }
+
+    /// Returns the number of words transferred each time the channel is triggered.
+    #[inline]
+    pub(super) fn block_words(&self) -> u32 {
+        if self.block_len == 0 {
+            0x100_0000
+        } else {
+            self.block_len
+        }
+    }
+
+    #[inline]
+    pub(super) fn src_addr_incr(&self) -> i32 {
+        match self.control.src_addr_control() {
+            0 => 4,
+            1 => -4,
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    pub(super) fn dst_addr_incr(&self) -> i32 {
+        match self.control.dst_addr_control() {
+            0 => 4,
+            1 => -4,
+            _ => 0,
+        }
+    }
+}
+
+/// One of the two sets of four "new" DMA channels, with 32-bit transfers and finer-grained startup
+/// modes.
+#[derive(Savestate)]
+pub struct Ndma {
+    global_control: u32,
+    pub(crate) channels: [Channel; 4],
+}
+
+impl Ndma {
+    pub(super) fn new() -> Self {
+        Ndma {
+            global_control: 0,
+            channels: [Channel::new(); 4],
+        }
+    }
+
+    #[inline]
+    pub fn global_control(&self) -> u32 {
+        self.global_control
+    }
+
+    #[inline]
+    pub fn channels(&self) -> &[Channel; 4] {
+        &self.channels
+    }
+
+    /// Reads from a word-aligned offset relative to `NDMAGCNT`.
+    pub fn read(&self, offset: u32) -> u32 {
+        if offset == 0 {
+            return self.global_control;
+        }
+        let i = ((offset - 4) / 0x1C) as usize;
+        let Some(channel) = self.channels.get(i) else {
+            return 0;
+        };
+        match (offset - 4) % 0x1C {
+            0x00 => channel.src_addr,
+            0x04 => channel.dst_addr,
+            0x08 => channel.total_len,
+            0x0C => channel.block_len,
+            0x10 => channel.block_interval,
+            0x14 => channel.fill_data,
+            _ => channel.control.0,
+        }
+    }
+
+    /// Writes the bits selected by `mask` to a word-aligned offset relative to `NDMAGCNT`,
+    /// returning the index of the channel that just got enabled, if any.
+    pub(super) fn write(&mut self, offset: u32, value: u32, mask: u32) -> Option<Index> {
+        #[inline]
+        fn merge(prev: u32, value: u32, mask: u32, write_mask: u32) -> u32 {
+            (prev & !(mask & write_mask)) | (value & mask & write_mask)
+        }
+
+        if offset == 0 {
+            self.global_control = merge(self.global_control, value, mask, 0x800F_0000);
+            return None;
+        }
+        let i = ((offset - 4) / 0x1C) as usize;
+        let channel = self.channels.get_mut(i)?;
+        match (offset - 4) % 0x1C {
+            0x00 => channel.src_addr = merge(channel.src_addr, value, mask, 0xFFFF_FFFC),
+            0x04 => channel.dst_addr = merge(channel.dst_addr, value, mask, 0xFFFF_FFFC),
+            0x08 => channel.total_len = merge(channel.total_len, value, mask, 0x0FFF_FFFF),
+            0x0C => channel.block_len = merge(channel.block_len, value, mask, 0x00FF_FFFF),
+            0x10 => {
+                channel.block_interval = merge(channel.block_interval, value, mask, 0x0003_FFFF);
+            }
+            0x14 => channel.fill_data = merge(channel.fill_data, value, mask, 0xFFFF_FFFF),
+            _ => {
+                let prev = channel.control;
+                channel.control = Control(merge(channel.control.0, value, mask, 0xFF0F_FC00));
+                if !prev.enabled() && channel.control.enabled() {
+                    channel.cur_src_addr = channel.src_addr;
+                    channel.cur_dst_addr = channel.dst_addr;
+                    channel.remaining_words = channel.total_len;
+                    return Some(Index::new(i as u8));
+                }
+            }
+        }
+        None
+    }
+}
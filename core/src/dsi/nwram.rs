@@ -0,0 +1,341 @@
+use crate::{
+    cpu::{arm7, arm9, Engine},
+    ds_slot::rom::header::DsiHeader,
+    emu::Emu,
+    utils::{OwnedBytesCellPtr, Savestate},
+    Model,
+};
+
+// TODO:
+// - Unassigned slots inside a window should read as zero, currently the underlying shared WRAM
+//   mapping is left visible
+// - The fallback bus paths used with the `bft-r`/`bft-w` features don't see NWRAM at all
+// - Slots mapped to the DSP aren't accessible to anything, as the DSP isn't emulated
+
+/// The CPU (or DSP memory) a NWRAM slot is currently assigned to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Master {
+    Arm9,
+    Arm7,
+    Dsp,
+}
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct SlotControl(pub u8): Debug {
+        pub raw_master: u8 @ 0..=1,
+        pub offset: u8 @ 2..=4,
+        pub enabled: bool @ 7,
+    }
+}
+
+impl SlotControl {
+    #[inline]
+    pub fn master(self) -> Master {
+        match self.raw_master() {
+            0 => Master::Arm9,
+            1 => Master::Arm7,
+            _ => Master::Dsp,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Bank {
+    A,
+    B,
+    C,
+}
+
+impl Bank {
+    const ALL: [Bank; 3] = [Bank::A, Bank::B, Bank::C];
+
+    #[inline]
+    fn slot_shift(self) -> u32 {
+        match self {
+            Bank::A => 16,
+            Bank::B | Bank::C => 15,
+        }
+    }
+
+    #[inline]
+    fn slots(self) -> usize {
+        match self {
+            Bank::A => 4,
+            Bank::B | Bank::C => 8,
+        }
+    }
+
+    #[inline]
+    fn slot_control_mask(self) -> u8 {
+        match self {
+            Bank::A => 0x8D,
+            Bank::B | Bank::C => 0x9F,
+        }
+    }
+}
+
+/// The DSi's new shared WRAM: three banks (A, B and C) of 256 KiB each, split into slots that can
+/// be individually assigned to either CPU (or the DSP) and mapped through per-CPU windows in the
+/// 0x0300_0000 region.
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct Nwram {
+    a: OwnedBytesCellPtr<0x4_0000>,
+    b: OwnedBytesCellPtr<0x4_0000>,
+    c: OwnedBytesCellPtr<0x4_0000>,
+    slot_control: [[SlotControl; 8]; 3],
+    windows_9: [u32; 3],
+    windows_7: [u32; 3],
+    write_protect: u32,
+}
+
+impl Nwram {
+    pub(super) fn new() -> Self {
+        Nwram {
+            a: OwnedBytesCellPtr::new_zeroed(),
+            b: OwnedBytesCellPtr::new_zeroed(),
+            c: OwnedBytesCellPtr::new_zeroed(),
+            slot_control: [[SlotControl(0); 8]; 3],
+            windows_9: [0; 3],
+            windows_7: [0; 3],
+            write_protect: 0,
+        }
+    }
+
+    #[inline]
+    pub fn a(&self) -> &OwnedBytesCellPtr<0x4_0000> {
+        &self.a
+    }
+
+    #[inline]
+    pub fn b(&self) -> &OwnedBytesCellPtr<0x4_0000> {
+        &self.b
+    }
+
+    #[inline]
+    pub fn c(&self) -> &OwnedBytesCellPtr<0x4_0000> {
+        &self.c
+    }
+
+    #[inline]
+    fn bank_contents(&self, bank: Bank) -> &OwnedBytesCellPtr<0x4_0000> {
+        match bank {
+            Bank::A => &self.a,
+            Bank::B => &self.b,
+            Bank::C => &self.c,
+        }
+    }
+
+    #[inline]
+    pub fn slot_control(&self) -> &[[SlotControl; 8]; 3] {
+        &self.slot_control
+    }
+
+    #[inline]
+    pub fn windows_9(&self) -> [u32; 3] {
+        self.windows_9
+    }
+
+    #[inline]
+    pub fn windows_7(&self) -> [u32; 3] {
+        self.windows_7
+    }
+
+    #[inline]
+    pub fn write_protect(&self) -> u32 {
+        self.write_protect
+    }
+
+    /// Reads from `MBK1`-`MBK5`, laid out as one byte per slot starting from bank A's slot 0.
+    #[inline]
+    pub fn read_slot_control(&self, i: usize) -> u8 {
+        match i {
+            0..=3 => self.slot_control[0][i].0,
+            4..=11 => self.slot_control[1][i - 4].0,
+            12..=19 => self.slot_control[2][i - 12].0,
+            _ => 0,
+        }
+    }
+
+    /// Writes to `MBK1`-`MBK5`, returning whether the memory map needs to be recalculated; slots
+    /// write-protected through `MBK9` are left untouched.
+    pub(super) fn write_slot_control(&mut self, i: usize, value: u8) -> bool {
+        let (bank, slot) = match i {
+            0..=3 => (Bank::A, i),
+            4..=11 => (Bank::B, i - 4),
+            12..=19 => (Bank::C, i - 12),
+            _ => return false,
+        };
+        let protect_bit = match bank {
+            Bank::A => slot,
+            Bank::B => 8 + slot,
+            Bank::C => 16 + slot,
+        };
+        if self.write_protect & 1 << protect_bit != 0 {
+            return false;
+        }
+        let new_value = SlotControl(value & bank.slot_control_mask());
+        let control = &mut self.slot_control[bank as usize][slot];
+        if *control == new_value {
+            return false;
+        }
+        *control = new_value;
+        true
+    }
+
+    #[inline]
+    pub(super) fn write_window_9(&mut self, i: usize, value: u32) -> bool {
+        let new_value = value & Self::window_mask(Bank::ALL[i]);
+        let changed = self.windows_9[i] != new_value;
+        self.windows_9[i] = new_value;
+        changed
+    }
+
+    #[inline]
+    pub(super) fn write_window_7(&mut self, i: usize, value: u32) -> bool {
+        let new_value = value & Self::window_mask(Bank::ALL[i]);
+        let changed = self.windows_7[i] != new_value;
+        self.windows_7[i] = new_value;
+        changed
+    }
+
+    #[inline]
+    pub(super) fn write_write_protect(&mut self, value: u32) {
+        self.write_protect = value & 0x00FF_FF0F;
+    }
+
+    /// Applies the NWRAM configuration stored in a DSi cartridge header, as done by the launcher
+    /// before starting a DSi-enhanced title.
+    pub(crate) fn setup_from_header(&mut self, header: &DsiHeader) {
+        self.setup(
+            header.mbk_slot_control(),
+            header.arm9_mbk_windows(),
+            header.arm7_mbk_windows(),
+            header.mbk_write_protect(),
+        );
+    }
+
+    /// Applies a full NWRAM configuration (`MBK1`-`MBK5` slot control bytes, `MBK6`-`MBK8` for each
+    /// CPU and `MBK9`), unlocking all slots first.
+    pub(super) fn setup(
+        &mut self,
+        slot_control: &[u8],
+        windows_9: [u32; 3],
+        windows_7: [u32; 3],
+        write_protect: u32,
+    ) {
+        self.write_protect = 0;
+        for (i, &value) in slot_control.iter().enumerate() {
+            self.write_slot_control(i, value);
+        }
+        for (i, (window_9, window_7)) in windows_9.into_iter().zip(windows_7).enumerate() {
+            self.write_window_9(i, window_9);
+            self.write_window_7(i, window_7);
+        }
+        self.write_write_protect(write_protect);
+    }
+
+    #[inline]
+    fn window_mask(bank: Bank) -> u32 {
+        match bank {
+            Bank::A => 0x1FF0_3FF0,
+            Bank::B | Bank::C => 0x0FF8_3FF8,
+        }
+    }
+
+    /// Returns the `(start, end, image slot count)` triple described by a `MBK6`-`MBK8` value; the
+    /// range is empty if `start >= end`.
+    fn decode_window(bank: Bank, value: u32) -> (u32, u32, usize) {
+        let image_slots = match bank {
+            Bank::A => [1, 1, 2, 4][(value >> 12 & 3) as usize],
+            Bank::B | Bank::C => [1, 2, 4, 8][(value >> 12 & 3) as usize],
+        };
+        let (start, end) = match bank {
+            Bank::A => (value >> 4 & 0xFF, value >> 20 & 0x1FF),
+            Bank::B | Bank::C => (value >> 3 & 0x1FF, value >> 19 & 0x1FF),
+        };
+        let shift = bank.slot_shift();
+        (
+            0x0300_0000 + (start << shift),
+            0x0300_0000 + (end << shift),
+            image_slots,
+        )
+    }
+
+    /// Returns a pointer to the slot mapped to the specified chunk of a CPU's window, if any.
+    fn chunk_ptr(
+        &self,
+        bank: Bank,
+        master: Master,
+        image_slots: usize,
+        image_index: usize,
+    ) -> Option<*mut u8> {
+        self.slot_control[bank as usize][..bank.slots()]
+            .iter()
+            .position(|control| {
+                control.enabled()
+                    && control.master() == master
+                    && control.offset() as usize % image_slots == image_index
+            })
+            .map(|slot| {
+                self.bank_contents(bank)
+                    .as_mut_ptr()
+                    .wrapping_add(slot << bank.slot_shift())
+            })
+    }
+
+    /// Recalculates the NWRAM mappings for both CPUs on top of the shared WRAM ones; needs to be
+    /// called whenever `WRAMCNT` or `MBK1`-`MBK8` change.
+    pub(crate) fn recalc<E: Engine>(emu: &mut Emu<E>) {
+        if emu.model() != Model::Dsi {
+            return;
+        }
+        emu.swram.recalc(&mut emu.arm7, &mut emu.arm9);
+
+        for bank in Bank::ALL {
+            let slot_size = 1_u32 << bank.slot_shift();
+
+            let (start, end, image_slots) =
+                Self::decode_window(bank, emu.dsi.nwram.windows_9[bank as usize]);
+            for addr in (start..end.min(0x0400_0000)).step_by(slot_size as usize) {
+                let image_index = ((addr - start) >> bank.slot_shift()) as usize % image_slots;
+                if let Some(ptr) =
+                    emu.dsi
+                        .nwram
+                        .chunk_ptr(bank, Master::Arm9, image_slots, image_index)
+                {
+                    unsafe {
+                        emu.arm9.map_sys_bus_ptr_range(
+                            arm9::bus::ptrs::mask::ALL,
+                            ptr,
+                            slot_size as usize,
+                            (addr, addr + slot_size - 1),
+                        );
+                    }
+                }
+            }
+
+            // TODO: Check whether ARM7 windows can overlap ARM7 WRAM
+            let (start, end, image_slots) =
+                Self::decode_window(bank, emu.dsi.nwram.windows_7[bank as usize]);
+            for addr in (start..end.min(0x0380_0000)).step_by(slot_size as usize) {
+                let image_index = ((addr - start) >> bank.slot_shift()) as usize % image_slots;
+                if let Some(ptr) =
+                    emu.dsi
+                        .nwram
+                        .chunk_ptr(bank, Master::Arm7, image_slots, image_index)
+                {
+                    unsafe {
+                        emu.arm7.map_sys_bus_ptr_range(
+                            arm7::bus::ptrs::mask::ALL,
+                            ptr,
+                            slot_size as usize,
+                            (addr, addr + slot_size - 1),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
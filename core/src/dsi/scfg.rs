@@ -0,0 +1,245 @@
+use crate::utils::Savestate;
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct Ext9(pub u32): Debug {
+        pub revised_dma: bool @ 0,
+        pub revised_geometry: bool @ 1,
+        pub revised_renderer: bool @ 2,
+        pub revised_2d_engine: bool @ 3,
+        pub revised_div_engine: bool @ 4,
+        pub revised_ds_slot: bool @ 7,
+        pub ext_irqs: bool @ 8,
+        pub ext_lcd: bool @ 12,
+        pub ext_vram: bool @ 13,
+        pub main_mem_limit: u8 @ 14..=15,
+        pub ndma: bool @ 16,
+        pub camera: bool @ 17,
+        pub dsp: bool @ 18,
+        pub ds_slot_2: bool @ 24,
+        pub nwram_regs: bool @ 25,
+        pub scfg_regs: bool @ 31,
+    }
+}
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct Ext7(pub u32): Debug {
+        pub revised_dma: bool @ 0,
+        pub revised_sound_dma: bool @ 1,
+        pub revised_sound: bool @ 2,
+        pub revised_ds_slot: bool @ 7,
+        pub ext_irqs: bool @ 8,
+        pub ext_spi_clock: bool @ 9,
+        pub ext_sound_dma: bool @ 10,
+        pub ndma: bool @ 16,
+        pub aes: bool @ 17,
+        pub sd_mmc: bool @ 18,
+        pub sdio: bool @ 19,
+        pub mic: bool @ 20,
+        pub sndexcnt: bool @ 21,
+        pub i2c: bool @ 22,
+        pub gpio: bool @ 23,
+        pub ds_slot_2: bool @ 24,
+        pub nwram_regs: bool @ 25,
+        pub scfg_regs: bool @ 31,
+    }
+}
+
+impl Ext9 {
+    /// Returns the size of the main memory region visible to both CPUs, as selected by the RAM
+    /// limit bits (retail units only ship with 16 MiB, so the 32 MiB setting is clamped).
+    #[inline]
+    pub fn main_mem_size(self) -> u32 {
+        if self.main_mem_limit() == 0 {
+            0x40_0000
+        } else {
+            0x100_0000
+        }
+    }
+}
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct RomControl(pub u16): Debug {
+        pub arm9_ds_bios: bool @ 0,
+        pub arm9_rom_disabled: bool @ 1,
+        pub arm7_ds_bios: bool @ 8,
+        pub arm7_rom_disabled: bool @ 9,
+        pub console_id_disabled: bool @ 10,
+    }
+}
+
+/// The DSi system configuration registers, controlling which of the new hardware features are
+/// visible to each CPU.
+#[derive(Savestate)]
+pub struct Scfg {
+    rom_control: RomControl,
+    clock_9: u16,
+    clock_7: u16,
+    reset_9: u16,
+    jtag_7: u16,
+    ext_9: Ext9,
+    ext_7: Ext7,
+    mem_card_control: u16,
+    mem_card_insert_delay: u16,
+    mem_card_power_off_delay: u16,
+    wifi_control: u16,
+}
+
+impl Scfg {
+    pub(super) fn new() -> Self {
+        Scfg {
+            rom_control: RomControl(0),
+            clock_9: 0x0080,
+            clock_7: 0x0187,
+            reset_9: 0,
+            jtag_7: 0,
+            ext_9: Ext9(0x8307_F100),
+            ext_7: Ext7(0x93FF_FB06),
+            mem_card_control: 0x0010,
+            mem_card_insert_delay: 0x1988,
+            mem_card_power_off_delay: 0x264C,
+            wifi_control: 0,
+        }
+    }
+
+    #[inline]
+    pub fn rom_control(&self) -> RomControl {
+        self.rom_control
+    }
+
+    /// Writes to `SCFG_ROM`; each of the bits can only be set once and stays set until the next
+    /// reset.
+    #[inline]
+    pub fn write_rom_control(&mut self, value: RomControl) {
+        self.rom_control.0 |= value.0 & 0x0703;
+    }
+
+    #[inline]
+    pub fn clock_9(&self) -> u16 {
+        self.clock_9
+    }
+
+    // TODO: Actually run the ARM9 at 134 MHz when bit 0 is set
+    #[inline]
+    pub fn write_clock_9(&mut self, value: u16) {
+        self.clock_9 = (self.clock_9 & 0xFF00) | (value & 0x0187);
+    }
+
+    #[inline]
+    pub fn clock_7(&self) -> u16 {
+        self.clock_7
+    }
+
+    #[inline]
+    pub fn write_clock_7(&mut self, value: u16) {
+        self.clock_7 = value & 0x0187;
+    }
+
+    #[inline]
+    pub fn reset_9(&self) -> u16 {
+        self.reset_9
+    }
+
+    #[inline]
+    pub fn write_reset_9(&mut self, value: u16) {
+        self.reset_9 = value & 1;
+    }
+
+    #[inline]
+    pub fn jtag_7(&self) -> u16 {
+        self.jtag_7
+    }
+
+    #[inline]
+    pub fn write_jtag_7(&mut self, value: u16) {
+        self.jtag_7 = value & 0x0301;
+    }
+
+    #[inline]
+    pub fn ext_9(&self) -> Ext9 {
+        self.ext_9
+    }
+
+    /// Writes to `SCFG_EXT9`, returning the previous value; the caller is responsible for applying
+    /// the side effects of the changed bits.
+    #[inline]
+    pub(super) fn write_ext_9(&mut self, value: Ext9) -> Ext9 {
+        let prev = self.ext_9;
+        // The SCFG/MBK access bit can only be cleared
+        self.ext_9.0 = (value.0 & 0x8307_F19F) & (prev.0 | !(1 << 31));
+        prev
+    }
+
+    #[inline]
+    pub fn ext_7(&self) -> Ext7 {
+        self.ext_7
+    }
+
+    /// Writes to `SCFG_EXT7`, returning the previous value; the caller is responsible for applying
+    /// the side effects of the changed bits.
+    #[inline]
+    pub(super) fn write_ext_7(&mut self, value: Ext7) -> Ext7 {
+        let prev = self.ext_7;
+        self.ext_7.0 = (value.0 & 0x93FF_FF87) & (prev.0 | !(1 << 31));
+        prev
+    }
+
+    #[inline]
+    pub fn mem_card_control(&self) -> u16 {
+        self.mem_card_control
+    }
+
+    // TODO: Emulate the DS slot power sequencing (bits 2-3)
+    #[inline]
+    pub fn write_mem_card_control(&mut self, value: u16) {
+        self.mem_card_control = (self.mem_card_control & 0x0011) | (value & 0x800C);
+    }
+
+    #[inline]
+    pub fn mem_card_insert_delay(&self) -> u16 {
+        self.mem_card_insert_delay
+    }
+
+    #[inline]
+    pub fn write_mem_card_insert_delay(&mut self, value: u16) {
+        self.mem_card_insert_delay = value;
+    }
+
+    #[inline]
+    pub fn mem_card_power_off_delay(&self) -> u16 {
+        self.mem_card_power_off_delay
+    }
+
+    #[inline]
+    pub fn write_mem_card_power_off_delay(&mut self, value: u16) {
+        self.mem_card_power_off_delay = value;
+    }
+
+    #[inline]
+    pub fn wifi_control(&self) -> u16 {
+        self.wifi_control
+    }
+
+    #[inline]
+    pub fn write_wifi_control(&mut self, value: u16) {
+        self.wifi_control = value & 1;
+    }
+
+    /// Sets up the registers the way the DSi boot ROM/launcher leaves them before starting a
+    /// title, depending on whether it's started in DSi or DS mode.
+    pub(super) fn setup_for_boot(&mut self, dsi_mode: bool) {
+        if dsi_mode {
+            self.rom_control = RomControl(0x0001);
+            self.ext_9 = Ext9(0x8307_F100);
+            self.ext_7 = Ext7(0x93FF_FB06);
+        } else {
+            self.rom_control = RomControl(0x0703);
+            self.clock_9 = 0x0080;
+            self.clock_7 = 0x0180;
+            self.ext_9 = Ext9(0x0300_0000);
+            self.ext_7 = Ext7(0x12A0_0000);
+        }
+    }
+}
@@ -0,0 +1,1063 @@
+use super::nand::NandProvider;
+use crate::utils::{Bytes, Savestate};
+
+// TODO:
+// - Commands and data transfers complete instantly, card/bus timings aren't emulated
+// - Only the subset of the SD/MMC command set used by the DSi firmware and common homebrew is
+//   implemented; locking, erase and security (CPRM) commands aren't supported
+// - The second controller (SDIO, at 0x0400_4A00), used for the DSi's Wi-Fi module, isn't emulated
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct Command(pub u16): Debug {
+        pub index: u8 @ 0..=5,
+        pub is_app_cmd: bool @ 6,
+        pub raw_response_type: u8 @ 8..=10,
+        pub has_data: bool @ 11,
+        pub is_read: bool @ 12,
+        pub is_multi_block: bool @ 13,
+        pub is_security_cmd: bool @ 14,
+    }
+}
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct IrqFlags(pub u32): Debug {
+        pub response_end: bool @ 0,
+        pub data_end: bool @ 2,
+        pub card_removed: bool @ 3,
+        pub card_inserted: bool @ 4,
+        pub card_present: bool @ 5,
+        pub write_unprotected: bool @ 7,
+        pub cmd_index_error: bool @ 16,
+        pub crc_error: bool @ 17,
+        pub end_bit_error: bool @ 18,
+        pub data_timeout: bool @ 19,
+        pub rx_overflow: bool @ 20,
+        pub tx_underrun: bool @ 21,
+        pub cmd_timeout: bool @ 22,
+        pub rx_ready: bool @ 24,
+        pub tx_request: bool @ 25,
+        pub cmd_ready: bool @ 29,
+        pub cmd_busy: bool @ 30,
+        pub illegal_access: bool @ 31,
+    }
+}
+
+/// Bits of `SD_IRQ_STATUS` that are acknowledged by writing 0 to them; the rest reflect the
+/// current state of the controller and the selected card.
+const IRQ_STATUS_ACK_MASK: u32 = 0x837F_001D;
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct Data32Control(pub u16): Debug {
+        pub enabled: bool @ 1,
+        pub rx_ready: bool @ 8,
+        pub tx_request: bool @ 9,
+        pub clear_fifo: bool @ 10,
+        pub rx_ready_irq_enabled: bool @ 11,
+        pub tx_request_irq_enabled: bool @ 12,
+    }
+}
+
+/// The two devices connected to the DSi's SD/MMC controller, selected through `SD_PORTSEL`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Savestate)]
+pub enum Port {
+    SdCard,
+    Emmc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Savestate)]
+pub enum CardKind {
+    Sd,
+    Mmc,
+}
+
+/// The states of the SD/MMC card state machine, numbered as in the `CURRENT_STATE` field of the
+/// card status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Savestate)]
+pub enum CardState {
+    Idle,
+    Ready,
+    Ident,
+    Standby,
+    Transfer,
+    Data,
+    Receive,
+}
+
+mod card_status {
+    pub const APP_CMD: u32 = 1 << 5;
+    pub const READY_FOR_DATA: u32 = 1 << 8;
+    pub const ILLEGAL_COMMAND: u32 = 1 << 22;
+    pub const BLOCK_LEN_ERROR: u32 = 1 << 29;
+    pub const WP_VIOLATION: u32 = 1 << 26;
+    pub const ADDRESS_ERROR: u32 = 1 << 30;
+    pub const OUT_OF_RANGE: u32 = 1 << 31;
+    pub const CLEARED_ON_READ: u32 = 0xFDF9_0008;
+}
+
+enum Response {
+    None,
+    Short(u32),
+    Long([u8; 16]),
+}
+
+enum DataTransfer {
+    None,
+    ReadSectors(u32),
+    WriteSectors(u32),
+    /// A register-like data block that the card places directly into the FIFO, with the specified
+    /// length.
+    ReadBuffer(u16),
+}
+
+/// An SD card or eMMC device, backed by a [`NandProvider`].
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct Card {
+    #[savestate(skip)]
+    provider: Option<Box<dyn NandProvider>>,
+    #[savestate(skip)]
+    kind: CardKind,
+    #[savestate(skip)]
+    cid: [u8; 16],
+    state: CardState,
+    rca: u16,
+    status: u32,
+    block_len: u32,
+    wide_bus: bool,
+}
+
+impl Card {
+    fn new(provider: Option<Box<dyn NandProvider>>, kind: CardKind, cid: [u8; 16]) -> Self {
+        let provider = provider.and_then(|mut provider| provider.setup().then_some(provider));
+        Card {
+            provider,
+            kind,
+            cid,
+            state: CardState::Idle,
+            rca: 0,
+            status: 0,
+            block_len: 0x200,
+            wide_bus: false,
+        }
+    }
+
+    #[inline]
+    pub fn kind(&self) -> CardKind {
+        self.kind
+    }
+
+    #[inline]
+    pub fn cid(&self) -> [u8; 16] {
+        self.cid
+    }
+
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        self.provider
+            .as_ref()
+            .is_some_and(|provider| provider.supports_writes())
+    }
+
+    #[inline]
+    pub fn state(&self) -> CardState {
+        self.state
+    }
+
+    #[inline]
+    pub fn rca(&self) -> u16 {
+        self.rca
+    }
+
+    #[inline]
+    pub fn wide_bus(&self) -> bool {
+        self.wide_bus
+    }
+
+    #[inline]
+    fn sector_count(&self) -> u32 {
+        self.provider
+            .as_ref()
+            .map_or(0, |provider| provider.sector_count())
+    }
+
+    /// Returns whether the card uses block addressing (SDHC/SDXC cards and eMMC devices over 2 GiB)
+    /// instead of byte addressing.
+    #[inline]
+    fn is_high_capacity(&self) -> bool {
+        self.sector_count() > 0x40_0000
+    }
+
+    #[inline]
+    fn ocr(&self) -> u32 {
+        0x80FF_8000 | (self.is_high_capacity() as u32) << 30
+    }
+
+    fn card_status(&mut self) -> u32 {
+        let status = self.status | (self.state as u32) << 9 | card_status::READY_FOR_DATA;
+        self.status &= !card_status::CLEARED_ON_READ;
+        status
+    }
+
+    fn csd(&self) -> [u8; 16] {
+        let sectors = self.sector_count() as u128;
+        let mut csd: u128 = 0x32 << 96 | 9 << 80 | 1;
+        if self.kind == CardKind::Sd && self.is_high_capacity() {
+            csd |= 1 << 126 | 0x5B5 << 84 | ((sectors >> 10).saturating_sub(1) & 0x3F_FFFF) << 48;
+        } else {
+            csd |= match self.kind {
+                CardKind::Sd => 0x5B5 << 84,
+                CardKind::Mmc => 2 << 126 | 4 << 122 | 0xF5 << 84,
+            };
+            // Find the smallest block length and size multiplier that can describe the card's
+            // capacity with a 12-bit block count
+            let bytes = sectors << 9;
+            let (read_bl_len, c_size_mult) = (9_u32..=11)
+                .flat_map(|read_bl_len| {
+                    (0_u32..=7).map(move |c_size_mult| (read_bl_len, c_size_mult))
+                })
+                .find(|&(read_bl_len, c_size_mult)| {
+                    bytes >> (read_bl_len + c_size_mult + 2) <= 0x1000
+                })
+                .unwrap_or((11, 7));
+            let c_size = (bytes >> (read_bl_len + c_size_mult + 2)).saturating_sub(1) & 0xFFF;
+            csd = (csd & !(0xF << 80))
+                | (read_bl_len as u128) << 80
+                | c_size << 62
+                | (c_size_mult as u128) << 47;
+        }
+        (csd >> 8).to_le_bytes()
+    }
+
+    fn ext_csd(&self, buffer: &mut Bytes<0x200>) {
+        buffer.fill(0);
+        // SEC_COUNT
+        buffer.write_le(212, self.sector_count());
+        // CSD_STRUCTURE, EXT_CSD_REV
+        buffer[194] = 2;
+        buffer[192] = 5;
+        // BUS_WIDTH
+        buffer[183] = self.wide_bus as u8;
+    }
+
+    /// Converts a data address argument to a sector index, according to the card's addressing
+    /// mode.
+    #[inline]
+    fn sector_from_arg(&self, arg: u32) -> u32 {
+        if self.is_high_capacity() {
+            arg
+        } else {
+            arg >> 9
+        }
+    }
+
+    /// Reads a sector straight from the provider, bypassing the card's state and status bits.
+    pub(super) fn read_sector_raw(&mut self, sector: u32, buffer: &mut Bytes<0x200>) -> bool {
+        sector < self.sector_count()
+            && self
+                .provider
+                .as_mut()
+                .is_some_and(|provider| provider.read_sector(sector, buffer))
+    }
+
+    fn read_sector(&mut self, sector: u32, buffer: &mut Bytes<0x200>) {
+        if !self.read_sector_raw(sector, buffer) {
+            buffer.fill(0);
+            self.status |= card_status::OUT_OF_RANGE;
+        }
+    }
+
+    fn write_sector(&mut self, sector: u32, buffer: &Bytes<0x200>) {
+        if !self.is_writable() {
+            self.status |= card_status::WP_VIOLATION;
+            return;
+        }
+        let result = sector < self.sector_count()
+            && self
+                .provider
+                .as_mut()
+                .is_some_and(|provider| provider.write_sector(sector, buffer));
+        if !result {
+            self.status |= card_status::OUT_OF_RANGE;
+        }
+    }
+
+    /// Handles a command sent by the host, returning `None` if the card doesn't respond (i.e. the
+    /// command times out).
+    fn handle_command(
+        &mut self,
+        index: u8,
+        arg: u32,
+        buffer: &mut Bytes<0x200>,
+    ) -> Option<(Response, DataTransfer)> {
+        if !self.is_present() {
+            return None;
+        }
+
+        let is_app_cmd = self.status & card_status::APP_CMD != 0;
+        self.status &= !card_status::APP_CMD;
+        let addressed = arg >> 16 == self.rca as u32;
+
+        let result = match (index, is_app_cmd, self.kind) {
+            (0, ..) => {
+                self.state = CardState::Idle;
+                self.rca = 0;
+                self.wide_bus = false;
+                self.block_len = 0x200;
+                (Response::None, DataTransfer::None)
+            }
+
+            (1, _, CardKind::Mmc) => {
+                if self.state != CardState::Idle {
+                    return None;
+                }
+                self.state = CardState::Ready;
+                (Response::Short(self.ocr()), DataTransfer::None)
+            }
+
+            (41, true, CardKind::Sd) => {
+                if self.state != CardState::Idle {
+                    return None;
+                }
+                // Only leave the idle state once the host actually reports supported voltages,
+                // probing requests with an empty voltage window are answered with the OCR alone
+                if arg & 0x00FF_8000 == 0 {
+                    return Some((Response::Short(self.ocr() & !(1 << 31)), DataTransfer::None));
+                }
+                self.state = CardState::Ready;
+                (Response::Short(self.ocr()), DataTransfer::None)
+            }
+
+            (2, ..) => {
+                if self.state != CardState::Ready {
+                    return None;
+                }
+                self.state = CardState::Ident;
+                (Response::Long(self.cid), DataTransfer::None)
+            }
+
+            (3, _, CardKind::Sd) => {
+                if !matches!(self.state, CardState::Ident | CardState::Standby) {
+                    return None;
+                }
+                self.state = CardState::Standby;
+                self.rca = self.rca.wrapping_add(1).max(1);
+                let status = self.card_status();
+                (
+                    Response::Short(
+                        (self.rca as u32) << 16
+                            | (status >> 8 & 0xC000)
+                            | (status >> 6 & 0x2000)
+                            | (status & 0x1FFF),
+                    ),
+                    DataTransfer::None,
+                )
+            }
+
+            (3, _, CardKind::Mmc) => {
+                if self.state != CardState::Ident {
+                    return None;
+                }
+                self.state = CardState::Standby;
+                self.rca = (arg >> 16) as u16;
+                (Response::Short(self.card_status()), DataTransfer::None)
+            }
+
+            (6, true, CardKind::Sd) => {
+                self.wide_bus = arg & 3 == 2;
+                (Response::Short(self.card_status()), DataTransfer::None)
+            }
+
+            (6, false, CardKind::Sd) => {
+                // SWITCH_FUNC: report the default function of every group as the only supported
+                // one
+                buffer[..64].fill(0);
+                buffer[1] = 1;
+                for group in 0..6 {
+                    buffer[13 - (group << 1)] = 1;
+                }
+                (
+                    Response::Short(self.card_status()),
+                    DataTransfer::ReadBuffer(64),
+                )
+            }
+
+            (6, _, CardKind::Mmc) => {
+                // SWITCH: only the BUS_WIDTH field of EXT_CSD is tracked
+                if arg >> 24 & 3 == 3 && arg >> 16 & 0xFF == 183 {
+                    self.wide_bus = arg >> 8 & 0xFF != 0;
+                }
+                (Response::Short(self.card_status()), DataTransfer::None)
+            }
+
+            (7, ..) => {
+                if addressed && self.rca != 0 {
+                    let status = self.card_status();
+                    self.state = CardState::Transfer;
+                    (Response::Short(status), DataTransfer::None)
+                } else {
+                    if self.state == CardState::Transfer {
+                        self.state = CardState::Standby;
+                    }
+                    (Response::None, DataTransfer::None)
+                }
+            }
+
+            (8, _, CardKind::Sd) => (Response::Short(arg & 0xFFF), DataTransfer::None),
+
+            (8, _, CardKind::Mmc) => {
+                self.ext_csd(buffer);
+                self.state = CardState::Data;
+                (
+                    Response::Short(self.card_status()),
+                    DataTransfer::ReadBuffer(0x200),
+                )
+            }
+
+            (9, ..) => {
+                if !addressed {
+                    return None;
+                }
+                (Response::Long(self.csd()), DataTransfer::None)
+            }
+
+            (10, ..) => {
+                if !addressed {
+                    return None;
+                }
+                (Response::Long(self.cid), DataTransfer::None)
+            }
+
+            (12, ..) => {
+                let status = self.card_status();
+                if matches!(self.state, CardState::Data | CardState::Receive) {
+                    self.state = CardState::Transfer;
+                }
+                (Response::Short(status), DataTransfer::None)
+            }
+
+            (13, true, CardKind::Sd) => {
+                buffer[..64].fill(0);
+                buffer[0] = (self.wide_bus as u8) << 7;
+                self.state = CardState::Data;
+                (
+                    Response::Short(self.card_status()),
+                    DataTransfer::ReadBuffer(64),
+                )
+            }
+
+            (13, ..) => {
+                if !addressed {
+                    return None;
+                }
+                (Response::Short(self.card_status()), DataTransfer::None)
+            }
+
+            (16, ..) => {
+                if arg == 0 || arg > 0x200 {
+                    self.status |= card_status::BLOCK_LEN_ERROR;
+                } else {
+                    self.block_len = arg;
+                }
+                (Response::Short(self.card_status()), DataTransfer::None)
+            }
+
+            (17 | 18, ..) => {
+                let status = self.card_status();
+                if self.state != CardState::Transfer {
+                    self.status |= card_status::ILLEGAL_COMMAND;
+                    return Some((Response::Short(status), DataTransfer::None));
+                }
+                if !self.is_high_capacity() && arg & 0x1FF != 0 {
+                    self.status |= card_status::ADDRESS_ERROR;
+                    return Some((Response::Short(status), DataTransfer::None));
+                }
+                self.state = CardState::Data;
+                (
+                    Response::Short(status),
+                    DataTransfer::ReadSectors(self.sector_from_arg(arg)),
+                )
+            }
+
+            (24 | 25, ..) => {
+                let status = self.card_status();
+                if self.state != CardState::Transfer {
+                    self.status |= card_status::ILLEGAL_COMMAND;
+                    return Some((Response::Short(status), DataTransfer::None));
+                }
+                if !self.is_high_capacity() && arg & 0x1FF != 0 {
+                    self.status |= card_status::ADDRESS_ERROR;
+                    return Some((Response::Short(status), DataTransfer::None));
+                }
+                self.state = CardState::Receive;
+                (
+                    Response::Short(status),
+                    DataTransfer::WriteSectors(self.sector_from_arg(arg)),
+                )
+            }
+
+            (42, true, CardKind::Sd) => (Response::Short(self.card_status()), DataTransfer::None),
+
+            (51, true, CardKind::Sd) => {
+                // SCR: SD 2.0, 1- and 4-bit bus widths
+                buffer[..8].copy_from_slice(&[0x02, 0x05, 0, 0, 0, 0, 0, 0]);
+                self.state = CardState::Data;
+                (
+                    Response::Short(self.card_status()),
+                    DataTransfer::ReadBuffer(8),
+                )
+            }
+
+            (55, ..) => {
+                self.status |= card_status::APP_CMD;
+                (Response::Short(self.card_status()), DataTransfer::None)
+            }
+
+            _ => {
+                self.status |= card_status::ILLEGAL_COMMAND;
+                return None;
+            }
+        };
+        Some(result)
+    }
+
+    /// Marks a data transfer as finished, returning the card to the transfer state.
+    #[inline]
+    fn finish_data(&mut self) {
+        if matches!(self.state, CardState::Data | CardState::Receive) {
+            self.state = CardState::Transfer;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Savestate)]
+enum Transfer {
+    None,
+    ReadSectors,
+    WriteSectors,
+    ReadBuffer,
+}
+
+/// The DSi's SD/MMC host controller, accessible from the ARM7 only and connected to both the SD
+/// card slot and the internal eMMC holding the system NAND.
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct SdMmc {
+    sd_card: Card,
+    emmc: Card,
+    port: Port,
+    port_select: u16,
+    command: Command,
+    arg: u32,
+    stop: u16,
+    block_count_16: u16,
+    response: [u32; 4],
+    irq_status: IrqFlags,
+    irq_mask: u32,
+    clock_control: u16,
+    block_len_16: u16,
+    card_option: u16,
+    error_detail: u32,
+    data_control: u16,
+    soft_reset: u16,
+    data32_control: Data32Control,
+    block_len_32: u16,
+    block_count_32: u16,
+    fifo: Bytes<0x200>,
+    fifo_pos: u16,
+    fifo_len: u16,
+    transfer: Transfer,
+    cur_sector: u32,
+    remaining_blocks: u16,
+    irq_line: bool,
+}
+
+impl SdMmc {
+    pub(super) fn new(
+        sd_card: Option<Box<dyn NandProvider>>,
+        nand: Option<Box<dyn NandProvider>>,
+        nand_cid: [u8; 16],
+    ) -> Self {
+        // Arbitrary CID for the SD card, only the manufacturer/OEM ID and the name matter to
+        // software
+        let mut sd_card_cid = [0; 16];
+        sd_card_cid[8..15].copy_from_slice(b"TSDUST\x03");
+        let mut sd_mmc = SdMmc {
+            sd_card: Card::new(sd_card, CardKind::Sd, sd_card_cid),
+            emmc: Card::new(nand, CardKind::Mmc, nand_cid),
+            port: Port::SdCard,
+            port_select: 0,
+            command: Command(0),
+            arg: 0,
+            stop: 0,
+            block_count_16: 0,
+            response: [0; 4],
+            irq_status: IrqFlags(0),
+            irq_mask: 0,
+            clock_control: 0,
+            block_len_16: 0,
+            card_option: 0,
+            error_detail: 0,
+            data_control: 0,
+            soft_reset: 0,
+            data32_control: Data32Control(0),
+            block_len_32: 0,
+            block_count_32: 0,
+            fifo: Bytes::new([0; 0x200]),
+            fifo_pos: 0,
+            fifo_len: 0,
+            transfer: Transfer::None,
+            cur_sector: 0,
+            remaining_blocks: 0,
+            irq_line: false,
+        };
+        sd_mmc.reset();
+        sd_mmc
+            .irq_status
+            .set_card_inserted(sd_mmc.sd_card.is_present());
+        sd_mmc
+    }
+
+    /// Resets the controller's registers, as done by clearing bit 0 of `SD_SOFT_RESET`; the cards'
+    /// states are unaffected.
+    fn reset(&mut self) {
+        self.port = Port::SdCard;
+        self.port_select = 0;
+        self.command = Command(0);
+        self.arg = 0;
+        self.stop = 0;
+        self.block_count_16 = 0;
+        self.response = [0; 4];
+        self.irq_status = IrqFlags(0);
+        self.irq_mask = 0x8B7F_031D;
+        self.clock_control = 0x0020;
+        self.block_len_16 = 0x200;
+        self.card_option = 0x40EE;
+        self.error_detail = 0;
+        self.data_control = 0x1010;
+        self.data32_control = Data32Control(0);
+        self.block_len_32 = 0x200;
+        self.block_count_32 = 0;
+        self.fifo_pos = 0;
+        self.fifo_len = 0;
+        self.transfer = Transfer::None;
+        self.remaining_blocks = 0;
+    }
+
+    #[inline]
+    pub fn sd_card(&self) -> &Card {
+        &self.sd_card
+    }
+
+    #[inline]
+    pub fn emmc(&self) -> &Card {
+        &self.emmc
+    }
+
+    #[inline]
+    pub(super) fn emmc_mut(&mut self) -> &mut Card {
+        &mut self.emmc
+    }
+
+    #[inline]
+    pub fn port(&self) -> Port {
+        self.port
+    }
+
+    #[inline]
+    pub fn irq_status(&self) -> IrqFlags {
+        let card = self.selected_card();
+        self.irq_status
+            .with_card_present(card.is_present())
+            .with_write_unprotected(card.is_writable())
+            .with_cmd_ready(true)
+    }
+
+    #[inline]
+    pub fn irq_mask(&self) -> u32 {
+        self.irq_mask
+    }
+
+    #[inline]
+    pub fn data32_control(&self) -> Data32Control {
+        self.data32_control
+            .with_rx_ready(self.data32_mode() && self.transfer_is_read() && self.fifo_len != 0)
+            .with_tx_request(
+                self.data32_mode() && self.transfer == Transfer::WriteSectors && self.fifo_len == 0,
+            )
+    }
+
+    /// Takes back the providers for the SD card and the eMMC, in that order.
+    pub fn into_providers(self) -> (Option<Box<dyn NandProvider>>, Option<Box<dyn NandProvider>>) {
+        (self.sd_card.provider, self.emmc.provider)
+    }
+
+    #[inline]
+    fn selected_card(&self) -> &Card {
+        match self.port {
+            Port::SdCard => &self.sd_card,
+            Port::Emmc => &self.emmc,
+        }
+    }
+
+    #[inline]
+    fn selected_card_mut(&mut self) -> &mut Card {
+        match self.port {
+            Port::SdCard => &mut self.sd_card,
+            Port::Emmc => &mut self.emmc,
+        }
+    }
+
+    #[inline]
+    fn data32_mode(&self) -> bool {
+        self.data_control & 2 != 0 && self.data32_control.enabled()
+    }
+
+    #[inline]
+    fn transfer_is_read(&self) -> bool {
+        matches!(self.transfer, Transfer::ReadSectors | Transfer::ReadBuffer)
+    }
+
+    #[inline]
+    fn block_len(&self) -> u16 {
+        let len = if self.data32_mode() {
+            self.block_len_32
+        } else {
+            self.block_len_16
+        };
+        len.clamp(1, 0x200)
+    }
+
+    /// Returns whether the controller is requesting an NDMA transfer to or from the 32-bit FIFO.
+    #[inline]
+    pub fn dma_requested(&self) -> bool {
+        let data32_control = self.data32_control();
+        data32_control.rx_ready() || data32_control.tx_request()
+    }
+
+    /// Updates the controller's IRQ line, returning whether it just got raised.
+    fn update_irq(&mut self) -> bool {
+        let data32_control = self.data32_control();
+        let line = self.irq_status().0 & !self.irq_mask & IRQ_STATUS_ACK_MASK != 0
+            || (data32_control.rx_ready() && data32_control.rx_ready_irq_enabled())
+            || (data32_control.tx_request() && data32_control.tx_request_irq_enabled());
+        let raised = line && !self.irq_line;
+        self.irq_line = line;
+        raised
+    }
+
+    /// Reads from a word-aligned offset relative to `SD_CMD`, also returning whether an IRQ needs
+    /// to be requested; `mask` selects the bytes actually being accessed, as reading from the data
+    /// FIFOs has side effects.
+    pub(super) fn read(&mut self, offset: u32, mask: u32, peek: bool) -> (u32, bool) {
+        let value = match offset {
+            0x00 => self.command.0 as u32 | (self.port_select as u32) << 16,
+            0x04 => self.arg,
+            0x08 => self.stop as u32 | (self.block_count_16 as u32) << 16,
+            0x0C..=0x1B => self.response[((offset - 0x0C) >> 2) as usize],
+            0x1C => self.irq_status().0,
+            0x20 => self.irq_mask,
+            0x24 => self.clock_control as u32 | (self.block_len_16 as u32) << 16,
+            0x28 => self.card_option as u32,
+            0x2C => self.error_detail,
+            0x30 => {
+                if mask & 0xFFFF == 0 || peek || self.data32_mode() {
+                    0
+                } else {
+                    self.read_fifo::<2>()
+                }
+            }
+            0xD8 => self.data_control as u32,
+            0xE0 => self.soft_reset as u32,
+            0x100 => self.data32_control().0 as u32,
+            0x104 => self.block_len_32 as u32,
+            0x108 => self.block_count_32 as u32,
+            0x10C => {
+                if peek || !self.data32_mode() {
+                    0
+                } else {
+                    self.read_fifo::<4>()
+                }
+            }
+            _ => 0,
+        };
+        (value, self.update_irq())
+    }
+
+    /// Writes the bits selected by `mask` to a word-aligned offset relative to `SD_CMD`, returning
+    /// whether an IRQ needs to be requested.
+    pub(super) fn write(&mut self, offset: u32, value: u32, mask: u32) -> bool {
+        #[inline]
+        fn merge_16(prev: u16, value: u32, mask: u32) -> u16 {
+            ((prev as u32 & !mask) | (value & mask)) as u16
+        }
+
+        match offset {
+            0x00 => {
+                if mask & 0xFFFF_0000 != 0 {
+                    self.port_select = merge_16(self.port_select, value >> 16, mask >> 16) & 0x030F;
+                    self.port = if self.port_select & 1 == 0 {
+                        Port::SdCard
+                    } else {
+                        Port::Emmc
+                    };
+                }
+                if mask & 0xFFFF != 0 {
+                    self.command = Command(merge_16(self.command.0, value, mask));
+                    self.run_command();
+                }
+            }
+            0x04 => self.arg = (self.arg & !mask) | (value & mask),
+            0x08 => {
+                if mask & 0xFFFF != 0 {
+                    self.stop = merge_16(self.stop, value, mask) & 0x0101;
+                    if self.stop & 1 != 0 {
+                        self.stop &= !1;
+                        self.finish_transfer();
+                    }
+                }
+                if mask & 0xFFFF_0000 != 0 {
+                    self.block_count_16 = merge_16(self.block_count_16, value >> 16, mask >> 16);
+                }
+            }
+            0x1C => {
+                // Bits are acknowledged by writing 0 to them
+                let ack = !value & mask & IRQ_STATUS_ACK_MASK;
+                self.irq_status.0 &= !ack;
+            }
+            0x20 => self.irq_mask = (self.irq_mask & !mask) | (value & mask & 0x8B7F_031D),
+            0x24 => {
+                if mask & 0xFFFF != 0 {
+                    self.clock_control = merge_16(self.clock_control, value, mask) & 0x03FF;
+                }
+                if mask & 0xFFFF_0000 != 0 {
+                    self.block_len_16 =
+                        merge_16(self.block_len_16, value >> 16, mask >> 16) & 0x03FF;
+                }
+            }
+            0x28 => {
+                if mask & 0xFFFF != 0 {
+                    self.card_option = merge_16(self.card_option, value, mask) & 0xC1FF;
+                }
+            }
+            0x30 => {
+                if mask & 0xFFFF != 0 && !self.data32_mode() {
+                    self.write_fifo::<2>(value);
+                }
+            }
+            0xD8 => {
+                if mask & 0xFFFF != 0 {
+                    self.data_control = merge_16(self.data_control, value, mask) & 0x1022 | 0x1010;
+                }
+            }
+            0xE0 => {
+                if mask & 0xFFFF != 0 {
+                    let new_value = merge_16(self.soft_reset, value, mask) & 1;
+                    if new_value & 1 == 0 {
+                        self.reset();
+                    }
+                    self.soft_reset = new_value;
+                }
+            }
+            0x100 => {
+                if mask & 0xFFFF != 0 {
+                    let value = merge_16(self.data32_control.0, value, mask);
+                    if value & 1 << 10 != 0 {
+                        self.fifo_pos = 0;
+                        if self.transfer_is_read() {
+                            self.fifo_len = 0;
+                        }
+                    }
+                    self.data32_control.0 = value & 0x1802;
+                }
+            }
+            0x104 => {
+                if mask & 0xFFFF != 0 {
+                    self.block_len_32 = merge_16(self.block_len_32, value, mask) & 0x03FF;
+                }
+            }
+            0x108 => {
+                if mask & 0xFFFF != 0 {
+                    self.block_count_32 = merge_16(self.block_count_32, value, mask);
+                }
+            }
+            0x10C => {
+                if self.data32_mode() {
+                    self.write_fifo::<4>(value);
+                }
+            }
+            _ => {}
+        }
+        self.update_irq()
+    }
+
+    fn run_command(&mut self) {
+        let command = self.command;
+        let arg = self.arg;
+        self.transfer = Transfer::None;
+        self.fifo_pos = 0;
+        self.fifo_len = 0;
+        self.irq_status.set_rx_ready(false);
+        self.irq_status.set_tx_request(false);
+
+        let port = self.port;
+        let card = match port {
+            Port::SdCard => &mut self.sd_card,
+            Port::Emmc => &mut self.emmc,
+        };
+        let Some((response, data)) = card.handle_command(command.index(), arg, &mut self.fifo)
+        else {
+            self.irq_status.set_cmd_timeout(true);
+            self.irq_status.set_response_end(true);
+            return;
+        };
+
+        match response {
+            Response::None => self.response = [0; 4],
+            Response::Short(value) => self.response = [value, 0, 0, 0],
+            Response::Long(bytes) => {
+                for (word, bytes) in self.response.iter_mut().zip(bytes.chunks_exact(4)) {
+                    *word = u32::from_le_bytes(bytes.try_into().unwrap());
+                }
+            }
+        }
+        self.irq_status.set_response_end(true);
+
+        let block_count = if command.is_multi_block() {
+            if self.data32_mode() {
+                self.block_count_32
+            } else {
+                self.block_count_16
+            }
+        } else {
+            1
+        };
+        match data {
+            DataTransfer::None => {}
+            DataTransfer::ReadBuffer(len) => {
+                self.transfer = Transfer::ReadBuffer;
+                self.fifo_len = len.min(self.block_len());
+                self.remaining_blocks = 1;
+                self.irq_status.set_rx_ready(!self.data32_mode());
+            }
+            DataTransfer::ReadSectors(sector) => {
+                self.transfer = Transfer::ReadSectors;
+                self.cur_sector = sector;
+                self.remaining_blocks = block_count;
+                if self.remaining_blocks == 0 {
+                    self.finish_transfer();
+                } else {
+                    self.load_read_block();
+                }
+            }
+            DataTransfer::WriteSectors(sector) => {
+                self.transfer = Transfer::WriteSectors;
+                self.cur_sector = sector;
+                self.remaining_blocks = block_count;
+                if self.remaining_blocks == 0 {
+                    self.finish_transfer();
+                } else {
+                    self.irq_status.set_tx_request(!self.data32_mode());
+                }
+            }
+        }
+    }
+
+    fn load_read_block(&mut self) {
+        let sector = self.cur_sector;
+        let card = match self.port {
+            Port::SdCard => &mut self.sd_card,
+            Port::Emmc => &mut self.emmc,
+        };
+        card.read_sector(sector, &mut self.fifo);
+        self.cur_sector = self.cur_sector.wrapping_add(1);
+        self.fifo_pos = 0;
+        self.fifo_len = self.block_len();
+        self.irq_status.set_rx_ready(!self.data32_mode());
+    }
+
+    fn read_fifo<const BYTES: usize>(&mut self) -> u32 {
+        if !self.transfer_is_read() || self.fifo_len == 0 {
+            return 0;
+        }
+        let pos = self.fifo_pos as usize;
+        let mut bytes = [0; 4];
+        for (i, byte) in bytes.iter_mut().take(BYTES).enumerate() {
+            *byte = self.fifo.get(pos + i).copied().unwrap_or(0);
+        }
+        self.fifo_pos += BYTES as u16;
+        if self.fifo_pos >= self.fifo_len {
+            self.fifo_pos = 0;
+            self.fifo_len = 0;
+            self.irq_status.set_rx_ready(false);
+            self.remaining_blocks -= 1;
+            if self.remaining_blocks == 0 || self.transfer == Transfer::ReadBuffer {
+                self.finish_transfer();
+            } else {
+                self.load_read_block();
+            }
+        }
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write_fifo<const BYTES: usize>(&mut self, value: u32) {
+        if self.transfer != Transfer::WriteSectors {
+            return;
+        }
+        let pos = self.fifo_pos as usize;
+        for (i, byte) in value.to_le_bytes().into_iter().take(BYTES).enumerate() {
+            if let Some(dst) = self.fifo.get_mut(pos + i) {
+                *dst = byte;
+            }
+        }
+        self.fifo_pos += BYTES as u16;
+        let block_len = self.block_len();
+        if self.fifo_pos >= block_len {
+            self.fifo_pos = 0;
+            self.irq_status.set_tx_request(false);
+            let sector = self.cur_sector;
+            let card = match self.port {
+                Port::SdCard => &mut self.sd_card,
+                Port::Emmc => &mut self.emmc,
+            };
+            // Partial blocks are padded with the sector's previous contents
+            if block_len < 0x200 {
+                let mut prev = Bytes::new([0; 0x200]);
+                card.read_sector(sector, &mut prev);
+                self.fifo[block_len as usize..].copy_from_slice(&prev[block_len as usize..]);
+            }
+            card.write_sector(sector, &self.fifo);
+            self.cur_sector = self.cur_sector.wrapping_add(1);
+            self.remaining_blocks -= 1;
+            if self.remaining_blocks == 0 {
+                self.finish_transfer();
+            } else {
+                self.irq_status.set_tx_request(!self.data32_mode());
+            }
+        }
+    }
+
+    fn finish_transfer(&mut self) {
+        if self.transfer == Transfer::None {
+            return;
+        }
+        let auto_stop = self.stop & 0x100 != 0 || self.transfer == Transfer::ReadBuffer;
+        self.transfer = Transfer::None;
+        self.fifo_pos = 0;
+        self.fifo_len = 0;
+        self.remaining_blocks = 0;
+        self.irq_status.set_rx_ready(false);
+        self.irq_status.set_tx_request(false);
+        self.irq_status.set_data_end(true);
+        if auto_stop || !self.command.is_multi_block() {
+            self.selected_card_mut().finish_data();
+        }
+    }
+}
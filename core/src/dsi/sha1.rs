@@ -0,0 +1,112 @@
+/// An incremental SHA-1 hasher, used both to derive console-unique keys and to implement the DSi
+/// BIOS's SHA-1 functions.
+#[derive(Clone)]
+pub struct Sha1 {
+    pub(crate) state: [u32; 5],
+    /// Total number of bytes hashed so far; the bytes of the last incomplete block are kept in
+    /// `buffer`.
+    pub(crate) len: u64,
+    pub(crate) buffer: [u8; 64],
+}
+
+impl Sha1 {
+    pub const DIGEST_LEN: usize = 20;
+
+    pub fn new() -> Self {
+        Sha1 {
+            state: [
+                0x6745_2301,
+                0xEFCD_AB89,
+                0x98BA_DCFE,
+                0x1032_5476,
+                0xC3D2_E1F0,
+            ],
+            len: 0,
+            buffer: [0; 64],
+        }
+    }
+
+    pub(crate) fn process_block(&mut self, block: &[u8; 64]) {
+        let mut w = [0_u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, word) in w.into_iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, new) in self.state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(new);
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        let buffered = (self.len & 63) as usize;
+        self.len = self.len.wrapping_add(data.len() as u64);
+
+        if buffered != 0 {
+            let len = data.len().min(64 - buffered);
+            self.buffer[buffered..buffered + len].copy_from_slice(&data[..len]);
+            data = &data[len..];
+            if buffered + len < 64 {
+                return;
+            }
+            let block = self.buffer;
+            self.process_block(&block);
+        }
+
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.process_block(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+    }
+
+    pub fn finish(mut self) -> [u8; Self::DIGEST_LEN] {
+        let bit_len = self.len << 3;
+        let padding_len = (119 - (self.len & 63) as usize) % 64 + 1;
+        let mut padding = [0; 64];
+        padding[0] = 0x80;
+        self.update(&padding[..padding_len]);
+        self.update(&bit_len.to_be_bytes());
+
+        let mut digest = [0; Self::DIGEST_LEN];
+        for (bytes, value) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        digest
+    }
+
+    pub fn digest(data: &[u8]) -> [u8; Self::DIGEST_LEN] {
+        let mut hasher = Self::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
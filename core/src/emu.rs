@@ -3,6 +3,7 @@ pub use schedule::{
     event_slots, Event, EventSlotIndex, Schedule, Timestamp, DEFAULT_BATCH_DURATION,
 };
 pub mod input;
+pub mod savestate;
 pub mod swram;
 
 use crate::{
@@ -16,13 +17,15 @@ use crate::{
     },
     dldi::{self, Dldi},
     ds_slot::{self, DsSlot},
+    dsi::{self, nwram::Nwram, Dsi},
     flash::Flash,
+    gba_slot::{self, GbaSlot},
     gpu::{self, engine_3d::Engine3d, Gpu},
     ipc::Ipc,
     rtc::{self, Rtc},
     spi,
     utils::{mem_prelude::*, schedule::RawTimestamp, ReadSavestate, Savestate, WriteSavestate},
-    wifi::WiFi,
+    wifi::{self, WiFi},
     Model,
 };
 #[cfg(feature = "xq-audio")]
@@ -80,7 +83,7 @@ proc_bitfield::bitfield! {
 
 mod bounded {
     use crate::utils::{bounded_int_lit, bounded_int_savestate};
-    bounded_int_lit!(pub struct MainMemMask(u32), min 0x3F_FFFF, max 0x7F_FFFF);
+    bounded_int_lit!(pub struct MainMemMask(u32), min 0x3F_FFFF, max 0xFF_FFFF);
     bounded_int_savestate!(MainMemMask(u32));
 }
 pub use bounded::*;
@@ -90,18 +93,19 @@ pub use bounded::*;
 #[store(post = "self.post_store(save)?")]
 pub struct Emu<E: cpu::Engine> {
     #[allow(dead_code)]
+    #[savestate(skip)]
     pub(crate) global_engine_data: E::GlobalData,
     pub arm7: Arm7<E>,
     pub arm9: Arm9<E>,
     #[savestate(skip)]
-    main_mem: OwnedBytesCellPtr<0x80_0000>,
-    #[savestate(skip)]
+    main_mem: OwnedBytesCellPtr<0x100_0000>,
     main_mem_mask: MainMemMask,
     pub swram: Swram,
     pub schedule: Schedule,
     global_ex_mem_control: GlobalExMemControl,
     pub ipc: Ipc,
     pub ds_slot: DsSlot,
+    pub gba_slot: GbaSlot,
     pub spi: spi::Controller,
     pub rtc: Rtc,
     pub gpu: Gpu,
@@ -109,9 +113,12 @@ pub struct Emu<E: cpu::Engine> {
     pub audio_wifi_power_control: AudioWifiPowerControl,
     pub audio: Audio,
     pub wifi: WiFi,
+    pub dsi: Dsi,
     #[savestate(skip)]
     pub dldi: Option<Dldi>,
     rcnt: u16, // TODO: Move to SIO
+    #[savestate(skip)]
+    model: Model,
     is_debugger: bool,
     #[cfg(feature = "debugger-hooks")]
     #[savestate(skip)]
@@ -121,23 +128,43 @@ pub struct Emu<E: cpu::Engine> {
 impl<E: cpu::Engine> Emu<E> {
     fn post_load<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
         save.start_field(b"main_mem")?;
-        if self.is_debugger {
-            self.main_mem_mask = MainMemMask::new(0x7F_FFFF);
-            save.load_into(&mut self.main_mem)?;
-        } else {
-            self.main_mem_mask = MainMemMask::new(0x3F_FFFF);
-            save.load_into(unsafe {
+        // Only the part of main memory that's actually visible is stored
+        match self.main_mem_mask.get() + 1 {
+            0x40_0000 => save.load_into(unsafe {
                 &mut *self.main_mem.as_bytes_mut_ptr().cast::<Bytes<0x40_0000>>()
-            })?;
+            })?,
+            0x80_0000 => save.load_into(unsafe {
+                &mut *self.main_mem.as_bytes_mut_ptr().cast::<Bytes<0x80_0000>>()
+            })?,
+            _ => save.load_into(&mut self.main_mem)?,
         }
 
-        E::Arm7Data::post_load(self);
-        E::Arm9Data::post_load(self);
+        // Only the engine-independent CPU state is stored here; engine-specific data is stored in
+        // a separate savestate section
+        save.start_field(b"arm7_core")?;
+        let mut arm7_core = self.arm7.engine_data.core_state();
+        save.load_into(&mut arm7_core)?;
+        save.start_field(b"arm9_core")?;
+        let mut arm9_core = self.arm9.engine_data.core_state();
+        save.load_into(&mut arm9_core)?;
+
+        self.map_main_mem();
         self.swram.recalc(&mut self.arm7, &mut self.arm9);
+        Nwram::recalc(self);
         self.gpu
             .vram
             .restore_mappings(&mut self.arm7, &mut self.arm9);
+        if self.arm7.is_in_gba_mode() {
+            Arm7::setup_gba_bus(self);
+        }
         arm9::cp15::Cp15::post_load(self);
+        // The pipeline may need to be refetched, so this has to happen after all memory mappings
+        // have been restored
+        E::Arm7Data::set_core_state(self, &arm7_core);
+        E::Arm9Data::set_core_state(self, &arm9_core);
+        E::Arm7Data::post_load(self);
+        E::Arm9Data::post_load(self);
+        self.gpu.post_load();
         #[cfg(feature = "xq-audio")]
         Audio::update_next_scaled_sample_index(self);
 
@@ -146,11 +173,20 @@ impl<E: cpu::Engine> Emu<E> {
 
     fn post_store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
         save.start_field(b"main_mem")?;
-        if self.is_debugger {
-            save.store(&mut self.main_mem)
-        } else {
-            save.store(unsafe { &mut *self.main_mem.as_bytes_mut_ptr().cast::<Bytes<0x40_0000>>() })
+        match self.main_mem_mask.get() + 1 {
+            0x40_0000 => save.store(unsafe {
+                &mut *self.main_mem.as_bytes_mut_ptr().cast::<Bytes<0x40_0000>>()
+            })?,
+            0x80_0000 => save.store(unsafe {
+                &mut *self.main_mem.as_bytes_mut_ptr().cast::<Bytes<0x80_0000>>()
+            })?,
+            _ => save.store(&mut self.main_mem)?,
         }
+
+        save.start_field(b"arm7_core")?;
+        save.store(&mut self.arm7.engine_data.core_state())?;
+        save.start_field(b"arm9_core")?;
+        save.store(&mut self.arm9.engine_data.core_state())
     }
 }
 
@@ -168,8 +204,16 @@ pub struct Builder {
     pub renderer_3d_tx: Box<dyn gpu::engine_3d::RendererTx>,
     pub dldi_provider: Option<Box<dyn dldi::Provider>>,
 
+    pub gba_slot: GbaSlot,
+    pub wifi_backend: Box<dyn wifi::Backend>,
+    pub dsi_sd_card: Option<Box<dyn dsi::NandProvider>>,
+    pub dsi_nand: Option<Box<dyn dsi::NandProvider>>,
+    pub dsi_nand_cid: [u8; 16],
+    pub dsi_console_id: u64,
     pub arm7_bios: Option<Box<Bytes<{ arm7::BIOS_SIZE }>>>,
     pub arm9_bios: Option<Box<Bytes<{ arm9::BIOS_SIZE }>>>,
+    pub gba_bios: Option<Box<Bytes<{ arm7::BIOS_SIZE }>>>,
+    pub dsi_arm7_bios: Option<Box<Bytes<{ dsi::boot::ARM7_BIOS_SIZE }>>>,
     pub model: Model,
     pub is_debugger: bool,
     pub direct_boot: bool,
@@ -187,6 +231,7 @@ pub enum BuildError {
     MissingSysFiles,
     RomCreation(ds_slot::rom::normal::CreationError),
     RomNeedsDecryptionButNoBiosProvided,
+    DsiStage2(dsi::boot::Stage2Error),
 }
 
 impl Builder {
@@ -204,6 +249,10 @@ impl Builder {
         dldi_provider: Option<Box<dyn dldi::Provider>>,
         #[cfg(feature = "log")] logger: slog::Logger,
     ) -> Self {
+        let gba_slot = GbaSlot::Empty(gba_slot::Empty::new(
+            #[cfg(feature = "log")]
+            logger.new(slog::o!("gba_slot" => "empty")),
+        ));
         Builder {
             #[cfg(feature = "log")]
             logger,
@@ -218,8 +267,16 @@ impl Builder {
             renderer_3d_tx,
             dldi_provider,
 
+            gba_slot,
+            wifi_backend: Box::new(wifi::DummyBackend),
+            dsi_sd_card: None,
+            dsi_nand: None,
+            dsi_nand_cid: [0; 16],
+            dsi_console_id: 0,
             arm7_bios: None,
             arm9_bios: None,
+            gba_bios: None,
+            dsi_arm7_bios: None,
             model: Model::Ds,
             is_debugger: false,
             direct_boot: true,
@@ -269,6 +326,7 @@ impl Builder {
         let mut arm7 = Arm7::new(
             arm7_engine_data,
             self.arm7_bios.map(Into::into),
+            self.gba_bios.map(Into::into),
             #[cfg(feature = "log")]
             self.logger.new(slog::o!("cpu" => "arm7")),
         );
@@ -286,7 +344,9 @@ impl Builder {
         let mut emu = Emu {
             global_engine_data,
             main_mem: OwnedBytesCellPtr::new_zeroed(),
-            main_mem_mask: MainMemMask::new(if self.is_debugger {
+            main_mem_mask: MainMemMask::new(if self.model == Model::Dsi {
+                0xFF_FFFF
+            } else if self.is_debugger {
                 0x7F_FFFF
             } else {
                 0x3F_FFFF
@@ -295,6 +355,7 @@ impl Builder {
             global_ex_mem_control: GlobalExMemControl(0x6000),
             ipc: Ipc::new(),
             ds_slot: DsSlot::new(ds_rom, self.ds_spi, &mut arm7.schedule, &mut arm9.schedule),
+            gba_slot: self.gba_slot,
             spi: spi::Controller::new(
                 self.model,
                 self.firmware,
@@ -307,6 +368,7 @@ impl Builder {
             rtc: Rtc::new(
                 self.rtc_backend,
                 self.first_launch,
+                &mut global_schedule,
                 #[cfg(feature = "log")]
                 self.logger.new(slog::o!("rtc" => "")),
             ),
@@ -331,9 +393,21 @@ impl Builder {
                 #[cfg(feature = "log")]
                 self.logger.new(slog::o!("audio" => "")),
             ),
-            wifi: WiFi::new(),
+            wifi: WiFi::new(
+                self.wifi_backend,
+                &mut arm7.schedule,
+                #[cfg(feature = "log")]
+                self.logger.new(slog::o!("wifi" => "")),
+            ),
+            dsi: Dsi::new(
+                self.dsi_sd_card,
+                self.dsi_nand,
+                self.dsi_nand_cid,
+                self.dsi_console_id,
+            ),
             dldi,
             rcnt: 0,
+            model: self.model,
             schedule: global_schedule,
             arm7,
             arm9,
@@ -344,11 +418,20 @@ impl Builder {
         Arm7::setup(&mut emu);
         Arm9::setup(&mut emu);
         emu.swram.recalc(&mut emu.arm7, &mut emu.arm9);
+        if self.model == Model::Dsi {
+            Dsi::setup_for_boot(&mut emu, true);
+        }
         E::Arm7Data::setup(&mut emu);
         E::Arm9Data::setup(&mut emu);
         if self.direct_boot {
             emu.setup_direct_boot()
                 .map_err(|()| BuildError::MissingRom)?;
+        } else if self.model == Model::Dsi {
+            let arm7_bios = self
+                .dsi_arm7_bios
+                .as_deref()
+                .ok_or(BuildError::MissingSysFiles)?;
+            dsi::boot::load_stage2(&mut emu, arm7_bios).map_err(BuildError::DsiStage2)?;
         }
         Ok(emu)
     }
@@ -367,6 +450,9 @@ bitflags::bitflags! {
 pub enum RunOutput {
     FrameFinished,
     Shutdown,
+    /// The system was stopped after software requested a switch to GBA mode, which can't be
+    /// emulated without a GBA BIOS.
+    MissingGbaBios,
     #[cfg(feature = "debugger-hooks")]
     StoppedByDebugHook,
     #[cfg(feature = "debugger-hooks")]
@@ -382,22 +468,48 @@ impl<E: cpu::Engine> Emu<E> {
         };
 
         let chip_id = ds_slot_rom.chip_id();
+        let boot_checks = ds_slot_rom.boot_checks();
         let mut header_bytes = Bytes::new([0; 0x170]);
         // NOTE: The ROM file's size is ensured beforehand, this should never panic.
         ds_slot_rom.contents().read_header(&mut header_bytes);
         let header = ds_slot::rom::header::Header::new(&header_bytes);
 
+        // NOTE: DSi ROMs are required to be at least 0x1000 bytes long, so this can't panic either.
+        let dsi_header_bytes = (self.model == Model::Dsi).then(|| {
+            let mut bytes = Bytes::new([0; 0x1000]);
+            ds_slot_rom.contents().read_slice(0, &mut *bytes);
+            bytes
+        });
+        let dsi_header = dsi_header_bytes
+            .as_ref()
+            .map(|bytes| ds_slot::rom::header::DsiHeader::new(bytes))
+            .filter(|_| {
+                matches!(
+                    header.unit_code(),
+                    Ok(ds_slot::rom::header::UnitCode::DsAndDsi
+                        | ds_slot::rom::header::UnitCode::Dsi)
+                )
+            });
+        if self.model == Model::Dsi {
+            Dsi::setup_for_boot(self, dsi_header.is_some());
+        }
+
+        // NOTE: The shared area is always at the end of main memory, which is at 0x027F_xxxx on
+        // DS-mode systems and at 0x02FF_xxxx for titles running in DSi mode.
         macro_rules! write_main_mem {
             ($addr: expr, $value: expr) => {
                 unsafe {
-                    self.main_mem
-                        .write_le_unchecked($addr & self.main_mem_mask.get() as usize, $value);
+                    self.main_mem.write_le_unchecked(
+                        ($addr | 0x80_0000) & self.main_mem_mask.get() as usize,
+                        $value,
+                    );
                 }
             };
             (copy $range_start: literal..$range_end: literal, $slice: expr) => {
                 unsafe {
-                    self.main_mem.as_mut_arr()[($range_start & self.main_mem_mask.get() as usize)
-                        ..($range_end & self.main_mem_mask.get() as usize)]
+                    self.main_mem.as_mut_arr()[(($range_start | 0x80_0000)
+                        & self.main_mem_mask.get() as usize)
+                        ..((($range_end - 1) | 0x80_0000) & self.main_mem_mask.get() as usize) + 1]
                         .copy_from_slice($slice);
                 }
             };
@@ -415,14 +527,14 @@ impl<E: cpu::Engine> Emu<E> {
         write_main_mem!(0x7F_F808, header.header_crc());
         // DS cart secure area CRC
         write_main_mem!(0x7F_F80A, header.secure_area_crc());
-        // Missing/bad DS cart CRC (0 == OK, TODO: Actually check? Does the game even boot?)
-        write_main_mem!(0x7F_F80C, 0_u16);
-        // DS cart secure area bad (0 == OK, TODO: Actually check)
-        write_main_mem!(0x7F_F80E, 0_u16);
+        // Missing/bad DS cart CRC (0 == OK)
+        write_main_mem!(0x7F_F80C, boot_checks.header_bad as u16);
+        // DS cart secure area bad (0 == OK)
+        write_main_mem!(0x7F_F80E, boot_checks.secure_area_bad as u16);
         // Boot handler task number
         write_main_mem!(0x7F_F810, 0xFFFF_u16);
-        // Secure area disable (0 == normal, TODO: Detect)
-        write_main_mem!(0x7F_F812, 0_u16);
+        // Secure area disable (0 == normal)
+        write_main_mem!(0x7F_F812, boot_checks.secure_area_disabled as u16);
         // SIO debug connection present (1 == present, TODO: Support it?)
         write_main_mem!(0x7F_F814, 0);
         // RTC status (0 == OK)
@@ -434,7 +546,12 @@ impl<E: cpu::Engine> Emu<E> {
         // Copy of NDS7 RAM address (?)
         write_main_mem!(0x7F_F860, header.arm7_ram_addr());
         // Firmware user settings bad (0 == OK)
-        write_main_mem!(0x7F_F864, 0);
+        write_main_mem!(
+            0x7F_F864,
+            !spi::firmware::are_user_settings_valid(spi::firmware::newest_user_settings(
+                self.spi.firmware.contents()
+            )) as u32
+        );
         // Firmware user settings FLASH address
         write_main_mem!(
             0x7F_F868,
@@ -460,13 +577,13 @@ impl<E: cpu::Engine> Emu<E> {
         // DS cart secure area CRC
         write_main_mem!(0x7F_FC0A, header.secure_area_crc());
         // Missing/bad DS cart CRC (0 == OK)
-        write_main_mem!(0x7F_FC0C, 0_u16);
+        write_main_mem!(0x7F_FC0C, boot_checks.header_bad as u16);
         // DS cart secure area bad (0 == OK)
-        write_main_mem!(0x7F_FC0E, 0_u16);
+        write_main_mem!(0x7F_FC0E, boot_checks.secure_area_bad as u16);
         // NDS7 BIOS CRC
         write_main_mem!(0x7F_FC10, 0x5835_u16);
-        // Secure area disable (0 == normal, TODO: Detect)
-        write_main_mem!(0x7F_FC12, 0_u16);
+        // Secure area disable (0 == normal)
+        write_main_mem!(0x7F_FC12, boot_checks.secure_area_disabled as u16);
         // SIO debug connection present (1 == present, TODO: Support it?)
         write_main_mem!(0x7F_FC14, 0);
         // RTC status (0 == OK)
@@ -489,6 +606,11 @@ impl<E: cpu::Engine> Emu<E> {
 
         write_main_mem!(copy 0x7F_FE00..0x7F_FF70, &*header_bytes);
 
+        if let Some(dsi_header_bytes) = &dsi_header_bytes {
+            // Full DSi cartridge header
+            write_main_mem!(copy 0x7F_E000..0x7F_F000, &**dsi_header_bytes);
+        }
+
         // –––––––––––––––– ARM7 WRAM init values ––––––––––––––––
 
         // TODO: "Fragments of NDS7 firmware boot code" at 0xF700
@@ -530,6 +652,33 @@ impl<E: cpu::Engine> Emu<E> {
         for (&byte, addr) in arm9_loaded_data.iter().zip(header.arm9_ram_addr()..) {
             arm9::bus::write_8::<CpuAccess, _>(self, addr, byte);
         }
+        if let Some(dsi_header) = dsi_header {
+            self.dsi.nwram.setup_from_header(&dsi_header);
+            Nwram::recalc(self);
+
+            let mut arm9i_loaded_data =
+                BoxedByteSlice::new_zeroed(dsi_header.arm9i_size() as usize);
+            self.ds_slot
+                .rom
+                .contents()
+                .expect("DS slot ROM contents should have been initialized")
+                .read_slice_wrapping(dsi_header.arm9i_rom_offset(), &mut arm9i_loaded_data);
+            for (&byte, addr) in arm9i_loaded_data.iter().zip(dsi_header.arm9i_ram_addr()..) {
+                arm9::bus::write_8::<CpuAccess, _>(self, addr, byte);
+            }
+
+            let mut arm7i_loaded_data =
+                BoxedByteSlice::new_zeroed(dsi_header.arm7i_size() as usize);
+            self.ds_slot
+                .rom
+                .contents()
+                .expect("DS slot ROM contents should have been initialized")
+                .read_slice_wrapping(dsi_header.arm7i_rom_offset(), &mut arm7i_loaded_data);
+            for (&byte, addr) in arm7i_loaded_data.iter().zip(dsi_header.arm7i_ram_addr()..) {
+                arm7::bus::write_8::<CpuAccess, _>(self, addr, byte);
+            }
+        }
+
         E::Arm9Data::setup_direct_boot(self, header.arm9_entry_addr());
 
         Ok(())
@@ -541,7 +690,12 @@ impl<E: cpu::Engine> Emu<E> {
     }
 
     #[inline]
-    pub fn main_mem(&self) -> &OwnedBytesCellPtr<0x80_0000> {
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    #[inline]
+    pub fn main_mem(&self) -> &OwnedBytesCellPtr<0x100_0000> {
         &self.main_mem
     }
 
@@ -550,6 +704,36 @@ impl<E: cpu::Engine> Emu<E> {
         self.main_mem_mask
     }
 
+    /// Changes the amount of main memory visible to the CPUs (as done through the DSi's
+    /// `SCFG_EXT9`), remapping it if needed.
+    pub(crate) fn set_main_mem_mask(&mut self, value: MainMemMask) {
+        if value == self.main_mem_mask {
+            return;
+        }
+        self.main_mem_mask = value;
+        self.map_main_mem();
+    }
+
+    fn map_main_mem(&mut self) {
+        let size = self.main_mem_mask.get() as usize + 1;
+        unsafe {
+            if !self.arm7.is_in_gba_mode() {
+                self.arm7.map_sys_bus_ptr_range(
+                    arm7::bus::ptrs::mask::ALL,
+                    self.main_mem.as_mut_ptr(),
+                    size,
+                    (0x0200_0000, 0x02FF_FFFF),
+                );
+            }
+            self.arm9.map_sys_bus_ptr_range(
+                arm9::bus::ptrs::mask::ALL,
+                self.main_mem.as_mut_ptr(),
+                size,
+                (0x0200_0000, 0x02FF_FFFF),
+            );
+        }
+    }
+
     #[inline]
     pub fn global_ex_mem_control(&self) -> GlobalExMemControl {
         self.global_ex_mem_control
@@ -591,6 +775,11 @@ impl<E: cpu::Engine> Emu<E> {
 
 macro_rules! run {
     ($emu: expr, $engine: ty $(, $cycles: expr)?) => {
+        // Sleep mode ends once a wakeup IRQ has been requested (by an event or by the frontend)
+        if $emu.spi.power.sleeping() && !$emu.arm7.irqs.sleeping() {
+            $emu.spi.power.wake_up();
+        }
+
         let mut batch_end_time = $emu.schedule.batch_end_time();
         $(
             #[cfg(feature = "debugger-hooks")]
@@ -618,7 +807,8 @@ macro_rules! run {
         }
 
         run_core!($emu.arm9, <$engine>::Arm9Data, {
-            if $emu.gpu.engine_3d.gx_fifo_stalled() {
+            // The ARM9 is stopped in GBA mode
+            if $emu.gpu.engine_3d.gx_fifo_stalled() || $emu.arm7.is_in_gba_mode() {
                 <$engine>::Arm9Data::run_stalled_until($emu, batch_end_time.into());
             } else {
                 <$engine>::Arm9Data::run_until($emu, batch_end_time.into());
@@ -660,9 +850,14 @@ macro_rules! run {
                     }
                 },
                 Event::Shutdown => {
-                    return RunOutput::Shutdown;
+                    return if $emu.arm7.gba_mode_failed {
+                        RunOutput::MissingGbaBios
+                    } else {
+                        RunOutput::Shutdown
+                    };
                 }
                 Event::Engine3dCommandFinished => Engine3d::process_next_command($emu),
+                Event::RtcTick => Rtc::handle_tick($emu, time),
             }
         }
         #[cfg(feature = "debugger-hooks")]
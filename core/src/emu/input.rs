@@ -144,6 +144,19 @@ impl<E: cpu::Engine> Emu<E> {
         self.input.key_irq_triggered[ARM9 as usize] = triggered;
     }
 
+    /// Opens or closes the lid, which is reported through the hinge bit in EXTKEYIN; opening it
+    /// also requests the ARM7's lid IRQ, waking it up from sleep mode if enabled.
+    pub fn set_lid_closed(&mut self, closed: bool) {
+        let was_closed = self.input.status.lid_closed();
+        self.input.status.set_lid_closed(closed);
+        if was_closed && !closed {
+            self.arm7.irqs.write_requested(
+                self.arm7.irqs.requested().with_lid_opened(true),
+                &mut self.arm7.schedule,
+            );
+        }
+    }
+
     pub fn set_touch_pos(&mut self, pos: [u16; 2]) {
         self.spi.tsc.set_x_pos(pos[0]);
         self.spi.tsc.set_y_pos(pos[1]);
@@ -156,3 +169,85 @@ impl<E: cpu::Engine> Emu<E> {
         self.spi.tsc.set_pen_down(false, &mut self.input.status);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cpu::{
+            arm7::{self, IrqFlags},
+            bus::DebugCpuAccess,
+            interpreter::Interpreter,
+        },
+        emu::Emu,
+        test_utils,
+    };
+
+    fn build_emu() -> Emu<Interpreter> {
+        let mut emu = test_utils::build(test_utils::idle_emu_builder(), Interpreter);
+        emu.arm7
+            .irqs
+            .write_requested(IrqFlags(0), &mut emu.arm7.schedule);
+        emu
+    }
+
+    fn lid_irq_requested(emu: &Emu<Interpreter>) -> bool {
+        emu.arm7.irqs.requested().lid_opened()
+    }
+
+    #[test]
+    fn lid_irq_is_requested_on_opening_edge_only() {
+        let mut emu = build_emu();
+        assert!(!emu.input.status().lid_closed());
+
+        emu.set_lid_closed(false);
+        assert!(
+            !lid_irq_requested(&emu),
+            "lid IRQ requested while staying open"
+        );
+
+        emu.set_lid_closed(true);
+        assert!(emu.input.status().lid_closed());
+        assert!(!lid_irq_requested(&emu), "lid IRQ requested on closing");
+        emu.set_lid_closed(true);
+        assert!(
+            !lid_irq_requested(&emu),
+            "lid IRQ requested while staying closed"
+        );
+
+        emu.set_lid_closed(false);
+        assert!(!emu.input.status().lid_closed());
+        assert!(lid_irq_requested(&emu), "lid IRQ not requested on opening");
+
+        emu.arm7
+            .irqs
+            .write_requested(IrqFlags(0), &mut emu.arm7.schedule);
+        emu.set_lid_closed(false);
+        assert!(
+            !lid_irq_requested(&emu),
+            "lid IRQ requested again without closing"
+        );
+    }
+
+    #[test]
+    fn opening_lid_ends_sleep() {
+        let mut emu = build_emu();
+        arm7::bus::write_32::<DebugCpuAccess, _>(
+            &mut emu,
+            0x0400_0210,
+            IrqFlags(0).with_lid_opened(true).0,
+        );
+        emu.set_lid_closed(true);
+        arm7::bus::write_8::<DebugCpuAccess, _>(&mut emu, 0x0400_0301, 0xC0);
+        assert!(emu.arm7.irqs.sleeping());
+        emu.run();
+        assert!(emu.spi.power.sleeping(), "sleep ended without a wakeup IRQ");
+
+        emu.set_lid_closed(false);
+        emu.run();
+        assert!(!emu.arm7.irqs.sleeping());
+        assert!(
+            !emu.spi.power.sleeping(),
+            "power management device stayed asleep"
+        );
+    }
+}
@@ -0,0 +1,555 @@
+//! Versioned savestate container.
+//!
+//! The raw output of the `Savestate` derive depends on the exact layout of every struct in the
+//! core, so it's wrapped in a container that records which emulator version, game, model and CPU
+//! engine produced it, and splits the state into tagged, individually versioned sections:
+//!
+//! ```text
+//! magic: b"DUSTSTAT"
+//! format version: u32
+//! emulator version: u8 length + UTF-8 string
+//! game code: u32
+//! model: u8
+//! engine name: u8 length + UTF-8 string
+//! section count: u32
+//! sections: [tag: [u8; 4], version: u32, length: u32, contents]
+//! ```
+//!
+//! All integers are little-endian. Whenever a change alters a section's layout, its version must
+//! be bumped; if the old layout can be converted to the new one, a migration should be added to
+//! `MIGRATIONS`, otherwise older states will be refused with
+//! [`LoadError::NoMigration`](LoadError::NoMigration).
+//!
+//! The [`EMU_SECTION`] section only contains engine-independent state (CPU cores are stored as
+//! [`cpu::CoreState`]), so it can be loaded by any CPU engine. The engine's own data, such as
+//! instruction timing state, is stored in the [`ENGINE_SECTION`] section, which is only loaded if
+//! the engine that created the savestate matches the current one, and skipped otherwise. Renderer
+//! state is never stored, so savestates don't depend on which 2D/3D renderers were in use either.
+//!
+//! Savestates created before the container was introduced (i.e. raw `Savestate` output) can't be
+//! converted, as their layout depended on the CPU engine and its features, and are refused with
+//! [`LoadError::Unversioned`](LoadError::Unversioned).
+
+use super::Emu;
+use crate::{
+    cpu::{self, CoreData},
+    utils::{PersistentReadSavestate, PersistentWriteSavestate, ReadSavestate, WriteSavestate},
+    Model,
+};
+
+const MAGIC: [u8; 8] = *b"DUSTSTAT";
+pub const FORMAT_VERSION: u32 = 1;
+pub const EMU_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub type SectionTag = [u8; 4];
+
+/// The engine-independent emulator state, as stored by the `Savestate` derive on [`Emu`].
+pub const EMU_SECTION: SectionTag = *b"EMU ";
+pub const EMU_SECTION_VERSION: u32 = 3;
+
+/// The CPU engine's global, ARM7 and ARM9 data; its layout is identified by the engine name in the
+/// header rather than by the section version.
+pub const ENGINE_SECTION: SectionTag = *b"ENGN";
+pub const ENGINE_SECTION_VERSION: u32 = 1;
+
+/// The known sections, along with their current version.
+const SECTIONS: [(SectionTag, u32); 2] = [
+    (EMU_SECTION, EMU_SECTION_VERSION),
+    (ENGINE_SECTION, ENGINE_SECTION_VERSION),
+];
+
+type Migration = fn(Vec<u8>) -> Result<Vec<u8>, LoadError>;
+
+/// Migrations upgrading a section from the specified version to the next one.
+static MIGRATIONS: &[(SectionTag, u32, Migration)] = &[
+    // Version 1 introduced the container; unversioned savestates are refused before reaching this
+    // point
+    // Version 2 moved engine data to its own section; older versions can't be converted, as the
+    // layout of the engine data they contain inline depends on the engine and its features
+    // Version 3 added the ARM7's and the power management device's sleep state and the RTC's
+    // interrupt state, moved the IR transceiver's state out of the FLASH save device and stored
+    // the DS slot SPI device's type before its state; the latter two can't be converted, as the
+    // older layout doesn't identify the save type
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub format_version: u32,
+    pub emu_version: String,
+    pub game_code: u32,
+    pub model: Model,
+    pub engine: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// The savestate ended before all of its header and sections could be read.
+    UnexpectedEof,
+    /// The savestate was created before savestates were versioned, and can't be converted.
+    Unversioned,
+    /// The savestate was created by a newer emulator version using an unknown container format.
+    UnsupportedFormatVersion(u32),
+    InvalidModel(u8),
+    InvalidString,
+    ModelMismatch {
+        savestate: Model,
+        emu: Model,
+    },
+    GameMismatch {
+        savestate: u32,
+        emu: u32,
+    },
+    MissingSection(SectionTag),
+    /// The section was created by a newer emulator version.
+    NewerSection {
+        tag: SectionTag,
+        version: u32,
+    },
+    /// The section's layout changed in a way that can't be converted automatically.
+    NoMigration {
+        tag: SectionTag,
+        version: u32,
+    },
+    /// The section's contents couldn't be loaded even though its version matched.
+    InvalidSection(SectionTag),
+}
+
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let tag_str = |tag: &SectionTag| String::from_utf8_lossy(tag).trim_end().to_string();
+        match self {
+            LoadError::UnexpectedEof => write!(f, "the savestate is truncated"),
+            LoadError::Unversioned => write!(
+                f,
+                "the savestate was created by an older emulator version that didn't version \
+                 savestates, and can't be loaded"
+            ),
+            LoadError::UnsupportedFormatVersion(version) => write!(
+                f,
+                "unsupported savestate format version {version} (current: {FORMAT_VERSION})"
+            ),
+            LoadError::InvalidModel(value) => write!(f, "invalid model {value}"),
+            LoadError::InvalidString => write!(f, "invalid string in savestate header"),
+            LoadError::ModelMismatch { savestate, emu } => write!(
+                f,
+                "the savestate was created for a different model ({savestate:?}, current: \
+                 {emu:?})"
+            ),
+            LoadError::GameMismatch { savestate, emu } => write!(
+                f,
+                "the savestate was created for a different game ({}, current: {})",
+                game_code_str(*savestate),
+                game_code_str(*emu),
+            ),
+            LoadError::MissingSection(tag) => write!(f, "missing section {:?}", tag_str(tag)),
+            LoadError::NewerSection { tag, version } => write!(
+                f,
+                "section {:?} was created by a newer emulator version (version {version})",
+                tag_str(tag)
+            ),
+            LoadError::NoMigration { tag, version } => write!(
+                f,
+                "section {:?} is from an incompatible older emulator version (version {version})",
+                tag_str(tag)
+            ),
+            LoadError::InvalidSection(tag) => {
+                write!(f, "section {:?} is corrupted", tag_str(tag))
+            }
+        }
+    }
+}
+
+fn game_code_str(game_code: u32) -> String {
+    let bytes = game_code.to_le_bytes();
+    if bytes.iter().all(u8::is_ascii_alphanumeric) {
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        format!("{game_code:#010X}")
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() < len {
+            return Err(LoadError::UnexpectedEof);
+        }
+        let (result, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(result)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_str(&mut self) -> Result<&'a str, LoadError> {
+        let len = self.read_u8()? as usize;
+        core::str::from_utf8(self.read_bytes(len)?).map_err(|_| LoadError::InvalidString)
+    }
+}
+
+fn write_str(result: &mut Vec<u8>, value: &str) {
+    let len = value.len().min(u8::MAX as usize);
+    result.push(len as u8);
+    result.extend_from_slice(&value.as_bytes()[..len]);
+}
+
+/// Returns whether the savestate uses the versioned container, as opposed to being a raw
+/// `Savestate` dump from an older emulator version.
+pub fn is_versioned(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+fn read_header(reader: &mut Reader) -> Result<Header, LoadError> {
+    let format_version = reader.read_u32()?;
+    if format_version != FORMAT_VERSION {
+        return Err(LoadError::UnsupportedFormatVersion(format_version));
+    }
+    let emu_version = reader.read_str()?.to_string();
+    let game_code = reader.read_u32()?;
+    let raw_model = reader.read_u8()?;
+    let model = Model::from_raw(raw_model).ok_or(LoadError::InvalidModel(raw_model))?;
+    let engine = reader.read_str()?.to_string();
+    Ok(Header {
+        format_version,
+        emu_version,
+        game_code,
+        model,
+        engine,
+    })
+}
+
+/// Reads the header of a versioned savestate, returning `Ok(None)` for unversioned ones.
+pub fn header(bytes: &[u8]) -> Result<Option<Header>, LoadError> {
+    if !is_versioned(bytes) {
+        return Ok(None);
+    }
+    let mut reader = Reader {
+        bytes: &bytes[MAGIC.len()..],
+    };
+    read_header(&mut reader).map(Some)
+}
+
+/// Upgrades a section's contents from `version` to the current one.
+fn migrate(
+    tag: SectionTag,
+    version: u32,
+    cur_version: u32,
+    contents: Vec<u8>,
+) -> Result<Vec<u8>, LoadError> {
+    migrate_with(MIGRATIONS, tag, version, cur_version, contents)
+}
+
+fn migrate_with(
+    migrations: &[(SectionTag, u32, Migration)],
+    tag: SectionTag,
+    mut version: u32,
+    cur_version: u32,
+    mut contents: Vec<u8>,
+) -> Result<Vec<u8>, LoadError> {
+    if version > cur_version {
+        return Err(LoadError::NewerSection { tag, version });
+    }
+    while version < cur_version {
+        let Some((.., migration)) = migrations
+            .iter()
+            .find(|(migration_tag, from, _)| *migration_tag == tag && *from == version)
+        else {
+            return Err(LoadError::NoMigration { tag, version });
+        };
+        contents = migration(contents)?;
+        version += 1;
+    }
+    Ok(contents)
+}
+
+impl<E: cpu::Engine> Emu<E> {
+    fn game_code(&self) -> u32 {
+        self.ds_slot
+            .rom
+            .contents()
+            .map_or(0, |contents| contents.game_code())
+    }
+
+    /// Stores the emulator's state into a versioned savestate, returning `None` if it couldn't be
+    /// serialized.
+    pub fn store_savestate(&mut self) -> Option<Vec<u8>> {
+        let mut emu_section = Vec::new();
+        PersistentWriteSavestate::new(&mut emu_section)
+            .store(self)
+            .ok()?;
+
+        let mut engine_section = Vec::new();
+        {
+            let mut savestate = PersistentWriteSavestate::new(&mut engine_section);
+            savestate.store(&mut self.global_engine_data).ok()?;
+            savestate.store(&mut self.arm7.engine_data).ok()?;
+            savestate.store(&mut self.arm9.engine_data).ok()?;
+        }
+
+        let mut result = Vec::with_capacity(emu_section.len() + engine_section.len() + 0x40);
+        result.extend_from_slice(&MAGIC);
+        result.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        write_str(&mut result, EMU_VERSION);
+        result.extend_from_slice(&self.game_code().to_le_bytes());
+        result.push(self.model.to_raw());
+        write_str(&mut result, E::NAME);
+
+        result.extend_from_slice(&(SECTIONS.len() as u32).to_le_bytes());
+        for (tag, version, contents) in [
+            (EMU_SECTION, EMU_SECTION_VERSION, &emu_section),
+            (ENGINE_SECTION, ENGINE_SECTION_VERSION, &engine_section),
+        ] {
+            result.extend_from_slice(&tag);
+            result.extend_from_slice(&version.to_le_bytes());
+            result.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            result.extend_from_slice(contents);
+        }
+        Some(result)
+    }
+
+    /// Loads a savestate created by [`store_savestate`](Self::store_savestate), upgrading its
+    /// sections to the current layout if needed.
+    ///
+    /// Savestates created by a different CPU engine are loaded too, but only their
+    /// engine-independent state is restored.
+    ///
+    /// The emulator's state is only modified if all checks and migrations succeed; however, if
+    /// loading the upgraded contents themselves fails, it may be left partially overwritten.
+    pub fn load_savestate(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+        if !is_versioned(bytes) {
+            return Err(LoadError::Unversioned);
+        }
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        let header = read_header(&mut reader)?;
+        if header.model != self.model {
+            return Err(LoadError::ModelMismatch {
+                savestate: header.model,
+                emu: self.model,
+            });
+        }
+        let game_code = self.game_code();
+        if header.game_code != game_code {
+            return Err(LoadError::GameMismatch {
+                savestate: header.game_code,
+                emu: game_code,
+            });
+        }
+        let same_engine = header.engine == E::NAME;
+
+        let section_count = reader.read_u32()?;
+        let mut emu_section = None;
+        let mut engine_section = None;
+        for _ in 0..section_count {
+            let tag: SectionTag = reader.read_bytes(4)?.try_into().unwrap();
+            let version = reader.read_u32()?;
+            let len = reader.read_u32()? as usize;
+            let contents = reader.read_bytes(len)?;
+            // Another engine's data is meaningless to the current one
+            if tag == ENGINE_SECTION && !same_engine {
+                continue;
+            }
+            // Unknown sections are only created by newer versions, and can be skipped safely
+            // as long as the known ones are still compatible
+            if let Some((_, cur_version)) = SECTIONS.iter().find(|(t, _)| *t == tag) {
+                let contents = migrate(tag, version, *cur_version, contents.to_vec())?;
+                if tag == EMU_SECTION {
+                    emu_section = Some(contents);
+                } else {
+                    engine_section = Some(contents);
+                }
+            }
+        }
+        let emu_section = emu_section.ok_or(LoadError::MissingSection(EMU_SECTION))?;
+
+        PersistentReadSavestate::new(&emu_section)
+            .and_then(|mut savestate| savestate.load_into(self).map_err(drop))
+            .map_err(|()| LoadError::InvalidSection(EMU_SECTION))?;
+
+        // Without engine data, the engine keeps the CPU state restored from the emulator section,
+        // with its timing state reset
+        if let Some(engine_section) = engine_section {
+            PersistentReadSavestate::new(&engine_section)
+                .and_then(|mut savestate| {
+                    savestate
+                        .load_into(&mut self.global_engine_data)
+                        .map_err(drop)?;
+                    savestate
+                        .load_into(&mut self.arm7.engine_data)
+                        .map_err(drop)?;
+                    savestate
+                        .load_into(&mut self.arm9.engine_data)
+                        .map_err(drop)
+                })
+                .map_err(|()| LoadError::InvalidSection(ENGINE_SECTION))?;
+            E::Arm7Data::post_load(self);
+            E::Arm9Data::post_load(self);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versioned_header(format_version: u32, model: u8) -> Vec<u8> {
+        let mut result = MAGIC.to_vec();
+        result.extend_from_slice(&format_version.to_le_bytes());
+        write_str(&mut result, "0.0.0");
+        result.extend_from_slice(b"ABCE");
+        result.push(model);
+        write_str(&mut result, "interpreter");
+        result
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let bytes = versioned_header(FORMAT_VERSION, Model::Lite.to_raw());
+        assert!(is_versioned(&bytes));
+        assert_eq!(
+            header(&bytes),
+            Ok(Some(Header {
+                format_version: FORMAT_VERSION,
+                emu_version: "0.0.0".to_string(),
+                game_code: u32::from_le_bytes(*b"ABCE"),
+                model: Model::Lite,
+                engine: "interpreter".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(header(b"not a savestate"), Ok(None));
+        assert!(!is_versioned(&[]));
+
+        let bytes = versioned_header(FORMAT_VERSION + 1, Model::Ds.to_raw());
+        assert_eq!(
+            header(&bytes),
+            Err(LoadError::UnsupportedFormatVersion(FORMAT_VERSION + 1))
+        );
+
+        let bytes = versioned_header(FORMAT_VERSION, 0xFF);
+        assert_eq!(header(&bytes), Err(LoadError::InvalidModel(0xFF)));
+
+        let bytes = versioned_header(FORMAT_VERSION, Model::Ds.to_raw());
+        assert_eq!(
+            header(&bytes[..bytes.len() - 1]),
+            Err(LoadError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn migrate_current_version_is_identity() {
+        let contents = vec![1, 2, 3];
+        assert_eq!(
+            migrate(
+                EMU_SECTION,
+                EMU_SECTION_VERSION,
+                EMU_SECTION_VERSION,
+                contents.clone()
+            ),
+            Ok(contents)
+        );
+    }
+
+    #[test]
+    fn migrate_rejects_newer_sections() {
+        assert_eq!(
+            migrate(
+                ENGINE_SECTION,
+                ENGINE_SECTION_VERSION + 1,
+                ENGINE_SECTION_VERSION,
+                Vec::new()
+            ),
+            Err(LoadError::NewerSection {
+                tag: ENGINE_SECTION,
+                version: ENGINE_SECTION_VERSION + 1,
+            })
+        );
+    }
+
+    /// Inserts a zeroed `u16` field after the first 4 bytes, as if one had been added to a struct
+    /// following a `u32` one.
+    fn insert_u16_field(mut contents: Vec<u8>) -> Result<Vec<u8>, LoadError> {
+        if contents.len() < 4 {
+            return Err(LoadError::InvalidSection(EMU_SECTION));
+        }
+        contents.splice(4..4, [0; 2]);
+        Ok(contents)
+    }
+
+    /// Widens the trailing `u8` field to a `u32`.
+    fn widen_last_field(mut contents: Vec<u8>) -> Result<Vec<u8>, LoadError> {
+        if contents.is_empty() {
+            return Err(LoadError::InvalidSection(EMU_SECTION));
+        }
+        contents.extend_from_slice(&[0; 3]);
+        Ok(contents)
+    }
+
+    static TEST_MIGRATIONS: &[(SectionTag, u32, Migration)] = &[
+        (EMU_SECTION, 1, insert_u16_field),
+        (EMU_SECTION, 2, widen_last_field),
+    ];
+
+    #[test]
+    fn migrate_applies_each_step_in_order() {
+        let contents = vec![1, 2, 3, 4, 5];
+        assert_eq!(
+            migrate_with(TEST_MIGRATIONS, EMU_SECTION, 1, 3, contents.clone()),
+            Ok(vec![1, 2, 3, 4, 0, 0, 5, 0, 0, 0])
+        );
+        assert_eq!(
+            migrate_with(TEST_MIGRATIONS, EMU_SECTION, 2, 3, contents),
+            Ok(vec![1, 2, 3, 4, 5, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn migrate_stops_at_failing_or_missing_steps() {
+        assert_eq!(
+            migrate_with(TEST_MIGRATIONS, EMU_SECTION, 1, 3, vec![1, 2]),
+            Err(LoadError::InvalidSection(EMU_SECTION))
+        );
+        assert_eq!(
+            migrate_with(TEST_MIGRATIONS, EMU_SECTION, 0, 3, vec![1, 2, 3, 4, 5]),
+            Err(LoadError::NoMigration {
+                tag: EMU_SECTION,
+                version: 0,
+            })
+        );
+        // Migrations only apply to the section they were registered for
+        assert_eq!(
+            migrate_with(TEST_MIGRATIONS, ENGINE_SECTION, 1, 2, vec![1, 2, 3, 4, 5]),
+            Err(LoadError::NoMigration {
+                tag: ENGINE_SECTION,
+                version: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn migrate_reports_the_unconvertible_version() {
+        for version in 0..EMU_SECTION_VERSION {
+            assert_eq!(
+                migrate(EMU_SECTION, version, EMU_SECTION_VERSION, Vec::new()),
+                Err(LoadError::NoMigration {
+                    tag: EMU_SECTION,
+                    version,
+                })
+            );
+        }
+    }
+}
@@ -13,6 +13,7 @@ pub enum Event {
     #[default]
     Shutdown, // Max 1
     Engine3dCommandFinished, // Max 1
+    RtcTick,         // Max 1
 }
 
 def_event_slots! {
@@ -21,6 +22,7 @@ def_event_slots! {
     GPU,
     SHUTDOWN,
     ENGINE_3D,
+    RTC,
 }
 
 def_event_slot_index!(bounded_esi, event_slots, pub struct EventSlotIndex(u8));
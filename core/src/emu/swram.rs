@@ -89,7 +89,7 @@ impl Swram {
         self.recalc(arm7, arm9);
     }
 
-    pub(super) fn recalc<E: cpu::Engine>(&mut self, arm7: &mut Arm7<E>, arm9: &mut Arm9<E>) {
+    pub(crate) fn recalc<E: cpu::Engine>(&mut self, arm7: &mut Arm7<E>, arm9: &mut Arm9<E>) {
         arm7.recalc_swram(self);
         arm9.recalc_swram(self);
         #[cfg(any(feature = "bft-r", feature = "bft-w"))]
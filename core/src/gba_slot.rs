@@ -0,0 +1,109 @@
+mod empty;
+pub use empty::Empty;
+pub mod cartridge;
+pub mod expansion_pak;
+pub mod rom;
+pub mod rumble_pak;
+
+use crate::{emu::LocalExMemControl, utils::Savestate};
+
+trait GbaSlotDevice {
+    /// Reads a halfword from the ROM region; `addr` is relative to the start of the region and
+    /// halfword-aligned. Returning `None` leaves the bus floating.
+    fn read_rom(&mut self, addr: u32) -> Option<u16>;
+    fn write_rom(&mut self, addr: u32, value: u16);
+    /// Reads a byte from the 8-bit RAM region; `addr` is relative to the start of the region.
+    /// Returning `None` leaves the bus floating.
+    fn read_ram(&mut self, addr: u32) -> Option<u8>;
+    fn write_ram(&mut self, addr: u32, value: u8);
+}
+
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub enum GbaSlot {
+    Cartridge(cartridge::Cartridge),
+    MemoryExpansionPak(expansion_pak::MemoryExpansionPak),
+    RumblePak(rumble_pak::RumblePak),
+    Empty(Empty),
+}
+
+macro_rules! forward_to_variants {
+    ($expr: expr, $f: ident $args: tt) => {
+        match $expr {
+            GbaSlot::Cartridge(device) => device.$f $args,
+            GbaSlot::MemoryExpansionPak(device) => device.$f $args,
+            GbaSlot::RumblePak(device) => device.$f $args,
+            GbaSlot::Empty(device) => device.$f $args,
+        }
+    };
+}
+
+impl GbaSlot {
+    #[must_use]
+    pub fn reset(self) -> Self {
+        match self {
+            GbaSlot::Cartridge(device) => GbaSlot::Cartridge(device.reset()),
+            GbaSlot::MemoryExpansionPak(device) => GbaSlot::MemoryExpansionPak(device.reset()),
+            GbaSlot::RumblePak(device) => GbaSlot::RumblePak(device.reset()),
+            GbaSlot::Empty(device) => GbaSlot::Empty(device.reset()),
+        }
+    }
+
+    pub(crate) fn read_rom_16(&mut self, addr: u32, ex_mem_control: LocalExMemControl) -> u16 {
+        if let GbaSlot::RumblePak(_) = self {
+            // The Rumble Pak only pulls AD1 low (which is used for detection), the other lines
+            // are left floating
+            return ex_mem_control.gba_rom_halfword(addr) & !2;
+        }
+        forward_to_variants!(self, read_rom(addr & 0x1FF_FFFE))
+            .unwrap_or_else(|| ex_mem_control.gba_rom_halfword(addr))
+    }
+
+    pub(crate) fn read_rom_32(&mut self, addr: u32, ex_mem_control: LocalExMemControl) -> u32 {
+        if let GbaSlot::Empty(_) = self {
+            return ex_mem_control.gba_rom_word(addr);
+        }
+        self.read_rom_16(addr, ex_mem_control) as u32
+            | (self.read_rom_16(addr | 2, ex_mem_control) as u32) << 16
+    }
+
+    pub(crate) fn write_rom_16(&mut self, addr: u32, value: u16) {
+        forward_to_variants!(self, write_rom(addr & 0x1FF_FFFE, value));
+    }
+
+    pub(crate) fn read_ram(&mut self, addr: u32) -> u8 {
+        forward_to_variants!(self, read_ram(addr & 0xFFFF)).unwrap_or(0xFF)
+    }
+
+    pub(crate) fn write_ram(&mut self, addr: u32, value: u8) {
+        forward_to_variants!(self, write_ram(addr & 0xFFFF, value));
+    }
+}
+
+impl From<cartridge::Cartridge> for GbaSlot {
+    #[inline]
+    fn from(other: cartridge::Cartridge) -> Self {
+        GbaSlot::Cartridge(other)
+    }
+}
+
+impl From<expansion_pak::MemoryExpansionPak> for GbaSlot {
+    #[inline]
+    fn from(other: expansion_pak::MemoryExpansionPak) -> Self {
+        GbaSlot::MemoryExpansionPak(other)
+    }
+}
+
+impl From<rumble_pak::RumblePak> for GbaSlot {
+    #[inline]
+    fn from(other: rumble_pak::RumblePak) -> Self {
+        GbaSlot::RumblePak(other)
+    }
+}
+
+impl From<Empty> for GbaSlot {
+    #[inline]
+    fn from(other: Empty) -> Self {
+        GbaSlot::Empty(other)
+    }
+}
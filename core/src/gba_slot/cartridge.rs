@@ -0,0 +1,362 @@
+use super::rom::{self, Contents};
+use crate::{
+    utils::{BoxedByteSlice, Savestate},
+    SaveContents, SaveReloadContents,
+};
+
+// TODO:
+// - EEPROM saves (accessed serially through the upper part of the ROM region) aren't supported
+// - GPIO-based cartridge peripherals (RTC, solar sensor, gyroscope, rumble) aren't supported
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SaveType {
+    None,
+    Sram32k,
+    Flash512k,
+    Flash1m,
+}
+
+impl SaveType {
+    #[inline]
+    pub fn expected_len(self) -> Option<usize> {
+        match self {
+            SaveType::None => None,
+            SaveType::Sram32k => Some(0x8000),
+            SaveType::Flash512k => Some(0x1_0000),
+            SaveType::Flash1m => Some(0x2_0000),
+        }
+    }
+
+    #[inline]
+    pub fn from_save_len(len: usize) -> Option<Self> {
+        match len {
+            0x8000 => Some(SaveType::Sram32k),
+            0x1_0000 => Some(SaveType::Flash512k),
+            0x2_0000 => Some(SaveType::Flash1m),
+            _ => None,
+        }
+    }
+
+    /// Tries to detect the save type used by a GBA game by looking for the library ID strings
+    /// embedded in its ROM by the official SDK.
+    pub fn detect(contents: &dyn Contents) -> Option<Self> {
+        const CHUNK_LEN: usize = 0x1_0000;
+        const PATTERNS: [(&[u8], SaveType); 5] = [
+            (b"SRAM_V", SaveType::Sram32k),
+            (b"SRAM_F_V", SaveType::Sram32k),
+            (b"FLASH_V", SaveType::Flash512k),
+            (b"FLASH512_V", SaveType::Flash512k),
+            (b"FLASH1M_V", SaveType::Flash1m),
+        ];
+
+        let len = contents.len() as usize;
+        let mut buffer = vec![0; CHUNK_LEN + 0x10];
+        let mut start = 0;
+        while start < len {
+            let chunk_len = (len - start).min(buffer.len());
+            let chunk = &mut buffer[..chunk_len];
+            contents.read_slice(start as u32, chunk);
+            // Library IDs are always word-aligned
+            for i in (0..chunk_len).step_by(4) {
+                for (pattern, save_type) in PATTERNS {
+                    if chunk[i..].starts_with(pattern) {
+                        return Some(save_type);
+                    }
+                }
+            }
+            start += CHUNK_LEN;
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CreationError {
+    InvalidRomSize,
+    InvalidSaveSize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+enum FlashCommandStage {
+    Ready,
+    FirstCycle,
+    SecondCycle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+enum FlashNextWrite {
+    Command,
+    Byte,
+    Bank,
+}
+
+#[derive(Savestate)]
+struct Flash {
+    stage: FlashCommandStage,
+    next_write: FlashNextWrite,
+    id_mode: bool,
+    erase_prepared: bool,
+    bank_base: u32,
+}
+
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct Cartridge {
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+    #[savestate(skip)]
+    contents: Box<dyn Contents>,
+    #[savestate(skip)]
+    rom_len: u32,
+    #[savestate(skip)]
+    save_type: SaveType,
+    #[savestate(skip)]
+    save_contents: BoxedByteSlice,
+    #[savestate(skip)]
+    save_contents_dirty: bool,
+    flash: Flash,
+}
+
+impl Cartridge {
+    /// # Errors
+    /// - [`CreationError::InvalidRomSize`](CreationError::InvalidRomSize): the ROM contents are
+    ///   either too small to contain a header or too big to fit in the GBA slot ROM region.
+    /// - [`CreationError::InvalidSaveSize`](CreationError::InvalidSaveSize): the save contents'
+    ///   size doesn't match the one expected for the specified save type.
+    pub fn new(
+        contents: Box<dyn Contents>,
+        save_type: SaveType,
+        save_contents: Option<SaveContents>,
+        #[cfg(feature = "log")] logger: slog::Logger,
+    ) -> Result<Self, CreationError> {
+        let rom_len = contents.len();
+        if !rom::is_valid_size(rom_len) {
+            return Err(CreationError::InvalidRomSize);
+        }
+        let expected_save_len = save_type.expected_len().unwrap_or(0);
+        let save_contents = match save_contents {
+            Some(save_contents) => {
+                if save_contents.len() != expected_save_len {
+                    return Err(CreationError::InvalidSaveSize);
+                }
+                save_contents.get_or_create(new_save_contents)
+            }
+            None => new_save_contents(expected_save_len),
+        };
+        Ok(Cartridge {
+            #[cfg(feature = "log")]
+            logger,
+            contents,
+            rom_len: rom_len as u32,
+            save_type,
+            save_contents,
+            save_contents_dirty: false,
+            flash: Flash {
+                stage: FlashCommandStage::Ready,
+                next_write: FlashNextWrite::Command,
+                id_mode: false,
+                erase_prepared: false,
+                bank_base: 0,
+            },
+        })
+    }
+
+    #[must_use]
+    pub fn reset(self) -> Self {
+        Cartridge {
+            flash: Flash {
+                stage: FlashCommandStage::Ready,
+                next_write: FlashNextWrite::Command,
+                id_mode: false,
+                erase_prepared: false,
+                bank_base: 0,
+            },
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn contents(&self) -> &dyn Contents {
+        &*self.contents
+    }
+
+    #[inline]
+    pub fn contents_mut(&mut self) -> &mut dyn Contents {
+        &mut *self.contents
+    }
+
+    #[inline]
+    pub fn into_contents(self) -> Box<dyn Contents> {
+        self.contents
+    }
+
+    #[inline]
+    pub fn save_type(&self) -> SaveType {
+        self.save_type
+    }
+
+    #[inline]
+    pub fn save_contents(&self) -> &[u8] {
+        &self.save_contents
+    }
+
+    #[inline]
+    pub fn save_contents_mut(&mut self) -> &mut [u8] {
+        &mut self.save_contents
+    }
+
+    pub fn reload_save_contents(&mut self, contents: SaveReloadContents) {
+        match contents {
+            SaveReloadContents::Existing(contents) => {
+                let len = contents.len().min(self.save_contents.len());
+                self.save_contents[..len].copy_from_slice(&contents[..len]);
+                self.save_contents[len..].fill(0xFF);
+            }
+            SaveReloadContents::New => self.save_contents.fill(0xFF),
+        }
+    }
+
+    #[inline]
+    pub fn save_contents_dirty(&self) -> bool {
+        self.save_contents_dirty
+    }
+
+    #[inline]
+    pub fn mark_save_contents_dirty(&mut self) {
+        self.save_contents_dirty = true;
+    }
+
+    #[inline]
+    pub fn mark_save_contents_flushed(&mut self) {
+        self.save_contents_dirty = false;
+    }
+
+    fn flash_id(&self) -> [u8; 2] {
+        match self.save_type {
+            // Sanyo LE26FV10N1TS
+            SaveType::Flash1m => [0x62, 0x13],
+            // Panasonic MN63F805MNP
+            _ => [0x32, 0x1B],
+        }
+    }
+
+    fn read_flash(&self, addr: u32) -> u8 {
+        if self.flash.id_mode && addr < 2 {
+            self.flash_id()[addr as usize]
+        } else {
+            self.save_contents[(self.flash.bank_base | addr) as usize]
+        }
+    }
+
+    fn write_flash(&mut self, addr: u32, value: u8) {
+        match self.flash.next_write {
+            FlashNextWrite::Byte => {
+                self.flash.next_write = FlashNextWrite::Command;
+                self.save_contents[(self.flash.bank_base | addr) as usize] = value;
+                self.save_contents_dirty = true;
+                return;
+            }
+            FlashNextWrite::Bank => {
+                self.flash.next_write = FlashNextWrite::Command;
+                if addr == 0 {
+                    self.flash.bank_base = (value as u32 & 1) << 16;
+                }
+                return;
+            }
+            FlashNextWrite::Command => {}
+        }
+
+        match (self.flash.stage, addr, value) {
+            (FlashCommandStage::Ready, 0x5555, 0xAA) => {
+                self.flash.stage = FlashCommandStage::FirstCycle;
+            }
+            (FlashCommandStage::FirstCycle, 0x2AAA, 0x55) => {
+                self.flash.stage = FlashCommandStage::SecondCycle;
+            }
+            (FlashCommandStage::SecondCycle, 0x5555, command) => {
+                self.flash.stage = FlashCommandStage::Ready;
+                match command {
+                    0x90 => self.flash.id_mode = true,
+                    0xF0 => self.flash.id_mode = false,
+                    0x80 => {
+                        self.flash.erase_prepared = true;
+                        return;
+                    }
+                    0x10 if self.flash.erase_prepared => {
+                        self.save_contents.fill(0xFF);
+                        self.save_contents_dirty = true;
+                    }
+                    0xA0 => self.flash.next_write = FlashNextWrite::Byte,
+                    0xB0 if self.save_type == SaveType::Flash1m => {
+                        self.flash.next_write = FlashNextWrite::Bank;
+                    }
+                    _ => {
+                        #[cfg(feature = "log")]
+                        slog::warn!(self.logger, "Unknown flash command: {:#04X}", command);
+                    }
+                }
+                self.flash.erase_prepared = false;
+            }
+            (FlashCommandStage::SecondCycle, addr, 0x30)
+                if self.flash.erase_prepared && addr & 0xFFF == 0 =>
+            {
+                self.flash.stage = FlashCommandStage::Ready;
+                self.flash.erase_prepared = false;
+                let start = (self.flash.bank_base | addr) as usize;
+                self.save_contents[start..start + 0x1000].fill(0xFF);
+                self.save_contents_dirty = true;
+            }
+            (_, _, 0xF0) => {
+                self.flash.stage = FlashCommandStage::Ready;
+                self.flash.id_mode = false;
+            }
+            _ => {
+                self.flash.stage = FlashCommandStage::Ready;
+            }
+        }
+    }
+}
+
+fn new_save_contents(len: usize) -> BoxedByteSlice {
+    let mut contents = BoxedByteSlice::new_zeroed(len);
+    contents.fill(0xFF);
+    contents
+}
+
+impl super::GbaSlotDevice for Cartridge {
+    fn read_rom(&mut self, addr: u32) -> Option<u16> {
+        if addr >= self.rom_len {
+            return None;
+        }
+        let mut bytes = [0xFF; 2];
+        let len = (self.rom_len - addr).min(2) as usize;
+        self.contents.read_slice(addr, &mut bytes[..len]);
+        Some(u16::from_le_bytes(bytes))
+    }
+
+    fn write_rom(&mut self, _addr: u32, _value: u16) {
+        #[cfg(feature = "log")]
+        slog::trace!(self.logger, "ROM write @ {:#09X}: {:#06X}", _addr, _value);
+    }
+
+    fn read_ram(&mut self, addr: u32) -> Option<u8> {
+        match self.save_type {
+            SaveType::None => None,
+            SaveType::Sram32k => Some(self.save_contents[(addr & 0x7FFF) as usize]),
+            SaveType::Flash512k | SaveType::Flash1m => Some(self.read_flash(addr)),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u32, value: u8) {
+        match self.save_type {
+            SaveType::None => {}
+            SaveType::Sram32k => {
+                self.save_contents[(addr & 0x7FFF) as usize] = value;
+                self.save_contents_dirty = true;
+            }
+            SaveType::Flash512k | SaveType::Flash1m => self.write_flash(addr, value),
+        }
+    }
+}
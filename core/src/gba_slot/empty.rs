@@ -0,0 +1,44 @@
+use crate::utils::Savestate;
+
+#[derive(Savestate)]
+pub struct Empty {
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+}
+
+#[allow(clippy::new_without_default)]
+impl Empty {
+    pub fn new(#[cfg(feature = "log")] logger: slog::Logger) -> Self {
+        Empty {
+            #[cfg(feature = "log")]
+            logger,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn reset(self) -> Self {
+        self
+    }
+}
+
+impl super::GbaSlotDevice for Empty {
+    fn read_rom(&mut self, _addr: u32) -> Option<u16> {
+        None
+    }
+
+    fn write_rom(&mut self, _addr: u32, _value: u16) {
+        #[cfg(feature = "log")]
+        slog::trace!(self.logger, "ROM write @ {:#09X}: {:#06X}", _addr, _value);
+    }
+
+    fn read_ram(&mut self, _addr: u32) -> Option<u8> {
+        None
+    }
+
+    fn write_ram(&mut self, _addr: u32, _value: u8) {
+        #[cfg(feature = "log")]
+        slog::trace!(self.logger, "RAM write @ {:#06X}: {:#04X}", _addr, _value);
+    }
+}
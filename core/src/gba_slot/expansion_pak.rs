@@ -0,0 +1,80 @@
+use crate::utils::{mem_prelude::*, zeroed_box, Savestate};
+
+/// The Memory Expansion Pak (NTR-011), providing 8 MiB of additional RAM for the DS web browser
+/// and a few homebrew applications.
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct MemoryExpansionPak {
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+    ram: Box<Bytes<0x80_0000>>,
+    ram_unlocked: bool,
+}
+
+#[allow(clippy::new_without_default)]
+impl MemoryExpansionPak {
+    pub fn new(#[cfg(feature = "log")] logger: slog::Logger) -> Self {
+        MemoryExpansionPak {
+            #[cfg(feature = "log")]
+            logger,
+            ram: zeroed_box(),
+            ram_unlocked: false,
+        }
+    }
+
+    #[must_use]
+    pub fn reset(self) -> Self {
+        MemoryExpansionPak {
+            ram_unlocked: false,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn ram(&self) -> &Bytes<0x80_0000> {
+        &self.ram
+    }
+
+    #[inline]
+    pub fn ram_unlocked(&self) -> bool {
+        self.ram_unlocked
+    }
+}
+
+impl super::GbaSlotDevice for MemoryExpansionPak {
+    fn read_rom(&mut self, addr: u32) -> Option<u16> {
+        Some(match addr {
+            // Header data used for detection
+            0xB0 | 0xB8 | 0xBA | 0xBC | 0x1_FFFC => 0xFFFF,
+            0xB2 | 0x24_0002 => 0,
+            0xB4 => 0x2400,
+            0xB6 => 0x2424,
+            0xBE | 0x1_FFFE => 0x7FFF,
+            0x24_0000 => self.ram_unlocked as u16,
+            0x100_0000..=0x17F_FFFF if self.ram_unlocked => {
+                self.ram.read_le((addr & 0x7F_FFFE) as usize)
+            }
+            _ => 0xFFFF,
+        })
+    }
+
+    fn write_rom(&mut self, addr: u32, value: u16) {
+        match addr {
+            0x24_0000 => self.ram_unlocked = value & 1 != 0,
+            0x100_0000..=0x17F_FFFF if self.ram_unlocked => {
+                self.ram.write_le((addr & 0x7F_FFFE) as usize, value);
+            }
+            _ => {
+                #[cfg(feature = "log")]
+                slog::trace!(self.logger, "ROM write @ {:#09X}: {:#06X}", addr, value);
+            }
+        }
+    }
+
+    fn read_ram(&mut self, _addr: u32) -> Option<u8> {
+        Some(0xFF)
+    }
+
+    fn write_ram(&mut self, _addr: u32, _value: u8) {}
+}
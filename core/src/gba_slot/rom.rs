@@ -0,0 +1,48 @@
+use crate::utils::mem_prelude::*;
+use core::any::Any;
+
+/// The maximum size of a GBA ROM image, limited by the size of the GBA slot ROM region.
+pub const MAX_SIZE: u64 = 0x200_0000;
+
+#[allow(clippy::len_without_is_empty)]
+pub trait Contents: Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn len(&self) -> u64;
+
+    fn game_code(&self) -> u32;
+
+    fn read_header(&self, output: &mut Bytes<0xC0>);
+    fn read_slice(&self, addr: u32, output: &mut [u8]);
+}
+
+impl Contents for BoxedByteSlice {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn len(&self) -> u64 {
+        (**self).len() as u64
+    }
+
+    fn game_code(&self) -> u32 {
+        self.read_le::<u32>(0xAC)
+    }
+
+    fn read_header(&self, output: &mut Bytes<0xC0>) {
+        output.copy_from_slice(&self[..0xC0]);
+    }
+
+    fn read_slice(&self, addr: u32, output: &mut [u8]) {
+        output.copy_from_slice(&self[addr as usize..addr as usize + output.len()]);
+    }
+}
+
+pub fn is_valid_size(len: u64) -> bool {
+    (0xC0..=MAX_SIZE).contains(&len)
+}
@@ -0,0 +1,81 @@
+use crate::utils::Savestate;
+use core::any::Any;
+
+pub trait RumbleBackend {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Called whenever the rumble motor gets switched on or off.
+    fn set_rumbling(&mut self, rumbling: bool);
+}
+
+pub struct DummyRumbleBackend;
+
+impl RumbleBackend for DummyRumbleBackend {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn set_rumbling(&mut self, _rumbling: bool) {}
+}
+
+/// The DS Rumble Pak (NTR-008).
+#[derive(Savestate)]
+#[load(in_place_only, post = "self.post_load()")]
+pub struct RumblePak {
+    #[savestate(skip)]
+    pub backend: Box<dyn RumbleBackend>,
+    rumbling: bool,
+}
+
+impl RumblePak {
+    pub fn new(backend: Box<dyn RumbleBackend>) -> Self {
+        RumblePak {
+            backend,
+            rumbling: false,
+        }
+    }
+
+    #[must_use]
+    pub fn reset(mut self) -> Self {
+        self.backend.set_rumbling(false);
+        RumblePak {
+            rumbling: false,
+            ..self
+        }
+    }
+
+    fn post_load(&mut self) {
+        self.backend.set_rumbling(self.rumbling);
+    }
+
+    #[inline]
+    pub fn rumbling(&self) -> bool {
+        self.rumbling
+    }
+}
+
+impl super::GbaSlotDevice for RumblePak {
+    fn read_rom(&mut self, _addr: u32) -> Option<u16> {
+        // Handled by `GbaSlot::read_rom_16`, as the floating lines depend on the bus timings
+        None
+    }
+
+    fn write_rom(&mut self, _addr: u32, value: u16) {
+        let rumbling = value & 2 != 0;
+        if rumbling != self.rumbling {
+            self.rumbling = rumbling;
+            self.backend.set_rumbling(rumbling);
+        }
+    }
+
+    fn read_ram(&mut self, _addr: u32) -> Option<u8> {
+        None
+    }
+
+    fn write_ram(&mut self, _addr: u32, _value: u8) {}
+}
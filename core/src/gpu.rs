@@ -1,9 +1,11 @@
 pub mod engine_2d;
 pub mod engine_3d;
+pub mod gba;
 pub mod vram;
 
 use crate::{
     cpu::{arm7, arm9, Engine},
+    dsi::{ndma, Dsi},
     emu::{self, event_slots, Emu, Timestamp},
     utils::{schedule::RawTimestamp, Savestate},
 };
@@ -82,6 +84,7 @@ pub struct Gpu {
     pub engine_2d_a: Engine2d<engine_2d::EngineA>,
     pub engine_2d_b: Engine2d<engine_2d::EngineB>,
     pub engine_3d: Engine3d,
+    pub(crate) gba_ppu: gba::Ppu,
 }
 
 impl Gpu {
@@ -121,6 +124,7 @@ impl Gpu {
                 #[cfg(feature = "log")]
                 logger.new(slog::o!("eng_3d" => "")),
             ),
+            gba_ppu: gba::Ppu::new(),
         };
 
         result.renderer_2d.start_scanline(
@@ -156,6 +160,15 @@ impl Gpu {
         );
     }
 
+    /// Resends any state the renderers were previously notified of, as they're never stored in
+    /// savestates: this way, a savestate behaves the same regardless of which renderers were in
+    /// use when it was created.
+    pub(crate) fn post_load(&mut self) {
+        self.engine_3d
+            .renderer_tx
+            .set_capture_enabled(self.engine_2d_a.is_capturing_3d_output());
+    }
+
     #[inline]
     pub fn into_renderers(self) -> (Box<dyn engine_2d::Renderer>, Box<dyn engine_3d::RendererTx>) {
         (self.renderer_2d, self.engine_3d.renderer_tx)
@@ -248,7 +261,16 @@ impl Gpu {
         self.vcount_compare_9 = value.vcount_compare();
     }
 
+    #[inline]
+    pub fn gba_ppu(&self) -> &gba::Ppu {
+        &self.gba_ppu
+    }
+
     pub(crate) fn end_hdraw(emu: &mut Emu<impl Engine>, time: Timestamp) {
+        if emu.arm7.is_in_gba_mode() {
+            return Self::end_gba_hdraw(emu, time);
+        }
+
         if emu.gpu.power_control.display_enabled() {
             emu.gpu.disp_status_7.set_hblank(true);
             if emu.gpu.disp_status_7.hblank_irq_enabled() {
@@ -268,6 +290,7 @@ impl Gpu {
             if emu.gpu.power_control.display_enabled() {
                 emu.arm9
                     .start_dma_transfers_with_timing::<{ arm9::dma::Timing::HBlank }>();
+                Dsi::start_ndma_transfers::<true, _>(emu, ndma::startup_mode::ARM9_HBLANK);
             }
             if emu.gpu.cur_scanline < SCREEN_HEIGHT as u32 {
                 emu.gpu.renderer_2d.finish_scanline(
@@ -298,6 +321,10 @@ impl Gpu {
     }
 
     pub(crate) fn end_hblank(emu: &mut Emu<impl Engine>, time: Timestamp) {
+        if emu.arm7.is_in_gba_mode() {
+            return Self::end_gba_hblank(emu, time);
+        }
+
         emu.gpu.cur_scanline = emu.gpu.cur_scanline.wrapping_add(1);
         emu.gpu.vcount = emu
             .gpu
@@ -378,6 +405,8 @@ impl Gpu {
                     .start_dma_transfers_with_timing::<{ arm7::dma::Timing::VBlank }>();
                 emu.arm9
                     .start_dma_transfers_with_timing::<{ arm9::dma::Timing::VBlank }>();
+                Dsi::start_ndma_transfers::<false, _>(emu, ndma::startup_mode::VBLANK);
+                Dsi::start_ndma_transfers::<true, _>(emu, ndma::startup_mode::VBLANK);
             }
         } else if emu.gpu.vcount == (TOTAL_SCANLINES - 48) as u16 {
             emu.gpu.engine_3d.start_rendering(&emu.gpu.vram);
@@ -9,6 +9,14 @@ pub trait Renderer {
 
     fn framebuffer(&self) -> &Framebuffer;
 
+    /// Returns the base 2 logarithm of the scale the output was composited at (compared to the
+    /// native resolution) and the output itself, if it's higher than the native resolution: like
+    /// for [`framebuffer`](Self::framebuffer), the upper screen's `SCREEN_HEIGHT << shift` lines
+    /// of `SCREEN_WIDTH << shift` RGBA8 pixels each are followed by the lower screen's.
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])> {
+        None
+    }
+
     fn start_prerendering_objs(
         &mut self,
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
@@ -23,6 +23,17 @@ pub trait SoftRendererRx {
     fn start_frame(&mut self);
     fn read_scanline(&mut self) -> &Scanline<u32>;
     fn skip_scanline(&mut self);
+
+    /// The base 2 logarithm of the scale 3D graphics are rendered at, compared to the native
+    /// resolution.
+    fn resolution_scale_shift(&self) -> u8 {
+        0
+    }
+
+    /// Returns the output rendered for the scanline last returned by `read_scanline`, at the
+    /// rendering resolution: `1 << resolution_scale_shift()` lines of
+    /// `SCREEN_WIDTH << resolution_scale_shift()` pixels each.
+    fn last_hi_res_scanline(&self) -> &[u32];
 }
 
 pub trait AccelRendererRx {
@@ -0,0 +1,428 @@
+//! GBA mode video output, implemented on top of 2D engine A.
+//!
+//! GBA software's view of the PPU registers, VRAM and timings is emulated here, while the actual
+//! rendering is left to the regular 2D renderer: the GBA display and BG control registers are
+//! translated to their DS equivalents, and GBA VRAM writes are mirrored to VRAM banks A (BG tiles
+//! and maps), B (OBJ tiles) and C (BG bitmaps, laid out like DS extended bitmap BGs). Engine B is
+//! kept enabled but fully darkened, so that the screen not selected through `POWCNT` stays black.
+//!
+//! The GBA picture occupies the top-left 240x160 pixels of the selected screen, and the rest of
+//! the screen is black.
+
+// TODO:
+// - The 16 columns past the right edge of the GBA picture show whatever the BGs and OBJs contain
+//   there, instead of being black
+// - Green swap
+// - Mosaic for bitmap BGs is applied on the DS layout, which should be equivalent, but hasn't been
+//   verified
+
+use super::{
+    engine_2d::{BgControl, BrightnessControl, CaptureControl, Control},
+    vram::BankControl,
+    DispStatus, Gpu, PowerControl,
+};
+use crate::{
+    cpu::{arm7, bus::AccessType, Engine},
+    emu::{self, event_slots, Emu, Timestamp},
+    utils::{mem_prelude::*, schedule::RawTimestamp, OwnedBytesCellPtr, Savestate},
+};
+use core::mem;
+
+pub const SCREEN_WIDTH: usize = 240;
+pub const SCREEN_HEIGHT: usize = 160;
+pub const TOTAL_SCANLINES: usize = 228;
+
+// GBA dots take 4 GBA cycles, i.e. 8 system clock cycles
+const DOT_CYCLES: RawTimestamp = 8;
+const HDRAW_DURATION: Timestamp = Timestamp(SCREEN_WIDTH as RawTimestamp * DOT_CYCLES + 92);
+const HBLANK_DURATION: Timestamp = Timestamp(308 * DOT_CYCLES - HDRAW_DURATION.0);
+
+const VRAM_LEN: usize = 0x1_8000;
+const BITMAP_BASE: u32 = 0x4_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Savestate)]
+enum BitmapLayout {
+    None,
+    Mode3,
+    Mode4,
+    Mode5,
+}
+
+impl BitmapLayout {
+    fn is_direct_color(self) -> bool {
+        matches!(self, BitmapLayout::Mode3 | BitmapLayout::Mode5)
+    }
+
+    /// Translates an offset inside GBA VRAM to the corresponding one in engine A's BG VRAM, for the
+    /// bitmap BG data in the current layout.
+    fn translate(self, addr: u32) -> Option<u32> {
+        let (frame, offset) = (addr / 0xA000, addr % 0xA000);
+        match self {
+            BitmapLayout::None => None,
+            BitmapLayout::Mode3 => {
+                (addr < 0x1_2C00).then(|| BITMAP_BASE + addr / 480 * 512 + addr % 480)
+            }
+            BitmapLayout::Mode4 => (frame < 2 && offset < 0x9600)
+                .then(|| BITMAP_BASE + (frame << 16) + offset / 240 * 256 + offset % 240),
+            BitmapLayout::Mode5 => {
+                (frame < 2).then(|| BITMAP_BASE + (frame << 16) + offset / 320 * 512 + offset % 320)
+            }
+        }
+    }
+}
+
+#[derive(Savestate)]
+#[load(in_place_only)]
+pub struct Ppu {
+    control: u16,
+    bg_control: [u16; 4],
+    bitmap_layout: BitmapLayout,
+    vram: OwnedBytesCellPtr<VRAM_LEN>,
+}
+
+impl Ppu {
+    pub(super) fn new() -> Self {
+        Ppu {
+            control: 0,
+            bg_control: [0; 4],
+            bitmap_layout: BitmapLayout::None,
+            vram: OwnedBytesCellPtr::new_zeroed(),
+        }
+    }
+
+    #[inline]
+    pub fn control(&self) -> u16 {
+        self.control
+    }
+
+    #[inline]
+    pub fn bg_control(&self) -> [u16; 4] {
+        self.bg_control
+    }
+
+    #[inline]
+    pub fn vram(&self) -> &Bytes<VRAM_LEN> {
+        unsafe { self.vram.as_bytes() }
+    }
+}
+
+#[inline]
+fn vram_offset(addr: u32) -> u32 {
+    let addr = addr & 0x1_FFFF;
+    if addr >= VRAM_LEN as u32 {
+        addr - 0x8000
+    } else {
+        addr
+    }
+}
+
+impl Gpu {
+    pub(crate) fn enter_gba_mode<E: Engine>(emu: &mut Emu<E>) {
+        let vram = &mut emu.gpu.vram;
+        let engine_3d = &mut emu.gpu.engine_3d;
+        // A: BG tiles and maps, B: OBJ tiles, C: BG bitmaps
+        vram.write_bank_control_a(BankControl(0x81), &mut emu.arm9, engine_3d);
+        vram.write_bank_control_b(BankControl(0x82), &mut emu.arm9, engine_3d);
+        vram.write_bank_control_c(BankControl(0x91), &mut emu.arm7, &mut emu.arm9, engine_3d);
+        vram.write_bank_control_d(BankControl(0), &mut emu.arm7, &mut emu.arm9, engine_3d);
+        vram.write_bank_control_e(BankControl(0), &mut emu.arm9, engine_3d);
+        vram.write_bank_control_f(BankControl(0), &mut emu.arm9, engine_3d);
+        vram.write_bank_control_g(BankControl(0), &mut emu.arm9, engine_3d);
+        vram.write_bank_control_h(BankControl(0), &mut emu.arm9);
+        vram.write_bank_control_i(BankControl(0), &mut emu.arm9);
+
+        let gpu = &mut emu.gpu;
+        gpu.write_power_control(PowerControl(0x0203 | (gpu.power_control.0 & 0x8000)));
+        gpu.engine_2d_a
+            .write_master_brightness_control(BrightnessControl(0));
+        gpu.engine_2d_a.write_capture_control(CaptureControl(0));
+        gpu.engine_2d_b.write_control(Control(0));
+        gpu.engine_2d_b
+            .write_master_brightness_control(BrightnessControl(0x8010));
+
+        gpu.disp_status_7 = DispStatus(0);
+        gpu.vcount_compare_7 = 0;
+        gpu.next_vcount = None;
+
+        gpu.gba_ppu.control = 0x80;
+        gpu.gba_ppu.bg_control = [0; 4];
+        gpu.gba_ppu.bitmap_layout = BitmapLayout::None;
+        gpu.gba_ppu.vram = OwnedBytesCellPtr::new_zeroed();
+        gpu.update_gba_engine_state();
+    }
+
+    fn update_gba_engine_state(&mut self) {
+        let gba_control = self.gba_ppu.control as u32;
+        let mut control = Control(
+            (gba_control & 0xFF80) // Forced blank, BG, OBJ and window enable bits
+                | (gba_control & 0x40) >> 2 // OBJ 1D mapping
+                | (gba_control & 0x20) << 18 // HBlank interval free
+                | 1 << 16, // Graphics display mode
+        );
+        let mut bg_control = self
+            .gba_ppu
+            .bg_control
+            .map(|value| BgControl(value & !0x30));
+
+        let bitmap_layout = match gba_control & 7 {
+            0 => BitmapLayout::None,
+            1 => {
+                control.0 = (control.0 & !0x800) | 2;
+                BitmapLayout::None
+            }
+            2 => {
+                control.0 = (control.0 & !0x300) | 2;
+                BitmapLayout::None
+            }
+            mode @ 3..=5 => {
+                let bitmap_layout = match mode {
+                    3 => BitmapLayout::Mode3,
+                    4 => BitmapLayout::Mode4,
+                    _ => BitmapLayout::Mode5,
+                };
+                let frame = if mode == 3 { 0 } else { gba_control >> 4 & 1 };
+                control.0 = (control.0 & !0xB00) | 5;
+                bg_control[2] = BgControl(
+                    (bg_control[2].0 & 0x43) // Priority, mosaic
+                        | 0x80 // Bitmap
+                        | (bitmap_layout.is_direct_color() as u16) << 2
+                        | ((BITMAP_BASE >> 14) as u16 + (frame << 2) as u16) << 8
+                        | 1 << 14, // 256x256
+                );
+                bitmap_layout
+            }
+            _ => {
+                control.0 &= !0xF00;
+                BitmapLayout::None
+            }
+        };
+
+        for (bg, bg_control) in self.engine_2d_a.bgs.iter_mut().zip(bg_control) {
+            bg.write_control(bg_control);
+        }
+        self.engine_2d_a.write_control(control);
+
+        if bitmap_layout != self.gba_ppu.bitmap_layout {
+            self.gba_ppu.bitmap_layout = bitmap_layout;
+            if bitmap_layout != BitmapLayout::None {
+                for addr in (BITMAP_BASE..BITMAP_BASE + 0x2_0000).step_by(2) {
+                    self.vram.write_a_bg(addr, 0_u16);
+                }
+                for addr in (0..0x1_4000).step_by(2) {
+                    let value = self.gba_ppu.vram.read_le(addr as usize);
+                    self.write_gba_bitmap_16(addr, value);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn read_gba_io_16<A: AccessType>(&mut self, addr: u32) -> u16 {
+        match addr & 0xFE {
+            0x00 => self.gba_ppu.control,
+            0x04 => self.disp_status_7.0,
+            0x06 => self.vcount,
+            0x08 | 0x0A | 0x0C | 0x0E => self.gba_ppu.bg_control[(addr >> 1 & 3) as usize],
+            0x48 | 0x4A | 0x50 | 0x52 => self.engine_2d_a.read_16::<A>(addr),
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn write_gba_io_16<A: AccessType>(&mut self, addr: u32, value: u16) {
+        match addr & 0xFE {
+            0x00 => {
+                self.gba_ppu.control = value & 0xFFF7;
+                self.update_gba_engine_state();
+            }
+            0x04 => {
+                self.disp_status_7.0 = (self.disp_status_7.0 & 7) | (value & 0xFF38);
+                self.vcount_compare_7 = value >> 8;
+            }
+            0x08 | 0x0A | 0x0C | 0x0E => {
+                self.gba_ppu.bg_control[(addr >> 1 & 3) as usize] = value;
+                self.update_gba_engine_state();
+            }
+            0x10..=0x54 => self.engine_2d_a.write_16::<A>(addr, value),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn write_gba_io_8<A: AccessType>(&mut self, addr: u32, value: u8) {
+        match addr & 0xFF {
+            0x00..=0x0F => {
+                let shift = (addr & 1) << 3;
+                let prev_value = self.read_gba_io_16::<A>(addr & !1);
+                self.write_gba_io_16::<A>(
+                    addr & !1,
+                    (prev_value & !(0xFF << shift)) | (value as u16) << shift,
+                );
+            }
+            0x10..=0x55 => self.engine_2d_a.write_8::<A>(addr, value),
+            _ => {}
+        }
+    }
+
+    #[inline]
+    pub(crate) fn read_gba_vram<T: MemValue>(&self, addr: u32) -> T {
+        unsafe {
+            self.gba_ppu
+                .vram
+                .read_le_aligned_unchecked(vram_offset(addr) as usize & !(mem::size_of::<T>() - 1))
+        }
+    }
+
+    fn write_gba_bitmap_16(&mut self, addr: u32, value: u16) {
+        let bitmap_layout = self.gba_ppu.bitmap_layout;
+        if let Some(bitmap_addr) = bitmap_layout.translate(addr) {
+            self.vram.write_a_bg(
+                bitmap_addr,
+                value | (bitmap_layout.is_direct_color() as u16) << 15,
+            );
+        }
+    }
+
+    pub(crate) fn write_gba_vram_16(&mut self, addr: u32, value: u16) {
+        let addr = vram_offset(addr) & !1;
+        self.gba_ppu.vram.write_le(addr as usize, value);
+        if addr < 0x1_0000 {
+            self.vram.write_a_bg(addr, value);
+        } else {
+            self.vram.write_a_obj(addr - 0x1_0000, value);
+        }
+        self.write_gba_bitmap_16(addr, value);
+    }
+
+    pub(crate) fn write_gba_vram_32(&mut self, addr: u32, value: u32) {
+        self.write_gba_vram_16(addr, value as u16);
+        self.write_gba_vram_16(addr | 2, (value >> 16) as u16);
+    }
+
+    pub(crate) fn write_gba_vram_8(&mut self, addr: u32, value: u8) {
+        // 8-bit writes are ignored for OBJ VRAM, and write the value to both bytes of the halfword
+        // for BG VRAM
+        let obj_vram_start = if self.gba_ppu.control & 7 >= 3 {
+            0x1_4000
+        } else {
+            0x1_0000
+        };
+        if vram_offset(addr) < obj_vram_start {
+            self.write_gba_vram_16(addr, u16::from_le_bytes([value; 2]));
+        }
+    }
+
+    fn render_gba_border(&mut self) {
+        let control = self.engine_2d_a.control();
+        let brightness_control = self.engine_2d_a.master_brightness_control();
+        // Display mode 0 outputs white, which then gets fully darkened
+        self.engine_2d_a.write_control(Control(0));
+        self.engine_2d_a
+            .write_master_brightness_control(BrightnessControl(0x8010));
+        for line in SCREEN_HEIGHT as u8..super::SCREEN_HEIGHT as u8 {
+            self.renderer_2d.start_scanline(
+                line,
+                line,
+                (&mut self.engine_2d_a, &mut self.engine_2d_b),
+                &mut self.vram,
+            );
+            self.renderer_2d.finish_scanline(
+                line,
+                line,
+                (&mut self.engine_2d_a, &mut self.engine_2d_b),
+                &mut self.vram,
+            );
+        }
+        self.engine_2d_a.write_control(control);
+        self.engine_2d_a
+            .write_master_brightness_control(brightness_control);
+    }
+
+    pub(super) fn end_gba_hdraw(emu: &mut Emu<impl Engine>, time: Timestamp) {
+        emu.gpu.disp_status_7.set_hblank(true);
+        if emu.gpu.disp_status_7.hblank_irq_enabled() {
+            emu.arm7
+                .irqs
+                .write_requested(emu.arm7.irqs.requested().with_hblank(true), ());
+        }
+
+        if emu.gpu.vcount < SCREEN_HEIGHT as u16 {
+            emu.arm7
+                .start_dma_transfers_with_timing::<{ arm7::dma::Timing::HBlank }>();
+            emu.gpu.renderer_2d.finish_scanline(
+                emu.gpu.vcount as u8,
+                emu.gpu.vcount as u8,
+                (&mut emu.gpu.engine_2d_a, &mut emu.gpu.engine_2d_b),
+                &mut emu.gpu.vram,
+            );
+        } else if emu.gpu.vcount == (TOTAL_SCANLINES - 1) as u16 {
+            // Render scanline 0 OBJs
+            emu.gpu.renderer_2d.start_prerendering_objs(
+                (&mut emu.gpu.engine_2d_a, &mut emu.gpu.engine_2d_b),
+                &mut emu.gpu.vram,
+            );
+        }
+
+        emu.schedule.set_event(
+            event_slots::GPU,
+            emu::Event::Gpu(if emu.gpu.vcount == (TOTAL_SCANLINES - 1) as u16 {
+                super::Event::FinishFrame
+            } else {
+                super::Event::EndHBlank
+            }),
+        );
+        emu.schedule
+            .schedule_event(event_slots::GPU, time + HBLANK_DURATION);
+    }
+
+    pub(super) fn end_gba_hblank(emu: &mut Emu<impl Engine>, time: Timestamp) {
+        emu.gpu.vcount += 1;
+        if emu.gpu.vcount >= TOTAL_SCANLINES as u16 {
+            emu.gpu.vcount = 0;
+            emu.gpu.engine_2d_a.end_vblank();
+            emu.gpu.engine_2d_b.end_vblank();
+        }
+        emu.gpu.cur_scanline = emu.gpu.vcount as u32;
+
+        emu.gpu.engine_2d_a.update_windows(emu.gpu.vcount as u8);
+        emu.gpu.engine_2d_b.update_windows(emu.gpu.vcount as u8);
+
+        emu.gpu.disp_status_7.set_hblank(false);
+        if emu.gpu.vcount == emu.gpu.vcount_compare_7 {
+            emu.gpu.disp_status_7.set_vcount_match(true);
+            if emu.gpu.disp_status_7.vcount_match_irq_enabled() {
+                emu.arm7
+                    .irqs
+                    .write_requested(emu.arm7.irqs.requested().with_vcount_match(true), ());
+            }
+        } else {
+            emu.gpu.disp_status_7.set_vcount_match(false);
+        }
+
+        if emu.gpu.vcount < SCREEN_HEIGHT as u16 {
+            emu.gpu.renderer_2d.start_scanline(
+                emu.gpu.vcount as u8,
+                emu.gpu.vcount as u8,
+                (&mut emu.gpu.engine_2d_a, &mut emu.gpu.engine_2d_b),
+                &mut emu.gpu.vram,
+            );
+        } else if emu.gpu.vcount == SCREEN_HEIGHT as u16 {
+            emu.gpu.engine_2d_a.start_vblank();
+            emu.gpu.engine_2d_b.start_vblank();
+            emu.gpu.render_gba_border();
+            emu.gpu.disp_status_7.set_vblank(true);
+            if emu.gpu.disp_status_7.vblank_irq_enabled() {
+                emu.arm7
+                    .irqs
+                    .write_requested(emu.arm7.irqs.requested().with_vblank(true), ());
+            }
+            emu.arm7
+                .start_dma_transfers_with_timing::<{ arm7::dma::Timing::VBlank }>();
+        } else if emu.gpu.vcount == (TOTAL_SCANLINES - 1) as u16 {
+            // The VBlank flag gets cleared one scanline earlier than the actual VBlank end.
+            emu.gpu.disp_status_7.set_vblank(false);
+        }
+
+        emu.schedule
+            .set_event(event_slots::GPU, emu::Event::Gpu(super::Event::EndHDraw));
+        emu.schedule
+            .schedule_event(event_slots::GPU, time + HDRAW_DURATION);
+    }
+}
@@ -39,12 +39,17 @@ pub mod audio;
 pub mod cpu;
 pub mod dldi;
 pub mod ds_slot;
+pub mod dsi;
 pub mod emu;
 pub mod flash;
+pub mod gba_slot;
 pub mod gpu;
 pub mod ipc;
+pub mod movie;
 pub mod rtc;
 pub mod spi;
+#[cfg(test)]
+mod test_utils;
 pub mod wifi;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
@@ -59,6 +64,30 @@ pub enum Model {
     Dsi,
 }
 
+impl Model {
+    /// Returns the value used to identify the model in persistent files (savestates and movies).
+    pub(crate) fn to_raw(self) -> u8 {
+        match self {
+            Model::Ds => 0,
+            Model::Lite => 1,
+            Model::Ique => 2,
+            Model::IqueLite => 3,
+            Model::Dsi => 4,
+        }
+    }
+
+    pub(crate) fn from_raw(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Model::Ds,
+            1 => Model::Lite,
+            2 => Model::Ique,
+            3 => Model::IqueLite,
+            4 => Model::Dsi,
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Clone)]
 pub enum SaveContents {
     Existing(utils::BoxedByteSlice),
@@ -0,0 +1,621 @@
+//! Deterministic input movies.
+//!
+//! A movie records, for every emulated frame, the keys that were held, the touchscreen position,
+//! the lid state and the microphone samples the game read, together with the RTC date and time
+//! and the save contents the recording started from, and optionally a savestate to start from
+//! instead of power-on. Playing it back on the same ROM, firmware and BIOS files reproduces the
+//! recorded session exactly.
+//!
+//! While recording or playing back, the RTC is driven by [`rtc::CycleBackend`], which advances with
+//! emulated time instead of following the host's clock.
+
+pub mod dsm;
+
+use crate::{
+    cpu,
+    emu::{input::Keys, savestate, Emu},
+    rtc::{self, Date, Time},
+    spi::tsc::{MicBackend, MicData, MIC_SAMPLES_PER_FRAME},
+    utils::{zeroed_box, BoxedByteSlice},
+    Model, SaveReloadContents,
+};
+use core::any::Any;
+
+const MAGIC: [u8; 8] = *b"DUSTMOVI";
+const VERSION: u32 = 1;
+
+pub type MicSamples = Box<[i16; MIC_SAMPLES_PER_FRAME]>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameInput {
+    pub pressed_keys: Keys,
+    pub touch_pos: Option<[u16; 2]>,
+    pub lid_closed: bool,
+    /// The microphone samples for the frame, or `None` if the game didn't read any.
+    pub mic_samples: Option<MicSamples>,
+}
+
+impl FrameInput {
+    fn capture<E: cpu::Engine>(emu: &Emu<E>) -> Self {
+        let tsc = &emu.spi.tsc;
+        FrameInput {
+            pressed_keys: Keys::from_bits_truncate(!emu.input.status.0),
+            touch_pos: tsc.pen_down().then(|| [tsc.x_pos(), tsc.y_pos()]),
+            lid_closed: emu.input.status.lid_closed(),
+            mic_samples: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Movie {
+    pub model: Model,
+    /// The RTC date and time at the start of the recording.
+    pub rtc_start: (Date, Time),
+    /// The save contents at the start of the recording, as savestates don't include them.
+    pub save: Option<BoxedByteSlice>,
+    /// A savestate to start from, or `None` if the recording started at power-on.
+    pub savestate: Option<Vec<u8>>,
+    pub frames: Vec<FrameInput>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    InvalidModel(u8),
+    UnexpectedEof,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetupError {
+    ModelMismatch { movie: Model, emu: Model },
+    SavestateCreation,
+    SavestateLoading(savestate::LoadError),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        if self.bytes.len() < len {
+            return Err(ParseError::UnexpectedEof);
+        }
+        let (result, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(result)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, ParseError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_opt_bytes(&mut self) -> Result<Option<&'a [u8]>, ParseError> {
+        if self.read_u8()? == 0 {
+            return Ok(None);
+        }
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len).map(Some)
+    }
+}
+
+fn write_opt_bytes(result: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    if let Some(bytes) = bytes {
+        result.push(1);
+        result.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        result.extend_from_slice(bytes);
+    } else {
+        result.push(0);
+    }
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.push(self.model.to_raw());
+        let (date, time) = self.rtc_start;
+        result.extend_from_slice(&[
+            date.years_since_2000,
+            date.month,
+            date.day,
+            date.days_from_sunday,
+            time.hour,
+            time.minute,
+            time.second,
+        ]);
+        write_opt_bytes(&mut result, self.save.as_deref());
+        write_opt_bytes(&mut result, self.savestate.as_deref());
+
+        result.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            result.push(
+                frame.touch_pos.is_some() as u8
+                    | (frame.lid_closed as u8) << 1
+                    | (frame.mic_samples.is_some() as u8) << 2,
+            );
+            result.extend_from_slice(&frame.pressed_keys.bits().to_le_bytes());
+            if let Some([x, y]) = frame.touch_pos {
+                result.extend_from_slice(&x.to_le_bytes());
+                result.extend_from_slice(&y.to_le_bytes());
+            }
+            if let Some(samples) = &frame.mic_samples {
+                for sample in samples.iter() {
+                    result.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+        result
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader { bytes };
+        if reader.read_bytes(8)? != MAGIC {
+            return Err(ParseError::InvalidMagic);
+        }
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+        let raw_model = reader.read_u8()?;
+        let model = Model::from_raw(raw_model).ok_or(ParseError::InvalidModel(raw_model))?;
+        let raw_date_time = reader.read_bytes(7)?;
+        let rtc_start = (
+            Date {
+                years_since_2000: raw_date_time[0],
+                month: raw_date_time[1],
+                day: raw_date_time[2],
+                days_from_sunday: raw_date_time[3],
+            },
+            Time {
+                hour: raw_date_time[4],
+                minute: raw_date_time[5],
+                second: raw_date_time[6],
+            },
+        );
+        let save = reader.read_opt_bytes()?.map(|bytes| {
+            let mut save = BoxedByteSlice::new_zeroed(bytes.len());
+            save.copy_from_slice(bytes);
+            save
+        });
+        let savestate = reader.read_opt_bytes()?.map(<[u8]>::to_vec);
+
+        let frame_count = reader.read_u32()? as usize;
+        let mut frames = Vec::with_capacity(frame_count.min(reader.bytes.len() / 5));
+        for _ in 0..frame_count {
+            let flags = reader.read_u8()?;
+            let pressed_keys = Keys::from_bits_truncate(reader.read_u32()?);
+            let touch_pos = if flags & 1 != 0 {
+                Some([reader.read_u16()?, reader.read_u16()?])
+            } else {
+                None
+            };
+            let mic_samples = if flags & 4 != 0 {
+                let raw_samples = reader.read_bytes(MIC_SAMPLES_PER_FRAME * 2)?;
+                let mut samples: MicSamples = zeroed_box();
+                for (sample, bytes) in samples.iter_mut().zip(raw_samples.chunks_exact(2)) {
+                    *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                Some(samples)
+            } else {
+                None
+            };
+            frames.push(FrameInput {
+                pressed_keys,
+                touch_pos,
+                lid_closed: flags & 2 != 0,
+                mic_samples,
+            });
+        }
+
+        Ok(Movie {
+            model,
+            rtc_start,
+            save,
+            savestate,
+            frames,
+        })
+    }
+}
+
+/// Wraps the frontend's microphone backend while recording, capturing a whole frame's worth of
+/// samples at the start of each frame so they can be stored in the movie.
+struct RecordingMic {
+    inner: Box<dyn MicBackend>,
+    samples: MicSamples,
+    read: bool,
+}
+
+impl MicBackend for RecordingMic {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn start_frame(&mut self) {
+        self.inner.start_frame();
+        self.inner.read_frame_samples(0, &mut self.samples[..]);
+        self.read = false;
+    }
+
+    fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]) {
+        let len = samples.len().min(MIC_SAMPLES_PER_FRAME - offset);
+        samples[..len].copy_from_slice(&self.samples[offset..offset + len]);
+        self.read = true;
+    }
+}
+
+/// Feeds recorded microphone samples back to the emulator during playback; frames without any
+/// recorded samples read silence.
+struct PlaybackMic {
+    samples: Option<MicSamples>,
+}
+
+impl MicBackend for PlaybackMic {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn start_frame(&mut self) {}
+
+    fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]) {
+        if let Some(recorded) = &self.samples {
+            let len = samples.len().min(MIC_SAMPLES_PER_FRAME - offset);
+            samples[..len].copy_from_slice(&recorded[offset..offset + len]);
+        } else {
+            samples.fill(0);
+        }
+    }
+}
+
+pub struct Recorder {
+    movie: Movie,
+    prev_rtc_backend: Box<dyn rtc::Backend>,
+}
+
+impl Recorder {
+    /// Starts recording from the emulator's current state, embedding a savestate of it unless
+    /// `from_power_on` is set (in which case no frames must have been run yet).
+    ///
+    /// The emulator's RTC and microphone backends are replaced until [`finish`](Self::finish) is
+    /// called.
+    pub fn new<E: cpu::Engine>(emu: &mut Emu<E>, from_power_on: bool) -> Result<Self, SetupError> {
+        let savestate = if from_power_on {
+            None
+        } else {
+            Some(emu.store_savestate().ok_or(SetupError::SavestateCreation)?)
+        };
+        let save_contents = emu.ds_slot.spi.contents();
+        let save = (!save_contents.is_empty()).then(|| {
+            let mut save = BoxedByteSlice::new_zeroed(save_contents.len());
+            save.copy_from_slice(save_contents);
+            save
+        });
+
+        let rtc_start = emu.rtc.backend.get_date_time();
+        let prev_rtc_backend = core::mem::replace(
+            &mut emu.rtc.backend,
+            Box::new(rtc::CycleBackend::new(rtc_start)),
+        );
+        if let Some(mic_data) = &mut emu.spi.tsc.mic_data {
+            let inner = core::mem::replace(
+                &mut mic_data.backend,
+                Box::new(PlaybackMic { samples: None }),
+            );
+            mic_data.backend = Box::new(RecordingMic {
+                inner,
+                samples: zeroed_box(),
+                read: false,
+            });
+        }
+
+        Ok(Recorder {
+            movie: Movie {
+                model: emu.model(),
+                rtc_start,
+                save,
+                savestate,
+                frames: Vec::new(),
+            },
+            prev_rtc_backend,
+        })
+    }
+
+    #[inline]
+    pub fn frames(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    fn capture_mic_samples<E: cpu::Engine>(&mut self, emu: &mut Emu<E>) {
+        let Some(last_frame) = self.movie.frames.last_mut() else {
+            return;
+        };
+        if let Some(mic) = emu
+            .spi
+            .tsc
+            .mic_data
+            .as_mut()
+            .and_then(|mic_data| mic_data.backend.as_any_mut().downcast_mut::<RecordingMic>())
+        {
+            if core::mem::replace(&mut mic.read, false) {
+                last_frame.mic_samples = Some(mic.samples.clone());
+            }
+        }
+    }
+
+    /// Records the input state for the frame that's about to be run; must be called right before
+    /// running each frame, after the frontend has applied its input changes.
+    pub fn record_frame<E: cpu::Engine>(&mut self, emu: &mut Emu<E>) {
+        self.capture_mic_samples(emu);
+        self.movie.frames.push(FrameInput::capture(emu));
+    }
+
+    /// Stops recording, restoring the emulator's original RTC and microphone backends.
+    pub fn finish<E: cpu::Engine>(mut self, emu: &mut Emu<E>) -> Movie {
+        self.capture_mic_samples(emu);
+        emu.rtc.backend = self.prev_rtc_backend;
+        if let Some(mic_data) = &mut emu.spi.tsc.mic_data {
+            if let Some(mic) = mic_data.backend.as_any_mut().downcast_mut::<RecordingMic>() {
+                mic_data.backend =
+                    core::mem::replace(&mut mic.inner, Box::new(PlaybackMic { samples: None }));
+            }
+        }
+        self.movie
+    }
+}
+
+pub struct Player {
+    movie: Movie,
+    cur_frame: usize,
+    prev_rtc_backend: Option<Box<dyn rtc::Backend>>,
+    prev_mic_data: Option<MicData>,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Player {
+            movie,
+            cur_frame: 0,
+            prev_rtc_backend: None,
+            prev_mic_data: None,
+        }
+    }
+
+    #[inline]
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    #[inline]
+    pub fn cur_frame(&self) -> usize {
+        self.cur_frame
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.cur_frame >= self.movie.frames.len()
+    }
+
+    /// Prepares the emulator for playback, loading the movie's savestate (if any) and save
+    /// contents, and replacing its RTC and microphone backends until [`finish`](Self::finish) is
+    /// called.
+    ///
+    /// For movies recorded from power-on, this must be called before running any frames.
+    pub fn setup<E: cpu::Engine>(&mut self, emu: &mut Emu<E>) -> Result<(), SetupError> {
+        if emu.model() != self.movie.model {
+            return Err(SetupError::ModelMismatch {
+                movie: self.movie.model,
+                emu: emu.model(),
+            });
+        }
+        if let Some(savestate) = &self.movie.savestate {
+            emu.load_savestate(savestate)
+                .map_err(SetupError::SavestateLoading)?;
+        }
+        if let Some(save) = &self.movie.save {
+            emu.ds_slot
+                .spi
+                .reload_contents(SaveReloadContents::Existing(save.clone()));
+        }
+
+        self.cur_frame = 0;
+        self.prev_rtc_backend = Some(core::mem::replace(
+            &mut emu.rtc.backend,
+            Box::new(rtc::CycleBackend::new(self.movie.rtc_start)),
+        ));
+        self.prev_mic_data = core::mem::replace(
+            &mut emu.spi.tsc.mic_data,
+            Some(MicData::new(Box::new(PlaybackMic { samples: None }))),
+        );
+        Ok(())
+    }
+
+    /// Applies the inputs for the next frame; must be called right before running each frame.
+    ///
+    /// Returns `false` once all recorded frames have been played back, without modifying the
+    /// emulator's state.
+    pub fn apply_frame<E: cpu::Engine>(&mut self, emu: &mut Emu<E>) -> bool {
+        let Some(frame) = self.movie.frames.get(self.cur_frame) else {
+            return false;
+        };
+        self.cur_frame += 1;
+
+        let pressed_keys = Keys::from_bits_truncate(!emu.input.status.0);
+        let newly_pressed = frame.pressed_keys & !pressed_keys;
+        let newly_released = pressed_keys & !frame.pressed_keys;
+        if !newly_pressed.is_empty() {
+            emu.press_keys(newly_pressed);
+        }
+        if !newly_released.is_empty() {
+            emu.release_keys(newly_released);
+        }
+
+        let tsc = &emu.spi.tsc;
+        let touch_pos = tsc.pen_down().then(|| [tsc.x_pos(), tsc.y_pos()]);
+        if touch_pos != frame.touch_pos {
+            if let Some(pos) = frame.touch_pos {
+                emu.set_touch_pos(pos);
+            } else {
+                emu.end_touch();
+            }
+        }
+
+        emu.set_lid_closed(frame.lid_closed);
+
+        if let Some(mic) = emu
+            .spi
+            .tsc
+            .mic_data
+            .as_mut()
+            .and_then(|mic_data| mic_data.backend.as_any_mut().downcast_mut::<PlaybackMic>())
+        {
+            mic.samples.clone_from(&frame.mic_samples);
+        }
+
+        true
+    }
+
+    /// Stops playback, restoring the emulator's original RTC and microphone backends.
+    pub fn finish<E: cpu::Engine>(self, emu: &mut Emu<E>) {
+        if let Some(prev_rtc_backend) = self.prev_rtc_backend {
+            emu.rtc.backend = prev_rtc_backend;
+            emu.spi.tsc.mic_data = self.prev_mic_data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_movie() -> Movie {
+        let mut mic_samples: MicSamples = zeroed_box();
+        for (i, sample) in mic_samples.iter_mut().enumerate() {
+            *sample = (i as i16).wrapping_mul(-97);
+        }
+        let mut save = BoxedByteSlice::new_zeroed(0x200);
+        save[0x1FF] = 0xA5;
+        Movie {
+            model: Model::Dsi,
+            rtc_start: (
+                Date {
+                    years_since_2000: 23,
+                    month: 12,
+                    day: 31,
+                    days_from_sunday: 0,
+                },
+                Time {
+                    hour: 23,
+                    minute: 59,
+                    second: 58,
+                },
+            ),
+            save: Some(save),
+            savestate: Some(vec![1, 2, 3]),
+            frames: vec![
+                FrameInput {
+                    pressed_keys: Keys::empty(),
+                    touch_pos: None,
+                    lid_closed: false,
+                    mic_samples: None,
+                },
+                FrameInput {
+                    pressed_keys: Keys::A | Keys::START | Keys::DEBUG,
+                    touch_pos: Some([0xFF0, 0]),
+                    lid_closed: false,
+                    mic_samples: Some(mic_samples),
+                },
+                FrameInput {
+                    pressed_keys: Keys::L,
+                    touch_pos: None,
+                    lid_closed: true,
+                    mic_samples: None,
+                },
+            ],
+        }
+    }
+
+    fn assert_movies_eq(a: &Movie, b: &Movie) {
+        assert_eq!(a.model, b.model);
+        assert_eq!(a.rtc_start, b.rtc_start);
+        assert_eq!(a.save.as_deref(), b.save.as_deref());
+        assert_eq!(a.savestate, b.savestate);
+        assert_eq!(a.frames, b.frames);
+    }
+
+    #[test]
+    fn round_trip() {
+        let movie = test_movie();
+        let parsed = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_movies_eq(&parsed, &movie);
+        assert_eq!(parsed.to_bytes(), movie.to_bytes());
+    }
+
+    #[test]
+    fn round_trip_from_power_on() {
+        let movie = Movie {
+            model: Model::Lite,
+            save: None,
+            savestate: None,
+            frames: Vec::new(),
+            ..test_movie()
+        };
+        assert_movies_eq(&Movie::from_bytes(&movie.to_bytes()).unwrap(), &movie);
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let bytes = test_movie().to_bytes();
+
+        let mut invalid_magic = bytes.clone();
+        invalid_magic[0] ^= 1;
+        assert_eq!(
+            Movie::from_bytes(&invalid_magic).err(),
+            Some(ParseError::InvalidMagic)
+        );
+
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Movie::from_bytes(&newer).err(),
+            Some(ParseError::UnsupportedVersion(VERSION + 1))
+        );
+
+        let mut invalid_model = bytes;
+        invalid_model[12] = 0xFF;
+        assert_eq!(
+            Movie::from_bytes(&invalid_model).err(),
+            Some(ParseError::InvalidModel(0xFF))
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = test_movie().to_bytes();
+        for len in [0, 4, 13, 20, bytes.len() - 1] {
+            assert_eq!(
+                Movie::from_bytes(&bytes[..len]).err(),
+                Some(ParseError::UnexpectedEof),
+                "length {len}"
+            );
+        }
+    }
+}
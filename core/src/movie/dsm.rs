@@ -0,0 +1,268 @@
+//! Import of DeSmuME's text-based `.dsm` movies.
+//!
+//! Only power-on movies are supported; mid-movie resets can't be represented and are rejected.
+//! DeSmuME doesn't record actual microphone samples, only whether the microphone was being
+//! "blown" into, so those frames are filled with a loud square wave. Frames with the lid command
+//! set are treated as having the lid closed.
+
+use super::{FrameInput, MicSamples, Movie};
+use crate::{
+    emu::input::Keys,
+    rtc::{self, Date, Time},
+    utils::zeroed_box,
+    Model,
+};
+
+const CMD_MIC: u32 = 1 << 0;
+const CMD_RESET: u32 = 1 << 1;
+const CMD_LID: u32 = 1 << 2;
+
+/// The keys corresponding to each character of a frame's pad field, in order.
+const PAD_KEYS: [Keys; 13] = [
+    Keys::RIGHT,
+    Keys::LEFT,
+    Keys::DOWN,
+    Keys::UP,
+    Keys::START,
+    Keys::SELECT,
+    Keys::B,
+    Keys::A,
+    Keys::Y,
+    Keys::X,
+    Keys::L,
+    Keys::R,
+    Keys::DEBUG,
+];
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    UnsupportedVersion(String),
+    InvalidRtcStart(String),
+    /// The frame at the given index (zero-based) couldn't be parsed.
+    InvalidFrame(usize),
+    /// The movie resets the console at the given frame.
+    UnsupportedReset(usize),
+}
+
+/// Parses an `rtcStartNew` value, formatted as `YYYY-MMM-DD HH:MM:SS:mmm`.
+fn parse_rtc_start(value: &str) -> Option<(Date, Time)> {
+    let (date, time) = value.split_once(' ')?;
+    let mut date_parts = date.split('-');
+    let year = date_parts.next()?.parse::<u16>().ok()?;
+    let month_name = date_parts.next()?;
+    let month = MONTHS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(month_name))? as u8
+        + 1;
+    let day = date_parts.next()?.parse::<u8>().ok()?;
+    let mut time_parts = time.split(':');
+    let hour = time_parts.next()?.parse::<u8>().ok()?;
+    let minute = time_parts.next()?.parse::<u8>().ok()?;
+    let second = time_parts.next()?.parse::<u8>().ok()?;
+
+    if !(2000..2100).contains(&year) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let years_since_2000 = (year - 2000) as u8;
+    if day == 0 || day > rtc::days_in_month(years_since_2000, month) {
+        return None;
+    }
+    // The day of the week isn't stored, so let the RTC's conversions fill it in
+    Some(rtc::date_time_from_timestamp(
+        rtc::timestamp_from_date_time((
+            Date {
+                years_since_2000,
+                month,
+                day,
+                days_from_sunday: 0,
+            },
+            Time {
+                hour,
+                minute,
+                second,
+            },
+        )),
+    ))
+}
+
+fn blown_mic_samples() -> MicSamples {
+    let mut samples: MicSamples = zeroed_box();
+    for (i, sample) in samples.iter_mut().enumerate() {
+        *sample = if i & 8 == 0 { 0x4000 } else { -0x4000 };
+    }
+    samples
+}
+
+/// Parses a frame line, formatted as `|commands|pad X Y touch|` (with the leading `|` already
+/// stripped); anything after the touch flag is ignored.
+fn parse_frame(line: &str, blown_mic_samples: &MicSamples) -> Option<(u32, FrameInput)> {
+    let (commands, rest) = line.split_once('|')?;
+    let commands = commands.trim().parse::<u32>().ok()?;
+    let rest = rest.strip_suffix('|').unwrap_or(rest);
+    let pad = rest.get(..PAD_KEYS.len())?;
+    let mut pressed_keys = Keys::empty();
+    for (mnemonic, key) in pad.chars().zip(PAD_KEYS) {
+        if mnemonic != '.' && mnemonic != ' ' {
+            pressed_keys |= key;
+        }
+    }
+    let mut touch_parts = rest[PAD_KEYS.len()..].split_whitespace();
+    let x = touch_parts.next()?.parse::<u16>().ok()?;
+    let y = touch_parts.next()?.parse::<u16>().ok()?;
+    let touching = touch_parts.next()?.parse::<u8>().ok()? != 0;
+
+    Some((
+        commands,
+        FrameInput {
+            pressed_keys,
+            touch_pos: touching.then(|| [x.min(255) << 4, y.min(191) << 4]),
+            lid_closed: commands & CMD_LID != 0,
+            mic_samples: (commands & CMD_MIC != 0).then(|| blown_mic_samples.clone()),
+        },
+    ))
+}
+
+/// Converts the contents of a `.dsm` file to a movie for the given model.
+pub fn import(contents: &str, model: Model) -> Result<Movie, ImportError> {
+    let mut rtc_start = (
+        Date {
+            years_since_2000: 9,
+            month: 1,
+            day: 1,
+            days_from_sunday: 4,
+        },
+        Time::default(),
+    );
+    let mut frames = Vec::new();
+    let blown_mic_samples = blown_mic_samples();
+
+    for line in contents.lines() {
+        if let Some(line) = line.strip_prefix('|') {
+            let frame_index = frames.len();
+            let (commands, frame) = parse_frame(line, &blown_mic_samples)
+                .ok_or(ImportError::InvalidFrame(frame_index))?;
+            if commands & CMD_RESET != 0 {
+                return Err(ImportError::UnsupportedReset(frame_index));
+            }
+            frames.push(frame);
+            continue;
+        }
+
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let value = value.trim();
+        match key {
+            "version" if value != "1" => {
+                return Err(ImportError::UnsupportedVersion(value.to_string()));
+            }
+            "rtcStartNew" => {
+                rtc_start = parse_rtc_start(value)
+                    .ok_or_else(|| ImportError::InvalidRtcStart(value.to_string()))?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Movie {
+        model,
+        rtc_start,
+        save: None,
+        savestate: None,
+        frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtc_start() {
+        assert_eq!(
+            parse_rtc_start("2012-FEB-29 13:04:05:678"),
+            Some((
+                Date {
+                    years_since_2000: 12,
+                    month: 2,
+                    day: 29,
+                    days_from_sunday: 3,
+                },
+                Time {
+                    hour: 13,
+                    minute: 4,
+                    second: 5,
+                },
+            ))
+        );
+        assert_eq!(
+            parse_rtc_start("2000-JAN-01 00:00:00:000").map(|(date, _)| date.days_from_sunday),
+            Some(6)
+        );
+        assert_eq!(parse_rtc_start("2013-FEB-29 13:04:05:678"), None);
+        assert_eq!(parse_rtc_start("1999-JAN-01 00:00:00:000"), None);
+        assert_eq!(parse_rtc_start("2009-JAN-01 24:00:00:000"), None);
+    }
+
+    #[test]
+    fn import_frames() {
+        let movie = import(
+            "version 1\n\
+             rtcStartNew 2010-MAR-04 05:06:07:000\n\
+             |0|.............000 000 0|\n\
+             |1|R......A....D010 191 1|\n\
+             |4|.L...........300 002 1|\n",
+            Model::Ds,
+        )
+        .unwrap();
+        assert_eq!(movie.model, Model::Ds);
+        assert_eq!(
+            movie.rtc_start.1,
+            Time {
+                hour: 5,
+                minute: 6,
+                second: 7,
+            }
+        );
+        assert!(movie.savestate.is_none());
+        assert_eq!(movie.frames.len(), 3);
+
+        assert_eq!(movie.frames[0].pressed_keys, Keys::empty());
+        assert_eq!(movie.frames[0].touch_pos, None);
+
+        assert_eq!(
+            movie.frames[1].pressed_keys,
+            Keys::RIGHT | Keys::A | Keys::DEBUG
+        );
+        assert_eq!(movie.frames[1].touch_pos, Some([10 << 4, 191 << 4]));
+        assert!(movie.frames[1].mic_samples.is_some());
+        assert!(!movie.frames[1].lid_closed);
+
+        assert_eq!(movie.frames[2].pressed_keys, Keys::LEFT);
+        assert_eq!(movie.frames[2].touch_pos, Some([255 << 4, 2 << 4]));
+        assert!(movie.frames[2].mic_samples.is_none());
+        assert!(movie.frames[2].lid_closed);
+    }
+
+    #[test]
+    fn import_errors() {
+        assert_eq!(
+            import("version 2\n", Model::Ds).err(),
+            Some(ImportError::UnsupportedVersion("2".to_string()))
+        );
+        assert_eq!(
+            import(
+                "|0|.............000 000 0|\n|2|.............000 000 0|\n",
+                Model::Ds
+            )
+            .err(),
+            Some(ImportError::UnsupportedReset(1))
+        );
+        assert_eq!(
+            import("|0|....\n", Model::Ds).err(),
+            Some(ImportError::InvalidFrame(0))
+        );
+    }
+}
@@ -1,7 +1,15 @@
-use crate::utils::Savestate;
+use crate::{
+    cpu,
+    emu::{self, event_slots, Emu, Timestamp},
+    utils::{schedule::RawTimestamp, Savestate},
+};
 use core::any::Any;
 
-// TODO: Implement INT1 and INT2 (and also expose them)
+/// The interval between updates of the interrupt outputs, i.e. half the period of the fastest
+/// selectable INT1 frequency (16 Hz).
+const TICK_CYCLES: RawTimestamp = 1 << 20;
+const TICKS_PER_SECOND: u8 = 32;
+const SECOND_CYCLES: RawTimestamp = TICK_CYCLES * TICKS_PER_SECOND as RawTimestamp;
 
 proc_bitfield::bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
@@ -54,6 +62,37 @@ proc_bitfield::bitfield! {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Int1Mode {
+    Disabled,
+    /// Steady output of the frequencies selected in the INT1 register.
+    SelectedFrequency,
+    PerMinuteEdge,
+    /// Per-minute output, active for the first 30 seconds of each minute.
+    PerMinuteSteady1,
+    /// Alarm 1, using the INT1 register as the alarm time.
+    Alarm,
+    /// Per-minute output, active for ~7.9 ms at the start of each minute.
+    PerMinuteSteady2,
+    /// 32 kHz clock output; doesn't cause any interrupts.
+    Clock32K,
+}
+
+impl Status2 {
+    #[inline]
+    pub fn int1_mode_kind(self) -> Int1Mode {
+        match self.int1_mode() {
+            0 => Int1Mode::Disabled,
+            1 | 5 => Int1Mode::SelectedFrequency,
+            2 | 6 => Int1Mode::PerMinuteEdge,
+            3 => Int1Mode::PerMinuteSteady1,
+            4 => Int1Mode::Alarm,
+            7 => Int1Mode::PerMinuteSteady2,
+            _ => Int1Mode::Clock32K,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub struct Time {
     pub hour: u8,
@@ -76,44 +115,89 @@ pub trait Backend {
     fn get_time(&mut self) -> Time;
     fn get_date_time(&mut self) -> (Date, Time);
     fn set_date_time(&mut self, value: (Date, Time));
+
+    /// Called as emulated time passes, with the number of (ARM7) cycles elapsed since the last
+    /// call; only backends that follow emulated time need to handle this.
+    fn advance(&mut self, _cycles: RawTimestamp) {}
 }
 
-pub struct DummyBackend;
+mod backends;
+pub use backends::*;
 
-impl Backend for DummyBackend {
-    fn as_any(&self) -> &dyn Any {
-        self
+/// The number of days in the given month, using the RTC's leap year rule (every year divisible by
+/// 4 in 2000-2099).
+pub fn days_in_month(years_since_2000: u8, month: u8) -> u8 {
+    match month {
+        2 if years_since_2000 % 4 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
     }
+}
 
-    fn as_any_mut(&mut self) -> &mut dyn Any {
-        self
-    }
+/// Converts a number of days since 1970-01-01 to a proleptic Gregorian `(year, month, day)`
+/// triple.
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
 
-    fn get_time(&mut self) -> Time {
-        Time {
-            hour: 9,
-            minute: 27,
-            second: 45,
-        }
-    }
+/// The inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
 
-    fn get_date_time(&mut self) -> (Date, Time) {
-        (
-            Date {
-                years_since_2000: 22,
-                month: 1,
-                day: 26,
-                days_from_sunday: 3,
-            },
-            Time {
-                hour: 9,
-                minute: 27,
-                second: 45,
-            },
-        )
-    }
+/// Converts a Unix timestamp (in seconds) to the RTC's date and time representation; years
+/// outside 2000-2099 wrap around.
+pub fn date_time_from_timestamp(timestamp: i64) -> (Date, Time) {
+    let days = timestamp.div_euclid(86400);
+    let secs = timestamp.rem_euclid(86400) as u32;
+    let (year, month, day) = civil_from_days(days);
+    (
+        Date {
+            years_since_2000: (year - 2000).rem_euclid(100) as u8,
+            month,
+            day,
+            // January 1st, 1970 was a Thursday
+            days_from_sunday: (days + 4).rem_euclid(7) as u8,
+        },
+        Time {
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        },
+    )
+}
 
-    fn set_date_time(&mut self, _: (Date, Time)) {}
+/// Converts an RTC date and time to a Unix timestamp (in seconds), ignoring the day of the week.
+pub fn timestamp_from_date_time((date, time): (Date, Time)) -> i64 {
+    days_from_civil(
+        2000 + i64::from(date.years_since_2000),
+        date.month,
+        date.day,
+    ) * 86400
+        + i64::from(time.hour) * 3600
+        + i64::from(time.minute) * 60
+        + i64::from(time.second)
 }
 
 #[derive(Savestate)]
@@ -140,6 +224,9 @@ pub struct Rtc {
     int2: [u8; 3],
     pub clock_adjust: u8,
     pub free_reg: u8,
+    tick: u8,
+    int1_frequency_active: bool,
+    last_minute: u8,
 }
 
 fn from_bcd(value: u8) -> u8 {
@@ -154,8 +241,12 @@ impl Rtc {
     pub(crate) fn new(
         backend: Box<dyn Backend>,
         first_launch: bool,
+        emu_schedule: &mut emu::Schedule,
         #[cfg(feature = "log")] logger: slog::Logger,
     ) -> Self {
+        emu_schedule.set_event(event_slots::RTC, emu::Event::RtcTick);
+        emu_schedule.schedule_event(event_slots::RTC, Timestamp(TICK_CYCLES));
+
         Rtc {
             #[cfg(feature = "log")]
             logger,
@@ -176,6 +267,9 @@ impl Rtc {
             int2: [0; 3],
             clock_adjust: 0,
             free_reg: 0,
+            tick: 0,
+            int1_frequency_active: false,
+            last_minute: 0xFF,
         }
     }
 
@@ -214,6 +308,19 @@ impl Rtc {
         self.status1
     }
 
+    /// The INT1 register: the selected frequencies in the last byte, or the alarm 1 day of the
+    /// week, hour and minute when INT1 is in alarm mode.
+    #[inline]
+    pub fn int1(&self) -> [u8; 3] {
+        self.int1
+    }
+
+    /// The alarm 2 day of the week, hour and minute.
+    #[inline]
+    pub fn int2(&self) -> [u8; 3] {
+        self.int2
+    }
+
     #[inline]
     pub fn write_status1(&mut self, value: Status1) {
         self.status1.0 = (self.status1.0 & 0xF0) | (value.0 & 0x0E);
@@ -244,15 +351,97 @@ impl Rtc {
             #[cfg(feature = "log")]
             slog::warn!(self.logger, "Tried to enter unimplemented test mode");
         }
-        if value.int1_mode() != 0 {
-            #[cfg(feature = "log")]
-            slog::warn!(self.logger, "Tried to enable unimplemented alarm 1");
+        self.status2 = value;
+        // Per-minute interrupts and alarms should only trigger at the start of the next minute
+        self.last_minute = self.backend.get_time().minute;
+    }
+
+    fn alarm_matches(&self, alarm: [u8; 3], date: Date, time: Time) -> bool {
+        let hour_mask = if self.status1.is_in_24_hour_mode() {
+            0x3F
+        } else {
+            0x7F
+        };
+        let cur_hour = ((time.hour >= 12) as u8) << 6
+            | to_bcd(if self.status1.is_in_24_hour_mode() || time.hour < 12 {
+                time.hour
+            } else {
+                time.hour - 12
+            });
+        (alarm[0] & 0x80 == 0 || alarm[0] & 7 == date.days_from_sunday)
+            && (alarm[1] & 0x80 == 0 || (alarm[1] ^ cur_hour) & hour_mask == 0)
+            && (alarm[2] & 0x80 == 0 || alarm[2] & 0x7F == to_bcd(time.minute))
+    }
+
+    /// Updates the interrupt outputs, returning the INT1/INT2 interrupts that occurred during this
+    /// tick.
+    fn update_interrupts(&mut self) -> Status1 {
+        self.tick = (self.tick + 1) % TICKS_PER_SECOND;
+        let mut raised = Status1(0);
+
+        let int1_mode = self.status2.int1_mode_kind();
+        if int1_mode == Int1Mode::SelectedFrequency {
+            // Each selected frequency (1, 2, 4, 8 and 16 Hz in bits 0-4) is active during the
+            // first half of its period
+            let active = (0..5).any(|i| {
+                let half_period = TICKS_PER_SECOND >> (i + 1);
+                self.int1[2] & 1 << i != 0 && self.tick / half_period % 2 == 0
+            });
+            if active && !self.int1_frequency_active {
+                raised.set_int1_flag(true);
+            }
+            self.int1_frequency_active = active;
+        } else {
+            self.int1_frequency_active = false;
         }
-        if value.int2_enabled() {
-            #[cfg(feature = "log")]
-            slog::warn!(self.logger, "Tried to enable unimplemented alarm 2");
+
+        let uses_minutes = matches!(
+            int1_mode,
+            Int1Mode::PerMinuteEdge
+                | Int1Mode::PerMinuteSteady1
+                | Int1Mode::Alarm
+                | Int1Mode::PerMinuteSteady2
+        ) || self.status2.int2_enabled();
+        if uses_minutes && self.tick == 0 {
+            let (date, time) = self.backend.get_date_time();
+            if time.minute != self.last_minute {
+                self.last_minute = time.minute;
+                match int1_mode {
+                    Int1Mode::PerMinuteEdge
+                    | Int1Mode::PerMinuteSteady1
+                    | Int1Mode::PerMinuteSteady2 => raised.set_int1_flag(true),
+                    Int1Mode::Alarm => {
+                        if self.alarm_matches(self.int1, date, time) {
+                            raised.set_int1_flag(true);
+                        }
+                    }
+                    _ => {}
+                }
+                if self.status2.int2_enabled() && self.alarm_matches(self.int2, date, time) {
+                    raised.set_int2_flag(true);
+                }
+            }
+        }
+
+        // The flags are only latched for software to read back; /INT is driven by the interrupt
+        // conditions themselves, so it gets a new edge even if the previous one wasn't acknowledged
+        self.status1.0 |= raised.0;
+        raised
+    }
+
+    pub(crate) fn handle_tick(emu: &mut Emu<impl cpu::Engine>, time: Timestamp) {
+        emu.schedule
+            .schedule_event(event_slots::RTC, time + Timestamp(TICK_CYCLES));
+        emu.rtc.backend.advance(TICK_CYCLES);
+        let raised = emu.rtc.update_interrupts();
+        // /INT is connected to the SI pin, which only causes IRQs in general-purpose mode with SI
+        // IRQs enabled
+        if raised.0 != 0 && emu.rcnt() & 0xC100 == 0x8100 {
+            emu.arm7.irqs.write_requested(
+                emu.arm7.irqs.requested().with_sio_rtc(true),
+                &mut emu.arm7.schedule,
+            );
         }
-        self.status2 = value;
     }
 
     fn latch_date(&mut self, date: Date) {
@@ -546,3 +735,316 @@ impl Rtc {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::interpreter::Interpreter, test_utils};
+
+    /// Builds an instance using the default [`FixedBackend`] (Wednesday, 2022-01-26, 09:27:45).
+    fn build_emu() -> Emu<Interpreter> {
+        test_utils::build(test_utils::idle_emu_builder(), Interpreter)
+    }
+
+    fn set_time(rtc: &mut Rtc, hour: u8, minute: u8) {
+        let (date, _) = rtc.backend.get_date_time();
+        rtc.backend.set_date_time((
+            date,
+            Time {
+                hour,
+                minute,
+                second: 0,
+            },
+        ));
+    }
+
+    /// Runs the interrupt outputs for a second, returning the ticks at which INT1 and INT2
+    /// interrupts occurred.
+    fn run_second(rtc: &mut Rtc) -> (Vec<u8>, Vec<u8>) {
+        let mut int1 = Vec::new();
+        let mut int2 = Vec::new();
+        for _ in 0..TICKS_PER_SECOND {
+            let raised = rtc.update_interrupts();
+            if raised.int1_flag() {
+                int1.push(rtc.tick);
+            }
+            if raised.int2_flag() {
+                int2.push(rtc.tick);
+            }
+        }
+        (int1, int2)
+    }
+
+    #[test]
+    fn int1_mode_kinds() {
+        for (mode, kind) in [
+            (0, Int1Mode::Disabled),
+            (1, Int1Mode::SelectedFrequency),
+            (2, Int1Mode::PerMinuteEdge),
+            (3, Int1Mode::PerMinuteSteady1),
+            (4, Int1Mode::Alarm),
+            (5, Int1Mode::SelectedFrequency),
+            (6, Int1Mode::PerMinuteEdge),
+            (7, Int1Mode::PerMinuteSteady2),
+        ]
+        .into_iter()
+        .chain((8..16).map(|mode| (mode, Int1Mode::Clock32K)))
+        {
+            assert_eq!(Status2(0).with_int1_mode(mode).int1_mode_kind(), kind);
+        }
+    }
+
+    #[test]
+    fn selected_frequencies_rise_at_start_of_period() {
+        for i in 0..5 {
+            let mut emu = build_emu();
+            let rtc = &mut emu.rtc;
+            rtc.write_status2(Status2(0).with_int1_mode(1));
+            rtc.int1[2] = 1 << i;
+            run_second(rtc);
+
+            let period = TICKS_PER_SECOND >> i;
+            let expected = (0..TICKS_PER_SECOND)
+                .map(|tick| (tick + 1) % TICKS_PER_SECOND)
+                .filter(|tick| tick % period == 0)
+                .collect::<Vec<_>>();
+            for _ in 0..2 {
+                let (int1, int2) = run_second(rtc);
+                assert_eq!(int1, expected, "wrong edges for {} Hz", 1 << i);
+                assert!(int2.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn selected_frequencies_are_combined() {
+        let mut emu = build_emu();
+        let rtc = &mut emu.rtc;
+        rtc.write_status2(Status2(0).with_int1_mode(5));
+        // 1 Hz and 2 Hz: the 2 Hz output's second active half-period directly follows the 1 Hz
+        // output's, so there's a single edge per second
+        rtc.int1[2] = 3;
+        run_second(rtc);
+        assert_eq!(run_second(rtc).0, [0]);
+    }
+
+    #[test]
+    fn per_minute_modes_raise_int1_once_per_minute() {
+        for mode in [2, 3, 6, 7] {
+            let mut emu = build_emu();
+            let rtc = &mut emu.rtc;
+            rtc.int1[2] = 0x1F;
+            rtc.write_status2(Status2(0).with_int1_mode(mode));
+            assert!(
+                run_second(rtc).0.is_empty(),
+                "mode {mode} raised INT1 within the current minute"
+            );
+
+            set_time(rtc, 9, 28);
+            let (int1, int2) = run_second(rtc);
+            assert_eq!(
+                int1,
+                [0],
+                "mode {mode} didn't raise INT1 at the start of the minute"
+            );
+            assert!(int2.is_empty());
+            assert!(run_second(rtc).0.is_empty());
+            assert!(rtc.status1().int1_flag());
+
+            set_time(rtc, 9, 29);
+            assert_eq!(run_second(rtc).0, [0]);
+        }
+    }
+
+    #[test]
+    fn disabled_and_clock_modes_dont_raise_int1() {
+        for mode in [0, 8] {
+            let mut emu = build_emu();
+            let rtc = &mut emu.rtc;
+            rtc.int1[2] = 0x1F;
+            rtc.write_status2(Status2(0).with_int1_mode(mode));
+            run_second(rtc);
+            set_time(rtc, 9, 28);
+            assert!(run_second(rtc).0.is_empty(), "mode {mode} raised INT1");
+            assert!(!rtc.status1().int1_flag());
+        }
+    }
+
+    fn alarm_raised(
+        rtc: &mut Rtc,
+        is_in_24_hour_mode: bool,
+        alarm: [u8; 3],
+        (hour, minute): (u8, u8),
+    ) -> (bool, bool) {
+        rtc.write_status1(Status1(0).with_is_in_24_hour_mode(is_in_24_hour_mode));
+        rtc.int1 = alarm;
+        rtc.int2 = alarm;
+        set_time(rtc, hour, minute.wrapping_sub(1));
+        run_second(rtc);
+        set_time(rtc, hour, minute);
+        let (int1, int2) = run_second(rtc);
+        (!int1.is_empty(), !int2.is_empty())
+    }
+
+    #[test]
+    fn alarms_match_bcd_date_and_time() {
+        let mut emu = build_emu();
+        let rtc = &mut emu.rtc;
+        rtc.write_status2(Status2(0).with_int1_mode(4).with_int2_enabled(true));
+
+        for (is_in_24_hour_mode, alarm, time, expected) in [
+            // Day of the week, hour and minute all compared
+            (true, [0x83, 0x89, 0xA8], (9, 28), true),
+            (true, [0x84, 0x89, 0xA8], (9, 28), false),
+            (true, [0x83, 0x88, 0xA8], (9, 28), false),
+            (true, [0x83, 0x89, 0xA9], (9, 28), false),
+            // Fields without bit 7 set are ignored
+            (true, [0x04, 0x08, 0xA8], (9, 28), true),
+            (true, [0x00, 0x00, 0x00], (9, 28), true),
+            // BCD hours and minutes
+            (true, [0x00, 0x80 | 0x21, 0x80 | 0x59], (21, 59), true),
+            (true, [0x00, 0x80 | 0x15, 0x80 | 0x10], (21, 10), false),
+            // The AM/PM bit is ignored in 24-hour mode, and compared in 12-hour mode
+            (true, [0x00, 0x80 | 0x61, 0x80 | 0x05], (21, 5), true),
+            (false, [0x00, 0x80 | 0x49, 0x80 | 0x05], (21, 5), true),
+            (false, [0x00, 0x80 | 0x09, 0x80 | 0x05], (21, 5), false),
+            (false, [0x00, 0x80 | 0x09, 0x80 | 0x05], (9, 5), true),
+            (false, [0x00, 0x80 | 0x40, 0x80 | 0x30], (12, 30), true),
+        ] {
+            assert_eq!(
+                alarm_raised(rtc, is_in_24_hour_mode, alarm, time),
+                (expected, expected),
+                "alarm {alarm:02X?} at {time:?} (24-hour mode: {is_in_24_hour_mode})",
+            );
+        }
+    }
+
+    #[test]
+    fn int2_is_independent_of_int1_mode() {
+        let mut emu = build_emu();
+        let rtc = &mut emu.rtc;
+        rtc.write_status1(Status1(0).with_is_in_24_hour_mode(true));
+        rtc.write_status2(Status2(0).with_int1_mode(2).with_int2_enabled(true));
+        rtc.int2 = [0x00, 0x89, 0xA8];
+        set_time(rtc, 9, 28);
+        let (int1, int2) = run_second(rtc);
+        assert_eq!(int1, [0]);
+        assert_eq!(int2, [0]);
+        assert!(rtc.status1().int1_flag() && rtc.status1().int2_flag());
+
+        // Disabling INT2 stops the alarm even when its time matches again
+        rtc.write_status2(Status2(0));
+        rtc.int1[2] = 0;
+        set_time(rtc, 9, 27);
+        run_second(rtc);
+        set_time(rtc, 9, 28);
+        assert_eq!(run_second(rtc), (vec![], vec![]));
+    }
+
+    fn is_leap_year(year: i64) -> bool {
+        year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+    }
+
+    #[test]
+    fn civil_days_known_dates() {
+        for (days, date) in [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (10_957, (2000, 1, 1)),
+            (11_017, (2000, 3, 1)),
+            (19_782, (2024, 2, 29)),
+            (-135_140, (1600, 1, 1)),
+            (157_419, (2400, 12, 31)),
+        ] {
+            assert_eq!(civil_from_days(days), date);
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days);
+        }
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        let mut prev = civil_from_days(-135_141);
+        assert_eq!(prev, (1599, 12, 31));
+        for days in -135_140..=157_419 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+
+            // Each day follows the previous one, with the Gregorian leap year rule
+            let (prev_year, prev_month, prev_day) = prev;
+            let prev_month_len = match prev_month {
+                2 if is_leap_year(prev_year) => 29,
+                2 => 28,
+                4 | 6 | 9 | 11 => 30,
+                _ => 31,
+            };
+            let expected = if prev_day < prev_month_len {
+                (prev_year, prev_month, prev_day + 1)
+            } else if prev_month < 12 {
+                (prev_year, prev_month + 1, 1)
+            } else {
+                (prev_year + 1, 1, 1)
+            };
+            assert_eq!((year, month, day), expected, "{days} days since the epoch");
+            prev = (year, month, day);
+        }
+    }
+
+    #[test]
+    fn leap_years() {
+        for (year, is_leap) in [
+            (1900, false),
+            (2000, true),
+            (2023, false),
+            (2024, true),
+            (2100, false),
+        ] {
+            let feb_28 = days_from_civil(year, 2, 28);
+            let expected = if is_leap { (year, 2, 29) } else { (year, 3, 1) };
+            assert_eq!(civil_from_days(feb_28 + 1), expected, "year {year}");
+        }
+        // The RTC's own rule only needs to hold in 2000-2099
+        for years_since_2000 in 0..100 {
+            assert_eq!(
+                days_in_month(years_since_2000, 2) == 29,
+                is_leap_year(2000 + i64::from(years_since_2000)),
+            );
+        }
+    }
+
+    #[test]
+    fn weekdays_from_timestamps() {
+        for (date, days_from_sunday) in [
+            ((1970, 1, 1), 4),
+            ((1969, 12, 31), 3),
+            ((2000, 1, 1), 6),
+            ((2000, 3, 1), 3),
+            ((2009, 1, 1), 4),
+            ((2024, 2, 29), 4),
+            ((2099, 12, 31), 4),
+        ] {
+            let timestamp = days_from_civil(date.0, date.1, date.2) * 86400 + 12 * 3600;
+            assert_eq!(
+                date_time_from_timestamp(timestamp).0.days_from_sunday,
+                days_from_sunday,
+                "{date:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        for timestamp in [946_684_800, 951_782_400, 1_709_164_799, 4_102_444_799]
+            .into_iter()
+            // A time in every hour of 2024
+            .chain((0..366 * 24).map(|hours| 1_704_067_200 + hours * 3600 + 30 * 60 + 15))
+        {
+            let date_time = date_time_from_timestamp(timestamp);
+            assert_eq!(timestamp_from_date_time(date_time), timestamp);
+        }
+
+        // Years outside 2000-2099 wrap around
+        let (date, time) = date_time_from_timestamp(4_102_444_800);
+        assert_eq!((date.years_since_2000, date.month, date.day), (0, 1, 1));
+        assert_eq!(time, Time::default());
+    }
+}
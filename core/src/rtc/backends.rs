@@ -0,0 +1,167 @@
+use super::{
+    date_time_from_timestamp, timestamp_from_date_time, Backend, Date, Time, SECOND_CYCLES,
+};
+use crate::utils::schedule::RawTimestamp;
+use core::any::Any;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A backend that always reports the same date and time, unless the game changes it.
+pub struct FixedBackend {
+    pub date: Date,
+    pub time: Time,
+}
+
+impl FixedBackend {
+    pub fn new((date, time): (Date, Time)) -> Self {
+        FixedBackend { date, time }
+    }
+}
+
+impl Default for FixedBackend {
+    fn default() -> Self {
+        FixedBackend {
+            date: Date {
+                years_since_2000: 22,
+                month: 1,
+                day: 26,
+                days_from_sunday: 3,
+            },
+            time: Time {
+                hour: 9,
+                minute: 27,
+                second: 45,
+            },
+        }
+    }
+}
+
+impl Backend for FixedBackend {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_time(&mut self) -> Time {
+        self.time
+    }
+
+    fn get_date_time(&mut self) -> (Date, Time) {
+        (self.date, self.time)
+    }
+
+    fn set_date_time(&mut self, (date, time): (Date, Time)) {
+        self.date = date;
+        self.time = time;
+    }
+}
+
+/// A backend that starts at a given date and time and then advances with emulated time, so that
+/// the clock seen by the game only depends on how long it has been running for.
+pub struct CycleBackend {
+    timestamp: i64,
+    cycles: RawTimestamp,
+}
+
+impl CycleBackend {
+    pub fn new(date_time: (Date, Time)) -> Self {
+        CycleBackend {
+            timestamp: timestamp_from_date_time(date_time),
+            cycles: 0,
+        }
+    }
+}
+
+impl Backend for CycleBackend {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_time(&mut self) -> Time {
+        date_time_from_timestamp(self.timestamp).1
+    }
+
+    fn get_date_time(&mut self) -> (Date, Time) {
+        date_time_from_timestamp(self.timestamp)
+    }
+
+    fn set_date_time(&mut self, date_time: (Date, Time)) {
+        self.timestamp = timestamp_from_date_time(date_time);
+        self.cycles = 0;
+    }
+
+    fn advance(&mut self, cycles: RawTimestamp) {
+        self.cycles += cycles;
+        self.timestamp += (self.cycles / SECOND_CYCLES) as i64;
+        self.cycles %= SECOND_CYCLES;
+    }
+}
+
+/// A backend following the host's clock, shifted by a configurable offset.
+///
+/// The host clock is read in UTC; to follow local time, a function returning the host's current
+/// UTC offset can be supplied through [`with_utc_offset`](Self::with_utc_offset). It's called on
+/// every read, so that changes to the offset (e.g. across DST changes) are followed immediately.
+pub struct HostClockBackend {
+    time_offset_seconds: i64,
+    utc_offset_seconds: fn() -> i64,
+}
+
+impl HostClockBackend {
+    pub fn new(time_offset_seconds: i64) -> Self {
+        Self::with_utc_offset(time_offset_seconds, || 0)
+    }
+
+    pub fn with_utc_offset(time_offset_seconds: i64, utc_offset_seconds: fn() -> i64) -> Self {
+        HostClockBackend {
+            time_offset_seconds,
+            utc_offset_seconds,
+        }
+    }
+
+    #[inline]
+    pub fn time_offset_seconds(&self) -> i64 {
+        self.time_offset_seconds
+    }
+
+    #[inline]
+    pub fn set_time_offset_seconds(&mut self, value: i64) {
+        self.time_offset_seconds = value;
+    }
+
+    fn host_timestamp(&self) -> i64 {
+        let utc = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        };
+        utc + (self.utc_offset_seconds)()
+    }
+}
+
+impl Backend for HostClockBackend {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_time(&mut self) -> Time {
+        date_time_from_timestamp(self.host_timestamp() + self.time_offset_seconds).1
+    }
+
+    fn get_date_time(&mut self) -> (Date, Time) {
+        date_time_from_timestamp(self.host_timestamp() + self.time_offset_seconds)
+    }
+
+    fn set_date_time(&mut self, date_time: (Date, Time)) {
+        self.time_offset_seconds = timestamp_from_date_time(date_time) - self.host_timestamp();
+    }
+}
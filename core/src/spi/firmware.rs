@@ -9,7 +9,7 @@ static CRC16_VALUES: [u16; 8] = [
     0xC0C1, 0xC181, 0xC301, 0xC601, 0xCC01, 0xD801, 0xF001, 0xA001,
 ];
 
-fn crc16(init: u16, bytes: &[u8]) -> u16 {
+pub(crate) fn crc16(init: u16, bytes: &[u8]) -> u16 {
     let mut result = init as u32;
     for &byte in bytes {
         result ^= byte as u32;
@@ -193,13 +193,31 @@ pub fn detect_model(firmware: &[u8]) -> Result<Option<Model>, ModelDetectionErro
     }
 }
 
+/// Returns whether a user settings copy's CRC16 value matches its contents.
+pub fn are_user_settings_valid(user_settings: &[u8]) -> bool {
+    crc16(0xFFFF, &user_settings[..0x70]) == user_settings.read_le::<u16>(0x72)
+}
+
+/// Returns the user settings copy the firmware would use: the only valid one if the other one is
+/// corrupted, or otherwise the one with the highest update count.
 pub fn newest_user_settings(firmware: &[u8]) -> &[u8] {
     let user_settings_offset = (firmware.read_le::<u16>(0x20) as usize) << 3;
-    let count_0 = firmware.read_le::<u16>(user_settings_offset + 0x70);
-    let count_1 = firmware.read_le::<u16>(user_settings_offset + 0x170);
-    if count_1 == (count_0 + 1) & 0x7F {
-        &firmware[user_settings_offset + 0x100..user_settings_offset + 0x200]
-    } else {
-        &firmware[user_settings_offset..user_settings_offset + 0x100]
+    let copy_0 = &firmware[user_settings_offset..user_settings_offset + 0x100];
+    let copy_1 = &firmware[user_settings_offset + 0x100..user_settings_offset + 0x200];
+    match (
+        are_user_settings_valid(copy_0),
+        are_user_settings_valid(copy_1),
+    ) {
+        (true, false) => copy_0,
+        (false, true) => copy_1,
+        _ => {
+            let count_0 = copy_0.read_le::<u16>(0x70);
+            let count_1 = copy_1.read_le::<u16>(0x70);
+            if count_1 == (count_0 + 1) & 0x7F {
+                copy_1
+            } else {
+                copy_0
+            }
+        }
     }
 }
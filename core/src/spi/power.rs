@@ -80,6 +80,7 @@ pub struct Power {
     mic_amplifier_gain: u8,
     ds_lite_backlight_control: DsLiteBacklightControl,
     ds_lite_backlight_level: DsLiteBacklightLevel,
+    sleeping: bool,
 }
 
 impl Power {
@@ -107,6 +108,7 @@ impl Power {
             mic_amplifier_gain: 0,
             ds_lite_backlight_control: DsLiteBacklightControl(0x40),
             ds_lite_backlight_level: DsLiteBacklightLevel::Low,
+            sleeping: false,
         }
     }
 
@@ -125,6 +127,30 @@ impl Power {
         self.control
     }
 
+    /// Returns whether the system is in sleep mode, during which both backlights and the sound
+    /// amplifier are powered down regardless of the control register.
+    #[inline]
+    pub fn sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    /// Enters sleep mode as requested through `HALTCNT`, unless an ARM7 wakeup IRQ is already
+    /// pending.
+    pub(crate) fn enter_sleep(
+        &mut self,
+        arm7_irqs: &mut arm7::Irqs,
+        arm7_schedule: &mut arm7::Schedule,
+    ) {
+        arm7_irqs.sleep(arm7_schedule);
+        self.sleeping = arm7_irqs.sleeping();
+    }
+
+    /// Leaves sleep mode, once the ARM7 has been woken up by an IRQ.
+    #[inline]
+    pub(crate) fn wake_up(&mut self) {
+        self.sleeping = false;
+    }
+
     #[inline]
     pub fn request_shutdown(
         &mut self,
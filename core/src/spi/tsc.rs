@@ -3,6 +3,7 @@ use crate::{
     emu::{input, Timestamp},
     utils::{zeroed_box, Savestate},
 };
+use core::any::Any;
 
 proc_bitfield::bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
@@ -18,6 +19,8 @@ proc_bitfield::bitfield! {
 pub const MIC_SAMPLES_PER_FRAME: usize = (6 * 355 * 263 + 128) / 128;
 
 pub trait MicBackend {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
     fn start_frame(&mut self);
     fn read_frame_samples(&mut self, offset: usize, samples: &mut [i16]);
 }
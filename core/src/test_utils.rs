@@ -0,0 +1,140 @@
+//! Helpers shared by the unit tests that need a whole emulator instance.
+
+use crate::{
+    audio, ds_slot,
+    emu::{self, Emu},
+    flash::Flash,
+    gpu::{
+        engine_2d::{self, Engine2d, EngineA, EngineB},
+        engine_3d::{self, Polygon, RenderingState, ScreenVertex},
+        vram::Vram,
+        Framebuffer,
+    },
+    rtc,
+    spi::firmware,
+    utils::{mem_prelude::*, zeroed_box, BoxedByteSlice, Bytes},
+    Model, SaveContents,
+};
+
+struct NullRenderer2d(Box<Framebuffer>);
+
+impl engine_2d::Renderer for NullRenderer2d {
+    fn uses_bg_obj_vram_tracking(&self) -> bool {
+        false
+    }
+
+    fn uses_lcdc_vram_tracking(&self) -> bool {
+        false
+    }
+
+    fn framebuffer(&self) -> &Framebuffer {
+        &self.0
+    }
+
+    fn start_prerendering_objs(
+        &mut self,
+        _engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
+        _vram: &mut Vram,
+    ) {
+    }
+
+    fn start_scanline(
+        &mut self,
+        _line: u8,
+        _vcount: u8,
+        _engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
+        _vram: &mut Vram,
+    ) {
+    }
+
+    fn finish_scanline(
+        &mut self,
+        _line: u8,
+        _vcount: u8,
+        _engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
+        _vram: &mut Vram,
+    ) {
+    }
+}
+
+struct NullRenderer3dTx;
+
+impl engine_3d::RendererTx for NullRenderer3dTx {
+    fn set_capture_enabled(&mut self, _capture_enabled: bool) {}
+
+    fn swap_buffers(
+        &mut self,
+        _vert_ram: &[ScreenVertex],
+        _poly_ram: &[Polygon],
+        _state: &RenderingState,
+    ) {
+    }
+
+    fn repeat_last_frame(&mut self, _state: &RenderingState) {}
+
+    fn start_rendering(
+        &mut self,
+        _texture: &Bytes<0x8_0000>,
+        _tex_pal: &Bytes<0x1_8000>,
+        _state: &RenderingState,
+    ) {
+    }
+
+    fn skip_rendering(&mut self) {}
+}
+
+/// Returns a builder for a direct-booted DS whose CPUs only run an idle loop, so that the hardware
+/// can be driven directly.
+pub fn idle_emu_builder() -> emu::Builder {
+    #[cfg(feature = "log")]
+    let logger = slog::Logger::root(slog::Discard, slog::o!());
+
+    // `b .` for both CPUs, loaded to main RAM and ARM7 WRAM respectively
+    let mut rom = BoxedByteSlice::new_zeroed(0x1000);
+    rom.write_le(0x200, 0xEAFF_FFFE_u32);
+    for (offset, value) in [
+        (0x20, 0x200),
+        (0x24, 0x0200_0000),
+        (0x28, 0x0200_0000),
+        (0x2C, 4),
+        (0x30, 0x200),
+        (0x34, 0x0380_F000),
+        (0x38, 0x0380_F000),
+        (0x3C, 4),
+    ] {
+        rom.write_le(offset, value as u32);
+    }
+
+    let mut emu_builder = emu::Builder::new(
+        Flash::new(
+            SaveContents::Existing(firmware::default(Model::Ds)),
+            firmware::id_for_model(Model::Ds),
+            #[cfg(feature = "log")]
+            logger.clone(),
+        )
+        .unwrap_or_else(|_| panic!("couldn't build firmware")),
+        Some(Box::new(rom)),
+        ds_slot::spi::Empty::new(
+            #[cfg(feature = "log")]
+            logger.clone(),
+        )
+        .into(),
+        Box::new(audio::DummyBackend),
+        None,
+        Box::new(rtc::FixedBackend::default()),
+        Box::new(NullRenderer2d(zeroed_box())),
+        Box::new(NullRenderer3dTx),
+        None,
+        #[cfg(feature = "log")]
+        logger,
+    );
+    emu_builder.direct_boot = true;
+    emu_builder
+}
+
+pub fn build<E: crate::cpu::Engine>(emu_builder: emu::Builder, engine: E) -> Emu<E> {
+    let Ok(emu) = emu_builder.build(engine) else {
+        panic!("couldn't build emulator instance");
+    };
+    emu
+}
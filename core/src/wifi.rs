@@ -1,18 +1,287 @@
 mod io;
+pub mod link;
 
-use crate::utils::{zeroed_box, Bytes, Savestate};
+use crate::{
+    cpu::{
+        arm7::{self, ScheduleUpdate},
+        Engine, Schedule as _,
+    },
+    emu::Emu,
+    utils::{mem_prelude::*, schedule::RawTimestamp, zeroed_box, Savestate},
+};
+use core::any::Any;
+
+// TODO:
+// - WEP encryption/decryption (W_MODE_WEP/W_WEP_CNT) isn't implemented, frames are always sent and
+//   received in plaintext
+// - The RX filters (W_RXFILTER/W_RXFILTER2) are only partially respected
+// - TX retries and ACK handling (W_TX_RETRYLIMIT, W_TXSTAT error codes) aren't emulated, every
+//   transfer is assumed to succeed on the first try
+
+// The ARM7 runs at 2^25 Hz; timing-related registers are all expressed in microseconds.
+const SYS_CLOCK_RATE: RawTimestamp = 1 << 25;
+
+// How often (in microseconds) the Wi-Fi hardware state gets updated while it's active
+const TICK_US: RawTimestamp = 8;
+const TICK_CYCLES: RawTimestamp = TICK_US * SYS_CLOCK_RATE / 1_000_000;
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct IrqFlags(pub u16): Debug {
+        pub rx_complete: bool @ 0,
+        pub tx_complete: bool @ 1,
+        pub rx_event_inc: bool @ 2,
+        pub tx_error_inc: bool @ 3,
+        pub rx_event_half_overflow: bool @ 4,
+        pub tx_error_half_overflow: bool @ 5,
+        pub rx_start: bool @ 6,
+        pub tx_start: bool @ 7,
+        pub txbuf_count_expired: bool @ 8,
+        pub rxbuf_count_expired: bool @ 9,
+        pub rf_wakeup: bool @ 11,
+        pub multiplay_cmd_done: bool @ 12,
+        pub post_beacon: bool @ 13,
+        pub beacon: bool @ 14,
+        pub pre_beacon: bool @ 15,
+    }
+}
+
+proc_bitfield::bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq, Savestate)]
+    pub struct TxSlotControl(pub u16): Debug {
+        pub halfword_addr: u16 @ 0..=11,
+        pub ignore_seq_number: bool @ 13,
+        pub enabled: bool @ 15,
+    }
+}
+
+/// The transfer rate of a frame, as stored in the TX/RX headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Savestate)]
+pub enum Rate {
+    OneMbps,
+    TwoMbps,
+}
+
+impl Rate {
+    #[inline]
+    pub fn from_header_byte(value: u8) -> Self {
+        if value == 0x14 {
+            Rate::TwoMbps
+        } else {
+            Rate::OneMbps
+        }
+    }
+
+    #[inline]
+    pub fn header_byte(self) -> u8 {
+        match self {
+            Rate::OneMbps => 0x0A,
+            Rate::TwoMbps => 0x14,
+        }
+    }
+
+    /// Returns the time it takes to transfer `len` bytes, in microseconds.
+    #[inline]
+    pub fn transfer_time_us(self, len: usize) -> RawTimestamp {
+        match self {
+            Rate::OneMbps => len as RawTimestamp * 8,
+            Rate::TwoMbps => len as RawTimestamp * 4,
+        }
+    }
+}
+
+/// An 802.11 frame received by the emulated Wi-Fi hardware. `data` doesn't include the trailing
+/// FCS.
+pub struct RxFrame {
+    pub data: Vec<u8>,
+    pub rate: Rate,
+}
+
+pub trait Backend {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Called whenever a frame has finished being transmitted; `data` doesn't include the trailing
+    /// FCS. `time_us` is the value of the hardware's internal microsecond clock at the end of the
+    /// transfer, which is monotonic and unaffected by writes to the TSF counter.
+    fn transmit_frame(&mut self, data: &[u8], rate: Rate, time_us: u64);
+
+    /// Polled periodically while reception is enabled, `time_us` is the hardware's current internal
+    /// microsecond clock value; should return the next frame that's due to be received at or before
+    /// that time, if any.
+    fn receive_frame(&mut self, time_us: u64) -> Option<RxFrame>;
+}
+
+pub struct DummyBackend;
+
+impl Backend for DummyBackend {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn transmit_frame(&mut self, _data: &[u8], _rate: Rate, _time_us: u64) {}
+
+    fn receive_frame(&mut self, _time_us: u64) -> Option<RxFrame> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Savestate)]
+pub enum TxSlot {
+    Loc1,
+    Cmd,
+    Loc2,
+    Loc3,
+    Beacon,
+}
+
+impl TxSlot {
+    #[inline]
+    fn req_mask(self) -> u16 {
+        match self {
+            TxSlot::Loc1 => 1 << 0,
+            TxSlot::Cmd => 1 << 1,
+            TxSlot::Loc2 => 1 << 2,
+            TxSlot::Loc3 => 1 << 3,
+            TxSlot::Beacon => 0,
+        }
+    }
+
+    #[inline]
+    fn stat_index(self) -> u16 {
+        match self {
+            TxSlot::Loc1 => 0,
+            TxSlot::Cmd => 1,
+            TxSlot::Loc2 => 2,
+            TxSlot::Loc3 => 3,
+            TxSlot::Beacon => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Savestate)]
+struct TxTransfer {
+    slot: TxSlot,
+    header_addr: u16,
+    end_time_us: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Savestate)]
+pub enum RfStatus {
+    Disabled,
+    Idle,
+    Transmitting,
+    Receiving,
+    PoweredDown,
+}
+
+impl RfStatus {
+    #[inline]
+    fn reg_value(self) -> u16 {
+        match self {
+            RfStatus::Disabled => 0,
+            RfStatus::Idle => 1,
+            RfStatus::Transmitting => 3,
+            RfStatus::Receiving => 6,
+            RfStatus::PoweredDown => 9,
+        }
+    }
+}
 
 #[derive(Savestate)]
+#[load(in_place_only)]
 pub struct WiFi {
-    pub mmio: Box<Bytes<0x1000>>,
+    #[cfg(feature = "log")]
+    #[savestate(skip)]
+    logger: slog::Logger,
+    #[savestate(skip)]
+    pub backend: Box<dyn Backend>,
+    #[savestate(skip)]
+    rx_frame_buffer: Vec<u8>,
+
+    /// Backing storage for all registers that don't have dedicated fields.
+    regs: Box<Bytes<0x1000>>,
     pub ram: Box<Bytes<0x2000>>,
     bb_regs: [u8; 0x100],
+    rf_regs: [u32; 0x40],
+
+    is_ticking: bool,
+    last_update_time: arm7::Timestamp,
+    us_fract: RawTimestamp,
+    /// Monotonic microsecond clock, only used for internal timing.
+    internal_us: u64,
+
+    irqs_requested: IrqFlags,
+    irqs_enabled: IrqFlags,
+    random: u16,
+
+    mode_rst: u16,
+    rf_status: RfStatus,
+    power_state: u16,
+    wakeup_pending: bool,
+
+    // Multiplayer counters
+    us_counter_enabled: bool,
+    us_counter: u64,
+    us_compare_enabled: bool,
+    us_compare: u64,
+    beacon_count: u16,
+    post_beacon_time_us: Option<u64>,
+    cmd_count_enabled: bool,
+    cmd_count: u16,
+
+    // TX
+    tx_slots: [TxSlotControl; 5],
+    tx_req: u16,
+    tx_busy: u16,
+    tx_stat: u16,
+    tx_seq_number: u16,
+    cur_tx: Option<TxTransfer>,
+    txbuf_write_addr: u16,
+    txbuf_count: u16,
+    txbuf_gap: u16,
+    txbuf_gap_disp: u16,
+
+    // RX
+    rx_control: u16,
+    rxbuf_begin: u16,
+    rxbuf_end: u16,
+    rxbuf_write_csr: u16,
+    rxbuf_write_addr: u16,
+    rxbuf_read_addr: u16,
+    rxbuf_read_csr: u16,
+    rxbuf_count: u16,
+    rxbuf_gap: u16,
+    rxbuf_gap_disp: u16,
+    rx_end_time_us: Option<u64>,
+}
+
+/// Returns the number of bytes that can be written to the RX buffer spanning `begin..end` without
+/// reaching the read pointer, keeping a halfword free so that a full buffer can be told apart from
+/// an empty one.
+///
+/// The pointers are written by the game and aren't guaranteed to be inside the buffer, so the
+/// distance between them is taken modulo its size.
+fn rxbuf_free_space(begin: u16, end: u16, write: u16, read: u16) -> usize {
+    if end <= begin {
+        return 0;
+    }
+    let size = (end - begin) as usize;
+    let used = (write as usize % size + size - read as usize % size) % size;
+    size.saturating_sub(used + 2)
 }
 
 impl WiFi {
-    pub(crate) fn new() -> Self {
-        let mut mmio = zeroed_box::<Bytes<0x1000>>();
-        mmio[0x3D] = 0x02;
+    pub(crate) fn new(
+        backend: Box<dyn Backend>,
+        arm7_schedule: &mut arm7::Schedule,
+        #[cfg(feature = "log")] logger: slog::Logger,
+    ) -> Self {
+        arm7_schedule.set_event(arm7::event_slots::WIFI, arm7::Event::WifiTick);
 
         let mut bb_regs = [0; 0x100];
         bb_regs[0x00] = 0x6D;
@@ -21,9 +290,510 @@ impl WiFi {
         bb_regs[0x64] = 0xFF; // ???
 
         WiFi {
-            mmio,
+            #[cfg(feature = "log")]
+            logger,
+            backend,
+            rx_frame_buffer: Vec::new(),
+
+            regs: zeroed_box(),
             ram: zeroed_box(),
             bb_regs,
+            rf_regs: [0; 0x40],
+
+            is_ticking: false,
+            last_update_time: arm7::Timestamp(0),
+            us_fract: 0,
+            internal_us: 0,
+
+            irqs_requested: IrqFlags(0),
+            irqs_enabled: IrqFlags(0),
+            random: 1,
+
+            mode_rst: 0,
+            rf_status: RfStatus::Disabled,
+            power_state: 0x0200,
+            wakeup_pending: false,
+
+            us_counter_enabled: false,
+            us_counter: 0,
+            us_compare_enabled: false,
+            us_compare: 0,
+            beacon_count: 0,
+            post_beacon_time_us: None,
+            cmd_count_enabled: false,
+            cmd_count: 0,
+
+            tx_slots: [TxSlotControl(0); 5],
+            tx_req: 0,
+            tx_busy: 0,
+            tx_stat: 0,
+            tx_seq_number: 0,
+            cur_tx: None,
+            txbuf_write_addr: 0,
+            txbuf_count: 0,
+            txbuf_gap: 0,
+            txbuf_gap_disp: 0,
+
+            rx_control: 0,
+            rxbuf_begin: 0,
+            rxbuf_end: 0,
+            rxbuf_write_csr: 0,
+            rxbuf_write_addr: 0,
+            rxbuf_read_addr: 0,
+            rxbuf_read_csr: 0,
+            rxbuf_count: 0,
+            rxbuf_gap: 0,
+            rxbuf_gap_disp: 0,
+            rx_end_time_us: None,
+        }
+    }
+
+    #[inline]
+    pub fn irqs_requested(&self) -> IrqFlags {
+        self.irqs_requested
+    }
+
+    #[inline]
+    pub fn irqs_enabled(&self) -> IrqFlags {
+        self.irqs_enabled
+    }
+
+    #[inline]
+    pub fn rf_status(&self) -> RfStatus {
+        self.rf_status
+    }
+
+    #[inline]
+    pub fn us_counter(&self) -> u64 {
+        self.us_counter
+    }
+
+    #[inline]
+    pub fn internal_us(&self) -> u64 {
+        self.internal_us
+    }
+
+    #[inline]
+    pub fn tx_slot(&self, slot: TxSlot) -> TxSlotControl {
+        self.tx_slots[slot.stat_index() as usize]
+    }
+
+    #[inline]
+    pub fn is_powered_down(&self) -> bool {
+        self.power_state & 0x0200 != 0
+    }
+
+    #[inline]
+    fn is_active(&self) -> bool {
+        self.mode_rst & 1 != 0 || self.us_counter_enabled
+    }
+
+    fn update_irq_line<S: ScheduleUpdate>(&self, arm7_irqs: &mut arm7::Irqs, schedule: S) {
+        if self.irqs_requested.0 & self.irqs_enabled.0 != 0 {
+            arm7_irqs.write_requested(arm7_irqs.requested().with_wifi(true), schedule);
+        }
+    }
+
+    fn request_irqs<S: ScheduleUpdate>(
+        &mut self,
+        value: IrqFlags,
+        arm7_irqs: &mut arm7::Irqs,
+        schedule: S,
+    ) {
+        let newly_requested = value.0 & !self.irqs_requested.0;
+        self.irqs_requested.0 |= value.0;
+        if newly_requested & self.irqs_enabled.0 != 0 {
+            arm7_irqs.write_requested(arm7_irqs.requested().with_wifi(true), schedule);
+        }
+    }
+
+    /// Advances the internal microsecond clock and the TSF counter to the given time, returning the
+    /// number of elapsed microseconds.
+    fn advance_clock(&mut self, time: arm7::Timestamp) -> u64 {
+        if time <= self.last_update_time {
+            return 0;
+        }
+        let total = self.us_fract + (time.0 - self.last_update_time.0) * 1_000_000;
+        self.last_update_time = time;
+        self.us_fract = total % SYS_CLOCK_RATE;
+        let elapsed_us = (total / SYS_CLOCK_RATE) as u64;
+        for _ in 0..elapsed_us.min(16) {
+            self.random = (self.random & 1) ^ (((self.random & 0x3FF) << 1) | (self.random >> 10));
+        }
+        elapsed_us
+    }
+
+    fn sync_clock(&mut self, time: arm7::Timestamp) {
+        let elapsed_us = self.advance_clock(time);
+        self.internal_us += elapsed_us;
+        if self.us_counter_enabled {
+            self.us_counter += elapsed_us;
+        }
+    }
+
+    pub(crate) fn start_ticking(&mut self, arm7_schedule: &mut arm7::Schedule) {
+        if self.is_ticking || !self.is_active() {
+            return;
+        }
+        self.is_ticking = true;
+        self.last_update_time = arm7_schedule.cur_time();
+        arm7_schedule.schedule_event(
+            arm7::event_slots::WIFI,
+            arm7_schedule.cur_time() + arm7::Timestamp(TICK_CYCLES),
+        );
+    }
+
+    pub(crate) fn handle_tick<E: Engine>(emu: &mut Emu<E>, time: arm7::Timestamp) {
+        let wifi = &mut emu.wifi;
+        let prev_us_counter = wifi.us_counter;
+        wifi.sync_clock(time);
+
+        if wifi.wakeup_pending {
+            wifi.wakeup_pending = false;
+            wifi.power_state &= !0x0202;
+            if wifi.rf_status == RfStatus::PoweredDown {
+                wifi.rf_status = RfStatus::Idle;
+            }
+            wifi.request_irqs(IrqFlags(0).with_rf_wakeup(true), &mut emu.arm7.irqs, ());
+        }
+
+        if wifi.us_counter_enabled {
+            Self::handle_tsf_events(emu, prev_us_counter);
+        }
+
+        Self::update_tx(emu);
+        Self::update_rx(emu);
+
+        let wifi = &mut emu.wifi;
+        if wifi.is_active() {
+            emu.arm7
+                .schedule
+                .schedule_event(arm7::event_slots::WIFI, time + arm7::Timestamp(TICK_CYCLES));
+        } else {
+            wifi.is_ticking = false;
+        }
+    }
+
+    fn handle_tsf_events<E: Engine>(emu: &mut Emu<E>, prev_us_counter: u64) {
+        let wifi = &mut emu.wifi;
+        let cur = wifi.us_counter;
+
+        // Beacon count, in TUs (1024 us)
+        let elapsed_tus = (cur >> 10).wrapping_sub(prev_us_counter >> 10);
+        if elapsed_tus != 0 {
+            wifi.beacon_count = wifi.beacon_count.wrapping_sub(elapsed_tus as u16);
+        }
+
+        // Multiplayer CMD countdown, in 10 us units
+        if wifi.cmd_count_enabled && wifi.cmd_count != 0 {
+            let elapsed = (cur / 10).wrapping_sub(prev_us_counter / 10) as u16;
+            if elapsed >= wifi.cmd_count {
+                wifi.cmd_count = 0;
+                wifi.request_irqs(
+                    IrqFlags(0).with_multiplay_cmd_done(true),
+                    &mut emu.arm7.irqs,
+                    (),
+                );
+            } else {
+                wifi.cmd_count -= elapsed;
+            }
+        }
+
+        if !wifi.us_compare_enabled {
+            return;
+        }
+
+        let pre_beacon_us = wifi.regs.read_le::<u16>(0x110) as u64;
+        let pre_beacon_time = wifi.us_compare.wrapping_sub(pre_beacon_us);
+        if pre_beacon_us != 0 && prev_us_counter < pre_beacon_time && cur >= pre_beacon_time {
+            wifi.request_irqs(IrqFlags(0).with_pre_beacon(true), &mut emu.arm7.irqs, ());
+        }
+
+        if let Some(post_beacon_time) = wifi.post_beacon_time_us {
+            if cur >= post_beacon_time {
+                wifi.post_beacon_time_us = None;
+                wifi.request_irqs(IrqFlags(0).with_post_beacon(true), &mut emu.arm7.irqs, ());
+            }
+        }
+
+        if prev_us_counter < wifi.us_compare && cur >= wifi.us_compare {
+            let beacon_interval = (wifi.regs.read_le::<u16>(0x08C) & 0x3FF) as u64;
+            wifi.beacon_count = beacon_interval as u16;
+            wifi.us_compare += beacon_interval.max(1) << 10;
+            wifi.post_beacon_time_us = Some(cur + wifi.regs.read_le::<u16>(0x10C) as u64);
+
+            let listen_count = wifi.regs.read_le::<u16>(0x088) & 0xFF;
+            wifi.regs.write_le(
+                0x088,
+                if listen_count == 0 {
+                    wifi.regs.read_le::<u16>(0x08E) & 0xFF
+                } else {
+                    listen_count - 1
+                },
+            );
+
+            if wifi.tx_slots[TxSlot::Beacon.stat_index() as usize].enabled() {
+                wifi.tx_req |= 1 << 4;
+            }
+            wifi.request_irqs(IrqFlags(0).with_beacon(true), &mut emu.arm7.irqs, ());
+        }
+    }
+
+    fn next_tx_slot(&self) -> Option<TxSlot> {
+        if self.tx_req & 1 << 4 != 0 {
+            return Some(TxSlot::Beacon);
+        }
+        [TxSlot::Cmd, TxSlot::Loc3, TxSlot::Loc2, TxSlot::Loc1]
+            .into_iter()
+            .find(|slot| {
+                self.tx_req & slot.req_mask() != 0
+                    && self.tx_slots[slot.stat_index() as usize].enabled()
+            })
+    }
+
+    fn update_tx<E: Engine>(emu: &mut Emu<E>) {
+        let wifi = &mut emu.wifi;
+
+        if let Some(transfer) = wifi.cur_tx {
+            if wifi.internal_us < transfer.end_time_us {
+                return;
+            }
+            wifi.cur_tx = None;
+            Self::finish_tx(emu, transfer);
+            return;
+        }
+
+        if wifi.mode_rst & 1 == 0 || wifi.is_powered_down() || wifi.rx_end_time_us.is_some() {
+            return;
+        }
+
+        let Some(slot) = wifi.next_tx_slot() else {
+            return;
+        };
+        if slot == TxSlot::Beacon {
+            wifi.tx_req &= !(1 << 4);
+        }
+        let header_addr = wifi.tx_slots[slot.stat_index() as usize].halfword_addr() << 1;
+        let len = (wifi
+            .ram
+            .read_le::<u16>((header_addr as usize + 0xA) & 0x1FFE)
+            & 0x3FFF) as usize;
+        let rate = Rate::from_header_byte(wifi.ram[(header_addr as usize + 8) & 0x1FFF]);
+        let preamble_us = if wifi.regs.read_le::<u16>(0x0BC) & 4 != 0 {
+            96
+        } else {
+            192
+        };
+        wifi.cur_tx = Some(TxTransfer {
+            slot,
+            header_addr,
+            end_time_us: wifi.internal_us + preamble_us + rate.transfer_time_us(len),
+        });
+        wifi.tx_busy |= 1 << slot.stat_index();
+        wifi.rf_status = RfStatus::Transmitting;
+        wifi.regs.write_le(0x19C, 0x0042_u16);
+        wifi.request_irqs(IrqFlags(0).with_tx_start(true), &mut emu.arm7.irqs, ());
+    }
+
+    fn finish_tx<E: Engine>(emu: &mut Emu<E>, transfer: TxTransfer) {
+        let wifi = &mut emu.wifi;
+        let slot_index = transfer.slot.stat_index();
+        let header_addr = transfer.header_addr as usize;
+        let frame_addr = header_addr + 0xC;
+        let len = (wifi.ram.read_le::<u16>((header_addr + 0xA) & 0x1FFE) & 0x3FFF) as usize;
+        let rate = Rate::from_header_byte(wifi.ram[(header_addr + 8) & 0x1FFF]);
+
+        // Insert the sequence number into the 802.11 header, unless told otherwise
+        if !wifi.tx_slots[slot_index as usize].ignore_seq_number() && len >= 24 {
+            wifi.ram
+                .write_le((frame_addr + 22) & 0x1FFE, wifi.tx_seq_number << 4);
+        }
+        wifi.tx_seq_number = (wifi.tx_seq_number + 1) & 0xFFF;
+
+        // The length includes the FCS, which isn't stored in RAM
+        let data_len = len.saturating_sub(4);
+        let mut data = Vec::with_capacity(data_len);
+        for i in 0..data_len {
+            data.push(wifi.ram[(frame_addr + i) & 0x1FFF]);
+        }
+        wifi.backend.transmit_frame(&data, rate, wifi.internal_us);
+
+        // Transfer status: success
+        wifi.ram.write_le(header_addr & 0x1FFE, 0x0001_u16);
+
+        wifi.tx_busy &= !(1 << slot_index);
+        wifi.tx_req &= !transfer.slot.req_mask();
+        wifi.tx_stat = 1 | slot_index << 8;
+        wifi.rf_status = RfStatus::Idle;
+        wifi.regs.write_le(0x19C, 0x0046_u16);
+
+        let mut irqs = IrqFlags(0);
+        match transfer.slot {
+            TxSlot::Beacon => {}
+            TxSlot::Cmd => {
+                wifi.tx_slots[slot_index as usize].set_enabled(false);
+                irqs.set_tx_complete(true);
+                if wifi.cmd_count_enabled {
+                    wifi.cmd_count = wifi.regs.read_le::<u16>(0x0C0);
+                    if wifi.cmd_count == 0 {
+                        irqs.set_multiplay_cmd_done(true);
+                    }
+                } else {
+                    irqs.set_multiplay_cmd_done(true);
+                }
+            }
+            _ => {
+                wifi.tx_slots[slot_index as usize].set_enabled(false);
+                irqs.set_tx_complete(true);
+            }
+        }
+        // Increment the "TX OK" counter
+        let tx_ok = wifi.regs.read_le::<u16>(0x1C0);
+        wifi.regs.write_le(0x1C0, tx_ok.wrapping_add(1));
+        wifi.request_irqs(irqs, &mut emu.arm7.irqs, ());
+    }
+
+    fn rxbuf_wrap(&self, addr: u16) -> u16 {
+        let begin = self.rxbuf_begin & 0x1FFE;
+        let end = self.rxbuf_end & 0x1FFE;
+        if addr & 0x1FFE >= end && end > begin {
+            begin
+        } else {
+            addr & 0x1FFE
+        }
+    }
+
+    fn rxbuf_free_space(&self) -> usize {
+        rxbuf_free_space(
+            self.rxbuf_begin & 0x1FFE,
+            self.rxbuf_end & 0x1FFE,
+            self.rxbuf_write_csr << 1,
+            self.rxbuf_read_csr << 1,
+        )
+    }
+
+    fn accepts_frame(&self, data: &[u8]) -> bool {
+        if data.len() < 10 {
+            return false;
+        }
+        let frame_type = data[0] >> 2 & 3;
+        let frame_subtype = data[0] >> 4;
+        // Always accept beacons and probe responses, so that scanning works
+        if frame_type == 0 && matches!(frame_subtype, 5 | 8) {
+            return true;
+        }
+        let dst_addr = &data[4..10];
+        if dst_addr[0] & 1 != 0 {
+            return true;
+        }
+        let mac_addr = [
+            self.regs.read_le::<u16>(0x018),
+            self.regs.read_le::<u16>(0x01A),
+            self.regs.read_le::<u16>(0x01C),
+        ];
+        dst_addr
+            .chunks_exact(2)
+            .zip(mac_addr)
+            .all(|(bytes, half)| u16::from_le_bytes([bytes[0], bytes[1]]) == half)
+            || self.regs.read_le::<u16>(0x0D0) & 1 != 0
+    }
+
+    fn update_rx<E: Engine>(emu: &mut Emu<E>) {
+        let wifi = &mut emu.wifi;
+
+        if let Some(end_time) = wifi.rx_end_time_us {
+            if wifi.internal_us < end_time {
+                return;
+            }
+            wifi.rx_end_time_us = None;
+            wifi.rf_status = RfStatus::Idle;
+            let rx_ok = wifi.regs.read_le::<u16>(0x1C4);
+            wifi.regs.write_le(0x1C4, rx_ok.wrapping_add(1));
+            wifi.request_irqs(IrqFlags(0).with_rx_complete(true), &mut emu.arm7.irqs, ());
+            return;
+        }
+
+        if wifi.mode_rst & 1 == 0
+            || wifi.rx_control & 1 << 15 == 0
+            || wifi.is_powered_down()
+            || wifi.cur_tx.is_some()
+        {
+            return;
+        }
+
+        let Some(frame) = wifi.backend.receive_frame(wifi.internal_us) else {
+            return;
+        };
+        if !wifi.accepts_frame(&frame.data) {
+            return;
+        }
+
+        let total_len = (0xC + frame.data.len() + 3) & !3;
+        if total_len > wifi.rxbuf_free_space() {
+            // Buffer overflow, drop the frame
+            let overflows = wifi.regs.read_le::<u16>(0x1AC);
+            wifi.regs.write_le(0x1AC, overflows.wrapping_add(1));
+            return;
+        }
+
+        let frame_type = frame.data[0] >> 2 & 3;
+        let frame_subtype = frame.data[0] >> 4;
+        let flags = 0x8000
+            | match (frame_type, frame_subtype) {
+                (0, 8) => 1,
+                (2, _) => 8,
+                _ => 0,
+            };
+
+        wifi.rx_frame_buffer.clear();
+        wifi.rx_frame_buffer.extend_from_slice(&flags.to_le_bytes());
+        wifi.rx_frame_buffer
+            .extend_from_slice(&[0x40, 0x00, 0x00, 0x00]);
+        wifi.rx_frame_buffer
+            .extend_from_slice(&(frame.rate.header_byte() as u16).to_le_bytes());
+        wifi.rx_frame_buffer
+            .extend_from_slice(&(frame.data.len() as u16).to_le_bytes());
+        wifi.rx_frame_buffer.extend_from_slice(&[0x40, 0x00]);
+        wifi.rx_frame_buffer.extend_from_slice(&frame.data);
+        wifi.rx_frame_buffer.resize(total_len, 0);
+
+        let mut addr = wifi.rxbuf_write_csr << 1;
+        for chunk in wifi.rx_frame_buffer.chunks_exact(2) {
+            wifi.ram.write_le(
+                addr as usize & 0x1FFE,
+                u16::from_le_bytes([chunk[0], chunk[1]]),
+            );
+            addr = wifi.rxbuf_wrap(addr + 2);
+        }
+        wifi.rxbuf_write_csr = addr >> 1;
+
+        wifi.rf_status = RfStatus::Receiving;
+        wifi.rx_end_time_us =
+            Some(wifi.internal_us + 192 + frame.rate.transfer_time_us(frame.data.len() + 4));
+        wifi.request_irqs(IrqFlags(0).with_rx_start(true), &mut emu.arm7.irqs, ());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rxbuf_free_space_in_range() {
+        assert_eq!(rxbuf_free_space(0x4C28, 0x5F60, 0x4C28, 0x4C28), 0x1336);
+        assert_eq!(rxbuf_free_space(0x4C28, 0x5F60, 0x4D28, 0x4C28), 0x1236);
+        // Wrapped around the end of the buffer
+        assert_eq!(rxbuf_free_space(0x4C28, 0x5F60, 0x4C28, 0x4D28), 0xFE);
+        assert_eq!(rxbuf_free_space(0x4C28, 0x5F60, 0x4D26, 0x4D28), 0);
+    }
+
+    #[test]
+    fn rxbuf_free_space_invalid_registers() {
+        assert_eq!(rxbuf_free_space(0x5F60, 0x4C28, 0x4C28, 0x4C28), 0);
+        assert_eq!(rxbuf_free_space(0x4000, 0x4000, 0, 0x1FFE), 0);
+        // Pointers outside of the buffer mustn't underflow
+        for (write, read) in [(0, 0x1FFE), (0x1FFE, 0), (0x4000, 0x1FFE), (0x1FFE, 0x4010)] {
+            assert!(rxbuf_free_space(0x4000, 0x4020, write, read) <= 0x1E);
         }
     }
 }
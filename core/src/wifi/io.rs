@@ -1,105 +1,481 @@
-use super::WiFi;
-use crate::{cpu::bus::AccessType, utils::mem_prelude::*};
+use super::{IrqFlags, RfStatus, TxSlot, TxSlotControl, WiFi};
+use crate::{
+    cpu::{bus::AccessType, Engine},
+    emu::Emu,
+    utils::mem_prelude::*,
+};
 
-#[allow(clippy::extra_unused_type_parameters)]
 impl WiFi {
-    fn read_io<A: AccessType>(&mut self, addr: u16) -> u8 {
-        self.mmio[(addr & 0xFFF) as usize]
+    fn read_rxbuf_data(&mut self) -> u16 {
+        let addr = self.rxbuf_read_addr & 0x1FFE;
+        let value = self.ram.read_le::<u16>(addr as usize);
+        let mut next_addr = self.rxbuf_wrap(addr + 2);
+        if self.rxbuf_gap != 0 && next_addr == self.rxbuf_gap & 0x1FFE {
+            next_addr = self.rxbuf_wrap(next_addr + (self.rxbuf_gap_disp << 1));
+        }
+        self.rxbuf_read_addr = next_addr;
+        value
+    }
+
+    fn write_txbuf_data(&mut self, value: u16) {
+        let addr = self.txbuf_write_addr & 0x1FFE;
+        self.ram.write_le(addr as usize, value);
+        let mut next_addr = (addr + 2) & 0x1FFE;
+        if self.txbuf_gap != 0 && next_addr == self.txbuf_gap & 0x1FFE {
+            next_addr = (next_addr + (self.txbuf_gap_disp << 1)) & 0x1FFE;
+        }
+        self.txbuf_write_addr = next_addr;
+    }
+
+    fn write_bb_control(&mut self, value: u16) {
+        let index = value as u8;
+        match value >> 12 {
+            5 => {
+                if let 0x01..=0x0C
+                | 0x13..=0x15
+                | 0x1B..=0x26
+                | 0x28..=0x4C
+                | 0x4E..=0x5C
+                | 0x62
+                | 0x63
+                | 0x65
+                | 0x67
+                | 0x68 = index
+                {
+                    self.bb_regs[index as usize] = self.regs[0x15A];
+                }
+            }
+            6 => self.regs[0x15C] = self.bb_regs[index as usize],
+            _ => {}
+        }
+    }
+
+    fn start_rf_transfer(&mut self) {
+        // Only the RF2958 protocol (24-bit transfers, 5-bit register index) is implemented
+        let data =
+            (self.regs.read_le::<u16>(0x17C) as u32) << 16 | self.regs.read_le::<u16>(0x17E) as u32;
+        let index = (data >> 18 & 0x1F) as usize;
+        if data & 1 << 23 != 0 {
+            let value = self.rf_regs[index];
+            self.regs
+                .write_le(0x17C, (value >> 16) as u16 & 3 | (data >> 16) as u16 & !3);
+            self.regs.write_le(0x17E, value as u16);
+        } else {
+            self.rf_regs[index] = data & 0x3_FFFF;
+        }
+    }
+
+    fn reset_rx_pointers(&mut self) {
+        self.rxbuf_write_csr = (self.rxbuf_begin & 0x1FFE) >> 1;
+        self.rxbuf_read_csr = self.rxbuf_write_csr;
+        self.rxbuf_read_addr = self.rxbuf_begin & 0x1FFE;
     }
 
-    fn write_io<A: AccessType>(&mut self, mut addr: u16, value: u8) {
-        addr &= 0xFFF;
-        #[allow(clippy::match_same_arms)]
+    fn read_io<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16) -> u16 {
+        let wifi = &mut emu.wifi;
         match addr {
-            0x03D => return,
-
-            0x159 => {
-                let index = self.mmio[0x158];
-                match value >> 4 {
-                    5 => {
-                        if let 0x01..=0x0C
-                        | 0x13..=0x15
-                        | 0x1B..=0x26
-                        | 0x28..=0x4C
-                        | 0x4E..=0x5C
-                        | 0x62
-                        | 0x63
-                        | 0x65
-                        | 0x67
-                        | 0x68 = index
-                        {
-                            self.bb_regs[index as usize] = self.mmio[0x15A];
+            0x000 => 0x1440,
+            0x004 => wifi.mode_rst,
+            0x010 => wifi.irqs_requested.0,
+            0x012 => wifi.irqs_enabled.0,
+            0x03C => wifi.power_state,
+            0x044 => {
+                if !A::IS_DEBUG {
+                    wifi.sync_clock(emu.arm7.schedule.cur_time());
+                }
+                wifi.random & 0x7FF
+            }
+
+            0x030 => wifi.rx_control,
+            0x050 => wifi.rxbuf_begin,
+            0x052 => wifi.rxbuf_end,
+            0x054 => wifi.rxbuf_write_csr,
+            0x056 => wifi.rxbuf_write_addr,
+            0x058 => wifi.rxbuf_read_addr,
+            0x05A => wifi.rxbuf_read_csr,
+            0x05C => wifi.rxbuf_count,
+            0x060 => {
+                if A::IS_DEBUG {
+                    wifi.ram.read_le((wifi.rxbuf_read_addr & 0x1FFE) as usize)
+                } else {
+                    let value = wifi.read_rxbuf_data();
+                    if wifi.rxbuf_count != 0 {
+                        wifi.rxbuf_count -= 1;
+                        if wifi.rxbuf_count == 0 {
+                            wifi.request_irqs(
+                                IrqFlags(0).with_rxbuf_count_expired(true),
+                                &mut emu.arm7.irqs,
+                                &mut emu.arm7.schedule,
+                            );
                         }
                     }
-                    6 => self.mmio[0x15C] = self.bb_regs[index as usize],
-                    _ => {}
+                    value
+                }
+            }
+            0x062 => wifi.rxbuf_gap,
+            0x064 => wifi.rxbuf_gap_disp,
+
+            0x068 => wifi.txbuf_write_addr,
+            0x06C => wifi.txbuf_count,
+            0x074 => wifi.txbuf_gap,
+            0x076 => wifi.txbuf_gap_disp,
+
+            0x080 => wifi.tx_slots[TxSlot::Beacon.stat_index() as usize].0,
+            0x090 => wifi.tx_slots[TxSlot::Cmd.stat_index() as usize].0,
+            0x0A0 => wifi.tx_slots[TxSlot::Loc1.stat_index() as usize].0,
+            0x0A4 => wifi.tx_slots[TxSlot::Loc2.stat_index() as usize].0,
+            0x0A8 => wifi.tx_slots[TxSlot::Loc3.stat_index() as usize].0,
+            0x0B0 => wifi.tx_req & 0xF | 0x10,
+            0x0B6 => wifi.tx_busy,
+            0x0B8 => wifi.tx_stat,
+
+            0x0E8 => wifi.us_counter_enabled as u16,
+            0x0EA => wifi.us_compare_enabled as u16,
+            0x0EE => wifi.cmd_count_enabled as u16,
+            0x0F0 => wifi.us_compare as u16 & 0xFC00,
+            0x0F2 => (wifi.us_compare >> 16) as u16,
+            0x0F4 => (wifi.us_compare >> 32) as u16,
+            0x0F6 => (wifi.us_compare >> 48) as u16,
+            0x0F8..=0x0FE => {
+                if !A::IS_DEBUG {
+                    wifi.sync_clock(emu.arm7.schedule.cur_time());
+                }
+                (wifi.us_counter >> ((addr - 0x0F8) << 3)) as u16
+            }
+            0x118 => wifi.cmd_count,
+            0x11C => wifi.beacon_count,
+
+            0x15E | 0x180 => 0,
+
+            0x210 => wifi.tx_seq_number,
+            0x214 => wifi.rf_status.reg_value(),
+
+            _ => wifi.regs.read_le(addr as usize),
+        }
+    }
+
+    fn write_io<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16, value: u16) {
+        let wifi = &mut emu.wifi;
+        match addr {
+            0x000 => return,
+
+            0x004 => {
+                let prev = wifi.mode_rst;
+                wifi.mode_rst = value;
+                if value & 1 != 0 && prev & 1 == 0 {
+                    wifi.rf_status = RfStatus::Idle;
+                    wifi.regs.write_le(0x19C, 0x0046_u16);
+                    wifi.start_ticking(&mut emu.arm7.schedule);
+                } else if value & 1 == 0 && prev & 1 != 0 {
+                    wifi.rf_status = RfStatus::Disabled;
+                    wifi.cur_tx = None;
+                    wifi.rx_end_time_us = None;
+                    wifi.tx_busy = 0;
                 }
+                if value & 1 << 13 != 0 {
+                    wifi.reset_rx_pointers();
+                    wifi.tx_req = 0;
+                }
+                if value & 1 << 14 != 0 {
+                    wifi.regs.write_le(0x006, 0_u16);
+                    wifi.tx_stat = 0;
+                    wifi.regs.write_le(0x00A, 0_u16);
+                    wifi.regs.write_le(0x018, 0_u16);
+                    wifi.regs.write_le(0x01A, 0_u16);
+                    wifi.regs.write_le(0x01C, 0_u16);
+                    wifi.regs.write_le(0x020, 0_u16);
+                    wifi.regs.write_le(0x022, 0_u16);
+                    wifi.regs.write_le(0x024, 0_u16);
+                    wifi.regs.write_le(0x028, 0_u16);
+                    wifi.regs.write_le(0x02A, 0_u16);
+                }
+                return;
+            }
+
+            0x010 => {
+                wifi.irqs_requested.0 &= !value;
+                return;
+            }
+            0x012 => {
+                wifi.irqs_enabled = IrqFlags(value);
+                wifi.update_irq_line(&mut emu.arm7.irqs, &mut emu.arm7.schedule);
+                return;
+            }
+            0x21C => {
+                wifi.request_irqs(IrqFlags(value), &mut emu.arm7.irqs, &mut emu.arm7.schedule);
+                return;
             }
 
-            0x15C..=0x15F => return,
+            0x03C => {
+                wifi.power_state = (wifi.power_state & !0x0002) | (value & 0x0002);
+                if value & 2 != 0 && wifi.is_powered_down() {
+                    wifi.wakeup_pending = true;
+                }
+                return;
+            }
+            0x040 => {
+                if value & 1 << 15 != 0 {
+                    wifi.sync_clock(emu.arm7.schedule.cur_time());
+                    if value & 1 != 0 {
+                        wifi.power_state = 0x0200;
+                        wifi.rf_status = RfStatus::PoweredDown;
+                        wifi.wakeup_pending = false;
+                    } else if wifi.is_powered_down() {
+                        wifi.wakeup_pending = true;
+                    }
+                }
+            }
+            0x044 => return,
+
+            0x030 => {
+                if value & 1 != 0 {
+                    wifi.rxbuf_write_csr = (wifi.rxbuf_write_addr & 0xFFF) & !1;
+                }
+                if value & 0x80 != 0 {
+                    let reply2 = wifi.regs.read_le::<u16>(0x098);
+                    wifi.regs.write_le(0x094, reply2);
+                    wifi.regs.write_le(0x098, 0_u16);
+                }
+                wifi.rx_control = value & 0xFF0E;
+                return;
+            }
+            0x050 => {
+                wifi.rxbuf_begin = value;
+                return;
+            }
+            0x052 => {
+                wifi.rxbuf_end = value;
+                return;
+            }
+            0x054 => return,
+            0x056 => {
+                wifi.rxbuf_write_addr = value & 0xFFF;
+                return;
+            }
+            0x058 => {
+                wifi.rxbuf_read_addr = value & 0x1FFE;
+                return;
+            }
+            0x05A => {
+                wifi.rxbuf_read_csr = value & 0xFFF;
+                return;
+            }
+            0x05C => {
+                wifi.rxbuf_count = value & 0xFFF;
+                return;
+            }
+            0x060 => return,
+            0x062 => {
+                wifi.rxbuf_gap = value & 0x1FFE;
+                return;
+            }
+            0x064 => {
+                wifi.rxbuf_gap_disp = value & 0xFFF;
+                return;
+            }
+
+            0x068 => {
+                wifi.txbuf_write_addr = value & 0x1FFE;
+                return;
+            }
+            0x06C => {
+                wifi.txbuf_count = value & 0xFFF;
+                return;
+            }
+            0x070 => {
+                wifi.write_txbuf_data(value);
+                if wifi.txbuf_count != 0 {
+                    wifi.txbuf_count -= 1;
+                    if wifi.txbuf_count == 0 {
+                        wifi.request_irqs(
+                            IrqFlags(0).with_txbuf_count_expired(true),
+                            &mut emu.arm7.irqs,
+                            &mut emu.arm7.schedule,
+                        );
+                    }
+                }
+                return;
+            }
+            0x074 => {
+                wifi.txbuf_gap = value & 0x1FFE;
+                return;
+            }
+            0x076 => {
+                wifi.txbuf_gap_disp = value & 0xFFF;
+                return;
+            }
+
+            0x080 => {
+                wifi.tx_slots[TxSlot::Beacon.stat_index() as usize] = TxSlotControl(value);
+                return;
+            }
+            0x090 => {
+                wifi.tx_slots[TxSlot::Cmd.stat_index() as usize] = TxSlotControl(value);
+                if value & 1 << 15 != 0 {
+                    wifi.tx_req |= TxSlot::Cmd.req_mask();
+                }
+                return;
+            }
+            0x0A0 => {
+                wifi.tx_slots[TxSlot::Loc1.stat_index() as usize] = TxSlotControl(value);
+                return;
+            }
+            0x0A4 => {
+                wifi.tx_slots[TxSlot::Loc2.stat_index() as usize] = TxSlotControl(value);
+                return;
+            }
+            0x0A8 => {
+                wifi.tx_slots[TxSlot::Loc3.stat_index() as usize] = TxSlotControl(value);
+                return;
+            }
+            0x0AC => {
+                wifi.tx_req &= !(value & 0xF);
+                return;
+            }
+            0x0AE => {
+                wifi.tx_req |= value & 0xF;
+                return;
+            }
+            0x0B0 | 0x0B6 | 0x0B8 => return,
+            0x0B4 => {
+                for (slot, bit) in [
+                    (TxSlot::Loc1, 0),
+                    (TxSlot::Cmd, 1),
+                    (TxSlot::Loc2, 2),
+                    (TxSlot::Loc3, 3),
+                ] {
+                    if value & 1 << bit != 0 {
+                        wifi.tx_slots[slot.stat_index() as usize].set_enabled(false);
+                    }
+                }
+                return;
+            }
+
+            0x0E8 => {
+                wifi.sync_clock(emu.arm7.schedule.cur_time());
+                wifi.us_counter_enabled = value & 1 != 0;
+                wifi.start_ticking(&mut emu.arm7.schedule);
+                return;
+            }
+            0x0EA => {
+                wifi.us_compare_enabled = value & 1 != 0;
+                return;
+            }
+            0x0EE => {
+                wifi.cmd_count_enabled = value & 1 != 0;
+                return;
+            }
+            0x0F0 => {
+                wifi.us_compare = (wifi.us_compare & !0xFFFF) | (value & 0xFC00) as u64;
+                if value & 1 != 0 {
+                    wifi.request_irqs(
+                        IrqFlags(0).with_beacon(true),
+                        &mut emu.arm7.irqs,
+                        &mut emu.arm7.schedule,
+                    );
+                }
+                return;
+            }
+            0x0F2 | 0x0F4 | 0x0F6 => {
+                let shift = (addr - 0x0F0) << 3;
+                wifi.us_compare = (wifi.us_compare & !(0xFFFF << shift)) | (value as u64) << shift;
+                return;
+            }
+            0x0F8..=0x0FE => {
+                wifi.sync_clock(emu.arm7.schedule.cur_time());
+                let shift = (addr - 0x0F8) << 3;
+                wifi.us_counter = (wifi.us_counter & !(0xFFFF << shift)) | (value as u64) << shift;
+                return;
+            }
+            0x118 => {
+                wifi.cmd_count = value;
+                return;
+            }
+            0x11C => {
+                wifi.beacon_count = value;
+                return;
+            }
+
+            0x158 => {
+                wifi.regs.write_le(addr as usize, value);
+                wifi.write_bb_control(value);
+                return;
+            }
+            0x15C | 0x15E | 0x180 => return,
+            0x17E => {
+                wifi.regs.write_le(addr as usize, value);
+                wifi.start_rf_transfer();
+                return;
+            }
+
+            0x210 | 0x214 => return,
 
             _ => {}
         }
-        self.mmio[addr as usize] = value;
+        wifi.regs.write_le(addr as usize, value);
     }
 
-    pub fn read_8<A: AccessType>(&mut self, addr: u16) -> u8 {
+    pub fn read_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16) -> u8 {
         match addr >> 13 & 3 {
-            0 | 3 => self.read_io::<A>(addr),
-            2 => self.ram[(addr as usize) & 0x1FFF],
+            0 | 3 => (Self::read_io::<A, E>(emu, addr & 0xFFE) >> ((addr & 1) << 3)) as u8,
+            2 => emu.wifi.ram[(addr as usize) & 0x1FFF],
             _ => 0,
         }
     }
 
-    pub fn read_16<A: AccessType>(&mut self, addr: u16) -> u16 {
+    pub fn read_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16) -> u16 {
         match addr >> 13 & 3 {
-            0 | 3 => self.read_io::<A>(addr) as u16 | (self.read_io::<A>(addr | 1) as u16) << 8,
-            2 => self.ram.read_le((addr as usize) & 0x1FFE),
+            0 | 3 => Self::read_io::<A, E>(emu, addr & 0xFFE),
+            2 => emu.wifi.ram.read_le((addr as usize) & 0x1FFE),
             _ => 0,
         }
     }
 
-    pub fn read_32<A: AccessType>(&mut self, addr: u16) -> u32 {
+    pub fn read_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16) -> u32 {
         match addr >> 13 & 3 {
             0 | 3 => {
-                self.read_io::<A>(addr) as u32
-                    | (self.read_io::<A>(addr | 1) as u32) << 8
-                    | (self.read_io::<A>(addr | 2) as u32) << 16
-                    | (self.read_io::<A>(addr | 3) as u32) << 24
+                Self::read_io::<A, E>(emu, addr & 0xFFC) as u32
+                    | (Self::read_io::<A, E>(emu, (addr & 0xFFC) | 2) as u32) << 16
             }
-            2 => self.ram.read_le((addr as usize) & 0x1FFC),
+            2 => emu.wifi.ram.read_le((addr as usize) & 0x1FFC),
             _ => 0,
         }
     }
 
-    pub fn write_8<A: AccessType>(&mut self, addr: u16, value: u8) {
+    pub fn write_8<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16, value: u8) {
         match addr >> 13 & 3 {
-            0 | 3 => self.write_io::<A>(addr, value),
-            2 => self.ram[(addr as usize) & 0x1FFF] = value,
+            0 | 3 => {
+                // 8-bit writes to the Wi-Fi registers are ignored
+                #[cfg(feature = "log")]
+                if !A::IS_DEBUG {
+                    slog::warn!(
+                        emu.wifi.logger,
+                        "Ignored IO write8 @ {:#05X}: {:#04X}",
+                        addr & 0xFFF,
+                        value
+                    );
+                }
+            }
+            2 => emu.wifi.ram[(addr as usize) & 0x1FFF] = value,
             _ => {}
         }
     }
 
-    pub fn write_16<A: AccessType>(&mut self, addr: u16, value: u16) {
+    pub fn write_16<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16, value: u16) {
         match addr >> 13 & 3 {
-            0 | 3 => {
-                self.write_io::<A>(addr, value as u8);
-                self.write_io::<A>(addr | 1, (value >> 8) as u8);
-            }
-            2 => self.ram.write_le((addr as usize) & 0x1FFE, value),
+            0 | 3 => Self::write_io::<A, E>(emu, addr & 0xFFE, value),
+            2 => emu.wifi.ram.write_le((addr as usize) & 0x1FFE, value),
             _ => {}
         }
     }
 
-    pub fn write_32<A: AccessType>(&mut self, addr: u16, value: u32) {
+    pub fn write_32<A: AccessType, E: Engine>(emu: &mut Emu<E>, addr: u16, value: u32) {
         match addr >> 13 & 3 {
             0 | 3 => {
-                self.write_io::<A>(addr, value as u8);
-                self.write_io::<A>(addr | 1, (value >> 8) as u8);
-                self.write_io::<A>(addr | 2, (value >> 16) as u8);
-                self.write_io::<A>(addr | 3, (value >> 24) as u8);
+                Self::write_io::<A, E>(emu, addr & 0xFFC, value as u16);
+                Self::write_io::<A, E>(emu, (addr & 0xFFC) | 2, (value >> 16) as u16);
             }
-            2 => self.ram.write_le((addr as usize) & 0x1FFC, value),
+            2 => emu.wifi.ram.write_le((addr as usize) & 0x1FFC, value),
             _ => {}
         }
     }
@@ -0,0 +1,441 @@
+//! A Wi-Fi backend linking several emulator instances together, for local multiplayer and DS
+//! Download Play.
+//!
+//! Each instance owns a [`LinkBackend`], which exchanges frames and clock announcements with its
+//! peers through a [`Transport`]. All instances share a common microsecond timeline, divided into
+//! sync quanta of `sync_quantum_us`: a frame finishing transmission at time `t` on one instance is
+//! received on every other one at the first quantum boundary at or after `t + latency_us`. Peers
+//! are only synchronized with once per quantum: when an instance reaches a boundary `b`, it
+//! announces its time and waits until all of its peers have reached at least `b - latency_us`,
+//! after which every frame due before the next boundary is known. As long as all peers keep
+//! announcing their time, the frames each instance receives (and their reception times) are fully
+//! deterministic, regardless of how fast the instances run relative to each other.
+//!
+//! Peers that don't answer in time (e.g. because their Wi-Fi hardware stopped polling for frames,
+//! or they disconnected) stop being waited for until they're heard from again; frames they send in
+//! the meantime are received as soon as possible.
+
+use super::{Backend, Rate, RxFrame};
+use core::any::Any;
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
+
+/// The default delay between the end of a transmission and the moment other instances start
+/// receiving the frame, in microseconds.
+pub const DEFAULT_LATENCY_US: u64 = 64;
+
+/// The default interval between synchronizations with peers, in microseconds. Frames are only
+/// delivered at multiples of this, so it also adds up to the same amount of latency.
+pub const DEFAULT_SYNC_QUANTUM_US: u64 = 256;
+
+/// The default maximum amount of real time to wait for a lagging peer before giving up on it.
+pub const DEFAULT_PEER_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// A frame that finished being transmitted at `time_us` (on the shared timeline), without the
+    /// trailing FCS.
+    Frame {
+        data: Vec<u8>,
+        rate: Rate,
+        time_us: u64,
+    },
+    /// An announcement that the sender has reached `time_us`, and won't send any frames finishing
+    /// before that time.
+    Time(u64),
+}
+
+impl Message {
+    #[inline]
+    pub fn time_us(&self) -> u64 {
+        match self {
+            Message::Frame { time_us, .. } | Message::Time(time_us) => *time_us,
+        }
+    }
+}
+
+/// A way to exchange [`Message`]s with a fixed set of peers.
+pub trait Transport {
+    fn peer_count(&self) -> usize;
+
+    /// Sends a message to all peers.
+    fn send(&mut self, message: &Message);
+
+    /// Returns the next message received from any peer, along with the index of the peer that sent
+    /// it. If no message is available, waits for up to `timeout` (if specified) for one to arrive.
+    fn receive(&mut self, timeout: Option<Duration>) -> Option<(usize, Message)>;
+}
+
+/// An in-process [`Transport`] based on channels, linking instances running on different threads.
+pub struct ChannelTransport {
+    index: usize,
+    peers: Vec<Sender<(usize, Message)>>,
+    rx: Receiver<(usize, Message)>,
+}
+
+impl ChannelTransport {
+    /// Creates `count` transports, all connected to each other.
+    pub fn new_linked(count: usize) -> Vec<Self> {
+        let (txs, rxs): (Vec<_>, Vec<_>) = (0..count).map(|_| mpsc::channel()).unzip();
+        rxs.into_iter()
+            .enumerate()
+            .map(|(index, rx)| ChannelTransport {
+                index,
+                peers: txs
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != index)
+                    .map(|(_, tx)| tx.clone())
+                    .collect(),
+                rx,
+            })
+            .collect()
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    fn send(&mut self, message: &Message) {
+        for peer in &self.peers {
+            // Disconnected peers are simply ignored
+            let _ = peer.send((self.index, message.clone()));
+        }
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> Option<(usize, Message)> {
+        let (sender_index, message) = match timeout {
+            Some(timeout) => match self.rx.recv_timeout(timeout) {
+                Ok(value) => value,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+            },
+            None => self.rx.try_recv().ok()?,
+        };
+        // Convert the sender's global index to a peer index
+        Some((sender_index - (sender_index > self.index) as usize, message))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PeerState {
+    /// The last time announced by the peer, or `None` if it's not being waited for.
+    time_us: Option<u64>,
+}
+
+struct PendingFrame {
+    frame: RxFrame,
+    delivery_time_us: u64,
+}
+
+pub struct LinkBackend<T: Transport> {
+    transport: T,
+    latency_us: u64,
+    sync_quantum_us: u64,
+    peer_timeout: Duration,
+    align_clocks: bool,
+    clock_offset_us: u64,
+    is_aligned: bool,
+    peers: Vec<PeerState>,
+    pending: VecDeque<PendingFrame>,
+    last_announced_time_us: Option<u64>,
+    next_sync_time_us: u64,
+}
+
+impl<T: Transport> LinkBackend<T> {
+    /// Creates a new backend exchanging frames through `transport`, synchronizing with its peers
+    /// every `sync_quantum_us` microseconds.
+    ///
+    /// If `align_clocks` is `true`, the instance's clock will be moved forward to match the first
+    /// peer it hears from if that's ahead of it; this is meant for instances that weren't started
+    /// at the same time (e.g. in different processes). Otherwise, all instances are assumed to have
+    /// started at the same time, which is required for runs to be fully reproducible.
+    pub fn new(
+        transport: T,
+        latency_us: u64,
+        sync_quantum_us: u64,
+        peer_timeout: Duration,
+        align_clocks: bool,
+    ) -> Self {
+        let peers = vec![PeerState { time_us: Some(0) }; transport.peer_count()];
+        LinkBackend {
+            transport,
+            latency_us: latency_us.max(1),
+            sync_quantum_us: sync_quantum_us.max(1),
+            peer_timeout,
+            align_clocks,
+            clock_offset_us: 0,
+            is_aligned: !align_clocks,
+            peers,
+            pending: VecDeque::new(),
+            last_announced_time_us: None,
+            next_sync_time_us: 0,
+        }
+    }
+
+    #[inline]
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    #[inline]
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    #[inline]
+    pub fn latency_us(&self) -> u64 {
+        self.latency_us
+    }
+
+    #[inline]
+    pub fn sync_quantum_us(&self) -> u64 {
+        self.sync_quantum_us
+    }
+
+    /// Returns the offset added to the emulated hardware's clock to obtain times on the timeline
+    /// shared with the other instances.
+    #[inline]
+    pub fn clock_offset_us(&self) -> u64 {
+        self.clock_offset_us
+    }
+
+    fn announce_time(&mut self, time_us: u64) {
+        if self.last_announced_time_us != Some(time_us) {
+            self.last_announced_time_us = Some(time_us);
+            self.transport.send(&Message::Time(time_us));
+        }
+    }
+
+    fn handle_message(&mut self, peer_index: usize, message: Message, local_time_us: u64) {
+        if !self.is_aligned {
+            self.is_aligned = true;
+            let peer_time_us = message.time_us();
+            if self.align_clocks && peer_time_us > local_time_us + self.clock_offset_us {
+                self.clock_offset_us = peer_time_us - local_time_us;
+            }
+        }
+
+        let Some(peer) = self.peers.get_mut(peer_index) else {
+            return;
+        };
+        let time_us = message.time_us();
+        peer.time_us = Some(peer.time_us.map_or(time_us, |prev| prev.max(time_us)));
+
+        if let Message::Frame {
+            data,
+            rate,
+            time_us,
+        } = message
+        {
+            let delivery_time_us =
+                (time_us + self.latency_us).next_multiple_of(self.sync_quantum_us);
+            let frame = PendingFrame {
+                frame: RxFrame { data, rate },
+                delivery_time_us,
+            };
+            // Keep frames sorted by delivery time, preserving arrival order for ties
+            let index = self
+                .pending
+                .partition_point(|pending| pending.delivery_time_us <= delivery_time_us);
+            self.pending.insert(index, frame);
+        }
+    }
+
+    fn is_waiting_for_peers(&self, time_us: u64) -> bool {
+        self.peers.iter().any(|peer| {
+            peer.time_us
+                .is_some_and(|peer_time_us| peer_time_us + self.latency_us <= time_us)
+        })
+    }
+
+    fn sync(&mut self, local_time_us: u64) {
+        while let Some((peer_index, message)) = self.transport.receive(None) {
+            self.handle_message(peer_index, message, local_time_us);
+        }
+
+        // Always announce the current time before blocking, so that peers waiting at the same
+        // boundary can make progress too
+        let mut time_us = local_time_us + self.clock_offset_us;
+        self.announce_time(time_us);
+        let mut boundary_us = time_us - time_us % self.sync_quantum_us;
+        if !self.is_waiting_for_peers(boundary_us) {
+            return;
+        }
+
+        let deadline = Instant::now() + self.peer_timeout;
+        while self.is_waiting_for_peers(boundary_us) {
+            let now = Instant::now();
+            let message = if now < deadline {
+                self.transport.receive(Some(deadline - now))
+            } else {
+                None
+            };
+            match message {
+                Some((peer_index, message)) => {
+                    self.handle_message(peer_index, message, local_time_us);
+                    let new_time_us = local_time_us + self.clock_offset_us;
+                    if new_time_us != time_us {
+                        time_us = new_time_us;
+                        boundary_us = time_us - time_us % self.sync_quantum_us;
+                        self.announce_time(time_us);
+                    }
+                }
+                None => {
+                    // Stop waiting for the peers that didn't answer in time
+                    for peer in &mut self.peers {
+                        if peer.time_us.is_some_and(|peer_time_us| {
+                            peer_time_us + self.latency_us <= boundary_us
+                        }) {
+                            peer.time_us = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T: Transport + 'static> Backend for LinkBackend<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn transmit_frame(&mut self, data: &[u8], rate: Rate, time_us: u64) {
+        let time_us = time_us + self.clock_offset_us;
+        self.transport.send(&Message::Frame {
+            data: data.to_vec(),
+            rate,
+            time_us,
+        });
+        self.last_announced_time_us = Some(time_us);
+    }
+
+    fn receive_frame(&mut self, time_us: u64) -> Option<RxFrame> {
+        // Frames are only ever delivered at quantum boundaries, so there's nothing new to learn
+        // from peers in between
+        if time_us + self.clock_offset_us >= self.next_sync_time_us {
+            self.sync(time_us);
+            let time_us = time_us + self.clock_offset_us;
+            self.next_sync_time_us = (time_us / self.sync_quantum_us + 1) * self.sync_quantum_us;
+        }
+        let time_us = time_us + self.clock_offset_us;
+        if self
+            .pending
+            .front()
+            .is_some_and(|pending| pending.delivery_time_us <= time_us)
+        {
+            self.pending.pop_front().map(|pending| pending.frame)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu::{bus::DebugCpuAccess, interpreter::Interpreter},
+        emu::Emu,
+        test_utils,
+        utils::mem_prelude::*,
+        wifi::{TxSlot, WiFi},
+    };
+    use std::thread;
+
+    /// Builds an idle instance linked through `transport`, with its Wi-Fi hardware set up to
+    /// receive frames.
+    fn build_emu(transport: ChannelTransport) -> Emu<Interpreter> {
+        let mut emu_builder = test_utils::idle_emu_builder();
+        emu_builder.wifi_backend = Box::new(LinkBackend::new(
+            transport,
+            DEFAULT_LATENCY_US,
+            DEFAULT_SYNC_QUANTUM_US,
+            Duration::from_secs(5),
+            false,
+        ));
+        let mut emu = test_utils::build(emu_builder, Interpreter);
+
+        for (addr, value) in [
+            // Wake up, enable the hardware and receive into 0x1000..0x1800
+            (0x040, 0x8000),
+            (0x004, 0x0001),
+            (0x050, 0x1000),
+            (0x052, 0x1800),
+            (0x056, 0x0800),
+            (0x05A, 0x0800),
+            (0x030, 0x8001),
+        ] {
+            WiFi::write_16::<DebugCpuAccess, _>(&mut emu, addr, value);
+        }
+        emu
+    }
+
+    fn test_frame() -> Vec<u8> {
+        let mut frame = vec![0x08, 0x00, 0x00, 0x00];
+        frame.extend_from_slice(&[0xFF; 6]);
+        frame.extend_from_slice(&[0x00, 0x09, 0xBF, 0x12, 0x34, 0x56]);
+        frame.extend_from_slice(&[0x00, 0x09, 0xBF, 0x12, 0x34, 0x56]);
+        frame.extend_from_slice(&[0x00, 0x00]);
+        frame.extend_from_slice(b"dust link test\0\0");
+        frame
+    }
+
+    #[test]
+    fn linked_instances_exchange_frames() {
+        let mut transports = ChannelTransport::new_linked(2).into_iter();
+        let tx_transport = transports.next().unwrap();
+        let rx_transport = transports.next().unwrap();
+
+        let sender = thread::spawn(move || {
+            let mut emu = build_emu(tx_transport);
+            let frame = test_frame();
+            // TX header at 0x000, with the 2 Mbps rate and the length including the FCS
+            emu.wifi.ram.write_le(8, 0x14_u16);
+            emu.wifi.ram.write_le(0xA, frame.len() as u16 + 4);
+            for (i, chunk) in frame.chunks_exact(2).enumerate() {
+                emu.wifi
+                    .ram
+                    .write_le(0xC + i * 2, u16::from_le_bytes([chunk[0], chunk[1]]));
+            }
+            // Send from LOC1 without overwriting the sequence number
+            WiFi::write_16::<DebugCpuAccess, _>(&mut emu, 0x0A0, 0xA000);
+            WiFi::write_16::<DebugCpuAccess, _>(&mut emu, 0x0AE, 0x0001);
+            for _ in 0..4 {
+                emu.run();
+            }
+            emu.wifi.tx_slot(TxSlot::Loc1).enabled()
+        });
+
+        let receiver = thread::spawn(move || {
+            let mut emu = build_emu(rx_transport);
+            for _ in 0..4 {
+                emu.run();
+            }
+            let frame = test_frame();
+            let received = (0..frame.len())
+                .map(|i| emu.wifi.ram[0x100C + i])
+                .collect::<Vec<_>>();
+            (
+                emu.wifi.ram.read_le::<u16>(0x1008) as usize,
+                received,
+                emu.wifi.irqs_requested().rx_complete(),
+            )
+        });
+
+        assert!(!sender.join().unwrap(), "frame wasn't sent");
+        let (len, received, rx_complete) = receiver.join().unwrap();
+        assert!(rx_complete, "frame wasn't received");
+        assert_eq!(len, test_frame().len());
+        assert_eq!(received, test_frame());
+    }
+}
@@ -4,6 +4,7 @@ pub use self::cpal::*;
 use super::{InterpMethod, SYS_CLOCK_RATE};
 use dust_core::spi::tsc::{MicBackend, MIC_SAMPLES_PER_FRAME};
 use std::{
+    any::Any,
     cell::UnsafeCell,
     sync::atomic::{AtomicUsize, Ordering},
     sync::Arc,
@@ -55,6 +56,14 @@ pub struct Receiver {
 }
 
 impl MicBackend for Receiver {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn start_frame(&mut self) {
         self.frame_start_i = self.buffer.write_pos.load(Ordering::Acquire);
     }
@@ -7,12 +7,15 @@ pub use setting::{
 };
 
 use crate::{
-    audio, input,
+    audio,
+    game_db::SaveType,
+    input,
     utils::{base_dirs, double_option, HomePathBuf},
 };
 use dust_core::{
     audio::ChannelInterpMethod as AudioChannelInterpMethod,
     cpu::{arm7, arm9},
+    dsi,
     spi::firmware,
     utils::{zeroed_box, BoxedByteSlice, Bytes},
     Model,
@@ -265,6 +268,11 @@ pub struct GlobalSysPaths {
     pub arm7_bios: Option<HomePathBuf>,
     pub arm9_bios: Option<HomePathBuf>,
     pub firmware: Option<HomePathBuf>,
+    pub gba_bios: Option<HomePathBuf>,
+    pub gba_slot_rom: Option<HomePathBuf>,
+    pub dsi_arm7_bios: Option<HomePathBuf>,
+    pub dsi_nand: Option<HomePathBuf>,
+    pub dsi_sd_card: Option<HomePathBuf>,
 }
 
 #[derive(Clone, Default, Serialize, Deserialize)]
@@ -278,6 +286,16 @@ pub struct GameSysPaths {
     pub arm9_bios: Option<Option<HomePathBuf>>,
     #[serde(skip_serializing_if = "Option::is_none", with = "double_option")]
     pub firmware: Option<Option<HomePathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "double_option")]
+    pub gba_bios: Option<Option<HomePathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "double_option")]
+    pub gba_slot_rom: Option<Option<HomePathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "double_option")]
+    pub dsi_arm7_bios: Option<Option<HomePathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "double_option")]
+    pub dsi_nand: Option<Option<HomePathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "double_option")]
+    pub dsi_sd_card: Option<Option<HomePathBuf>>,
 }
 
 impl GameSysPaths {
@@ -287,6 +305,11 @@ impl GameSysPaths {
             arm7_bios: Some(None),
             arm9_bios: Some(None),
             firmware: Some(None),
+            gba_bios: Some(None),
+            gba_slot_rom: Some(None),
+            dsi_arm7_bios: Some(None),
+            dsi_nand: Some(None),
+            dsi_sd_card: Some(None),
         }
     }
 }
@@ -296,6 +319,11 @@ pub struct ResolvedSysPaths {
     pub arm7_bios: Option<HomePathBuf>,
     pub arm9_bios: Option<HomePathBuf>,
     pub firmware: Option<HomePathBuf>,
+    pub gba_bios: Option<HomePathBuf>,
+    pub gba_slot_rom: Option<HomePathBuf>,
+    pub dsi_arm7_bios: Option<HomePathBuf>,
+    pub dsi_nand: Option<HomePathBuf>,
+    pub dsi_sd_card: Option<HomePathBuf>,
 }
 
 impl ResolvedSysPaths {
@@ -311,7 +339,17 @@ impl ResolvedSysPaths {
             };
         }
 
-        override_paths!(dir, arm7_bios, arm9_bios, firmware);
+        override_paths!(
+            dir,
+            arm7_bios,
+            arm9_bios,
+            firmware,
+            gba_bios,
+            gba_slot_rom,
+            dsi_arm7_bios,
+            dsi_nand,
+            dsi_sd_card
+        );
 
         macro_rules! path {
             ($field: ident, $path_in_sys_dir: expr) => {
@@ -327,6 +365,11 @@ impl ResolvedSysPaths {
                 arm7_bios: path!(arm7_bios, "biosnds7.bin"),
                 arm9_bios: path!(arm9_bios, "biosnds9.bin"),
                 firmware: path!(firmware, "firmware.bin"),
+                gba_bios: path!(gba_bios, "gba_bios.bin"),
+                gba_slot_rom: gba_slot_rom.clone(),
+                dsi_arm7_bios: path!(dsi_arm7_bios, "biosdsi7.bin"),
+                dsi_nand: path!(dsi_nand, "nand.bin"),
+                dsi_sd_card: dsi_sd_card.clone(),
             },
             SettingOrigin::Game,
         )
@@ -351,6 +394,15 @@ pub enum ModelConfig {
     Dsi,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GbaSlotKind {
+    None,
+    Cartridge,
+    MemoryExpansionPak,
+    RumblePak,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum Renderer2dKind {
@@ -437,6 +489,11 @@ def_config! {
             screen_integer_scale: bool = false,
             reset_on_save_slot_switch: bool = true,
             gdb_server_addr: SocketAddr = ([127_u8, 0, 0, 1], 12345_u16).into(),
+            wifi_link_enabled: bool = false,
+            wifi_link_local_addr: SocketAddr = ([127_u8, 0, 0, 1], 24780_u16).into(),
+            wifi_link_peer_addr: SocketAddr = ([127_u8, 0, 0, 1], 24781_u16).into(),
+            dsi_nand_cid: String = String::new(),
+            dsi_console_id: String = String::new(),
         }
         overridable {
             ds_slot_rom_in_memory_max_size: u32 = 32 * 1024 * 1024, Some(32 * 1024 * 1024), None,
@@ -454,11 +511,20 @@ def_config! {
                 resolve resolve_option, set set_option,
             prefer_hle_bios: bool = false, Some(false), None,
                 resolve resolve_option, set set_option,
+            gba_slot_kind: GbaSlotKind
+                = GbaSlotKind::Cartridge, Some(GbaSlotKind::Cartridge), None,
+                resolve resolve_option, set set_option,
             input_map: input::Map, input::GlobalMap, input::Map, ()
                 = Default::default(), Default::default(), input::Map::empty(),
                 resolve input::Map::resolve, set set_unreachable,
             include_save_in_savestates: bool = true, Some(true), None,
                 resolve resolve_option, set set_option,
+            rewind_enabled: bool = false, Some(false), None,
+                resolve resolve_option, set set_option,
+            rewind_interval_frames: u32 = 1, Some(1), None,
+                resolve resolve_option, set set_option,
+            rewind_buffer_size_mib: u32 = 256, Some(256), None,
+                resolve resolve_option, set set_option,
         }
         game {}
     }
@@ -516,9 +582,12 @@ def_config! {
                 resolve resolve_option, set set_option,
             resolution_scale_shift: u8 = 0, Some(0), None,
                 resolve resolve_option, set set_option,
+            soft_3d_thread_count: u8 = 1, Some(1), None,
+                resolve resolve_option, set set_option,
         }
         game {
             save_path_config: Option<saves::PathConfig> = Some(Default::default()),
+            detected_save_type: Option<SaveType> = None,
         }
     }
     ui {
@@ -532,6 +601,14 @@ impl Config {
             .as_ref()
             .and_then(|config| config.path(&config!(self, &save_dir_path).0, game_title))
     }
+
+    /// Returns the path of the save file for a GBA slot cartridge, kept apart from the DS ones to
+    /// avoid name collisions.
+    pub fn gba_save_path(&self, rom_path: &Path) -> Option<PathBuf> {
+        let mut file_name = rom_path.file_stem()?.to_os_string();
+        file_name.push(".sav");
+        Some(config!(self, &save_dir_path).0.join("gba").join(file_name))
+    }
 }
 
 #[derive(Default)]
@@ -604,6 +681,8 @@ pub struct SysFiles {
     pub arm7_bios: Option<Box<Bytes<{ arm7::BIOS_SIZE }>>>,
     pub arm9_bios: Option<Box<Bytes<{ arm9::BIOS_SIZE }>>>,
     pub firmware: Option<BoxedByteSlice>,
+    pub gba_bios: Option<Box<Bytes<{ arm7::BIOS_SIZE }>>>,
+    pub dsi_arm7_bios: Option<Box<Bytes<{ dsi::boot::ARM7_BIOS_SIZE }>>>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -611,6 +690,8 @@ pub enum SystemFile {
     Arm7Bios,
     Arm9Bios,
     Firmware,
+    GbaBios,
+    DsiArm7Bios,
 }
 
 pub enum LaunchWarning {
@@ -667,7 +748,13 @@ pub enum LaunchError {
 
 impl fmt::Display for LaunchError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        const SYS_FILE_NAMES: [&str; 3] = ["ARM7 BIOS", "ARM9 BIOS", "firmware"];
+        const SYS_FILE_NAMES: [&str; 5] = [
+            "ARM7 BIOS",
+            "ARM9 BIOS",
+            "firmware",
+            "GBA BIOS",
+            "DSi ARM7 BIOS",
+        ];
 
         match self {
             LaunchError::MissingSysPath(file) => {
@@ -789,6 +876,37 @@ impl Launch {
             }),
         );
 
+        // The GBA BIOS is only needed to switch to GBA mode, so it's only loaded if present
+        let gba_bios = config.sys_paths.get().gba_bios.as_ref().and_then(|path| {
+            let mut file = match fs::File::open(&path.0) {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+                Err(err) => {
+                    errors.push(LaunchError::SysFileError(SystemFile::GbaBios, err));
+                    return None;
+                }
+            };
+            (|| {
+                let len = file.metadata()?.len();
+                if len == arm7::BIOS_SIZE as u64 {
+                    let mut buf = zeroed_box::<Bytes<{ arm7::BIOS_SIZE }>>();
+                    file.read_exact(&mut **buf)?;
+                    Ok(Some(buf))
+                } else {
+                    errors.push(LaunchError::InvalidSysFileLength {
+                        file: SystemFile::GbaBios,
+                        expected: arm7::BIOS_SIZE,
+                        got: len,
+                    });
+                    Ok(None)
+                }
+            })()
+            .unwrap_or_else(|err| {
+                errors.push(LaunchError::SysFileError(SystemFile::GbaBios, err));
+                None
+            })
+        });
+
         if let Some(firmware) = &firmware {
             if !firmware::is_valid_size(firmware.len()) {
                 errors.push(LaunchError::InvalidFirmwareFileLength {
@@ -801,6 +919,11 @@ impl Launch {
             return Err(errors);
         }
 
+        // Without all system files, the firmware can't actually run, so its boot process is
+        // emulated instead
+        let skip_firmware =
+            skip_firmware || arm7_bios.is_none() || arm9_bios.is_none() || firmware.is_none();
+
         let model = match config.model.get() {
             ModelConfig::Auto => firmware
                 .as_ref()
@@ -823,12 +946,42 @@ impl Launch {
             }
         }
 
+        // Booting the DSi firmware additionally needs the DSi ARM7 BIOS, which holds the keys used
+        // to decrypt the stage 2 bootloader stored in the NAND image
+        let dsi_arm7_bios = if model == Model::Dsi && !skip_firmware {
+            open_file!(&config.sys_paths.get().dsi_arm7_bios, DsiArm7Bios, |file| {
+                let len = file.metadata()?.len();
+                if len == dsi::boot::ARM7_BIOS_SIZE as u64 {
+                    let mut buf = zeroed_box::<Bytes<{ dsi::boot::ARM7_BIOS_SIZE }>>();
+                    file.read_exact(&mut **buf)?;
+                    Some(buf)
+                } else {
+                    errors.push(LaunchError::InvalidSysFileLength {
+                        file: SystemFile::DsiArm7Bios,
+                        expected: dsi::boot::ARM7_BIOS_SIZE,
+                        got: len,
+                    });
+                    None
+                }
+            })
+        } else {
+            None
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let skip_firmware = skip_firmware || (model == Model::Dsi && dsi_arm7_bios.is_none());
+
         Ok((
             Launch {
                 sys_files: SysFiles {
                     arm7_bios,
                     arm9_bios,
                     firmware,
+                    gba_bios,
+                    dsi_arm7_bios,
                 },
                 skip_firmware,
                 model,
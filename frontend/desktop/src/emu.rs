@@ -3,8 +3,10 @@ mod dldi;
 pub mod ds_slot_rom;
 #[cfg(feature = "gdb-server")]
 mod gdb_server;
-mod rtc;
-pub mod soft_renderer_3d;
+pub mod movie;
+pub mod nand_image;
+mod rewind;
+mod wifi;
 
 #[cfg(feature = "debug-views")]
 use super::debug_views;
@@ -14,27 +16,25 @@ use ds_slot_rom::DsSlotRom;
 use dust_core::audio::{Audio, ChannelInterpMethod as AudioChannelInterpMethod};
 use dust_core::{
     audio::DummyBackend as DummyAudioBackend,
-    cpu::{self, interpreter::Interpreter},
-    ds_slot,
+    cpu, ds_slot, dsi,
     emu::{self, RunOutput},
     flash::Flash,
+    gba_slot::{self, cartridge as gba_cartridge, expansion_pak, rumble_pak},
     gpu::{engine_2d, engine_3d, Framebuffer},
+    rtc,
     spi::{self, firmware},
-    utils::{
-        BoxedByteSlice, PersistentReadSavestate, PersistentWriteSavestate, ReadSavestate,
-        WriteSavestate,
-    },
+    utils::BoxedByteSlice,
+    wifi::link,
     Model, SaveContents, SaveReloadContents,
 };
 use emu_utils::triple_buffer;
-#[cfg(feature = "gdb-server")]
-use std::net::SocketAddr;
 #[cfg(feature = "xq-audio")]
 use std::num::NonZeroU32;
 use std::{
     fs::{self, File},
     hint,
     io::{self, Read},
+    net::SocketAddr,
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -43,6 +43,12 @@ use std::{
     time::{Duration, Instant},
 };
 
+/// The CPU engine the emulated system is run with.
+#[cfg(feature = "interp-cache")]
+pub type CpuEngine = cpu::interpreter::CachedInterpreter;
+#[cfg(not(feature = "interp-cache"))]
+pub type CpuEngine = cpu::interpreter::Interpreter;
+
 pub struct SharedState {
     // UI to emu
     pub playing: AtomicBool,
@@ -101,6 +107,17 @@ pub enum Message {
 
     ToggleAudioInput(Option<audio::input::Receiver>),
 
+    UpdateRewinding(bool),
+
+    SetLidClosed(bool),
+
+    StartMovieRecording {
+        path: PathBuf,
+        from_power_on: bool,
+    },
+    PlayMovie(Box<dust_core::movie::Movie>),
+    StopMovie,
+
     #[cfg(feature = "logging")]
     UpdateLogger(slog::Logger),
 
@@ -114,8 +131,10 @@ pub enum Notification {
     DebugViews(debug_views::Notification),
 
     RtcTimeOffsetSecondsUpdated(i64),
+    SaveTypeDetected(SaveType),
     SavestateCreated(String, Savestate),
     SavestateFailed(String),
+    MovieStateUpdated(Option<movie::State>),
 }
 
 #[cfg(feature = "debug-views")]
@@ -140,12 +159,38 @@ pub struct DsSlot {
     pub has_ir: bool,
 }
 
+pub enum GbaSlot {
+    Cartridge {
+        rom: BoxedByteSlice,
+        save_path: Option<PathBuf>,
+    },
+    MemoryExpansionPak,
+    RumblePak,
+}
+
+pub struct DsiStorage {
+    pub nand_path: Option<PathBuf>,
+    pub sd_card_path: Option<PathBuf>,
+    pub nand_cid: [u8; 16],
+    pub console_id: u64,
+}
+
 #[cfg(feature = "dldi")]
 pub struct Dldi {
     pub root_path: PathBuf,
     pub skip_path: PathBuf,
 }
 
+pub struct RewindConfig {
+    pub interval_frames: u32,
+    pub buffer_size: usize,
+}
+
+pub struct WifiLink {
+    pub local_addr: SocketAddr,
+    pub peer_addr: SocketAddr,
+}
+
 fn read_save_file_contents(save_path: &PathBuf) -> io::Result<Option<BoxedByteSlice>> {
     let mut save_file = match File::open(save_path) {
         Ok(save_file) => save_file,
@@ -160,6 +205,72 @@ fn read_save_file_contents(save_path: &PathBuf) -> io::Result<Option<BoxedByteSl
     Ok(Some(save_contents))
 }
 
+fn setup_gba_slot(
+    gba_slot: GbaSlot,
+    #[cfg(feature = "log")] logger: &slog::Logger,
+) -> Option<gba_slot::GbaSlot> {
+    Some(match gba_slot {
+        GbaSlot::Cartridge { rom, save_path } => {
+            let save_contents = if let Some(save_path) = &save_path {
+                read_save_file_contents(save_path).unwrap_or_else(|err| {
+                    error!("GBA save file error", "Couldn't read GBA save file: {err}");
+                    None
+                })
+            } else {
+                None
+            };
+
+            let save_type = if let Some(save_contents) = &save_contents {
+                gba_cartridge::SaveType::from_save_len(save_contents.len()).unwrap_or_else(|| {
+                    error!(
+                        "Unrecognized GBA save type",
+                        "Unrecognized GBA save file size ({} B), defaulting to an empty save.",
+                        save_contents.len()
+                    );
+                    gba_cartridge::SaveType::None
+                })
+            } else {
+                gba_cartridge::SaveType::detect(&rom).unwrap_or(gba_cartridge::SaveType::None)
+            };
+
+            let save_contents = save_contents
+                .filter(|contents| Some(contents.len()) == save_type.expected_len())
+                .map(SaveContents::Existing);
+
+            match gba_cartridge::Cartridge::new(
+                Box::new(rom),
+                save_type,
+                save_contents,
+                #[cfg(feature = "log")]
+                logger.new(slog::o!("gba_slot" => "cartridge")),
+            ) {
+                Ok(cartridge) => cartridge.into(),
+                Err(err) => {
+                    error!(
+                        "GBA ROM error",
+                        "Couldn't insert GBA ROM: {}",
+                        match err {
+                            gba_cartridge::CreationError::InvalidRomSize => "invalid ROM size",
+                            gba_cartridge::CreationError::InvalidSaveSize => "invalid save size",
+                        }
+                    );
+                    return None;
+                }
+            }
+        }
+
+        GbaSlot::MemoryExpansionPak => expansion_pak::MemoryExpansionPak::new(
+            #[cfg(feature = "log")]
+            logger.new(slog::o!("gba_slot" => "expansion_pak")),
+        )
+        .into(),
+
+        GbaSlot::RumblePak => {
+            rumble_pak::RumblePak::new(Box::new(rumble_pak::DummyRumbleBackend)).into()
+        }
+    })
+}
+
 fn setup_ds_slot(
     ds_slot: Option<DsSlot>,
     save_path: &Option<PathBuf>,
@@ -213,15 +324,25 @@ fn setup_ds_slot(
                     SaveType::None
                 })
             }
+        } else if let Some(save_type) = ds_slot.save_type {
+            save_type
+        } else if ds_slot.has_ir {
+            // Every save command is prefixed by an IR command on cartridges with an infrared
+            // transceiver, so their save type can't be detected; all known ones use 4 Mib FLASH
+            // chips
+            SaveType::Flash4m
         } else {
-            ds_slot.save_type.unwrap_or_else(|| {
-                error!(
-                    "Unknown save type",
-                    "No existing save file present and no database entry found, defaulting to an \
-                     empty save.",
-                );
-                SaveType::None
-            })
+            // No existing save file present and no database entry found, detect the save type
+            // from the game's accesses; once known, it's reported through
+            // `Notification::SaveTypeDetected` and stored in the game's config
+            return (
+                Some(rom),
+                ds_slot::spi::AutoDetect::new(
+                    #[cfg(feature = "log")]
+                    logger.new(slog::o!("ds_spi" => "auto_detect")),
+                )
+                .into(),
+            );
         };
 
         let spi = if save_type == SaveType::None {
@@ -268,28 +389,34 @@ fn setup_ds_slot(
                     .into()
                 }
                 SaveType::Flash2m | SaveType::Flash4m | SaveType::Flash8m => {
-                    ds_slot::spi::flash::Flash::new(
+                    let flash = ds_slot::spi::flash::Flash::new(
                         save_contents,
                         [0; 20],
-                        ds_slot.has_ir,
                         #[cfg(feature = "log")]
-                        logger.new(
-                            slog::o!("ds_spi" => if ds_slot.has_ir { "flash" } else { "flash_ir" }),
-                        ),
+                        logger.new(slog::o!("ds_spi" => "flash")),
                     )
                     // NOTE: The save contents' size is ensured beforehand, this should never occur.
-                    .expect("couldn't create FLASH DS slot SPI device")
-                    .into()
+                    .expect("couldn't create FLASH DS slot SPI device");
+                    if ds_slot.has_ir {
+                        ds_slot::spi::ir::Ir::new(
+                            flash,
+                            Box::new(ds_slot::spi::ir::DummyBackend),
+                            #[cfg(feature = "log")]
+                            logger.new(slog::o!("ds_spi" => "flash_ir")),
+                        )
+                        .into()
+                    } else {
+                        flash.into()
+                    }
                 }
                 SaveType::Nand64m | SaveType::Nand128m | SaveType::Nand256m => {
-                    error!(
-                        "Save file unsupported",
-                        "TODO: NAND saves are currently unsupported, falling back to no save file.",
-                    );
-                    ds_slot::spi::Empty::new(
+                    ds_slot::spi::nand::Nand::new(
+                        save_contents,
                         #[cfg(feature = "log")]
-                        logger.new(slog::o!("ds_spi" => "nand_todo")),
+                        logger.new(slog::o!("ds_spi" => "nand")),
                     )
+                    // NOTE: The save contents' size is ensured beforehand, this should never occur.
+                    .expect("couldn't create NAND DS slot save device")
                     .into()
                 }
             }
@@ -308,6 +435,13 @@ fn setup_ds_slot(
     }
 }
 
+/// Creates an RTC backend following the host's local time, shifted by the given offset.
+fn host_rtc_backend(time_offset_seconds: i64) -> rtc::HostClockBackend {
+    rtc::HostClockBackend::with_utc_offset(time_offset_seconds, || {
+        chrono::Local::now().offset().local_minus_utc().into()
+    })
+}
+
 fn build_emu<E: cpu::Engine>(emu_builder: emu::Builder, engine: E) -> Option<emu::Emu<E>> {
     match emu_builder.build(engine) {
         Ok(emu) => Some(emu),
@@ -326,6 +460,19 @@ fn build_emu<E: cpu::Engine>(emu_builder: emu::Builder, engine: E) -> Option<emu
                 );
                 None
             }
+            emu::BuildError::DsiStage2(err) => {
+                error!(
+                    "Emulator error",
+                    "Couldn't load the DSi stage 2 bootloader from the NAND image: {}",
+                    match err {
+                        dsi::boot::Stage2Error::MissingNand => "no NAND image provided".to_string(),
+                        dsi::boot::Stage2Error::ReadFailed { sector } =>
+                            format!("couldn't read sector {sector:#X}"),
+                        dsi::boot::Stage2Error::InvalidBootInfo => "invalid boot info".to_string(),
+                    }
+                );
+                None
+            }
         },
     }
 }
@@ -333,8 +480,10 @@ fn build_emu<E: cpu::Engine>(emu_builder: emu::Builder, engine: E) -> Option<emu
 pub struct LaunchData {
     pub sys_files: SysFiles,
     pub ds_slot: Option<DsSlot>,
+    pub gba_slot: Option<GbaSlot>,
     #[cfg(feature = "dldi")]
     pub dldi: Option<Dldi>,
+    pub dsi_storage: Option<DsiStorage>,
 
     pub model: Model,
     pub skip_firmware: bool,
@@ -362,6 +511,10 @@ pub struct LaunchData {
 
     pub rtc_time_offset_seconds: i64,
 
+    pub wifi_link: Option<WifiLink>,
+
+    pub rewind: Option<RewindConfig>,
+
     pub renderer_2d_is_accel: bool,
     pub renderer_2d: Box<dyn engine_2d::Renderer + Send>,
     pub renderer_3d_tx: Box<dyn engine_3d::RendererTx + Send>,
@@ -375,8 +528,10 @@ pub(super) fn run(
     LaunchData {
         sys_files,
         ds_slot,
+        gba_slot,
         #[cfg(feature = "dldi")]
         dldi,
+        dsi_storage,
 
         model,
         skip_firmware,
@@ -404,6 +559,10 @@ pub(super) fn run(
 
         mut rtc_time_offset_seconds,
 
+        wifi_link,
+
+        rewind,
+
         mut renderer_2d_is_accel,
         renderer_2d,
         renderer_3d_tx,
@@ -449,7 +608,7 @@ pub(super) fn run(
             None => Box::new(DummyAudioBackend),
         },
         mic_rx.map(|mic_rx| Box::new(mic_rx) as Box<dyn spi::tsc::MicBackend>),
-        Box::new(rtc::Backend::new(rtc_time_offset_seconds)),
+        Box::new(host_rtc_backend(rtc_time_offset_seconds)),
         renderer_2d,
         renderer_3d_tx,
         #[cfg(feature = "dldi")]
@@ -463,8 +622,55 @@ pub(super) fn run(
         logger.clone(),
     );
 
+    let gba_save_path = match &gba_slot {
+        Some(GbaSlot::Cartridge { save_path, .. }) => save_path.clone(),
+        _ => None,
+    };
+    if let Some(gba_slot) = gba_slot.and_then(|gba_slot| {
+        setup_gba_slot(
+            gba_slot,
+            #[cfg(feature = "log")]
+            &logger,
+        )
+    }) {
+        emu_builder.gba_slot = gba_slot;
+    }
+
+    if let Some(dsi_storage) = dsi_storage {
+        let open_image = |path: PathBuf| {
+            Box::new(nand_image::FileImage::new(path)) as Box<dyn dsi::NandProvider>
+        };
+        emu_builder.dsi_nand = dsi_storage.nand_path.map(open_image);
+        emu_builder.dsi_sd_card = dsi_storage.sd_card_path.map(open_image);
+        emu_builder.dsi_nand_cid = dsi_storage.nand_cid;
+        emu_builder.dsi_console_id = dsi_storage.console_id;
+    }
+
+    if let Some(wifi_link) = wifi_link {
+        match wifi::UdpTransport::new(wifi_link.local_addr, vec![wifi_link.peer_addr]) {
+            Ok(transport) => {
+                emu_builder.wifi_backend = Box::new(link::LinkBackend::new(
+                    transport,
+                    link::DEFAULT_LATENCY_US,
+                    link::DEFAULT_SYNC_QUANTUM_US,
+                    link::DEFAULT_PEER_TIMEOUT,
+                    true,
+                ));
+            }
+            Err(err) => {
+                error!(
+                    "Local wireless error",
+                    "Couldn't bind local wireless socket to {}: {err}", wifi_link.local_addr,
+                );
+            }
+        }
+    }
     emu_builder.arm7_bios.clone_from(&sys_files.arm7_bios);
     emu_builder.arm9_bios.clone_from(&sys_files.arm9_bios);
+    emu_builder.gba_bios.clone_from(&sys_files.gba_bios);
+    emu_builder
+        .dsi_arm7_bios
+        .clone_from(&sys_files.dsi_arm7_bios);
 
     emu_builder.model = model;
     emu_builder.direct_boot = skip_firmware;
@@ -476,7 +682,7 @@ pub(super) fn run(
         emu_builder.audio_channel_interp_method = audio_channel_interp_method;
     }
 
-    let Some(mut emu) = build_emu(emu_builder, Interpreter) else {
+    let Some(mut emu) = build_emu(emu_builder, CpuEngine {}) else {
         return frame_tx;
     };
 
@@ -493,6 +699,13 @@ pub(super) fn run(
     let mut save_interval = Duration::from_secs_f32(save_interval_ms);
     let mut last_save_flush_time = last_frame_time;
 
+    let mut rewind =
+        rewind.map(|config| rewind::Rewind::new(config.interval_frames, config.buffer_size));
+    let mut rewinding = false;
+    let mut lid_closed = false;
+
+    let mut active_movie: Option<movie::Active> = None;
+
     #[cfg(feature = "debug-views")]
     let mut debug_views = debug_views::EmuState::new();
 
@@ -512,15 +725,34 @@ pub(super) fn run(
                     emu.ds_slot.spi.mark_contents_flushed();
                 }
             }
+            if let (Some(save_path), gba_slot::GbaSlot::Cartridge(cartridge)) =
+                (&gba_save_path, &mut emu.gba_slot)
+            {
+                if cartridge.save_contents_dirty()
+                    && save_path
+                        .parent()
+                        .map(|parent| fs::create_dir_all(parent).is_ok())
+                        .unwrap_or(true)
+                    && fs::write(save_path, cartridge.save_contents()).is_ok()
+                {
+                    cartridge.mark_save_contents_flushed();
+                }
+            }
         };
     }
 
+    let mut detecting_save_type = emu.ds_slot.spi.is_detecting_save_type();
+
     'run_loop: loop {
         let mut reset_triggered = false;
+        let mut pending_movie = None;
 
         for message in from_ui.try_iter() {
             match message {
                 Message::UpdateInput(changes) => {
+                    if matches!(active_movie, Some(movie::Active::Playing(_))) {
+                        continue;
+                    }
                     emu.press_keys(changes.pressed);
                     emu.release_keys(changes.released);
                     if let Some(new_touch_pos) = changes.touch_pos {
@@ -546,11 +778,7 @@ pub(super) fn run(
                 }
 
                 Message::CreateSavestate { name, include_save } => {
-                    let mut contents = Vec::new();
-                    if PersistentWriteSavestate::new(&mut contents)
-                        .store(&mut emu)
-                        .is_ok()
-                    {
+                    if let Some(contents) = emu.store_savestate() {
                         notif!(Notification::SavestateCreated(
                             name,
                             Savestate {
@@ -584,15 +812,15 @@ pub(super) fn run(
                 }
 
                 Message::ApplySavestate(savestate) => {
-                    if PersistentReadSavestate::new(&savestate.contents)
-                        .and_then(|mut savestate| savestate.load_into(&mut emu).map_err(drop))
-                        .is_ok()
-                    {
-                        if let Some(save) = savestate.save {
-                            emu.ds_slot
-                                .spi
-                                .reload_contents(SaveReloadContents::Existing(save));
-                        }
+                    if let Err(err) = emu.load_savestate(&savestate.contents) {
+                        error!(
+                            "Savestate loading failed",
+                            "Couldn't load savestate: {err}."
+                        );
+                    } else if let Some(save) = savestate.save {
+                        emu.ds_slot
+                            .spi
+                            .reload_contents(SaveReloadContents::Existing(save));
                     }
                 }
 
@@ -643,12 +871,14 @@ pub(super) fn run(
 
                 Message::UpdateRtcTimeOffsetSeconds(value) => {
                     rtc_time_offset_seconds = value;
-                    emu.rtc
+                    if let Some(backend) = emu
+                        .rtc
                         .backend
                         .as_any_mut()
-                        .downcast_mut::<rtc::Backend>()
-                        .unwrap()
-                        .set_time_offset_seconds(value);
+                        .downcast_mut::<rtc::HostClockBackend>()
+                    {
+                        backend.set_time_offset_seconds(value);
+                    }
                 }
 
                 Message::UpdateRenderers {
@@ -691,7 +921,42 @@ pub(super) fn run(
                     emu.audio.set_channel_interp_method(value);
                 }
 
+                Message::UpdateRewinding(value) => {
+                    rewinding = value;
+                }
+
+                Message::SetLidClosed(value) => {
+                    lid_closed = value;
+                    if !matches!(active_movie, Some(movie::Active::Playing(_))) {
+                        emu.set_lid_closed(value);
+                    }
+                }
+
+                Message::StartMovieRecording {
+                    path,
+                    from_power_on,
+                } => {
+                    pending_movie = Some(movie::Pending::Record {
+                        path,
+                        from_power_on,
+                    });
+                }
+
+                Message::PlayMovie(movie) => {
+                    pending_movie = Some(movie::Pending::Play(movie));
+                }
+
+                Message::StopMovie => {
+                    if let Some(movie) = active_movie.take() {
+                        movie.stop(&mut emu);
+                        notif!(Notification::MovieStateUpdated(None));
+                    }
+                }
+
                 Message::ToggleAudioInput(mic_rx) => {
+                    if matches!(active_movie, Some(movie::Active::Playing(_))) {
+                        continue;
+                    }
                     emu.spi.tsc.mic_data =
                         mic_rx.map(|mic_rx| spi::tsc::MicData::new(Box::new(mic_rx)));
                 }
@@ -741,6 +1006,16 @@ pub(super) fn run(
             playing &= gdb_server.is_running();
         }
 
+        if let Some(pending_movie) = &pending_movie {
+            reset_triggered |= pending_movie.needs_reset();
+        }
+
+        if (reset_triggered || pending_movie.is_some()) && active_movie.is_some() {
+            // Movies can't represent resets, and only one can be active at a time
+            active_movie.take().unwrap().stop(&mut emu);
+            notif!(Notification::MovieStateUpdated(None));
+        }
+
         if reset_triggered {
             #[cfg(feature = "xq-audio")]
             let audio_custom_sample_rate = emu.audio.custom_sample_rate();
@@ -763,8 +1038,17 @@ pub(super) fn run(
                 logger.clone(),
             );
 
+            emu_builder.gba_slot = emu.gba_slot.reset();
+            emu_builder.wifi_backend = emu.wifi.backend;
+            emu_builder.dsi_console_id = emu.dsi.console_id();
+            emu_builder.dsi_nand_cid = emu.dsi.sd_mmc.emmc().cid();
+            (emu_builder.dsi_sd_card, emu_builder.dsi_nand) = emu.dsi.sd_mmc.into_providers();
             emu_builder.arm7_bios.clone_from(&sys_files.arm7_bios);
             emu_builder.arm9_bios.clone_from(&sys_files.arm9_bios);
+            emu_builder.gba_bios.clone_from(&sys_files.gba_bios);
+            emu_builder
+                .dsi_arm7_bios
+                .clone_from(&sys_files.dsi_arm7_bios);
 
             emu_builder.model = model;
             emu_builder.direct_boot = skip_firmware;
@@ -776,18 +1060,44 @@ pub(super) fn run(
                 emu_builder.audio_channel_interp_method = audio_channel_interp_method;
             }
 
-            if let Some(new_emu) = build_emu(emu_builder, Interpreter) {
+            if let Some(new_emu) = build_emu(emu_builder, CpuEngine {}) {
                 emu = new_emu;
+                emu.set_lid_closed(lid_closed);
+                if let Some(rewind) = &mut rewind {
+                    rewind.clear();
+                }
             } else {
                 return frame_tx;
             };
         }
 
+        if let Some(pending_movie) = pending_movie {
+            active_movie = pending_movie.start(&mut emu);
+            if let Some(movie) = &active_movie {
+                notif!(Notification::MovieStateUpdated(Some(movie.state())));
+            }
+        }
+
         playing &= shared_state.playing.load(Ordering::Relaxed);
 
         let frame = frame_tx.current();
 
-        if playing {
+        let rewound = match &mut rewind {
+            Some(rewind) if playing && rewinding && active_movie.is_none() => {
+                rewind.step_back(&mut emu, &mut frame.fb);
+                true
+            }
+            _ => false,
+        };
+
+        if playing && !rewound {
+            if let Some(movie) = &mut active_movie {
+                if !movie.before_frame(&mut emu) {
+                    active_movie.take().unwrap().stop(&mut emu);
+                    notif!(Notification::MovieStateUpdated(None));
+                }
+            }
+
             #[cfg(not(feature = "gdb-server"))]
             let run_output = emu.run();
             #[cfg(feature = "gdb-server")]
@@ -801,7 +1111,13 @@ pub(super) fn run(
             };
             match run_output {
                 RunOutput::FrameFinished => {}
-                RunOutput::Shutdown => {
+                RunOutput::Shutdown | RunOutput::MissingGbaBios => {
+                    if run_output == RunOutput::MissingGbaBios {
+                        error!(
+                            "Missing GBA BIOS",
+                            "Emulation was stopped, as switching to GBA mode requires a GBA BIOS."
+                        );
+                    }
                     notif!(Notification::Stopped);
                     playing = false;
                     #[cfg(feature = "gdb-server")]
@@ -820,10 +1136,28 @@ pub(super) fn run(
             }
         }
 
-        if !renderer_2d_is_accel {
-            frame
-                .fb
-                .copy_from_slice(emu.gpu.renderer_2d().framebuffer());
+        if !renderer_2d_is_accel && !rewound {
+            let renderer_2d = emu.gpu.renderer_2d();
+            frame.fb.copy_from_slice(renderer_2d.framebuffer());
+            frame.hi_res_fb.clear();
+            frame.resolution_scale_shift = 0;
+            if let Some((resolution_scale_shift, hi_res_fb)) = renderer_2d.hi_res_framebuffer() {
+                frame.hi_res_fb.extend_from_slice(hi_res_fb);
+                frame.resolution_scale_shift = resolution_scale_shift;
+            }
+        } else if rewound {
+            // Rewind states only hold native-resolution frames
+            frame.hi_res_fb.clear();
+            frame.resolution_scale_shift = 0;
+        }
+        let restored_fb = rewind.as_ref().is_some_and(rewind::Rewind::has_framebuffer);
+        frame.is_stale = rewound && (renderer_2d_is_accel || !restored_fb);
+        frame.backlights_off = emu.spi.power.sleeping();
+
+        if playing && !rewound {
+            if let Some(rewind) = &mut rewind {
+                rewind.frame_finished(&mut emu, (!renderer_2d_is_accel).then_some(&*frame.fb));
+            }
         }
 
         #[cfg(feature = "debug-views")]
@@ -847,14 +1181,25 @@ pub(super) fn run(
             save!();
         }
 
-        let new_rtc_time_offset_seconds = emu
+        if detecting_save_type && !emu.ds_slot.spi.is_detecting_save_type() {
+            // Report the detected save type so it can be stored in the game's config, and write
+            // the save file right away so its size matches it
+            detecting_save_type = false;
+            if let Some(save_type) = SaveType::from_ds_slot_spi(&emu.ds_slot.spi) {
+                notif!(Notification::SaveTypeDetected(save_type));
+            }
+            save!();
+        }
+
+        // While a movie is active, the RTC is driven by the movie's own backend instead
+        if let Some(new_rtc_time_offset_seconds) = emu
             .rtc
             .backend
             .as_any()
-            .downcast_ref::<rtc::Backend>()
-            .unwrap()
-            .time_offset_seconds();
-        if new_rtc_time_offset_seconds != rtc_time_offset_seconds {
+            .downcast_ref::<rtc::HostClockBackend>()
+            .map(rtc::HostClockBackend::time_offset_seconds)
+            .filter(|value| *value != rtc_time_offset_seconds)
+        {
             rtc_time_offset_seconds = new_rtc_time_offset_seconds;
             notif!(Notification::RtcTimeOffsetSecondsUpdated(
                 new_rtc_time_offset_seconds,
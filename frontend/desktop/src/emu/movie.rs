@@ -0,0 +1,114 @@
+use super::CpuEngine;
+use dust_core::{
+    emu::Emu,
+    movie::{Movie, Player, Recorder, SetupError},
+};
+use std::{fs, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Recording,
+    Playing,
+}
+
+pub(super) enum Pending {
+    Record { path: PathBuf, from_power_on: bool },
+    Play(Box<Movie>),
+}
+
+pub(super) enum Active {
+    Recording { recorder: Recorder, path: PathBuf },
+    Playing(Player),
+}
+
+fn setup_error_message(err: SetupError) -> String {
+    match err {
+        SetupError::ModelMismatch { movie, emu } => {
+            format!("The movie was recorded on a different model ({movie:?}, current: {emu:?}).")
+        }
+        SetupError::SavestateCreation => "Couldn't create the starting savestate.".to_string(),
+        SetupError::SavestateLoading(err) => {
+            format!("Couldn't load the movie's savestate: {err}.")
+        }
+    }
+}
+
+impl Pending {
+    /// Whether the emulator needs to be reset before the movie can start.
+    pub fn needs_reset(&self) -> bool {
+        match self {
+            Pending::Record { from_power_on, .. } => *from_power_on,
+            Pending::Play(movie) => movie.savestate.is_none(),
+        }
+    }
+
+    pub fn start(self, emu: &mut Emu<CpuEngine>) -> Option<Active> {
+        match self {
+            Pending::Record {
+                path,
+                from_power_on,
+            } => match Recorder::new(emu, from_power_on) {
+                Ok(recorder) => Some(Active::Recording { recorder, path }),
+                Err(err) => {
+                    error!(
+                        "Movie recording failed",
+                        "Couldn't start recording movie: {}",
+                        setup_error_message(err)
+                    );
+                    None
+                }
+            },
+            Pending::Play(movie) => {
+                let mut player = Player::new(*movie);
+                match player.setup(emu) {
+                    Ok(()) => Some(Active::Playing(player)),
+                    Err(err) => {
+                        error!(
+                            "Movie playback failed",
+                            "Couldn't start playing movie: {}",
+                            setup_error_message(err)
+                        );
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Active {
+    pub fn state(&self) -> State {
+        match self {
+            Active::Recording { .. } => State::Recording,
+            Active::Playing(_) => State::Playing,
+        }
+    }
+
+    /// Records or applies the inputs for the frame that's about to be run; returns `false` if
+    /// playback has reached the end of the movie.
+    pub fn before_frame(&mut self, emu: &mut Emu<CpuEngine>) -> bool {
+        match self {
+            Active::Recording { recorder, .. } => {
+                recorder.record_frame(emu);
+                true
+            }
+            Active::Playing(player) => player.apply_frame(emu),
+        }
+    }
+
+    pub fn stop(self, emu: &mut Emu<CpuEngine>) {
+        match self {
+            Active::Recording { recorder, path } => {
+                let movie = recorder.finish(emu);
+                if let Err(err) = fs::write(&path, movie.to_bytes()) {
+                    error!(
+                        "Movie recording failed",
+                        "Couldn't save movie to {}: {err}",
+                        path.display()
+                    );
+                }
+            }
+            Active::Playing(player) => player.finish(emu),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use dust_core::{
+    dsi::{nand::parse_nocash_footer, NandProvider},
+    utils::Bytes,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// A NAND or SD card image backed directly by a file on the host, read and written in place.
+pub struct FileImage {
+    path: PathBuf,
+    file: Option<File>,
+    is_writable: bool,
+    sector_count: u32,
+}
+
+impl FileImage {
+    pub fn new(path: PathBuf) -> Self {
+        FileImage {
+            path,
+            file: None,
+            is_writable: false,
+            sector_count: 0,
+        }
+    }
+
+    /// Reads the CID and console ID from the footer appended to no$gba-style NAND dumps, if
+    /// present.
+    pub fn read_nocash_footer(path: &Path) -> io::Result<Option<([u8; 16], u64)>> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < 0x40 {
+            return Ok(None);
+        }
+        let mut footer = [0; 0x40];
+        file.seek(SeekFrom::Start(len - 0x40))?;
+        file.read_exact(&mut footer)?;
+        Ok(parse_nocash_footer(&footer))
+    }
+
+    fn try_setup(&mut self) -> io::Result<()> {
+        let (file, is_writable) = match OpenOptions::new().read(true).write(true).open(&self.path) {
+            Ok(file) => (file, true),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                (File::open(&self.path)?, false)
+            }
+            Err(err) => return Err(err),
+        };
+        self.sector_count = (file.metadata()?.len() >> 9).min(u32::MAX as u64) as u32;
+        self.file = Some(file);
+        self.is_writable = is_writable;
+        Ok(())
+    }
+}
+
+impl NandProvider for FileImage {
+    fn setup(&mut self) -> bool {
+        self.try_setup().is_ok()
+    }
+
+    fn supports_writes(&self) -> bool {
+        self.is_writable
+    }
+
+    fn sector_count(&self) -> u32 {
+        self.sector_count
+    }
+
+    fn read_sector(&mut self, sector: u32, buffer: &mut Bytes<0x200>) -> bool {
+        let Some(file) = &mut self.file else {
+            return false;
+        };
+        file.seek(SeekFrom::Start((sector as u64) << 9)).is_ok()
+            && file.read_exact(&mut **buffer).is_ok()
+    }
+
+    fn write_sector(&mut self, sector: u32, buffer: &Bytes<0x200>) -> bool {
+        if !self.is_writable {
+            return false;
+        }
+        let Some(file) = &mut self.file else {
+            return false;
+        };
+        file.seek(SeekFrom::Start((sector as u64) << 9)).is_ok()
+            && file.write_all(&**buffer).is_ok()
+    }
+}
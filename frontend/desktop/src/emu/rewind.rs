@@ -0,0 +1,299 @@
+//! In-memory rewind buffer.
+//!
+//! Snapshots are taken every `interval` frames using the same savestate format as named
+//! savestates, followed by the framebuffer they were taken at (if available). Only the newest
+//! snapshot is kept in full; each older one is stored as the deflate-compressed XOR of itself
+//! against the snapshot after it, which is mostly zeros since consecutive snapshots share most of
+//! main memory, VRAM and 3D state. Stepping back undoes the newest delta, and the oldest deltas are
+//! dropped once the buffer's size limit is exceeded.
+
+use super::CpuEngine;
+use dust_core::{emu::Emu, gpu::Framebuffer};
+use miniz_oxide::{
+    deflate::{compress_to_vec, CompressionLevel},
+    inflate::decompress_to_vec,
+};
+use std::{collections::VecDeque, mem::size_of};
+
+const FB_LEN: usize = size_of::<Framebuffer>();
+
+struct Snapshot {
+    /// The savestate, followed by the framebuffer if `has_fb` is set.
+    contents: Vec<u8>,
+    has_fb: bool,
+}
+
+impl Snapshot {
+    fn state_len(&self) -> usize {
+        self.contents.len() - if self.has_fb { FB_LEN } else { 0 }
+    }
+}
+
+struct Delta {
+    /// Length of the older snapshot's contents, which may be shorter than the newer one's.
+    len: usize,
+    has_fb: bool,
+    compressed: Vec<u8>,
+}
+
+pub struct Rewind {
+    interval: u32,
+    max_size: usize,
+    frames_until_snapshot: u32,
+    newest: Option<Snapshot>,
+    /// Whether the emulator's state currently matches `newest`, i.e. no frames have run since it
+    /// was loaded.
+    newest_loaded: bool,
+    /// The deltas turning each snapshot into the previous one, oldest first.
+    deltas: VecDeque<Delta>,
+    deltas_size: usize,
+}
+
+fn xor_into(dst: &mut Vec<u8>, src: &[u8]) {
+    if dst.len() < src.len() {
+        dst.resize(src.len(), 0);
+    }
+    for (dst, src) in dst.iter_mut().zip(src) {
+        *dst ^= src;
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: u32, max_size: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            max_size,
+            frames_until_snapshot: 0,
+            newest: None,
+            newest_loaded: false,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames_until_snapshot = 0;
+        self.newest = None;
+        self.newest_loaded = false;
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+
+    fn take_snapshot(
+        emu: &mut Emu<CpuEngine>,
+        framebuffer: Option<&Framebuffer>,
+    ) -> Option<Snapshot> {
+        let mut contents = emu.store_savestate()?;
+        if let Some(framebuffer) = framebuffer {
+            contents.reserve(FB_LEN);
+            for pixel in framebuffer[0].iter().chain(&framebuffer[1]) {
+                contents.extend_from_slice(&pixel.to_ne_bytes());
+            }
+        }
+        Some(Snapshot {
+            contents,
+            has_fb: framebuffer.is_some(),
+        })
+    }
+
+    /// Counts a finished frame, returning whether a snapshot should be taken after it.
+    fn snapshot_due(&mut self) -> bool {
+        self.newest_loaded = false;
+        if self.frames_until_snapshot > 1 {
+            self.frames_until_snapshot -= 1;
+            return false;
+        }
+        self.frames_until_snapshot = self.interval;
+        true
+    }
+
+    fn push_snapshot(&mut self, snapshot: Snapshot) {
+        if let Some(prev) = self.newest.replace(snapshot) {
+            let len = prev.contents.len();
+            let mut delta = prev.contents;
+            xor_into(&mut delta, &self.newest.as_ref().unwrap().contents);
+            let compressed = compress_to_vec(&delta, CompressionLevel::BestSpeed as u8);
+            self.deltas_size += compressed.len();
+            self.deltas.push_back(Delta {
+                len,
+                has_fb: prev.has_fb,
+                compressed,
+            });
+            while self.deltas_size > self.max_size {
+                let Some(oldest) = self.deltas.pop_front() else {
+                    break;
+                };
+                self.deltas_size -= oldest.compressed.len();
+            }
+        }
+    }
+
+    /// Replaces the newest snapshot with the one before it, returning `false` if there are no
+    /// older snapshots left or the previous one couldn't be restored (in which case the buffer is
+    /// cleared).
+    fn pop_snapshot(&mut self) -> bool {
+        let Some(delta) = self.deltas.pop_back() else {
+            return false;
+        };
+        self.deltas_size -= delta.compressed.len();
+        let Ok(delta_contents) = decompress_to_vec(&delta.compressed) else {
+            self.clear();
+            return false;
+        };
+        let newest = self.newest.as_mut().unwrap();
+        xor_into(&mut newest.contents, &delta_contents);
+        newest.contents.truncate(delta.len);
+        newest.has_fb = delta.has_fb;
+        self.newest_loaded = false;
+        true
+    }
+
+    /// Notifies the buffer that a frame was run, taking a new snapshot if the interval has
+    /// elapsed.
+    pub fn frame_finished(&mut self, emu: &mut Emu<CpuEngine>, framebuffer: Option<&Framebuffer>) {
+        if !self.snapshot_due() {
+            return;
+        }
+        if let Some(snapshot) = Self::take_snapshot(emu, framebuffer) {
+            self.push_snapshot(snapshot);
+        }
+    }
+
+    /// Returns whether the newest snapshot holds a framebuffer, i.e. whether the last call to
+    /// [`step_back`](Self::step_back) restored one.
+    pub fn has_framebuffer(&self) -> bool {
+        self.newest.as_ref().is_some_and(|newest| newest.has_fb)
+    }
+
+    /// Steps back to the previous snapshot (or to the newest one, if any frames have run since it
+    /// was taken), loading it into the emulator and copying its framebuffer to `framebuffer`.
+    ///
+    /// Returns `false` if there are no older snapshots left, in which case the emulator is left at
+    /// the oldest one.
+    pub fn step_back(&mut self, emu: &mut Emu<CpuEngine>, framebuffer: &mut Framebuffer) -> bool {
+        let stepped = !self.newest_loaded || self.pop_snapshot();
+
+        let Some(newest) = &self.newest else {
+            return false;
+        };
+        let state_len = newest.state_len();
+        if !self.newest_loaded && emu.load_savestate(&newest.contents[..state_len]).is_err() {
+            self.clear();
+            return false;
+        }
+        if newest.has_fb {
+            for (pixel, bytes) in framebuffer
+                .iter_mut()
+                .flat_map(|screen| screen.iter_mut())
+                .zip(newest.contents[state_len..].chunks_exact(4))
+            {
+                *pixel = u32::from_ne_bytes(bytes.try_into().unwrap());
+            }
+        }
+        self.newest_loaded = true;
+        self.frames_until_snapshot = self.interval;
+        stepped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(seed: u8, state_len: usize, has_fb: bool) -> Snapshot {
+        let len = state_len + if has_fb { FB_LEN } else { 0 };
+        Snapshot {
+            contents: (0..len)
+                .map(|i| if i % 61 == 0 { seed ^ i as u8 } else { i as u8 })
+                .collect(),
+            has_fb,
+        }
+    }
+
+    fn assert_newest_eq(rewind: &Rewind, expected: &Snapshot) {
+        let newest = rewind.newest.as_ref().unwrap();
+        assert_eq!(newest.has_fb, expected.has_fb);
+        assert_eq!(newest.state_len(), expected.state_len());
+        assert!(newest.contents == expected.contents);
+    }
+
+    #[test]
+    fn snapshot_interval() {
+        let mut rewind = Rewind::new(3, usize::MAX);
+        let due = (0..7).map(|_| rewind.snapshot_due()).collect::<Vec<_>>();
+        assert_eq!(due, [true, false, false, true, false, false, true]);
+
+        let mut every_frame = Rewind::new(0, usize::MAX);
+        assert!((0..3).all(|_| every_frame.snapshot_due()));
+    }
+
+    #[test]
+    fn step_back_through_deltas() {
+        let snapshots = [
+            snapshot(1, 0x1000, true),
+            snapshot(2, 0x1200, false),
+            snapshot(3, 0x0F00, true),
+            snapshot(4, 0x1000, true),
+        ];
+        let mut rewind = Rewind::new(1, usize::MAX);
+        for snapshot in &snapshots {
+            rewind.push_snapshot(Snapshot {
+                contents: snapshot.contents.clone(),
+                has_fb: snapshot.has_fb,
+            });
+        }
+        assert_eq!(rewind.deltas.len(), snapshots.len() - 1);
+        assert_newest_eq(&rewind, &snapshots[3]);
+
+        for expected in snapshots[..3].iter().rev() {
+            assert!(rewind.pop_snapshot());
+            assert_newest_eq(&rewind, expected);
+        }
+        assert!(!rewind.pop_snapshot());
+        assert_newest_eq(&rewind, &snapshots[0]);
+        assert_eq!(rewind.deltas_size, 0);
+    }
+
+    #[test]
+    fn size_limit_drops_oldest_deltas() {
+        // Alternating between two snapshots makes all deltas identical
+        let mut rewind = Rewind::new(1, usize::MAX);
+        rewind.push_snapshot(snapshot(0, 0x1000, false));
+        rewind.push_snapshot(snapshot(1, 0x1000, false));
+        let delta_size = rewind.deltas_size;
+        assert_ne!(delta_size, 0);
+
+        let mut rewind = Rewind::new(1, delta_size * 2);
+        for i in 0..5 {
+            rewind.push_snapshot(snapshot(i & 1, 0x1000, false));
+        }
+        assert_eq!(rewind.deltas.len(), 2);
+        assert_eq!(rewind.deltas_size, delta_size * 2);
+
+        assert!(rewind.pop_snapshot());
+        assert_newest_eq(&rewind, &snapshot(1, 0x1000, false));
+        assert!(rewind.pop_snapshot());
+        assert_newest_eq(&rewind, &snapshot(0, 0x1000, false));
+        assert!(!rewind.pop_snapshot());
+    }
+
+    #[test]
+    fn corrupted_delta_clears_buffer() {
+        let mut rewind = Rewind::new(1, usize::MAX);
+        rewind.push_snapshot(snapshot(0, 0x100, false));
+        rewind.push_snapshot(snapshot(1, 0x100, false));
+        rewind.deltas[0].compressed = vec![0xFF; 4];
+        rewind.deltas_size = 4;
+        assert!(!rewind.pop_snapshot());
+        assert!(rewind.newest.is_none());
+        assert!(rewind.deltas.is_empty());
+        assert_eq!(rewind.deltas_size, 0);
+    }
+
+    #[test]
+    fn xor_into_extends_destination() {
+        let mut dst = vec![0b1010, 0b0110];
+        xor_into(&mut dst, &[0b0011, 0b0110, 0b1111]);
+        assert_eq!(dst, [0b1001, 0, 0b1111]);
+    }
+}
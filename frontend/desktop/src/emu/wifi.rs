@@ -0,0 +1,292 @@
+use dust_core::wifi::{
+    link::{Message, Transport},
+    Rate,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+// Packet layout:
+// - 0x0: kind (0 = time announcement, 1 = frame, 2 = acknowledgement)
+// - 0x1..=0x4: sequence number, little-endian:
+//   - for frames, the index of the frame among the ones sent by the instance
+//   - for time announcements, the number of frames sent before the announcement
+//   - for acknowledgements, the number of frames received in order so far
+// - (time announcements and frames only) 0x5..=0xC: time in microseconds, little-endian
+// - (frames only) 0xD: rate, as stored in the TX/RX headers
+// - (frames only) 0xE..: frame data, without the FCS
+//
+// Frames are resent until acknowledged and delivered in order, and time announcements are only
+// delivered once all frames sent before them have been, so that lost or reordered packets can't
+// make a peer look further ahead than it actually is. Only the latest time announcement matters,
+// so it's simply repeated while waiting for peers.
+const KIND_TIME: u8 = 0;
+const KIND_FRAME: u8 = 1;
+const KIND_ACK: u8 = 2;
+const HEADER_LEN: usize = 0xD;
+const MAX_PACKET_LEN: usize = 0xE + 0x2000;
+
+/// The amount of real time after which unacknowledged frames and the last time announcement are
+/// sent again.
+const RESEND_INTERVAL: Duration = Duration::from_millis(10);
+
+struct SentFrame {
+    seq: u32,
+    packet: Box<[u8]>,
+    last_sent: Instant,
+}
+
+struct Peer {
+    addr: SocketAddr,
+    /// The number of frames the peer has acknowledged receiving.
+    acked_frames: u32,
+    /// The number of frames received in order from the peer.
+    received_frames: u32,
+    out_of_order_frames: BTreeMap<u32, Message>,
+    /// The latest time announcement that can't be delivered yet because some of the frames sent
+    /// before it are still missing, along with the number of frames sent before it.
+    pending_time: Option<(u32, u64)>,
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peers: Vec<Peer>,
+    is_nonblocking: bool,
+    buffer: Box<[u8; MAX_PACKET_LEN]>,
+    sent_frames: u32,
+    unacked_frames: VecDeque<SentFrame>,
+    last_time_packet: Option<([u8; HEADER_LEN], Instant)>,
+    received: VecDeque<(usize, Message)>,
+}
+
+impl UdpTransport {
+    pub fn new(local_addr: SocketAddr, peers: Vec<SocketAddr>) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local_addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpTransport {
+            socket,
+            peers: peers
+                .into_iter()
+                .map(|addr| Peer {
+                    addr,
+                    acked_frames: 0,
+                    received_frames: 0,
+                    out_of_order_frames: BTreeMap::new(),
+                    pending_time: None,
+                })
+                .collect(),
+            is_nonblocking: true,
+            buffer: Box::new([0; MAX_PACKET_LEN]),
+            sent_frames: 0,
+            unacked_frames: VecDeque::new(),
+            last_time_packet: None,
+            received: VecDeque::new(),
+        })
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match timeout {
+            Some(timeout) => {
+                if self.is_nonblocking {
+                    self.socket.set_nonblocking(false)?;
+                    self.is_nonblocking = false;
+                }
+                // A zero timeout would make reads block indefinitely
+                self.socket
+                    .set_read_timeout(Some(timeout.max(Duration::from_micros(1))))
+            }
+            None => {
+                if !self.is_nonblocking {
+                    self.socket.set_nonblocking(true)?;
+                    self.is_nonblocking = true;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn resend(&mut self, now: Instant) {
+        for frame in &mut self.unacked_frames {
+            if now - frame.last_sent < RESEND_INTERVAL {
+                continue;
+            }
+            frame.last_sent = now;
+            for peer in &self.peers {
+                if peer.acked_frames <= frame.seq {
+                    // Packets that couldn't be sent will be retried later
+                    let _ = self.socket.send_to(&frame.packet, peer.addr);
+                }
+            }
+        }
+
+        if let Some((packet, last_sent)) = &mut self.last_time_packet {
+            if now - *last_sent >= RESEND_INTERVAL {
+                *last_sent = now;
+                for peer in &self.peers {
+                    let _ = self.socket.send_to(&packet[..], peer.addr);
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, peer_index: usize, len: usize) {
+        let packet = &self.buffer[..len];
+        let (Some(&kind), Some(seq)) = (packet.first(), packet.get(1..5)) else {
+            return;
+        };
+        let seq = u32::from_le_bytes(seq.try_into().unwrap());
+        let peer = &mut self.peers[peer_index];
+
+        if kind == KIND_ACK {
+            peer.acked_frames = peer.acked_frames.max(seq);
+            let min_acked_frames = self
+                .peers
+                .iter()
+                .map(|peer| peer.acked_frames)
+                .min()
+                .unwrap_or(u32::MAX);
+            while self
+                .unacked_frames
+                .front()
+                .is_some_and(|frame| frame.seq < min_acked_frames)
+            {
+                self.unacked_frames.pop_front();
+            }
+            return;
+        }
+
+        let Some(message) = decode_message(packet) else {
+            return;
+        };
+        match message {
+            Message::Time(time_us) => {
+                if seq <= peer.received_frames {
+                    self.received.push_back((peer_index, message));
+                } else if peer
+                    .pending_time
+                    .map_or(true, |(_, pending_time_us)| time_us > pending_time_us)
+                {
+                    peer.pending_time = Some((seq, time_us));
+                }
+                return;
+            }
+
+            Message::Frame { .. } => {
+                if seq == peer.received_frames {
+                    self.received.push_back((peer_index, message));
+                    peer.received_frames += 1;
+                    while let Some(message) = peer.out_of_order_frames.remove(&peer.received_frames)
+                    {
+                        self.received.push_back((peer_index, message));
+                        peer.received_frames += 1;
+                    }
+                    if let Some((time_seq, time_us)) = peer.pending_time {
+                        if time_seq <= peer.received_frames {
+                            peer.pending_time = None;
+                            self.received
+                                .push_back((peer_index, Message::Time(time_us)));
+                        }
+                    }
+                } else if seq > peer.received_frames {
+                    peer.out_of_order_frames.insert(seq, message);
+                }
+            }
+        }
+
+        // Acknowledge duplicates too, in case the previous acknowledgement got lost
+        let mut ack = [0; 5];
+        ack[0] = KIND_ACK;
+        ack[1..5].copy_from_slice(&peer.received_frames.to_le_bytes());
+        let _ = self.socket.send_to(&ack, peer.addr);
+    }
+}
+
+fn decode_message(packet: &[u8]) -> Option<Message> {
+    let time_us = u64::from_le_bytes(packet.get(5..HEADER_LEN)?.try_into().unwrap());
+    match packet[0] {
+        KIND_TIME => Some(Message::Time(time_us)),
+        KIND_FRAME => Some(Message::Frame {
+            rate: Rate::from_header_byte(*packet.get(HEADER_LEN)?),
+            data: packet[HEADER_LEN + 1..].to_vec(),
+            time_us,
+        }),
+        _ => None,
+    }
+}
+
+impl Transport for UdpTransport {
+    fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    fn send(&mut self, message: &Message) {
+        self.buffer[1..5].copy_from_slice(&self.sent_frames.to_le_bytes());
+        self.buffer[5..HEADER_LEN].copy_from_slice(&message.time_us().to_le_bytes());
+        let now = Instant::now();
+        let len = match message {
+            Message::Time(_) => {
+                self.buffer[0] = KIND_TIME;
+                let mut packet = [0; HEADER_LEN];
+                packet.copy_from_slice(&self.buffer[..HEADER_LEN]);
+                self.last_time_packet = Some((packet, now));
+                HEADER_LEN
+            }
+            Message::Frame { data, rate, .. } => {
+                let len = HEADER_LEN + 1 + data.len().min(MAX_PACKET_LEN - HEADER_LEN - 1);
+                self.buffer[0] = KIND_FRAME;
+                self.buffer[HEADER_LEN] = rate.header_byte();
+                self.buffer[HEADER_LEN + 1..len].copy_from_slice(&data[..len - HEADER_LEN - 1]);
+                self.unacked_frames.push_back(SentFrame {
+                    seq: self.sent_frames,
+                    packet: self.buffer[..len].into(),
+                    last_sent: now,
+                });
+                self.sent_frames += 1;
+                len
+            }
+        };
+        for peer in &self.peers {
+            // Packets that couldn't be sent will be retried later
+            let _ = self.socket.send_to(&self.buffer[..len], peer.addr);
+        }
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> Option<(usize, Message)> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(received) = self.received.pop_front() {
+                return Some(received);
+            }
+
+            let now = Instant::now();
+            self.resend(now);
+            let timeout = match deadline {
+                Some(deadline) => {
+                    if now >= deadline {
+                        return None;
+                    }
+                    // Wake up regularly to resend packets that might have been lost
+                    Some((deadline - now).min(RESEND_INTERVAL))
+                }
+                None => None,
+            };
+            self.set_timeout(timeout).ok()?;
+
+            match self.socket.recv_from(&mut self.buffer[..]) {
+                Ok((len, addr)) => {
+                    if let Some(peer_index) = self.peers.iter().position(|peer| peer.addr == addr) {
+                        self.handle_packet(peer_index, len);
+                    }
+                }
+                Err(_) => {
+                    if deadline.is_none() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -4,6 +4,16 @@ use dust_core::gpu::Framebuffer;
 
 pub struct FrameData {
     pub fb: Box<Framebuffer>,
+    /// The output composited at a higher resolution by the software 2D renderers, if any, as
+    /// returned by `engine_2d::Renderer::hi_res_framebuffer`; `fb` is used when empty.
+    pub hi_res_fb: Vec<u32>,
+    pub resolution_scale_shift: u8,
+    /// Whether the output shown is left over from a later frame, because the frame was restored
+    /// while rewinding and its output wasn't stored (which is always the case for the accelerated
+    /// 2D renderer).
+    pub is_stale: bool,
+    /// Whether the backlights are powered down because the system is in sleep mode.
+    pub backlights_off: bool,
     pub fps: f32,
     #[cfg(feature = "debug-views")]
     pub debug: debug_views::FrameData,
@@ -13,6 +23,10 @@ impl Default for FrameData {
     fn default() -> Self {
         FrameData {
             fb: unsafe { Box::new_zeroed().assume_init() },
+            hi_res_fb: Vec::new(),
+            resolution_scale_shift: 0,
+            is_stale: false,
+            backlights_off: false,
             fps: 0.0,
             #[cfg(feature = "debug-views")]
             debug: debug_views::FrameData::new(),
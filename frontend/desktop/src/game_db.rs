@@ -1,7 +1,8 @@
+use dust_core::ds_slot::spi::Spi as DsSlotSpi;
 use serde::{Deserialize, Serialize};
 use std::{fs, io, path::Path};
 
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum SaveType {
     None,
@@ -59,6 +60,18 @@ impl SaveType {
             _ => None,
         }
     }
+
+    /// Returns the save type corresponding to a DS slot save device, or `None` if it's still being
+    /// detected or doesn't correspond to a single save type.
+    pub fn from_ds_slot_spi(spi: &DsSlotSpi) -> Option<Self> {
+        match spi {
+            DsSlotSpi::Eeprom4k(_) => Some(SaveType::Eeprom4k),
+            DsSlotSpi::EepromFram(_) | DsSlotSpi::Flash(_) => {
+                Self::from_save_len(spi.contents().len())
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -67,6 +80,10 @@ pub struct Entry {
     pub code: u32,
     pub rom_size: u32,
     pub save_type: SaveType,
+    /// Whether the save chip is behind an infrared transceiver; if unspecified, it's guessed from
+    /// the game code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_ir: Option<bool>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
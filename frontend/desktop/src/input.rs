@@ -16,6 +16,8 @@ pub enum Action {
     ToggleFramerateLimit,
     ToggleSyncToAudio,
     ToggleFullWindowScreen,
+    Rewind,
+    ToggleLid,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
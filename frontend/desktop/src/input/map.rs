@@ -39,6 +39,8 @@ static ACTION_IDENTS: &[(Action, &str)] = &[
     ),
     (Action::ToggleSyncToAudio, "toggle-sync-to-audio"),
     (Action::ToggleFramerateLimit, "toggle-framerate-limit"),
+    (Action::Rewind, "rewind"),
+    (Action::ToggleLid, "toggle-lid"),
 ];
 
 #[derive(Clone)]
@@ -102,6 +104,8 @@ fn default_hotkey_map() -> HashMap<Action, Option<Trigger>> {
         (Action::ToggleFullWindowScreen, None),
         (Action::ToggleSyncToAudio, None),
         (Action::ToggleFramerateLimit, None),
+        (Action::Rewind, None),
+        (Action::ToggleLid, None),
     ]
     .into_iter()
     .collect()
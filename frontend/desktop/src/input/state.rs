@@ -175,6 +175,11 @@ impl State {
         }
     }
 
+    /// Returns whether the given hotkey was held as of the last call to `drain_changes`.
+    pub fn is_hotkey_pressed(&self, action: Action) -> bool {
+        self.pressed_hotkeys.contains(&action)
+    }
+
     pub fn drain_changes(
         &mut self,
         map: &Map,
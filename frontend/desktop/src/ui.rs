@@ -18,7 +18,7 @@ pub mod window;
 use crate::debug_views;
 use crate::{
     audio,
-    config::{self, Launch, Renderer2dKind, Renderer3dKind},
+    config::{self, GbaSlotKind, Launch, Renderer2dKind, Renderer3dKind},
     emu::{
         self,
         ds_slot_rom::{self, DsSlotRom},
@@ -29,8 +29,11 @@ use crate::{
 };
 use dust_core::{
     ds_slot::rom::Contents,
-    gpu::{engine_2d, engine_3d, Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
-    utils::zeroed_box,
+    gba_slot,
+    gpu::{engine_2d, engine_3d, SCREEN_HEIGHT, SCREEN_WIDTH},
+    movie::{self, Movie},
+    utils::BoxedByteSlice,
+    Model,
 };
 use emu_utils::triple_buffer;
 #[cfg(feature = "logging")]
@@ -43,7 +46,10 @@ use std::num::NonZeroU32;
 #[cfg(feature = "discord-presence")]
 use std::time::SystemTime;
 use std::{
-    env, fs, io, panic,
+    env, fs,
+    io::{self, Read},
+    num::NonZeroU8,
+    panic,
     path::{Path, PathBuf},
     slice,
     sync::{
@@ -74,6 +80,10 @@ enum Renderer3dData {
 
 struct EmuState {
     playing: bool,
+    rewinding: bool,
+    lid_closed: bool,
+    model: Model,
+    movie_state: Option<emu::movie::State>,
     title: String,
     game_loaded: bool,
     save_path_update: Option<emu::SavePathUpdate>,
@@ -206,6 +216,8 @@ pub struct UiState {
     title_menu_bar: TitleMenuBarState,
 
     screen_focused: bool,
+    screen_is_stale: bool,
+    backlights_off: bool,
 
     input: input::State,
 
@@ -227,6 +239,8 @@ pub struct UiState {
 }
 
 static ALLOWED_ROM_EXTENSIONS: &[&str] = &["nds", "bin"];
+static MOVIE_EXTENSION: &str = "dmv";
+static DESMUME_MOVIE_EXTENSION: &str = "dsm";
 
 impl UiState {
     fn play_pause(&mut self) {
@@ -243,6 +257,57 @@ impl UiState {
             emu.send_message(emu::Message::Reset);
         }
     }
+
+    fn toggle_lid(&mut self) {
+        if let Some(emu) = &mut self.emu {
+            emu.lid_closed = !emu.lid_closed;
+            emu.send_message(emu::Message::SetLidClosed(emu.lid_closed));
+        }
+    }
+
+    fn record_movie(&mut self, from_power_on: bool) {
+        let Some(emu) = &self.emu else {
+            return;
+        };
+        if let Some(path) = FileDialog::new()
+            .add_filter("Movie file", &[MOVIE_EXTENSION])
+            .save_file()
+        {
+            emu.send_message(emu::Message::StartMovieRecording {
+                path: path.with_extension(MOVIE_EXTENSION),
+                from_power_on,
+            });
+        }
+    }
+
+    fn play_movie(&mut self) {
+        let Some(emu) = &self.emu else {
+            return;
+        };
+        let Some(path) = FileDialog::new()
+            .add_filter("Movie file", &[MOVIE_EXTENSION, DESMUME_MOVIE_EXTENSION])
+            .pick_file()
+        else {
+            return;
+        };
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!("Movie playback failed", "Couldn't read movie file: {err}");
+                return;
+            }
+        };
+        let movie = if path.extension() == Some(DESMUME_MOVIE_EXTENSION.as_ref()) {
+            movie::dsm::import(&String::from_utf8_lossy(&contents), emu.model)
+                .map_err(|err| format!("{err:?}"))
+        } else {
+            Movie::from_bytes(&contents).map_err(|err| format!("{err:?}"))
+        };
+        match movie {
+            Ok(movie) => emu.send_message(emu::Message::PlayMovie(Box::new(movie))),
+            Err(err) => error!("Movie playback failed", "Invalid movie file: {err}"),
+        }
+    }
 }
 
 impl UiState {
@@ -345,6 +410,112 @@ impl UiState {
         }
     }
 
+    fn load_gba_slot(config: &config::Config) -> Option<emu::GbaSlot> {
+        match config!(config, gba_slot_kind) {
+            GbaSlotKind::None => None,
+
+            GbaSlotKind::Cartridge => {
+                let rom_path = &config.sys_paths.get().gba_slot_rom.as_ref()?.0;
+                let rom = (|| {
+                    let mut file = fs::File::open(rom_path)?;
+                    let len = file.metadata()?.len();
+                    if !gba_slot::rom::is_valid_size(len) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("invalid ROM file size: {len} B"),
+                        ));
+                    }
+                    let mut rom = BoxedByteSlice::new_zeroed(len as usize);
+                    file.read_exact(&mut rom)?;
+                    Ok(rom)
+                })();
+                match rom {
+                    Ok(rom) => Some(emu::GbaSlot::Cartridge {
+                        rom,
+                        save_path: config.gba_save_path(rom_path),
+                    }),
+                    Err(err) => {
+                        error!(
+                            "Couldn't load GBA ROM file",
+                            "Couldn't load the GBA slot ROM file at `{}`: {err}",
+                            rom_path.display()
+                        );
+                        None
+                    }
+                }
+            }
+
+            GbaSlotKind::MemoryExpansionPak => Some(emu::GbaSlot::MemoryExpansionPak),
+
+            GbaSlotKind::RumblePak => Some(emu::GbaSlot::RumblePak),
+        }
+    }
+
+    fn load_dsi_storage(config: &config::Config, model: Model) -> Option<emu::DsiStorage> {
+        if model != Model::Dsi {
+            return None;
+        }
+        let sys_paths = config.sys_paths.get();
+        let nand_path = sys_paths.dsi_nand.as_ref().map(|path| path.0.clone());
+        let sd_card_path = sys_paths.dsi_sd_card.as_ref().map(|path| path.0.clone());
+
+        // The CID and console ID are taken from the config if specified, and otherwise from the
+        // footer of no$gba-style NAND dumps
+        let nand_cid_str = config!(config, &dsi_nand_cid).trim();
+        let console_id_str = config!(config, &dsi_console_id).trim();
+        let ids = if nand_cid_str.is_empty() && console_id_str.is_empty() {
+            nand_path.as_ref().and_then(
+                |path| match emu::nand_image::FileImage::read_nocash_footer(path) {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        error!(
+                            "Couldn't read DSi NAND image",
+                            "Couldn't read the DSi NAND image at `{}`: {err}",
+                            path.display()
+                        );
+                        None
+                    }
+                },
+            )
+        } else {
+            let nand_cid = (nand_cid_str.len() == 32)
+                .then(|| {
+                    let mut cid = [0; 16];
+                    for (i, byte) in cid.iter_mut().enumerate() {
+                        *byte = u8::from_str_radix(nand_cid_str.get(i * 2..i * 2 + 2)?, 16).ok()?;
+                    }
+                    Some(cid)
+                })
+                .flatten();
+            let console_id = u64::from_str_radix(console_id_str, 16).ok();
+            if nand_cid.is_none() || console_id.is_none() {
+                error!(
+                    "Invalid DSi console IDs",
+                    "The configured DSi eMMC CID and console ID should be 32 and 16 hexadecimal \
+                     digits long respectively."
+                );
+            }
+            nand_cid.zip(console_id)
+        };
+        let (nand_cid, console_id) = ids.unwrap_or_else(|| {
+            if nand_path.is_some() {
+                warning!(
+                    "Missing DSi console IDs",
+                    "No valid eMMC CID and console ID were found for the DSi NAND image, its \
+                     contents won't be decrypted correctly."
+                );
+            }
+            ([0; 16], 0)
+        });
+
+        Some(emu::DsiStorage {
+            nand_path,
+            sd_card_path,
+            nand_cid,
+            console_id,
+        })
+    }
+
     fn create_renderers(
         window: &window::Window,
         config: &config::Config,
@@ -363,13 +534,18 @@ impl UiState {
         }
 
         let resolution_scale_shift = config!(config, resolution_scale_shift);
+        let soft_3d_thread_count =
+            NonZeroU8::new(config!(config, soft_3d_thread_count)).unwrap_or(NonZeroU8::MIN);
 
         let (renderer_2d, renderer_3d_tx, renderer_2d_data, renderer_3d_data) = {
             match renderer_2d_kind {
                 Renderer2dKind::WgpuLockstepScanlines => {
                     let (tx_3d, rx_3d_2d_data, renderer_3d_data) = match renderer_3d_kind {
                         Renderer3dKind::Soft => {
-                            let (tx_3d, rx_3d) = emu::soft_renderer_3d::init();
+                            let (tx_3d, rx_3d) = dust_soft_3d::threaded::init(
+                                soft_3d_thread_count,
+                                resolution_scale_shift,
+                            );
                             (
                                 Box::new(tx_3d) as Box<dyn engine_3d::RendererTx + Send>,
                                 dust_wgpu_2d::Renderer3dRx::Soft(Box::new(rx_3d)),
@@ -415,7 +591,8 @@ impl UiState {
                 }
 
                 _ => {
-                    let (tx_3d, rx_3d) = emu::soft_renderer_3d::init();
+                    let (tx_3d, rx_3d) =
+                        dust_soft_3d::threaded::init(soft_3d_thread_count, resolution_scale_shift);
 
                     let (renderer_2d, renderer_2d_data) = match renderer_2d_kind {
                         Renderer2dKind::SoftSync => {
@@ -499,7 +676,7 @@ impl UiState {
         let ds_slot = ds_slot_rom.and_then(|mut rom| {
             let game_code = rom.game_code();
 
-            let save_type = self
+            let db_entry = self
                 .game_db
                 .get(|| {
                     config!(config.config, game_db_path)
@@ -544,12 +721,16 @@ impl UiState {
                             rom.len()
                         );
                     }
-                    entry.save_type
+                    (entry.save_type, entry.has_ir)
                 });
             Some(emu::DsSlot {
                 rom,
-                save_type,
-                has_ir: game_code as u8 == b'I',
+                save_type: db_entry
+                    .map(|(save_type, _)| save_type)
+                    .or_else(|| config!(config.config, detected_save_type)),
+                has_ir: db_entry
+                    .and_then(|(_, has_ir)| has_ir)
+                    .unwrap_or(game_code as u8 == b'I'),
             })
         });
 
@@ -590,9 +771,11 @@ impl UiState {
         let (renderer_2d_is_accel, renderer_2d, renderer_3d_tx, renderer_2d_data, renderer_3d_data) =
             Self::create_renderers(window, &config.config, &mut self.fb_texture);
 
+        let model = launch_config.model;
         let launch_data = emu::LaunchData {
             sys_files: launch_config.sys_files,
             ds_slot,
+            gba_slot: Self::load_gba_slot(&config.config),
             #[cfg(feature = "dldi")]
             dldi: ds_slot_rom_path.and_then(|rom_path| {
                 Some(emu::Dldi {
@@ -600,8 +783,9 @@ impl UiState {
                     skip_path: rom_path.to_path_buf(),
                 })
             }),
+            dsi_storage: Self::load_dsi_storage(&config.config, model),
 
-            model: launch_config.model,
+            model,
             skip_firmware: launch_config.skip_firmware,
 
             save_path,
@@ -630,6 +814,16 @@ impl UiState {
 
             rtc_time_offset_seconds: config!(config.config, rtc_time_offset_seconds),
 
+            wifi_link: config!(config.config, wifi_link_enabled).then(|| emu::WifiLink {
+                local_addr: config!(config.config, wifi_link_local_addr),
+                peer_addr: config!(config.config, wifi_link_peer_addr),
+            }),
+
+            rewind: config!(config.config, rewind_enabled).then(|| emu::RewindConfig {
+                interval_frames: config!(config.config, rewind_interval_frames),
+                buffer_size: (config!(config.config, rewind_buffer_size_mib) as usize) << 20,
+            }),
+
             renderer_2d_is_accel,
             renderer_2d,
             renderer_3d_tx,
@@ -648,6 +842,10 @@ impl UiState {
 
         self.emu = Some(EmuState {
             playing,
+            rewinding: false,
+            lid_closed: false,
+            model,
+            movie_state: None,
             title,
             game_loaded,
             save_path_update: None,
@@ -714,6 +912,8 @@ impl UiState {
                     for fb in data.fb.iter_mut() {
                         fb.fill(0);
                     }
+                    data.hi_res_fb.clear();
+                    data.resolution_scale_shift = 0;
                     data.fps = 0.0;
                     #[cfg(feature = "debug-views")]
                     data.debug.clear();
@@ -730,6 +930,8 @@ impl UiState {
 
         self.fb_texture.set_owned(window);
         self.fb_texture.clear(window);
+        self.screen_is_stale = false;
+        self.backlights_off = false;
     }
 
     fn playing(&self) -> bool {
@@ -740,15 +942,17 @@ impl UiState {
 struct FbTexture {
     id: imgui::TextureId,
     is_view: bool,
+    /// The resolution scale shift the owned texture was created for, if not using a view.
+    resolution_scale_shift: u8,
 }
 
 impl FbTexture {
-    fn create_owned(window: &window::Window) -> imgui::TextureId {
+    fn create_owned(window: &window::Window, resolution_scale_shift: u8) -> imgui::TextureId {
         window.imgui_gfx.create_and_add_owned_texture(
             Some("Framebuffer".into()),
             imgui_wgpu::TextureDescriptor {
-                width: SCREEN_WIDTH as u32,
-                height: SCREEN_HEIGHT as u32 * 2,
+                width: (SCREEN_WIDTH as u32) << resolution_scale_shift,
+                height: (SCREEN_HEIGHT as u32 * 2) << resolution_scale_shift,
                 format: wgpu::TextureFormat::Rgba8Unorm,
                 ..Default::default()
             },
@@ -774,8 +978,9 @@ impl FbTexture {
 
     fn new(window: &window::Window) -> Self {
         let result = FbTexture {
-            id: Self::create_owned(window),
+            id: Self::create_owned(window, 0),
             is_view: false,
+            resolution_scale_shift: 0,
         };
         result.clear(window);
         result
@@ -786,10 +991,19 @@ impl FbTexture {
             return;
         }
         window.imgui_gfx.remove_texture(self.id);
-        self.id = Self::create_owned(window);
+        self.id = Self::create_owned(window, self.resolution_scale_shift);
         self.is_view = false;
     }
 
+    fn set_owned_resolution_scale_shift(&mut self, window: &window::Window, value: u8) {
+        if self.is_view || value == self.resolution_scale_shift {
+            return;
+        }
+        window.imgui_gfx.remove_texture(self.id);
+        self.id = Self::create_owned(window, value);
+        self.resolution_scale_shift = value;
+    }
+
     fn set_view(&mut self, window: &window::Window, view: wgpu::TextureView) {
         if self.is_view {
             window
@@ -809,7 +1023,8 @@ impl FbTexture {
     }
 
     fn clear(&self, window: &window::Window) {
-        let mut data = zeroed_box::<[u8; SCREEN_WIDTH * SCREEN_HEIGHT * 8]>();
+        let mut data =
+            vec![0_u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 8) << (self.resolution_scale_shift << 1)];
         for i in (3..data.len()).step_by(4) {
             data[i] = 0xFF;
         }
@@ -820,12 +1035,29 @@ impl FbTexture {
             .set_data(
                 window.gfx_device(),
                 window.gfx_queue(),
-                &*data,
+                &data,
                 imgui_wgpu::TextureSetRange::default(),
             );
     }
 
-    fn set_data(&self, window: &window::Window, data: &Framebuffer) {
+    fn set_data(&mut self, window: &window::Window, frame: &FrameData) {
+        let data = if frame.hi_res_fb.is_empty() {
+            self.set_owned_resolution_scale_shift(window, 0);
+            unsafe {
+                slice::from_raw_parts(
+                    frame.fb.as_ptr() as *const u8,
+                    2 * 4 * SCREEN_WIDTH * SCREEN_HEIGHT,
+                )
+            }
+        } else {
+            self.set_owned_resolution_scale_shift(window, frame.resolution_scale_shift);
+            unsafe {
+                slice::from_raw_parts(
+                    frame.hi_res_fb.as_ptr() as *const u8,
+                    frame.hi_res_fb.len() * 4,
+                )
+            }
+        };
         window
             .imgui_gfx
             .texture(self.id)
@@ -833,12 +1065,7 @@ impl FbTexture {
             .set_data(
                 window.gfx_device(),
                 window.gfx_queue(),
-                unsafe {
-                    slice::from_raw_parts(
-                        data.as_ptr() as *const u8,
-                        2 * 4 * SCREEN_WIDTH * SCREEN_HEIGHT,
-                    )
-                },
+                data,
                 imgui_wgpu::TextureSetRange::default(),
             );
     }
@@ -912,6 +1139,8 @@ pub fn main() {
                 title_menu_bar: TitleMenuBarState::new(&config.config),
 
                 screen_focused: true,
+                screen_is_stale: false,
+                backlights_off: false,
 
                 input: input::State::new(),
 
@@ -995,6 +1224,17 @@ pub fn main() {
                     input::Action::ToggleFullWindowScreen => {
                         toggle_config!(config.config, full_window_screen)
                     }
+                    // Handled below, as it needs to be held
+                    input::Action::Rewind => {}
+                    input::Action::ToggleLid => state.toggle_lid(),
+                }
+            }
+
+            if let Some(emu) = &mut state.emu {
+                let rewinding = state.input.is_hotkey_pressed(input::Action::Rewind);
+                if rewinding != emu.rewinding {
+                    emu.rewinding = rewinding;
+                    emu.send_message(emu::Message::UpdateRewinding(rewinding));
                 }
             }
 
@@ -1126,7 +1366,13 @@ pub fn main() {
                         }
                     }
 
-                    if config_changed!(config.config, renderer_2d_kind | renderer_3d_kind) {
+                    // The software 3D renderer's resolution can only be changed by recreating it
+                    if config_changed!(
+                        config.config,
+                        renderer_2d_kind | renderer_3d_kind | soft_3d_thread_count
+                    ) || (config_changed!(config.config, resolution_scale_shift)
+                        && matches!(&emu.renderer_3d, Renderer3dData::Soft))
+                    {
                         let (
                             renderer_2d_is_accel,
                             renderer_2d,
@@ -1225,6 +1471,10 @@ pub fn main() {
                                 config.config.rtc_time_offset_seconds.clear_updates();
                             }
 
+                            emu::Notification::SaveTypeDetected(save_type) => {
+                                set_config!(config.config, detected_save_type, Some(save_type));
+                            }
+
                             emu::Notification::SavestateCreated(name, savestate) => {
                                 state
                                     .savestate_editor
@@ -1234,6 +1484,10 @@ pub fn main() {
                             emu::Notification::SavestateFailed(name) => {
                                 state.savestate_editor.savestate_failed(name);
                             }
+
+                            emu::Notification::MovieStateUpdated(movie_state) => {
+                                emu.movie_state = movie_state;
+                            }
                         }
                     }
                 }
@@ -1248,8 +1502,10 @@ pub fn main() {
                     .update_from_frame_data(&frame.debug, window);
 
                 if !state.fb_texture.is_view {
-                    state.fb_texture.set_data(window, &frame.fb);
+                    state.fb_texture.set_data(window, frame);
                 }
+                state.screen_is_stale = frame.is_stale;
+                state.backlights_off = frame.backlights_off;
 
                 state.title_menu_bar.update_fps(frame.fps);
             }
@@ -1332,6 +1588,46 @@ pub fn main() {
                         state
                             .savestate_editor
                             .draw(ui, window, &config.config, &state.emu);
+
+                        ui.separator();
+
+                        let lid_closed = state.emu.as_ref().is_some_and(|emu| emu.lid_closed);
+                        if ui
+                            .menu_item_config("Close lid")
+                            .selected(lid_closed)
+                            .enabled(state.emu.is_some())
+                            .build()
+                        {
+                            state.toggle_lid();
+                        }
+
+                        ui.enabled(state.emu.is_some(), || {
+                            ui.menu("\u{f008} Movie", || {
+                                let movie_state =
+                                    state.emu.as_ref().and_then(|emu| emu.movie_state);
+                                if ui.menu_item("Record from power-on...") {
+                                    state.record_movie(true);
+                                }
+                                if ui.menu_item("Record from current state...") {
+                                    state.record_movie(false);
+                                }
+                                if ui.menu_item("Play...") {
+                                    state.play_movie();
+                                }
+                                if ui
+                                    .menu_item_config(match movie_state {
+                                        Some(emu::movie::State::Recording) => "Stop recording",
+                                        _ => "Stop playback",
+                                    })
+                                    .enabled(movie_state.is_some())
+                                    .build()
+                                {
+                                    if let Some(emu) = &state.emu {
+                                        emu.send_message(emu::Message::StopMovie);
+                                    }
+                                }
+                            });
+                        });
                     });
 
                     ui.menu("Config", || {
@@ -1577,6 +1873,15 @@ pub fn main() {
             let window_size = window.inner_size();
             let screen_integer_scale = config!(config.config, screen_integer_scale);
             let screen_rot = (config!(config.config, screen_rot) as f32).to_radians();
+            // Black out the screens while the backlights are off in sleep mode, and dim outdated
+            // output while rewinding, so it's not mistaken for the restored frame
+            let screen_tint = if state.backlights_off {
+                [0.0, 0.0, 0.0, 1.0]
+            } else if state.screen_is_stale {
+                [0.4, 0.4, 0.4, 1.0]
+            } else {
+                [1.0; 4]
+            };
             if config!(config.config, full_window_screen) {
                 let (center, points) = scale_to_fit_rotated(
                     [SCREEN_WIDTH as f32, (2 * SCREEN_HEIGHT) as f32],
@@ -1592,6 +1897,7 @@ pub fn main() {
                         points[2],
                         points[3],
                     )
+                    .col(screen_tint)
                     .build();
                 state.screen_focused =
                     !ui.is_window_focused_with_flags(imgui::WindowFocusedFlags::ANY_WINDOW);
@@ -1651,6 +1957,7 @@ pub fn main() {
                                 abs_points[2],
                                 abs_points[3],
                             )
+                            .col(screen_tint)
                             .build();
                         state.screen_focused = ui.is_window_focused();
                         state.input.set_touchscreen_bounds_from_points(
@@ -122,7 +122,8 @@ use crate::config::TitleBarMode;
 use crate::{
     audio,
     config::{
-        self, saves, GameIconMode, ModelConfig, Renderer2dKind, Renderer3dKind, Setting as _,
+        self, saves, GameIconMode, GbaSlotKind, ModelConfig, Renderer2dKind, Renderer3dKind,
+        Setting as _,
     },
     ui::{
         utils::{
@@ -143,6 +144,15 @@ use std::borrow::Cow;
 #[cfg(feature = "xq-audio")]
 use std::num::NonZeroU32;
 
+macro_rules! string {
+    (nonoverridable $id: ident) => {
+        setting::String::new(
+            |config| config!(config, &$id),
+            |config, value| set_config!(config, $id, value.to_string()),
+        )
+    };
+}
+
 macro_rules! home_path {
     (nonoverridable $id: ident) => {
         setting::HomePath::new(
@@ -490,6 +500,13 @@ struct PathsSettings {
     arm7_bios_path: setting::Overridable<setting::OptHomePath>,
     arm9_bios_path: setting::Overridable<setting::OptHomePath>,
     firmware_path: setting::Overridable<setting::OptHomePath>,
+    gba_bios_path: setting::Overridable<setting::OptHomePath>,
+    gba_slot_rom_path: setting::Overridable<setting::OptHomePath>,
+    dsi_arm7_bios_path: setting::Overridable<setting::OptHomePath>,
+    dsi_nand_path: setting::Overridable<setting::OptHomePath>,
+    dsi_sd_card_path: setting::Overridable<setting::OptHomePath>,
+    dsi_nand_cid: setting::NonOverridable<setting::String>,
+    dsi_console_id: setting::NonOverridable<setting::String>,
 }
 
 impl PathsSettings {
@@ -501,6 +518,13 @@ impl PathsSettings {
             arm7_bios_path: sys_path!(arm7_bios, "$sys_dir_path/biosnds7.bin", false),
             arm9_bios_path: sys_path!(arm9_bios, "$sys_dir_path/biosnds9.bin", false),
             firmware_path: sys_path!(firmware, "$sys_dir_path/firmware.bin", false),
+            gba_bios_path: sys_path!(gba_bios, "$sys_dir_path/gba_bios.bin", false),
+            gba_slot_rom_path: sys_path!(gba_slot_rom, "", false),
+            dsi_arm7_bios_path: sys_path!(dsi_arm7_bios, "$sys_dir_path/biosdsi7.bin", false),
+            dsi_nand_path: sys_path!(dsi_nand, "$sys_dir_path/nand.bin", false),
+            dsi_sd_card_path: sys_path!(dsi_sd_card, "", false),
+            dsi_nand_cid: nonoverridable!(dsi_nand_cid, string),
+            dsi_console_id: nonoverridable!(dsi_console_id, string),
         }
     }
 }
@@ -644,6 +668,9 @@ struct SavesSettings {
     include_save_in_savestates: setting::Overridable<setting::Bool>,
     save_dir_path: setting::NonOverridable<setting::HomePath>,
     savestate_dir_path: setting::NonOverridable<setting::HomePath>,
+    rewind_enabled: setting::Overridable<setting::Bool>,
+    rewind_interval_frames: setting::Overridable<setting::Scalar<u32>>,
+    rewind_buffer_size_mib: setting::Overridable<setting::Scalar<u32>>,
 }
 
 impl SavesSettings {
@@ -654,6 +681,21 @@ impl SavesSettings {
             include_save_in_savestates: overridable!(include_save_in_savestates, bool),
             save_dir_path: nonoverridable!(save_dir_path, home_path),
             savestate_dir_path: nonoverridable!(savestate_dir_path, home_path),
+            rewind_enabled: overridable!(rewind_enabled, bool),
+            rewind_interval_frames: overridable!(
+                rewind_interval_frames,
+                scalar,
+                Some(1),
+                None,
+                "%d frames"
+            ),
+            rewind_buffer_size_mib: overridable!(
+                rewind_buffer_size_mib,
+                scalar,
+                Some(16),
+                None,
+                "%d MiB"
+            ),
         }
     }
 }
@@ -666,11 +708,16 @@ struct EmulationSettings {
     skip_firmware: setting::Overridable<setting::Bool>,
     prefer_hle_bios: setting::Overridable<setting::Bool>,
     model: setting::Overridable<setting::Combo<ModelConfig>>,
+    gba_slot_kind: setting::Overridable<setting::Combo<GbaSlotKind>>,
     ds_slot_rom_in_memory_max_size: setting::Overridable<setting::Scalar<u32>>,
     rtc_time_offset_seconds: setting::Overridable<setting::Scalar<i64>>,
     renderer_2d_kind: setting::Overridable<setting::Combo<Renderer2dKind>>,
     renderer_3d_kind: setting::Overridable<setting::Combo<Renderer3dKind>>,
     resolution_scale_shift: setting::Overridable<setting::StringFormatSlider<u8>>,
+    soft_3d_thread_count: setting::Overridable<setting::StringFormatSlider<u8>>,
+    wifi_link_enabled: setting::NonOverridable<setting::Bool>,
+    wifi_link_local_addr: setting::NonOverridable<setting::SocketAddr>,
+    wifi_link_peer_addr: setting::NonOverridable<setting::SocketAddr>,
 }
 
 impl EmulationSettings {
@@ -716,6 +763,23 @@ impl EmulationSettings {
                 }
                 .into()
             ),
+            gba_slot_kind: overridable!(
+                gba_slot_kind,
+                combo,
+                &[
+                    GbaSlotKind::None,
+                    GbaSlotKind::Cartridge,
+                    GbaSlotKind::MemoryExpansionPak,
+                    GbaSlotKind::RumblePak,
+                ],
+                |gba_slot_kind| match gba_slot_kind {
+                    GbaSlotKind::None => "None",
+                    GbaSlotKind::Cartridge => "GBA cartridge",
+                    GbaSlotKind::MemoryExpansionPak => "Memory Expansion Pak",
+                    GbaSlotKind::RumblePak => "Rumble Pak",
+                }
+                .into()
+            ),
             ds_slot_rom_in_memory_max_size: overridable!(
                 ds_slot_rom_in_memory_max_size,
                 scalar,
@@ -763,6 +827,20 @@ impl EmulationSettings {
                 3,
                 |value| format!("{}x", 1 << value)
             ),
+            soft_3d_thread_count: overridable!(
+                soft_3d_thread_count,
+                string_format_slider,
+                1,
+                8,
+                |value| if value == 1 {
+                    "1 thread".to_owned()
+                } else {
+                    format!("{value} threads")
+                }
+            ),
+            wifi_link_enabled: nonoverridable!(wifi_link_enabled, bool),
+            wifi_link_local_addr: nonoverridable!(wifi_link_local_addr, socket_addr),
+            wifi_link_peer_addr: nonoverridable!(wifi_link_peer_addr, socket_addr),
         }
     }
 }
@@ -1420,6 +1498,8 @@ impl Editor {
                         // imgui_config_path
                         // game_db_path
                         // sys_paths
+                        // dsi_nand_cid
+                        // dsi_console_id
 
                         draw!(
                             "Paths",
@@ -1450,8 +1530,9 @@ impl Editor {
                                             sys_dir_path,
                                             "System dir",
                                             "The location of the directory containing the system \
-                                             files (biosnds7.bin, biosnds9.bin, firmware.bin); \
-                                             can be overridden by the below settings.",
+                                             files (biosnds7.bin, biosnds9.bin, firmware.bin, \
+                                             gba_bios.bin, biosdsi7.bin, nand.bin); can be \
+                                             overridden by the below settings.",
                                         ),
                                         (
                                             arm7_bios_path,
@@ -1473,6 +1554,64 @@ impl Editor {
                                             "The location where the firmware binary is stored; \
                                              will default to $sys_dir_path/firmware.bin if not \
                                              specified.",
+                                        ),
+                                        (
+                                            gba_bios_path,
+                                            "GBA BIOS",
+                                            "The location where the GBA BIOS binary is stored, \
+                                             needed to run GBA software in GBA mode; will default \
+                                             to $sys_dir_path/gba_bios.bin if not specified.",
+                                        )
+                                    ]
+                                ),
+                                (
+                                    "GBA slot",
+                                    [(
+                                        gba_slot_rom_path,
+                                        "GBA ROM",
+                                        "The location of the GBA ROM to insert into the GBA slot \
+                                         when the GBA slot device is set to a cartridge; its save \
+                                         file will be stored in $save_dir_path/gba.",
+                                    )]
+                                ),
+                                (
+                                    "DSi",
+                                    [
+                                        (
+                                            dsi_arm7_bios_path,
+                                            "DSi ARM7 BIOS",
+                                            "The location where the DSi ARM7 BIOS binary is \
+                                             stored, needed to boot the DSi firmware from the NAND \
+                                             image; will default to $sys_dir_path/biosdsi7.bin if \
+                                             not specified.",
+                                        ),
+                                        (
+                                            dsi_nand_path,
+                                            "NAND image",
+                                            "The location of the DSi NAND dump used as the \
+                                             emulated eMMC; will default to \
+                                             $sys_dir_path/nand.bin if not specified. Changes to \
+                                             its contents are written back to the file.",
+                                        ),
+                                        (
+                                            dsi_sd_card_path,
+                                            "SD card image",
+                                            "The location of the image to insert into the DSi's \
+                                             SD card slot, if any.",
+                                        ),
+                                        (
+                                            dsi_nand_cid,
+                                            "eMMC CID",
+                                            "The CID of the eMMC the NAND image was dumped from, \
+                                             as 32 hexadecimal digits; if both this and the \
+                                             console ID are empty, they're read from the footer \
+                                             of no$gba-style NAND dumps.",
+                                        ),
+                                        (
+                                            dsi_console_id,
+                                            "Console ID",
+                                            "The ID of the console the NAND image was dumped \
+                                             from, as 16 hexadecimal digits.",
                                         )
                                     ]
                                 )
@@ -1630,48 +1769,80 @@ with the Imgui title bar mode)
                         // include_save_in_savestates
                         // save_dir_path
                         // save_path_config
+                        // rewind_enabled
+                        // rewind_interval_frames
+                        // rewind_buffer_size_mib
 
                         draw!(
                             "Saves",
                             saves,
-                            [(
-                                "General",
-                                [
-                                    (
-                                        save_interval_ms,
-                                        "Save interval",
-                                        "The interval at which any new save file changes are \
-                                         committed to the filesystem.",
-                                    ),
-                                    (
-                                        reset_on_save_slot_switch,
-                                        "Restart on save slot switch",
-                                        "Whether to restart the emulator when switching save \
-                                         slots (not doing so could lead to save file corruption).",
-                                    ),
-                                    (
-                                        include_save_in_savestates,
-                                        "Include save in savestates",
-                                        "Whether to embed the current version of the save file in \
-                                         savestates (not doing so could lead to save file \
-                                         corruption due to inconsistencies when loading a \
-                                         savestate).",
-                                    ),
-                                    (
-                                        save_dir_path,
-                                        "Save directory path",
-                                        "The location of the directory where save files for games \
-                                         will be stored (unless the specific game is customized \
-                                         not to use the global save directory).",
-                                    ),
-                                    (
-                                        savestate_dir_path,
-                                        "Savestate directory path",
-                                        "The location of the directory where created savestate \
-                                         files for games will be stored.",
-                                    )
-                                ]
-                            )]
+                            [
+                                (
+                                    "General",
+                                    [
+                                        (
+                                            save_interval_ms,
+                                            "Save interval",
+                                            "The interval at which any new save file changes are \
+                                             committed to the filesystem.",
+                                        ),
+                                        (
+                                            reset_on_save_slot_switch,
+                                            "Restart on save slot switch",
+                                            "Whether to restart the emulator when switching save \
+                                             slots (not doing so could lead to save file \
+                                             corruption).",
+                                        ),
+                                        (
+                                            include_save_in_savestates,
+                                            "Include save in savestates",
+                                            "Whether to embed the current version of the save file \
+                                             in savestates (not doing so could lead to save file \
+                                             corruption due to inconsistencies when loading a \
+                                             savestate).",
+                                        ),
+                                        (
+                                            save_dir_path,
+                                            "Save directory path",
+                                            "The location of the directory where save files for \
+                                             games will be stored (unless the specific game is \
+                                             customized not to use the global save directory).",
+                                        ),
+                                        (
+                                            savestate_dir_path,
+                                            "Savestate directory path",
+                                            "The location of the directory where created savestate \
+                                             files for games will be stored.",
+                                        )
+                                    ]
+                                ),
+                                (
+                                    "Rewind",
+                                    [
+                                        (
+                                            rewind_enabled,
+                                            "Enabled",
+                                            "Whether to periodically record the emulator's state, \
+                                             so that it can be stepped back while the rewind \
+                                             hotkey is held.",
+                                        ),
+                                        (
+                                            rewind_interval_frames,
+                                            "Interval",
+                                            "The number of frames between each recorded state; \
+                                             lower values allow for finer rewinding, but take up \
+                                             more memory and slow down emulation.",
+                                        ),
+                                        (
+                                            rewind_buffer_size_mib,
+                                            "Buffer size",
+                                            "The maximum amount of memory to use to store recorded \
+                                             states, after which the oldest ones will be \
+                                             discarded.",
+                                        )
+                                    ]
+                                )
+                            ]
                         );
 
                         add_y_spacing(ui, 8.0);
@@ -1701,11 +1872,16 @@ with the Imgui title bar mode)
                         // skip_firmware
                         // prefer_hle_bios
                         // model
+                        // gba_slot_kind
                         // ds_slot_rom_in_memory_max_size
                         // rtc_time_offset_seconds
                         // renderer_2d_kind
                         // renderer_3d_kind
                         // resolution_scale_shift
+                        // soft_3d_thread_count
+                        // wifi_link_enabled
+                        // wifi_link_local_addr
+                        // wifi_link_peer_addr
 
                         draw!(
                             "Emulation",
@@ -1762,6 +1938,13 @@ The firmware boot sequence will always be skipped if any system files are not pr
                                         "What model of Nintendo DS to emulate (currently only DS \
                                          and DS Lite are functional).",
                                     ),
+                                    (
+                                        gba_slot_kind,
+                                        "GBA slot device",
+                                        "Which device to insert into the GBA slot; a GBA \
+                                         cartridge will only be inserted if a GBA ROM path is \
+                                         specified.",
+                                    ),
                                     (
                                         ds_slot_rom_in_memory_max_size,
                                         "DS slot ROM in-memory max size",
@@ -1796,10 +1979,37 @@ The firmware boot sequence will always be skipped if any system files are not pr
                                     ),
                                     (
                                         resolution_scale_shift,
-                                        "3D HW resolution scale",
-                                        "With the hardware 3D renderer enabled, the scale at \
-                                         which 3D graphics should be rendered compared to the \
-                                         native resolution.",
+                                        "3D resolution scale",
+                                        "The scale at which 3D graphics should be rendered \
+                                         compared to the native resolution. The software 2D \
+                                         renderers upscale 2D graphics to match, and the \
+                                         software 3D renderer is limited to 4x. Full-width \
+                                         display captures are kept at this resolution too.",
+                                    ),
+                                    (
+                                        soft_3d_thread_count,
+                                        "3D SW renderer threads",
+                                        "With the software 3D renderer enabled, how many worker \
+                                         threads to split each frame's scanlines across.",
+                                    ),
+                                    (
+                                        wifi_link_enabled,
+                                        "Local wireless link",
+                                        "Whether to link the emulated Wi-Fi hardware to another \
+                                         emulator instance over UDP, for local multiplayer and DS \
+                                         Download Play. Changes are applied when the emulator is \
+                                         restarted.",
+                                    ),
+                                    (
+                                        wifi_link_local_addr,
+                                        "Local wireless address",
+                                        "The address to receive Wi-Fi frames from the other \
+                                         instance at.",
+                                    ),
+                                    (
+                                        wifi_link_peer_addr,
+                                        "Local wireless peer address",
+                                        "The address the other instance receives Wi-Fi frames at.",
                                     )
                                 ]
                             )]
@@ -94,6 +94,8 @@ static ACTIONS: &[(Action, &str)] = &[
     (Action::ToggleFramerateLimit, "Toggle framerate limit"),
     (Action::ToggleSyncToAudio, "Toggle sync to audio"),
     (Action::ToggleFullWindowScreen, "Toggle full-window screen"),
+    (Action::Rewind, "Rewind (hold)"),
+    (Action::ToggleLid, "Close/open lid"),
 ];
 
 type InputMap = config::Overridable<Map, GlobalMap, Map, ()>;
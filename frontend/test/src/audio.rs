@@ -0,0 +1,33 @@
+use crate::hash::Hash;
+use dust_core::audio::OutputSample;
+use std::{cell::Cell, rc::Rc};
+
+pub type Handle = Rc<Cell<Hash>>;
+
+/// Audio backend hashing the emulator's output sample stream instead of playing it.
+pub struct Backend {
+    hash: Handle,
+}
+
+impl Backend {
+    pub fn new() -> (Self, Handle) {
+        let hash = Rc::new(Cell::new(Hash::new()));
+        (
+            Backend {
+                hash: Rc::clone(&hash),
+            },
+            hash,
+        )
+    }
+}
+
+impl dust_core::audio::Backend for Backend {
+    fn handle_sample_chunk(&mut self, samples: &mut Vec<[OutputSample; 2]>) {
+        let mut hash = self.hash.get();
+        for [l, r] in samples.drain(..) {
+            hash.update(&l.to_le_bytes());
+            hash.update(&r.to_le_bytes());
+        }
+        self.hash.set(hash);
+    }
+}
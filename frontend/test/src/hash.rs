@@ -0,0 +1,31 @@
+use std::fmt;
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` since reference hashes need to stay stable across
+/// Rust versions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Hash(u64);
+
+impl Hash {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+    pub const fn new() -> Self {
+        Hash(Self::OFFSET_BASIS)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        u64::from_str_radix(value, 16).ok().map(Hash)
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
@@ -0,0 +1,350 @@
+//! Differential tests comparing the HLE BIOS against real BIOS dumps.
+//!
+//! Each case sets up some registers and memory contents, then calls an SWI from ARM code running
+//! on one of the CPUs, which falls into an idle loop once the SWI returns. The case is run both
+//! with the HLE BIOS and with the provided dumps, and the selected registers and memory ranges are
+//! compared between the two.
+
+use crate::{path_value, stub_rom, Bios, CpuEngine, IDLE_LOOP};
+use dust_core::{
+    cpu::{
+        arm7::{self, Arm7},
+        arm9::{self, Arm9},
+        bus::DebugCpuAccess,
+        psr::Psr,
+    },
+    emu::{Emu, RunOutput},
+    utils::{zeroed_box, Bytes},
+    Model,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+const USAGE: &str = "\
+Usage: dust-test hle-diff --arm7-bios <path> --arm9-bios <path> [--table <path>] [filter...]
+
+Options:
+    --arm7-bios <path>  ARM7 BIOS dump to compare the HLE BIOS against
+    --arm9-bios <path>  ARM9 BIOS dump to compare the HLE BIOS against
+    --table <path>      Table to use (defaults to `hle_diff.json` in this crate's directory)";
+
+const ARM7_CODE_ADDR: u32 = 0x0380_E000;
+const ARM9_CODE_ADDR: u32 = 0x0200_1000;
+
+// System mode, with IRQs and FIQs disabled
+const CPSR: u32 = 0xDF;
+
+// Number of frames to run for, which needs to be enough for any SWI to return
+const FRAMES: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Cpu {
+    Arm7,
+    Arm9,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MemContents {
+    /// Address to place the data at, in hexadecimal.
+    addr: String,
+    /// Data to place, as a hexadecimal byte string.
+    data: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MemRange {
+    /// Start address, in hexadecimal.
+    addr: String,
+    /// Length in bytes, in hexadecimal.
+    len: String,
+}
+
+fn default_check_regs() -> Vec<String> {
+    vec!["r0".to_string()]
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Case {
+    name: String,
+    cpu: Cpu,
+    /// SWI number, in hexadecimal.
+    swi: String,
+    /// Initial values for r0-r12, in hexadecimal.
+    #[serde(default)]
+    regs: BTreeMap<String, String>,
+    #[serde(default)]
+    mem: Vec<MemContents>,
+    /// Registers to compare after the SWI returns; defaults to r0.
+    #[serde(default = "default_check_regs")]
+    check_regs: Vec<String>,
+    /// Memory ranges to compare after the SWI returns.
+    #[serde(default)]
+    check_mem: Vec<MemRange>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Table {
+    cases: Vec<Case>,
+}
+
+fn parse_hex(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("Invalid hexadecimal value: {value}"))
+}
+
+fn parse_hex_bytes(value: &str) -> Result<Vec<u8>, String> {
+    let value = value.replace(' ', "");
+    if value.len() % 2 != 0 {
+        return Err(format!("Invalid hexadecimal byte string: {value}"));
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&value[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hexadecimal byte string: {value}"))
+        })
+        .collect()
+}
+
+fn parse_reg(reg: &str, max: usize) -> Result<usize, String> {
+    reg.strip_prefix('r')
+        .and_then(|i| i.parse::<usize>().ok())
+        .filter(|i| *i <= max)
+        .ok_or_else(|| format!("Invalid register: {reg}"))
+}
+
+/// The state of the CPU and memory after running a case.
+#[derive(PartialEq, Eq)]
+struct Results {
+    regs: Vec<u32>,
+    mem: Vec<Vec<u8>>,
+}
+
+macro_rules! run_swi {
+    ($emu: expr, $core: ident, $core_ty: ident, $case: expr, $code_addr: expr) => {{
+        let emu = $emu;
+        let case = $case;
+
+        for mem in &case.mem {
+            let addr = parse_hex(&mem.addr)?;
+            for (i, byte) in parse_hex_bytes(&mem.data)?.into_iter().enumerate() {
+                $core::bus::write_8::<DebugCpuAccess, _>(emu, addr + i as u32, byte);
+            }
+        }
+
+        let swi = parse_hex(&case.swi)?;
+        $core::bus::write_32::<DebugCpuAccess, _>(
+            emu,
+            $code_addr,
+            0xEF00_0000 | (swi & 0xFF) << 16,
+        );
+        $core::bus::write_32::<DebugCpuAccess, _>(emu, $code_addr + 4, IDLE_LOOP);
+
+        let mut regs = emu.$core.regs();
+        for (reg, value) in &case.regs {
+            regs.gprs[parse_reg(reg, 12)?] = parse_hex(value)?;
+        }
+        $core_ty::set_regs(emu, &regs);
+        $core_ty::set_cpsr(emu, Psr::from_raw(CPSR));
+        $core_ty::jump(emu, $code_addr);
+
+        for frame in 0..FRAMES {
+            match emu.run() {
+                RunOutput::FrameFinished => {}
+                RunOutput::Shutdown => return Err(format!("emulator shut down at frame {frame}")),
+                RunOutput::MissingGbaBios => {
+                    return Err(format!(
+                        "GBA mode requested without a GBA BIOS at frame {frame}"
+                    ))
+                }
+                #[cfg(feature = "timing")]
+                RunOutput::StoppedByDebugHook | RunOutput::CyclesOver(_) => unreachable!(),
+            }
+        }
+        if emu.$core.r15().wrapping_sub(8) != $code_addr + 4 {
+            return Err(format!(
+                "SWI didn't return (PC = {:#010X})",
+                emu.$core.r15().wrapping_sub(8)
+            ));
+        }
+
+        let regs = emu.$core.regs();
+        let mut results = Results {
+            regs: Vec::new(),
+            mem: Vec::new(),
+        };
+        for reg in &case.check_regs {
+            results.regs.push(regs.gprs[parse_reg(reg, 14)?]);
+        }
+        for range in &case.check_mem {
+            let addr = parse_hex(&range.addr)?;
+            let len = parse_hex(&range.len)?;
+            results.mem.push(
+                (0..len)
+                    .map(|i| $core::bus::read_8::<DebugCpuAccess, _>(emu, addr + i))
+                    .collect(),
+            );
+        }
+        results
+    }};
+}
+
+fn run_case(case: &Case, bios: Option<&Bios>) -> Result<Results, String> {
+    let (mut emu, _) = crate::build_emu(Model::Lite, &stub_rom(), bios)?;
+    let emu: &mut Emu<CpuEngine> = &mut emu;
+
+    Ok(match case.cpu {
+        Cpu::Arm7 => run_swi!(emu, arm7, Arm7, case, ARM7_CODE_ADDR),
+        Cpu::Arm9 => run_swi!(emu, arm9, Arm9, case, ARM9_CODE_ADDR),
+    })
+}
+
+fn compare(case: &Case, hle: &Results, lle: &Results) -> Vec<String> {
+    let mut errors = Vec::new();
+    for ((reg, hle), lle) in case.check_regs.iter().zip(&hle.regs).zip(&lle.regs) {
+        if hle != lle {
+            errors.push(format!("{reg}: HLE {hle:#010X}, LLE {lle:#010X}"));
+        }
+    }
+    for ((range, hle), lle) in case.check_mem.iter().zip(&hle.mem).zip(&lle.mem) {
+        if let Some(i) = hle.iter().zip(lle).position(|(hle, lle)| hle != lle) {
+            errors.push(format!(
+                "memory at {} + {i:#X}: HLE {:#04X}, LLE {:#04X}",
+                range.addr, hle[i], lle[i]
+            ));
+        }
+    }
+    errors
+}
+
+fn read_bios<const LEN: usize>(path: &Path) -> Result<Box<Bytes<LEN>>, String> {
+    let contents =
+        fs::read(path).map_err(|err| format!("Couldn't read {}: {err}", path.display()))?;
+    if contents.len() != LEN {
+        return Err(format!(
+            "Invalid BIOS size for {}: expected {LEN} bytes, got {}",
+            path.display(),
+            contents.len()
+        ));
+    }
+    let mut bios = zeroed_box::<Bytes<LEN>>();
+    bios.copy_from_slice(&contents);
+    Ok(bios)
+}
+
+struct Args {
+    table_path: PathBuf,
+    bios: Bios,
+    filters: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = OsString>) -> Result<Args, String> {
+    let mut table_path = None;
+    let mut arm7_bios_path = None;
+    let mut arm9_bios_path = None;
+    let mut filters = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--arm7-bios") => arm7_bios_path = Some(path_value(&mut args, "--arm7-bios")?),
+            Some("--arm9-bios") => arm9_bios_path = Some(path_value(&mut args, "--arm9-bios")?),
+            Some("--table") => table_path = Some(path_value(&mut args, "--table")?),
+            Some("-h" | "--help") => return Err(USAGE.to_string()),
+            Some(filter) if !filter.starts_with('-') => filters.push(filter.to_string()),
+            _ => {
+                return Err(format!(
+                    "Unknown argument: {}\n\n{USAGE}",
+                    arg.to_string_lossy()
+                ))
+            }
+        }
+    }
+
+    let (Some(arm7_bios_path), Some(arm9_bios_path)) = (arm7_bios_path, arm9_bios_path) else {
+        return Err(format!("Both BIOS dumps need to be specified\n\n{USAGE}"));
+    };
+
+    Ok(Args {
+        table_path: table_path
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("hle_diff.json")),
+        bios: Bios {
+            arm7: read_bios::<{ arm7::BIOS_SIZE }>(&arm7_bios_path)?,
+            arm9: read_bios::<{ arm9::BIOS_SIZE }>(&arm9_bios_path)?,
+        },
+        filters,
+    })
+}
+
+pub fn main(args: impl Iterator<Item = OsString>) -> ExitCode {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let table = match fs::read(&args.table_path)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            serde_json::from_slice::<Table>(&contents).map_err(|err| err.to_string())
+        }) {
+        Ok(table) => table,
+        Err(err) => {
+            eprintln!(
+                "Couldn't read HLE BIOS test table at {}: {err}",
+                args.table_path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (mut passed, mut failed) = (0, 0);
+    for case in &table.cases {
+        if !args.filters.is_empty() && !args.filters.iter().any(|filter| case.name.contains(filter))
+        {
+            continue;
+        }
+        let results = run_case(case, None)
+            .map_err(|err| format!("HLE: {err}"))
+            .and_then(|hle| {
+                run_case(case, Some(&args.bios))
+                    .map(|lle| (hle, lle))
+                    .map_err(|err| format!("LLE: {err}"))
+            });
+        match results {
+            Ok((hle, lle)) if hle == lle => {
+                println!("{}: ok", case.name);
+                passed += 1;
+            }
+            Ok((hle, lle)) => {
+                for error in compare(case, &hle, &lle) {
+                    println!("{}: FAILED ({error})", case.name);
+                }
+                failed += 1;
+            }
+            Err(err) => {
+                println!("{}: FAILED ({err})", case.name);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{passed} passed, {failed} failed");
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,480 @@
+#![feature(new_uninit)]
+#![warn(clippy::all)]
+
+mod audio;
+mod hash;
+mod hle_diff;
+mod manifest;
+mod renderer_3d;
+#[cfg(feature = "timing")]
+mod timing;
+
+use dust_core::{
+    cpu::{arm7, arm9, interpreter},
+    ds_slot,
+    emu::{self, input::Keys, Emu, RunOutput},
+    flash::Flash,
+    gpu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    movie::{self, Movie},
+    rtc,
+    spi::firmware,
+    utils::{BoxedByteSlice, Bytes},
+    Model, SaveContents,
+};
+use hash::Hash;
+use manifest::{Manifest, Test};
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The CPU engine tests are run with.
+#[cfg(feature = "interp-cache")]
+type CpuEngine = interpreter::CachedInterpreter;
+#[cfg(not(feature = "interp-cache"))]
+type CpuEngine = interpreter::Interpreter;
+
+struct Args {
+    manifest_path: PathBuf,
+    rom_dir: PathBuf,
+    dump_dir: Option<PathBuf>,
+    update: bool,
+    filters: Vec<String>,
+}
+
+const USAGE: &str = "\
+Usage: dust-test [--update] [--roms <dir>] [--dump <dir>] [--manifest <path>] [filter...]
+       dust-test timing [--update] [--table <path>] [filter...]
+       dust-test hle-diff --arm7-bios <path> --arm9-bios <path> [--table <path>] [filter...]
+
+Runs all tests in the manifest whose name contains one of the filters (or all of them if none are
+specified), comparing the final framebuffer and the audio output against the recorded hashes.
+Tests whose ROM is missing or that have no recorded reference hashes fail.
+
+Options:
+    --update            Record the current results as the new references
+    --roms <dir>        Directory containing the test ROMs (defaults to $DUST_TEST_ROMS, or to
+                        `roms` next to the manifest)
+    --dump <dir>        Save the framebuffers of failing tests as PPM images inside <dir>
+    --manifest <path>   Manifest to use (defaults to the one in this crate's directory)
+
+The `timing` subcommand runs the instruction timing tests instead, and is only available when built
+with the `timing` feature.
+
+The `hle-diff` subcommand runs SWIs under both the HLE BIOS and the specified BIOS dumps, comparing
+the resulting registers and memory contents.";
+
+fn parse_args(mut args: impl Iterator<Item = OsString>) -> Result<Args, String> {
+    let mut manifest_path = None;
+    let mut rom_dir = None;
+    let mut dump_dir = None;
+    let mut update = false;
+    let mut filters = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--update") => update = true,
+            Some("--roms") => rom_dir = Some(path_value(&mut args, "--roms")?),
+            Some("--dump") => dump_dir = Some(path_value(&mut args, "--dump")?),
+            Some("--manifest") => manifest_path = Some(path_value(&mut args, "--manifest")?),
+            Some("-h" | "--help") => return Err(USAGE.to_string()),
+            Some(filter) if !filter.starts_with('-') => filters.push(filter.to_string()),
+            _ => {
+                return Err(format!(
+                    "Unknown argument: {}\n\n{USAGE}",
+                    arg.to_string_lossy()
+                ))
+            }
+        }
+    }
+
+    let manifest_path = manifest_path
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("manifest.json"));
+    let rom_dir = rom_dir
+        .or_else(|| env::var_os("DUST_TEST_ROMS").map(PathBuf::from))
+        .unwrap_or_else(|| manifest_path.with_file_name("roms"));
+
+    Ok(Args {
+        manifest_path,
+        rom_dir,
+        dump_dir,
+        update,
+        filters,
+    })
+}
+
+fn path_value(args: &mut impl Iterator<Item = OsString>, name: &str) -> Result<PathBuf, String> {
+    args.next()
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("Missing value for {name}"))
+}
+
+struct Results {
+    framebuffer: Box<Framebuffer>,
+    framebuffer_hash: Hash,
+    audio_hash: Hash,
+}
+
+fn parse_keys(names: &[String]) -> Result<Keys, String> {
+    names.iter().try_fold(Keys::empty(), |keys, name| {
+        Keys::from_name(&name.to_ascii_uppercase())
+            .map(|key| keys | key)
+            .ok_or_else(|| format!("Unknown key: {name}"))
+    })
+}
+
+// `b .`, used to keep both CPUs busy outside of the code being tested
+const IDLE_LOOP: u32 = 0xEAFF_FFFE;
+const ARM7_IDLE_ADDR: u32 = 0x0380_F000;
+const ARM9_IDLE_ADDR: u32 = 0x0200_0000;
+
+/// Builds a ROM whose ARM7 and ARM9 binaries only consist of an idle loop.
+fn stub_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x1000];
+    rom[0x200..0x204].copy_from_slice(&IDLE_LOOP.to_le_bytes());
+    for (offset, value) in [
+        (0x20, 0x200),
+        (0x24, ARM9_IDLE_ADDR),
+        (0x28, ARM9_IDLE_ADDR),
+        (0x2C, 4),
+        (0x30, 0x200),
+        (0x34, ARM7_IDLE_ADDR),
+        (0x38, ARM7_IDLE_ADDR),
+        (0x3C, 4),
+    ] {
+        rom[offset..offset + 4].copy_from_slice(&u32::to_le_bytes(value));
+    }
+    rom
+}
+
+/// Dumps of both CPUs' BIOSes, used instead of the HLE BIOS when specified.
+struct Bios {
+    arm7: Box<Bytes<{ arm7::BIOS_SIZE }>>,
+    arm9: Box<Bytes<{ arm9::BIOS_SIZE }>>,
+}
+
+fn build_emu(
+    model: Model,
+    rom_contents: &[u8],
+    bios: Option<&Bios>,
+) -> Result<(Emu<CpuEngine>, audio::Handle), String> {
+    #[cfg(feature = "log")]
+    let logger = slog::Logger::root(slog::Discard, slog::o!());
+
+    let mut rom = BoxedByteSlice::new_zeroed(rom_contents.len().next_power_of_two());
+    rom[..rom_contents.len()].copy_from_slice(rom_contents);
+    if !ds_slot::rom::is_valid_size(rom.len() as u64, model) {
+        return Err("Invalid ROM size".to_string());
+    }
+
+    let (audio_backend, audio_handle) = audio::Backend::new();
+    let (tx_3d, rx_3d) = renderer_3d::init();
+
+    let mut emu_builder = emu::Builder::new(
+        Flash::new(
+            SaveContents::Existing(firmware::default(model)),
+            firmware::id_for_model(model),
+            #[cfg(feature = "log")]
+            logger.new(slog::o!("fw" => "")),
+        )
+        .expect("couldn't build firmware"),
+        Some(Box::new(rom)),
+        ds_slot::spi::Empty::new(
+            #[cfg(feature = "log")]
+            logger.new(slog::o!("ds_spi" => "empty")),
+        )
+        .into(),
+        Box::new(audio_backend),
+        None,
+        Box::new(rtc::FixedBackend::default()),
+        Box::new(dust_soft_2d::sync::Renderer::new(Box::new(rx_3d))),
+        Box::new(tx_3d),
+        None,
+        #[cfg(feature = "log")]
+        logger,
+    );
+
+    emu_builder.model = model;
+    emu_builder.direct_boot = true;
+    if let Some(bios) = bios {
+        emu_builder.arm7_bios = Some(bios.arm7.clone());
+        emu_builder.arm9_bios = Some(bios.arm9.clone());
+    }
+
+    let emu = emu_builder.build(CpuEngine {}).map_err(|err| match err {
+        emu::BuildError::MissingRom => "Missing DS slot ROM".to_string(),
+        emu::BuildError::MissingSysFiles => "Missing emulator system files".to_string(),
+        emu::BuildError::RomCreation(err) => match err {
+            ds_slot::rom::normal::CreationError::InvalidSize => {
+                "Invalid DS slot ROM file size".to_string()
+            }
+        },
+        emu::BuildError::RomNeedsDecryptionButNoBiosProvided => {
+            "ROM needs decryption but no BIOS provided".to_string()
+        }
+        emu::BuildError::DsiStage2(err) => {
+            format!("Couldn't load the DSi stage 2 bootloader: {err:?}")
+        }
+    })?;
+
+    Ok((emu, audio_handle))
+}
+
+fn run_test(test: &Test, rom_contents: &[u8], movie: Option<Movie>) -> Result<Results, String> {
+    let mut inputs = test
+        .inputs
+        .iter()
+        .map(|event| {
+            Ok((
+                event.frame,
+                parse_keys(&event.press)?,
+                parse_keys(&event.release)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    inputs.sort_by_key(|(frame, ..)| *frame);
+    let mut inputs = inputs.into_iter().peekable();
+
+    let (mut emu, audio_hash) = build_emu(test.model, rom_contents, None)?;
+
+    let mut player = movie.map(movie::Player::new);
+    if let Some(player) = &mut player {
+        player.setup(&mut emu).map_err(|err| match err {
+            movie::SetupError::ModelMismatch { movie, .. } => {
+                format!("Movie was recorded on a different model ({movie:?})")
+            }
+            movie::SetupError::SavestateCreation => "Couldn't create savestate".to_string(),
+            movie::SetupError::SavestateLoading(err) => {
+                format!("Couldn't load the movie's savestate: {err}")
+            }
+        })?;
+    }
+
+    for frame in 0..test.frames {
+        if let Some(player) = &mut player {
+            player.apply_frame(&mut emu);
+        }
+        while let Some((_, pressed, released)) = inputs.next_if(|(f, ..)| *f == frame) {
+            emu.press_keys(pressed);
+            emu.release_keys(released);
+        }
+        match emu.run() {
+            RunOutput::FrameFinished => {}
+            RunOutput::Shutdown => return Err(format!("Emulator shut down at frame {frame}")),
+            RunOutput::MissingGbaBios => {
+                return Err(format!(
+                    "GBA mode requested without a GBA BIOS at frame {frame}"
+                ))
+            }
+            #[cfg(feature = "timing")]
+            RunOutput::StoppedByDebugHook | RunOutput::CyclesOver(_) => unreachable!(),
+        }
+    }
+
+    let framebuffer = Box::new(*emu.gpu.renderer_2d().framebuffer());
+    let mut framebuffer_hash = Hash::new();
+    for pixel in framebuffer.iter().flatten() {
+        framebuffer_hash.update(&pixel.to_le_bytes());
+    }
+
+    Ok(Results {
+        framebuffer,
+        framebuffer_hash,
+        audio_hash: audio_hash.get(),
+    })
+}
+
+fn dump_framebuffer(path: &Path, framebuffer: &Framebuffer) -> io::Result<()> {
+    // Both screens are stacked vertically, with the top one first
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT * 2)?;
+    for pixel in framebuffer.iter().flatten() {
+        let [r, g, b, _] = pixel.to_le_bytes();
+        file.write_all(&[r, g, b])?;
+    }
+    file.flush()
+}
+
+enum Outcome {
+    Passed,
+    Recorded,
+    Failed,
+}
+
+fn check_hash(
+    name: &str,
+    reference: &mut Option<String>,
+    hash: Hash,
+    update: bool,
+) -> Result<bool, String> {
+    if update {
+        let changed = reference.as_deref() != Some(&hash.to_string());
+        *reference = Some(hash.to_string());
+        return Ok(changed);
+    }
+    match reference.as_deref() {
+        None => Err(format!("no reference {name} hash recorded (got {hash})")),
+        Some(reference) => match Hash::parse(reference) {
+            None => Err(format!("invalid reference {name} hash: {reference}")),
+            Some(reference) if reference != hash => Err(format!(
+                "{name} hash mismatch: expected {reference}, got {hash}"
+            )),
+            Some(_) => Ok(false),
+        },
+    }
+}
+
+fn handle_test(test: &mut Test, args: &Args) -> Outcome {
+    let rom_contents = match fs::read(args.rom_dir.join(&test.rom)) {
+        Ok(rom_contents) => rom_contents,
+        // A missing ROM fails the test instead of skipping it, so that an incomplete ROM directory
+        // can't make the suite pass without running anything
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            println!(
+                "{}: FAILED (ROM not found at {})",
+                test.name,
+                args.rom_dir.join(&test.rom).display()
+            );
+            return Outcome::Failed;
+        }
+        Err(err) => {
+            println!("{}: FAILED (couldn't read ROM: {err})", test.name);
+            return Outcome::Failed;
+        }
+    };
+
+    let movie = match &test.movie {
+        Some(path) => match fs::read(args.rom_dir.join(path)) {
+            Ok(contents) => match Movie::from_bytes(&contents) {
+                Ok(movie) => Some(movie),
+                Err(err) => {
+                    println!("{}: FAILED (invalid movie: {err:?})", test.name);
+                    return Outcome::Failed;
+                }
+            },
+            Err(err) => {
+                println!("{}: FAILED (couldn't read movie: {err})", test.name);
+                return Outcome::Failed;
+            }
+        },
+        None => None,
+    };
+
+    let results = match run_test(test, &rom_contents, movie) {
+        Ok(results) => results,
+        Err(err) => {
+            println!("{}: FAILED ({err})", test.name);
+            return Outcome::Failed;
+        }
+    };
+
+    let checks = [
+        check_hash(
+            "framebuffer",
+            &mut test.framebuffer,
+            results.framebuffer_hash,
+            args.update,
+        ),
+        check_hash("audio", &mut test.audio, results.audio_hash, args.update),
+    ];
+
+    let errors = checks
+        .iter()
+        .filter_map(|check| check.as_ref().err())
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        if checks.iter().any(|check| check == &Ok(true)) {
+            println!("{}: recorded", test.name);
+            return Outcome::Recorded;
+        }
+        println!("{}: ok", test.name);
+        return Outcome::Passed;
+    }
+
+    for error in errors {
+        println!("{}: FAILED ({error})", test.name);
+    }
+    if let Some(dump_dir) = &args.dump_dir {
+        let path = dump_dir.join(format!("{}.ppm", test.name));
+        if let Err(err) =
+            fs::create_dir_all(dump_dir).and_then(|_| dump_framebuffer(&path, &results.framebuffer))
+        {
+            eprintln!("Couldn't save framebuffer to {}: {err}", path.display());
+        }
+    }
+    Outcome::Failed
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args_os().skip(1).peekable();
+    if args.next_if(|arg| arg == "timing").is_some() {
+        #[cfg(feature = "timing")]
+        return timing::main(args);
+        #[cfg(not(feature = "timing"))]
+        {
+            eprintln!("dust-test was built without the `timing` feature");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if args.next_if(|arg| arg == "hle-diff").is_some() {
+        return hle_diff::main(args);
+    }
+
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut manifest = match Manifest::read(&args.manifest_path) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!(
+                "Couldn't read manifest at {}: {err:?}",
+                args.manifest_path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (mut passed, mut recorded, mut failed) = (0, 0, 0);
+    for test in &mut manifest.tests {
+        if !args.filters.is_empty() && !args.filters.iter().any(|filter| test.name.contains(filter))
+        {
+            continue;
+        }
+        match handle_test(test, &args) {
+            Outcome::Passed => passed += 1,
+            Outcome::Recorded => recorded += 1,
+            Outcome::Failed => failed += 1,
+        }
+    }
+
+    if args.update && recorded != 0 {
+        if let Err(err) = manifest.write(&args.manifest_path) {
+            eprintln!(
+                "Couldn't write manifest at {}: {err:?}",
+                args.manifest_path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!(
+        "\n{passed} passed, {recorded} recorded, {failed} failed (ROMs searched in {})",
+        args.rom_dir.display()
+    );
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
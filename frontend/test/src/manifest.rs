@@ -0,0 +1,70 @@
+use dust_core::Model;
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InputEvent {
+    pub frame: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub press: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub release: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Test {
+    pub name: String,
+    /// Path of the ROM, relative to the ROM directory passed to the runner.
+    pub rom: String,
+    #[serde(default)]
+    pub model: Model,
+    pub frames: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<InputEvent>,
+    /// Path of an input movie to play back, relative to the ROM directory. Its inputs are applied
+    /// before the ones in `inputs`, and only for as many frames as it contains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub movie: Option<String>,
+    /// Expected hash of the final framebuffer, or `None` if no reference has been recorded yet.
+    pub framebuffer: Option<String>,
+    /// Expected hash of all audio samples output during the test.
+    pub audio: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub tests: Vec<Test>,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut contents = serde_json::to_string_pretty(self)?;
+        contents.push('\n');
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use dust_core::{
+    gpu::{
+        engine_3d::{
+            Polygon, RendererTx, RenderingState as CoreRenderingState, ScreenVertex, SoftRendererRx,
+        },
+        Scanline, SCREEN_HEIGHT,
+    },
+    utils::Bytes,
+};
+use dust_soft_3d::{Renderer, RenderingData};
+use std::{cell::RefCell, rc::Rc};
+
+type ScanlineBuffer = [Scanline<u32>; SCREEN_HEIGHT];
+
+// Rendering happens synchronously inside `start_rendering`, so that the output doesn't depend on
+// thread scheduling
+
+pub struct Tx {
+    renderer: Renderer,
+    rendering_data: Box<RenderingData>,
+    scanline_buffer: Rc<RefCell<Box<ScanlineBuffer>>>,
+}
+
+impl RendererTx for Tx {
+    fn set_capture_enabled(&mut self, _capture_enabled: bool) {}
+
+    fn swap_buffers(
+        &mut self,
+        vert_ram: &[ScreenVertex],
+        poly_ram: &[Polygon],
+        state: &CoreRenderingState,
+    ) {
+        self.rendering_data.prepare(vert_ram, poly_ram, state);
+    }
+
+    fn repeat_last_frame(&mut self, state: &CoreRenderingState) {
+        self.rendering_data.repeat_last_frame(state);
+    }
+
+    fn start_rendering(
+        &mut self,
+        texture: &Bytes<0x8_0000>,
+        tex_pal: &Bytes<0x1_8000>,
+        state: &CoreRenderingState,
+    ) {
+        self.rendering_data.copy_vram(texture, tex_pal, state);
+
+        let rendering_data = &*self.rendering_data;
+        let scanline_buffer = &mut **self.scanline_buffer.borrow_mut();
+        self.renderer.start_frame(rendering_data);
+        self.renderer.render_line(0, rendering_data);
+        for y in 0..SCREEN_HEIGHT as u16 {
+            if y < SCREEN_HEIGHT as u16 - 1 {
+                self.renderer.render_line(y + 1, rendering_data);
+            }
+            self.renderer
+                .postprocess_line(y, &mut scanline_buffer[y as usize].0, rendering_data);
+        }
+    }
+
+    fn skip_rendering(&mut self) {}
+}
+
+pub struct Rx {
+    next_scanline: u8,
+    shared_buffer: Rc<RefCell<Box<ScanlineBuffer>>>,
+    scanline_buffer: Box<ScanlineBuffer>,
+}
+
+impl Rx {
+    fn latch_frame(&mut self) {
+        if self.next_scanline == 0 {
+            self.scanline_buffer
+                .copy_from_slice(&**self.shared_buffer.borrow());
+        }
+    }
+}
+
+impl SoftRendererRx for Rx {
+    fn start_frame(&mut self) {
+        self.next_scanline = 0;
+    }
+
+    fn read_scanline(&mut self) -> &Scanline<u32> {
+        self.latch_frame();
+        let result = &self.scanline_buffer[self.next_scanline as usize];
+        self.next_scanline += 1;
+        result
+    }
+
+    fn skip_scanline(&mut self) {
+        self.latch_frame();
+        self.next_scanline += 1;
+    }
+
+    fn last_hi_res_scanline(&self) -> &[u32] {
+        &self.scanline_buffer[self.next_scanline.saturating_sub(1) as usize].0
+    }
+}
+
+pub fn init() -> (Tx, Rx) {
+    let shared_buffer = Rc::new(RefCell::new(unsafe { Box::new_zeroed().assume_init() }));
+    (
+        Tx {
+            renderer: Renderer::new(),
+            rendering_data: unsafe { Box::new_zeroed().assume_init() },
+            scanline_buffer: Rc::clone(&shared_buffer),
+        },
+        Rx {
+            next_scanline: 0,
+            shared_buffer,
+            scanline_buffer: unsafe { Box::new_zeroed().assume_init() },
+        },
+    )
+}
@@ -0,0 +1,331 @@
+//! Table-driven instruction timing tests.
+//!
+//! Each case places a short sequence of ARM or Thumb instructions at a given address on one of the
+//! CPUs, sets up its registers, and measures the number of cycles elapsed in the CPU's schedule
+//! between reaching the first instruction and reaching the end address, using breakpoints to stop
+//! the emulator at both points. Since the timing model changes with the interpreter features the
+//! runner was built with, expected cycle counts are stored separately for each feature combination.
+//!
+//! The ARM9 overlaps data accesses with the following code fetch, so cases measuring them end with
+//! an extra instruction that doesn't depend on the loaded registers.
+
+use crate::{path_value, stub_rom, CpuEngine};
+use dust_core::{
+    cpu::{
+        arm7::{self, Arm7},
+        arm9::{self, Arm9},
+        bus::DebugCpuAccess,
+        debug::BreakpointHook,
+        psr::Psr,
+        schedule::Schedule,
+    },
+    emu::{Emu, RunOutput},
+    utils::schedule::RawTimestamp,
+    Model,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+const USAGE: &str = "\
+Usage: dust-test timing [--update] [--table <path>] [filter...]
+
+Options:
+    --update            Record the current results as the references for the current feature
+                        combination
+    --table <path>      Table to use (defaults to `timing.json` in this crate's directory)";
+
+// System mode, with IRQs and FIQs disabled
+const CPSR: u32 = 0xDF;
+
+// Maximum number of frames to wait for before deciding a breakpoint will never be reached
+const MAX_FRAMES: u32 = 4;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Cpu {
+    Arm7,
+    Arm9,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Case {
+    name: String,
+    cpu: Cpu,
+    #[serde(default, skip_serializing_if = "is_false")]
+    thumb: bool,
+    /// Address to place the code at, in hexadecimal.
+    addr: String,
+    /// Raw instruction encodings, in hexadecimal.
+    code: Vec<String>,
+    /// Initial values for r0-r14, in hexadecimal.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    regs: BTreeMap<String, String>,
+    /// Offset from `addr` at which to stop measuring, in hexadecimal; defaults to the end of the
+    /// code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    /// Expected cycle counts, indexed by feature combination.
+    #[serde(default)]
+    cycles: BTreeMap<String, RawTimestamp>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Table {
+    cases: Vec<Case>,
+}
+
+fn parse_hex(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("Invalid hexadecimal value: {value}"))
+}
+
+/// Returns the name of the combination of features affecting the timing model this runner was
+/// built with.
+///
+/// `interp-cache` and `interp-r15-write-checks` aren't included, as they must not change any
+/// timings.
+fn feature_combination() -> String {
+    let features = [
+        (
+            "interp-timing-details",
+            cfg!(feature = "interp-timing-details"),
+        ),
+        ("interp-pipeline", cfg!(feature = "interp-pipeline")),
+        (
+            "interp-pipeline-accurate-reloads",
+            cfg!(feature = "interp-pipeline-accurate-reloads"),
+        ),
+        (
+            "interp-arm9-interlocks",
+            cfg!(feature = "interp-arm9-interlocks"),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect::<Vec<_>>();
+    if features.is_empty() {
+        "base".to_string()
+    } else {
+        features.join("+")
+    }
+}
+
+fn run_until_breakpoint(emu: &mut Emu<CpuEngine>) -> Result<(), String> {
+    let mut frames = 0;
+    loop {
+        match emu.run_with_cycles(&mut [RawTimestamp::MAX; 2]) {
+            RunOutput::StoppedByDebugHook => return Ok(()),
+            RunOutput::FrameFinished => {
+                frames += 1;
+                if frames == MAX_FRAMES {
+                    return Err("breakpoint not reached".to_string());
+                }
+            }
+            RunOutput::Shutdown => return Err("emulator shut down".to_string()),
+            RunOutput::MissingGbaBios => {
+                return Err("GBA mode requested without a GBA BIOS".to_string())
+            }
+            RunOutput::CyclesOver(_) => unreachable!(),
+        }
+    }
+}
+
+macro_rules! measure {
+    ($emu: expr, $core: ident, $core_ty: ident, $case: expr, $addr: expr, $end_addr: expr) => {{
+        let emu = $emu;
+        let case = $case;
+
+        for (i, instr) in case.code.iter().enumerate() {
+            let instr = parse_hex(instr)?;
+            if case.thumb {
+                $core::bus::write_16::<DebugCpuAccess, _>(
+                    emu,
+                    $addr + (i as u32) * 2,
+                    instr as u16,
+                );
+            } else {
+                $core::bus::write_32::<DebugCpuAccess, _>(emu, $addr + (i as u32) * 4, instr);
+            }
+        }
+
+        let mut regs = emu.$core.regs();
+        for (reg, value) in &case.regs {
+            let i = reg
+                .strip_prefix('r')
+                .and_then(|i| i.parse::<usize>().ok())
+                .filter(|i| *i < 15)
+                .ok_or_else(|| format!("Invalid register: {reg}"))?;
+            regs.gprs[i] = parse_hex(value)?;
+        }
+        $core_ty::set_regs(emu, &regs);
+        $core_ty::set_cpsr(emu, Psr::from_raw(CPSR));
+
+        emu.$core
+            .set_breakpoint_hook(Some(BreakpointHook::new(Box::new(|_, _| true))));
+        emu.$core.add_breakpoint($addr);
+        $core_ty::jump(emu, $addr | case.thumb as u32);
+        run_until_breakpoint(emu)?;
+        let start_time = emu.$core.schedule.cur_time().0;
+
+        emu.$core.is_stopped = false;
+        emu.$core.clear_breakpoints();
+        emu.$core.add_breakpoint($end_addr);
+        run_until_breakpoint(emu)?;
+        let end_time = emu.$core.schedule.cur_time().0;
+
+        end_time - start_time
+    }};
+}
+
+fn run_case(case: &Case) -> Result<RawTimestamp, String> {
+    let addr = parse_hex(&case.addr)?;
+    let end_addr = addr
+        + match &case.end {
+            Some(end) => parse_hex(end)?,
+            None => case.code.len() as u32 * if case.thumb { 2 } else { 4 },
+        };
+
+    let (mut emu, _) = crate::build_emu(Model::Lite, &stub_rom(), None)?;
+    let emu = &mut emu;
+
+    Ok(match case.cpu {
+        Cpu::Arm7 => measure!(emu, arm7, Arm7, case, addr, end_addr),
+        Cpu::Arm9 => measure!(emu, arm9, Arm9, case, addr, end_addr),
+    })
+}
+
+struct Args {
+    table_path: PathBuf,
+    update: bool,
+    filters: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = OsString>) -> Result<Args, String> {
+    let mut table_path = None;
+    let mut update = false;
+    let mut filters = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--update") => update = true,
+            Some("--table") => table_path = Some(path_value(&mut args, "--table")?),
+            Some("-h" | "--help") => return Err(USAGE.to_string()),
+            Some(filter) if !filter.starts_with('-') => filters.push(filter.to_string()),
+            _ => {
+                return Err(format!(
+                    "Unknown argument: {}\n\n{USAGE}",
+                    arg.to_string_lossy()
+                ))
+            }
+        }
+    }
+
+    Ok(Args {
+        table_path: table_path
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("timing.json")),
+        update,
+        filters,
+    })
+}
+
+pub fn main(args: impl Iterator<Item = OsString>) -> ExitCode {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut table = match fs::read(&args.table_path)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            serde_json::from_slice::<Table>(&contents).map_err(|err| err.to_string())
+        }) {
+        Ok(table) => table,
+        Err(err) => {
+            eprintln!(
+                "Couldn't read timing table at {}: {err}",
+                args.table_path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let features = feature_combination();
+    let (mut passed, mut recorded, mut failed) = (0, 0, 0);
+    for case in &mut table.cases {
+        if !args.filters.is_empty() && !args.filters.iter().any(|filter| case.name.contains(filter))
+        {
+            continue;
+        }
+        let cycles = match run_case(case) {
+            Ok(cycles) => cycles,
+            Err(err) => {
+                println!("{}: FAILED ({err})", case.name);
+                failed += 1;
+                continue;
+            }
+        };
+        match case.cycles.get(&features) {
+            Some(&expected) if expected == cycles => {
+                println!("{}: ok ({cycles} cycles)", case.name);
+                passed += 1;
+            }
+            _ if args.update => {
+                println!("{}: recorded ({cycles} cycles)", case.name);
+                case.cycles.insert(features.clone(), cycles);
+                recorded += 1;
+            }
+            Some(expected) => {
+                println!(
+                    "{}: FAILED (expected {expected} cycles, got {cycles})",
+                    case.name
+                );
+                failed += 1;
+            }
+            None => {
+                println!(
+                    "{}: FAILED (no reference recorded for {features}, got {cycles} cycles)",
+                    case.name
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    if recorded != 0 {
+        let result = serde_json::to_string_pretty(&table)
+            .map_err(|err| err.to_string())
+            .and_then(|mut contents| {
+                contents.push('\n');
+                fs::write(&args.table_path, contents).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            eprintln!(
+                "Couldn't write timing table at {}: {err}",
+                args.table_path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!("\n{passed} passed, {recorded} recorded, {failed} failed ({features})");
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
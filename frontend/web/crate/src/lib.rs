@@ -7,7 +7,7 @@ mod console_log;
 pub mod renderer_3d;
 
 use dust_core::{
-    cpu::{self, arm7, arm9, interpreter::Interpreter},
+    cpu::{self, arm7, arm9},
     ds_slot,
     emu::{self, input::Keys, Emu},
     flash::Flash,
@@ -20,6 +20,11 @@ use dust_core::{
 use js_sys::{Function, Uint32Array, Uint8Array};
 use wasm_bindgen::prelude::*;
 
+#[cfg(feature = "interp-cache")]
+type CpuEngine = cpu::interpreter::CachedInterpreter;
+#[cfg(not(feature = "interp-cache"))]
+type CpuEngine = cpu::interpreter::Interpreter;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[wasm_bindgen]
 pub enum SaveType {
@@ -96,7 +101,7 @@ pub struct EmuState {
     #[cfg(feature = "log")]
     logger: slog::Logger,
     model: Model,
-    emu: Option<Emu<Interpreter>>,
+    emu: Option<Emu<CpuEngine>>,
     arm7_bios: Option<Box<Bytes<{ arm7::BIOS_SIZE }>>>,
     arm9_bios: Option<Box<Bytes<{ arm9::BIOS_SIZE }>>>,
 }
@@ -115,6 +120,9 @@ fn build_emu<E: cpu::Engine>(emu_builder: emu::Builder, engine: E) -> emu::Emu<E
             emu::BuildError::RomNeedsDecryptionButNoBiosProvided => {
                 panic!("Couldn't start emulator: ROM needs decryption but no BIOS provided.");
             }
+            emu::BuildError::DsiStage2(err) => {
+                panic!("Couldn't load the DSi stage 2 bootloader: {err:?}");
+            }
         },
     }
 }
@@ -148,7 +156,7 @@ impl EmuState {
         emu_builder.model = self.model;
         emu_builder.direct_boot = true;
 
-        self.emu = Some(build_emu(emu_builder, Interpreter));
+        self.emu = Some(build_emu(emu_builder, CpuEngine {}));
     }
 
     pub fn load_save(&mut self, ram_arr: Uint8Array) {
@@ -338,27 +346,34 @@ pub fn create_emu_state(
                     .into()
                 }
                 SaveType::Flash2m | SaveType::Flash4m | SaveType::Flash8m => {
-                    ds_slot::spi::flash::Flash::new(
+                    let flash = ds_slot::spi::flash::Flash::new(
                         save_contents,
                         [0; 20],
-                        has_ir,
                         #[cfg(feature = "log")]
-                        logger.new(slog::o!("ds_spi" => if has_ir { "flash" } else { "flash_ir" })),
+                        logger.new(slog::o!("ds_spi" => "flash")),
                     )
                     // NOTE: The save contents' size is ensured beforehand, this should never occur.
-                    .expect("couldn't create FLASH DS slot SPI device")
-                    .into()
+                    .expect("couldn't create FLASH DS slot SPI device");
+                    if has_ir {
+                        ds_slot::spi::ir::Ir::new(
+                            flash,
+                            Box::new(ds_slot::spi::ir::DummyBackend),
+                            #[cfg(feature = "log")]
+                            logger.new(slog::o!("ds_spi" => "flash_ir")),
+                        )
+                        .into()
+                    } else {
+                        flash.into()
+                    }
                 }
                 SaveType::Nand64m | SaveType::Nand128m | SaveType::Nand256m => {
-                    #[cfg(feature = "log")]
-                    slog::error!(
-                        logger,
-                        "TODO: NAND saves are currently unsupported, falling back to no save file."
-                    );
-                    ds_slot::spi::Empty::new(
+                    ds_slot::spi::nand::Nand::new(
+                        save_contents,
                         #[cfg(feature = "log")]
-                        logger.new(slog::o!("ds_spi" => "nand_todo")),
+                        logger.new(slog::o!("ds_spi" => "nand")),
                     )
+                    // NOTE: The save contents' size is ensured beforehand, this should never occur.
+                    .expect("couldn't create NAND DS slot save device")
                     .into()
                 }
             }
@@ -379,7 +394,7 @@ pub fn create_emu_state(
         ds_slot_spi,
         Box::new(audio::Backend::new(audio_callback)),
         None,
-        Box::new(rtc::DummyBackend),
+        Box::new(rtc::FixedBackend::default()),
         Box::new(dust_soft_2d::sync::Renderer::new(Box::new(rx_3d))),
         Box::new(tx_3d),
         None,
@@ -393,7 +408,7 @@ pub fn create_emu_state(
     emu_builder.model = model;
     emu_builder.direct_boot = true;
 
-    let emu = build_emu(emu_builder, Interpreter);
+    let emu = build_emu(emu_builder, CpuEngine {});
 
     EmuState {
         #[cfg(feature = "log")]
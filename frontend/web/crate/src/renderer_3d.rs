@@ -121,6 +121,11 @@ impl SoftRendererRx for Rx {
     fn skip_scanline(&mut self) {
         self.next_scanline += 1;
     }
+
+    fn last_hi_res_scanline(&self) -> &[u32] {
+        let line = self.next_scanline.saturating_sub(1) as usize;
+        unsafe { &(&*shared_data!().scanline_buffer.get())[line].0 }
+    }
 }
 
 pub fn init() -> (Tx, Rx) {
@@ -158,9 +163,9 @@ pub fn run_worker() {
         for y in 0..192 {
             let scanline = &mut unsafe { &mut *shared_data.scanline_buffer.get() }[y as usize];
             if y < 191 {
-                raw_renderer.render_line(y + 1, rendering_data);
+                raw_renderer.render_line(y as u16 + 1, rendering_data);
             }
-            raw_renderer.postprocess_line(y, scanline, rendering_data);
+            raw_renderer.postprocess_line(y as u16, &mut scanline.0, rendering_data);
             if shared_data
                 .processing_scanline
                 .compare_exchange(y, y + 1, Ordering::Release, Ordering::Relaxed)
@@ -0,0 +1,295 @@
+//! Display capture at the rendering resolution.
+//!
+//! Captures are always performed at the native resolution, so that games see the expected VRAM
+//! contents; on top of that, every 256-pixel-wide capture is repeated into a hi-res copy of the
+//! destination bank, which is then used in place of the native data whenever a VRAM bank line is
+//! displayed (in VRAM display mode) or used as capture source B.
+//!
+//! As the CPU can modify captured lines at any time, the native contents written by each capture
+//! are kept around by a [`Tracker`], and a hi-res line is only used if the native one hasn't
+//! changed since. 128-pixel-wide captures pack two captured lines into each bank line, and are
+//! only performed at the native resolution.
+
+use super::{capture, BgObjPixel};
+use dust_core::gpu::{
+    engine_2d::{CaptureControl, Control},
+    vram::Vram,
+    Scanline, SCREEN_WIDTH,
+};
+
+/// The number of 256-pixel lines in each of VRAM banks A-D.
+pub const BANK_LINES: usize = 0x100;
+
+/// Returns the bank line a 256-pixel-wide capture of `line` is written to.
+#[inline]
+pub fn capture_dst_line(capture_control: CaptureControl, line: u8) -> u8 {
+    (capture_control.dst_offset_raw() << 6).wrapping_add(line)
+}
+
+/// Returns the bank and bank line read as capture source B for `line`, or `None` if it's read from
+/// the display FIFO.
+#[inline]
+pub fn capture_src_b_line(
+    control: Control,
+    capture_control: CaptureControl,
+    line: u8,
+) -> Option<(u8, u8)> {
+    if capture_control.src_b_display_fifo() {
+        return None;
+    }
+    let bank_line = if control.display_mode_a() == 2 {
+        line
+    } else {
+        (capture_control.src_b_vram_offset_raw() << 6).wrapping_add(line)
+    };
+    Some((control.a_vram_bank(), bank_line))
+}
+
+/// Returns a pointer to the given line of VRAM bank A-D, if it's currently mapped to LCDC.
+pub fn bank_line_ptr(vram: &Vram, bank: u8, line: u8) -> Option<*mut u16> {
+    let bank_control = vram.bank_control()[bank as usize];
+    if !bank_control.enabled() || bank_control.mst() != 0 {
+        return None;
+    }
+    let bank_ptr = match bank {
+        0 => vram.banks.a.as_mut_ptr(),
+        1 => vram.banks.b.as_mut_ptr(),
+        2 => vram.banks.c.as_mut_ptr(),
+        _ => vram.banks.d.as_mut_ptr(),
+    };
+    Some(unsafe { bank_ptr.add((line as usize) << 9).cast() })
+}
+
+/// Keeps track of which VRAM bank lines still hold the results of a 256-pixel-wide capture.
+pub struct Tracker {
+    captured_lines: Box<[[Scanline<u16>; BANK_LINES]; 4]>,
+    valid_lines: [[u64; BANK_LINES / 64]; 4],
+}
+
+#[allow(clippy::new_without_default)]
+impl Tracker {
+    pub fn new() -> Self {
+        Tracker {
+            captured_lines: unsafe { Box::new_zeroed().assume_init() },
+            valid_lines: [[0; BANK_LINES / 64]; 4],
+        }
+    }
+
+    /// Returns whether the hi-res copy of the given bank line can be used in place of its current
+    /// native contents.
+    pub fn is_line_valid(&self, vram: &Vram, bank: u8, line: u8) -> bool {
+        if self.valid_lines[bank as usize][line as usize >> 6] & 1 << (line & 63) == 0 {
+            return false;
+        }
+        let Some(line_ptr) = bank_line_ptr(vram, bank, line) else {
+            return false;
+        };
+        let native_line = unsafe { &*line_ptr.cast::<[u16; SCREEN_WIDTH]>() };
+        *native_line == self.captured_lines[bank as usize][line as usize].0
+    }
+
+    /// Records the native results of the capture of `line`, which should already have been
+    /// written to VRAM.
+    pub fn capture_finished(&mut self, vram: &Vram, capture_control: CaptureControl, line: u8) {
+        let bank = capture_control.dst_bank();
+        if capture_control.size() == 0 {
+            // 128-pixel-wide captures aren't repeated at a higher resolution, and are packed so
+            // that two of them share a single bank line
+            let bank_line = (capture_control.dst_offset_raw() << 6).wrapping_add(line >> 1);
+            self.valid_lines[bank as usize][bank_line as usize >> 6] &= !(1 << (bank_line & 63));
+            return;
+        }
+        let bank_line = capture_dst_line(capture_control, line);
+        let Some(line_ptr) = bank_line_ptr(vram, bank, bank_line) else {
+            return;
+        };
+        self.captured_lines[bank as usize][bank_line as usize].0 =
+            unsafe { *line_ptr.cast::<[u16; SCREEN_WIDTH]>() };
+        self.valid_lines[bank as usize][bank_line as usize >> 6] |= 1 << (bank_line & 63);
+    }
+}
+
+#[inline]
+fn rgb6_to_rgb5(value: u32) -> u16 {
+    ((value >> 1 & 0x1F) | (value >> 2 & 0x3E0) | (value >> 3 & 0x7C00)) as u16
+}
+
+/// Hi-res copies of VRAM banks A-D in the same RGB555 + alpha format as the native data, used by
+/// the software renderers.
+pub struct Banks {
+    resolution_scale_shift: u8,
+    tracker: Tracker,
+    data: Box<[u16]>,
+}
+
+impl Banks {
+    pub fn new(resolution_scale_shift: u8) -> Self {
+        Banks {
+            resolution_scale_shift,
+            tracker: Tracker::new(),
+            data: vec![0; (4 * BANK_LINES * SCREEN_WIDTH) << (resolution_scale_shift << 1)]
+                .into_boxed_slice(),
+        }
+    }
+
+    #[inline]
+    fn lines_offset(&self, bank: u8, line: u8) -> usize {
+        ((bank as usize * BANK_LINES + line as usize) * SCREEN_WIDTH)
+            << (self.resolution_scale_shift << 1)
+    }
+
+    /// Returns the `1 << resolution_scale_shift` hi-res lines holding the given bank line, if they
+    /// can be used in place of its current native contents.
+    pub fn valid_lines(&self, vram: &Vram, bank: u8, line: u8) -> Option<&[u16]> {
+        self.is_line_valid(vram, bank, line)
+            .then(|| self.lines(bank, line))
+    }
+
+    /// Returns whether the hi-res copy of the given bank line can be used in place of its current
+    /// native contents.
+    #[inline]
+    pub fn is_line_valid(&self, vram: &Vram, bank: u8, line: u8) -> bool {
+        self.tracker.is_line_valid(vram, bank, line)
+    }
+
+    /// Returns the `1 << resolution_scale_shift` hi-res lines holding the given bank line, without
+    /// checking whether they're still valid.
+    pub fn lines(&self, bank: u8, line: u8) -> &[u16] {
+        &self.data[self.lines_offset(bank, line)..]
+            [..SCREEN_WIDTH << (self.resolution_scale_shift << 1)]
+    }
+
+    /// Runs the capture of `line` at both the native and the rendering resolution.
+    ///
+    /// `scanline_3d` holds the native 3D scanline along with the matching hi-res lines; as in
+    /// [`capture::run`], it should only be provided if the 3D layer was rendered for this line.
+    pub fn run(
+        &mut self,
+        line: u8,
+        control: Control,
+        capture_control: CaptureControl,
+        bg_obj_scanline: &Scanline<BgObjPixel>,
+        scanline_3d: Option<(&Scanline<u32>, &[u32])>,
+        vram: &Vram,
+    ) {
+        // Source B might be overwritten by the native capture, so it needs to be read first
+        if capture_control.size() != 0 {
+            self.run_hi_res(
+                line,
+                control,
+                capture_control,
+                bg_obj_scanline,
+                scanline_3d,
+                vram,
+            );
+        }
+        capture::run(
+            line,
+            control,
+            capture_control,
+            bg_obj_scanline,
+            scanline_3d.map(|(scanline_3d, _)| scanline_3d),
+            vram,
+        );
+        self.tracker.capture_finished(vram, capture_control, line);
+    }
+
+    fn run_hi_res(
+        &mut self,
+        line: u8,
+        control: Control,
+        capture_control: CaptureControl,
+        bg_obj_scanline: &Scanline<BgObjPixel>,
+        scanline_3d: Option<(&Scanline<u32>, &[u32])>,
+        vram: &Vram,
+    ) {
+        let dst_bank = capture_control.dst_bank();
+        let dst_bank_line = capture_dst_line(capture_control, line);
+        if bank_line_ptr(vram, dst_bank, dst_bank_line).is_none() {
+            return;
+        }
+
+        enum SrcB {
+            HiRes(usize),
+            Native(*const u16),
+        }
+
+        let capture_source = capture_control.src();
+        let factor_a = capture_control.factor_a().min(16) as u16;
+        let factor_b = capture_control.factor_b().min(16) as u16;
+
+        let src_b = if capture_source != 0 && (factor_b != 0 || capture_source & 2 == 0) {
+            if capture_control.src_b_display_fifo() {
+                todo!("Display capture display FIFO source");
+            }
+            capture_src_b_line(control, capture_control, line).and_then(|(bank, bank_line)| {
+                if self.tracker.is_line_valid(vram, bank, bank_line) {
+                    Some(SrcB::HiRes(self.lines_offset(bank, bank_line)))
+                } else {
+                    bank_line_ptr(vram, bank, bank_line)
+                        .map(|line_ptr| SrcB::Native(line_ptr as *const u16))
+                }
+            })
+        } else {
+            None
+        };
+
+        let copy_src_b = capture_source == 1
+            || (capture_source & 2 != 0 && factor_a == 0)
+            || (capture_control.src_a_3d_only() && scanline_3d.is_none());
+
+        let resolution_scale_shift = self.resolution_scale_shift;
+        let width = SCREEN_WIDTH << resolution_scale_shift;
+        let dst_offset = self.lines_offset(dst_bank, dst_bank_line);
+
+        for i in 0..width << resolution_scale_shift {
+            let x = (i & (width - 1)) >> resolution_scale_shift;
+
+            let b_pixel = src_b.as_ref().map(|src_b| match src_b {
+                SrcB::HiRes(offset) => self.data[offset + i],
+                SrcB::Native(line_ptr) => unsafe { line_ptr.add(x).read() },
+            });
+
+            let pixel = if copy_src_b {
+                b_pixel.unwrap_or(0)
+            } else {
+                let a_pixel = if capture_control.src_a_3d_only() {
+                    let (_, hi_res_3d) = unsafe { scanline_3d.unwrap_unchecked() };
+                    let sample = hi_res_3d[i];
+                    rgb6_to_rgb5(sample) | ((sample >> 18 & 0x1F != 0) as u16) << 15
+                } else {
+                    // Only 3D pixels that were taken unmodified from the 3D layer are replaced
+                    // with their hi-res samples, as in `upscale_scanline`
+                    let pixel = bg_obj_scanline.0[x];
+                    let rgb = match scanline_3d {
+                        Some((_, hi_res_3d))
+                            if pixel.is_3d()
+                                && pixel.rgb()
+                                    == hi_res_3d[x << resolution_scale_shift] & 0x3_FFFF
+                                && hi_res_3d[i] >> 18 != 0 =>
+                        {
+                            hi_res_3d[i]
+                        }
+                        _ => pixel.rgb(),
+                    };
+                    rgb6_to_rgb5(rgb) | 0x8000
+                };
+
+                if let Some(b_pixel) = b_pixel {
+                    let a_a = a_pixel >> 15;
+                    let b_a = b_pixel >> 15;
+                    let blend = |shift: u8| {
+                        let a = a_pixel >> shift & 0x1F;
+                        let b = b_pixel >> shift & 0x1F;
+                        (((a * a_a * factor_a) + (b * b_a * factor_b)) >> 4).min(0x1F) << shift
+                    };
+                    blend(0) | blend(5) | blend(10) | (a_a | b_a) << 15
+                } else {
+                    a_pixel
+                }
+            };
+
+            self.data[dst_offset + i] = pixel;
+        }
+    }
+}
@@ -11,6 +11,7 @@
 #![allow(incomplete_features, clippy::missing_safety_doc)]
 
 pub mod capture;
+pub mod hi_res_capture;
 mod impls;
 pub mod render;
 
@@ -84,6 +85,12 @@ pub const fn rgb5_to_rgb6_64(value: u16) -> u64 {
     (value << 1 & 0x3E) | (value << 2 & 0xF80) | (value << 3 & 0x3_E000)
 }
 
+#[inline]
+pub const fn rgb6_to_rgba8(value: u32) -> u32 {
+    let rgb6_8 = (value & 0x3F) | (value << 2 & 0x3F00) | (value << 4 & 0x3F_0000);
+    0xFF00_0000 | rgb6_8 << 2 | (rgb6_8 >> 4 & 0x0003_0303)
+}
+
 #[allow(clippy::mut_from_ref, clippy::missing_safety_doc)]
 pub trait Buffers {
     unsafe fn obj_window(&self) -> &mut [u8; SCREEN_WIDTH / 8];
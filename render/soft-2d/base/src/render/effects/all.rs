@@ -1,4 +1,4 @@
-use crate::{BgObjPixel, Buffers, RenderingData};
+use crate::{rgb6_to_rgba8, BgObjPixel, Buffers, RenderingData};
 use dust_core::gpu::{Scanline, SCREEN_WIDTH};
 
 pub fn apply_color_effects<B: Buffers, D: RenderingData, const EFFECT: u8>(buffers: &B, data: &D) {
@@ -91,12 +91,6 @@ pub fn apply_color_effects<B: Buffers, D: RenderingData, const EFFECT: u8>(buffe
     }
 }
 
-#[inline]
-fn rgb6_to_rgba8(value: u32) -> u32 {
-    let rgb6_8 = (value & 0x3F) | (value << 2 & 0x3F00) | (value << 4 & 0x3F_0000);
-    0xFF00_0000 | rgb6_8 << 2 | (rgb6_8 >> 4 & 0x0003_0303)
-}
-
 pub fn apply_brightness<D: RenderingData>(scanline_buffer: &mut Scanline<u32>, data: &D) {
     let brightness_factor = data.master_brightness_factor();
     match data.master_brightness_control().mode() {
@@ -1,7 +1,7 @@
 pub use dust_soft_2d_base::*;
 
 use core::marker::PhantomData;
-use dust_core::gpu::{engine_2d::Role, Scanline};
+use dust_core::gpu::{engine_2d::Role, Scanline, SCREEN_HEIGHT, SCREEN_WIDTH};
 use render::{bgs, effects};
 
 #[allow(clippy::type_complexity)]
@@ -41,3 +41,79 @@ impl<R: Role, B: Buffers, D: RenderingData, V: Vram<R>> FnPtrs<R, B, D, V> {
         }
     }
 }
+
+/// Returns the length of a framebuffer holding both screens at `1 << resolution_scale_shift`
+/// times the native resolution.
+pub fn hi_res_framebuffer_len(resolution_scale_shift: u8) -> usize {
+    (2 * SCREEN_WIDTH * SCREEN_HEIGHT) << (resolution_scale_shift << 1)
+}
+
+/// Returns the `1 << resolution_scale_shift` lines of a high-resolution framebuffer that
+/// correspond to the native scanline `line` of the specified screen.
+pub fn hi_res_lines(
+    hi_res_framebuffer: &mut [u32],
+    resolution_scale_shift: u8,
+    is_on_lower_screen: bool,
+    line: u8,
+) -> &mut [u32] {
+    let len = SCREEN_WIDTH << (resolution_scale_shift << 1);
+    &mut hi_res_framebuffer[(is_on_lower_screen as usize * SCREEN_HEIGHT + line as usize) * len..]
+        [..len]
+}
+
+/// Returns whether master brightness is currently changing the output's colors.
+pub fn master_brightness_active<D: RenderingData>(data: &D) -> bool {
+    matches!(data.master_brightness_control().mode(), 1 | 2) && data.master_brightness_factor() != 0
+}
+
+/// Fills the `1 << resolution_scale_shift` lines of `hi_res_lines` that correspond to the native
+/// `scanline` by upscaling it, then, if `hi_res_3d` is provided, replaces the pixels that were
+/// taken unmodified from the 3D layer with the ones it rendered at the higher resolution.
+///
+/// `hi_res_3d` holds the BG/OBJ scanline (after color effects) the output was produced from and
+/// the matching lines rendered by the 3D renderer; as the native 3D output is the top-left sample
+/// of each enlarged pixel, 3D pixels that were blended or otherwise altered can be told apart by
+/// comparing them against it, and are only upscaled.
+pub fn upscale_scanline(
+    hi_res_lines: &mut [u32],
+    resolution_scale_shift: u8,
+    scanline: &Scanline<u32>,
+    hi_res_3d: Option<(&Scanline<BgObjPixel>, &[u32])>,
+) {
+    let scale = 1 << resolution_scale_shift;
+    let width = SCREEN_WIDTH << resolution_scale_shift;
+    for (sub_line, line) in hi_res_lines.chunks_exact_mut(width).enumerate() {
+        for (dst, src) in line.chunks_exact_mut(scale).zip(scanline.0.iter()) {
+            dst.fill(*src);
+        }
+
+        let Some((bg_obj_scanline, hi_res_3d)) = hi_res_3d else {
+            continue;
+        };
+        let line_3d = &hi_res_3d[sub_line * width..][..width];
+        for (x, pixel) in bg_obj_scanline.0.iter().enumerate() {
+            let start = x << resolution_scale_shift;
+            if !pixel.is_3d() || pixel.rgb() != hi_res_3d[start] & 0x3_FFFF {
+                continue;
+            }
+            for (dst, src) in line[start..][..scale]
+                .iter_mut()
+                .zip(&line_3d[start..][..scale])
+            {
+                // Transparent samples keep showing whatever was below the native 3D pixel
+                if *src >> 18 != 0 {
+                    *dst = rgb6_to_rgba8(*src);
+                }
+            }
+        }
+    }
+}
+
+/// Fills the `1 << resolution_scale_shift` lines of `hi_res_lines` with the hi-res copy of a
+/// captured VRAM bank line (see [`hi_res_capture`]), for VRAM display mode; master brightness
+/// isn't applied, so this should only be used while it's inactive.
+pub fn copy_hi_res_vram_lines(hi_res_lines: &mut [u32], hi_res_vram_lines: &[u16]) {
+    for (dst, src) in hi_res_lines.iter_mut().zip(hi_res_vram_lines) {
+        *dst = rgb6_to_rgba8(rgb5_to_rgb6(*src));
+    }
+}
@@ -1,7 +1,7 @@
 mod impls;
 
 use crate::common::{
-    self, capture,
+    self, capture, hi_res_capture,
     render::{self, objs::prerender_objs},
     rgb5_to_rgb6_64, BgObjPixel, ObjPixel, WindowPixel,
 };
@@ -31,6 +31,10 @@ pub struct Renderer {
     renderer_3d_rx: Box<dyn engine_3d::SoftRendererRx>,
     buffers: [Buffers; 2],
     framebuffer: Box<[[Scanline<u32>; SCREEN_HEIGHT]; 2]>,
+    resolution_scale_shift: u8,
+    /// The output at the 3D renderer's resolution; empty if rendering at the native resolution.
+    hi_res_framebuffer: Box<[u32]>,
+    hi_res_capture_banks: Option<hi_res_capture::Banks>,
 }
 
 unsafe impl Send for Renderer {}
@@ -48,11 +52,21 @@ impl Renderer {
             };
         }
 
+        let resolution_scale_shift = renderer_3d_rx.resolution_scale_shift();
+
         Renderer {
             fns: (FnPtrs::new(), FnPtrs::new()),
             renderer_3d_rx,
             buffers: [buffers!(), buffers!()],
             framebuffer: unsafe { Box::new_zeroed().assume_init() },
+            resolution_scale_shift,
+            hi_res_framebuffer: if resolution_scale_shift == 0 {
+                Box::new([])
+            } else {
+                vec![0; common::hi_res_framebuffer_len(resolution_scale_shift)].into_boxed_slice()
+            },
+            hi_res_capture_banks: (resolution_scale_shift != 0)
+                .then(|| hi_res_capture::Banks::new(resolution_scale_shift)),
         }
     }
 
@@ -87,6 +101,15 @@ impl Renderer {
             // TODO: Display capture interaction?
 
             scanline_buffer.0.fill(0xFFFF_FFFF);
+            if self.resolution_scale_shift != 0 {
+                common::hi_res_lines(
+                    &mut self.hi_res_framebuffer,
+                    self.resolution_scale_shift,
+                    engine.is_on_lower_screen(),
+                    line,
+                )
+                .fill(0xFFFF_FFFF);
+            }
             return;
         }
 
@@ -107,7 +130,9 @@ impl Renderer {
                 && (engine.capture_control().src_a_3d_only() || enabled_in_bg_obj))
                 || (display_mode == 1 && enabled_in_bg_obj)
             {
-                Some(self.renderer_3d_rx.read_scanline())
+                // The scanline stays valid until the next one is read or skipped, which allows the
+                // hi-res one to be accessed alongside it
+                Some(unsafe { &*(self.renderer_3d_rx.read_scanline() as *const Scanline<u32>) })
             } else {
                 self.renderer_3d_rx.skip_scanline();
                 None
@@ -115,6 +140,7 @@ impl Renderer {
         } else {
             None
         };
+        let has_scanline_3d = scanline_3d.is_some();
 
         if render_bg_obj_line {
             let window = buffers.window.get_mut();
@@ -205,15 +231,66 @@ impl Renderer {
             prerender_objs::<R, _, _, _>(buffers, line + 1, engine, vram);
         }
 
-        if R::IS_A && engine.capture_enabled_in_frame() && line < engine.capture_height() {
-            capture::run(
+        if self.resolution_scale_shift != 0 {
+            let hi_res_lines = common::hi_res_lines(
+                &mut self.hi_res_framebuffer,
+                self.resolution_scale_shift,
+                engine.is_on_lower_screen(),
                 line,
-                engine.control(),
-                engine.capture_control(),
-                buffers.bg_obj_scanline.get_mut(),
-                scanline_3d,
-                vram,
-            )
+            );
+            let hi_res_vram_lines =
+                if R::IS_A && display_mode == 2 && !common::master_brightness_active(&*engine) {
+                    self.hi_res_capture_banks.as_ref().and_then(|banks| {
+                        banks.valid_lines(vram, engine.control().a_vram_bank(), vcount)
+                    })
+                } else {
+                    None
+                };
+            if let Some(hi_res_vram_lines) = hi_res_vram_lines {
+                common::copy_hi_res_vram_lines(hi_res_lines, hi_res_vram_lines);
+            } else {
+                let hi_res_3d = if display_mode == 1
+                    && has_scanline_3d
+                    && !common::master_brightness_active(&*engine)
+                {
+                    Some((
+                        &*buffers.bg_obj_scanline.get_mut(),
+                        self.renderer_3d_rx.last_hi_res_scanline(),
+                    ))
+                } else {
+                    None
+                };
+                common::upscale_scanline(
+                    hi_res_lines,
+                    self.resolution_scale_shift,
+                    scanline_buffer,
+                    hi_res_3d,
+                );
+            }
+        }
+
+        if R::IS_A && engine.capture_enabled_in_frame() && line < engine.capture_height() {
+            if let Some(hi_res_capture_banks) = &mut self.hi_res_capture_banks {
+                hi_res_capture_banks.run(
+                    line,
+                    engine.control(),
+                    engine.capture_control(),
+                    buffers.bg_obj_scanline.get_mut(),
+                    scanline_3d.map(|scanline_3d| {
+                        (scanline_3d, self.renderer_3d_rx.last_hi_res_scanline())
+                    }),
+                    vram,
+                );
+            } else {
+                capture::run(
+                    line,
+                    engine.control(),
+                    engine.capture_control(),
+                    buffers.bg_obj_scanline.get_mut(),
+                    scanline_3d,
+                    vram,
+                );
+            }
         }
     }
 }
@@ -231,6 +308,11 @@ impl RendererTrait for Renderer {
         unsafe { &*(self.framebuffer.as_ptr() as *const () as *const Framebuffer) }
     }
 
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])> {
+        (self.resolution_scale_shift != 0)
+            .then_some((self.resolution_scale_shift, &self.hi_res_framebuffer[..]))
+    }
+
     fn start_prerendering_objs(
         &mut self,
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
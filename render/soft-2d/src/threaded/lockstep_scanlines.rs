@@ -1,7 +1,7 @@
 mod impls;
 
 use crate::common::{
-    self, capture,
+    self, capture, hi_res_capture,
     render::{self, objs::prerender_objs},
     rgb5_to_rgb6_64, BgObjPixel, ObjPixel, WindowPixel,
 };
@@ -43,9 +43,18 @@ struct SharedData {
 
     vram: UnsafeCell<(Box<Vram<EngineA>>, Box<Vram<EngineB>>)>,
     rendering_data: UnsafeCell<[RenderingData; 2]>,
-    capture_scanlines: UnsafeCell<(Scanline<BgObjPixel>, Scanline<u32>)>,
+    /// The BG/OBJ and 3D scanlines to capture, along with the hi-res 3D lines if rendering at a
+    /// higher resolution.
+    capture_scanlines: UnsafeCell<(Scanline<BgObjPixel>, Scanline<u32>, Box<[u32]>)>,
 
     framebuffer: UnsafeCell<Box<[[Scanline<u32>; SCREEN_HEIGHT]; 2]>>,
+    resolution_scale_shift: u8,
+    /// The output at the 3D renderer's resolution; empty if rendering at the native resolution.
+    hi_res_framebuffer: UnsafeCell<Box<[u32]>>,
+    hi_res_capture_banks: UnsafeCell<Option<hi_res_capture::Banks>>,
+    /// Whether the current scanline should be displayed from the hi-res copy of its VRAM bank line,
+    /// in VRAM display mode.
+    hi_res_vram_display: UnsafeCell<bool>,
 }
 
 unsafe impl Sync for SharedData {}
@@ -187,6 +196,8 @@ impl Renderer {
             capture_height: 128,
         };
 
+        let resolution_scale_shift = renderer_3d_rx.resolution_scale_shift();
+
         let shared_data = Arc::new(unsafe {
             SharedData {
                 stopped: AtomicBool::new(false),
@@ -207,9 +218,22 @@ impl Renderer {
                 capture_scanlines: UnsafeCell::new((
                     Scanline([BgObjPixel(0); SCREEN_WIDTH]),
                     Scanline([0; SCREEN_WIDTH]),
+                    vec![0; SCREEN_WIDTH << (resolution_scale_shift << 1)].into_boxed_slice(),
                 )),
 
                 framebuffer: UnsafeCell::new(Box::new_zeroed().assume_init()),
+                resolution_scale_shift,
+                hi_res_framebuffer: UnsafeCell::new(if resolution_scale_shift == 0 {
+                    Box::new([])
+                } else {
+                    vec![0; common::hi_res_framebuffer_len(resolution_scale_shift)]
+                        .into_boxed_slice()
+                }),
+                hi_res_capture_banks: UnsafeCell::new(
+                    (resolution_scale_shift != 0)
+                        .then(|| hi_res_capture::Banks::new(resolution_scale_shift)),
+                ),
+                hi_res_vram_display: UnsafeCell::new(false),
             }
         });
 
@@ -377,6 +401,17 @@ impl RendererTrait for Renderer {
         }
     }
 
+    fn hi_res_framebuffer(&self) -> Option<(u8, &[u32])> {
+        let resolution_scale_shift = self.shared_data.resolution_scale_shift;
+        if resolution_scale_shift == 0 {
+            return None;
+        }
+        self.wait_for_scanline_finish();
+        Some((resolution_scale_shift, unsafe {
+            &**self.shared_data.hi_res_framebuffer.get()
+        }))
+    }
+
     fn start_prerendering_objs(
         &mut self,
         engines: (&mut Engine2d<EngineA>, &mut Engine2d<EngineB>),
@@ -442,6 +477,15 @@ impl RendererTrait for Renderer {
 
         self.flush_rendering_data((engines.0, engines.1));
 
+        if let Some(hi_res_capture_banks) = unsafe { &*self.shared_data.hi_res_capture_banks.get() }
+        {
+            let control = engines.0.control();
+            unsafe {
+                *self.shared_data.hi_res_vram_display.get() = control.display_mode_a() == 2
+                    && hi_res_capture_banks.is_line_valid(vram, control.a_vram_bank(), vcount);
+            }
+        }
+
         macro_rules! update_affine_bgs {
             ($($engine_i: literal, $engine: expr, ($($i: literal),*));*) => {{
                 let shared_data = unsafe { &mut *self.shared_data.rendering_data.get() };
@@ -470,19 +514,33 @@ impl RendererTrait for Renderer {
     ) {
         if engines.0.capture_enabled_in_frame() && line < engines.0.capture_height() {
             self.wait_for_scanline_finish();
-            let (bg_obj_scanline, scanline_3d) =
+            let (bg_obj_scanline, scanline_3d, hi_res_scanline_3d) =
                 unsafe { &*self.shared_data.capture_scanlines.get() };
-            capture::run(
-                line,
-                engines.0.control(),
-                engines.0.capture_control(),
-                bg_obj_scanline,
-                engines
-                    .0
-                    .engine_3d_enabled_in_frame()
-                    .then_some(scanline_3d),
-                vram,
-            )
+            let scanline_3d = engines
+                .0
+                .engine_3d_enabled_in_frame()
+                .then_some(scanline_3d);
+            if let Some(hi_res_capture_banks) =
+                unsafe { &mut *self.shared_data.hi_res_capture_banks.get() }
+            {
+                hi_res_capture_banks.run(
+                    line,
+                    engines.0.control(),
+                    engines.0.capture_control(),
+                    bg_obj_scanline,
+                    scanline_3d.map(|scanline_3d| (scanline_3d, &**hi_res_scanline_3d)),
+                    vram,
+                );
+            } else {
+                capture::run(
+                    line,
+                    engines.0.control(),
+                    engines.0.capture_control(),
+                    bg_obj_scanline,
+                    scanline_3d,
+                    vram,
+                );
+            }
         }
     }
 }
@@ -556,6 +614,15 @@ impl ThreadData {
                     self.renderer_3d_rx.skip_scanline();
                 }
                 scanline_buffer.0.fill(0xFFFF_FFFF);
+                if self.shared_data.resolution_scale_shift != 0 {
+                    common::hi_res_lines(
+                        unsafe { &mut *self.shared_data.hi_res_framebuffer.get() },
+                        self.shared_data.resolution_scale_shift,
+                        data.is_on_lower_screen,
+                        self.cur_scanline as u8,
+                    )
+                    .fill(0xFFFF_FFFF);
+                }
             } else {
                 let scanline_3d = if R::IS_A && data.engine_3d_enabled_in_frame {
                     let enabled_in_bg_obj = data.bgs[0].priority != 4 && data.control.bg0_3d();
@@ -571,6 +638,7 @@ impl ThreadData {
                 } else {
                     None
                 };
+                let has_scanline_3d = scanline_3d.is_some();
 
                 if render_bg_obj_line {
                     let window = buffers.window.get_mut();
@@ -655,13 +723,60 @@ impl ThreadData {
                     && data.capture_enabled_in_frame
                     && self.cur_scanline < data.capture_height as i16
                 {
-                    let (capture_bg_obj_scanline, capture_scanline_3d) =
+                    let (capture_bg_obj_scanline, capture_scanline_3d, capture_hi_res_scanline_3d) =
                         unsafe { &mut *self.shared_data.capture_scanlines.get() };
                     capture_bg_obj_scanline
                         .0
                         .copy_from_slice(&buffers.bg_obj_scanline.get_mut().0);
                     if let Some(scanline_3d) = scanline_3d {
                         capture_scanline_3d.0.copy_from_slice(&scanline_3d.0);
+                        if self.shared_data.resolution_scale_shift != 0 {
+                            capture_hi_res_scanline_3d
+                                .copy_from_slice(self.renderer_3d_rx.last_hi_res_scanline());
+                        }
+                    }
+                }
+
+                let resolution_scale_shift = self.shared_data.resolution_scale_shift;
+                if resolution_scale_shift != 0 {
+                    let hi_res_lines = common::hi_res_lines(
+                        unsafe { &mut *self.shared_data.hi_res_framebuffer.get() },
+                        resolution_scale_shift,
+                        data.is_on_lower_screen,
+                        self.cur_scanline as u8,
+                    );
+                    if R::IS_A
+                        && display_mode == 2
+                        && unsafe { *self.shared_data.hi_res_vram_display.get() }
+                        && !common::master_brightness_active(&*data)
+                    {
+                        let hi_res_capture_banks = unsafe {
+                            (*self.shared_data.hi_res_capture_banks.get())
+                                .as_ref()
+                                .unwrap_unchecked()
+                        };
+                        common::copy_hi_res_vram_lines(
+                            hi_res_lines,
+                            hi_res_capture_banks.lines(data.control.a_vram_bank(), vcount),
+                        );
+                    } else {
+                        let hi_res_3d = if display_mode == 1
+                            && has_scanline_3d
+                            && !common::master_brightness_active(&*data)
+                        {
+                            Some((
+                                &*buffers.bg_obj_scanline.get_mut(),
+                                self.renderer_3d_rx.last_hi_res_scanline(),
+                            ))
+                        } else {
+                            None
+                        };
+                        common::upscale_scanline(
+                            hi_res_lines,
+                            resolution_scale_shift,
+                            scanline_buffer,
+                            hi_res_3d,
+                        );
                     }
                 }
             }
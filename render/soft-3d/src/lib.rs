@@ -3,6 +3,8 @@
 
 mod data;
 pub use data::RenderingData;
+#[cfg(feature = "threaded")]
+pub mod threaded;
 mod utils;
 
 use core::simd::{cmp::SimdOrd, num::SimdUint};
@@ -12,15 +14,18 @@ use dust_core::{
             Color, InterpColor, PolyAddr, PolyVertIndex, RenderingPolygonAttrs, TexCoords,
             TextureParams,
         },
-        Scanline,
+        SCREEN_HEIGHT, SCREEN_WIDTH,
     },
     utils::mem_prelude::*,
 };
 use utils::{
     clip_x_range, dec_poly_vert_index, decode_rgb5, expand_depth, inc_poly_vert_index,
-    rgb5_to_rgb6, rgb5_to_rgb6_shift, DummyEdge, Edge, Edges, InterpLineData,
+    rgb5_to_rgb6, rgb5_to_rgb6_shift, scaled_coords, DummyEdge, Edge, Edges, InterpLineData,
 };
 
+/// The maximum supported value for the base 2 logarithm of the resolution scale (i.e. 4x).
+pub const MAX_RESOLUTION_SCALE_SHIFT: u8 = 2;
+
 type DepthTestFn = fn(u32, u32, PixelAttrs) -> bool;
 type ProcessPixelFn = fn(&RenderingData, &RenderingPolygon, TexCoords, InterpColor) -> InterpColor;
 
@@ -31,9 +36,9 @@ struct RenderingPolygon {
     is_shadow: bool,
     tex_params: TextureParams,
     tex_palette_base: u16,
-    top_y: u8,
-    bot_y: u8,
-    height: u8,
+    top_y: u16,
+    bot_y: u16,
+    height: u16,
     edges: Edges,
     l_vert_i: PolyVertIndex,
     r_vert_i: PolyVertIndex,
@@ -366,10 +371,18 @@ static PROCESS_PIXEL_TEXTURES_DISABLED: [ProcessPixelFn; 4] = [
     process_pixel::<0, 3>,
 ];
 
+/// A software 3D renderer, drawing frames one line at a time.
+///
+/// Frames can be rendered at an integer multiple of the native resolution, in which case each
+/// native line is split into `1 << resolution_scale_shift` lines of `SCREEN_WIDTH <<
+/// resolution_scale_shift` pixels; lines are always numbered at the output resolution.
 pub struct Renderer {
-    color_buffer: Box<[Scanline<Color>; 192]>,
-    depth_buffer: Box<[Scanline<u32, 258>; 194]>,
-    attr_buffer: Box<[Scanline<PixelAttrs, 258>; 194]>,
+    resolution_scale_shift: u8,
+    color_buffer: Box<[Color]>,
+    // Both have an additional line above and below the screen and an additional pixel on each
+    // side of each line, used for edge marking
+    depth_buffer: Box<[u32]>,
+    attr_buffer: Box<[PixelAttrs]>,
     polys: Vec<RenderingPolygon>,
 }
 
@@ -401,244 +414,345 @@ fn depth_test_less_back_facing(a: u32, b: u32, _: PixelAttrs) -> bool {
     a < b
 }
 
-impl Renderer {
-    pub fn new() -> Self {
-        Renderer {
-            color_buffer: unsafe { Box::new_zeroed().assume_init() },
-            depth_buffer: unsafe { Box::new_zeroed().assume_init() },
-            attr_buffer: unsafe { Box::new_zeroed().assume_init() },
-            polys: Vec::with_capacity(2048),
-        }
-    }
+fn setup_polys(
+    polys: &mut Vec<RenderingPolygon>,
+    rendering_data: &RenderingData,
+    resolution_scale_shift: u8,
+) {
+    polys.clear();
 
-    pub fn start_frame(&mut self, rendering_data: &RenderingData) {
-        self.polys.clear();
+    for poly_addr in 0..rendering_data.poly_ram_level {
+        let poly_addr = unsafe { PolyAddr::new_unchecked(poly_addr) };
+        let poly = &rendering_data.poly_ram[poly_addr.get() as usize];
+        let verts_len = unsafe { poly.attrs.verts_len() };
 
-        for poly_addr in 0..rendering_data.poly_ram_level {
-            let poly_addr = unsafe { PolyAddr::new_unchecked(poly_addr) };
-            let poly = &rendering_data.poly_ram[poly_addr.get() as usize];
-            let verts_len = unsafe { poly.attrs.verts_len() };
+        if verts_len.get() < 3 {
+            continue;
+        }
 
-            if verts_len.get() < 3 {
-                continue;
+        let depth_test: fn(u32, u32, PixelAttrs) -> bool = if poly.attrs.depth_test_equal() {
+            if rendering_data.w_buffering {
+                depth_test_equal_w
+            } else {
+                depth_test_equal_z
             }
+        } else if poly.attrs.is_front_facing() {
+            depth_test_less_front_facing
+        } else {
+            depth_test_less_back_facing
+        };
 
-            let depth_test: fn(u32, u32, PixelAttrs) -> bool = if poly.attrs.depth_test_equal() {
-                if rendering_data.w_buffering {
-                    depth_test_equal_w
-                } else {
-                    depth_test_equal_z
-                }
-            } else if poly.attrs.is_front_facing() {
-                depth_test_less_front_facing
+        let is_shadow = poly.attrs.mode() == 3;
+        let process_pixel = {
+            let mode = if is_shadow {
+                // TODO: Do process shadow/shadow mask polygons
+                continue;
+                // if poly.attrs.id() == 0 {
+                //     // TODO: Shadow mask polygons
+                // }
+                // 1
             } else {
-                depth_test_less_back_facing
-            };
-
-            let is_shadow = poly.attrs.mode() == 3;
-            let process_pixel = {
-                let mode = if is_shadow {
-                    // TODO: Do process shadow/shadow mask polygons
-                    continue;
-                    // if poly.attrs.id() == 0 {
-                    //     // TODO: Shadow mask polygons
-                    // }
-                    // 1
-                } else {
-                    match poly.attrs.mode() {
-                        2 => 2 + rendering_data.control.highlight_shading_enabled() as u8,
-                        mode => mode,
-                    }
-                };
-                if rendering_data.control.texture_mapping_enabled() {
-                    PROCESS_PIXEL_TEXTURES_ENABLED[(mode << 3 | poly.tex_params.format()) as usize]
-                } else {
-                    PROCESS_PIXEL_TEXTURES_DISABLED[mode as usize]
+                match poly.attrs.mode() {
+                    2 => 2 + rendering_data.control.highlight_shading_enabled() as u8,
+                    mode => mode,
                 }
             };
+            if rendering_data.control.texture_mapping_enabled() {
+                PROCESS_PIXEL_TEXTURES_ENABLED[(mode << 3 | poly.tex_params.format()) as usize]
+            } else {
+                PROCESS_PIXEL_TEXTURES_DISABLED[mode as usize]
+            }
+        };
 
-            let top_y = poly.top_y;
-            let bot_y = poly.bot_y;
+        let (top_y, bot_y) = if resolution_scale_shift == 0 {
+            (poly.top_y as u16, poly.bot_y as u16)
+        } else {
+            poly.verts[..verts_len.get() as usize].iter().fold(
+                (u16::MAX, 0),
+                |(top_y, bot_y), vert_addr| {
+                    let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
+                    let y = scaled_coords(vert, resolution_scale_shift)[1];
+                    (top_y.min(y), bot_y.max(y))
+                },
+            )
+        };
+
+        if top_y == bot_y {
+            let mut top_i = PolyVertIndex::new(0);
+            let mut bot_i = top_i;
+            let mut top_vert_addr = poly.verts[0];
+            let mut top_vert = &rendering_data.vert_ram[top_vert_addr.get() as usize];
+            let mut top_x = scaled_coords(top_vert, resolution_scale_shift)[0];
+            let mut bot_vert_addr = top_vert_addr;
+            let mut bot_vert = top_vert;
+            let mut bot_x = top_x;
+
+            macro_rules! vert {
+                ($i: expr) => {{
+                    let i = $i;
+                    let vert_addr = poly.verts[i.get() as usize];
+                    let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
+                    let x = scaled_coords(vert, resolution_scale_shift)[0];
+                    if x < top_x {
+                        top_i = i;
+                        top_vert_addr = vert_addr;
+                        top_vert = vert;
+                        top_x = x;
+                    }
+                    if x > bot_x {
+                        bot_i = i;
+                        bot_vert_addr = vert_addr;
+                        bot_vert = vert;
+                        bot_x = x;
+                    }
+                }};
+            }
 
-            if top_y == bot_y {
+            vert!(PolyVertIndex::new(1));
+            vert!(PolyVertIndex::new(verts_len.get() - 1));
+
+            polys.push(RenderingPolygon {
+                poly_addr,
+                attrs: poly.attrs,
+                is_shadow,
+                tex_params: poly.tex_params,
+                tex_palette_base: poly.tex_palette_base,
+                top_y,
+                bot_y,
+                height: 1,
+                alpha: poly.attrs.alpha(),
+                id: poly.attrs.id(),
+                edges: Edges::Dummy([
+                    DummyEdge::new(poly, top_i, top_vert_addr, top_vert, resolution_scale_shift),
+                    DummyEdge::new(poly, bot_i, bot_vert_addr, bot_vert, resolution_scale_shift),
+                ]),
+                l_vert_i: top_i,
+                r_vert_i: bot_i,
+                bot_i,
+                depth_test,
+                process_pixel,
+            });
+        } else {
+            let (top_i, top_vert_addr, top_vert, bot_i) = unsafe {
                 let mut top_i = PolyVertIndex::new(0);
                 let mut bot_i = top_i;
-                let mut top_vert_addr = poly.verts[0];
-                let mut top_vert = &rendering_data.vert_ram[top_vert_addr.get() as usize];
-                let mut bot_vert_addr = top_vert_addr;
-                let mut bot_vert = top_vert;
-
-                macro_rules! vert {
-                    ($i: expr) => {{
-                        let i = $i;
-                        let vert_addr = poly.verts[i.get() as usize];
-                        let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
-                        if vert.coords[0] < top_vert.coords[0] {
-                            top_i = i;
-                            top_vert_addr = vert_addr;
-                            top_vert = vert;
-                        }
-                        if vert.coords[0] > bot_vert.coords[0] {
-                            bot_i = i;
-                            bot_vert_addr = vert_addr;
-                            bot_vert = vert;
-                        }
-                    }};
-                }
-
-                vert!(PolyVertIndex::new(1));
-                vert!(PolyVertIndex::new(verts_len.get() - 1));
-
-                self.polys.push(RenderingPolygon {
-                    poly_addr,
-                    attrs: poly.attrs,
-                    is_shadow,
-                    tex_params: poly.tex_params,
-                    tex_palette_base: poly.tex_palette_base,
-                    top_y: poly.top_y,
-                    bot_y: poly.bot_y,
-                    height: 1,
-                    alpha: poly.attrs.alpha(),
-                    id: poly.attrs.id(),
-                    edges: Edges::Dummy([
-                        DummyEdge::new(poly, top_i, top_vert_addr, top_vert),
-                        DummyEdge::new(poly, bot_i, bot_vert_addr, bot_vert),
-                    ]),
-                    l_vert_i: top_i,
-                    r_vert_i: bot_i,
-                    bot_i,
-                    depth_test,
-                    process_pixel,
-                });
-            } else {
-                let (top_i, top_vert_addr, top_vert, bot_i) = unsafe {
-                    let mut top_i = PolyVertIndex::new(0);
-                    let mut bot_i = top_i;
-                    let mut top_vert = None;
-                    for i in 0..verts_len.get() as usize {
-                        let i = PolyVertIndex::new(i as u8);
-                        let vert_addr = poly.verts[i.get() as usize];
-                        let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
-                        if vert.coords[1] as u8 == top_y && top_vert.is_none() {
-                            top_i = i;
-                            top_vert = Some((vert_addr, vert));
-                        }
-                        if vert.coords[1] as u8 == bot_y {
-                            bot_i = i;
-                        }
+                let mut top_vert = None;
+                for i in 0..verts_len.get() as usize {
+                    let i = PolyVertIndex::new(i as u8);
+                    let vert_addr = poly.verts[i.get() as usize];
+                    let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
+                    let y = scaled_coords(vert, resolution_scale_shift)[1];
+                    if y == top_y && top_vert.is_none() {
+                        top_i = i;
+                        top_vert = Some((vert_addr, vert));
+                    }
+                    if y == bot_y {
+                        bot_i = i;
                     }
-                    let (top_vert_addr, top_vert) = top_vert.unwrap_unchecked();
-                    (top_i, top_vert_addr, top_vert, bot_i)
-                };
-
-                macro_rules! vert {
-                    ($i: expr) => {{
-                        let i = $i;
-                        let addr = poly.verts[i.get() as usize];
-                        (i, addr, &rendering_data.vert_ram[addr.get() as usize])
-                    }};
                 }
+                let (top_vert_addr, top_vert) = top_vert.unwrap_unchecked();
+                (top_i, top_vert_addr, top_vert, bot_i)
+            };
 
-                let mut other_verts = [
-                    vert!(inc_poly_vert_index(top_i, verts_len)),
-                    vert!(dec_poly_vert_index(top_i, verts_len)),
-                ];
+            macro_rules! vert {
+                ($i: expr) => {{
+                    let i = $i;
+                    let addr = poly.verts[i.get() as usize];
+                    (i, addr, &rendering_data.vert_ram[addr.get() as usize])
+                }};
+            }
 
-                if !poly.attrs.is_front_facing() {
-                    other_verts.swap(0, 1);
-                }
+            let mut other_verts = [
+                vert!(inc_poly_vert_index(top_i, verts_len)),
+                vert!(dec_poly_vert_index(top_i, verts_len)),
+            ];
 
-                self.polys.push(RenderingPolygon {
-                    poly_addr,
-                    attrs: poly.attrs,
-                    is_shadow,
-                    tex_params: poly.tex_params,
-                    tex_palette_base: poly.tex_palette_base,
-                    top_y: poly.top_y,
-                    bot_y: poly.bot_y,
-                    height: poly.bot_y - poly.top_y,
-                    alpha: poly.attrs.alpha(),
-                    id: poly.attrs.id(),
-                    edges: Edges::Normal([
-                        Edge::new(
-                            poly,
-                            top_i,
-                            top_vert_addr,
-                            top_vert,
-                            other_verts[0].0,
-                            other_verts[0].1,
-                            other_verts[0].2,
-                        ),
-                        Edge::new(
-                            poly,
-                            top_i,
-                            top_vert_addr,
-                            top_vert,
-                            other_verts[1].0,
-                            other_verts[1].1,
-                            other_verts[1].2,
-                        ),
-                    ]),
-                    l_vert_i: other_verts[0].0,
-                    r_vert_i: other_verts[1].0,
-                    bot_i,
-                    depth_test,
-                    process_pixel,
-                });
+            if !poly.attrs.is_front_facing() {
+                other_verts.swap(0, 1);
             }
+
+            polys.push(RenderingPolygon {
+                poly_addr,
+                attrs: poly.attrs,
+                is_shadow,
+                tex_params: poly.tex_params,
+                tex_palette_base: poly.tex_palette_base,
+                top_y,
+                bot_y,
+                height: bot_y - top_y,
+                alpha: poly.attrs.alpha(),
+                id: poly.attrs.id(),
+                edges: Edges::Normal([
+                    Edge::new(
+                        poly,
+                        top_i,
+                        top_vert_addr,
+                        top_vert,
+                        other_verts[0].0,
+                        other_verts[0].1,
+                        other_verts[0].2,
+                        resolution_scale_shift,
+                    ),
+                    Edge::new(
+                        poly,
+                        top_i,
+                        top_vert_addr,
+                        top_vert,
+                        other_verts[1].0,
+                        other_verts[1].1,
+                        other_verts[1].2,
+                        resolution_scale_shift,
+                    ),
+                ]),
+                l_vert_i: other_verts[0].0,
+                r_vert_i: other_verts[1].0,
+                bot_i,
+                depth_test,
+                process_pixel,
+            });
         }
+    }
+}
+
+/// The per-frame polygon setup, which only depends on the frame's rendering data; it can be done
+/// once and shared by several [`Renderer`]s drawing different lines of the same frame.
+#[derive(Clone)]
+pub struct FrameSetup {
+    resolution_scale_shift: u8,
+    polys: Vec<RenderingPolygon>,
+}
 
+impl FrameSetup {
+    pub fn new(resolution_scale_shift: u8) -> Self {
+        FrameSetup {
+            resolution_scale_shift: resolution_scale_shift.min(MAX_RESOLUTION_SCALE_SHIFT),
+            polys: Vec::with_capacity(2048),
+        }
+    }
+
+    pub fn prepare(&mut self, rendering_data: &RenderingData) {
+        setup_polys(&mut self.polys, rendering_data, self.resolution_scale_shift);
+    }
+}
+
+impl Default for FrameSetup {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::with_resolution_scale_shift(0)
+    }
+
+    /// Creates a renderer drawing frames at `1 << resolution_scale_shift` times the native
+    /// resolution; the shift is clamped to [`MAX_RESOLUTION_SCALE_SHIFT`].
+    pub fn with_resolution_scale_shift(resolution_scale_shift: u8) -> Self {
+        let resolution_scale_shift = resolution_scale_shift.min(MAX_RESOLUTION_SCALE_SHIFT);
+        let width = SCREEN_WIDTH << resolution_scale_shift;
+        let height = SCREEN_HEIGHT << resolution_scale_shift;
+        Renderer {
+            resolution_scale_shift,
+            color_buffer: vec![Color::splat(0); width * height].into_boxed_slice(),
+            depth_buffer: vec![0; (width + 2) * (height + 2)].into_boxed_slice(),
+            attr_buffer: vec![PixelAttrs(0); (width + 2) * (height + 2)].into_boxed_slice(),
+            polys: Vec::with_capacity(2048),
+        }
+    }
+
+    #[inline]
+    pub fn resolution_scale_shift(&self) -> u8 {
+        self.resolution_scale_shift
+    }
+
+    /// The width of each output line, in pixels.
+    #[inline]
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH << self.resolution_scale_shift
+    }
+
+    /// The number of output lines in a frame.
+    #[inline]
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT << self.resolution_scale_shift
+    }
+
+    pub fn start_frame(&mut self, rendering_data: &RenderingData) {
+        setup_polys(&mut self.polys, rendering_data, self.resolution_scale_shift);
+        self.clear_outside_lines(rendering_data);
+    }
+
+    /// Starts rendering a frame using polygon setup data already prepared for it, instead of
+    /// preparing it again.
+    pub fn start_frame_with_setup(&mut self, setup: &FrameSetup, rendering_data: &RenderingData) {
+        debug_assert_eq!(setup.resolution_scale_shift, self.resolution_scale_shift);
+        self.polys.clone_from(&setup.polys);
+        self.clear_outside_lines(rendering_data);
+    }
+
+    fn clear_outside_lines(&mut self, rendering_data: &RenderingData) {
         // The bitmap rear plane's out-of-screen pixels get the same depth and attributes as a
         // non-bitmap rear plane (but the fog flag isn't copied since it's unneeded)
         let outside_pixel_attrs = PixelAttrs(0).with_opaque_poly_id(rendering_data.clear_poly_id);
-        self.depth_buffer[0].0.fill(rendering_data.clear_depth);
-        self.depth_buffer[193].0.fill(rendering_data.clear_depth);
-        self.attr_buffer[0].0.fill(outside_pixel_attrs);
-        self.attr_buffer[193].0.fill(outside_pixel_attrs);
+        let full_width = self.width() + 2;
+        let last_line_start = (self.height() + 1) * full_width;
+        self.depth_buffer[..full_width].fill(rendering_data.clear_depth);
+        self.depth_buffer[last_line_start..].fill(rendering_data.clear_depth);
+        self.attr_buffer[..full_width].fill(outside_pixel_attrs);
+        self.attr_buffer[last_line_start..].fill(outside_pixel_attrs);
     }
 
-    pub fn render_line(&mut self, y: u8, rendering_data: &RenderingData) {
-        let color_line = &mut self.color_buffer[y as usize].0;
-        let depth_full_line = &mut self.depth_buffer[y as usize + 1].0;
-        let attr_full_line = &mut self.attr_buffer[y as usize + 1].0;
+    pub fn render_line(&mut self, y: u16, rendering_data: &RenderingData) {
+        let resolution_scale_shift = self.resolution_scale_shift;
+        let width = self.width();
+        let full_width = width + 2;
+        let color_line = &mut self.color_buffer[y as usize * width..][..width];
+        let depth_full_line = &mut self.depth_buffer[(y as usize + 1) * full_width..][..full_width];
+        let attr_full_line = &mut self.attr_buffer[(y as usize + 1) * full_width..][..full_width];
 
         if rendering_data.control.rear_plane_bitmap_enabled() {
-            let line_base = (y.wrapping_add(rendering_data.clear_image_offset[1]) as usize) << 9;
-            let mut x_in_image = rendering_data.clear_image_offset[0];
+            let line_base = (((y >> resolution_scale_shift) as u8)
+                .wrapping_add(rendering_data.clear_image_offset[1])
+                as usize)
+                << 9;
+            let x_in_image = |x: usize| {
+                rendering_data.clear_image_offset[0]
+                    .wrapping_add((x >> resolution_scale_shift) as u8) as usize
+            };
 
             let color_line_base = 0x4_0000 | line_base;
-            for dst in &mut *color_line {
+            for (x, dst) in color_line.iter_mut().enumerate() {
                 let raw_color = rendering_data
                     .texture
-                    .read_le(color_line_base | (x_in_image as usize) << 1);
+                    .read_le(color_line_base | x_in_image(x) << 1);
                 *dst = rgb5_to_rgb6(decode_rgb5(
                     raw_color,
                     if raw_color >> 15 != 0 { 31 } else { 0 },
                 ))
                 .cast();
-                x_in_image = x_in_image.wrapping_add(1);
             }
 
             let depth_line_base = 0x4_0000 | line_base;
             let pixel_attrs = PixelAttrs(0).with_opaque_poly_id(rendering_data.clear_poly_id);
-            for (dst_depth, dst_attrs) in depth_full_line[1..257]
+            for (x, (dst_depth, dst_attrs)) in depth_full_line[1..=width]
                 .iter_mut()
-                .zip(&mut attr_full_line[1..257])
+                .zip(&mut attr_full_line[1..=width])
+                .enumerate()
             {
                 let raw_depth = rendering_data
                     .texture
-                    .read_le(depth_line_base | (x_in_image as usize) << 1);
+                    .read_le(depth_line_base | x_in_image(x) << 1);
                 *dst_depth = expand_depth(raw_depth);
                 *dst_attrs = pixel_attrs.with_fog_enabled(raw_depth >> 15 != 0);
-                x_in_image = x_in_image.wrapping_add(1);
             }
 
             // The bitmap rear plane's out-of-screen pixels get the same depth and attributes as a
             // non-bitmap rear plane (but the fog flag isn't copied since it's unneeded)
             depth_full_line[0] = rendering_data.clear_depth;
-            depth_full_line[257] = rendering_data.clear_depth;
+            depth_full_line[width + 1] = rendering_data.clear_depth;
             attr_full_line[0] = pixel_attrs;
-            attr_full_line[257] = pixel_attrs;
+            attr_full_line[width + 1] = pixel_attrs;
         } else {
             color_line.fill(rgb5_to_rgb6(rendering_data.clear_color.cast()).cast());
             depth_full_line.fill(rendering_data.clear_depth);
@@ -649,8 +763,8 @@ impl Renderer {
             );
         }
 
-        let depth_line = <&mut [_; 256]>::try_from(&mut depth_full_line[1..257]).unwrap();
-        let attr_line = <&mut [_; 256]>::try_from(&mut attr_full_line[1..257]).unwrap();
+        let depth_line = &mut depth_full_line[1..=width];
+        let attr_line = &mut attr_full_line[1..=width];
 
         for poly in self.polys.iter_mut() {
             if y.wrapping_sub(poly.top_y) >= poly.height {
@@ -684,7 +798,9 @@ impl Renderer {
                                     let vert_addr = raw_poly.verts[i.get() as usize];
                                     let vert = &rendering_data.vert_ram[vert_addr.get() as usize];
 
-                                    if vert.coords[1] as u8 > y || i == poly.bot_i {
+                                    if scaled_coords(vert, resolution_scale_shift)[1] > y
+                                        || i == poly.bot_i
+                                    {
                                         $edge = Edge::new(
                                             &raw_poly,
                                             prev_i,
@@ -693,6 +809,7 @@ impl Renderer {
                                             i,
                                             vert_addr,
                                             vert,
+                                            resolution_scale_shift,
                                         );
                                         $vert_i = i;
                                         break;
@@ -833,9 +950,9 @@ impl Renderer {
             for i in 0..2 {
                 if fill_edges[i] {
                     // If the range is out-of-screen don't render it
-                    let (start, end) = clip_x_range(ranges[i]);
+                    let (start, end) = clip_x_range(ranges[i], width as u16);
                     for x in start..=end {
-                        render_pixel!(x as u16, true);
+                        render_pixel!(x, true);
                     }
                 }
             }
@@ -848,13 +965,13 @@ impl Renderer {
         }
     }
 
-    pub fn postprocess_line(
-        &mut self,
-        y: u8,
-        scanline: &mut Scanline<u32>,
-        rendering_data: &RenderingData,
-    ) {
-        let color_line = &mut self.color_buffer[y as usize].0;
+    /// Applies edge marking and fog to the specified line and writes it to `output`, which has to
+    /// be [`width`](Self::width) pixels long; the line below it has to be rendered first if edge
+    /// marking is enabled.
+    pub fn postprocess_line(&mut self, y: u16, output: &mut [u32], rendering_data: &RenderingData) {
+        let width = self.width();
+        let full_width = width + 2;
+        let color_line = &mut self.color_buffer[y as usize * width..][..width];
 
         if rendering_data.control.edge_marking_enabled() {
             let y_ = y as usize + 1;
@@ -862,18 +979,19 @@ impl Renderer {
             for (x, color_dst) in color_line.iter_mut().enumerate() {
                 let x_ = x + 1;
 
-                let attrs = self.attr_buffer[y_].0[x_];
+                let attrs = self.attr_buffer[y_ * full_width + x_];
                 if !attrs.is_opaque_edge() {
                     continue;
                 }
 
                 let opaque_poly_id = attrs.opaque_poly_id();
-                let depth = self.depth_buffer[y_].0[x_];
+                let depth = self.depth_buffer[y_ * full_width + x_];
 
                 macro_rules! has_edge {
                     ($x: expr, $y: expr) => {
-                        (depth < self.depth_buffer[$y].0[$x]
-                            && self.attr_buffer[$y].0[$x].opaque_poly_id() != opaque_poly_id)
+                        (depth < self.depth_buffer[$y * full_width + $x]
+                            && self.attr_buffer[$y * full_width + $x].opaque_poly_id()
+                                != opaque_poly_id)
                     };
                 }
 
@@ -892,10 +1010,8 @@ impl Renderer {
             }
         }
 
-        let depth_line =
-            <&mut [_; 256]>::try_from(&mut self.depth_buffer[y as usize + 1].0[1..257]).unwrap();
-        let attr_line =
-            <&mut [_; 256]>::try_from(&mut self.attr_buffer[y as usize + 1].0[1..257]).unwrap();
+        let depth_line = &self.depth_buffer[(y as usize + 1) * full_width + 1..][..width];
+        let attr_line = &self.attr_buffer[(y as usize + 1) * full_width + 1..][..width];
 
         if rendering_data.control.fog_enabled() {
             macro_rules! fog_density {
@@ -918,7 +1034,7 @@ impl Renderer {
 
             if rendering_data.control.fog_only_alpha() {
                 let fog_alpha = rendering_data.fog_color[3] as u16;
-                for x in 0..256 {
+                for x in 0..width {
                     let attrs = attr_line[x];
                     if !attrs.fog_enabled() {
                         continue;
@@ -930,7 +1046,7 @@ impl Renderer {
                 }
             } else {
                 let fog_color = rgb5_to_rgb6(rendering_data.fog_color.cast());
-                for x in 0..256 {
+                for x in 0..width {
                     let attrs = attr_line[x];
                     if !attrs.fog_enabled() {
                         continue;
@@ -945,7 +1061,7 @@ impl Renderer {
             }
         }
 
-        for (dst, src) in scanline.0.iter_mut().zip(&*color_line) {
+        for (dst, src) in output.iter_mut().zip(&*color_line) {
             let [r, g, b, a] = src.to_array();
             *dst = r as u32 | (g as u32) << 6 | (b as u32) << 12 | (a as u32) << 18
         }
@@ -0,0 +1,351 @@
+use crate::{FrameSetup, Renderer, RenderingData, MAX_RESOLUTION_SCALE_SHIFT};
+use core::{
+    cell::UnsafeCell,
+    hint,
+    num::NonZeroU8,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+use dust_core::{
+    gpu::{
+        engine_3d::{
+            Polygon, RendererTx, RenderingState as CoreRenderingState, ScreenVertex, SoftRendererRx,
+        },
+        Scanline, SCREEN_HEIGHT, SCREEN_WIDTH,
+    },
+    utils::Bytes,
+};
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+
+/// The progress value of a band whose frame has been requested, but not started yet.
+const FRAME_PENDING: u8 = u8::MAX;
+
+struct Band {
+    start: u8,
+    end: u8,
+    /// The number of lines of the band that have been finished in the current frame, or
+    /// [`FRAME_PENDING`].
+    finished_lines: AtomicU8,
+}
+
+impl Band {
+    fn is_done(&self) -> bool {
+        let finished_lines = self.finished_lines.load(Ordering::Acquire);
+        finished_lines != FRAME_PENDING && finished_lines >= self.end - self.start
+    }
+}
+
+struct SharedData {
+    rendering_data: Box<UnsafeCell<RenderingData>>,
+    frame_setup: UnsafeCell<FrameSetup>,
+    resolution_scale_shift: u8,
+    scanline_buffer: Box<UnsafeCell<[Scanline<u32>; SCREEN_HEIGHT]>>,
+    /// The output at the rendering resolution; empty if rendering at the native resolution.
+    hi_res_buffer: UnsafeCell<Box<[u32]>>,
+    bands: Box<[Band]>,
+    line_bands: [u8; SCREEN_HEIGHT],
+    setup_barrier: Barrier,
+    stopped: AtomicBool,
+}
+
+unsafe impl Sync for SharedData {}
+
+impl SharedData {
+    /// The number of pixels rendered for each native scanline.
+    fn hi_res_scanline_len(&self) -> usize {
+        SCREEN_WIDTH << (self.resolution_scale_shift << 1)
+    }
+}
+
+pub struct Tx {
+    shared_data: Arc<SharedData>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl Tx {
+    fn wait_for_frame_end(&self) {
+        for band in self.shared_data.bands.iter() {
+            while !band.is_done() {
+                hint::spin_loop();
+            }
+        }
+    }
+}
+
+impl RendererTx for Tx {
+    fn set_capture_enabled(&mut self, _capture_enabled: bool) {}
+
+    fn swap_buffers(
+        &mut self,
+        vert_ram: &[ScreenVertex],
+        poly_ram: &[Polygon],
+        state: &CoreRenderingState,
+    ) {
+        self.wait_for_frame_end();
+        unsafe { &mut *self.shared_data.rendering_data.get() }.prepare(vert_ram, poly_ram, state);
+    }
+
+    fn repeat_last_frame(&mut self, state: &CoreRenderingState) {
+        self.wait_for_frame_end();
+        unsafe { &mut *self.shared_data.rendering_data.get() }.repeat_last_frame(state);
+    }
+
+    fn start_rendering(
+        &mut self,
+        texture: &Bytes<0x8_0000>,
+        tex_pal: &Bytes<0x1_8000>,
+        state: &CoreRenderingState,
+    ) {
+        unsafe { &mut *self.shared_data.rendering_data.get() }.copy_vram(texture, tex_pal, state);
+
+        for band in self.shared_data.bands.iter() {
+            band.finished_lines.store(FRAME_PENDING, Ordering::Release);
+        }
+        for thread in &self.threads {
+            thread.thread().unpark();
+        }
+    }
+
+    fn skip_rendering(&mut self) {}
+}
+
+impl Drop for Tx {
+    fn drop(&mut self) {
+        self.shared_data.stopped.store(true, Ordering::Relaxed);
+        for thread in &self.threads {
+            thread.thread().unpark();
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        // Release any receiver still waiting for a line
+        for band in self.shared_data.bands.iter() {
+            band.finished_lines
+                .store(band.end - band.start, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Rx {
+    next_scanline: u8,
+    shared_data: Arc<SharedData>,
+}
+
+impl Rx {
+    fn wait_for_line(&self, line: u8) {
+        let band = &self.shared_data.bands[self.shared_data.line_bands[line as usize] as usize];
+        while {
+            let finished_lines = band.finished_lines.load(Ordering::Acquire);
+            finished_lines == FRAME_PENDING || band.start + finished_lines <= line
+        } {
+            hint::spin_loop();
+        }
+    }
+}
+
+impl SoftRendererRx for Rx {
+    fn start_frame(&mut self) {
+        self.next_scanline = 0;
+    }
+
+    fn read_scanline(&mut self) -> &Scanline<u32> {
+        self.wait_for_line(self.next_scanline);
+        let result =
+            unsafe { &(&*self.shared_data.scanline_buffer.get())[self.next_scanline as usize] };
+        self.next_scanline += 1;
+        result
+    }
+
+    fn skip_scanline(&mut self) {
+        self.next_scanline += 1;
+    }
+
+    fn resolution_scale_shift(&self) -> u8 {
+        self.shared_data.resolution_scale_shift
+    }
+
+    fn last_hi_res_scanline(&self) -> &[u32] {
+        let line = self.next_scanline.saturating_sub(1) as usize;
+        if self.shared_data.resolution_scale_shift == 0 {
+            return unsafe { &(&*self.shared_data.scanline_buffer.get())[line].0 };
+        }
+        let len = self.shared_data.hi_res_scanline_len();
+        unsafe { &(&*self.shared_data.hi_res_buffer.get())[line * len..][..len] }
+    }
+}
+
+fn render_band(
+    renderer: &mut Renderer,
+    shared_data: &SharedData,
+    band_index: usize,
+    rendering_data: &RenderingData,
+) {
+    let band = &shared_data.bands[band_index];
+    let resolution_scale_shift = shared_data.resolution_scale_shift;
+    let start = (band.start as u16) << resolution_scale_shift;
+    let end = (band.end as u16) << resolution_scale_shift;
+
+    // Edge marking needs the depth and attributes of the lines right above and below each pixel,
+    // so the neighboring bands' boundary lines have to be rendered too
+    let (render_start, render_end) = if rendering_data.control.edge_marking_enabled() {
+        (
+            start.saturating_sub(1),
+            (end + 1).min(renderer.height() as u16),
+        )
+    } else {
+        (start, end)
+    };
+
+    for y in render_start..=start {
+        renderer.render_line(y, rendering_data);
+    }
+    let width = renderer.width();
+    for y in start..end {
+        if y + 1 < render_end {
+            renderer.render_line(y + 1, rendering_data);
+        }
+        let native_y = (y >> resolution_scale_shift) as u8;
+        let scanline = &mut unsafe { &mut *shared_data.scanline_buffer.get() }[native_y as usize];
+        if resolution_scale_shift == 0 {
+            renderer.postprocess_line(y, &mut scanline.0, rendering_data);
+        } else {
+            let hi_res_line = &mut unsafe { &mut *shared_data.hi_res_buffer.get() }
+                [y as usize * width..][..width];
+            renderer.postprocess_line(y, hi_res_line, rendering_data);
+            let sub_line_mask = (1 << resolution_scale_shift) - 1;
+            if y & sub_line_mask == 0 {
+                // Native scanlines use the top-left sample of each pixel, which is where the
+                // native-resolution rasterizer would sample it too
+                for (dst, src) in scanline
+                    .0
+                    .iter_mut()
+                    .zip(hi_res_line.iter().step_by(1 << resolution_scale_shift))
+                {
+                    *dst = *src;
+                }
+            }
+            if y & sub_line_mask != sub_line_mask {
+                continue;
+            }
+        }
+        band.finished_lines
+            .store(native_y + 1 - band.start, Ordering::Release);
+    }
+}
+
+/// Creates a 3D renderer that renders frames on `thread_count` worker threads, each one drawing a
+/// band of consecutive scanlines, at `1 << resolution_scale_shift` times the native resolution
+/// (up to [`MAX_RESOLUTION_SCALE_SHIFT`]).
+///
+/// Polygon setup is done once per frame by the first thread and shared by the others; edge
+/// marking, fog and anti-aliasing are then applied by each thread to its own band.
+///
+/// When rendering at a higher resolution, the enlarged output is available through
+/// [`SoftRendererRx::last_hi_res_scanline`], while [`SoftRendererRx::read_scanline`] keeps
+/// returning native-resolution scanlines.
+pub fn init(thread_count: NonZeroU8, resolution_scale_shift: u8) -> (Tx, Rx) {
+    let thread_count = thread_count.get().min(SCREEN_HEIGHT as u8);
+    let resolution_scale_shift = resolution_scale_shift.min(MAX_RESOLUTION_SCALE_SHIFT);
+    let bands = (0..thread_count)
+        .map(|i| {
+            let start = (SCREEN_HEIGHT * i as usize / thread_count as usize) as u8;
+            let end = (SCREEN_HEIGHT * (i as usize + 1) / thread_count as usize) as u8;
+            Band {
+                start,
+                end,
+                finished_lines: AtomicU8::new(end - start),
+            }
+        })
+        .collect::<Box<[_]>>();
+    let mut line_bands = [0; SCREEN_HEIGHT];
+    for (i, band) in bands.iter().enumerate() {
+        line_bands[band.start as usize..band.end as usize].fill(i as u8);
+    }
+
+    let shared_data = Arc::new(unsafe {
+        SharedData {
+            rendering_data: Box::new_zeroed().assume_init(),
+            frame_setup: UnsafeCell::new(FrameSetup::new(resolution_scale_shift)),
+            resolution_scale_shift,
+            scanline_buffer: Box::new_zeroed().assume_init(),
+            hi_res_buffer: UnsafeCell::new(if resolution_scale_shift == 0 {
+                Box::new([])
+            } else {
+                vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT) << (resolution_scale_shift << 1)]
+                    .into_boxed_slice()
+            }),
+            bands,
+            line_bands,
+            setup_barrier: Barrier::new(thread_count as usize),
+            stopped: AtomicBool::new(false),
+        }
+    });
+    let rx = Rx {
+        next_scanline: 0,
+        shared_data: Arc::clone(&shared_data),
+    };
+
+    let threads = (0..thread_count as usize)
+        .map(|band_index| {
+            let shared_data = Arc::clone(&shared_data);
+            thread::Builder::new()
+                .name(if thread_count == 1 {
+                    "3D rendering".to_owned()
+                } else {
+                    format!("3D rendering {band_index}")
+                })
+                .spawn(move || {
+                    let mut raw_renderer =
+                        Renderer::with_resolution_scale_shift(shared_data.resolution_scale_shift);
+                    let band = &shared_data.bands[band_index];
+                    loop {
+                        // Pending frames are always finished before stopping, as the other
+                        // threads could be waiting for this one at the barrier
+                        if band
+                            .finished_lines
+                            .compare_exchange(
+                                FRAME_PENDING,
+                                0,
+                                Ordering::Acquire,
+                                Ordering::Acquire,
+                            )
+                            .is_ok()
+                        {
+                            let rendering_data = unsafe { &*shared_data.rendering_data.get() };
+                            if band_index == 0 {
+                                unsafe { &mut *shared_data.frame_setup.get() }
+                                    .prepare(rendering_data);
+                            }
+                            shared_data.setup_barrier.wait();
+                            raw_renderer.start_frame_with_setup(
+                                unsafe { &*shared_data.frame_setup.get() },
+                                rendering_data,
+                            );
+                            render_band(
+                                &mut raw_renderer,
+                                &shared_data,
+                                band_index,
+                                rendering_data,
+                            );
+                        } else if shared_data.stopped.load(Ordering::Relaxed) {
+                            return;
+                        } else {
+                            thread::park();
+                        }
+                    }
+                })
+                .expect("couldn't spawn 3D rendering thread")
+        })
+        .collect();
+
+    (
+        Tx {
+            shared_data,
+            threads,
+        },
+        rx,
+    )
+}
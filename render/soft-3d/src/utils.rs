@@ -8,14 +8,25 @@ use dust_core::gpu::engine_3d::{
     InterpColor, PolyVertIndex, PolyVertsLen, Polygon, ScreenVertex, TexCoords, VertexAddr,
 };
 
-pub fn clip_x_range((start, end): (u16, u16)) -> (u8, u8) {
-    if start > 255 {
-        (255, 0)
+pub fn clip_x_range((start, end): (u16, u16), width: u16) -> (u16, u16) {
+    if start >= width {
+        (width - 1, 0)
     } else {
-        (start as u8, end.min(255) as u8)
+        (start, end.min(width - 1))
     }
 }
 
+/// Returns a vertex's screen coordinates at the given resolution scale, using its subpixel
+/// coordinates if available.
+#[inline]
+pub fn scaled_coords(vert: &ScreenVertex, resolution_scale_shift: u8) -> [u16; 2] {
+    #[cfg(feature = "hi-res-coords")]
+    if resolution_scale_shift != 0 {
+        return (vert.hi_res_coords >> (4 - resolution_scale_shift) as u16).to_array();
+    }
+    (vert.coords << resolution_scale_shift as u16).to_array()
+}
+
 #[inline]
 pub fn expand_depth(depth: u16) -> u32 {
     let depth = depth as u32;
@@ -68,12 +79,12 @@ pub fn dec_poly_vert_index(i: PolyVertIndex, verts: PolyVertsLen) -> PolyVertInd
 #[derive(Clone, Copy, Debug)]
 pub struct Edge {
     a_addr: VertexAddr,
-    a_y: u8,
+    a_y: u16,
     a_z: u32,
     a_w: u16,
 
     b_addr: VertexAddr,
-    b_y: u8,
+    b_y: u16,
     b_z: u32,
     b_w: u16,
 
@@ -89,6 +100,7 @@ pub struct Edge {
 }
 
 impl Edge {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         poly: &Polygon,
         a_i: PolyVertIndex,
@@ -97,18 +109,17 @@ impl Edge {
         b_i: PolyVertIndex,
         b_addr: VertexAddr,
         b: &ScreenVertex,
+        resolution_scale_shift: u8,
     ) -> Self {
         // Slope calculation based on https://github.com/StrikerX3/nds-interp
 
         let a_w = poly.w_values[a_i.get() as usize];
         let b_w = poly.w_values[b_i.get() as usize];
 
-        let a_x = a.coords[0];
-        let b_x = b.coords[0];
-        let a_y = a.coords[1] as u8;
-        let b_y = b.coords[1] as u8;
+        let [a_x, a_y] = scaled_coords(a, resolution_scale_shift);
+        let [b_x, b_y] = scaled_coords(b, resolution_scale_shift);
         let x_diff = b_x as i16 - a_x as i16;
-        let y_len = (b_y - a_y) as u16;
+        let y_len = b_y - a_y;
 
         let mut x_ref = (a_x as u32) << 18;
 
@@ -152,7 +163,7 @@ impl Edge {
             is_x_major,
             is_negative,
 
-            interp_ref: if is_x_major { a_x.min(b_x) } else { a_y as u16 },
+            interp_ref: if is_x_major { a_x.min(b_x) } else { a_y },
             interp_len: if is_x_major { x_len } else { y_len },
             interp_data: InterpLineData::new(a_w, b_w),
         }
@@ -174,7 +185,7 @@ impl Edge {
         self.b_addr
     }
 
-    pub fn b_y(&self) -> u8 {
+    pub fn b_y(&self) -> u16 {
         self.b_y
     }
 
@@ -198,7 +209,7 @@ impl Edge {
         self.is_x_major
     }
 
-    pub fn line_x_range(&self, y: u8) -> (u16, u16) {
+    pub fn line_x_range(&self, y: u16) -> (u16, u16) {
         let line_x_disp = self.x_incr * (y - self.a_y) as u32;
         let start_frac_x = if self.is_negative {
             self.x_ref - line_x_disp
@@ -226,7 +237,7 @@ impl Edge {
         }
     }
 
-    pub fn edge_interp(&self, y: u8, x: u16) -> InterpData<true> {
+    pub fn edge_interp(&self, y: u16, x: u16) -> InterpData<true> {
         self.interp_data.set_x(
             if self.is_x_major {
                 let rel = x - self.interp_ref;
@@ -236,7 +247,7 @@ impl Edge {
                     rel
                 }
             } else {
-                y as u16 - self.interp_ref
+                y - self.interp_ref
             },
             self.interp_len,
         )
@@ -252,8 +263,14 @@ pub struct DummyEdge {
 }
 
 impl DummyEdge {
-    pub fn new(poly: &Polygon, i: PolyVertIndex, addr: VertexAddr, v: &ScreenVertex) -> Self {
-        let x = v.coords[0];
+    pub fn new(
+        poly: &Polygon,
+        i: PolyVertIndex,
+        addr: VertexAddr,
+        v: &ScreenVertex,
+        resolution_scale_shift: u8,
+    ) -> Self {
+        let x = scaled_coords(v, resolution_scale_shift)[0];
         let z = poly.depth_values[i.get() as usize];
         let w = poly.w_values[i.get() as usize];
 
@@ -329,9 +346,9 @@ impl<const EDGE: bool> InterpLineData<EDGE> {
             },
         );
         let p_factor = {
-            let numer = (x as u32 * self.p_w0_numer as u32) << Self::PERSP_PRECISION;
+            let numer = (x as u64 * self.p_w0_numer as u64) << Self::PERSP_PRECISION;
             let denom =
-                x as u32 * self.p_w0_denom as u32 + (len - x) as u32 * self.p_w1_denom as u32;
+                x as u64 * self.p_w0_denom as u64 + (len - x) as u64 * self.p_w1_denom as u64;
             if denom == 0 {
                 // TODO: ???
                 0
@@ -1,4 +1,5 @@
 pub mod gfx;
+pub mod hi_res_capture;
 pub mod render;
 
 pub use dust_soft_2d_base::*;
@@ -43,12 +44,19 @@ impl<R: Role, B: Buffers, D: RenderingData, V: Vram<R>> FnPtrs<R, B, D, V> {
 }
 
 #[derive(Clone, Copy, Default)]
+#[repr(C)]
 #[allow(dead_code)] // These are read from WGSL
 pub struct ScanlineFlags {
     pub master_brightness_control: u32,
     pub color_effects_control: u32,
     pub blend_coeffs: u32,
     pub brightness_coeff: u32,
+    /// For captured scanlines, the capture control value, see [`hi_res_capture`].
+    pub capture_control: u32,
+    /// The VRAM bank line shown in VRAM display mode, or read as capture source B, see
+    /// [`hi_res_capture`].
+    pub vram_line: u32,
+    _padding: [u32; 2],
 }
 
 impl ScanlineFlags {
@@ -60,6 +68,9 @@ impl ScanlineFlags {
             color_effects_control: 0,
             blend_coeffs: 0,
             brightness_coeff: 0,
+            capture_control: 0,
+            vram_line: 0,
+            _padding: [0; 2],
         }
     }
 
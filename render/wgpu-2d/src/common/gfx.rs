@@ -1,8 +1,12 @@
-use super::{BgObjPixel, ScanlineFlags};
+use super::{
+    hi_res_capture::{self, BANK_LINES},
+    BgObjPixel, ScanlineFlags,
+};
 use dust_core::gpu::{engine_3d, Scanline, SCREEN_HEIGHT, SCREEN_WIDTH};
 use emu_utils::{resource_str, triple_buffer};
 use parking_lot::RwLock;
 use std::{
+    mem,
     num::NonZeroU64,
     slice,
     sync::{
@@ -29,7 +33,9 @@ enum Renderer3dRenderThreadData {
 }
 
 enum Renderer3dUpdateGfxThreadData {
-    Soft,
+    Soft {
+        resolution_scale_shift: u8,
+    },
     Accel {
         color_output_view: wgpu::TextureView,
         color_output_view_rx: crossbeam_channel::Receiver<wgpu::TextureView>,
@@ -40,6 +46,7 @@ enum Renderer3dUpdateGfxThreadData {
 enum Renderer3dGfxThreadData {
     Soft {
         color_output_texture: wgpu::Texture,
+        resolution_scale_shift: u8,
     },
     Accel {
         color_output_view_rx: crossbeam_channel::Receiver<wgpu::TextureView>,
@@ -94,6 +101,445 @@ impl OutputAttachments {
     }
 }
 
+const FB_SCANLINE_FLAGS_SIZE: usize = mem::size_of::<[[ScanlineFlags; SCREEN_HEIGHT]; 2]>();
+
+/// The hi-res copies of VRAM banks A-D written by display captures, see [`hi_res_capture`].
+struct HiResCaptureAttachments {
+    bank_views: [wgpu::TextureView; 4],
+    banks: wgpu::Texture,
+    src_b: wgpu::Texture,
+    display_bg: wgpu::BindGroup,
+    capture_bg: wgpu::BindGroup,
+}
+
+impl HiResCaptureAttachments {
+    fn new(
+        device: &wgpu::Device,
+        bg_layout: &wgpu::BindGroupLayout,
+        native_src_b_view: &wgpu::TextureView,
+        dummy_banks_view: &wgpu::TextureView,
+        resolution_scale_shift: u8,
+    ) -> Self {
+        let banks = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D renderer hi-res capture banks"),
+            size: wgpu::Extent3d {
+                width: (SCREEN_WIDTH as u32) << resolution_scale_shift,
+                height: (BANK_LINES as u32) << resolution_scale_shift,
+                depth_or_array_layers: 4,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let banks_view = banks.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("2D renderer hi-res capture banks view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        let bank_views = [0, 1, 2, 3].map(|i| {
+            banks.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("2D renderer hi-res capture bank view"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: i,
+                array_layer_count: Some(1),
+                ..wgpu::TextureViewDescriptor::default()
+            })
+        });
+
+        let src_b = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D renderer hi-res capture source B"),
+            size: wgpu::Extent3d {
+                width: (SCREEN_WIDTH as u32) << resolution_scale_shift,
+                height: (SCREEN_HEIGHT as u32) << resolution_scale_shift,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let src_b_view = src_b.create_view(&Default::default());
+
+        // The capture pass renders to the banks, so it can't sample them at the same time; source
+        // B lines are copied to a separate texture first instead
+        let create_bg = |label: &str, banks_view: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: bg_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(banks_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(native_src_b_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&src_b_view),
+                    },
+                ],
+            })
+        };
+        let display_bg = create_bg("2D renderer hi-res capture display", &banks_view);
+        let capture_bg = create_bg("2D renderer hi-res capture", dummy_banks_view);
+
+        HiResCaptureAttachments {
+            bank_views,
+            banks,
+            src_b,
+            display_bg,
+            capture_bg,
+        }
+    }
+}
+
+/// The state needed to repeat display captures at the rendering resolution, see
+/// [`hi_res_capture`].
+struct HiResCaptureData {
+    fb_texture: wgpu::Texture,
+    scanline_flags: Box<[ScanlineFlags; SCREEN_HEIGHT]>,
+    scanline_flags_buffer: wgpu::Buffer,
+    data_bg: wgpu::BindGroup,
+    src_b_texture: wgpu::Texture,
+    src_b_view: wgpu::TextureView,
+    dummy_banks_view: wgpu::TextureView,
+    bg_layout: wgpu::BindGroupLayout,
+    attachments: HiResCaptureAttachments,
+    resolution_scale_shift: u8,
+    /// Bitmasks of the bank lines whose hi-res copies are up to date with the last capture to them.
+    valid_lines: [[u64; BANK_LINES / 64]; 4],
+}
+
+impl HiResCaptureData {
+    fn new(
+        device: &wgpu::Device,
+        fb_data_bg_layout: &wgpu::BindGroupLayout,
+        resolution_scale_shift: u8,
+    ) -> Self {
+        // Captured scanlines use the same layout as the framebuffer, but only the first screen's
+        // worth of scanline flags
+        let fb_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D renderer capture framebuffer texture"),
+            size: wgpu::Extent3d {
+                width: SCREEN_WIDTH as u32,
+                height: SCREEN_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rg32Uint,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let scanline_flags_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("2D renderer capture scanline flags"),
+            size: FB_SCANLINE_FLAGS_SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let data_bg = GfxThreadData::create_fb_data_bg(
+            device,
+            fb_data_bg_layout,
+            "2D renderer capture framebuffer texture",
+            &fb_texture.create_view(&Default::default()),
+            &scanline_flags_buffer,
+        );
+
+        let src_b_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D renderer capture source B"),
+            size: wgpu::Extent3d {
+                width: SCREEN_WIDTH as u32,
+                height: SCREEN_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R16Uint,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let src_b_view = src_b_texture.create_view(&Default::default());
+
+        let dummy_banks_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("2D renderer dummy capture banks"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 4,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..wgpu::TextureViewDescriptor::default()
+            });
+
+        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("2D renderer hi-res capture"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let attachments = HiResCaptureAttachments::new(
+            device,
+            &bg_layout,
+            &src_b_view,
+            &dummy_banks_view,
+            resolution_scale_shift,
+        );
+
+        HiResCaptureData {
+            fb_texture,
+            scanline_flags: unsafe { Box::new_zeroed().assume_init() },
+            scanline_flags_buffer,
+            data_bg,
+            src_b_texture,
+            src_b_view,
+            dummy_banks_view,
+            bg_layout,
+            attachments,
+            resolution_scale_shift,
+            valid_lines: [[0; BANK_LINES / 64]; 4],
+        }
+    }
+
+    fn set_resolution_scale_shift(&mut self, device: &wgpu::Device, value: u8) {
+        self.resolution_scale_shift = value;
+        self.attachments = HiResCaptureAttachments::new(
+            device,
+            &self.bg_layout,
+            &self.src_b_view,
+            &self.dummy_banks_view,
+            value,
+        );
+        self.invalidate();
+    }
+
+    fn invalidate(&mut self) {
+        self.valid_lines = [[0; BANK_LINES / 64]; 4];
+    }
+
+    /// Removes the hi-res flag from a [`ScanlineFlags::vram_line`] value if the corresponding bank
+    /// line's hi-res copy isn't up to date.
+    fn validate_vram_line(&self, vram_line: u32) -> u32 {
+        if vram_line & hi_res_capture::VRAM_LINE_HI_RES == 0 {
+            return vram_line;
+        }
+        let bank = (vram_line >> 8 & 3) as usize;
+        let line = (vram_line & 0xFF) as usize;
+        if self.resolution_scale_shift != 0
+            && self.valid_lines[bank][line >> 6] & 1 << (line & 63) != 0
+        {
+            vram_line
+        } else {
+            vram_line & !(hi_res_capture::VRAM_LINE_HI_RES | 0x3FF)
+        }
+    }
+
+    /// Repeats the frame's 256-pixel-wide captures at the rendering resolution; needs to run after
+    /// the frame is displayed, as VRAM display reads bank lines before they're overwritten by the
+    /// capture of the same scanline.
+    fn run(
+        &mut self,
+        queue: &wgpu::Queue,
+        pipeline: &wgpu::RenderPipeline,
+        color_output_3d_bg: &wgpu::BindGroup,
+        frame: &FrameData,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let is_captured = |scanline_flags: &ScanlineFlags| {
+            scanline_flags.capture_control & hi_res_capture::CAPTURE_ENABLED != 0
+        };
+        if !frame.capture_scanline_flags.iter().any(is_captured) {
+            return;
+        }
+
+        let resolution_scale_shift = self.resolution_scale_shift;
+        let hi_res_width = (SCREEN_WIDTH as u32) << resolution_scale_shift;
+
+        self.scanline_flags
+            .copy_from_slice(&*frame.capture_scanline_flags);
+        for line in 0..SCREEN_HEIGHT {
+            let scanline_flags = self.scanline_flags[line];
+            if !is_captured(&scanline_flags) {
+                continue;
+            }
+            let vram_line = self.validate_vram_line(scanline_flags.vram_line);
+            self.scanline_flags[line].vram_line = vram_line;
+            if vram_line & hi_res_capture::VRAM_LINE_HI_RES != 0 {
+                command_encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.attachments.banks,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: (vram_line & 0xFF) << resolution_scale_shift,
+                            z: vram_line >> 8 & 3,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &self.attachments.src_b,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: (line as u32) << resolution_scale_shift,
+                            z: 0,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: hi_res_width,
+                        height: 1 << resolution_scale_shift,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        queue.write_texture(
+            self.fb_texture.as_image_copy(),
+            unsafe {
+                slice::from_raw_parts(
+                    frame.capture_framebuffer.as_ptr() as *const u8,
+                    SCREEN_WIDTH * SCREEN_HEIGHT * 8,
+                )
+            },
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some((SCREEN_WIDTH * 8) as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: SCREEN_WIDTH as u32,
+                height: SCREEN_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.write_texture(
+            self.src_b_texture.as_image_copy(),
+            unsafe {
+                slice::from_raw_parts(
+                    frame.capture_src_b.as_ptr() as *const u8,
+                    SCREEN_WIDTH * SCREEN_HEIGHT * 2,
+                )
+            },
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some((SCREEN_WIDTH * 2) as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: SCREEN_WIDTH as u32,
+                height: SCREEN_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.write_buffer(&self.scanline_flags_buffer, 0, unsafe {
+            slice::from_raw_parts(
+                self.scanline_flags.as_ptr() as *const u8,
+                mem::size_of::<[ScanlineFlags; SCREEN_HEIGHT]>(),
+            )
+        });
+
+        for bank in 0..4 {
+            let is_captured_to_bank = |scanline_flags: &ScanlineFlags| {
+                is_captured(scanline_flags)
+                    && (scanline_flags.capture_control >> 16 & 3) as usize == bank
+            };
+            if !self.scanline_flags.iter().any(is_captured_to_bank) {
+                continue;
+            }
+
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("2D renderer capture render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.attachments.bank_views[bank],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_bind_group(0, &self.data_bg, &[]);
+            render_pass.set_bind_group(1, color_output_3d_bg, &[]);
+            render_pass.set_bind_group(2, &self.attachments.capture_bg, &[]);
+            render_pass.set_pipeline(pipeline);
+
+            let mut line = 0;
+            while line < SCREEN_HEIGHT {
+                if !is_captured_to_bank(&self.scanline_flags[line]) {
+                    line += 1;
+                    continue;
+                }
+                // Draw consecutive scanlines captured to the same bank as a single batch, with the
+                // vertex shader placing each one at its destination bank line
+                let start_line = line;
+                while line < SCREEN_HEIGHT && is_captured_to_bank(&self.scanline_flags[line]) {
+                    let dst_line = ((self.scanline_flags[line].capture_control >> 18 & 3) as usize
+                        * 64
+                        + line)
+                        & (BANK_LINES - 1);
+                    self.valid_lines[bank][dst_line >> 6] |= 1 << (dst_line & 63);
+                    line += 1;
+                }
+                render_pass.draw(0..4, start_line as u32..line as u32);
+            }
+        }
+    }
+}
+
 pub struct FrontendChannels {
     color_output_view_rx: crossbeam_channel::Receiver<wgpu::TextureView>,
     renderer_3d_rx_tx: crossbeam_channel::Sender<Renderer3dRx>,
@@ -163,8 +609,15 @@ impl GfxThreadChannels {
 
 struct FrameData {
     output_3d: Box<[Scanline<u32>; SCREEN_HEIGHT]>,
+    /// The 3D output at the software 3D renderer's resolution, if higher than the native one.
+    output_3d_hi_res: Vec<u32>,
     framebuffer: Box<[[Scanline<BgObjPixel>; SCREEN_HEIGHT]; 2]>,
     fb_scanline_flags: Box<[[ScanlineFlags; SCREEN_HEIGHT]; 2]>,
+    /// The source A 2D output for each captured scanline, before 3D and color effects are applied.
+    capture_framebuffer: Box<[Scanline<BgObjPixel>; SCREEN_HEIGHT]>,
+    capture_scanline_flags: Box<[ScanlineFlags; SCREEN_HEIGHT]>,
+    /// The native source B data for each captured scanline.
+    capture_src_b: Box<[Scanline<u16>; SCREEN_HEIGHT]>,
     engine_3d_enabled: bool,
     frame_index: u64,
 }
@@ -174,8 +627,12 @@ impl Default for FrameData {
         unsafe {
             FrameData {
                 output_3d: Box::new_zeroed().assume_init(),
+                output_3d_hi_res: Vec::new(),
                 framebuffer: Box::new_zeroed().assume_init(),
                 fb_scanline_flags: Box::new_zeroed().assume_init(),
+                capture_framebuffer: Box::new_zeroed().assume_init(),
+                capture_scanline_flags: Box::new_zeroed().assume_init(),
+                capture_src_b: Box::new_zeroed().assume_init(),
                 engine_3d_enabled: false,
                 frame_index: 0,
             }
@@ -198,10 +655,15 @@ impl GfxData {
         renderer_3d_rx: Renderer3dRx,
     ) -> (Renderer3dRenderThreadData, Renderer3dUpdateGfxThreadData) {
         match renderer_3d_rx {
-            Renderer3dRx::Soft(rx) => (
-                Renderer3dRenderThreadData::Soft(rx),
-                Renderer3dUpdateGfxThreadData::Soft,
-            ),
+            Renderer3dRx::Soft(rx) => {
+                let resolution_scale_shift = rx.resolution_scale_shift();
+                (
+                    Renderer3dRenderThreadData::Soft(rx),
+                    Renderer3dUpdateGfxThreadData::Soft {
+                        resolution_scale_shift,
+                    },
+                )
+            }
             Renderer3dRx::Accel {
                 rx,
                 color_output_view,
@@ -284,6 +746,15 @@ impl GfxData {
                 .expect("couldn't send new 3D renderer receiver");
         }
 
+        for scanline_flags in self
+            .frame_data_tx
+            .current()
+            .capture_scanline_flags
+            .iter_mut()
+        {
+            scanline_flags.capture_control = 0;
+        }
+
         if engine_3d_enabled_in_frame {
             match &mut self.renderer_3d_data {
                 Renderer3dRenderThreadData::Soft(rx) => rx.start_frame(),
@@ -302,14 +773,21 @@ impl GfxData {
 
     pub fn process_3d_scanline(&mut self, cur_scanline: usize) {
         if let Renderer3dRenderThreadData::Soft(rx) = &mut self.renderer_3d_data {
+            let frame = self.frame_data_tx.current();
             unsafe {
-                self.frame_data_tx
-                    .current()
+                frame
                     .output_3d
                     .get_unchecked_mut(cur_scanline)
                     .0
                     .copy_from_slice(&rx.read_scanline().0);
             }
+            if rx.resolution_scale_shift() != 0 {
+                let hi_res_scanline = rx.last_hi_res_scanline();
+                let len = hi_res_scanline.len();
+                frame.output_3d_hi_res.resize(len * SCREEN_HEIGHT, 0);
+                frame.output_3d_hi_res[cur_scanline * len..][..len]
+                    .copy_from_slice(hi_res_scanline);
+            }
         }
     }
 
@@ -325,6 +803,24 @@ impl GfxData {
         }
     }
 
+    /// Returns the data to be filled in for a scanline captured at the rendering resolution: its
+    /// source A 2D output, flags and native source B data.
+    pub fn capture_scanline_mut(
+        &mut self,
+        cur_scanline: usize,
+    ) -> (
+        &mut Scanline<BgObjPixel>,
+        &mut ScanlineFlags,
+        &mut Scanline<u16>,
+    ) {
+        let frame = self.frame_data_tx.current();
+        (
+            &mut frame.capture_framebuffer[cur_scanline],
+            &mut frame.capture_scanline_flags[cur_scanline],
+            &mut frame.capture_src_b[cur_scanline],
+        )
+    }
+
     pub fn finish_frame(
         &mut self,
         framebuffer: &[[Scanline<BgObjPixel>; SCREEN_HEIGHT]; 2],
@@ -363,6 +859,7 @@ struct GfxThreadData {
     output_attachments: OutputAttachments,
 
     fb_texture: wgpu::Texture,
+    fb_scanline_flags: Box<[[ScanlineFlags; SCREEN_HEIGHT]; 2]>,
     fb_scanline_flags_buffer: wgpu::Buffer,
     fb_data_bg_layout: wgpu::BindGroupLayout,
     fb_data_bg: wgpu::BindGroup,
@@ -373,15 +870,24 @@ struct GfxThreadData {
     color_output_3d_bg_layout: wgpu::BindGroupLayout,
     color_output_3d_bg: wgpu::BindGroup,
 
+    hi_res_capture: HiResCaptureData,
+    last_frame_index: Option<u64>,
+
     pipeline: wgpu::RenderPipeline,
+    capture_pipeline: wgpu::RenderPipeline,
 }
 
 impl GfxThreadData {
-    fn create_pipeline_and_output_3d_bg_layout(
+    fn create_pipelines_and_output_3d_bg_layout(
         device: &wgpu::Device,
         fb_data_bg_layout: &wgpu::BindGroupLayout,
+        hi_res_capture_bg_layout: &wgpu::BindGroupLayout,
         accel: bool,
-    ) -> (wgpu::RenderPipeline, wgpu::BindGroupLayout) {
+    ) -> (
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::BindGroupLayout,
+    ) {
         let color_output_3d_bg_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("2D renderer 3D output"),
@@ -403,7 +909,11 @@ impl GfxThreadData {
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("2D renderer"),
-            bind_group_layouts: &[fb_data_bg_layout, &color_output_3d_bg_layout],
+            bind_group_layouts: &[
+                fb_data_bg_layout,
+                &color_output_3d_bg_layout,
+                hi_res_capture_bg_layout,
+            ],
             push_constant_ranges: &[],
         });
 
@@ -419,44 +929,50 @@ impl GfxThreadData {
             ),
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("2D renderer"),
-            layout: Some(&pipeline_layout),
+        let create_pipeline = |label: &str, vs_entry_point: &str, fs_entry_point: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
 
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: vs_entry_point,
+                    buffers: &[],
+                },
 
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
 
-            depth_stencil: None,
+                depth_stencil: None,
 
-            multisample: wgpu::MultisampleState::default(),
+                multisample: wgpu::MultisampleState::default(),
 
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: fs_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
 
-            multiview: None,
-        });
+                multiview: None,
+            })
+        };
 
-        (pipeline, color_output_3d_bg_layout)
+        (
+            create_pipeline("2D renderer", "vs_main", "fs_main"),
+            create_pipeline("2D renderer capture", "vs_capture", "fs_capture"),
+            color_output_3d_bg_layout,
+        )
     }
 
     fn create_renderer_3d_data_and_color_output_3d_view(
@@ -464,12 +980,14 @@ impl GfxThreadData {
         update: Renderer3dUpdateGfxThreadData,
     ) -> (Renderer3dGfxThreadData, wgpu::TextureView) {
         match update {
-            Renderer3dUpdateGfxThreadData::Soft => {
+            Renderer3dUpdateGfxThreadData::Soft {
+                resolution_scale_shift,
+            } => {
                 let color_output_texture = device.create_texture(&wgpu::TextureDescriptor {
                     label: Some("2D renderer 3D output"),
                     size: wgpu::Extent3d {
-                        width: SCREEN_WIDTH as u32,
-                        height: SCREEN_HEIGHT as u32,
+                        width: (SCREEN_WIDTH as u32) << resolution_scale_shift,
+                        height: (SCREEN_HEIGHT as u32) << resolution_scale_shift,
                         depth_or_array_layers: 1,
                     },
                     mip_level_count: 1,
@@ -483,6 +1001,7 @@ impl GfxThreadData {
                 (
                     Renderer3dGfxThreadData::Soft {
                         color_output_texture,
+                        resolution_scale_shift,
                     },
                     color_output_view,
                 )
@@ -504,6 +1023,33 @@ impl GfxThreadData {
         }
     }
 
+    fn create_fb_data_bg(
+        device: &wgpu::Device,
+        fb_data_bg_layout: &wgpu::BindGroupLayout,
+        label: &str,
+        texture_view: &wgpu::TextureView,
+        scanline_flags_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: fb_data_bg_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: scanline_flags_buffer,
+                        offset: 0,
+                        size: NonZeroU64::new(FB_SCANLINE_FLAGS_SIZE as u64),
+                    }),
+                },
+            ],
+        })
+    }
+
     fn create_output_3d_bg(
         device: &wgpu::Device,
         color_output_3d_bg_layout: &wgpu::BindGroupLayout,
@@ -548,7 +1094,7 @@ impl GfxThreadData {
 
         let fb_scanline_flags_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("2D renderer framebuffer scanline flags"),
-            size: (SCREEN_HEIGHT * 2 * 16) as u64,
+            size: FB_SCANLINE_FLAGS_SIZE as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -568,46 +1114,41 @@ impl GfxThreadData {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // Also read by the capture vertex shader to place scanlines in their banks
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new((SCREEN_HEIGHT * 2 * 16) as u64),
+                        min_binding_size: NonZeroU64::new(FB_SCANLINE_FLAGS_SIZE as u64),
                     },
                     count: None,
                 },
             ],
         });
-        let fb_data_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("2D renderer framebuffer texture"),
-            layout: &fb_data_bg_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&fb_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &fb_scanline_flags_buffer,
-                        offset: 0,
-                        size: NonZeroU64::new((SCREEN_HEIGHT * 2 * 16) as u64),
-                    }),
-                },
-            ],
-        });
+        let fb_data_bg = Self::create_fb_data_bg(
+            &device,
+            &fb_data_bg_layout,
+            "2D renderer framebuffer texture",
+            &fb_texture_view,
+            &fb_scanline_flags_buffer,
+        );
+
+        let hi_res_capture =
+            HiResCaptureData::new(&device, &fb_data_bg_layout, resolution_scale_shift);
 
         let (output_attachments, color_output_view) =
             OutputAttachments::new(&device, resolution_scale_shift);
 
-        let (pipeline, color_output_3d_bg_layout) = Self::create_pipeline_and_output_3d_bg_layout(
-            &device,
-            &fb_data_bg_layout,
-            matches!(
-                renderer_3d_data,
-                Renderer3dUpdateGfxThreadData::Accel { .. }
-            ),
-        );
+        let (pipeline, capture_pipeline, color_output_3d_bg_layout) =
+            Self::create_pipelines_and_output_3d_bg_layout(
+                &device,
+                &fb_data_bg_layout,
+                &hi_res_capture.bg_layout,
+                matches!(
+                    renderer_3d_data,
+                    Renderer3dUpdateGfxThreadData::Accel { .. }
+                ),
+            );
 
         let (renderer_3d_data, color_output_3d_view) =
             Self::create_renderer_3d_data_and_color_output_3d_view(&device, renderer_3d_data);
@@ -627,6 +1168,7 @@ impl GfxThreadData {
                 output_attachments,
 
                 fb_texture,
+                fb_scanline_flags: unsafe { Box::new_zeroed().assume_init() },
                 fb_scanline_flags_buffer,
                 fb_data_bg_layout,
                 fb_data_bg,
@@ -637,7 +1179,11 @@ impl GfxThreadData {
                 color_output_3d_bg_layout,
                 color_output_3d_bg,
 
+                hi_res_capture,
+                last_frame_index: None,
+
                 pipeline,
+                capture_pipeline,
             },
             color_output_view,
         )
@@ -659,18 +1205,33 @@ impl GfxThreadData {
                         OutputAttachments::new(&self.device, resolution_scale_shift);
                     self.output_attachments = output_attachments;
                     self.channels.set_color_output_view(color_output_view);
+                    self.hi_res_capture
+                        .set_resolution_scale_shift(&self.device, resolution_scale_shift);
                 }
 
+                // Captures from skipped frames were never repeated at the rendering resolution, so
+                // none of the hi-res bank lines can be trusted anymore
+                if self.last_frame_index.map_or(false, |last_frame_index| {
+                    frame.frame_index != last_frame_index + 1
+                }) {
+                    self.hi_res_capture.invalidate();
+                }
+                self.last_frame_index = Some(frame.frame_index);
+
                 if let Some(renderer_3d_data) = self.renderer_3d_data_rx.try_iter().last() {
-                    (self.pipeline, self.color_output_3d_bg_layout) =
-                        Self::create_pipeline_and_output_3d_bg_layout(
-                            &self.device,
-                            &self.fb_data_bg_layout,
-                            matches!(
-                                renderer_3d_data,
-                                Renderer3dUpdateGfxThreadData::Accel { .. }
-                            ),
-                        );
+                    (
+                        self.pipeline,
+                        self.capture_pipeline,
+                        self.color_output_3d_bg_layout,
+                    ) = Self::create_pipelines_and_output_3d_bg_layout(
+                        &self.device,
+                        &self.fb_data_bg_layout,
+                        &self.hi_res_capture.bg_layout,
+                        matches!(
+                            renderer_3d_data,
+                            Renderer3dUpdateGfxThreadData::Accel { .. }
+                        ),
+                    );
 
                     (self.renderer_3d_data, self.color_output_3d_view) =
                         Self::create_renderer_3d_data_and_color_output_3d_view(
@@ -708,27 +1269,44 @@ impl GfxThreadData {
 
                 if let Renderer3dGfxThreadData::Soft {
                     color_output_texture: output_texture,
+                    resolution_scale_shift,
                 } = &self.renderer_3d_data
                 {
-                    self.queue.write_texture(
-                        output_texture.as_image_copy(),
-                        unsafe {
+                    let width = SCREEN_WIDTH << resolution_scale_shift;
+                    let height = SCREEN_HEIGHT << resolution_scale_shift;
+                    let output_3d = if *resolution_scale_shift == 0 {
+                        Some(unsafe {
                             slice::from_raw_parts(
                                 frame.output_3d.as_ptr() as *const u8,
                                 SCREEN_WIDTH * SCREEN_HEIGHT * 4,
                             )
-                        },
-                        wgpu::ImageDataLayout {
-                            offset: 0,
-                            bytes_per_row: Some((SCREEN_WIDTH * 4) as u32),
-                            rows_per_image: None,
-                        },
-                        wgpu::Extent3d {
-                            width: SCREEN_WIDTH as u32,
-                            height: SCREEN_HEIGHT as u32,
-                            depth_or_array_layers: 1,
-                        },
-                    );
+                        })
+                    } else {
+                        // The hi-res output could be missing or have a different size for the
+                        // first frame after the 3D renderer was changed
+                        (frame.output_3d_hi_res.len() == width * height).then(|| unsafe {
+                            slice::from_raw_parts(
+                                frame.output_3d_hi_res.as_ptr() as *const u8,
+                                width * height * 4,
+                            )
+                        })
+                    };
+                    if let Some(output_3d) = output_3d {
+                        self.queue.write_texture(
+                            output_texture.as_image_copy(),
+                            output_3d,
+                            wgpu::ImageDataLayout {
+                                offset: 0,
+                                bytes_per_row: Some((width * 4) as u32),
+                                rows_per_image: None,
+                            },
+                            wgpu::Extent3d {
+                                width: width as u32,
+                                height: height as u32,
+                                depth_or_array_layers: 1,
+                            },
+                        );
+                    }
                 }
 
                 self.queue.write_texture(
@@ -750,11 +1328,18 @@ impl GfxThreadData {
                         depth_or_array_layers: 1,
                     },
                 );
+                self.fb_scanline_flags
+                    .copy_from_slice(&*frame.fb_scanline_flags);
+                for scanline_flags in self.fb_scanline_flags.iter_mut().flatten() {
+                    scanline_flags.vram_line = self
+                        .hi_res_capture
+                        .validate_vram_line(scanline_flags.vram_line);
+                }
                 self.queue
                     .write_buffer(&self.fb_scanline_flags_buffer, 0, unsafe {
                         slice::from_raw_parts(
-                            frame.fb_scanline_flags.as_ptr() as *const u8,
-                            SCREEN_HEIGHT * 2 * 16,
+                            self.fb_scanline_flags.as_ptr() as *const u8,
+                            FB_SCANLINE_FLAGS_SIZE,
                         )
                     });
 
@@ -782,11 +1367,22 @@ impl GfxThreadData {
 
                 render_pass.set_bind_group(0, &self.fb_data_bg, &[]);
                 render_pass.set_bind_group(1, &self.color_output_3d_bg, &[]);
+                render_pass.set_bind_group(2, &self.hi_res_capture.attachments.display_bg, &[]);
                 render_pass.set_pipeline(&self.pipeline);
                 render_pass.draw(0..4, 0..1);
 
                 drop(render_pass);
 
+                if self.resolution_scale_shift != 0 {
+                    self.hi_res_capture.run(
+                        &self.queue,
+                        &self.capture_pipeline,
+                        &self.color_output_3d_bg,
+                        &frame,
+                        &mut command_encoder,
+                    );
+                }
+
                 // TODO: Proper synchronization
                 // if let Renderer3dGfxThreadData::Accel {
                 //     last_submitted_frame,
@@ -0,0 +1,27 @@
+//! Display capture at the rendering resolution, see
+//! [`dust_soft_2d_base::hi_res_capture`].
+//!
+//! Captures are still performed at the native resolution on the emulation thread, where the
+//! [`Tracker`] is updated, while the graphics thread repeats every 256-pixel-wide capture into the
+//! hi-res copies of the VRAM banks. [`Engine2d`](dust_core::gpu::engine_2d::Engine2d) only ever
+//! sees the native capture results, as they're what games read back from VRAM.
+
+pub use dust_soft_2d_base::hi_res_capture::{
+    bank_line_ptr, capture_dst_line, capture_src_b_line, Tracker, BANK_LINES,
+};
+
+/// Set in [`ScanlineFlags::vram_line`](super::ScanlineFlags::vram_line) if the scanline should use
+/// the hi-res copy of the VRAM bank line in bits 8-9 (bank) and 0-7 (line).
+pub const VRAM_LINE_HI_RES: u32 = 1 << 31;
+/// Set in [`ScanlineFlags::vram_line`](super::ScanlineFlags::vram_line) for capture scanlines if
+/// the native source B line is available.
+pub const VRAM_LINE_NATIVE: u32 = 1 << 30;
+
+/// Set in [`ScanlineFlags::capture_control`](super::ScanlineFlags::capture_control) for scanlines
+/// that should be captured at the rendering resolution.
+pub const CAPTURE_ENABLED: u32 = 1 << 31;
+
+#[inline]
+pub fn hi_res_vram_line(bank: u8, line: u8) -> u32 {
+    VRAM_LINE_HI_RES | (bank as u32) << 8 | line as u32
+}
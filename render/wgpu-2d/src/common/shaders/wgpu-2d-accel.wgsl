@@ -32,11 +32,27 @@ struct ScanlineFlags {
     color_effects_control: u32,
     blend_coeffs: u32,
     brightness_coeff: u32,
+    capture_control: u32,
+    vram_line: u32,
+    padding_0: u32,
+    padding_1: u32,
 }
 
 @group(0) @binding(0) var t_output_2d: texture_2d<u32>;
 @group(0) @binding(1) var<uniform> scanline_flags: array<array<ScanlineFlags, 192>, 2>;
 @group(1) @binding(0) var t_output_3d: texture_2d<f32>;
+@group(2) @binding(0) var t_capture_banks: texture_2d_array<f32>;
+@group(2) @binding(1) var t_capture_src_b: texture_2d<u32>;
+@group(2) @binding(2) var t_capture_src_b_hi_res: texture_2d<f32>;
+
+fn rgb5_to_rgba32f(value: u32) -> vec4<f32> {
+    return vec4<f32>(
+        f32(value & 0x1Fu) * (1.0 / 31.0),
+        f32((value >> 5u) & 0x1Fu) * (1.0 / 31.0),
+        f32((value >> 10u) & 0x1Fu) * (1.0 / 31.0),
+        f32(value >> 15u),
+    );
+}
 
 fn rgb6_to_rgba32f(value: u32) -> vec4<f32> {
     return vec4<f32>(
@@ -51,29 +67,25 @@ fn blend(a: vec4<f32>, b: vec4<f32>, coeff_a: f32, coeff_b: f32) -> vec4<f32> {
     return vec4<f32>(min(a.rgb * coeff_a + b.rgb * coeff_b, vec3<f32>(1.0)), 1.0);
 }
 
-@fragment
-fn fs_main(
-    @location(0) uv: vec2<f32>,
-) -> @location(0) vec4<f32> {
-    let screen_index = u32(uv.y * 2.0);
-    let scanline_index = u32(fract(uv.y * 2.0) * 192.0);
-    let scanline_flags = scanline_flags[screen_index][scanline_index];
-    let pixel = textureLoad(t_output_2d, vec2<i32>(uv * vec2<f32>(256.0, 384.0)), 0);
-
-    let uv_3d = fract(uv * vec2<f32>(1.0, 2.0));
-    let pixel_3d =
-        textureLoad(t_output_3d, vec2<i32>(uv_3d * vec2<f32>(textureDimensions(t_output_3d))), 0);
+fn load_3d(uv_3d: vec2<f32>) -> vec4<f32> {
+    return textureLoad(
+        t_output_3d,
+        vec2<i32>(uv_3d * vec2<f32>(textureDimensions(t_output_3d))),
+        0,
+    );
+}
 
+fn compose(pixel: vec4<u32>, pixel_3d: vec4<f32>, scanline_flags: ScanlineFlags) -> vec4<f32> {
     var top_rgb = rgb6_to_rgba32f(pixel.r);
     var bot_rgb = rgb6_to_rgba32f(pixel.g);
     if (pixel.r & (1u << 23u)) != 0u {
-        top_rgb = pixel_3d;
+        top_rgb = vec4<f32>(pixel_3d.rgb, 1.0);
         if pixel_3d.a == 0.0 {
             top_rgb = bot_rgb;
         }
     }
     if (pixel.g & (1u << 23u)) != 0u {
-        bot_rgb = pixel_3d;
+        bot_rgb = vec4<f32>(pixel_3d.rgb, 1.0);
         if pixel_3d.a == 0.0 {
             bot_rgb = vec4<f32>(0.0, 0.0, 0.0, 1.0);
         }
@@ -90,9 +102,8 @@ fn fs_main(
     let blend_coeff_b = f32(scanline_flags.blend_coeffs >> 16u) * (1.0 / 16.0);
     let brightness_coeff = f32(scanline_flags.brightness_coeff) * (1.0 / 16.0);
 
-    var blended_rgb = top_rgb;
     if (pixel.r & (1u << 23u)) != 0u && bot_matches {
-        blended_rgb = blend(top_rgb, bot_rgb, pixel_3d.a, 1.0 - pixel_3d.a);
+        return blend(top_rgb, bot_rgb, pixel_3d.a, 1.0 - pixel_3d.a);
     } else if (pixel.r & (1u << 24u)) != 0u && bot_matches {
         var coeff_a: f32;
         var coeff_b: f32;
@@ -103,52 +114,157 @@ fn fs_main(
             coeff_a = blend_coeff_a;
             coeff_b = blend_coeff_b;
         }
-        blended_rgb = blend(top_rgb, bot_rgb, coeff_a, coeff_b);
+        return blend(top_rgb, bot_rgb, coeff_a, coeff_b);
     } else if top_matches {
         switch color_effect {
             case 1u: {
                 if bot_matches {
-                    blended_rgb = blend(top_rgb, bot_rgb, blend_coeff_a, blend_coeff_b);
-                } else {
-                    blended_rgb = top_rgb;
+                    return blend(top_rgb, bot_rgb, blend_coeff_a, blend_coeff_b);
                 }
             }
 
             case 2u: {
-                blended_rgb = vec4(
+                return vec4(
                     top_rgb.rgb + (vec3<f32>(1.0) - top_rgb.rgb) * brightness_coeff,
                     1.0,
                 );
             }
 
             case 3u: {
-                blended_rgb = vec4(top_rgb.rgb - top_rgb.rgb * brightness_coeff, 1.0);
+                return vec4(top_rgb.rgb - top_rgb.rgb * brightness_coeff, 1.0);
             }
 
-            default: {
-                blended_rgb = top_rgb;
-            }
+            default: {}
         }
-    } else {
-        blended_rgb = top_rgb;
     }
+    return top_rgb;
+}
 
-    let brightness_factor = f32(scanline_flags.master_brightness_control & 0x1Fu) * (1.0 / 16.0);
-    let brightness_mode = scanline_flags.master_brightness_control >> 14u;
+fn apply_master_brightness(rgb: vec4<f32>, master_brightness_control: u32) -> vec4<f32> {
+    let brightness_factor = f32(master_brightness_control & 0x1Fu) * (1.0 / 16.0);
+    let brightness_mode = master_brightness_control >> 14u;
     switch brightness_mode {
         case 1u: {
-            return vec4(
-                blended_rgb.rgb + (vec3<f32>(1.0) - blended_rgb.rgb) * brightness_factor,
-                1.0,
-            );
+            return vec4(rgb.rgb + (vec3<f32>(1.0) - rgb.rgb) * brightness_factor, 1.0);
         }
 
         case 2u: {
-            return vec4(blended_rgb.rgb - blended_rgb.rgb * brightness_factor, 1.0);
+            return vec4(rgb.rgb - rgb.rgb * brightness_factor, 1.0);
         }
 
         default: {
-            return blended_rgb;
+            return rgb;
         }
     }
 }
+
+@fragment
+fn fs_main(
+    @location(0) uv: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    let screen_index = u32(uv.y * 2.0);
+    let scanline_pos = fract(uv.y * 2.0) * 192.0;
+    let scanline_flags = scanline_flags[screen_index][u32(scanline_pos)];
+
+    if (scanline_flags.vram_line & (1u << 31u)) != 0u {
+        // Displaying a VRAM bank line that still holds the results of a hi-res capture
+        let bank_line_pos = f32(scanline_flags.vram_line & 0xFFu) + fract(scanline_pos);
+        let bank_pixel = textureLoad(
+            t_capture_banks,
+            vec2<i32>(
+                vec2<f32>(uv.x, bank_line_pos * (1.0 / 256.0))
+                    * vec2<f32>(textureDimensions(t_capture_banks))
+            ),
+            i32((scanline_flags.vram_line >> 8u) & 3u),
+            0,
+        );
+        return apply_master_brightness(
+            vec4<f32>(bank_pixel.rgb, 1.0),
+            scanline_flags.master_brightness_control,
+        );
+    }
+
+    let pixel = textureLoad(t_output_2d, vec2<i32>(uv * vec2<f32>(256.0, 384.0)), 0);
+    let pixel_3d = load_3d(fract(uv * vec2<f32>(1.0, 2.0)));
+    return apply_master_brightness(
+        compose(pixel, pixel_3d, scanline_flags),
+        scanline_flags.master_brightness_control,
+    );
+}
+
+struct CaptureVertOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) line: u32,
+}
+
+// Draws each captured scanline (one per instance) to its destination line inside the bank
+@vertex
+fn vs_capture(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) line: u32,
+) -> CaptureVertOutput {
+    let x = f32(vertex_index & 1u);
+    let y = f32(vertex_index >> 1u);
+    let dst_line = (((scanline_flags[0][line].capture_control >> 18u) & 3u) * 64u + line) & 0xFFu;
+
+    var output: CaptureVertOutput;
+    output.pos = vec4<f32>(x * 2.0 - 1.0, 1.0 - (f32(dst_line) + y) * (2.0 / 256.0), 0.0, 1.0);
+    output.uv = vec2<f32>(x, (f32(line) + y) * (1.0 / 192.0));
+    output.line = line;
+    return output;
+}
+
+@fragment
+fn fs_capture(
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) line: u32,
+) -> @location(0) vec4<f32> {
+    let scanline_flags = scanline_flags[0][line];
+    let capture_control = scanline_flags.capture_control;
+    let src = (capture_control >> 29u) & 3u;
+    let factor_a = f32(min(capture_control & 0x1Fu, 16u)) * (1.0 / 16.0);
+    let factor_b = f32(min((capture_control >> 8u) & 0x1Fu, 16u)) * (1.0 / 16.0);
+
+    let src_b_available = (scanline_flags.vram_line & (1u << 30u)) != 0u
+        && src != 0u
+        && (factor_b != 0.0 || (src & 2u) == 0u);
+    var pixel_b = vec4<f32>(0.0);
+    if src_b_available {
+        if (scanline_flags.vram_line & (1u << 31u)) != 0u {
+            pixel_b = textureLoad(
+                t_capture_src_b_hi_res,
+                vec2<i32>(uv * vec2<f32>(textureDimensions(t_capture_src_b_hi_res))),
+                0,
+            );
+        } else {
+            pixel_b = rgb5_to_rgba32f(
+                textureLoad(t_capture_src_b, vec2<i32>(uv * vec2<f32>(256.0, 192.0)), 0).r
+            );
+        }
+    }
+
+    if src == 1u || ((src & 2u) != 0u && factor_a == 0.0) {
+        return pixel_b;
+    }
+
+    let pixel_3d = load_3d(uv);
+    var pixel_a: vec4<f32>;
+    if (capture_control & (1u << 24u)) != 0u {
+        pixel_a = vec4<f32>(pixel_3d.rgb, select(0.0, 1.0, pixel_3d.a != 0.0));
+    } else {
+        let pixel = textureLoad(t_output_2d, vec2<i32>(uv * vec2<f32>(256.0, 192.0)), 0);
+        pixel_a = vec4<f32>(compose(pixel, pixel_3d, scanline_flags).rgb, 1.0);
+    }
+
+    if !src_b_available {
+        return pixel_a;
+    }
+    return vec4<f32>(
+        min(
+            pixel_a.rgb * (pixel_a.a * factor_a) + pixel_b.rgb * (pixel_b.a * factor_b),
+            vec3<f32>(1.0),
+        ),
+        max(pixel_a.a, pixel_b.a),
+    );
+}
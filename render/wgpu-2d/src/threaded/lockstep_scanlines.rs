@@ -3,6 +3,7 @@ mod impls;
 use crate::common::{
     self, capture,
     gfx::{self, GfxData, Renderer3dRx},
+    hi_res_capture,
     render::{self, objs::prerender_objs},
     rgb5_to_rgb6_64, BgObjPixel, ObjPixel, ScanlineFlags, WindowPixel,
 };
@@ -36,6 +37,14 @@ where
     oam: Bytes<0x400>,
 }
 
+/// The VRAM bank lines used by the current scanline, see [`hi_res_capture`].
+#[derive(Clone, Copy, Default)]
+struct HiResLineData {
+    vram_display_line: u32,
+    capture_enabled: bool,
+    capture_src_b_line: u32,
+}
+
 #[allow(clippy::type_complexity)]
 struct SharedData {
     stopped: AtomicBool,
@@ -45,6 +54,8 @@ struct SharedData {
     vram: UnsafeCell<(Box<Vram<EngineA>>, Box<Vram<EngineB>>)>,
     rendering_data: UnsafeCell<[RenderingData; 2]>,
     capture_scanlines: UnsafeCell<(Scanline<BgObjPixel>, Scanline<u32>)>,
+    hi_res_line_data: UnsafeCell<HiResLineData>,
+    capture_src_b_scanline: UnsafeCell<Scanline<u16>>,
 
     framebuffer: UnsafeCell<Box<[[Scanline<BgObjPixel>; SCREEN_HEIGHT]; 2]>>,
 }
@@ -161,6 +172,7 @@ impl FrontendChannels {
 
 pub struct Renderer {
     affine_bg_pos: [[[i32; 2]; 2]; 2],
+    hi_res_capture_tracker: hi_res_capture::Tracker,
     shared_data: Arc<SharedData>,
     thread: Option<thread::JoinHandle<()>>,
 }
@@ -227,6 +239,8 @@ impl Renderer {
                     Scanline([BgObjPixel(0); SCREEN_WIDTH]),
                     Scanline([0; SCREEN_WIDTH]),
                 )),
+                hi_res_line_data: UnsafeCell::new(HiResLineData::default()),
+                capture_src_b_scanline: UnsafeCell::new(Scanline([0; SCREEN_WIDTH])),
 
                 framebuffer: UnsafeCell::new(Box::new_zeroed().assume_init()),
             }
@@ -250,6 +264,7 @@ impl Renderer {
         (
             Renderer {
                 affine_bg_pos: [[[0; 2]; 2]; 2],
+                hi_res_capture_tracker: hi_res_capture::Tracker::new(),
 
                 shared_data: Arc::clone(&shared_data),
 
@@ -382,6 +397,67 @@ impl Renderer {
         rendering_data[1].affine_bg_data[1].pos = prev_affine_bg_pos[1][1];
     }
 
+    fn update_hi_res_line_data(
+        &mut self,
+        line: u8,
+        vcount: u8,
+        engine: &Engine2d<EngineA>,
+        vram: &vram::Vram,
+    ) {
+        let hi_res_line_data = unsafe { &mut *self.shared_data.hi_res_line_data.get() };
+        *hi_res_line_data = HiResLineData::default();
+
+        let control = engine.control();
+        if control.display_mode_a() == 2 && vcount < SCREEN_HEIGHT as u8 {
+            let bank = control.a_vram_bank();
+            if self
+                .hi_res_capture_tracker
+                .is_line_valid(vram, bank, vcount)
+            {
+                hi_res_line_data.vram_display_line = hi_res_capture::hi_res_vram_line(bank, vcount);
+            }
+        }
+
+        if engine.capture_enabled_in_frame() && line < engine.capture_height() {
+            let capture_control = engine.capture_control();
+            // Only 256-pixel-wide captures are repeated at the rendering resolution
+            hi_res_line_data.capture_enabled = capture_control.size() != 0
+                && hi_res_capture::bank_line_ptr(
+                    vram,
+                    capture_control.dst_bank(),
+                    hi_res_capture::capture_dst_line(capture_control, line),
+                )
+                .is_some();
+
+            let src_b_scanline = unsafe { &mut *self.shared_data.capture_src_b_scanline.get() };
+            match hi_res_capture::capture_src_b_line(control, capture_control, line).and_then(
+                |(bank, bank_line)| {
+                    hi_res_capture::bank_line_ptr(vram, bank, bank_line)
+                        .map(|line_ptr| (bank, bank_line, line_ptr))
+                },
+            ) {
+                Some((bank, bank_line, line_ptr)) => {
+                    unsafe {
+                        src_b_scanline
+                            .0
+                            .as_mut_ptr()
+                            .copy_from_nonoverlapping(line_ptr, SCREEN_WIDTH);
+                    }
+                    hi_res_line_data.capture_src_b_line = if self
+                        .hi_res_capture_tracker
+                        .is_line_valid(vram, bank, bank_line)
+                    {
+                        hi_res_capture::hi_res_vram_line(bank, bank_line)
+                            | hi_res_capture::VRAM_LINE_NATIVE
+                    } else {
+                        hi_res_capture::VRAM_LINE_NATIVE
+                    };
+                }
+                None => src_b_scanline.0.fill(0),
+            }
+        }
+    }
+
     fn start_scanline(&mut self) {
         self.shared_data
             .processing_line
@@ -472,6 +548,7 @@ impl RendererTrait for Renderer {
         }
 
         self.flush_rendering_data((engines.0, engines.1));
+        self.update_hi_res_line_data(line, vcount, engines.0, vram);
 
         macro_rules! update_affine_bgs {
             ($($engine_i: literal, $engine: expr, ($($i: literal),*));*) => {{
@@ -513,7 +590,9 @@ impl RendererTrait for Renderer {
                     .engine_3d_enabled_in_frame()
                     .then_some(scanline_3d),
                 vram,
-            )
+            );
+            self.hi_res_capture_tracker
+                .capture_finished(vram, engines.0.capture_control(), line);
         }
     }
 }
@@ -731,6 +810,8 @@ impl ThreadData {
                     2 => {
                         *scanline_flags =
                             ScanlineFlags::master_brightness_only(data.master_brightness_control);
+                        scanline_flags.vram_line =
+                            unsafe { &*self.shared_data.hi_res_line_data.get() }.vram_display_line;
                     }
 
                     _ => {}
@@ -740,6 +821,42 @@ impl ThreadData {
                     && data.capture_enabled_in_frame
                     && self.cur_scanline < data.capture_height as i16
                 {
+                    let hi_res_line_data = unsafe { &*self.shared_data.hi_res_line_data.get() };
+                    if hi_res_line_data.capture_enabled {
+                        let (hi_res_bg_obj_scanline, hi_res_scanline_flags, hi_res_src_b_scanline) =
+                            self.gfx_data
+                                .capture_scanline_mut(self.cur_scanline as usize);
+
+                        let mut capture_control = data.capture_control;
+                        if capture_control.src_a_3d_only() && !data.engine_3d_enabled_in_frame {
+                            // Without 3D output, source B is captured on its own
+                            capture_control.set_src(1);
+                        } else if !capture_control.src_a_3d_only() {
+                            // Color effects are applied while capturing, like in display mode 1
+                            hi_res_bg_obj_scanline
+                                .0
+                                .copy_from_slice(&buffers.bg_obj_scanline.get_mut().0);
+                            for i in 0..SCREEN_WIDTH {
+                                if !buffers.window.get_mut().0[i].color_effects_enabled() {
+                                    hi_res_bg_obj_scanline.0[i].0 &= !0xFC00_0000_FC00_0000;
+                                }
+                            }
+                        }
+
+                        *hi_res_scanline_flags = ScanlineFlags::new(
+                            BrightnessControl(0),
+                            data.color_effects_control,
+                            data.blend_coeffs,
+                            data.brightness_coeff,
+                        );
+                        hi_res_scanline_flags.capture_control =
+                            capture_control.0 | hi_res_capture::CAPTURE_ENABLED;
+                        hi_res_scanline_flags.vram_line = hi_res_line_data.capture_src_b_line;
+                        hi_res_src_b_scanline.0.copy_from_slice(
+                            &unsafe { &*self.shared_data.capture_src_b_scanline.get() }.0,
+                        );
+                    }
+
                     let (capture_bg_obj_scanline, capture_scanline_3d) =
                         unsafe { &mut *self.shared_data.capture_scanlines.get() };
                     if data.is_capturing_3d_output {
@@ -278,9 +278,13 @@ pub fn init(
                                         &mut *shared_data.capture_scanline_buffer.get()
                                     }[y as usize];
                                     if y < 191 {
-                                        raw_soft_renderer.render_line(y + 1, rendering_data);
+                                        raw_soft_renderer.render_line(y as u16 + 1, rendering_data);
                                     }
-                                    raw_soft_renderer.postprocess_line(y, scanline, rendering_data);
+                                    raw_soft_renderer.postprocess_line(
+                                        y as u16,
+                                        &mut scanline.0,
+                                        rendering_data,
+                                    );
                                     let _ =
                                         shared_data.capture_processing_scanline.compare_exchange(
                                             y,